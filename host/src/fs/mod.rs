@@ -0,0 +1,8 @@
+//! The parts of the file-system that only manipulate paths.
+
+#[path = "../../../src/fs/error.rs"]
+mod error;
+#[path = "../../../src/fs/path.rs"]
+pub mod path;
+
+pub use self::error::*;
@@ -0,0 +1,17 @@
+//! The parts of the kernel that do not depend on the hardware, built for the host so that their
+//! unit tests can run there.
+//!
+//! Run them with `make test`.
+
+#![allow(dead_code)]
+#![feature(
+    maybe_uninit_uninit_array,
+    maybe_uninit_slice,
+    const_maybe_uninit_uninit_array
+)]
+
+#[path = "../../src/utility/checksum.rs"]
+pub mod checksum;
+
+mod fs;
+mod utility;
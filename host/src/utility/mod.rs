@@ -0,0 +1,10 @@
+//! The parts of `src/utility` that do not depend on the hardware.
+
+#[path = "../../../src/utility/array_vec.rs"]
+mod array_vec;
+#[path = "../../../src/utility/fixed.rs"]
+mod fixed;
+#[path = "../../../src/utility/format.rs"]
+mod format;
+
+pub use self::array_vec::*;
@@ -0,0 +1,309 @@
+//! Micro-benchmarks of the paths of the kernel that must stay fast.
+//!
+//! The `bench` command runs them and prints how long each operation took. Durations are
+//! measured with the time-stamp counter, whose frequency is measured once against the PIT.
+//! Without it, the monotonic clock is used instead, whose resolution is much coarser.
+
+use core::arch::asm;
+use core::fmt::Write;
+use core::hint::black_box;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::cpu::paging::{kmem, PageTableFlags};
+use crate::drivers::speaker;
+use crate::error::KernelError;
+use crate::shell::{split_command, usage, Command, Shell};
+use crate::state::GLOBAL;
+use crate::utility::instr::{has_tsc, rdtsc};
+use crate::utility::{Fixed, HumanBytes, Mutex, OnceCell};
+use crate::{kthread, printk, sched, time, TERMINAL};
+
+/// The number of milliseconds during which the time-stamp counter is compared to the PIT.
+const CALIBRATION_MS: u32 = 10;
+
+/// The size of each of the buffers copied by the `memcpy` and `memset` benchmarks.
+const BUFFER_SIZE: usize = 32 * 1024;
+
+/// The number of the `getpid` system call, which does nothing but answer.
+const SYS_GETPID: u32 = 20;
+
+/// The number of cycles of the time-stamp counter per millisecond, or 0 when it cannot be
+/// used.
+static TSC_PER_MS: OnceCell<u64> = OnceCell::new();
+
+/// The buffers copied by the `memcpy` and `memset` benchmarks.
+static BUFFERS: Mutex<[[u8; BUFFER_SIZE]; 2]> = Mutex::new([[0; BUFFER_SIZE]; 2]);
+
+/// Whether the thread of the `switch` benchmark keeps yielding.
+static SWITCHING: AtomicBool = AtomicBool::new(false);
+
+/// Returns the number of cycles of the time-stamp counter per millisecond, or 0 when it cannot
+/// be used.
+///
+/// The first call measures it, which uses the channel 2 of the PIT.
+fn tsc_per_ms() -> u64 {
+    *TSC_PER_MS.get_or_init(|| {
+        if !has_tsc() {
+            return 0;
+        }
+        let start = unsafe { rdtsc() };
+        if !speaker::wait_silently(CALIBRATION_MS) {
+            return 0;
+        }
+        (unsafe { rdtsc() } - start) / CALIBRATION_MS as u64
+    })
+}
+
+/// Measures the time elapsed since it was started.
+struct Stopwatch {
+    /// The value of the time-stamp counter when it started, or of the monotonic clock when the
+    /// counter cannot be used.
+    start: u64,
+}
+
+impl Stopwatch {
+    /// Starts measuring.
+    fn start() -> Self {
+        let start = if tsc_per_ms() != 0 {
+            unsafe { rdtsc() }
+        } else {
+            time::monotonic_ns()
+        };
+        Self { start }
+    }
+
+    /// Returns the number of nanoseconds elapsed since the stopwatch started.
+    fn elapsed_ns(&self) -> u64 {
+        match tsc_per_ms() {
+            0 => time::monotonic_ns() - self.start,
+            per_ms => (unsafe { rdtsc() } - self.start) * 1_000_000 / per_ms,
+        }
+    }
+}
+
+/// What a benchmark measured.
+struct Measure {
+    /// The number of operations that were timed.
+    count: u64,
+    /// The number of bytes that each operation handles, or 0.
+    bytes: u64,
+    /// The time the operations took, in nanoseconds.
+    ns: u64,
+}
+
+/// Runs `count` times the operation `f`, and measures the time it took.
+fn measure(count: u64, bytes: u64, mut f: impl FnMut()) -> Measure {
+    let stopwatch = Stopwatch::start();
+    (0..count).for_each(|_| f());
+    Measure {
+        count,
+        bytes,
+        ns: stopwatch.elapsed_ns(),
+    }
+}
+
+/// A benchmark that the `bench` command can run.
+struct Bench {
+    /// The name with which the benchmark is selected.
+    name: &'static [u8],
+    /// A description of what each operation does.
+    operation: &'static str,
+    /// Runs the benchmark.
+    run: fn() -> Result<Measure, KernelError>,
+}
+
+/// The benchmarks, in the order in which they run.
+const BENCHES: &[Bench] = &[
+    Bench {
+        name: b"memcpy",
+        operation: "copy of 32 KiB",
+        run: bench_memcpy,
+    },
+    Bench {
+        name: b"memset",
+        operation: "fill of 32 KiB",
+        run: bench_memset,
+    },
+    Bench {
+        name: b"switch",
+        operation: "context switch",
+        run: bench_switch,
+    },
+    Bench {
+        name: b"syscall",
+        operation: "`getpid` round-trip",
+        run: bench_syscall,
+    },
+    Bench {
+        name: b"map",
+        operation: "map and unmap of a page",
+        run: bench_map,
+    },
+    Bench {
+        name: b"scroll",
+        operation: "scroll of the terminal",
+        run: bench_scroll,
+    },
+];
+
+/// Copies a buffer to another.
+fn bench_memcpy() -> Result<Measure, KernelError> {
+    let mut buffers = BUFFERS.lock();
+    let [src, dst] = &mut *buffers;
+    Ok(measure(256, BUFFER_SIZE as u64, || {
+        dst.copy_from_slice(black_box(&src[..]));
+        black_box(&mut dst[..]);
+    }))
+}
+
+/// Fills a buffer with a byte.
+fn bench_memset() -> Result<Measure, KernelError> {
+    let mut buffers = BUFFERS.lock();
+    let mut byte = 0u8;
+    Ok(measure(256, BUFFER_SIZE as u64, || {
+        byte = byte.wrapping_add(1);
+        buffers[0].fill(black_box(byte));
+        black_box(&mut buffers[0][..]);
+    }))
+}
+
+/// Yields to a kernel thread that yields back.
+///
+/// Each yield switches twice: to the other thread, and back.
+fn bench_switch() -> Result<Measure, KernelError> {
+    /// The thread that yields back to the benchmark.
+    fn partner() {
+        while SWITCHING.load(Relaxed) {
+            sched::yield_now();
+        }
+    }
+
+    SWITCHING.store(true, Relaxed);
+    if let Err(err) = kthread::spawn("bench", partner) {
+        SWITCHING.store(false, Relaxed);
+        return Err(err.into());
+    }
+    // Let the thread start before timing anything.
+    sched::yield_now();
+    let mut measure = measure(1000, 0, sched::yield_now);
+    SWITCHING.store(false, Relaxed);
+    sched::yield_now();
+
+    measure.count *= 2;
+    Ok(measure)
+}
+
+/// Makes the `getpid` system call from the kernel, with `int 0x80`.
+fn bench_syscall() -> Result<Measure, KernelError> {
+    Ok(measure(1000, 0, || unsafe {
+        asm!(
+            "int 0x80",
+            inout("eax") SYS_GETPID => _,
+            out("ecx") _,
+            out("edx") _,
+        );
+    }))
+}
+
+/// Maps a page of the kernel heap to a new frame, and unmaps it.
+fn bench_map() -> Result<Measure, KernelError> {
+    let glob = GLOBAL.get().unwrap();
+    let mut result = Ok(());
+    let measure = measure(256, 0, || {
+        if result.is_err() {
+            return;
+        }
+        let mut allocator = glob.allocator.lock();
+        match kmem::alloc_and_map(&mut allocator, 4096, PageTableFlags::WRITABLE, "[bench]") {
+            Ok(page) => kmem::unmap_and_free(&mut allocator, page, 4096),
+            Err(err) => result = Err(err),
+        }
+    });
+    result?;
+    Ok(measure)
+}
+
+/// Scrolls the screen of the terminal by one line.
+fn bench_scroll() -> Result<Measure, KernelError> {
+    let mut term = TERMINAL.lock();
+    Ok(measure(256, 0, || term.scroll_once()))
+}
+
+/// The `bench` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"bench",
+    summary: "measure the speed of the core paths of the kernel",
+    usage: "bench [memcpy|memset|switch|syscall|map|scroll]...",
+    details: "Runs the named benchmarks, or all of them, and prints how long each operation\n\
+              took. `memcpy` and `memset` also print their throughput. `scroll` scrolls the\n\
+              screen, and `map` needs the super-user. Durations are measured with the\n\
+              time-stamp counter when the CPU has one.",
+    handler: bench,
+};
+
+/// The `bench` command.
+fn bench(shell: &mut Shell, args: &[u8]) {
+    let mut selected = [args.is_empty(); BENCHES.len()];
+    let mut rest = args;
+    loop {
+        let (name, next) = split_command(rest);
+        if name.is_empty() {
+            break;
+        }
+        match BENCHES.iter().position(|bench| bench.name == name) {
+            Some(index) => selected[index] = true,
+            None => {
+                printk!("usage: {}\n", usage(b"bench"));
+                shell.fail();
+                return;
+            }
+        }
+        rest = next;
+    }
+
+    let map = BENCHES.iter().position(|bench| bench.name == b"map");
+    if map.is_some_and(|index| selected[index]) && !shell.is_super_user() {
+        printk!("bench: only the super-user may run `map`\n");
+        shell.fail();
+        return;
+    }
+
+    match tsc_per_ms() {
+        0 => printk!("timing with the monotonic clock\n"),
+        per_ms => printk!(
+            "timing with the time-stamp counter ({:.2} MHz)\n",
+            Fixed::from_ratio(per_ms, 1000)
+        ),
+    }
+
+    for (bench, _) in BENCHES
+        .iter()
+        .zip(selected)
+        .filter(|&(_, selected)| selected)
+    {
+        let name = core::str::from_utf8(bench.name).unwrap_or("?");
+        match (bench.run)() {
+            Ok(measure) => report(name, bench.operation, &measure),
+            Err(err) => {
+                printk!("bench: {name}: {err}\n");
+                shell.fail();
+            }
+        }
+    }
+}
+
+/// Prints what a benchmark measured.
+fn report(name: &str, operation: &str, measure: &Measure) {
+    let mut term = TERMINAL.lock();
+    let _ = write!(
+        term,
+        "{name:<8} {:.2} ns per {operation}",
+        Fixed::from_ratio(measure.ns, measure.count.max(1)),
+    );
+    if measure.bytes != 0 && measure.ns != 0 {
+        let per_second = measure.bytes * measure.count * 1_000_000_000 / measure.ns;
+        let _ = write!(term, " ({}/s)", HumanBytes(per_second));
+    }
+    let _ = writeln!(term);
+}
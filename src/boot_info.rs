@@ -0,0 +1,188 @@
+//! A description of the environment the kernel was loaded in, independent of the protocol
+//! used by the bootloader.
+//!
+//! Only the multiboot (version 1) protocol is supported. Other protocols would only need to
+//! provide their own entry point and a way to build a [`BootInfo`]; the rest of the kernel
+//! only relies on this module.
+//!
+//! Notably, the Limine protocol is *not* supported, and there is no Limine entry point: the
+//! protocol only loads 64-bit kernels, already running in long mode, while this kernel targets
+//! i386. Supporting it would require a 64-bit trampoline that switches back to protected mode.
+
+use core::ffi::CStr;
+
+use crate::log;
+use crate::multiboot::{self, MultibootInfo};
+use crate::state::{MemoryRegion, MAX_MEMORY_REGIONS};
+use crate::utility::{ArrayVec, HumanBytes};
+
+/// The maximum number of boot modules that are taken into account.
+pub const MAX_BOOT_MODULES: usize = 8;
+
+/// The maximum length of the command-line of a boot module. Longer command-lines are
+/// truncated.
+pub const MAX_MODULE_CMDLINE_LEN: usize = 64;
+
+/// A file loaded in memory by the bootloader alongside the kernel.
+#[derive(Clone)]
+pub struct BootModule {
+    /// The physical address of the first byte of the module.
+    pub start: u32,
+    /// The physical address of the byte that follows the module.
+    pub end: u32,
+    /// The command-line of the module, usually the path of the file it was loaded from.
+    ///
+    /// This is copied out of the memory of the bootloader.
+    pub cmdline: ArrayVec<u8, MAX_MODULE_CMDLINE_LEN>,
+}
+
+impl BootModule {
+    /// Returns the content of the module.
+    ///
+    /// # Safety
+    ///
+    /// The memory of the module must still be mapped where the bootloader loaded it, and must
+    /// not have been reused.
+    pub unsafe fn data(&self) -> &'static [u8] {
+        core::slice::from_raw_parts(self.start as *const u8, (self.end - self.start) as usize)
+    }
+
+    /// Returns the name of the module: the last component of the first word of its
+    /// command-line.
+    ///
+    /// For example, the name of a module loaded with `module /boot/rc` is `rc`.
+    pub fn name(&self) -> &[u8] {
+        let path = self.cmdline.split(|&c| c == b' ').next().unwrap_or(&[]);
+        path.rsplit(|&c| c == b'/').next().unwrap_or(&[])
+    }
+}
+
+/// Information provided by the bootloader.
+pub struct BootInfo {
+    /// The name of the bootloader, if it provided one.
+    pub bootloader_name: Option<&'static [u8]>,
+    /// The command-line passed to the kernel.
+    ///
+    /// This is empty if the bootloader did not provide one.
+    pub cmdline: &'static [u8],
+    /// The memory map of the system, including regions that are not available.
+    ///
+    /// This is empty if the bootloader did not provide one.
+    pub memory_map: ArrayVec<MemoryRegion, MAX_MEMORY_REGIONS>,
+    /// The modules loaded by the bootloader.
+    pub modules: ArrayVec<BootModule, MAX_BOOT_MODULES>,
+}
+
+impl BootInfo {
+    /// Creates a [`BootInfo`] from the information provided by a multiboot bootloader.
+    ///
+    /// # Safety
+    ///
+    /// `info` must have been provided by the bootloader, and the memory it references must
+    /// not have been reused yet.
+    pub unsafe fn from_multiboot(info: &MultibootInfo) -> Self {
+        let bootloader_name = if info.flags.intersects(multiboot::InfoFlags::BOOTLOADER_NAME) {
+            let name = CStr::from_ptr(info.bootloader_name);
+            log!("Bootloader: {:?}\n", name);
+            Some(name.to_bytes())
+        } else {
+            log!("Bootloader has not provided its name.\n");
+            None
+        };
+
+        let cmdline = if info.flags.intersects(multiboot::InfoFlags::CMDLINE) {
+            let cmdline = CStr::from_ptr(info.cmdline);
+            log!("Command-line: {:?}\n", cmdline);
+            cmdline.to_bytes()
+        } else {
+            log!("Bootloader has not provided a command-line.\n");
+            &[]
+        };
+
+        let mut memory_map = ArrayVec::<MemoryRegion, MAX_MEMORY_REGIONS>::new();
+        if info.flags.intersects(multiboot::InfoFlags::MEMORY_MAP) {
+            let mut ignored = 0;
+            let mut ignored_available = 0;
+            log!("Memory map:\n");
+            for entry in multiboot::MemMapIter::new(info.mmap_addr, info.mmap_length) {
+                let region = MemoryRegion {
+                    start: entry.addr_low as u64 | (entry.addr_high as u64) << 32,
+                    len: entry.len_low as u64 | (entry.len_high as u64) << 32,
+                    ty: entry.ty,
+                };
+                let Some(end) = region.end() else {
+                    log!(
+                        "  {:#012x} + {:#x} overflows ({}); ignored\n",
+                        region.start,
+                        region.len,
+                        region.ty.name(),
+                    );
+                    continue;
+                };
+                log!(
+                    "  {:#012x} -> {:#012x} ({})\n",
+                    region.start,
+                    end,
+                    region.ty.name(),
+                );
+
+                // Firmwares often split contiguous regions of the same type; merging them keeps
+                // the table small.
+                if let Some(last) = memory_map.last_mut() {
+                    if last.ty == region.ty && last.end() == Some(region.start) {
+                        last.len += region.len;
+                        continue;
+                    }
+                }
+                if memory_map.try_push(region).is_err() {
+                    ignored += 1;
+                    if region.ty == multiboot::MemMapType::AVAILABLE {
+                        ignored_available += region.len;
+                    }
+                }
+            }
+            if ignored != 0 {
+                log!(
+                    "The memory map has too many entries; {} were ignored ({} available).\n",
+                    ignored,
+                    HumanBytes(ignored_available),
+                );
+            }
+        } else {
+            log!("Bootloader has not provided a memory map.\n");
+        }
+
+        let mut modules = ArrayVec::new();
+        if info.flags.intersects(multiboot::InfoFlags::MODULES) {
+            for i in 0..info.mods_count as usize {
+                let module = *info.mods_addr.add(i);
+                let module = BootModule {
+                    start: module.mod_start,
+                    end: module.mod_end,
+                    cmdline: if module.string.is_null() {
+                        ArrayVec::new()
+                    } else {
+                        ArrayVec::from_slice_truncated(CStr::from_ptr(module.string).to_bytes())
+                    },
+                };
+                log!(
+                    "Module: {:#010x} -> {:#010x} ({:?})\n",
+                    module.start,
+                    module.end,
+                    core::str::from_utf8(&module.cmdline).unwrap_or("<invalid utf-8>"),
+                );
+                if modules.try_push(module).is_err() {
+                    log!("Too many modules were loaded; some will be ignored.\n");
+                    break;
+                }
+            }
+        }
+
+        Self {
+            bootloader_name,
+            cmdline,
+            memory_map,
+            modules,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+//! Parses the command-line that the bootloader passed to the kernel.
+//!
+//! The command-line is a list of options separated by spaces. Each option is either a simple
+//! flag (`key`), or a key-value pair (`key=value`).
+
+/// Returns an iterator over the options of the provided command-line.
+pub fn options(cmdline: &[u8]) -> impl '_ + Iterator<Item = (&[u8], Option<&[u8]>)> {
+    cmdline
+        .split(|&c| c == b' ')
+        .filter(|opt| !opt.is_empty())
+        .map(|opt| match opt.iter().position(|&c| c == b'=') {
+            Some(i) => (&opt[..i], Some(&opt[i + 1..])),
+            None => (opt, None),
+        })
+}
+
+/// Returns the value of the option named `key`, if it was passed to the kernel.
+///
+/// If the option is specified multiple times, the last value is returned.
+pub fn get<'a>(cmdline: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    options(cmdline)
+        .filter(|&(k, _)| k == key)
+        .filter_map(|(_, v)| v)
+        .last()
+}
+
+/// Returns whether the flag named `key` was passed to the kernel.
+pub fn has_flag(cmdline: &[u8], key: &[u8]) -> bool {
+    options(cmdline).any(|(k, _)| k == key)
+}
@@ -0,0 +1,244 @@
+//! Initializes the FPU and the SSE extensions, and manages the FPU state of tasks.
+//!
+//! The FPU state is switched lazily: when switching tasks, the scheduler only marks the FPU as
+//! unavailable (see [`switch_to`]). The first FPU instruction executed by the new task then
+//! raises a DEVICE_NOT_AVAILABLE fault, whose handler saves the state of the previous owner of
+//! the FPU and restores the state of the current task. Tasks that never use the FPU never pay
+//! the cost of saving and restoring its state.
+
+use core::arch::asm;
+use core::arch::x86::{__cpuid, has_cpuid};
+use core::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use core::sync::atomic::{AtomicBool, AtomicPtr};
+
+use crate::log;
+use crate::utility::instr::{Cr0, Cr4};
+use crate::utility::RestoreInterrupts;
+
+/// The bit of the EDX register returned by `cpuid(1)` indicating that an FPU is present.
+const CPUID_FPU: u32 = 1 << 0;
+/// The bit of the EDX register returned by `cpuid(1)` indicating support for FXSAVE/FXRSTOR.
+const CPUID_FXSR: u32 = 1 << 24;
+/// The bit of the EDX register returned by `cpuid(1)` indicating support for SSE.
+const CPUID_SSE: u32 = 1 << 25;
+
+/// Whether an FPU is available.
+static HAS_FPU: AtomicBool = AtomicBool::new(false);
+/// Whether the FXSAVE and FXRSTOR instructions can be used.
+static HAS_FXSR: AtomicBool = AtomicBool::new(false);
+
+/// Whether the SSE extensions are enabled.
+static HAS_SSE: AtomicBool = AtomicBool::new(false);
+
+/// The state of the task that is currently running, or null if it is not known.
+static CURRENT: AtomicPtr<FpuState> = AtomicPtr::new(core::ptr::null_mut());
+/// The state of the task whose state is currently loaded in the FPU, or null.
+static OWNER: AtomicPtr<FpuState> = AtomicPtr::new(core::ptr::null_mut());
+
+/// The memory area used by the FXSAVE and FXRSTOR instructions.
+///
+/// When they are not available, the smaller layout of FNSAVE is used instead.
+#[repr(C, align(16))]
+struct SaveArea([u8; 512]);
+
+/// The saved state of the FPU and the SSE registers of a task.
+pub struct FpuState {
+    /// The saved registers.
+    area: SaveArea,
+    /// Whether `area` holds a saved state. A task that never used the FPU has no state yet.
+    saved: bool,
+}
+
+impl FpuState {
+    /// Creates a new [`FpuState`] instance.
+    ///
+    /// The FPU is initialized to its default state the first time a task that owns this
+    /// state uses it.
+    pub const fn new() -> Self {
+        Self {
+            area: SaveArea([0; 512]),
+            saved: false,
+        }
+    }
+
+    /// Saves the current state of the FPU into this instance.
+    ///
+    /// # Safety
+    ///
+    /// The FPU must be available (the TS bit of CR0 must be cleared).
+    unsafe fn save(&mut self) {
+        let area = self.area.0.as_mut_ptr();
+        if HAS_FXSR.load(Relaxed) {
+            asm!("fxsave [{}]", in(reg) area, options(nostack, preserves_flags));
+        } else {
+            asm!("fnsave [{}]", in(reg) area, options(nostack, preserves_flags));
+        }
+        self.saved = true;
+    }
+
+    /// Loads the state saved in this instance into the FPU.
+    ///
+    /// # Safety
+    ///
+    /// The FPU must be available.
+    unsafe fn restore(&self) {
+        let area = self.area.0.as_ptr();
+        if !self.saved {
+            asm!("fninit", options(nomem, nostack, preserves_flags));
+        } else if HAS_FXSR.load(Relaxed) {
+            asm!("fxrstor [{}]", in(reg) area, options(nostack, preserves_flags));
+        } else {
+            asm!("frstor [{}]", in(reg) area, options(nostack, preserves_flags));
+        }
+    }
+}
+
+/// Detects and initializes the FPU.
+///
+/// SSE is enabled if the CPU supports it.
+pub fn init() {
+    if !has_cpuid() {
+        log!("The CPU does not support CPUID; the FPU is not enabled.\n");
+        return;
+    }
+
+    let features = unsafe { __cpuid(1) }.edx;
+
+    if features & CPUID_FPU == 0 {
+        log!("The CPU has no FPU.\n");
+        return;
+    }
+
+    let fxsr = features & CPUID_FXSR != 0;
+    let sse = fxsr && features & CPUID_SSE != 0;
+
+    unsafe {
+        let cr0 = Cr0::read() - Cr0::EMULATION;
+        (cr0 | Cr0::MONITOR_COPROCESSOR | Cr0::NUMERIC_ERROR).write();
+
+        if fxsr {
+            let mut cr4 = Cr4::read() | Cr4::OSFXSR;
+            cr4.set(Cr4::OSXMMEXCPT, sse);
+            cr4.write();
+        }
+
+        asm!("fninit", options(nomem, nostack, preserves_flags));
+    }
+
+    HAS_FXSR.store(fxsr, Relaxed);
+    HAS_SSE.store(sse, Relaxed);
+    HAS_FPU.store(true, Release);
+
+    log!("FPU enabled (FXSR: {fxsr}, SSE: {sse}).\n");
+}
+
+/// Returns whether the FPU has been enabled.
+#[inline]
+pub fn is_enabled() -> bool {
+    HAS_FPU.load(Acquire)
+}
+
+/// Notifies the FPU code that the task whose FPU state is `state` is about to run.
+///
+/// This is meant to be called by the scheduler on every task switch. The FPU is marked as
+/// unavailable, so that its state is only switched when the new task actually uses it.
+///
+/// # Safety
+///
+/// `state` must remain valid until another state is passed to this function, or until it is
+/// passed to [`forget`].
+pub unsafe fn switch_to(state: *mut FpuState) {
+    CURRENT.store(state, Relaxed);
+
+    if !is_enabled() {
+        return;
+    }
+
+    // Set the TS bit of CR0.
+    asm!(
+        "
+        mov {tmp}, cr0
+        or {tmp}, 0x8
+        mov cr0, {tmp}
+        ",
+        tmp = out(reg) _,
+        options(nomem, nostack, preserves_flags),
+    );
+}
+
+/// Runs `f` with the SSE registers available to the kernel, and returns what it returns.
+///
+/// The state of the task that owns the FPU is saved first, and the state of the running task
+/// is loaded again the next time it uses the FPU. Interrupts are disabled while `f` runs.
+/// `None` is returned without calling `f` when SSE is not enabled.
+pub fn with_sse<R>(f: impl FnOnce() -> R) -> Option<R> {
+    if !is_enabled() || !HAS_SSE.load(Relaxed) {
+        return None;
+    }
+
+    let _without_interrupts = RestoreInterrupts::without_interrupts();
+    unsafe {
+        asm!("clts", options(nomem, nostack, preserves_flags));
+        let owner = OWNER.swap(core::ptr::null_mut(), Relaxed);
+        if !owner.is_null() {
+            (*owner).save();
+        }
+    }
+
+    let ret = f();
+
+    // The registers now hold the values of the kernel: the next FPU instruction must fault,
+    // so that the state of the running task is restored.
+    unsafe {
+        asm!(
+            "
+            mov {tmp}, cr0
+            or {tmp}, 0x8
+            mov cr0, {tmp}
+            ",
+            tmp = out(reg) _,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    Some(ret)
+}
+
+/// Notifies the FPU code that the provided state is about to be destroyed.
+pub fn forget(state: *mut FpuState) {
+    let _ = OWNER.compare_exchange(state, core::ptr::null_mut(), Relaxed, Relaxed);
+    let _ = CURRENT.compare_exchange(state, core::ptr::null_mut(), Relaxed, Relaxed);
+}
+
+/// Handles a DEVICE_NOT_AVAILABLE fault.
+///
+/// Returns whether the fault was caused by the lazy switching of the FPU state. If it was not,
+/// the FPU is simply not available and the fault cannot be recovered from.
+pub fn handle_device_not_available() -> bool {
+    if !is_enabled() {
+        return false;
+    }
+
+    unsafe {
+        asm!("clts", options(nomem, nostack, preserves_flags));
+
+        let current = CURRENT.load(Relaxed);
+        let owner = OWNER.swap(current, Relaxed);
+
+        if owner == current {
+            return true;
+        }
+
+        if !owner.is_null() {
+            (*owner).save();
+        }
+
+        if current.is_null() {
+            // The running task is not known. It must not see the state of the previous one.
+            asm!("fninit", options(nomem, nostack, preserves_flags));
+        } else {
+            (*current).restore();
+        }
+    }
+
+    true
+}
@@ -2,7 +2,6 @@
 
 use core::arch::asm;
 
-use crate::log;
 use crate::utility::instr::{lgdt, DescriptorTablePointer};
 
 /// The address at which the GDT must be loaded.
@@ -12,9 +11,15 @@ const ADDRESS: *mut u64 = 0x800 as *mut u64;
 pub const KERNEL_DATA_SEGMENT: u16 = 0x10;
 /// The offset of the kernel code segment within the kernel's GDT.
 pub const KERNEL_CODE_SEGMENT: u16 = 0x08;
+/// The offset of the user code segment within the kernel's GDT.
+pub const USER_CODE_SEGMENT: u16 = 0x18;
+/// The offset of the user data segment within the kernel's GDT.
+pub const USER_DATA_SEGMENT: u16 = 0x20;
+/// The offset of the TSS descriptor within the kernel's GDT.
+pub const TSS_SEGMENT: u16 = 0x28;
 
 /// The GDT that will be copied and loaded.
-const GDT: [u64; 5] = [
+const GDT: [u64; 6] = [
     // Null Segment
     0u64,
     // Kernel Mode Code Segment
@@ -25,11 +30,13 @@ const GDT: [u64; 5] = [
     0x00cffa000000ffff,
     // User Mode Data Segment
     0x00cff2000000ffff,
+    // Task State Segment (filled in by `init`)
+    0u64,
 ];
 
 /// The GDTP that will be loaded with `lgdt`.
 const GDTP: DescriptorTablePointer = DescriptorTablePointer {
-    limit: 5 * 8 - 1,
+    limit: GDT.len() as u16 * 8 - 1,
     base: ADDRESS as *mut (),
 };
 
@@ -40,6 +47,10 @@ const GDTP: DescriptorTablePointer = DescriptorTablePointer {
 /// The memory address where the GDT is installed must not currently be in use.
 pub unsafe fn init() {
     core::ptr::copy_nonoverlapping(GDT.as_ptr(), ADDRESS, GDT.len());
+    // The address of the TSS is not known at compile time.
+    ADDRESS
+        .add(TSS_SEGMENT as usize / 8)
+        .write(super::tss::descriptor());
 
     lgdt(&GDTP);
 
@@ -66,4 +77,11 @@ pub unsafe fn init() {
         code_segment_offset = const KERNEL_CODE_SEGMENT,
         options(att_syntax)
     );
+
+    // Load the task register.
+    asm!(
+        "ltr {:x}",
+        in(reg) TSS_SEGMENT,
+        options(nomem, nostack, preserves_flags)
+    );
 }
@@ -1,12 +1,16 @@
 //! Defines the interrupt service routines for CPU exceptions.
 
-use core::arch::asm;
-
 use bitflags::bitflags;
 
+use crate::cpu::paging::{self, uaccess};
+use crate::state::GLOBAL;
+use crate::utility::instr::read_cr2;
+use crate::{reaper, sched};
+
 use super::InterruptStackFrame;
 
-pub extern "x86-interrupt" fn division_error(_stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn division_error(frame: InterruptStackFrame) {
+    kill_if_user(&frame);
     panic!("Received a DIVISION_ERROR fault.");
 }
 
@@ -22,19 +26,26 @@ pub extern "x86-interrupt" fn breakpoint(_stack_frame: InterruptStackFrame) {
     panic!("Received a BREAKPOINT trap.");
 }
 
-pub extern "x86-interrupt" fn overflow(_stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn overflow(frame: InterruptStackFrame) {
+    kill_if_user(&frame);
     panic!("Received an OVERFLOW trap.");
 }
 
-pub extern "x86-interrupt" fn bound_range_exceeded(_stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn bound_range_exceeded(frame: InterruptStackFrame) {
+    kill_if_user(&frame);
     panic!("Received a BOUND_RANGE_EXCEEDED fault.");
 }
 
-pub extern "x86-interrupt" fn invalid_opcode(_stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn invalid_opcode(frame: InterruptStackFrame) {
+    kill_if_user(&frame);
     panic!("Received an INVALID_OPCODE fault.");
 }
 
 pub extern "x86-interrupt" fn device_not_available(_stack_frame: InterruptStackFrame) {
+    if crate::cpu::fpu::handle_device_not_available() {
+        return;
+    }
+
     panic!("Received a DEVICE_NOT_AVAILABLE fault.");
 }
 
@@ -76,6 +87,7 @@ pub extern "x86-interrupt" fn general_protection_fault(
     frame: InterruptStackFrame,
     error_code: u32,
 ) {
+    kill_if_user(&frame);
     panic!(
         "\
         Received a GENERAL_PROTECTION_FAULT fault with error code {:#x}.\n\
@@ -106,11 +118,32 @@ bitflags! {
 }
 
 pub extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, error_code: PageFaultError) {
-    let mut cr2: usize;
-    unsafe {
-        asm!("mov {}, cr2", out(reg) cr2, options(nostack, nomem, preserves_flags));
+    let cr2 = read_cr2();
+
+    // The page may belong to memory mapped on demand.
+    if !error_code.contains(PageFaultError::PRESENT) {
+        let handled = paging::mmap::handle_fault(
+            cr2 as usize,
+            error_code.contains(PageFaultError::WRITE),
+            error_code.contains(PageFaultError::INSTRUCTION_FETCH),
+        );
+        match handled {
+            Ok(true) => return,
+            Ok(false) => (),
+            // A user program cannot go on without the page, but the rest of the system can.
+            Err(_) if frame.cs & 3 == 3 => kill_current(),
+            Err(_) => (),
+        }
+    }
+
+    // The kernel was accessing the memory of the process on its behalf.
+    if uaccess::is_copying() && paging::spaces::user_range().contains(&(cr2 as usize)) {
+        uaccess::abort_copy();
+        kill_current();
     }
 
+    // Any other fault of a user program is its own bug.
+    kill_if_user(&frame);
     panic!(
         "\
         Received a PAGE_FAULT fault.\n\
@@ -123,6 +156,22 @@ pub extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, error_code:
     );
 }
 
+/// Kills the current process if the fault described by `frame` happened in user mode.
+///
+/// The faults of user programs must not bring the kernel down.
+fn kill_if_user(frame: &InterruptStackFrame) {
+    if frame.cs & 3 == 3 {
+        kill_current();
+    }
+}
+
+/// Kills the current process after a fault it caused, and exits the current thread.
+pub(super) fn kill_current() -> ! {
+    let pid = GLOBAL.get().unwrap().processes.lock().current_id();
+    reaper::kill(pid);
+    sched::exit(reaper::KILLED_STATUS);
+}
+
 pub extern "x86-interrupt" fn x87_floating_point(_stack_frame: InterruptStackFrame) {
     panic!("Received an X87_FLOATING_POINT fault.");
 }
@@ -0,0 +1,20 @@
+//! The handlers of the interrupts raised by the local APIC.
+
+use crate::drivers::lapic;
+
+use super::InterruptStackFrame;
+
+/// The tick of the current processor.
+///
+/// The wall-clock and the timer callbacks are driven by the PIT instead.
+pub extern "x86-interrupt" fn timer(stack_frame: InterruptStackFrame) {
+    let tick = lapic::count_tick();
+    crate::profiler::sample(&stack_frame, tick);
+    crate::sched::account_tick(stack_frame.cs & 3 != 0);
+    lapic::end_of_interrupt();
+}
+
+/// The local APIC raises this vector when the interrupt it was delivering went away.
+///
+/// It must not be acknowledged.
+pub extern "x86-interrupt" fn spurious(_stack_frame: InterruptStackFrame) {}
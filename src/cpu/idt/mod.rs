@@ -1,9 +1,15 @@
 //! Defines the Interrupt Descriptor Table that the kernel will use.
 
 mod exceptions;
+mod ipi;
 mod pic;
 mod syscall;
 
+pub use self::syscall::set_sysenter_stack;
+// Only reported through `/proc`.
+#[cfg_attr(not(feature = "fs"), allow(unused_imports))]
+pub use self::syscall::syscall_mechanism;
+
 use crate::utility::instr::{lidt, DescriptorTablePointer};
 
 use super::gdt::KERNEL_CODE_SEGMENT;
@@ -24,6 +30,14 @@ static IDTP: DescriptorTablePointer = DescriptorTablePointer {
 /// The following 32 interrupts (32 to 63) are reserved for the PIC.
 pub const PIC_OFFSET: u8 = 32;
 
+/// The vector of the timer of the local APIC.
+pub const LAPIC_TIMER_VECTOR: u8 = 0xEF;
+
+/// The vector of the spurious interrupts of the local APIC.
+///
+/// Its lowest four bits must be set on older processors.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
 /// The stack frame that is pushed onto the stack when an interrupt is triggered.
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -90,8 +104,14 @@ pub fn init() {
 
         IDT[0x80] = create_gate_descriptor(false, syscall::system_call as usize);
 
+        IDT[LAPIC_TIMER_VECTOR as usize] = create_gate_descriptor(true, ipi::timer as usize);
+        IDT[SPURIOUS_VECTOR as usize] = create_gate_descriptor(true, ipi::spurious as usize);
+
         lidt(&IDTP);
     }
+
+    let mechanism = syscall::init_sysenter();
+    crate::log!("System calls: {}\n", mechanism.name());
 }
 
 /// Creates a gate descriptor suitable for the IDT.
@@ -1,12 +1,13 @@
 use core::sync::atomic::Ordering::Relaxed;
 
-use crate::drivers::{pic, ps2};
+use crate::drivers::{keyboard, lapic, pic, ps2};
 use crate::state::GLOBAL;
+use crate::trace::trace;
 use crate::{printk, TERMINAL};
 
 use super::InterruptStackFrame;
 
-pub unsafe extern "x86-interrupt" fn timer(_stack_frame: InterruptStackFrame) {
+pub unsafe extern "x86-interrupt" fn timer(stack_frame: InterruptStackFrame) {
     let glob = GLOBAL.get_unchecked();
 
     // Update the global tick count.
@@ -16,35 +17,70 @@ pub unsafe extern "x86-interrupt" fn timer(_stack_frame: InterruptStackFrame) {
     let old_value = glob.system_info.tick_count.fetch_add(1, Relaxed);
     assert!(old_value != u32::MAX, "The tick count overflowed.");
 
+    // Once the timer of the local APIC runs, it charges the CPU time instead.
+    if lapic::timer_hz().is_none() {
+        crate::profiler::sample(&stack_frame, old_value + 1);
+        crate::sched::account_tick(stack_frame.cs & 3 != 0);
+    }
+    crate::timer::tick(old_value + 1);
+
     pic::end_of_interrupt(pic::Irq::Timer);
 }
 
 pub unsafe extern "x86-interrupt" fn keyboard(_stack_frame: InterruptStackFrame) {
+    ps2_data(ps2::Port::First);
+    pic::end_of_interrupt(pic::Irq::Keyboard);
+}
+
+/// Handles a byte received from `port` of the PS/2 controller.
+///
+/// It is routed to the keyboard or to the mouse, depending on the device identified on the port.
+fn ps2_data(port: ps2::Port) {
     // Check the status register of the PS/2 controller. When the interrupt is received, the
-    // output buffer should be full. It's probably not necessary to check, but it's probably
-    // a good idea.
+    // output buffer should be full, unless the byte was already consumed by someone polling
+    // the controller while interrupts were disabled (such as the pager of the terminal). In
+    // that case, there is nothing to do.
     if !ps2::is_output_buffer_full() {
-        printk!(
-            "\
-        	WARN: a keyboard interrupt was received (IRQ1), but the output buffer\n\
-         	of the PS/2 controller is empty.\n\
-            "
-        );
         return;
     }
 
-    // Send the scancode to the terminal.
-    // Note: reading the scancode is *necessary* to clear the PS/2 controller's output buffer.
+    // Note: reading the byte is *necessary* to clear the PS/2 controller's output buffer.
     // Without this, no new interrupts will be received.
+    let byte = ps2::read_data();
+    if ps2::kind(port) == Some(ps2::DeviceKind::Mouse) {
+        if !TERMINAL.lock().buffer_mouse_byte(byte) {
+            printk!("WARN: the mouse buffer is full; we are dropping mouse packets.\n");
+        }
+        crate::terminal::INPUT.wake_all();
+        return;
+    }
+
+    // The scan-code is only buffered here, and processed by the main loop.
+    crate::rng::add_event_timing(byte as u32);
+    trace!("irq", "keyboard scan-code {byte:#04x}");
 
-    // TODO: buffer the scancode and process it in the main loop. Doing too much processing
-    // in the IRQ handler will probably end up blocking the system.
-    if !TERMINAL.lock().buffer_scancode(ps2::read_data()) {
-        // The terminal buffer is full. We are probably lagging behind.
-        printk!("WARN: the terminal buffer is full; we are dropping scancodes.\n");
+    // The keyboard may have been unplugged and plugged in again.
+    let kind = keyboard::classify(byte);
+    if kind != keyboard::Byte::Scancode {
+        keyboard::handle(byte, kind);
+        return;
     }
 
-    pic::end_of_interrupt(pic::Irq::Keyboard);
+    let mut term = TERMINAL.lock();
+    let was_dropping = term.has_dropped_scancodes();
+    if !term.buffer_scancode(byte) {
+        drop(term);
+        // The main loop is lagging behind. What the controller still holds for the keyboard
+        // would be dropped as well, and flushing it lets the keyboard start again from an
+        // empty buffer. The bytes of the mouse are left alone, as dropping one of them would
+        // break its packets.
+        let flushed = ps2::flush_port_output(port);
+        trace!("irq", "scan-code buffer full, {flushed} bytes flushed");
+        if !was_dropping {
+            printk!("WARN: the terminal buffer is full; we are dropping scancodes.\n");
+        }
+    }
+    crate::terminal::INPUT.wake_all();
 }
 
 pub extern "x86-interrupt" fn cascade(_stack_frame: InterruptStackFrame) {
@@ -87,8 +123,9 @@ pub extern "x86-interrupt" fn periph3(_stack_frame: InterruptStackFrame) {
     panic!("Received a PERIPH3 interrupt (IRQ11).");
 }
 
-pub extern "x86-interrupt" fn mouse(_stack_frame: InterruptStackFrame) {
-    panic!("Received a MOUSE interrupt (IRQ12).");
+pub unsafe extern "x86-interrupt" fn mouse(_stack_frame: InterruptStackFrame) {
+    ps2_data(ps2::Port::Second);
+    pic::end_of_interrupt(pic::Irq::Mouse);
 }
 
 pub extern "x86-interrupt" fn fpu(_stack_frame: InterruptStackFrame) {
@@ -1,6 +1,20 @@
 use core::arch::asm;
+use core::arch::x86::__cpuid;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::Relaxed;
 
-use crate::printk;
+use crate::cpu::gdt::{KERNEL_CODE_SEGMENT, USER_CODE_SEGMENT};
+use crate::cpu::paging::uaccess::{UserPtr, UserSlice};
+use crate::cpu::{paging, tss};
+use crate::error::KernelError;
+#[cfg(feature = "net")]
+use crate::net::{tcp, Ipv4Addr, SocketAddr};
+use crate::reaper::{self, WaitError};
+use crate::state::{self, Process, ProcessId, Resource, StraceOutput, GLOBAL, ROOT};
+use crate::trace::trace;
+use crate::utility::instr::Msr;
+use crate::utility::ArrayVec;
+use crate::{log, mqueue, printk, sched, shm, time};
 
 use super::InterruptStackFrame;
 
@@ -16,14 +30,19 @@ pub unsafe extern "x86-interrupt" fn system_call(_stack_frame: InterruptStackFra
         // Already on the stack, we have:
         //   ss, sp, flags, cs, ip
         //
+        // The direction flag is still the one of the program, and the kernel expects it to
+        // be clear (`memcpy` uses `rep movsd`). `iretd` restores it.
+        //
         // The idea is to match the system call ABI of Linux, which is:
         "\
+        cld
+        push dword ptr [esp + 4]
         push edx
         push ecx
         push ebx
         push eax
         call {}
-        add esp, 16
+        add esp, 20
         iretd
         ",
         sym inner,
@@ -31,8 +50,969 @@ pub unsafe extern "x86-interrupt" fn system_call(_stack_frame: InterruptStackFra
     );
 }
 
+/// Whether system calls can be made with the `sysenter` instruction.
+static SYSENTER_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The instruction with which user programs make system calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallMechanism {
+    /// The `int 0x80` instruction, which always works.
+    Interrupt,
+    /// The `sysenter` instruction, which is faster. `int 0x80` still works.
+    Sysenter,
+}
+
+impl SyscallMechanism {
+    /// Returns the name of the mechanism.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Interrupt => "int 0x80",
+            Self::Sysenter => "sysenter",
+        }
+    }
+}
+
+/// Returns the fastest mechanism with which system calls can be made.
+pub fn syscall_mechanism() -> SyscallMechanism {
+    if SYSENTER_ENABLED.load(Relaxed) {
+        SyscallMechanism::Sysenter
+    } else {
+        SyscallMechanism::Interrupt
+    }
+}
+
+/// Returns whether the CPU supports the `sysenter` and `sysexit` instructions.
+fn is_sysenter_supported() -> bool {
+    if !Msr::SysenterCs.is_supported() {
+        return false;
+    }
+
+    // The first Pentium Pro processors report the feature without supporting it.
+    let eax = unsafe { __cpuid(1).eax };
+    let (family, model, stepping) = ((eax >> 8) & 0xF, (eax >> 4) & 0xF, eax & 0xF);
+    !(family == 6 && model < 3 && stepping < 3)
+}
+
+/// Configures the `sysenter` instruction, if the CPU supports it.
+///
+/// The kernel stack used by `sysenter` is the one of the TSS (see
+/// [`tss::set_kernel_stack`]), which must already be set.
+pub fn init_sysenter() -> SyscallMechanism {
+    if !is_sysenter_supported() {
+        return SyscallMechanism::Interrupt;
+    }
+
+    // The stack segment is the one that follows the code segment, and the user segments follow
+    // them, which matches the layout of the GDT.
+    let ok = unsafe {
+        Msr::SysenterCs.write(KERNEL_CODE_SEGMENT as u64)
+            && Msr::SysenterEsp.write(tss::kernel_stack() as u64)
+            && Msr::SysenterEip.write(sysenter_entry as usize as u64)
+    };
+    SYSENTER_ENABLED.store(ok, Relaxed);
+    syscall_mechanism()
+}
+
+/// Updates the stack used by `sysenter`, if it is enabled.
+pub fn set_sysenter_stack(esp0: u32) {
+    if SYSENTER_ENABLED.load(Relaxed) {
+        unsafe { Msr::SysenterEsp.write(esp0 as u64) };
+    }
+}
+
+/// The entry point of the system calls made with `sysenter`.
+///
+/// The registers are the same as with `int 0x80`, except for the second and third arguments,
+/// which are read from the user stack. The calling sequence is:
+///
+/// ```text
+/// push offset 2f  ; the address at which the program resumes
+/// push ecx        ; the second argument
+/// push edx        ; the third argument
+/// push ebp
+/// mov ebp, esp
+/// sysenter
+/// 2:
+/// pop ebp
+/// pop edx
+/// pop ecx
+/// add esp, 4
+/// ```
+///
+/// The return value is in `eax`, like with `int 0x80`. The kernel never trusts `ebp`: the
+/// words it points to are copied from the memory of the process (see [`sysenter_inner`]).
+///
+/// # Safety
+///
+/// This function expects to be called from user mode with `sysenter`.
+#[naked]
+unsafe extern "C" fn sysenter_entry() {
+    asm!(
+        // `sysenter` disabled the interrupts and switched to the kernel stack, but left the
+        // direction flag of the program, which the kernel expects to be clear.
+        // `ebp` is preserved by the call, as the calling convention requires.
+        "
+        cld
+        push ebp
+        push ebx
+        push eax
+        call {inner}
+        add esp, 12
+        ",
+        // `sysexit` resumes the program at `edx` with its stack pointer set to `ecx`. The
+        // interrupts are enabled again once `sysexit` has run.
+        "
+        mov ecx, ebp
+        sti
+        sysexit
+        ",
+        inner = sym sysenter_inner,
+        options(noreturn)
+    );
+}
+
+/// The inner function of [`sysenter_entry`].
+///
+/// `frame` is the value of `ebp` in the program. The second and third arguments, and the address
+/// at which the program resumes, are copied from it. When the arguments cannot be read, the
+/// system call fails with [`KernelError::BadAddress`]. When the resume address cannot be read,
+/// there is nowhere to return to, and the process is killed.
+///
+/// Returns the value of the system call in the low half, and the resume address in the high
+/// half, which end up in `eax` and `edx`.
+extern "C" fn sysenter_inner(sysno: u32, arg0: usize, frame: usize) -> u64 {
+    let user_range = paging::spaces::user_range();
+    let in_range = frame >= user_range.start
+        && frame
+            .checked_add(16)
+            .is_some_and(|end| end <= user_range.end);
+
+    let resume = match UserPtr::<usize>::new(frame.wrapping_add(12)).read() {
+        Ok(resume) if in_range => resume,
+        _ => super::exceptions::kill_current(),
+    };
+    let ret = match UserPtr::<[usize; 2]>::new(frame.wrapping_add(4)).read() {
+        Ok([arg2, arg1]) => inner(sysno, arg0, arg1, arg2, USER_CODE_SEGMENT as u32 | 3),
+        Err(err) => err.errno().wrapping_neg() as usize,
+    };
+    (resume as u64) << 32 | ret as u64
+}
+
+/// The number of the `exit` system call, as on Linux.
+///
+/// Only the calling thread exits. When it is the last thread of its process, the process exits
+/// with the provided status.
+const SYS_EXIT: u32 = 1;
+/// The number of the `close` system call, as on Linux.
+///
+/// Sockets are the only objects that are closed.
+const SYS_CLOSE: u32 = 6;
+/// The number of the `waitpid` system call, as on Linux.
+const SYS_WAITPID: u32 = 7;
+/// The number of the `getpid` system call, as on Linux.
+const SYS_GETPID: u32 = 20;
+/// The number of the `gettimeofday` system call, as on Linux.
+const SYS_GETTIMEOFDAY: u32 = 78;
+/// The number of the `mmap` system call, as on Linux.
+///
+/// Like the original `mmap` of Linux, it takes a pointer to its arguments.
+const SYS_MMAP: u32 = 90;
+/// The number of the `munmap` system call, as on Linux.
+const SYS_MUNMAP: u32 = 91;
+/// The number of the `ioperm` system call, as on Linux.
+const SYS_IOPERM: u32 = 101;
+/// The number of the `mprotect` system call, as on Linux.
+const SYS_MPROTECT: u32 = 125;
+/// The number of the `gettid` system call, as on Linux.
+const SYS_GETTID: u32 = 224;
+/// The number of the `socket` system call, as on Linux.
+const SYS_SOCKET: u32 = 359;
+/// The number of the `bind` system call, as on Linux.
+const SYS_BIND: u32 = 361;
+/// The number of the `connect` system call, as on Linux.
+const SYS_CONNECT: u32 = 362;
+/// The number of the `listen` system call, as on Linux.
+const SYS_LISTEN: u32 = 363;
+
+/// The number of the `shm_open` system call.
+///
+/// Linux has no such system call, as `shm_open` is implemented on top of a file-system there.
+const SYS_SHM_OPEN: u32 = 500;
+/// The number of the `shm_unlink` system call.
+const SYS_SHM_UNLINK: u32 = 501;
+/// The number of the `mq_open` system call.
+const SYS_MQ_OPEN: u32 = 502;
+/// The number of the `mq_unlink` system call.
+const SYS_MQ_UNLINK: u32 = 503;
+/// The number of the `mq_send` system call.
+///
+/// It takes a pointer to its arguments.
+const SYS_MQ_SEND: u32 = 504;
+/// The number of the `mq_receive` system call.
+///
+/// It takes a pointer to its arguments.
+const SYS_MQ_RECEIVE: u32 = 505;
+/// The number of the `thread_create` system call.
+///
+/// Linux creates threads with `clone`, whose interface is much more general.
+const SYS_THREAD_CREATE: u32 = 506;
+/// The number of the `process_create` system call.
+///
+/// Processes share the address space of the kernel, so there is no `fork`: a new process starts
+/// with a single thread, like one created with `thread_create`.
+const SYS_PROCESS_CREATE: u32 = 507;
+/// The number of the `accept` system call.
+///
+/// Linux only has `accept4` on i386, which takes a fourth argument.
+const SYS_ACCEPT: u32 = 508;
+/// The number of the `send` system call.
+///
+/// Linux sends with `sendto`, which takes six arguments.
+const SYS_SEND: u32 = 509;
+/// The number of the `recv` system call.
+///
+/// Linux receives with `recvfrom`, which takes six arguments.
+const SYS_RECV: u32 = 510;
+
+/// `waitpid` returns immediately if no child exited.
+const WNOHANG: usize = 1;
+
+/// The mapping is shared with the other processes that map it.
+const MAP_SHARED: usize = 0x01;
+/// The mapping is private to the process.
+const MAP_PRIVATE: usize = 0x02;
+/// The mapping must be placed at the requested address.
+const MAP_FIXED: usize = 0x10;
+/// The mapping is not backed by a file.
+const MAP_ANONYMOUS: usize = 0x20;
+
+#[cfg(feature = "net")]
+/// The address family of IPv4.
+const AF_INET: usize = 2;
+#[cfg(feature = "net")]
+/// The type of the sockets that carry a stream of bytes.
+const SOCK_STREAM: usize = 1;
+#[cfg(feature = "net")]
+/// The protocol number of TCP.
+const IPPROTO_TCP: usize = 6;
+#[cfg(feature = "net")]
+/// The size of the `sockaddr_in` structure, which holds an IPv4 address and a port.
+const SOCKADDR_IN_LEN: usize = 16;
+
+/// The value returned by a system call, or the error that made it fail.
+type SyscallResult = Result<usize, KernelError>;
+
+/// The kind of an argument of a system call, used to decode it when it is traced.
+#[derive(Debug, Clone, Copy)]
+enum Argument {
+    /// A pointer, displayed in hexadecimal (`NULL` when zero).
+    Pointer,
+    /// An unsigned integer.
+    Unsigned,
+    /// A boolean.
+    Bool,
+}
+
+/// A system call known by the kernel.
+struct Syscall {
+    /// The number of the system call.
+    number: u32,
+    /// The name of the system call.
+    name: &'static str,
+    /// The arguments of the system call.
+    args: &'static [Argument],
+}
+
+/// The system calls that are decoded when they are traced.
+const SYSCALLS: [Syscall; 25] = [
+    Syscall {
+        number: SYS_EXIT,
+        name: "exit",
+        args: &[Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_CLOSE,
+        name: "close",
+        args: &[Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_WAITPID,
+        name: "waitpid",
+        args: &[Argument::Unsigned, Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_GETPID,
+        name: "getpid",
+        args: &[],
+    },
+    Syscall {
+        number: SYS_GETTIMEOFDAY,
+        name: "gettimeofday",
+        args: &[Argument::Pointer, Argument::Pointer],
+    },
+    Syscall {
+        number: SYS_MMAP,
+        name: "mmap",
+        args: &[Argument::Pointer],
+    },
+    Syscall {
+        number: SYS_MUNMAP,
+        name: "munmap",
+        args: &[Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_IOPERM,
+        name: "ioperm",
+        args: &[Argument::Unsigned, Argument::Unsigned, Argument::Bool],
+    },
+    Syscall {
+        number: SYS_MPROTECT,
+        name: "mprotect",
+        args: &[Argument::Pointer, Argument::Unsigned, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_GETTID,
+        name: "gettid",
+        args: &[],
+    },
+    Syscall {
+        number: SYS_SOCKET,
+        name: "socket",
+        args: &[Argument::Unsigned, Argument::Unsigned, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_BIND,
+        name: "bind",
+        args: &[Argument::Unsigned, Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_CONNECT,
+        name: "connect",
+        args: &[Argument::Unsigned, Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_LISTEN,
+        name: "listen",
+        args: &[Argument::Unsigned, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_SHM_OPEN,
+        name: "shm_open",
+        args: &[Argument::Pointer, Argument::Unsigned, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_SHM_UNLINK,
+        name: "shm_unlink",
+        args: &[Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_MQ_OPEN,
+        name: "mq_open",
+        args: &[Argument::Pointer, Argument::Unsigned, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_MQ_UNLINK,
+        name: "mq_unlink",
+        args: &[Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_MQ_SEND,
+        name: "mq_send",
+        args: &[Argument::Unsigned, Argument::Pointer],
+    },
+    Syscall {
+        number: SYS_MQ_RECEIVE,
+        name: "mq_receive",
+        args: &[Argument::Unsigned, Argument::Pointer],
+    },
+    Syscall {
+        number: SYS_THREAD_CREATE,
+        name: "thread_create",
+        args: &[Argument::Pointer, Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_PROCESS_CREATE,
+        name: "process_create",
+        args: &[Argument::Pointer, Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_ACCEPT,
+        name: "accept",
+        args: &[Argument::Unsigned, Argument::Pointer],
+    },
+    Syscall {
+        number: SYS_SEND,
+        name: "send",
+        args: &[Argument::Unsigned, Argument::Pointer, Argument::Unsigned],
+    },
+    Syscall {
+        number: SYS_RECV,
+        name: "recv",
+        args: &[Argument::Unsigned, Argument::Pointer, Argument::Unsigned],
+    },
+];
+
 /// The inner function of the system call handler.
-extern "C" fn inner(sysno: u32, arg0: usize, arg1: usize, arg2: usize) -> usize {
+///
+/// `cs` is the code segment of the caller, which tells whether it runs in user mode.
+extern "C" fn inner(sysno: u32, arg0: usize, arg1: usize, arg2: usize, cs: u32) -> usize {
+    trace!("syscall", "{sysno} ({arg0:#x}, {arg1:#x}, {arg2:#x})");
+
+    let strace = GLOBAL
+        .get()
+        .and_then(|glob| glob.processes.lock().current().strace);
+    if let Some(output) = strace {
+        let call = DecodedCall(sysno, [arg0, arg1, arg2]);
+        emit(output, format_args!("strace: {call} ...\n"));
+    }
+
+    let ret = match sysno {
+        SYS_EXIT => sched::exit(arg0 as u8),
+        #[cfg(feature = "net")]
+        SYS_CLOSE => sys_close(arg0),
+        SYS_WAITPID => sys_waitpid(arg0 as isize, arg1, arg2),
+        SYS_GETPID => sys_getpid(),
+        SYS_GETTIMEOFDAY => sys_gettimeofday(arg0, arg1),
+        SYS_MMAP => sys_mmap(arg0),
+        SYS_MUNMAP => sys_munmap(arg0, arg1),
+        SYS_IOPERM => sys_ioperm(arg0, arg1, arg2 != 0),
+        SYS_MPROTECT => sys_mprotect(arg0, arg1, arg2),
+        SYS_GETTID => Ok(sched::current() as usize),
+        #[cfg(feature = "net")]
+        SYS_SOCKET => sys_socket(arg0, arg1, arg2),
+        #[cfg(feature = "net")]
+        SYS_BIND => sys_bind(arg0, arg1, arg2),
+        #[cfg(feature = "net")]
+        SYS_CONNECT => sys_connect(arg0, arg1, arg2),
+        #[cfg(feature = "net")]
+        SYS_LISTEN => sys_listen(arg0, arg1),
+        SYS_SHM_OPEN => sys_shm_open(arg0, arg1, arg2),
+        SYS_SHM_UNLINK => sys_shm_unlink(arg0, arg1),
+        SYS_MQ_OPEN => sys_mq_open(arg0, arg1, arg2),
+        SYS_MQ_UNLINK => sys_mq_unlink(arg0, arg1),
+        SYS_MQ_SEND => sys_mq_send(arg0, arg1),
+        SYS_MQ_RECEIVE => sys_mq_receive(arg0, arg1),
+        SYS_THREAD_CREATE => sys_thread_create(arg0, arg1, arg2, cs & 3 == 3),
+        SYS_PROCESS_CREATE => sys_process_create(arg0, arg1, arg2, cs & 3 == 3),
+        #[cfg(feature = "net")]
+        SYS_ACCEPT => sys_accept(arg0, arg1),
+        #[cfg(feature = "net")]
+        SYS_SEND => sys_send(arg0, arg1, arg2),
+        #[cfg(feature = "net")]
+        SYS_RECV => sys_recv(arg0, arg1, arg2),
+        // Sockets are the only objects that can be closed.
+        #[cfg(not(feature = "net"))]
+        SYS_CLOSE | SYS_SOCKET | SYS_BIND | SYS_CONNECT | SYS_LISTEN | SYS_ACCEPT | SYS_SEND
+        | SYS_RECV => Err(KernelError::NotImplemented),
+        _ => Ok(debug(sysno, arg0, arg1, arg2)),
+    };
+    // Errors are returned as negated error codes, like on Linux.
+    let ret = ret.unwrap_or_else(|err| err.errno().wrapping_neg() as usize);
+
+    if let Some(output) = strace {
+        let call = DecodedCall(sysno, [arg0, arg1, arg2]);
+        emit(
+            output,
+            format_args!("strace: {call} = {}\n", DecodedReturn(ret)),
+        );
+    }
+
+    // A process killed in the meantime never returns to user mode.
+    if reaper::is_current_killed() {
+        sched::exit(reaper::KILLED_STATUS);
+    }
+
+    ret
+}
+
+/// Writes a line of the trace of a system call to `output`.
+fn emit(output: StraceOutput, line: core::fmt::Arguments) {
+    match output {
+        StraceOutput::Terminal => printk!("{line}"),
+        StraceOutput::Serial => log!("{line}"),
+    }
+}
+
+/// Formats a system call and its arguments, such as `ioperm(0x60, 1, true)`.
+struct DecodedCall(u32, [usize; 3]);
+
+impl core::fmt::Display for DecodedCall {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let Some(syscall) = SYSCALLS.iter().find(|s| s.number == self.0) else {
+            return write!(
+                f,
+                "syscall_{}({:#x}, {:#x}, {:#x})",
+                self.0, self.1[0], self.1[1], self.1[2],
+            );
+        };
+
+        write!(f, "{}(", syscall.name)?;
+        for (i, (kind, &value)) in syscall.args.iter().zip(self.1.iter()).enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            match kind {
+                Argument::Pointer if value == 0 => f.write_str("NULL")?,
+                Argument::Pointer => write!(f, "{value:#x}")?,
+                Argument::Unsigned => write!(f, "{value}")?,
+                Argument::Bool => write!(f, "{}", value != 0)?,
+            }
+        }
+        f.write_str(")")
+    }
+}
+
+/// Formats the value returned by a system call, decoding the error codes.
+struct DecodedReturn(usize);
+
+impl core::fmt::Display for DecodedReturn {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let value = self.0 as isize;
+        match KernelError::from_errno(value.wrapping_neg()) {
+            Some(err) => write!(f, "-1 {}", err.name()),
+            None => write!(f, "{value} ({:#x})", self.0),
+        }
+    }
+}
+
+/// Allows or denies the current process the access to the ports `from..from + count`.
+///
+/// Only processes owned by the super-user may be granted ports. Like on Linux, only the first
+/// [`tss::IO_PORTS`] ports can be granted.
+fn sys_ioperm(from: usize, count: usize, turn_on: bool) -> SyscallResult {
+    let Some(glob) = GLOBAL.get() else {
+        return Err(KernelError::NotImplemented);
+    };
+
+    let mut processes = glob.processes.lock();
+    let process = processes.current_mut();
+
+    if turn_on && process.owner != ROOT {
+        return Err(KernelError::NotPermitted);
+    }
+    if !process.io_permissions.set(from, count, turn_on) {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    tss::load_io_permissions(&process.io_permissions);
+    Ok(0)
+}
+
+/// The size of the buffers through which data is copied from and to the current process.
+#[cfg(feature = "net")]
+const BOUNCE_SIZE: usize = 256;
+
+/// Maps memory in the address space of the current process, and returns its address.
+///
+/// `args` points to the arguments of the call: the requested address, the length, the
+/// protection, the flags, the file descriptor and the offset. Anonymous shared mappings behave
+/// like private ones, as processes never share them. Otherwise, the file descriptor must be the
+/// ID of a shared-memory object returned by `shm_open`, and the mapping must be shared.
+fn sys_mmap(args: usize) -> SyscallResult {
+    let [addr, len, prot, flags, fd, offset] = UserPtr::<[usize; 6]>::new(args).read()?;
+
+    let known = MAP_SHARED | MAP_PRIVATE | MAP_FIXED | MAP_ANONYMOUS;
+    let sharing = flags & (MAP_SHARED | MAP_PRIVATE);
+    if flags & !known != 0 || sharing == 0 || sharing == MAP_SHARED | MAP_PRIVATE {
+        return Err(KernelError::InvalidArgument);
+    }
+    if offset % 4096 != 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    let Some(page_flags) = paging::mmap::protection_flags(prot) else {
+        return Err(KernelError::InvalidArgument);
+    };
+
+    let fixed = flags & MAP_FIXED != 0;
+    let result = if flags & MAP_ANONYMOUS != 0 {
+        paging::mmap::map(addr, len, page_flags, fixed)
+    } else if sharing == MAP_SHARED {
+        paging::mmap::map_shared(addr, len, page_flags, fixed, fd as u32, offset)
+    } else {
+        // Private mappings of an object would require copy-on-write.
+        return Err(KernelError::NotImplemented);
+    };
+
+    Ok(result?)
+}
+
+/// The longest name of a shared-memory object or a message queue.
+const MAX_NAME_LEN: usize = if shm::MAX_NAME_LEN > mqueue::MAX_NAME_LEN {
+    shm::MAX_NAME_LEN
+} else {
+    mqueue::MAX_NAME_LEN
+};
+
+/// Copies the name of `len` bytes at `name` from the current process.
+///
+/// Names that are too long are cut one byte after the longest valid name, so that they are
+/// still rejected.
+fn user_name(name: usize, len: usize) -> Result<ArrayVec<u8, { MAX_NAME_LEN + 1 }>, KernelError> {
+    let mut buf = [0; MAX_NAME_LEN + 1];
+    let len = UserSlice::new(name, len).read(&mut buf)?;
+    Ok(ArrayVec::from_slice_truncated(&buf[..len]))
+}
+
+/// Opens the shared-memory object named `name`, and returns its ID.
+///
+/// When `size` is not zero, a new object of `size` bytes is created instead.
+fn sys_shm_open(name: usize, len: usize, size: usize) -> SyscallResult {
+    let name = user_name(name, len)?;
+
+    Ok(shm::open(&name, size)? as usize)
+}
+
+/// Removes the name of the shared-memory object named `name`.
+///
+/// The object is destroyed once it is no longer mapped.
+fn sys_shm_unlink(name: usize, len: usize) -> SyscallResult {
+    let name = user_name(name, len)?;
+
+    shm::unlink(&name)?;
+    Ok(0)
+}
+
+/// Unmaps the memory within `addr..addr + len` from the address space of the current process.
+fn sys_munmap(addr: usize, len: usize) -> SyscallResult {
+    paging::mmap::unmap(addr, len)?;
+    Ok(0)
+}
+
+/// Changes the protection of the memory within `addr..addr + len`.
+///
+/// The whole range must have been mapped with `mmap`.
+fn sys_mprotect(addr: usize, len: usize, prot: usize) -> SyscallResult {
+    let Some(flags) = paging::mmap::protection_flags(prot) else {
+        return Err(KernelError::InvalidArgument);
+    };
+
+    paging::mmap::protect(addr, len, flags)?;
+    Ok(0)
+}
+
+/// Writes the current Unix time to the `timeval` structure at `tv` (seconds and
+/// microseconds), and clears the `timezone` structure at `tz`.
+///
+/// Both pointers may be null.
+fn sys_gettimeofday(tv: usize, tz: usize) -> SyscallResult {
+    let (tv, tz) = (UserPtr::<[u32; 2]>::new(tv), UserPtr::<[u32; 2]>::new(tz));
+    if !tv.is_null() {
+        let ns = time::unix_time_ns();
+        let secs = (ns / time::NANOS_PER_SECOND) as u32;
+        let micros = (ns % time::NANOS_PER_SECOND / 1000) as u32;
+        tv.write([secs, micros])?;
+    }
+
+    // Time zones are not supported: the time is always in UTC.
+    if !tz.is_null() {
+        tz.write([0, 0])?;
+    }
+
+    Ok(0)
+}
+
+/// Opens the message queue named `name`, and returns its ID.
+///
+/// When `capacity` is not zero, a new queue that can hold `capacity` messages is created
+/// instead.
+fn sys_mq_open(name: usize, len: usize, capacity: usize) -> SyscallResult {
+    let name = user_name(name, len)?;
+
+    Ok(mqueue::open(&name, capacity)? as usize)
+}
+
+/// Removes the message queue named `name`.
+fn sys_mq_unlink(name: usize, len: usize) -> SyscallResult {
+    let name = user_name(name, len)?;
+
+    mqueue::unlink(&name)?;
+    Ok(0)
+}
+
+/// Sends a message to the queue `id`.
+///
+/// `args` points to the address of the message, its length and its priority (at most 255).
+fn sys_mq_send(id: usize, args: usize) -> SyscallResult {
+    let [buf, len, priority] = UserPtr::<[usize; 3]>::new(args).read()?;
+    let Ok(priority) = u8::try_from(priority) else {
+        return Err(KernelError::InvalidArgument);
+    };
+
+    // Messages that are too long are cut one byte after the limit, so that they are rejected.
+    let mut data = [0; mqueue::MAX_MESSAGE_SIZE + 1];
+    let len = UserSlice::new(buf, len).read(&mut data)?;
+    mqueue::send(id as u32, &data[..len], priority)?;
+    Ok(0)
+}
+
+/// Receives a message from the queue `id`, and returns its length.
+///
+/// `args` points to the address of the buffer that receives the message, its length, the
+/// timeout in milliseconds, and a word that receives the priority of the message. A timeout of
+/// zero never blocks, and a timeout of `usize::MAX` waits forever.
+fn sys_mq_receive(id: usize, args: usize) -> SyscallResult {
+    let priority = UserPtr::<usize>::new(args).add(3);
+    let args = UserPtr::<[usize; 4]>::new(args);
+    if !args.is_accessible(true) {
+        return Err(KernelError::BadAddress);
+    }
+    let [buf, len, timeout, _] = args.read()?;
+    let buf = UserSlice::new(buf, len);
+    if !buf.is_accessible(true) {
+        return Err(KernelError::BadAddress);
+    }
+
+    let timeout = (timeout != usize::MAX).then_some(timeout as u32);
+    let mut message = [0; mqueue::MAX_MESSAGE_SIZE];
+    let capacity = len.min(message.len());
+    let (len, prio) = mqueue::receive(id as u32, &mut message[..capacity], timeout)?;
+    buf.write(&message[..len])?;
+    priority.write(prio as usize)?;
+    Ok(len)
+}
+
+/// Creates a thread in the current process, and returns its ID.
+///
+/// When called from user mode, the thread runs `entry(arg)` in user mode on the stack whose top
+/// is `stack`, and must end with the `exit` system call. Otherwise, `entry` is a kernel function
+/// that runs on the kernel stack of the thread, and `stack` is ignored.
+fn sys_thread_create(entry: usize, stack: usize, arg: usize, user: bool) -> SyscallResult {
+    let Some(glob) = GLOBAL.get() else {
+        return Err(KernelError::NotImplemented);
+    };
+    let process = glob.processes.lock().current_id();
+    Ok(spawn_thread(process, entry, stack, arg, user)? as usize)
+}
+
+/// Creates a child of the current process, whose first thread is created like with
+/// `thread_create`, and returns its ID.
+///
+/// The child is owned by the same user, and is not created if the user would exceed its
+/// [process limit](Resource::Processes). The memory mapped by the parent, including the stack
+/// of the child, stays charged to the parent, and is released when the parent exits.
+fn sys_process_create(entry: usize, stack: usize, arg: usize, user: bool) -> SyscallResult {
+    let Some(glob) = GLOBAL.get() else {
+        return Err(KernelError::NotImplemented);
+    };
+
+    let mut processes = glob.processes.lock();
+    let parent = processes.current_id();
+    let owner = processes.current().owner;
+    if !state::allows(&processes, owner, Resource::Processes, 1) {
+        return Err(KernelError::WouldBlock);
+    }
+    // The child runs in the address space of its parent, whose memory it uses.
+    let address_space = processes.current().address_space;
+    let Some(child) = processes.insert(Process::new(parent, owner, address_space)) else {
+        return Err(KernelError::WouldBlock);
+    };
+    paging::spaces::acquire(address_space);
+    drop(processes);
+
+    match spawn_thread(child, entry, stack, arg, user) {
+        Ok(_) => Ok(child as usize),
+        Err(err) => {
+            glob.processes.lock().remove(child);
+            paging::spaces::release(&mut glob.allocator.lock(), address_space);
+            Err(err)
+        }
+    }
+}
+
+/// Creates a thread in `process` that runs `entry(arg)`, as described by `thread_create`.
+fn spawn_thread(
+    process: ProcessId,
+    entry: usize,
+    stack: usize,
+    arg: usize,
+    user: bool,
+) -> Result<sched::ThreadId, KernelError> {
+    if entry == 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    let ret = if user {
+        // The thread starts as if `entry` had been called with `arg`.
+        let Some(sp) = stack.checked_sub(8) else {
+            return Err(KernelError::BadAddress);
+        };
+        UserPtr::<[usize; 2]>::new(sp).write([0, arg])?;
+        sched::spawn_user(process, entry, sp)
+    } else {
+        let entry: extern "C" fn(usize) = unsafe { core::mem::transmute(entry) };
+        sched::spawn_kernel(process, "thread", entry, arg)
+    };
+
+    Ok(ret?)
+}
+
+#[cfg(feature = "net")]
+/// Creates a socket, and returns its ID.
+///
+/// Only TCP sockets exist: `domain` must be `AF_INET`, `kind` must be `SOCK_STREAM`, and
+/// `protocol` must be zero or `IPPROTO_TCP`.
+fn sys_socket(domain: usize, kind: usize, protocol: usize) -> SyscallResult {
+    if domain != AF_INET || kind != SOCK_STREAM || (protocol != 0 && protocol != IPPROTO_TCP) {
+        return Err(KernelError::Unsupported);
+    }
+    Ok(tcp::open()? as usize)
+}
+
+#[cfg(feature = "net")]
+/// Reads the `sockaddr_in` structure of `len` bytes at `addr`.
+fn read_sockaddr(addr: usize, len: usize) -> Result<SocketAddr, KernelError> {
+    if len < SOCKADDR_IN_LEN {
+        return Err(KernelError::InvalidArgument);
+    }
+    let bytes = UserPtr::<[u8; SOCKADDR_IN_LEN]>::new(addr).read()?;
+    if u16::from_ne_bytes([bytes[0], bytes[1]]) as usize != AF_INET {
+        return Err(KernelError::InvalidArgument);
+    }
+    let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+    let addr = Ipv4Addr([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    Ok(SocketAddr::new(addr, port))
+}
+
+#[cfg(feature = "net")]
+/// Writes `value` as a `sockaddr_in` structure at `addr`.
+fn write_sockaddr(addr: usize, value: SocketAddr) -> Result<(), KernelError> {
+    let mut bytes = [0u8; SOCKADDR_IN_LEN];
+    bytes[0..2].copy_from_slice(&(AF_INET as u16).to_ne_bytes());
+    bytes[2..4].copy_from_slice(&value.port.to_be_bytes());
+    bytes[4..8].copy_from_slice(&value.addr.0);
+    UserPtr::<[u8; SOCKADDR_IN_LEN]>::new(addr).write(bytes)
+}
+
+#[cfg(feature = "net")]
+/// Binds the socket `id` to the address in the `sockaddr_in` structure of `len` bytes at
+/// `addr`.
+fn sys_bind(id: usize, addr: usize, len: usize) -> SyscallResult {
+    tcp::bind(id as tcp::SocketId, read_sockaddr(addr, len)?)?;
+    Ok(0)
+}
+
+#[cfg(feature = "net")]
+/// Connects the socket `id` to the address in the `sockaddr_in` structure of `len` bytes at
+/// `addr`, and waits for the connection to be established.
+fn sys_connect(id: usize, addr: usize, len: usize) -> SyscallResult {
+    tcp::connect(id as tcp::SocketId, read_sockaddr(addr, len)?)?;
+    Ok(0)
+}
+
+#[cfg(feature = "net")]
+/// Makes the socket `id` accept connections, at most `backlog` of which wait to be accepted.
+fn sys_listen(id: usize, backlog: usize) -> SyscallResult {
+    tcp::listen(id as tcp::SocketId, backlog as u32)?;
+    Ok(0)
+}
+
+#[cfg(feature = "net")]
+/// Waits for a connection on the listening socket `id`, and returns the ID of its socket.
+///
+/// When `addr` is not null, the address of the peer is stored there as a `sockaddr_in`
+/// structure.
+fn sys_accept(id: usize, addr: usize) -> SyscallResult {
+    if addr != 0 && !UserPtr::<[u8; SOCKADDR_IN_LEN]>::new(addr).is_accessible(true) {
+        return Err(KernelError::BadAddress);
+    }
+    let (socket, peer) = tcp::accept(id as tcp::SocketId)?;
+    if addr != 0 {
+        write_sockaddr(addr, peer)?;
+    }
+    Ok(socket as usize)
+}
+
+#[cfg(feature = "net")]
+/// Sends the `len` bytes at `buf` through the connection of the socket `id`.
+///
+/// This waits until all of them are queued, and returns `len`.
+fn sys_send(id: usize, buf: usize, len: usize) -> SyscallResult {
+    let data = UserSlice::new(buf, len);
+    if !data.is_accessible(false) {
+        return Err(KernelError::BadAddress);
+    }
+
+    // The data is copied a chunk at a time, and an empty buffer is still sent once.
+    let mut chunk = [0; BOUNCE_SIZE];
+    let mut sent = 0;
+    loop {
+        let count = data.skip(sent).take(BOUNCE_SIZE).read(&mut chunk)?;
+        tcp::send(id as tcp::SocketId, &chunk[..count])?;
+        sent += count;
+        if sent == len {
+            return Ok(len);
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+/// Receives at most `len` bytes from the connection of the socket `id` into `buf`, and
+/// returns how many were received.
+///
+/// This waits until some data is received, and returns zero once the peer closed the
+/// connection.
+fn sys_recv(id: usize, buf: usize, len: usize) -> SyscallResult {
+    let buf = UserSlice::new(buf, len);
+    if !buf.is_accessible(true) {
+        return Err(KernelError::BadAddress);
+    }
+
+    // At most one chunk is received at a time.
+    let mut chunk = [0; BOUNCE_SIZE];
+    let count = tcp::recv(id as tcp::SocketId, &mut chunk[..len.min(BOUNCE_SIZE)])?;
+    buf.write(&chunk[..count])
+}
+
+#[cfg(feature = "net")]
+/// Closes the socket `id`.
+fn sys_close(id: usize) -> SyscallResult {
+    tcp::close(id as tcp::SocketId)?;
+    Ok(0)
+}
+
+/// Returns the ID of the current process.
+fn sys_getpid() -> SyscallResult {
+    match GLOBAL.get() {
+        Some(glob) => Ok(glob.processes.lock().current_id() as usize),
+        None => Err(KernelError::NotImplemented),
+    }
+}
+
+/// Waits for a child of the current process to exit, and returns its ID.
+///
+/// A `pid` of -1 waits for any child. When `status` is not null, the exit status is stored
+/// there, encoded like on Linux. With [`WNOHANG`], 0 is returned if no child exited yet.
+fn sys_waitpid(pid: isize, status: usize, options: usize) -> SyscallResult {
+    if GLOBAL.get().is_none() {
+        return Err(KernelError::NotImplemented);
+    }
+    if options & !WNOHANG != 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    let pid = match pid {
+        -1 => None,
+        1.. => Some(pid as ProcessId),
+        // Process groups do not exist.
+        _ => return Err(KernelError::InvalidArgument),
+    };
+    let status = UserPtr::<u32>::new(status);
+    if !status.is_null() && !status.is_accessible(true) {
+        return Err(KernelError::BadAddress);
+    }
+
+    let (id, code) = match reaper::wait(pid, options & WNOHANG == 0) {
+        Ok(child) => child,
+        Err(WaitError::WouldBlock) => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    if !status.is_null() {
+        status.write((code as u32) << 8)?;
+    }
+    Ok(id as usize)
+}
+
+/// Prints the arguments of an unknown system call.
+fn debug(sysno: u32, arg0: usize, arg1: usize, arg2: usize) -> usize {
     printk!("Received a system call interrupt!\n");
     printk!("> sysno = {sysno:#x}\n");
     printk!("> arg0  = {:#x}\n", arg0);
@@ -1,5 +1,9 @@
 //! Any CPU-specific configuration is done in this module.
 
+pub mod fpu;
 pub mod gdt;
 pub mod idt;
 pub mod paging;
+pub mod smp;
+pub mod stack;
+pub mod tss;
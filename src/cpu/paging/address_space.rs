@@ -1,13 +1,16 @@
-use crate::cpu::paging::PageTableIndex;
+use core::marker::PhantomData;
+
 use crate::state::OutOfMemory;
+use crate::utility::zero_pages;
 
-use super::{PageTable, PageTableFlags};
+use super::{PageEntry, PageTableFlags};
 
 /// The size of a single 4 KiB page.
-const FOUR_KIB: usize = 4096;
+pub(super) const FOUR_KIB: usize = 4096;
 
-/// The size of a single 4 MiB page.
-const FOUR_MIB: usize = 4096 * 1024;
+/// The flags that restrict the access to a page.
+pub(super) const ACCESS_RIGHTS: PageTableFlags =
+    PageTableFlags::WRITABLE.union(PageTableFlags::USER_ACCESSIBLE);
 
 /// An error that might occur while mapping memory.
 #[derive(Debug)]
@@ -16,6 +19,31 @@ pub enum MappingError {
     OutOfMemory,
     /// The requested mapping conflicts with an existing one.
     AlreadyMapped,
+    /// The requested range is empty, or not properly aligned.
+    InvalidRange,
+    /// The address space has too many areas (see [`Areas`](super::vma::Areas)).
+    TooManyAreas,
+}
+
+/// Whether [`AddressSpace::map_range`] maps huge pages.
+///
+/// Huge pages use less page tables and TLB entries, but their permissions can only be changed
+/// as a whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HugePages {
+    /// Huge pages are used whenever the addresses are properly aligned.
+    Prefer,
+    /// Only 4 KiB pages are used.
+    Forbid,
+}
+
+/// The number of pages mapped by an address space, by size.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageCounts {
+    /// The number of 4 KiB pages.
+    pub small: u32,
+    /// The number of huge pages (see [`PageEntry::HUGE_PAGE_SIZE`]).
+    pub huge: u32,
 }
 
 impl From<OutOfMemory> for MappingError {
@@ -26,29 +54,50 @@ impl From<OutOfMemory> for MappingError {
 }
 
 /// Represent an address space.
-pub struct AddressSpace<C> {
+///
+/// `E` is the format of the entries of its page tables. It selects between legacy paging
+/// ([`PageTableFlags`]) and PAE paging ([`PaeEntry`](super::PaeEntry)).
+pub struct AddressSpace<C, E = PageTableFlags> {
     /// The context used to manipulate the page table.
     context: C,
     /// The root page table.
     ///
     /// This is a physical address.
     root: u32,
+    /// The format of the entries.
+    _entry: PhantomData<E>,
 }
 
-impl<C: Context> AddressSpace<C> {
+impl<C: Context, E: PageEntry> AddressSpace<C, E> {
     /// Creates a new [`AddressSpace`] instance.
     pub fn new(mut context: C) -> Result<Self, OutOfMemory> {
-        let root = context.allocate()?;
+        let root = context.allocate_zeroed()?;
 
-        unsafe {
-            let root_ptr = context.map(root) as *mut PageTable;
-            root_ptr.write_bytes(0x00, 1);
-        }
+        Ok(Self {
+            context,
+            root,
+            _entry: PhantomData,
+        })
+    }
 
-        Ok(Self { context, root })
+    /// Creates an [`AddressSpace`] from an existing root page table.
+    ///
+    /// # Safety
+    ///
+    /// `root` must be the physical address of a valid root page table whose entries have the
+    /// format `E`, and whose tables can be accessed through `context`.
+    pub unsafe fn from_raw(context: C, root: u32) -> Self {
+        Self {
+            context,
+            root,
+            _entry: PhantomData,
+        }
     }
 
-    /// Returns the physical address of the page directory.
+    /// Returns the physical address of the root page table.
+    ///
+    /// This is the page directory with legacy paging, and the page directory pointer table
+    /// with PAE.
     #[inline(always)]
     pub fn page_directory(&self) -> u32 {
         self.root
@@ -60,29 +109,331 @@ impl<C: Context> AddressSpace<C> {
         core::mem::forget(self);
     }
 
-    /// Translates the provided virtual address to a physical address, if it is mapped.
-    pub fn translate(&self, virt: usize) -> Option<u32> {
-        let dir = unsafe { &*(self.context.map(self.root) as *const PageTable) };
-        let pde = &dir[PageTableIndex::extract_page_directory_index(virt)];
+    /// Makes the address space the one in use, by loading its root table in CR3.
+    ///
+    /// # Safety
+    ///
+    /// The address space must map the kernel like the one in use does, which is the case of
+    /// the ones created with [`spaces::create`](super::spaces::create).
+    #[inline]
+    pub unsafe fn activate(&self) {
+        super::spaces::activate(self.root);
+    }
+
+    /// Deallocates the page tables of the address space, along with its root table.
+    ///
+    /// The page tables for which `shared` returns `true`, given an address they translate, are
+    /// kept, as other address spaces use them. The pages that are mapped are never
+    /// deallocated.
+    pub fn deallocate(mut self, shared: impl Fn(usize) -> bool) {
+        // The entries of the window reference the page directories themselves.
+        for virt in (0..E::RECURSIVE_WINDOW).step_by(E::HUGE_PAGE_SIZE) {
+            let Some(entry) = self.directory_entry(virt) else {
+                continue;
+            };
+            let entry = unsafe { *entry };
+            if entry.flags().is_present() && !entry.flags().is_huge_page() && !shared(virt) {
+                unsafe { self.context.deallocate(entry.address()) };
+            }
+        }
+
+        if E::LEVELS > 2 {
+            for index in 0..Self::entry_count(0) as usize {
+                let entry = unsafe { *self.entry(self.root, index) };
+                if entry.flags().is_present() {
+                    unsafe { self.context.deallocate(entry.address()) };
+                }
+            }
+        }
+        unsafe { self.context.deallocate(self.root) };
+    }
+
+    /// Returns a pointer to the entry at `index` in the table at `table`.
+    ///
+    /// # Safety
+    ///
+    /// `table` must be the physical address of a page table of the address space.
+    #[inline]
+    unsafe fn entry(&self, table: u32, index: usize) -> *mut E {
+        (self.context.map(table) as *mut E).add(index)
+    }
+
+    /// Walks the page tables until the entry that translates `virt` at the provided level.
+    ///
+    /// Returns the entry, along with the access rights granted by the entries that were
+    /// traversed. `None` is returned if an entry of a previous level is missing.
+    ///
+    /// `level` must not be greater than `E::LEVELS - 2`, as huge pages are not expected.
+    fn walk(&self, virt: usize, level: usize) -> Option<(*mut E, PageTableFlags)> {
+        let mut table = self.root;
+        let mut access = ACCESS_RIGHTS;
+
+        for l in 0..level {
+            let entry = unsafe { *self.entry(table, E::index(virt, l)) };
+            if !entry.flags().is_present() {
+                return None;
+            }
+            access &= entry.access(l);
+            table = entry.address();
+        }
+
+        Some((unsafe { self.entry(table, E::index(virt, level)) }, access))
+    }
+
+    /// Returns a pointer to the entry of the page directory that translates `virt`, which maps
+    /// a huge page or references a page table.
+    ///
+    /// `None` is returned if the page directory is missing.
+    pub fn directory_entry(&self, virt: usize) -> Option<*mut E> {
+        self.walk(virt, E::LEVELS - 2).map(|(entry, _)| entry)
+    }
+
+    /// Returns the effective flags of the page that contains `virt`, if it is mapped.
+    ///
+    /// The access rights of the entries that reference the page are taken into account.
+    pub fn flags(&self, virt: usize) -> Option<PageTableFlags> {
+        // The entry that maps a huge page, or references the last level of tables.
+        let (entry, access) = self.walk(virt, E::LEVELS - 2)?;
+        let entry = unsafe { *entry };
 
-        let pt = if !pde.is_present() {
+        let leaf = if !entry.flags().is_present() {
             return None;
-        } else if pde.is_huge_page() {
-            return Some(PageTableIndex::extract_4mib_offset(virt) + pde.address_4mib());
+        } else if entry.flags().is_huge_page() {
+            entry
         } else {
-            // The page is present. We need to continue reading the page table.
-            unsafe { &*(self.context.map(pde.address_4kib()) as *const PageTable) }
+            unsafe { *self.entry(entry.address(), E::index(virt, E::LEVELS - 1)) }
         };
 
-        let pte = &pt[PageTableIndex::extract_page_table_index(virt)];
+        let flags = leaf.flags();
+        if !flags.is_present() {
+            return None;
+        }
 
-        if pte.is_present() {
-            Some(PageTableIndex::extract_4kib_offset(virt) + pte.address_4kib())
+        let access = access & entry.access(E::LEVELS - 2) & leaf.access(E::LEVELS - 1);
+        Some((flags - ACCESS_RIGHTS) | access)
+    }
+
+    /// Translates the provided virtual address to a physical address, if it is mapped.
+    pub fn translate(&self, virt: usize) -> Option<u32> {
+        let (entry, _) = self.walk(virt, E::LEVELS - 2)?;
+        let entry = unsafe { *entry };
+
+        if !entry.flags().is_present() {
+            return None;
+        } else if entry.flags().is_huge_page() {
+            return Some(entry.address() + (virt % E::HUGE_PAGE_SIZE) as u32);
+        }
+
+        let leaf = unsafe { *self.entry(entry.address(), E::index(virt, E::LEVELS - 1)) };
+
+        if leaf.flags().is_present() {
+            Some(leaf.address() + (virt % FOUR_KIB) as u32)
         } else {
             None
         }
     }
 
+    /// Returns the entry that maps the 4 KiB page at `virt`, if it is present.
+    ///
+    /// `None` is returned when the page is part of a huge page.
+    fn leaf_4kib(&self, virt: usize) -> Option<*mut E> {
+        let (entry, _) = self.walk(virt, E::LEVELS - 2)?;
+        let entry = unsafe { *entry };
+        if !entry.flags().is_present() || entry.flags().is_huge_page() {
+            return None;
+        }
+
+        let leaf = unsafe { self.entry(entry.address(), E::index(virt, E::LEVELS - 1)) };
+        unsafe { (*leaf).flags().is_present() }.then_some(leaf)
+    }
+
+    /// Unmaps the 4 KiB page at `virt`, and returns the physical page it was mapped to.
+    ///
+    /// The page tables are left in place, even when they become empty. The TLB is not
+    /// flushed.
+    pub fn unmap_4kib(&mut self, virt: usize) -> Option<u32> {
+        let leaf = self.leaf_4kib(virt)?;
+        unsafe {
+            let phys = (*leaf).address();
+            *leaf = E::EMPTY;
+            Some(phys)
+        }
+    }
+
+    /// Replaces the flags of the 4 KiB page at `virt`, which must be mapped.
+    ///
+    /// The flags of the parent entries are updated conservatively. The TLB is not flushed.
+    pub fn protect_4kib(&mut self, virt: usize, flags: PageTableFlags) -> Result<(), MappingError> {
+        let phys = unsafe { (*self.leaf_4kib(virt).ok_or(MappingError::InvalidRange)?).address() };
+        let table = self.table_for(virt, E::LEVELS - 1, flags)?;
+        unsafe { *self.entry(table, E::index(virt, E::LEVELS - 1)) = E::page(phys, flags, false) };
+        Ok(())
+    }
+
+    /// Calls `f` for every mapped page, in increasing order of virtual address.
+    ///
+    /// `f` receives the virtual address of the page, its size and its effective flags (see
+    /// [`flags`](Self::flags)).
+    pub fn for_each_page(&self, mut f: impl FnMut(usize, usize, PageTableFlags)) {
+        self.visit(self.root, 0, 0, ACCESS_RIGHTS, &mut f);
+    }
+
+    /// Calls `f` for every present entry, including those that reference page tables.
+    ///
+    /// `f` receives the level of the table that contains the entry, the first virtual address
+    /// it translates and the entry itself. An entry is visited before the table it references.
+    pub fn for_each_entry(&self, mut f: impl FnMut(usize, usize, E)) {
+        self.visit_entries(self.root, 0, 0, &mut f);
+    }
+
+    /// Visits the entries of the table at `table`, of the provided level, that translates the
+    /// addresses starting at `base`. See [`for_each_entry`](Self::for_each_entry).
+    fn visit_entries(
+        &self,
+        table: u32,
+        level: usize,
+        base: u64,
+        f: &mut dyn FnMut(usize, usize, E),
+    ) {
+        let size = Self::entry_size(level);
+        for index in 0..Self::entry_count(level) {
+            let entry = unsafe { *self.entry(table, index as usize) };
+            let flags = entry.flags();
+            if !flags.is_present() {
+                continue;
+            }
+
+            let virt = base + index * size;
+            f(level, virt as usize, entry);
+            let is_leaf =
+                level == E::LEVELS - 1 || (level == E::LEVELS - 2 && flags.is_huge_page());
+            if !is_leaf {
+                self.visit_entries(entry.address(), level + 1, virt, f);
+            }
+        }
+    }
+
+    /// Counts the pages mapped by the address space.
+    pub fn page_counts(&self) -> PageCounts {
+        let mut counts = PageCounts::default();
+        self.for_each_page(|_, size, _| {
+            if size == FOUR_KIB {
+                counts.small += 1;
+            } else {
+                counts.huge += 1;
+            }
+        });
+        counts
+    }
+
+    /// Returns the size of the memory translated by an entry of the provided level.
+    fn entry_size(level: usize) -> u64 {
+        let per_table = (E::HUGE_PAGE_SIZE / FOUR_KIB) as u64;
+        FOUR_KIB as u64 * per_table.pow((E::LEVELS - 1 - level) as u32)
+    }
+
+    /// Returns the number of entries of a table of the provided level.
+    fn entry_count(level: usize) -> u64 {
+        if level == 0 {
+            (1 << 32) / Self::entry_size(0)
+        } else {
+            (E::HUGE_PAGE_SIZE / FOUR_KIB) as u64
+        }
+    }
+
+    /// Visits the entries of the table at `table`, of the provided level, that translates the
+    /// addresses starting at `base`. See [`for_each_page`](Self::for_each_page).
+    fn visit(
+        &self,
+        table: u32,
+        level: usize,
+        base: u64,
+        access: PageTableFlags,
+        f: &mut dyn FnMut(usize, usize, PageTableFlags),
+    ) {
+        let size = Self::entry_size(level);
+        for index in 0..Self::entry_count(level) {
+            let entry = unsafe { *self.entry(table, index as usize) };
+            let flags = entry.flags();
+            if !flags.is_present() {
+                continue;
+            }
+
+            let virt = base + index * size;
+            let access = access & entry.access(level);
+            let is_leaf =
+                level == E::LEVELS - 1 || (level == E::LEVELS - 2 && flags.is_huge_page());
+            if is_leaf {
+                f(
+                    virt as usize,
+                    size as usize,
+                    (flags - ACCESS_RIGHTS) | access,
+                );
+            } else {
+                self.visit(entry.address(), level + 1, virt, access, f);
+            }
+        }
+    }
+
+    /// Returns the table of the provided level that translates `virt`, allocating the missing
+    /// tables along the way.
+    ///
+    /// The flags of `flags` are properly dispatched to the entries that are traversed.
+    fn table_for(
+        &mut self,
+        virt: usize,
+        level: usize,
+        flags: PageTableFlags,
+    ) -> Result<u32, MappingError> {
+        let mut table = self.root;
+
+        for l in 0..level {
+            let entry = unsafe { &mut *self.entry(table, E::index(virt, l)) };
+
+            table = if !entry.flags().is_present() {
+                // The entry is not present. We need to allocate a page table for it.
+                let pta = self.context.allocate_zeroed()?;
+                *entry = E::table(pta, l, flags);
+                pta
+            } else if l == E::LEVELS - 2 && entry.flags().is_huge_page() {
+                // The entry maps a huge page. The requested page is already mapped.
+                return Err(MappingError::AlreadyMapped);
+            } else {
+                // The entry is present. We need to update its flags conservatively.
+                entry.merge_flags(l, flags);
+                entry.address()
+            };
+        }
+
+        Ok(table)
+    }
+
+    /// Maps the page tables of the address space in its own [`E::RECURSIVE_WINDOW`].
+    ///
+    /// The last entries of the last page directory reference the page directories, so that the
+    /// page directories and the page tables appear in the window once the address space is in
+    /// use. With PAE, the four page directories are allocated upfront, so that the page
+    /// directory pointer table never has to change afterwards.
+    ///
+    /// The window must not be mapped yet. See [`CurrentAddressSpace`](super::CurrentAddressSpace).
+    ///
+    /// [`E::RECURSIVE_WINDOW`]: PageEntry::RECURSIVE_WINDOW
+    pub fn map_recursively(&mut self) -> Result<(), MappingError> {
+        let level = E::LEVELS - 2;
+        let directories = if level == 0 { 1 } else { 4 };
+        let last = self.table_for(E::RECURSIVE_WINDOW, level, PageTableFlags::WRITABLE)?;
+        for k in 0..directories {
+            let directory = self.table_for(k << 30, level, PageTableFlags::WRITABLE)?;
+            let virt = E::RECURSIVE_WINDOW + k * E::HUGE_PAGE_SIZE;
+            let entry = unsafe { &mut *self.entry(last, E::index(virt, level)) };
+            if entry.flags().is_present() {
+                return Err(MappingError::AlreadyMapped);
+            }
+            *entry = E::table(directory, level, PageTableFlags::WRITABLE);
+        }
+        Ok(())
+    }
+
     /// Maps a 4 KiB virtual page to a specific physical page.
     ///
     /// The flags of `entry` are properly dispatched to its parent entries.
@@ -110,105 +461,67 @@ impl<C: Context> AddressSpace<C> {
             "invalid flags provided"
         );
 
-        // Read the page directory.
-        let dir = unsafe { &mut *(self.context.map(self.root) as *mut PageTable) };
-
-        // Read the page directory entry.
-        let pde_index = PageTableIndex::extract_page_directory_index(virt);
-        let pde = &mut dir[pde_index];
+        let table = self.table_for(virt, E::LEVELS - 1, flags)?;
+        let pte = unsafe { &mut *self.entry(table, E::index(virt, E::LEVELS - 1)) };
 
-        let pta = if !pde.is_present() {
-            // The page directory entry is not present. We need to allocate
-            // a page table for it.
-            let pta = self.context.allocate()?;
-            let pta_ptr;
-
-            // Initialize the page table.
-            unsafe {
-                pta_ptr = self.context.map(pta) as *mut PageTable;
-                pta_ptr.write_bytes(0x00, 1);
-            }
-
-            // Update the page directory entry.
-            *pde = flags | PageTableFlags::PRESENT | PageTableFlags::from_bits_retain(pta);
-
-            unsafe { &mut *pta_ptr }
-        } else if pde.is_huge_page() {
-            // The entry corresponds to a 4 MiB page. We cannot map a 4 KiB page here
-            // as it is already mapped.
-            return Err(MappingError::AlreadyMapped);
-        } else {
-            // The page directory entry is present. We need to update its
-            // flags conservatively.
-            update_flags(pde, flags);
-
-            // And read it to get the page table address.
-            unsafe { &mut *(self.context.map(pde.address_4kib()) as *mut PageTable) }
-        };
-
-        let pte_index = PageTableIndex::extract_page_table_index(virt);
-        let pte = &mut pta[pte_index];
-
-        if pte.is_present() {
+        if pte.flags().is_present() {
             // The page table entry is already present. We cannot map the page.
             return Err(MappingError::AlreadyMapped);
-        } else {
-            // Otherwise, we can map the page.
-            *pte = flags | PageTableFlags::PRESENT | PageTableFlags::from_bits_retain(phys);
         }
 
+        *pte = E::page(phys, flags, false);
+
         Ok(())
     }
 
-    /// Maps a 4 MiB virtual page to a specific physical page.
+    /// Maps a huge virtual page to a specific physical page.
+    ///
+    /// Huge pages are 4 MiB large with legacy paging, and 2 MiB large with PAE (see
+    /// [`PageEntry::HUGE_PAGE_SIZE`]).
     ///
     /// The flags of `entry` are properly dispatched to its parent entries.
     ///
     /// # Panics
     ///
     /// This function panics in debug builds if the provided virtual address
-    /// is not properly aligned to a 4 MiB boundary.
-    pub fn map_4mib(
+    /// is not properly aligned to the size of a huge page.
+    pub fn map_huge_page(
         &mut self,
         virt: usize,
         phys: u32,
         flags: PageTableFlags,
     ) -> Result<(), MappingError> {
         debug_assert!(
-            virt % FOUR_MIB == 0,
-            "virtual address is not properly aligned to 4 MiB"
+            virt % E::HUGE_PAGE_SIZE == 0,
+            "virtual address is not properly aligned to a huge page"
         );
         debug_assert!(
-            phys as usize % FOUR_MIB == 0,
-            "entry does not represent a 4 MiB page"
+            phys as usize % E::HUGE_PAGE_SIZE == 0,
+            "physical address is not properly aligned to a huge page"
         );
         debug_assert!(
             !flags.intersects(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE),
             "invalid flags provided"
         );
 
-        // Read the page directory.
-        let dir = unsafe { &mut *(self.context.map(self.root) as *mut PageTable) };
-
-        // Read the page directory entry.
-        let pde_index = PageTableIndex::extract_page_directory_index(virt);
-        let pde = &mut dir[pde_index];
+        let table = self.table_for(virt, E::LEVELS - 2, flags)?;
+        let pde = unsafe { &mut *self.entry(table, E::index(virt, E::LEVELS - 2)) };
 
-        if pde.is_present() {
+        if pde.flags().is_present() {
             // The page directory entry is already mapped somewhere.
             return Err(MappingError::AlreadyMapped);
         }
 
-        *pde = flags
-            | PageTableFlags::PRESENT
-            | PageTableFlags::HUGE_PAGE
-            | PageTableFlags::from_bits_retain(phys);
+        *pde = E::page(phys, flags, true);
 
         Ok(())
     }
 
     /// Maps a range of virtual pages to a range of physical pages.
     ///
+    /// `huge_pages` selects whether the parts of the range that are properly aligned are
+    /// mapped with huge pages.
+    ///
     /// # Panics
     ///
     /// In debug builds, this function panics if any of the provided addresses or
@@ -217,43 +530,149 @@ impl<C: Context> AddressSpace<C> {
     /// # Errors
     ///
     /// This function fails if any part of the mapping is already present in the
-    /// virtual address space. Note that in that case, the function does not
-    /// attempt to unmap the pages that were successfully mapped before the error
-    /// occurred.
+    /// virtual address space, or if a page table cannot be allocated. In that case,
+    /// the pages that were mapped by the call are unmapped, and the page tables it
+    /// allocated are deallocated, so that the address space is left as it was.
     pub fn map_range(
         &mut self,
-        mut virt: usize,
-        mut phys: u32,
-        mut length: usize,
+        start: usize,
+        phys: u32,
+        length: usize,
         flags: PageTableFlags,
+        huge_pages: HugePages,
     ) -> Result<(), MappingError> {
-        debug_assert!(virt % FOUR_KIB == 0);
+        debug_assert!(start % FOUR_KIB == 0);
         debug_assert!(phys as usize % FOUR_KIB == 0);
         debug_assert!(length % FOUR_KIB == 0);
 
+        let huge = E::HUGE_PAGE_SIZE;
+        let mut existed = Existed::new();
+        let (mut virt, mut phys, mut length) = (start, phys, length);
+
         while length != 0 {
-            if length >= FOUR_MIB && virt % FOUR_MIB == 0 && phys as usize % FOUR_MIB == 0 {
-                // We can map a 4 MiB page.
-                self.map_4mib(virt, phys, flags)?;
-                virt += FOUR_MIB;
-                phys += FOUR_MIB as u32;
-                length -= FOUR_MIB;
+            if virt == start || virt % huge == 0 {
+                existed.record(self, virt);
+            }
+
+            let result = if huge_pages == HugePages::Prefer
+                && length >= huge
+                && virt % huge == 0
+                && phys as usize % huge == 0
+            {
+                // We can map a huge page.
+                self.map_huge_page(virt, phys, flags).map(|()| huge)
             } else {
                 // We can only map a 4 KiB page.
-                self.map_4kib(virt, phys, flags)?;
-                virt += FOUR_KIB;
-                phys += FOUR_KIB as u32;
-                length -= FOUR_KIB;
+                self.map_4kib(virt, phys, flags).map(|()| FOUR_KIB)
+            };
+
+            match result {
+                Ok(size) => {
+                    virt += size;
+                    phys += size as u32;
+                    length -= size;
+                }
+                Err(err) => {
+                    self.unmap_range(start, virt, &existed);
+                    return Err(err);
+                }
             }
         }
 
         Ok(())
     }
+
+    /// Undoes a call to [`map_range`](Self::map_range) that mapped `start..end` before failing
+    /// to map the page at `end`.
+    ///
+    /// `existed` tells which entries were present before the call. Those that were not
+    /// reference tables allocated by the call, and are removed.
+    fn unmap_range(&mut self, start: usize, end: usize, existed: &Existed) {
+        let huge = E::HUGE_PAGE_SIZE;
+
+        // The page at `end` is included, as mapping it may have allocated tables.
+        for chunk in (start / huge)..=(end / huge) {
+            let base = chunk * huge;
+            let Some(entry) = self.directory_entry(base) else {
+                continue;
+            };
+            let directory = unsafe { *entry };
+            if !directory.flags().is_present() {
+                continue;
+            }
+
+            if existed.directory(chunk) {
+                // The table existed: only the pages mapped by the call are removed.
+                let table = directory.address();
+                for virt in (base.max(start)..(base + huge).min(end)).step_by(FOUR_KIB) {
+                    unsafe { *self.entry(table, E::index(virt, E::LEVELS - 1)) = E::EMPTY };
+                }
+            } else {
+                if !directory.flags().is_huge_page() {
+                    unsafe { self.context.deallocate(directory.address()) };
+                }
+                unsafe { *entry = E::EMPTY };
+            }
+        }
+
+        if E::LEVELS > 2 {
+            for index in (start >> 30)..=(end >> 30) {
+                let entry = unsafe { &mut *self.entry(self.root, index) };
+                if !existed.root(index) && entry.flags().is_present() {
+                    unsafe { self.context.deallocate(entry.address()) };
+                    *entry = E::EMPTY;
+                }
+            }
+        }
+    }
+}
+
+/// The entries that were present before a call to [`AddressSpace::map_range`].
+struct Existed {
+    /// The entries of the page directories, indexed by the huge page they translate.
+    ///
+    /// Huge pages are at least 2 MiB large, so there are at most 2048 of them.
+    directories: [u32; 2048 / 32],
+    /// The entries of the page directory pointer table, with PAE.
+    root: u8,
 }
 
-fn update_flags(parent: &mut PageTableFlags, child: PageTableFlags) {
-    // TODO: properly fuse the flags.
-    *parent |= child;
+impl Existed {
+    /// Creates an [`Existed`] instance in which no entry was present.
+    fn new() -> Self {
+        Self {
+            directories: [0; 2048 / 32],
+            root: 0,
+        }
+    }
+
+    /// Records whether the entries that translate `virt` are present in `space`.
+    fn record<C: Context, E: PageEntry>(&mut self, space: &AddressSpace<C, E>, virt: usize) {
+        if E::LEVELS > 2
+            && unsafe { *space.entry(space.root, virt >> 30) }
+                .flags()
+                .is_present()
+        {
+            self.root |= 1 << (virt >> 30);
+        }
+        let chunk = virt / E::HUGE_PAGE_SIZE;
+        let present = space
+            .directory_entry(virt)
+            .is_some_and(|entry| unsafe { *entry }.flags().is_present());
+        if present {
+            self.directories[chunk / 32] |= 1 << (chunk % 32);
+        }
+    }
+
+    /// Returns whether the entry of the page directories at `chunk` was present.
+    fn directory(&self, chunk: usize) -> bool {
+        self.directories[chunk / 32] & (1 << (chunk % 32)) != 0
+    }
+
+    /// Returns whether the entry of the page directory pointer table at `index` was present.
+    fn root(&self, index: usize) -> bool {
+        self.root & (1 << index) != 0
+    }
 }
 
 /// Contains the functions required to manipulate a page table.
@@ -274,6 +693,15 @@ pub unsafe trait Context {
     /// This function returns the physical address of the allocated page.
     fn allocate(&mut self) -> Result<u32, OutOfMemory>;
 
+    /// Allocates a new physical page filled with zeros.
+    ///
+    /// By default, the page is zeroed through [`map`](Self::map).
+    fn allocate_zeroed(&mut self) -> Result<u32, OutOfMemory> {
+        let page = self.allocate()?;
+        unsafe { zero_pages(self.map(page), FOUR_KIB) };
+        Ok(page)
+    }
+
     /// Deallocates the provided page.
     ///
     /// # Safety
@@ -0,0 +1,335 @@
+//! Checks the page tables of an address space against the invariants that the kernel relies
+//! on.
+//!
+//! This is a diagnostic pass: it never changes the tables, and reports what it finds so that
+//! mistakes in the code that edits them show up before they cause a fault.
+
+use core::fmt;
+
+use crate::multiboot::MemMapType;
+use crate::state::{Allocator, MemoryRegion};
+
+use super::spaces::{self, FrameContext};
+use super::vma::{Areas, Backing};
+use super::{
+    is_pae_enabled, pae, pse36, AddressSpace, MappingError, PaeEntry, PageEntry, PageTableFlags,
+    KERNEL_AREAS,
+};
+
+/// A broken invariant, found by [`check`].
+#[derive(Debug, Clone, Copy)]
+pub enum Violation {
+    /// Bits that the CPU reserves are set in an entry of a table of the provided level.
+    ReservedBits {
+        /// The level of the table that contains the entry.
+        level: usize,
+    },
+    /// A page table is not located in memory that the firmware reported as available.
+    UnknownTable {
+        /// The physical address of the table.
+        phys: u64,
+    },
+    /// A page is mapped to memory that the firmware did not report as available, while it
+    /// does not belong to a device.
+    UnknownFrame {
+        /// The physical address of the page.
+        phys: u64,
+    },
+    /// A page outside of the user range is accessible from user mode.
+    UserKernelPage,
+    /// A page is mapped outside of any area.
+    NoArea,
+    /// A page is mapped in a guard area.
+    MappedGuard,
+    /// A page grants more rights than the area that contains it.
+    AreaPermissions {
+        /// The effective flags of the page.
+        page: PageTableFlags,
+        /// The flags of the area.
+        area: PageTableFlags,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReservedBits { level } => write!(f, "reserved bits set at level {level}"),
+            Self::UnknownTable { phys } => write!(f, "page table in unknown memory ({phys:#x})"),
+            Self::UnknownFrame { phys } => write!(f, "page mapped to unknown memory ({phys:#x})"),
+            Self::UserKernelPage => f.write_str("kernel page accessible from user mode"),
+            Self::NoArea => f.write_str("page mapped outside of any area"),
+            Self::MappedGuard => f.write_str("page mapped in a guard area"),
+            Self::AreaPermissions { page, area } => write!(
+                f,
+                "page grants more rights than its area ({:#x} > {:#x})",
+                page.bits(),
+                area.bits(),
+            ),
+        }
+    }
+}
+
+/// Walks the address space whose root table is at `root`, and calls `report` with the virtual
+/// address of every violation that is found.
+///
+/// `memory_map` is the memory map reported by the firmware. Pages are compared against
+/// [`KERNEL_AREAS`], which stays locked during the walk.
+pub fn check(root: u32, memory_map: &[MemoryRegion], report: impl FnMut(usize, Violation)) {
+    let context = FrameContext { allocator: None };
+    let areas = KERNEL_AREAS.lock();
+    if is_pae_enabled() {
+        let space = unsafe { AddressSpace::<_, PaeEntry>::from_raw(context, root) };
+        check_as(&space, &areas, memory_map, report);
+    } else {
+        let space = unsafe { AddressSpace::<_, PageTableFlags>::from_raw(context, root) };
+        check_as(&space, &areas, memory_map, report);
+    }
+}
+
+/// See [`check`].
+fn check_as<E: PageEntry>(
+    space: &AddressSpace<FrameContext, E>,
+    areas: &Areas,
+    memory_map: &[MemoryRegion],
+    mut report: impl FnMut(usize, Violation),
+) {
+    let is_available = |phys: u64| {
+        memory_map.iter().any(|region| {
+            region.ty == MemMapType::AVAILABLE
+                && region
+                    .end()
+                    .is_some_and(|end| (region.start..end).contains(&phys))
+        })
+    };
+    // The window of PSE-36 maps memory above 4 GiB on demand, outside of any area.
+    let window = pse36::window();
+
+    space.for_each_entry(|level, virt, entry| {
+        if entry.has_reserved_bits(level) {
+            report(virt, Violation::ReservedBits { level });
+        }
+
+        let flags = entry.flags();
+        let is_leaf = level == E::LEVELS - 1 || (level == E::LEVELS - 2 && flags.is_huge_page());
+        let phys = entry.address() as u64;
+        if !is_leaf {
+            if !is_available(phys) {
+                report(virt, Violation::UnknownTable { phys });
+            }
+        } else if !window.contains(&virt) {
+            let is_device = areas
+                .find(virt)
+                .is_some_and(|area| matches!(area.backing, Backing::Device { .. }));
+            if !is_device && !is_available(phys) {
+                report(virt, Violation::UnknownFrame { phys });
+            }
+        }
+    });
+
+    let user_range = spaces::user_range();
+    let rights = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    space.for_each_page(|start, size, flags| {
+        let end = start + (size - 1);
+        if window.contains(&start) {
+            return;
+        }
+        let is_user = user_range.contains(&start) && user_range.contains(&end);
+        if flags.contains(PageTableFlags::USER_ACCESSIBLE) && !is_user {
+            report(start, Violation::UserKernelPage);
+        }
+        // The pages of the window are the page tables themselves, whose entries only carry the
+        // rights of their children. Its last page does not fit in an area.
+        if start >= E::RECURSIVE_WINDOW {
+            return;
+        }
+
+        // Huge pages may span several areas, which are checked one after the other.
+        let mut virt = start;
+        while virt <= end {
+            let Some(area) = areas.find(virt) else {
+                report(virt, Violation::NoArea);
+                // The areas are sorted, so the next one that the page overlaps starts later.
+                match areas.iter().map(|area| area.start).find(|&s| s > virt) {
+                    Some(next) if next <= end => virt = next,
+                    _ => break,
+                }
+                continue;
+            };
+            let next = area.end;
+            match area.backing {
+                Backing::Guard => report(virt, Violation::MappedGuard),
+                _ => {
+                    let mut extra = (flags & rights) - area.flags;
+                    // Without the NX bit, every page is executable.
+                    if pae::is_nx_enabled()
+                        && !flags.contains(PageTableFlags::NO_EXECUTE)
+                        && area.flags.contains(PageTableFlags::NO_EXECUTE)
+                    {
+                        extra |= PageTableFlags::NO_EXECUTE;
+                    }
+                    if !extra.is_empty() {
+                        report(
+                            virt,
+                            Violation::AreaPermissions {
+                                page: flags & (rights | PageTableFlags::NO_EXECUTE),
+                                area: area.flags,
+                            },
+                        );
+                    }
+                }
+            }
+            if next == 0 || next > end {
+                break;
+            }
+            virt = next;
+        }
+    });
+}
+
+/// A failure of [`self_test`].
+#[derive(Debug)]
+pub struct SelfTestFailure {
+    /// The format of the entries that was tested: `legacy` or `PAE`.
+    pub format: &'static str,
+    /// What was being checked.
+    pub step: &'static str,
+    /// What went wrong.
+    pub error: SelfTestError,
+}
+
+/// What went wrong during [`self_test`].
+#[derive(Debug)]
+pub enum SelfTestError {
+    /// A page could not be mapped.
+    Mapping(MappingError),
+    /// A page is not mapped with the expected access rights.
+    Rights {
+        /// The access rights the page has.
+        found: PageTableFlags,
+        /// The access rights the page should have.
+        expected: PageTableFlags,
+    },
+}
+
+impl fmt::Display for SelfTestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} paging, {}: ", self.format, self.step)?;
+        match &self.error {
+            SelfTestError::Mapping(err) => write!(f, "{err:?}"),
+            SelfTestError::Rights { found, expected } => write!(
+                f,
+                "rights {:#x} instead of {:#x}",
+                found.bits(),
+                expected.bits(),
+            ),
+        }
+    }
+}
+
+/// Checks that the pages that share a page table keep their own access rights.
+///
+/// A read-only kernel page and a writable user page are mapped next to each other in a scratch
+/// address space, which is never loaded, in both orders and with both formats of entries. The
+/// parent entries must grant the union of their rights, while the kernel page stays read-only
+/// and out of reach of user mode.
+pub fn self_test(allocator: &mut Allocator) -> Result<(), SelfTestFailure> {
+    for kernel_first in [true, false] {
+        self_test_as::<PageTableFlags>(allocator, "legacy", kernel_first)?;
+        self_test_as::<PaeEntry>(allocator, "PAE", kernel_first)?;
+    }
+    Ok(())
+}
+
+/// See [`self_test`].
+fn self_test_as<E: PageEntry>(
+    allocator: &mut Allocator,
+    format: &'static str,
+    kernel_first: bool,
+) -> Result<(), SelfTestFailure> {
+    let context = FrameContext {
+        allocator: Some(allocator),
+    };
+    let fail = |step, error| SelfTestFailure {
+        format,
+        step,
+        error,
+    };
+    let mut space = AddressSpace::<_, E>::new(context).map_err(|err| {
+        fail(
+            "creating the address space",
+            SelfTestError::Mapping(err.into()),
+        )
+    })?;
+    let result = self_test_in(&mut space, kernel_first).map_err(|(step, err)| fail(step, err));
+    space.deallocate(|_| false);
+    result
+}
+
+/// Runs [`self_test`] in `space`, and returns the step that failed.
+fn self_test_in<E: PageEntry>(
+    space: &mut AddressSpace<FrameContext, E>,
+    kernel_first: bool,
+) -> Result<(), (&'static str, SelfTestError)> {
+    let user_rights = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let kernel = spaces::user_range()
+        .start
+        .next_multiple_of(E::HUGE_PAGE_SIZE);
+    let user = kernel + 0x1000;
+    // The space is never loaded, so the pages can be mapped to any frame.
+    let phys = space.page_directory();
+
+    let rights = |space: &AddressSpace<FrameContext, E>, virt| {
+        space
+            .flags(virt)
+            .map_or(PageTableFlags::empty(), |f| f & user_rights)
+    };
+    let expect = |step, found: PageTableFlags, expected: PageTableFlags| {
+        if found.bits() == expected.bits() {
+            Ok(())
+        } else {
+            Err((step, SelfTestError::Rights { found, expected }))
+        }
+    };
+
+    let order = match kernel_first {
+        true => [(kernel, PageTableFlags::empty()), (user, user_rights)],
+        false => [(user, user_rights), (kernel, PageTableFlags::empty())],
+    };
+    for (virt, flags) in order {
+        space
+            .map_4kib(virt, phys, flags)
+            .map_err(|err| ("mapping the pages", SelfTestError::Mapping(err)))?;
+    }
+
+    expect(
+        "kernel page",
+        rights(space, kernel),
+        PageTableFlags::empty(),
+    )?;
+    expect("user page", rights(space, user), user_rights)?;
+    let directory = space
+        .directory_entry(kernel)
+        .map_or(PageTableFlags::empty(), |e| unsafe {
+            (*e).access(E::LEVELS - 2)
+        });
+    expect("directory entry", directory, user_rights)?;
+
+    // Restricting the user page must not change the kernel page either.
+    space
+        .protect_4kib(user, PageTableFlags::USER_ACCESSIBLE)
+        .map_err(|err| ("protecting the user page", SelfTestError::Mapping(err)))?;
+    expect(
+        "kernel page after mprotect",
+        rights(space, kernel),
+        PageTableFlags::empty(),
+    )?;
+    expect(
+        "user page after mprotect",
+        rights(space, user),
+        PageTableFlags::USER_ACCESSIBLE,
+    )?;
+
+    space.unmap_4kib(kernel);
+    space.unmap_4kib(user);
+    Ok(())
+}
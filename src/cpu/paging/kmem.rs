@@ -0,0 +1,115 @@
+//! Memory that the kernel maps for itself, backed by frames of the allocator.
+//!
+//! The memory is mapped right away, with zeroed frames, and reserved in [`KERNEL_AREAS`]. It
+//! is mapped either at a fixed address, or anywhere in [`KERNEL_HEAP`], which lies between the
+//! user range and the kernel stacks. Like the rest of the kernel, it is mapped in every address
+//! space.
+
+use core::ops::Range;
+
+use crate::cpu::stack::THREAD_STACKS_START;
+use crate::state::{Allocator, FrameOwner};
+
+use super::vma::{Area, Backing};
+use super::{spaces, tlb, MappingError, PageTableFlags, KERNEL_AREAS};
+
+/// The addresses in which [`alloc_and_map`] places memory.
+pub const KERNEL_HEAP: Range<usize> = spaces::USER_END..THREAD_STACKS_START;
+
+/// Rounds `len` up to a multiple of 4 KiB, and checks that it is not zero.
+fn page_align(len: usize) -> Result<usize, MappingError> {
+    len.checked_next_multiple_of(4096)
+        .filter(|&len| len != 0)
+        .ok_or(MappingError::InvalidRange)
+}
+
+/// Returns the area of the memory mapped at `start..start + len`.
+fn area(start: usize, len: usize, flags: PageTableFlags, name: &'static str) -> Area {
+    Area {
+        start,
+        end: start + len,
+        flags,
+        backing: Backing::Anonymous,
+        name,
+        owner: None,
+    }
+}
+
+/// Allocates frames for `len` bytes, maps them anywhere in [`KERNEL_HEAP`] with the provided
+/// flags, and returns their address.
+///
+/// `name` describes the area in which the memory is reserved, such as `[thread stack]`.
+pub fn alloc_and_map(
+    allocator: &mut Allocator,
+    len: usize,
+    flags: PageTableFlags,
+    name: &'static str,
+) -> Result<usize, MappingError> {
+    let len = page_align(len)?;
+    let mut areas = KERNEL_AREAS.lock();
+    let start = areas
+        .find_free(len, KERNEL_HEAP.start, KERNEL_HEAP.end)
+        .ok_or(MappingError::OutOfMemory)?;
+    areas.insert(area(start, len, flags, name))?;
+    drop(areas);
+
+    populate(allocator, start, len, flags)?;
+    Ok(start)
+}
+
+/// Allocates frames for `len` bytes, and maps them at `start` with the provided flags.
+///
+/// Nothing may be mapped within the range yet. `name` is used like with [`alloc_and_map`].
+pub fn alloc_and_map_at(
+    allocator: &mut Allocator,
+    start: usize,
+    len: usize,
+    flags: PageTableFlags,
+    name: &'static str,
+) -> Result<(), MappingError> {
+    let len = page_align(len)?;
+    if start % 4096 != 0 || start.checked_add(len).is_none() {
+        return Err(MappingError::InvalidRange);
+    }
+    KERNEL_AREAS.lock().insert(area(start, len, flags, name))?;
+    populate(allocator, start, len, flags)
+}
+
+/// Maps zeroed frames in the area reserved at `start..start + len`.
+///
+/// The area is released if a frame cannot be allocated or mapped.
+fn populate(
+    allocator: &mut Allocator,
+    start: usize,
+    len: usize,
+    flags: PageTableFlags,
+) -> Result<(), MappingError> {
+    for virt in (start..start + len).step_by(4096) {
+        let mapped = allocator
+            .allocate_zeroed(FrameOwner::Kernel)
+            .map_err(MappingError::from)
+            .and_then(|phys| {
+                super::map_kernel_page(allocator, virt, phys, flags | tlb::kernel_flag())
+                    .inspect_err(|_| allocator.deallocate(phys))
+            });
+        if let Err(err) = mapped {
+            unmap_and_free(allocator, start, len);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Unmaps the memory mapped at `start..start + len` by [`alloc_and_map`] or
+/// [`alloc_and_map_at`], and releases its frames.
+///
+/// The pages of the range that are not mapped are ignored.
+pub fn unmap_and_free(allocator: &mut Allocator, start: usize, len: usize) {
+    let end = start.saturating_add(len).next_multiple_of(4096);
+    let _ = KERNEL_AREAS.lock().remove(start, end, |_| ());
+    for virt in (start..end).step_by(4096) {
+        if let Some(phys) = super::unmap_kernel_page(virt) {
+            allocator.deallocate(phys);
+        }
+    }
+}
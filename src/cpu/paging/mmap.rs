@@ -0,0 +1,347 @@
+//! The memory that processes map with the `mmap` system call.
+//!
+//! Mapping anonymous memory only reserves an area of the address space. No frame is allocated
+//! until a page of the area is first accessed: the page fault handler then maps a zeroed frame
+//! there (see [`handle_fault`]). The frames of shared-memory objects are mapped right away.
+
+use crate::oom;
+use crate::shm::{self, ObjectId};
+use crate::state::{self, FrameOwner, OutOfMemory, ProcessId, Resource, GLOBAL};
+use crate::utility::ArrayVec;
+
+use super::vma::{Area, Areas, Backing, MAX_AREAS};
+use super::{spaces, MappingError, PageTableFlags, KERNEL_AREAS};
+
+/// The pages may be read.
+pub const PROT_READ: usize = 1 << 0;
+/// The pages may be written.
+pub const PROT_WRITE: usize = 1 << 1;
+/// The pages may be executed.
+pub const PROT_EXEC: usize = 1 << 2;
+
+/// Returns the flags of the pages mapped with the protection `prot`.
+///
+/// `None` is returned if `prot` has unknown bits, or lacks [`PROT_READ`]: x86 pages that are
+/// present can always be read.
+pub fn protection_flags(prot: usize) -> Option<PageTableFlags> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 || prot & PROT_READ == 0 {
+        return None;
+    }
+
+    let mut flags = PageTableFlags::USER_ACCESSIBLE;
+    if prot & PROT_WRITE != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & PROT_EXEC == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    Some(flags)
+}
+
+/// Checks that `start..start + len` is a range of pages in which processes may map memory,
+/// and returns its end.
+fn check_range(start: usize, len: usize) -> Result<usize, MappingError> {
+    let range = spaces::user_range();
+    let end = start.checked_add(len).ok_or(MappingError::InvalidRange)?;
+    if len == 0 || start % 4096 != 0 || start < range.start || end > range.end {
+        return Err(MappingError::InvalidRange);
+    }
+    Ok(end)
+}
+
+/// Returns the ID of the process that is running, once processes exist.
+fn current_process() -> Option<ProcessId> {
+    Some(GLOBAL.get()?.processes.lock().current_id())
+}
+
+/// Checks that the owner of the current process may map `len` more bytes.
+fn check_limit(len: usize) -> Result<(), MappingError> {
+    let Some(glob) = GLOBAL.get() else {
+        return Ok(());
+    };
+    let processes = glob.processes.lock();
+    let owner = processes.current().owner;
+    if state::allows(&processes, owner, Resource::Memory, (len >> 12) as u32) {
+        Ok(())
+    } else {
+        Err(MappingError::OutOfMemory)
+    }
+}
+
+/// Rounds `len` up to a multiple of the size of a page.
+fn page_align(len: usize) -> Result<usize, MappingError> {
+    len.checked_next_multiple_of(4096)
+        .ok_or(MappingError::InvalidRange)
+}
+
+/// Returns the address at which `len` bytes should be mapped.
+///
+/// When `fixed` is set, this is `addr`, and what was mapped there is unmapped. Otherwise,
+/// `addr` is only used if nothing is mapped there.
+fn place(areas: &mut Areas, addr: usize, len: usize, fixed: bool) -> Result<usize, MappingError> {
+    if fixed {
+        let end = check_range(addr, len)?;
+        release(areas, addr, end)?;
+        return Ok(addr);
+    }
+
+    match check_range(addr, len) {
+        Ok(end) if !areas.overlaps(addr, end) => Ok(addr),
+        _ => {
+            let range = spaces::user_range();
+            areas
+                .find_free(len, range.start, range.end)
+                .ok_or(MappingError::OutOfMemory)
+        }
+    }
+}
+
+/// Maps `len` bytes of anonymous memory with the provided flags, and returns its address.
+///
+/// When `fixed` is set, the memory is mapped at `addr`, replacing what was mapped there.
+/// Otherwise, `addr` is only used if the memory fits there. This fails if the owner of the
+/// current process would exceed its [memory limit](Resource::Memory).
+pub fn map(
+    addr: usize,
+    len: usize,
+    flags: PageTableFlags,
+    fixed: bool,
+) -> Result<usize, MappingError> {
+    let len = page_align(len)?;
+    check_limit(len)?;
+    let owner = current_process();
+    let mut areas = KERNEL_AREAS.lock();
+
+    let start = place(&mut areas, addr, len, fixed)?;
+    areas.insert(Area {
+        start,
+        end: start + len,
+        flags,
+        backing: Backing::Anonymous,
+        name: "[anon]",
+        owner,
+    })?;
+    drop(areas);
+
+    if let Some(glob) = GLOBAL.get() {
+        let mut processes = glob.processes.lock();
+        processes
+            .current_mut()
+            .memory
+            .map((len >> 12) as u32, false);
+    }
+
+    Ok(start)
+}
+
+/// Maps `len` bytes of the shared-memory object `object`, starting at `offset`, and returns
+/// their address.
+///
+/// `addr` and `fixed` are used like with [`map`].
+pub fn map_shared(
+    addr: usize,
+    len: usize,
+    flags: PageTableFlags,
+    fixed: bool,
+    object: ObjectId,
+    offset: usize,
+) -> Result<usize, MappingError> {
+    let len = page_align(len)?;
+    check_limit(len)?;
+    let owner = current_process();
+    let mut areas = KERNEL_AREAS.lock();
+
+    let frames = shm::acquire(object, offset, len >> 12)?;
+    let start = match place(&mut areas, addr, len, fixed) {
+        Ok(start) => start,
+        Err(err) => {
+            shm::release(object, frames.len());
+            return Err(err);
+        }
+    };
+    let area = Area {
+        start,
+        end: start + len,
+        flags,
+        backing: Backing::Shared { object, offset },
+        name: "[shm]",
+        owner,
+    };
+    if let Err(err) = areas.insert(area) {
+        shm::release(object, frames.len());
+        return Err(err);
+    }
+
+    let glob = GLOBAL.get().unwrap();
+    let mut allocator = glob.allocator.lock();
+    for (virt, &phys) in (start..start + len).step_by(4096).zip(frames.iter()) {
+        if let Err(err) = super::map_kernel_page(&mut allocator, virt, phys, flags) {
+            drop(allocator);
+            if matches!(err, MappingError::OutOfMemory) {
+                oom::report("mapping a shared-memory object");
+            }
+            // The pages that were mapped are unmapped along with the area.
+            let _ = release(&mut areas, start, start + len);
+            return Err(err);
+        }
+    }
+    drop(allocator);
+    drop(areas);
+
+    let mut processes = glob.processes.lock();
+    processes
+        .current_mut()
+        .memory
+        .map((len >> 12) as u32, false);
+    Ok(start)
+}
+
+/// Unmaps the memory within `addr..addr + len`.
+///
+/// The parts of the range where nothing is mapped are ignored.
+pub fn unmap(addr: usize, len: usize) -> Result<(), MappingError> {
+    let len = page_align(len)?;
+    let mut areas = KERNEL_AREAS.lock();
+    let end = check_range(addr, len)?;
+    release(&mut areas, addr, end)
+}
+
+/// Changes the flags of the memory within `addr..addr + len`.
+///
+/// The whole range must be mapped.
+pub fn protect(addr: usize, len: usize, flags: PageTableFlags) -> Result<(), MappingError> {
+    let len = page_align(len)?;
+    let mut areas = KERNEL_AREAS.lock();
+    let end = check_range(addr, len)?;
+
+    let covered: usize = areas
+        .iter()
+        .map(|area| area.end.min(end).saturating_sub(area.start.max(addr)))
+        .sum();
+    if covered != len {
+        return Err(MappingError::OutOfMemory);
+    }
+
+    let mut result = Ok(());
+    areas.protect(addr, end, flags, |area| {
+        for virt in (area.start..area.end).step_by(4096) {
+            match super::protect_kernel_page(virt, flags) {
+                // The page is not resident yet.
+                Err(MappingError::InvalidRange) => (),
+                Err(err) => result = Err(err),
+                Ok(()) => (),
+            }
+        }
+    })?;
+    result
+}
+
+/// Releases the memory that the process `id` mapped.
+///
+/// This is called once the last thread of the process exited.
+pub fn release_process(id: ProcessId) {
+    let mut areas = KERNEL_AREAS.lock();
+    let owned: ArrayVec<(usize, usize), MAX_AREAS> = areas
+        .iter()
+        .filter(|area| area.owner == Some(id))
+        .map(|area| (area.start, area.end))
+        .collect();
+
+    for &(start, end) in owned.iter() {
+        // Removing a whole area never splits another one.
+        let _ = release(&mut areas, start, end);
+    }
+}
+
+/// Removes the areas within `start..end`, and releases the frames that were allocated for
+/// them.
+///
+/// The pages are no longer charged to the processes that mapped them.
+fn release(areas: &mut Areas, start: usize, end: usize) -> Result<(), MappingError> {
+    let Some(glob) = GLOBAL.get() else {
+        return areas.remove(start, end, |_| ());
+    };
+
+    areas.remove(start, end, |area| {
+        let mapped = (area.len() >> 12) as u32;
+        let mut resident = 0;
+        let pages = (area.start..area.end).step_by(4096);
+        match area.backing {
+            Backing::Shared { object, .. } => {
+                pages.for_each(|virt| _ = super::unmap_kernel_page(virt));
+                shm::release(object, area.len() >> 12);
+            }
+            _ => {
+                let mut allocator = glob.allocator.lock();
+                for phys in pages.filter_map(super::unmap_kernel_page) {
+                    allocator.deallocate(phys);
+                    resident += 1;
+                }
+            }
+        }
+
+        let mut processes = glob.processes.lock();
+        if let Some(process) = area.owner.and_then(|id| processes.get_mut(id)) {
+            process.memory.unmap(mapped - resident, false);
+            process.memory.unmap(resident, true);
+        }
+    })
+}
+
+/// Returns whether the page that contains `virt` was mapped with [`map`], and allows the
+/// requested access.
+///
+/// The page may not be resident yet.
+pub fn is_accessible(virt: usize, write: bool) -> bool {
+    KERNEL_AREAS.lock().find(virt).is_some_and(|area| {
+        area.backing == Backing::Anonymous
+            && area.flags.contains(PageTableFlags::USER_ACCESSIBLE)
+            && (!write || area.flags.contains(PageTableFlags::WRITABLE))
+    })
+}
+
+/// Handles a page fault caused by an access to a page that is not present.
+///
+/// If the page belongs to memory mapped with [`map`], and the access is allowed, a zeroed
+/// frame is mapped there and `true` is returned. Otherwise, the fault cannot be recovered from.
+///
+/// When no frame can be allocated for the page, the event is reported (see [`oom::report`])
+/// and an error is returned.
+pub fn handle_fault(virt: usize, write: bool, fetch: bool) -> Result<bool, OutOfMemory> {
+    let Some(glob) = GLOBAL.get() else {
+        return Ok(false);
+    };
+
+    let areas = KERNEL_AREAS.lock();
+    let Some(area) = areas.find(virt) else {
+        return Ok(false);
+    };
+    if area.backing != Backing::Anonymous
+        || !area.flags.contains(PageTableFlags::USER_ACCESSIBLE)
+        || (write && !area.flags.contains(PageTableFlags::WRITABLE))
+        || (fetch && area.flags.contains(PageTableFlags::NO_EXECUTE))
+    {
+        return Ok(false);
+    }
+
+    let mut allocator = glob.allocator.lock();
+    let mapped =
+        allocator.allocate_zeroed(FrameOwner::Process).and_then(
+            |phys| match super::map_kernel_page(&mut allocator, virt & !0xFFF, phys, area.flags) {
+                Ok(()) => Ok(()),
+                Err(_) => {
+                    allocator.deallocate(phys);
+                    Err(OutOfMemory)
+                }
+            },
+        );
+    drop(allocator);
+    drop(areas);
+
+    if let Err(err) = mapped {
+        oom::report("handling a page fault");
+        return Err(err);
+    }
+    glob.processes.lock().current_mut().memory.resident += 1;
+    Ok(true)
+}
@@ -0,0 +1,137 @@
+//! The memory-mapped registers of devices.
+//!
+//! Device memory is mapped in [`KERNEL_HEAP`] with caching disabled, so that every access
+//! reaches the device in order. It is only accessed through [`VolatileMmio`], whose accesses the
+//! compiler cannot merge or elide.
+
+use core::mem::{align_of, size_of};
+
+use crate::state::Allocator;
+
+use super::kmem::KERNEL_HEAP;
+use super::vma::{Area, Backing};
+use super::{tlb, MappingError, PageTableFlags, KERNEL_AREAS};
+
+/// The flags of the pages that map device memory.
+const MMIO_FLAGS: PageTableFlags = PageTableFlags::WRITABLE
+    .union(PageTableFlags::NO_EXECUTE)
+    .union(PageTableFlags::CACHE_DISABLED)
+    .union(PageTableFlags::WRITE_THROUGH);
+
+/// Maps the `len` bytes of device memory at `phys` in the kernel's address space.
+///
+/// The memory must lie below 4 GiB. It is unmapped when the returned value is dropped. The
+/// missing page tables are allocated from `allocator`.
+pub fn map_mmio(
+    allocator: &mut Allocator,
+    phys: u64,
+    len: usize,
+) -> Result<VolatileMmio, MappingError> {
+    let offset = (phys % 4096) as usize;
+    let first = phys - offset as u64;
+    let pages = offset
+        .checked_add(len)
+        .and_then(|len| len.checked_next_multiple_of(4096))
+        .filter(|&pages| pages != 0 && first + pages as u64 <= 1 << 32)
+        .ok_or(MappingError::InvalidRange)?;
+
+    let mut areas = KERNEL_AREAS.lock();
+    let start = areas
+        .find_free(pages, KERNEL_HEAP.start, KERNEL_HEAP.end)
+        .ok_or(MappingError::OutOfMemory)?;
+    areas.insert(Area {
+        start,
+        end: start + pages,
+        flags: PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+        backing: Backing::Device { phys: first },
+        name: "[mmio]",
+        owner: None,
+    })?;
+    drop(areas);
+
+    let mmio = VolatileMmio {
+        base: start + offset,
+        len,
+        start,
+        pages,
+    };
+    for page in (0..pages).step_by(4096) {
+        let flags = MMIO_FLAGS | tlb::kernel_flag();
+        // If this fails, the pages that were mapped are unmapped along with `mmio`.
+        super::map_kernel_page(allocator, start + page, (first + page as u64) as u32, flags)?;
+    }
+    Ok(mmio)
+}
+
+/// Registers of a device, mapped by [`map_mmio`].
+///
+/// Offsets are in bytes from the physical address that was mapped.
+#[derive(Debug)]
+pub struct VolatileMmio {
+    /// The virtual address of the registers.
+    base: usize,
+    /// The size of the registers, in bytes.
+    len: usize,
+    /// The first page of the mapping.
+    start: usize,
+    /// The size of the mapping, in bytes.
+    pages: usize,
+}
+
+impl VolatileMmio {
+    /// Returns a pointer to the register at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a `T` at `offset` does not lie within the registers, or is not aligned.
+    fn register<T>(&self, offset: usize) -> *mut T {
+        assert!(
+            offset
+                .checked_add(size_of::<T>())
+                .is_some_and(|end| end <= self.len),
+            "access out of the bounds of the registers"
+        );
+        let ptr = self.base + offset;
+        assert!(ptr % align_of::<T>() == 0, "unaligned access to a register");
+        ptr as *mut T
+    }
+
+    /// Reads the register at `offset`.
+    ///
+    /// # Safety
+    ///
+    /// Reading the register must not have side effects that compromise memory safety.
+    #[inline]
+    pub unsafe fn read<T: Copy>(&self, offset: usize) -> T {
+        self.register::<T>(offset).read_volatile()
+    }
+
+    /// Writes `value` to the register at `offset`.
+    ///
+    /// # Safety
+    ///
+    /// Writing the register must not have side effects that compromise memory safety, such as
+    /// making the device write to arbitrary memory.
+    #[inline]
+    pub unsafe fn write<T: Copy>(&self, offset: usize, value: T) {
+        self.register::<T>(offset).write_volatile(value);
+    }
+
+    /// Returns a pointer to the first register.
+    #[inline(always)]
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.base as *mut u8
+    }
+}
+
+impl Drop for VolatileMmio {
+    fn drop(&mut self) {
+        let _ = KERNEL_AREAS
+            .lock()
+            .remove(self.start, self.start + self.pages, |_| ());
+        // The frames belong to the device, and are not released.
+        for page in (self.start..self.start + self.pages).step_by(4096) {
+            let _ = super::unmap_kernel_page(page);
+        }
+    }
+}
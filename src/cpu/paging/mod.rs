@@ -2,74 +2,296 @@
 
 mod address_space;
 mod model;
+mod recursive;
+
+pub mod check;
+pub mod kmem;
+pub mod mmap;
+pub mod mmio;
+pub mod pae;
+pub mod pse36;
+pub mod spaces;
+pub mod tlb;
+pub mod uaccess;
+pub mod vma;
 
 use core::alloc::Layout;
 use core::arch::asm;
 
 use crate::die::oom;
-use crate::state::OutOfMemory;
-use crate::utility::InitAllocator;
+use crate::state::{Allocator, OutOfMemory};
+use crate::utility::instr::{Cr0, Cr4};
+use crate::utility::{InitAllocator, Mutex};
+use crate::{kernel_image, log};
 
 pub use self::address_space::*;
 pub use self::model::*;
+pub use self::pae::PaeEntry;
+pub use self::recursive::*;
 
-/// Initiates paging and memory protection for the kernel.
-pub unsafe fn init(allocator: &mut InitAllocator, upper_bound: u32) {
-    struct InitContext<'a> {
-        allocator: &'a mut InitAllocator,
-    }
+use self::vma::{Area, Areas, Backing};
 
-    unsafe impl<'a> Context for InitContext<'a> {
-        #[inline]
-        fn allocate(&mut self) -> Result<u32, OutOfMemory> {
-            let layout = unsafe { Layout::from_size_align_unchecked(4096, 4096) };
-            self.allocator
-                .try_allocate_raw(layout)
-                .map(|addr| addr as u32)
-        }
+/// The areas of the kernel's address space, along with the ones that processes mapped.
+///
+/// The areas of the processes never overlap, even when they run in different address spaces
+/// (see [`spaces`]).
+pub static KERNEL_AREAS: Mutex<Areas> = Mutex::new(Areas::new());
 
-        unsafe fn deallocate(&mut self, _: u32) {
-            unreachable!("this Context implementation should never be used to deallocate pages");
-        }
+/// A [`Context`] used when paging is not enabled.
+struct InitContext<'a> {
+    allocator: &'a mut InitAllocator,
+}
 
-        #[inline]
-        unsafe fn map(&self, physical: u32) -> *mut u8 {
-            // At this point in the execution, we are setting up the kernel's address space, meaning
-            // that paging is not yet initiating. Every "virtual" address is equal to its
-            // physical address.
-            physical as *mut u8
-        }
+unsafe impl<'a> Context for InitContext<'a> {
+    #[inline]
+    fn allocate(&mut self) -> Result<u32, OutOfMemory> {
+        let layout = unsafe { Layout::from_size_align_unchecked(4096, 4096) };
+        self.allocator
+            .try_allocate_raw(layout)
+            .map(|addr| addr as u32)
+    }
+
+    unsafe fn deallocate(&mut self, _: u32) {
+        unreachable!("this Context implementation should never be used to deallocate pages");
     }
 
-    let mut address_space = AddressSpace::new(InitContext { allocator }).unwrap_or_else(|_| oom());
+    #[inline]
+    unsafe fn map(&self, physical: u32) -> *mut u8 {
+        // At this point in the execution, we are setting up the kernel's address space, meaning
+        // that paging is not yet initiating. Every "virtual" address is equal to its
+        // physical address.
+        physical as *mut u8
+    }
+}
 
-    // Identity map the whole address space.
-    address_space
-        .map_range(0, 0, upper_bound as usize, PageTableFlags::WRITABLE)
-        .unwrap_or_else(|err| handle_mapping_error(err));
-    let page_directory = address_space.page_directory();
-    address_space.leak();
+/// Initiates paging and memory protection for the kernel.
+///
+/// When `pae` is set and the CPU supports it, the PAE paging mode is used. This allows marking
+/// every page that does not contain code as non-executable, if the CPU supports the NX bit.
+/// Otherwise, PSE-36 is used when available to reach the memory above 4 GiB.
+///
+/// `huge_pages` selects whether the memory that is identity mapped may use huge pages.
+pub unsafe fn init(
+    allocator: &mut InitAllocator,
+    upper_bound: u32,
+    pae: bool,
+    huge_pages: HugePages,
+) {
+    let pae = pae && pae::is_supported();
+    let nx = pae && pae::enable_nx();
+
+    log!(
+        "Paging mode: {} (NX: {})\n",
+        if pae { "PAE" } else { "legacy" },
+        if nx { "enabled" } else { "unavailable" },
+    );
+
+    // Must be known before the mappings of the kernel are created.
+    let global = tlb::is_global_supported();
+    if global {
+        tlb::use_global_pages();
+    }
+    log!(
+        "Global pages: {}\n",
+        if global { "enabled" } else { "unavailable" }
+    );
+    log!(
+        "Huge pages: {}\n",
+        match huge_pages {
+            HugePages::Prefer => "enabled",
+            HugePages::Forbid => "disabled",
+        }
+    );
+
+    let context = InitContext { allocator };
+    let (page_directory, mut cr4) = if pae {
+        (
+            identity_map::<PaeEntry>(context, upper_bound, huge_pages),
+            Cr4::PHYSICAL_ADDRESS_EXTENSION,
+        )
+    } else {
+        (
+            identity_map::<PageTableFlags>(context, upper_bound, huge_pages),
+            Cr4::PAGE_SIZE_EXTENSION,
+        )
+    };
+    if global {
+        cr4 |= Cr4::PAGE_GLOBAL;
+    }
 
     asm!(
-        // Update the CR3 register with our page directory.
+        // Update the CR3 register with our root page table.
         "
         mov cr3, {page_directory}
         ",
-        // Make sure that the PSE is enabled (this is necessary to use 4MiB mappings).
+        // Select the paging mode.
         "
         mov {tmp}, cr4
-        or {tmp}, 0x00000010
+        or {tmp}, {cr4}
         mov cr4, {tmp}
         ",
-        // Enable paging.
+        // Enable paging, and make read-only pages read-only for the kernel too.
         "
         mov {tmp}, cr0
-        or {tmp}, 0x80000000
+        or {tmp}, {cr0}
         mov cr0, {tmp}
         ",
         page_directory = in(reg) page_directory,
+        cr4 = in(reg) cr4.bits(),
+        cr0 = const Cr0::PAGING.union(Cr0::WRITE_PROTECT).bits(),
         tmp = lateout(reg) _,
     );
+
+    // Memory above 4 GiB can only be reached through PSE-36 with legacy paging.
+    if !pae && pse36::is_supported() {
+        pse36::init(upper_bound);
+        log!(
+            "PSE-36: {} bits of physical address space\n",
+            pse36::physical_address_bits(),
+        );
+    }
+    spaces::init(page_directory, upper_bound as usize);
+}
+
+/// Creates the kernel's address space, and returns the physical address of its root table.
+///
+/// The physical memory up to `upper_bound` is identity mapped. The sections of the kernel are
+/// mapped with their own permissions, so that stray writes to its code or read-only data fault.
+/// The page tables are mapped recursively, so that they can be edited through a
+/// [`CurrentAddressSpace`] once the address space is in use.
+fn identity_map<E: PageEntry>(
+    context: InitContext,
+    upper_bound: u32,
+    huge_pages: HugePages,
+) -> u32 {
+    let mut address_space = AddressSpace::<_, E>::new(context).unwrap_or_else(|_| oom());
+
+    // Memory that is not part of the kernel image only holds data (stacks, heaps, ...).
+    let data = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+
+    let mut areas = KERNEL_AREAS.lock();
+    let mut identity_map = |start: usize, end: usize, flags: PageTableFlags, name| {
+        areas
+            .insert(Area {
+                start,
+                end,
+                flags,
+                backing: Backing::Device { phys: start as u64 },
+                name,
+                owner: None,
+            })
+            .unwrap_or_else(|err| handle_mapping_error(err));
+        address_space
+            .map_range(
+                start,
+                start as u32,
+                end - start,
+                flags | tlb::kernel_flag(),
+                huge_pages,
+            )
+            .unwrap_or_else(|err| handle_mapping_error(err));
+    };
+
+    let mut mapped = 0;
+    for section in kernel_image::sections() {
+        if section.start > mapped {
+            identity_map(mapped, section.start, data, "[physical]");
+        }
+
+        if section.end > section.start {
+            identity_map(section.start, section.end, section.flags(), section.name);
+        }
+        mapped = section.end;
+    }
+    if (upper_bound as usize) > mapped {
+        identity_map(mapped, upper_bound as usize, data, "[physical]");
+    }
+
+    // The last page of the window cannot be part of an area, as its end would not fit.
+    areas
+        .insert(Area {
+            start: E::RECURSIVE_WINDOW,
+            end: usize::MAX & !0xFFF,
+            flags: data,
+            backing: Backing::PageTables,
+            name: "[page tables]",
+            owner: None,
+        })
+        .unwrap_or_else(|err| handle_mapping_error(err));
+    address_space
+        .map_recursively()
+        .unwrap_or_else(|err| handle_mapping_error(err));
+
+    let page_directory = address_space.page_directory();
+    address_space.leak();
+    page_directory
+}
+
+/// Returns whether the PAE paging mode is in use.
+pub fn is_pae_enabled() -> bool {
+    Cr4::read().contains(Cr4::PHYSICAL_ADDRESS_EXTENSION)
+}
+
+/// Returns the size of the huge pages of the paging mode in use.
+pub fn huge_page_size() -> usize {
+    if is_pae_enabled() {
+        PaeEntry::HUGE_PAGE_SIZE
+    } else {
+        PageTableFlags::HUGE_PAGE_SIZE
+    }
+}
+
+/// Returns the effective flags of the page that contains `virt` in the current address space.
+///
+/// The access rights of the entries that reference the page are taken into account, so that
+/// the returned flags reflect the effective permissions of the page.
+pub fn current_flags(virt: usize) -> Option<PageTableFlags> {
+    if is_pae_enabled() {
+        unsafe { CurrentAddressSpace::<PaeEntry>::new() }.flags(virt)
+    } else {
+        unsafe { CurrentAddressSpace::<PageTableFlags>::new() }.flags(virt)
+    }
+}
+
+/// Maps the 4 KiB page at `phys` to `virt` in the kernel's address space.
+///
+/// The missing page table is allocated from `allocator`. `virt` must not be mapped yet.
+pub fn map_kernel_page(
+    allocator: &mut Allocator,
+    virt: usize,
+    phys: u32,
+    flags: PageTableFlags,
+) -> Result<(), MappingError> {
+    if is_pae_enabled() {
+        unsafe { CurrentAddressSpace::<PaeEntry>::new() }.map_4kib(allocator, virt, phys, flags)
+    } else {
+        unsafe { CurrentAddressSpace::<PageTableFlags>::new() }
+            .map_4kib(allocator, virt, phys, flags)
+    }
+}
+
+/// Unmaps the 4 KiB page at `virt` from the kernel's address space, and returns the physical
+/// page it was mapped to.
+///
+/// The page is flushed from the TLB, but not deallocated.
+pub fn unmap_kernel_page(virt: usize) -> Option<u32> {
+    if is_pae_enabled() {
+        unsafe { CurrentAddressSpace::<PaeEntry>::new() }.unmap_4kib(virt)
+    } else {
+        unsafe { CurrentAddressSpace::<PageTableFlags>::new() }.unmap_4kib(virt)
+    }
+}
+
+/// Replaces the flags of the 4 KiB page at `virt` in the kernel's address space, and flushes
+/// it from the TLB.
+///
+/// The page must be mapped. Whether it is global is kept.
+pub fn protect_kernel_page(virt: usize, flags: PageTableFlags) -> Result<(), MappingError> {
+    if is_pae_enabled() {
+        unsafe { CurrentAddressSpace::<PaeEntry>::new() }.protect_4kib(virt, flags)
+    } else {
+        unsafe { CurrentAddressSpace::<PageTableFlags>::new() }.protect_4kib(virt, flags)
+    }
 }
 
 /// Handle a mapping error occuring within the initialization routine.
@@ -77,5 +299,7 @@ fn handle_mapping_error(err: MappingError) -> ! {
     match err {
         MappingError::OutOfMemory => oom(),
         MappingError::AlreadyMapped => panic!("attempted to map a region that was already mapped"),
+        MappingError::InvalidRange => panic!("attempted to map an invalid region"),
+        MappingError::TooManyAreas => panic!("too many areas in the kernel's address space"),
     }
 }
@@ -2,6 +2,9 @@ use core::ops::{Index, IndexMut};
 
 use bitflags::bitflags;
 
+use super::address_space::ACCESS_RIGHTS;
+use super::pse36;
+
 bitflags! {
     /// Represents the bits that a page table entry can have.
     #[derive(Debug, Clone, Copy)]
@@ -37,6 +40,13 @@ bitflags! {
         /// This means that the page directory entry is not flushed from the TLB when the CR3
         /// register is overwritten.
         const GLOBAL = 1 << 8;
+        /// Whether instructions may not be fetched from the page.
+        ///
+        /// This bit is available to the software in legacy entries and is ignored by the CPU.
+        /// PAE entries translate it to their actual NX bit (see [`PaeEntry`]).
+        ///
+        /// [`PaeEntry`]: super::PaeEntry
+        const NO_EXECUTE = 1 << 11;
     }
 }
 
@@ -73,6 +83,142 @@ impl PageTableFlags {
     }
 }
 
+/// The format of the entries of a page table.
+///
+/// This abstracts over the legacy 32-bit format ([`PageTableFlags`]) and the format used with
+/// the Physical Address Extension ([`PaeEntry`](super::PaeEntry)). Levels are numbered from the
+/// root table (level 0) to the tables that map 4 KiB pages (level `LEVELS - 1`).
+///
+/// # Safety
+///
+/// The implementation must match the format expected by the CPU in the corresponding paging
+/// mode.
+pub unsafe trait PageEntry: Copy {
+    /// The number of levels of page tables.
+    const LEVELS: usize;
+
+    /// The size of the pages mapped by the entries of the level `LEVELS - 2`.
+    const HUGE_PAGE_SIZE: usize;
+
+    /// An entry that is not present.
+    const EMPTY: Self;
+
+    /// The first address of the window through which the page tables that map the address
+    /// space are accessed, once they are mapped recursively. It extends to the end of the
+    /// address space.
+    ///
+    /// See [`AddressSpace::map_recursively`](super::AddressSpace::map_recursively).
+    const RECURSIVE_WINDOW: usize;
+
+    /// Returns the index of the entry that translates `virt` in a table of the provided level.
+    fn index(virt: usize, level: usize) -> usize;
+
+    /// Creates an entry referencing the page table at `phys`, that will be written in a table
+    /// of the provided level.
+    fn table(phys: u32, level: usize, flags: PageTableFlags) -> Self;
+
+    /// Creates an entry mapping the page at `phys`.
+    ///
+    /// `huge` is set when the entry maps a page of [`HUGE_PAGE_SIZE`](Self::HUGE_PAGE_SIZE)
+    /// bytes.
+    fn page(phys: u32, flags: PageTableFlags, huge: bool) -> Self;
+
+    /// Returns the flags of the entry.
+    ///
+    /// The physical address referenced by the entry is not included.
+    fn flags(self) -> PageTableFlags;
+
+    /// Returns the physical address referenced by the entry.
+    fn address(self) -> u32;
+
+    /// Returns the access rights (`WRITABLE` and `USER_ACCESSIBLE`) granted by the entry, which
+    /// is located in a table of the provided level.
+    fn access(self, level: usize) -> PageTableFlags;
+
+    /// Updates the flags of an entry referencing a page table so that the flags of a new child
+    /// entry are not restricted by it.
+    ///
+    /// Only the access rights of the child are added, as the entry must grant the union of
+    /// the rights of its children. The rights of each page are restricted by the entry that
+    /// maps it, so the other children of the table are not affected.
+    fn merge_flags(&mut self, level: usize, child: PageTableFlags);
+
+    /// Returns whether bits that the CPU reserves are set in the entry, which is located in a
+    /// table of the provided level.
+    ///
+    /// The CPU raises a page fault when it reads such an entry.
+    fn has_reserved_bits(self, level: usize) -> bool;
+}
+
+unsafe impl PageEntry for PageTableFlags {
+    const LEVELS: usize = 2;
+    const HUGE_PAGE_SIZE: usize = 4096 * 1024;
+    const EMPTY: Self = Self::empty();
+    const RECURSIVE_WINDOW: usize = 0xFFC0_0000;
+
+    #[inline]
+    fn index(virt: usize, level: usize) -> usize {
+        match level {
+            0 => PageTableIndex::extract_page_directory_index(virt).as_usize(),
+            _ => PageTableIndex::extract_page_table_index(virt).as_usize(),
+        }
+    }
+
+    #[inline]
+    fn table(phys: u32, _level: usize, flags: PageTableFlags) -> Self {
+        // The other flags, such as the caching policy, only concern the page that is mapped.
+        (flags & ACCESS_RIGHTS) | Self::PRESENT | Self::from_bits_retain(phys)
+    }
+
+    #[inline]
+    fn page(phys: u32, flags: PageTableFlags, huge: bool) -> Self {
+        // The CPU ignores the NX bit of legacy entries, so it is not kept to avoid reporting a
+        // protection that does not exist.
+        let mut ret = (flags - Self::NO_EXECUTE) | Self::PRESENT | Self::from_bits_retain(phys);
+        ret.set(Self::HUGE_PAGE, huge);
+        ret
+    }
+
+    #[inline]
+    fn flags(self) -> PageTableFlags {
+        self & Self::from_bits_retain(0xFFF)
+    }
+
+    #[inline]
+    fn address(self) -> u32 {
+        if self.is_huge_page() {
+            self.address_4mib()
+        } else {
+            self.address_4kib()
+        }
+    }
+
+    #[inline]
+    fn access(self, _level: usize) -> PageTableFlags {
+        self & (Self::WRITABLE | Self::USER_ACCESSIBLE)
+    }
+
+    #[inline]
+    fn merge_flags(&mut self, _level: usize, child: PageTableFlags) {
+        *self |= child & ACCESS_RIGHTS;
+    }
+
+    fn has_reserved_bits(self, level: usize) -> bool {
+        if level != 0 || !self.is_huge_page() {
+            return false;
+        }
+        // Bits 13 to 21 of the entries that map huge pages are reserved, except those in which
+        // PSE-36 stores the high bits of the address.
+        let high = if pse36::is_supported() {
+            pse36::physical_address_bits() - 32
+        } else {
+            0
+        };
+        let reserved = (0x1FF << 13) & !(((1 << high) - 1) << 13);
+        self.bits() & reserved != 0
+    }
+}
+
 /// Represents a page table or page directory (depending on where it is located).
 #[derive(Clone, Copy, Debug)]
 #[repr(align(4096))]
@@ -0,0 +1,170 @@
+//! The page table format used by the Physical Address Extension (PAE).
+//!
+//! With PAE, entries are 64 bits wide and a third level of tables is added: a four-entry page
+//! directory pointer table sits on top of the page directories. Huge pages are 2 MiB large. The
+//! main advantage of this mode for the kernel is the NX bit, which prevents instructions from
+//! being fetched from data pages.
+
+use core::arch::x86::{__cpuid, has_cpuid};
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::utility::instr::Msr;
+
+use super::address_space::ACCESS_RIGHTS;
+use super::{PageEntry, PageTableFlags};
+
+/// The bit of the EDX register returned by `cpuid(1)` indicating support for PAE.
+const CPUID_PAE: u32 = 1 << 6;
+/// The bit of the EDX register returned by `cpuid(0x80000001)` indicating support for the NX
+/// bit.
+const CPUID_NX: u32 = 1 << 20;
+
+/// The bit of EFER that enables the NX bit.
+const EFER_NXE: u64 = 1 << 11;
+
+/// The NX bit of a PAE entry.
+const NX: u64 = 1 << 63;
+
+/// The bits of an entry that hold the physical address it references.
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Whether the NX bit has been enabled in EFER.
+///
+/// The NX bit is a reserved bit until then, and setting it would cause page faults.
+static NX_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// An entry of a PAE page table.
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct PaeEntry(u64);
+
+unsafe impl PageEntry for PaeEntry {
+    const LEVELS: usize = 3;
+    const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+    const EMPTY: Self = Self(0);
+    const RECURSIVE_WINDOW: usize = 0xFF80_0000;
+
+    #[inline]
+    fn index(virt: usize, level: usize) -> usize {
+        match level {
+            0 => virt >> 30,
+            1 => (virt >> 21) & 0x1FF,
+            _ => (virt >> 12) & 0x1FF,
+        }
+    }
+
+    #[inline]
+    fn table(phys: u32, level: usize, flags: PageTableFlags) -> Self {
+        if level == 0 {
+            // Entries of the page directory pointer table only support a few flags. The others
+            // are reserved.
+            Self(phys as u64 | PageTableFlags::PRESENT.bits() as u64)
+        } else {
+            // The other flags, such as the caching policy or the NX bit, only concern the page
+            // that is mapped.
+            Self::page(phys, flags & ACCESS_RIGHTS, false)
+        }
+    }
+
+    #[inline]
+    fn page(phys: u32, flags: PageTableFlags, huge: bool) -> Self {
+        let mut low = (flags - PageTableFlags::NO_EXECUTE) | PageTableFlags::PRESENT;
+        low.set(PageTableFlags::HUGE_PAGE, huge);
+
+        let mut ret = phys as u64 | low.bits() as u64;
+        if flags.contains(PageTableFlags::NO_EXECUTE) && NX_ENABLED.load(Relaxed) {
+            ret |= NX;
+        }
+        Self(ret)
+    }
+
+    #[inline]
+    fn flags(self) -> PageTableFlags {
+        let mut ret = PageTableFlags::from_bits_retain(self.0 as u32 & 0xFFF);
+        ret.set(PageTableFlags::NO_EXECUTE, self.0 & NX != 0);
+        ret
+    }
+
+    #[inline]
+    fn address(self) -> u32 {
+        (self.0 & ADDRESS_MASK) as u32
+    }
+
+    #[inline]
+    fn access(self, level: usize) -> PageTableFlags {
+        let rights = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        if level == 0 {
+            // Entries of the page directory pointer table do not restrict accesses.
+            rights
+        } else {
+            self.flags() & rights
+        }
+    }
+
+    #[inline]
+    fn merge_flags(&mut self, level: usize, child: PageTableFlags) {
+        if level != 0 {
+            self.0 |= (child & ACCESS_RIGHTS).bits() as u64;
+        }
+    }
+
+    fn has_reserved_bits(self, level: usize) -> bool {
+        // The bits above the physical address width are reserved, up to the NX bit.
+        let mut reserved = (!0 << physical_address_bits()) & !NX;
+        if level == 0 || !NX_ENABLED.load(Relaxed) {
+            reserved |= NX;
+        }
+        if level == 0 {
+            // Entries of the page directory pointer table only support a few flags.
+            reserved |= 0x1E6;
+        } else if level == 1 && self.flags().is_huge_page() {
+            // Bits 13 to 20 of the entries that map huge pages are reserved.
+            reserved |= 0xFF << 13;
+        }
+        self.0 & reserved != 0
+    }
+}
+
+/// Returns the number of bits of the physical addresses that the CPU supports.
+pub fn physical_address_bits() -> u32 {
+    let reported = unsafe {
+        if __cpuid(0x8000_0000).eax >= 0x8000_0008 {
+            __cpuid(0x8000_0008).eax & 0xFF
+        } else {
+            36
+        }
+    };
+    reported.min(52)
+}
+
+/// Returns whether the CPU supports PAE.
+pub fn is_supported() -> bool {
+    has_cpuid() && unsafe { __cpuid(1).edx & CPUID_PAE != 0 }
+}
+
+/// Enables the NX bit, if the CPU supports it.
+///
+/// Returns whether the NX bit can be used. This must be called before creating the entries
+/// that should be protected, and only if PAE is supported.
+pub fn enable_nx() -> bool {
+    unsafe {
+        if __cpuid(0x8000_0000).eax < 0x8000_0001 || __cpuid(0x8000_0001).edx & CPUID_NX == 0 {
+            return false;
+        }
+
+        let Some(efer) = Msr::Efer.read() else {
+            return false;
+        };
+        Msr::Efer.write(efer | EFER_NXE);
+    }
+
+    NX_ENABLED.store(true, Relaxed);
+    true
+}
+
+/// Returns whether the NX bit has been enabled.
+#[inline]
+pub fn is_nx_enabled() -> bool {
+    NX_ENABLED.load(Relaxed)
+}
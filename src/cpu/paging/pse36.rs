@@ -0,0 +1,172 @@
+//! The 36-bit Page Size Extension (PSE-36).
+//!
+//! With legacy paging, the entries of the page directory that map 4 MiB pages have a few
+//! unused bits. PSE-36 uses them to hold the high bits of the physical address of the page, so
+//! that memory above 4 GiB can be reached without switching to PAE. Such memory cannot be
+//! identity mapped: it is mapped on demand in a small window of virtual addresses located
+//! right after the identity-mapped region.
+
+use core::arch::x86::{__cpuid, has_cpuid};
+use core::ops::Range;
+
+use crate::cpu::stack::THREAD_STACKS_START;
+use crate::utility::Mutex;
+
+use super::{spaces, tlb, CurrentAddressSpace, PageTableFlags};
+
+/// The bit of the EDX register returned by `cpuid(1)` indicating support for PSE-36.
+const CPUID_PSE36: u32 = 1 << 17;
+
+/// The size of a page mapped through PSE-36.
+pub const HUGE_PAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// The maximum number of pages that can be mapped in the window at once.
+pub const MAX_WINDOW_SLOTS: usize = 8;
+
+/// The position of the bits 32 and above of the physical address in a page directory entry.
+const HIGH_ADDRESS_SHIFT: u32 = 13;
+
+/// The virtual addresses used to map pages above 4 GiB.
+static WINDOW: Mutex<Window> = Mutex::new(Window {
+    base: 0,
+    slots: 0,
+    used: 0,
+});
+
+/// A range of virtual addresses in which pages above 4 GiB are mapped.
+struct Window {
+    /// The virtual address of the first slot.
+    base: usize,
+    /// The number of slots in the window.
+    slots: u8,
+    /// A bitmask of the slots that are in use.
+    used: u8,
+}
+
+/// Returns whether the CPU supports PSE-36.
+pub fn is_supported() -> bool {
+    has_cpuid() && unsafe { __cpuid(1).edx & CPUID_PSE36 != 0 }
+}
+
+/// Returns the number of bits of the physical addresses that PSE-36 can reference.
+///
+/// This is 36 on most CPUs, and up to 40 on the ones that report a larger physical
+/// address width.
+pub fn physical_address_bits() -> u32 {
+    let reported = unsafe {
+        if __cpuid(0x8000_0000).eax >= 0x8000_0008 {
+            __cpuid(0x8000_0008).eax & 0xFF
+        } else {
+            36
+        }
+    };
+    reported.clamp(36, 40)
+}
+
+/// Returns the first physical address that PSE-36 cannot reference.
+pub fn physical_memory_end() -> u64 {
+    1 << physical_address_bits()
+}
+
+/// Returns whether PSE-36 is in use.
+///
+/// This is only the case when [`init`] found room for the window.
+pub fn is_enabled() -> bool {
+    WINDOW.lock().slots != 0
+}
+
+/// Returns the virtual addresses reserved by [`init`] for the window.
+pub fn window() -> Range<usize> {
+    let window = WINDOW.lock();
+    window.base..window.base + window.slots as usize * HUGE_PAGE_SIZE
+}
+
+/// Creates a page directory entry mapping the 4 MiB page at `phys`.
+///
+/// `phys` must be aligned to 4 MiB and below [`physical_memory_end`].
+pub fn huge_page(phys: u64, flags: PageTableFlags) -> PageTableFlags {
+    debug_assert!(phys % HUGE_PAGE_SIZE as u64 == 0);
+    let high = ((phys >> 32) as u32 & 0xFF) << HIGH_ADDRESS_SHIFT;
+    PageTableFlags::from_bits_retain(phys as u32 | high)
+        | flags
+        | PageTableFlags::PRESENT
+        | PageTableFlags::HUGE_PAGE
+}
+
+/// Returns the physical address of the 4 MiB page mapped by `entry`, including the bits that
+/// PSE-36 adds.
+pub fn huge_page_address(entry: PageTableFlags) -> u64 {
+    let high = (entry.bits() >> HIGH_ADDRESS_SHIFT) & 0xFF;
+    entry.address_4mib() as u64 | (high as u64) << 32
+}
+
+/// Reserves the virtual addresses right after `upper_bound` to map pages above 4 GiB.
+///
+/// Nothing is reserved if the identity-mapped region leaves no room for the window. This must
+/// be called with legacy paging, once the kernel's address space is in use.
+pub fn init(upper_bound: u32) {
+    let base = (upper_bound as usize).next_multiple_of(HUGE_PAGE_SIZE);
+    // The window must end before the kernel stacks and the page tables.
+    let end = THREAD_STACKS_START & !(HUGE_PAGE_SIZE - 1);
+    let room = end.saturating_sub(base) / HUGE_PAGE_SIZE;
+
+    let mut window = WINDOW.lock();
+    window.base = base;
+    window.slots = room.min(MAX_WINDOW_SLOTS) as u8;
+}
+
+/// A page above 4 GiB mapped in the window.
+///
+/// The page is unmapped when this value is dropped.
+pub struct HighMapping {
+    /// The index of the slot in which the page is mapped.
+    slot: u8,
+    /// The virtual address of the page.
+    virt: usize,
+}
+
+impl HighMapping {
+    /// Maps the 4 MiB page at `phys` in a free slot of the window.
+    ///
+    /// Returns `None` if PSE-36 is not in use, or if every slot is taken.
+    pub fn new(phys: u64, flags: PageTableFlags) -> Option<Self> {
+        if phys >= physical_memory_end() {
+            return None;
+        }
+
+        let mut window = WINDOW.lock();
+        let slot = (0..window.slots).find(|&s| window.used & (1 << s) == 0)?;
+        window.used |= 1 << slot;
+        let virt = window.base + slot as usize * HUGE_PAGE_SIZE;
+        drop(window);
+
+        unsafe { set_entry(virt, huge_page(phys, flags)) };
+        Some(Self { slot, virt })
+    }
+
+    /// Returns a pointer to the start of the page.
+    #[inline(always)]
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.virt as *mut u8
+    }
+}
+
+impl Drop for HighMapping {
+    fn drop(&mut self) {
+        unsafe { set_entry(self.virt, PageTableFlags::empty()) };
+        WINDOW.lock().used &= !(1 << self.slot);
+    }
+}
+
+/// Replaces the page directory entry that translates `virt` in every address space, and flushes
+/// it from the TLB.
+///
+/// # Safety
+///
+/// Legacy paging must be in use, and the entry must belong to the window.
+unsafe fn set_entry(virt: usize, entry: PageTableFlags) {
+    let address_space = CurrentAddressSpace::<PageTableFlags>::new();
+    address_space.directory_entry(virt).write_volatile(entry);
+    spaces::share_entry(virt, entry);
+    tlb::flush_page(virt);
+}
@@ -0,0 +1,210 @@
+use core::marker::PhantomData;
+
+use crate::state::{Allocator, FrameOwner};
+
+use super::address_space::{ACCESS_RIGHTS, FOUR_KIB};
+use super::{spaces, tlb, MappingError, PageEntry, PageTableFlags};
+
+/// The lowest address of the window through which the page tables are accessed, whatever the
+/// paging mode.
+///
+/// Nothing else may be mapped above it.
+pub const PAGE_TABLES_WINDOW: usize = 0xFF80_0000;
+
+/// Returns the address at which the page table that translates `virt` appears in the window.
+///
+/// The table is only there if the entry of the page directory that references it is present.
+#[inline]
+fn table_address<E: PageEntry>(virt: usize) -> usize {
+    E::RECURSIVE_WINDOW + (virt / E::HUGE_PAGE_SIZE) * FOUR_KIB
+}
+
+/// Returns the address at which the page directory that translates `virt` appears in the
+/// window.
+#[inline]
+fn directory_address<E: PageEntry>(virt: usize) -> usize {
+    // The page directories are referenced by the last entries of the last one, in order.
+    let directory = if E::LEVELS > 2 { E::index(virt, 0) } else { 0 };
+    table_address::<E>(E::RECURSIVE_WINDOW + directory * E::HUGE_PAGE_SIZE)
+}
+
+/// The address space in use, whose page tables are edited through the window in which they are
+/// mapped recursively.
+///
+/// Unlike [`AddressSpace`](super::AddressSpace), this does not require the page tables to be
+/// reachable at any other address.
+///
+/// The page directory pointer table of PAE is not reachable through the window: all the page
+/// directories exist from the start, so its entries never change.
+pub struct CurrentAddressSpace<E = PageTableFlags> {
+    /// The format of the entries.
+    _entry: PhantomData<E>,
+}
+
+impl<E: PageEntry> CurrentAddressSpace<E> {
+    /// Creates a new [`CurrentAddressSpace`] instance.
+    ///
+    /// # Safety
+    ///
+    /// The address space loaded in CR3 must have been mapped with
+    /// [`map_recursively`](super::AddressSpace::map_recursively), and its entries must have
+    /// the format `E`. The address space must not be edited through another instance at the
+    /// same time.
+    #[inline]
+    pub unsafe fn new() -> Self {
+        Self {
+            _entry: PhantomData,
+        }
+    }
+
+    /// Returns a pointer to the entry of the page directory that translates `virt`.
+    #[inline]
+    pub fn directory_entry(&self, virt: usize) -> *mut E {
+        let directory = directory_address::<E>(virt) as *mut E;
+        unsafe { directory.add(E::index(virt, E::LEVELS - 2)) }
+    }
+
+    /// Returns a pointer to the entry of the page table that translates `virt`.
+    ///
+    /// The entry may only be accessed if the entry of the page directory that references the
+    /// table is present, and does not map a huge page.
+    #[inline]
+    fn table_entry(&self, virt: usize) -> *mut E {
+        let table = table_address::<E>(virt) as *mut E;
+        unsafe { table.add(E::index(virt, E::LEVELS - 1)) }
+    }
+
+    /// Returns the entry that maps the 4 KiB page at `virt`, if it is present.
+    ///
+    /// `None` is returned when the page is part of a huge page.
+    fn leaf_4kib(&self, virt: usize) -> Option<*mut E> {
+        let directory = unsafe { *self.directory_entry(virt) };
+        if !directory.flags().is_present() || directory.flags().is_huge_page() {
+            return None;
+        }
+        let leaf = self.table_entry(virt);
+        unsafe { (*leaf).flags().is_present() }.then_some(leaf)
+    }
+
+    /// Returns the effective flags of the page that contains `virt`, if it is mapped.
+    ///
+    /// The access rights of the entries that reference the page are taken into account.
+    pub fn flags(&self, virt: usize) -> Option<PageTableFlags> {
+        let directory = unsafe { *self.directory_entry(virt) };
+        let leaf = if !directory.flags().is_present() {
+            return None;
+        } else if directory.flags().is_huge_page() {
+            directory
+        } else {
+            unsafe { *self.table_entry(virt) }
+        };
+
+        let flags = leaf.flags();
+        if !flags.is_present() {
+            return None;
+        }
+
+        let access = ACCESS_RIGHTS & directory.access(E::LEVELS - 2) & leaf.access(E::LEVELS - 1);
+        Some((flags - ACCESS_RIGHTS) | access)
+    }
+
+    /// Translates the provided virtual address to a physical address, if it is mapped.
+    pub fn translate(&self, virt: usize) -> Option<u32> {
+        let directory = unsafe { *self.directory_entry(virt) };
+        if !directory.flags().is_present() {
+            return None;
+        } else if directory.flags().is_huge_page() {
+            return Some(directory.address() + (virt % E::HUGE_PAGE_SIZE) as u32);
+        }
+
+        let leaf = unsafe { *self.table_entry(virt) };
+        leaf.flags()
+            .is_present()
+            .then(|| leaf.address() + (virt % FOUR_KIB) as u32)
+    }
+
+    /// Maps the 4 KiB page at `virt` to the physical page at `phys`.
+    ///
+    /// The missing page table is allocated from `allocator`, and shared with the other address
+    /// spaces when it translates kernel addresses. The flags of the entry of the page directory
+    /// are updated conservatively.
+    pub fn map_4kib(
+        &mut self,
+        allocator: &mut Allocator,
+        virt: usize,
+        phys: u32,
+        flags: PageTableFlags,
+    ) -> Result<(), MappingError> {
+        debug_assert!(virt % FOUR_KIB == 0);
+        debug_assert!(phys as usize % FOUR_KIB == 0);
+        debug_assert!(
+            !flags.intersects(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE),
+            "invalid flags provided"
+        );
+
+        let directory = unsafe { &mut *self.directory_entry(virt) };
+        if !directory.flags().is_present() {
+            let table = allocator.allocate_zeroed(FrameOwner::PageTable)?;
+            *directory = E::table(table, E::LEVELS - 2, flags);
+
+            // The window may still translate the address of the table to the one that was
+            // there before.
+            tlb::flush_page(table_address::<E>(virt));
+            spaces::share_entry(virt, *directory);
+        } else if directory.flags().is_huge_page() {
+            return Err(MappingError::AlreadyMapped);
+        } else {
+            merge_directory(virt, directory, flags);
+        }
+
+        let leaf = unsafe { &mut *self.table_entry(virt) };
+        if leaf.flags().is_present() {
+            return Err(MappingError::AlreadyMapped);
+        }
+        *leaf = E::page(phys, flags, false);
+        Ok(())
+    }
+
+    /// Unmaps the 4 KiB page at `virt`, and returns the physical page it was mapped to.
+    ///
+    /// The page is flushed from the TLB of every processor. The page table is left in place, even when it becomes
+    /// empty.
+    pub fn unmap_4kib(&mut self, virt: usize) -> Option<u32> {
+        let leaf = self.leaf_4kib(virt)?;
+        let phys = unsafe {
+            let phys = (*leaf).address();
+            *leaf = E::EMPTY;
+            phys
+        };
+        tlb::shootdown_page(virt);
+        Some(phys)
+    }
+
+    /// Replaces the flags of the 4 KiB page at `virt`, which must be mapped.
+    ///
+    /// The flags of the entry of the page directory are updated conservatively, and whether the
+    /// page is global is kept. The page is flushed from the TLB of every processor.
+    pub fn protect_4kib(&mut self, virt: usize, flags: PageTableFlags) -> Result<(), MappingError> {
+        let leaf = self.leaf_4kib(virt).ok_or(MappingError::InvalidRange)?;
+        unsafe {
+            let global = (*leaf).flags() & PageTableFlags::GLOBAL;
+            merge_directory(virt, &mut *self.directory_entry(virt), flags);
+            *leaf = E::page((*leaf).address(), flags | global, false);
+        }
+        tlb::shootdown_page(virt);
+        Ok(())
+    }
+}
+
+/// Merges `flags` in `directory`, the entry of the page directory that translates `virt`.
+///
+/// When the access rights of the entry change, they are copied to the other address spaces
+/// that share it.
+fn merge_directory<E: PageEntry>(virt: usize, directory: &mut E, flags: PageTableFlags) {
+    let level = E::LEVELS - 2;
+    let before = directory.access(level);
+    directory.merge_flags(level, flags);
+    if directory.access(level).bits() != before.bits() {
+        spaces::share_entry(virt, *directory);
+    }
+}
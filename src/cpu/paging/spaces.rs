@@ -0,0 +1,267 @@
+//! The address spaces of the processes.
+//!
+//! Every address space maps the kernel the same way: outside of [`user_range`], the entries of
+//! their page directories reference the same page tables. When the kernel creates a page table
+//! there, the entry that references it is copied to every address space (see
+//! [`share_entry`]). The page tables of the user range, and the window of the recursive
+//! mapping, belong to each address space.
+//!
+//! A process runs in the address space of its parent, which it shares, and the kernel's is the
+//! only one until programs can be loaded in a new one (see [`create`]).
+
+use core::ops::Range;
+use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicU32, AtomicUsize};
+
+use crate::cpu::stack::THREAD_STACKS_START;
+use crate::state::{Allocator, FrameOwner, OutOfMemory};
+use crate::utility::instr::{read_cr3, write_cr3};
+use crate::utility::{ArrayVec, Mutex};
+
+use super::{
+    is_pae_enabled, pse36, AddressSpace, Context, CurrentAddressSpace, MappingError, PaeEntry,
+    PageCounts, PageEntry, PageTableFlags,
+};
+
+/// The maximum number of address spaces that can exist besides the kernel's.
+pub const MAX_ADDRESS_SPACES: usize = 32;
+
+/// The alignment of the bounds of the user range.
+///
+/// This is the size of the memory translated by an entry of a legacy page directory, so that
+/// no page table translates both user and kernel addresses.
+const USER_ALIGN: usize = 4 * 1024 * 1024;
+
+/// The address that follows the user range.
+pub const USER_END: usize = THREAD_STACKS_START & !(USER_ALIGN - 1);
+
+/// The first address of the user range, which follows the identity map and the PSE-36 window.
+static USER_START: AtomicUsize = AtomicUsize::new(USER_END);
+
+/// The physical address of the root table of the kernel's address space.
+static KERNEL: AtomicU32 = AtomicU32::new(0);
+
+/// The address spaces that exist besides the kernel's.
+static SPACES: Mutex<ArrayVec<Space, MAX_ADDRESS_SPACES>> = Mutex::new(ArrayVec::new());
+
+/// An address space that is not the kernel's.
+struct Space {
+    /// The physical address of the root table of the address space.
+    root: u32,
+    /// The number of processes that run in the address space.
+    users: u32,
+}
+
+/// A [`Context`] for the address spaces that are not necessarily in use.
+///
+/// Their page tables are reached through the identity map, as every frame of the allocator is
+/// identity mapped.
+pub(super) struct FrameContext<'a> {
+    pub allocator: Option<&'a mut Allocator>,
+}
+
+unsafe impl<'a> Context for FrameContext<'a> {
+    #[inline]
+    fn allocate(&mut self) -> Result<u32, OutOfMemory> {
+        self.allocator
+            .as_mut()
+            .ok_or(OutOfMemory)?
+            .allocate(FrameOwner::PageTable)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&mut self) -> Result<u32, OutOfMemory> {
+        self.allocator
+            .as_mut()
+            .ok_or(OutOfMemory)?
+            .allocate_zeroed(FrameOwner::PageTable)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&mut self, page: u32) {
+        if let Some(allocator) = self.allocator.as_mut() {
+            allocator.deallocate(page);
+        }
+    }
+
+    #[inline]
+    unsafe fn map(&self, physical: u32) -> *mut u8 {
+        physical as *mut u8
+    }
+}
+
+/// Records the kernel's address space, whose root table is at `root`, and the end of the
+/// memory that it identity maps.
+///
+/// This must be called once paging is enabled, and the PSE-36 window is reserved.
+pub(super) fn init(root: u32, identity_end: usize) {
+    let start = identity_end
+        .max(pse36::window().end)
+        .next_multiple_of(USER_ALIGN);
+    USER_START.store(start.min(USER_END), Relaxed);
+    KERNEL.store(root, Relaxed);
+}
+
+/// Returns the addresses in which processes may map memory.
+///
+/// The page tables that translate them are not shared between address spaces.
+pub fn user_range() -> Range<usize> {
+    USER_START.load(Relaxed)..USER_END
+}
+
+/// Returns whether the page table that translates `virt` is shared by every address space.
+///
+/// The page tables of the window of the recursive mapping are not.
+fn is_shared<E: PageEntry>(virt: usize) -> bool {
+    virt < E::RECURSIVE_WINDOW && !user_range().contains(&virt)
+}
+
+/// Returns the physical address of the root table of the kernel's address space.
+#[inline]
+pub fn kernel() -> u32 {
+    KERNEL.load(Relaxed)
+}
+
+/// Makes the address space whose root table is at `root` the one in use.
+///
+/// Nothing happens if it is already in use, so that the TLB is not flushed needlessly. The
+/// mappings of the kernel are global, and remain in the TLB anyway.
+///
+/// # Safety
+///
+/// `root` must be the kernel's address space, or one returned by [`create`] that was not
+/// released.
+pub unsafe fn activate(root: u32) {
+    if read_cr3() != root {
+        write_cr3(root);
+    }
+}
+
+/// Creates a new address space, in which only the kernel is mapped, and returns the physical
+/// address of its root table.
+///
+/// Its page directories are allocated right away, from `allocator`. It is used by a single
+/// process, until more [`acquire`] it.
+pub fn create(allocator: &mut Allocator) -> Result<u32, MappingError> {
+    if is_pae_enabled() {
+        create_as::<PaeEntry>(allocator)
+    } else {
+        create_as::<PageTableFlags>(allocator)
+    }
+}
+
+/// See [`create`].
+fn create_as<E: PageEntry>(allocator: &mut Allocator) -> Result<u32, MappingError> {
+    let mut spaces = SPACES.lock();
+    // Too many address spaces is reported like a lack of memory, as for any other table.
+    if spaces.is_full() {
+        return Err(MappingError::OutOfMemory);
+    }
+
+    let context = FrameContext {
+        allocator: Some(allocator),
+    };
+    let mut space = AddressSpace::<_, E>::new(context)?;
+    if let Err(err) = space.map_recursively() {
+        space.deallocate(|_| false);
+        return Err(err);
+    }
+
+    // Every address space shares the same entries, so the one in use can be copied.
+    let current = unsafe { CurrentAddressSpace::<E>::new() };
+    for virt in (0..E::RECURSIVE_WINDOW).step_by(E::HUGE_PAGE_SIZE) {
+        if !is_shared::<E>(virt) {
+            continue;
+        }
+        let entry = unsafe { *current.directory_entry(virt) };
+        if let Some(copy) = space.directory_entry(virt) {
+            unsafe { *copy = entry };
+        }
+    }
+
+    let root = space.page_directory();
+    space.leak();
+    spaces.push(Space { root, users: 1 });
+    Ok(root)
+}
+
+/// Records that one more process runs in the address space whose root table is at `root`.
+pub fn acquire(root: u32) {
+    let mut spaces = SPACES.lock();
+    if let Some(space) = spaces.iter_mut().find(|s| s.root == root) {
+        space.users += 1;
+    }
+}
+
+/// Records that one less process runs in the address space whose root table is at `root`.
+///
+/// The address space is deallocated once no process runs in it anymore, along with the page
+/// tables that are not shared. The pages it maps must have been released already. When it is in
+/// use, the kernel's address space is loaded instead. The kernel's is never deallocated.
+pub fn release(allocator: &mut Allocator, root: u32) {
+    let mut spaces = SPACES.lock();
+    let Some(index) = spaces.iter().position(|s| s.root == root) else {
+        return;
+    };
+    spaces[index].users -= 1;
+    if spaces[index].users != 0 {
+        return;
+    }
+    spaces.remove_range(index..=index);
+    drop(spaces);
+
+    unsafe { activate_kernel_if_current(root) };
+    let context = FrameContext {
+        allocator: Some(allocator),
+    };
+    if is_pae_enabled() {
+        unsafe { AddressSpace::<_, PaeEntry>::from_raw(context, root) }
+            .deallocate(is_shared::<PaeEntry>);
+    } else {
+        unsafe { AddressSpace::<_, PageTableFlags>::from_raw(context, root) }
+            .deallocate(is_shared::<PageTableFlags>);
+    }
+}
+
+/// Counts the pages mapped by the address space whose root table is at `root`.
+pub fn page_counts(root: u32) -> PageCounts {
+    let context = FrameContext { allocator: None };
+    if is_pae_enabled() {
+        unsafe { AddressSpace::<_, PaeEntry>::from_raw(context, root) }.page_counts()
+    } else {
+        unsafe { AddressSpace::<_, PageTableFlags>::from_raw(context, root) }.page_counts()
+    }
+}
+
+/// Loads the kernel's address space if the one whose root table is at `root` is in use.
+///
+/// # Safety
+///
+/// The running code must be mapped in the kernel's address space.
+unsafe fn activate_kernel_if_current(root: u32) {
+    if read_cr3() == root {
+        write_cr3(kernel());
+    }
+}
+
+/// Copies `entry`, the entry of the page directory of the address space in use that translates
+/// `virt`, to the other address spaces.
+///
+/// This is called whenever the kernel changes such an entry outside of the user range, so that
+/// the kernel is mapped the same way everywhere. Entries of the user range are left alone.
+pub fn share_entry<E: PageEntry>(virt: usize, entry: E) {
+    if !is_shared::<E>(virt) {
+        return;
+    }
+
+    let current = read_cr3();
+    let spaces = SPACES.lock();
+    let roots = spaces.iter().map(|s| s.root).chain([kernel()]);
+    for root in roots.filter(|&root| root != current) {
+        let space =
+            unsafe { AddressSpace::<_, E>::from_raw(FrameContext { allocator: None }, root) };
+        if let Some(copy) = space.directory_entry(virt) {
+            unsafe { *copy = entry };
+        }
+    }
+}
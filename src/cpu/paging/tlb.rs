@@ -0,0 +1,113 @@
+//! Maintenance of the translation lookaside buffer (TLB).
+//!
+//! The CPU caches the translations it reads from the page tables, and does not notice when an
+//! entry changes. A translation must be flushed once its entry is removed, or once it grants
+//! less than before. Entries that are only added need no flush, as the CPU does not cache
+//! missing translations.
+//!
+//! When the CPU supports it, the mappings of the kernel are global (`CR4.PGE`): reloading CR3
+//! keeps them in the TLB, as they are the same in every address space. They can still be
+//! flushed one by one with [`flush_page`], or all at once with [`flush_all`].
+//!
+//! Each processor has its own TLB, but only the bootstrap processor runs the kernel: the
+//! changes to the tables that would have to reach the others go through [`shootdown_page`],
+//! which only flushes the local TLB.
+
+use core::arch::asm;
+use core::arch::x86::{__cpuid, has_cpuid};
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::utility::instr::{read_cr3, write_cr3, Cr4};
+
+use super::PageTableFlags;
+
+/// The bit of the EDX register returned by `cpuid(1)` indicating support for global pages.
+const CPUID_PGE: u32 = 1 << 13;
+
+/// The number of pages above which [`flush_range`] flushes the whole TLB instead.
+const MAX_RANGE_PAGES: usize = 32;
+
+/// Whether the kernel maps its pages as global.
+static GLOBAL_PAGES: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether the CPU supports global pages.
+pub fn is_global_supported() -> bool {
+    has_cpuid() && unsafe { __cpuid(1) }.edx & CPUID_PGE != 0
+}
+
+/// Records that the mappings of the kernel are global from now on.
+///
+/// This is called before paging is enabled, and `CR4.PGE` is set along with the paging mode.
+pub(super) fn use_global_pages() {
+    GLOBAL_PAGES.store(true, Relaxed);
+}
+
+/// Returns whether the mappings of the kernel are global.
+#[inline]
+pub fn is_global_enabled() -> bool {
+    GLOBAL_PAGES.load(Relaxed)
+}
+
+/// Returns the flag that the mappings of the kernel carry: [`PageTableFlags::GLOBAL`] when
+/// global pages are in use, and nothing otherwise.
+///
+/// The mappings of processes must not be global, as they differ from one address space to
+/// another.
+#[inline]
+pub fn kernel_flag() -> PageTableFlags {
+    if is_global_enabled() {
+        PageTableFlags::GLOBAL
+    } else {
+        PageTableFlags::empty()
+    }
+}
+
+/// Removes the translation of the page that contains `virt` from the TLB, even if it is global.
+#[inline]
+pub fn flush_page(virt: usize) {
+    unsafe { asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags)) };
+}
+
+/// Removes the translations of the pages within `start..end` from the TLB.
+///
+/// Large ranges flush the whole TLB instead, global pages included.
+pub fn flush_range(start: usize, end: usize) {
+    let start = start & !0xFFF;
+    if end.saturating_sub(start) / 4096 > MAX_RANGE_PAGES {
+        flush_all();
+    } else {
+        (start..end).step_by(4096).for_each(flush_page);
+    }
+}
+
+/// Removes every translation that is not global from the TLB, by reloading CR3.
+#[inline]
+pub fn flush() {
+    unsafe { write_cr3(read_cr3()) };
+}
+
+/// Removes every translation from the TLB, global pages included.
+///
+/// Global pages are flushed by toggling `CR4.PGE`.
+pub fn flush_all() {
+    let cr4 = Cr4::read();
+    if cr4.contains(Cr4::PAGE_GLOBAL) {
+        unsafe {
+            (cr4 - Cr4::PAGE_GLOBAL).write();
+            cr4.write();
+        }
+    } else {
+        flush();
+    }
+}
+
+/// Removes the translation of the page that contains `virt` from the TLB of every processor
+/// that runs the kernel.
+///
+/// Only the bootstrap processor is started, so this is a local `invlpg`: there is no other TLB
+/// to reach.
+#[inline]
+pub fn shootdown_page(virt: usize) {
+    flush_page(virt);
+}
@@ -0,0 +1,222 @@
+//! Accesses to the memory of the current process on its behalf.
+//!
+//! System calls receive addresses from the process, which must not be trusted: they may point
+//! to the kernel, or to memory that the process may not access. [`UserPtr`] and [`UserSlice`]
+//! check the pages before copying anything, and only copy from or to kernel buffers, so that
+//! the kernel never keeps references to the memory of the process.
+//!
+//! A page that passed the checks may still fault, when it is mapped on demand and no frame
+//! is left. The page fault handler then kills the process, as if it had made the access itself
+//! (see [`is_copying`]).
+
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::error::KernelError;
+
+use super::{current_flags, mmap, PageTableFlags};
+
+/// Whether the kernel is copying memory from or to the current process.
+static COPYING: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether the kernel is copying memory from or to the current process.
+///
+/// A page fault on a user address during the copy is the fault of the process.
+#[inline]
+pub fn is_copying() -> bool {
+    COPYING.load(Relaxed)
+}
+
+/// Records that the copy that was in progress was abandoned, because the process was killed.
+#[inline]
+pub fn abort_copy() {
+    COPYING.store(false, Relaxed);
+}
+
+/// Returns whether the current process may access `len` bytes at `addr`, writing them if
+/// `write` is set.
+///
+/// Pages that are mapped on demand are accepted even when they are not resident yet.
+pub fn is_accessible(addr: usize, len: usize, write: bool) -> bool {
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+
+    let mut required = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if write {
+        required |= PageTableFlags::WRITABLE;
+    }
+    (addr & !0xFFF..end)
+        .step_by(0x1000)
+        .all(|page| match current_flags(page) {
+            Some(flags) => flags.contains(required),
+            None => mmap::is_accessible(page, write),
+        })
+}
+
+/// Copies `len` bytes from `src` to `dst`, once the range that belongs to the current process
+/// has been checked.
+///
+/// # Safety
+///
+/// Both ranges must be valid for `len` bytes, and must not overlap.
+unsafe fn copy(src: *const u8, dst: *mut u8, len: usize) {
+    COPYING.store(true, Relaxed);
+    core::ptr::copy_nonoverlapping(src, dst, len);
+    COPYING.store(false, Relaxed);
+}
+
+/// A pointer to a `T` in the memory of the current process.
+pub struct UserPtr<T> {
+    /// The address of the value.
+    addr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T: Copy> UserPtr<T> {
+    /// Creates a [`UserPtr`] from an address received from the process.
+    #[inline(always)]
+    pub fn new(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns whether the pointer is null.
+    #[inline(always)]
+    pub fn is_null(self) -> bool {
+        self.addr == 0
+    }
+
+    /// Returns the pointer to the `T` that follows `count` values after this one.
+    #[inline(always)]
+    pub fn add(self, count: usize) -> Self {
+        Self::new(self.addr.wrapping_add(count.wrapping_mul(size_of::<T>())))
+    }
+
+    /// Returns whether the process may access the value, writing it if `write` is set.
+    #[inline]
+    pub fn is_accessible(self, write: bool) -> bool {
+        is_accessible(self.addr, size_of::<T>(), write)
+    }
+
+    /// Copies the value from the memory of the process.
+    ///
+    /// The value may be unaligned. This is only meant for integers and arrays of them, which
+    /// are valid for any content.
+    pub fn read(self) -> Result<T, KernelError> {
+        if !self.is_accessible(false) {
+            return Err(KernelError::BadAddress);
+        }
+        let mut value = core::mem::MaybeUninit::<T>::uninit();
+        unsafe {
+            copy(
+                self.addr as *const u8,
+                value.as_mut_ptr() as *mut u8,
+                size_of::<T>(),
+            );
+            Ok(value.assume_init())
+        }
+    }
+
+    /// Copies `value` to the memory of the process.
+    ///
+    /// The value may be unaligned.
+    pub fn write(self, value: T) -> Result<(), KernelError> {
+        if !self.is_accessible(true) {
+            return Err(KernelError::BadAddress);
+        }
+        unsafe {
+            copy(
+                &value as *const T as *const u8,
+                self.addr as *mut u8,
+                size_of::<T>(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A range of bytes in the memory of the current process.
+#[derive(Clone, Copy)]
+pub struct UserSlice {
+    /// The address of the first byte.
+    addr: usize,
+    /// The number of bytes.
+    len: usize,
+}
+
+impl UserSlice {
+    /// Creates a [`UserSlice`] from an address and a length received from the process.
+    #[inline(always)]
+    pub fn new(addr: usize, len: usize) -> Self {
+        Self { addr, len }
+    }
+
+    /// Returns the number of bytes of the range.
+    #[inline(always)]
+    pub fn len(self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the range is empty.
+    #[inline(always)]
+    pub fn is_empty(self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the part of the range that starts `offset` bytes after its start.
+    #[inline]
+    pub fn skip(self, offset: usize) -> Self {
+        let offset = offset.min(self.len);
+        Self::new(self.addr.wrapping_add(offset), self.len - offset)
+    }
+
+    /// Returns the first `len` bytes of the range, or the whole range if it is shorter.
+    #[inline]
+    pub fn take(self, len: usize) -> Self {
+        Self::new(self.addr, self.len.min(len))
+    }
+
+    /// Returns whether the process may access the range, writing it if `write` is set.
+    #[inline]
+    pub fn is_accessible(self, write: bool) -> bool {
+        is_accessible(self.addr, self.len, write)
+    }
+
+    /// Copies the first bytes of the range to `buf`, and returns how many were copied.
+    ///
+    /// The whole range must be readable by the process, even when `buf` is smaller.
+    pub fn read(self, buf: &mut [u8]) -> Result<usize, KernelError> {
+        if !self.is_accessible(false) {
+            return Err(KernelError::BadAddress);
+        }
+        let len = buf.len().min(self.len);
+        unsafe { copy(self.addr as *const u8, buf.as_mut_ptr(), len) };
+        Ok(len)
+    }
+
+    /// Copies `data` to the start of the range, and returns how many bytes were copied.
+    ///
+    /// The whole range must be writable by the process, even when `data` is smaller.
+    pub fn write(self, data: &[u8]) -> Result<usize, KernelError> {
+        if !self.is_accessible(true) {
+            return Err(KernelError::BadAddress);
+        }
+        let len = data.len().min(self.len);
+        unsafe { copy(data.as_ptr(), self.addr as *mut u8, len) };
+        Ok(len)
+    }
+}
@@ -0,0 +1,289 @@
+//! The virtual memory areas of an address space.
+//!
+//! The page tables only record which pages are mapped right now. The areas record what the
+//! address space is supposed to contain: the ranges of addresses that were reserved, their
+//! permissions, and where their content comes from. They are checked before anything is
+//! mapped, so that two mappings never overlap.
+
+use crate::fs::NodeId;
+use crate::shm::ObjectId;
+use crate::state::ProcessId;
+use crate::utility::ArrayVec;
+
+use super::{MappingError, PageTableFlags};
+
+/// The maximum number of areas in an address space.
+pub const MAX_AREAS: usize = 64;
+
+/// Where the content of an area comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backing {
+    /// The area is backed by frames allocated for it, which start out zeroed.
+    Anonymous,
+    /// The area is backed by the content of a file, starting at `offset`.
+    File {
+        /// The node that holds the content of the area.
+        node: NodeId,
+        /// The offset of the first byte of the area within the file.
+        offset: u64,
+    },
+    /// The area maps a shared-memory object, starting at `offset`.
+    ///
+    /// The frames of the object are mapped along with the area.
+    Shared {
+        /// The ID of the object.
+        object: ObjectId,
+        /// The offset of the first byte of the area within the object.
+        offset: usize,
+    },
+    /// The area maps fixed physical memory, starting at `phys`.
+    ///
+    /// This is how the identity map and the memory of devices are mapped.
+    Device {
+        /// The physical address of the first byte of the area.
+        phys: u64,
+    },
+    /// Nothing is ever mapped in the area, so that accessing it faults.
+    Guard,
+    /// The page tables of the address space, which are mapped recursively.
+    PageTables,
+}
+
+impl Backing {
+    /// Returns the name of the kind of backing.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Anonymous => "anonymous",
+            Self::File { .. } => "file",
+            Self::Shared { .. } => "shared",
+            Self::Device { .. } => "device",
+            Self::Guard => "guard",
+            Self::PageTables => "page tables",
+        }
+    }
+
+    /// Returns the backing of the part of an area that starts `offset` bytes after its start.
+    fn advance(self, offset: usize) -> Self {
+        match self {
+            Self::Anonymous | Self::Guard | Self::PageTables => self,
+            Self::File { node, offset: o } => Self::File {
+                node,
+                offset: o + offset as u64,
+            },
+            Self::Shared { object, offset: o } => Self::Shared {
+                object,
+                offset: o + offset,
+            },
+            Self::Device { phys } => Self::Device {
+                phys: phys + offset as u64,
+            },
+        }
+    }
+}
+
+/// A range of virtual addresses reserved in an address space.
+#[derive(Debug, Clone, Copy)]
+pub struct Area {
+    /// The first address of the area.
+    ///
+    /// This is aligned to 4 KiB.
+    pub start: usize,
+    /// The address that follows the area.
+    ///
+    /// This is aligned to 4 KiB, and greater than `start`.
+    pub end: usize,
+    /// The permissions of the pages of the area (`WRITABLE`, `USER_ACCESSIBLE` and
+    /// `NO_EXECUTE`).
+    pub flags: PageTableFlags,
+    /// Where the content of the area comes from.
+    pub backing: Backing,
+    /// A short description of the area, such as `[stack]`.
+    pub name: &'static str,
+    /// The process that mapped the area, or `None` for the areas of the kernel.
+    ///
+    /// The areas of a process are released when it exits.
+    pub owner: Option<ProcessId>,
+}
+
+impl Area {
+    /// Returns the size of the area, in bytes.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns whether the area contains `virt`.
+    #[inline(always)]
+    pub fn contains(&self, virt: usize) -> bool {
+        (self.start..self.end).contains(&virt)
+    }
+
+    /// Returns the part of the area that lies within `start..end`, if any.
+    fn clamp(&self, start: usize, end: usize) -> Option<Self> {
+        let s = self.start.max(start);
+        let e = self.end.min(end);
+        (s < e).then(|| Self {
+            start: s,
+            end: e,
+            backing: self.backing.advance(s - self.start),
+            ..*self
+        })
+    }
+}
+
+/// The list of the areas of an address space, sorted by address.
+///
+/// Areas never overlap.
+pub struct Areas {
+    list: ArrayVec<Area, MAX_AREAS>,
+}
+
+impl Areas {
+    /// Creates a new, empty [`Areas`] instance.
+    pub const fn new() -> Self {
+        Self {
+            list: ArrayVec::new(),
+        }
+    }
+
+    /// Returns the areas, in increasing order of address.
+    #[inline(always)]
+    pub fn iter(&self) -> core::slice::Iter<'_, Area> {
+        self.list.iter()
+    }
+
+    /// Returns the area that contains `virt`, if any.
+    pub fn find(&self, virt: usize) -> Option<&Area> {
+        self.list.iter().find(|area| area.contains(virt))
+    }
+
+    /// Returns whether some area overlaps `start..end`.
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.list
+            .iter()
+            .any(|area| area.start < end && start < area.end)
+    }
+
+    /// Reserves a new area.
+    ///
+    /// The area must not overlap an existing one.
+    pub fn insert(&mut self, area: Area) -> Result<(), MappingError> {
+        check_range(area.start, area.end)?;
+        if self.overlaps(area.start, area.end) {
+            return Err(MappingError::AlreadyMapped);
+        }
+
+        let index = self.list.partition_point(|a| a.start < area.start);
+        self.list
+            .try_insert(index, area)
+            .map_err(|_| MappingError::TooManyAreas)
+    }
+
+    /// Returns the first free range of `len` bytes within `min..max`.
+    ///
+    /// `len` must be a multiple of 4 KiB.
+    pub fn find_free(&self, len: usize, min: usize, max: usize) -> Option<usize> {
+        let mut candidate = min.next_multiple_of(4096);
+        for area in self.list.iter() {
+            if area.end <= candidate {
+                continue;
+            }
+            if area.start >= candidate.checked_add(len)? {
+                break;
+            }
+            candidate = area.end;
+        }
+        (candidate.checked_add(len)? <= max).then_some(candidate)
+    }
+
+    /// Releases the addresses within `start..end`.
+    ///
+    /// Areas that only partially overlap the range are shrunk, or split in two. The parts that
+    /// were released are passed to `f`, so that the caller can unmap them.
+    pub fn remove(
+        &mut self,
+        start: usize,
+        end: usize,
+        mut f: impl FnMut(&Area),
+    ) -> Result<(), MappingError> {
+        self.replace(start, end, false, |area| {
+            f(area);
+            None
+        })
+    }
+
+    /// Changes the permissions of the addresses within `start..end`.
+    ///
+    /// Only the parts of the range that are covered by areas are affected. They are passed to
+    /// `f` once updated, so that the caller can update the page tables.
+    pub fn protect(
+        &mut self,
+        start: usize,
+        end: usize,
+        flags: PageTableFlags,
+        mut f: impl FnMut(&Area),
+    ) -> Result<(), MappingError> {
+        self.replace(start, end, true, |area| {
+            let area = Area { flags, ..*area };
+            f(&area);
+            Some(area)
+        })
+    }
+
+    /// Replaces the parts of the areas that lie within `start..end` with the result of `f`.
+    ///
+    /// `keep` indicates whether `f` returns a replacement. Nothing is modified when the list
+    /// would not have room for the resulting areas.
+    fn replace(
+        &mut self,
+        start: usize,
+        end: usize,
+        keep: bool,
+        mut f: impl FnMut(&Area) -> Option<Area>,
+    ) -> Result<(), MappingError> {
+        check_range(start, end)?;
+
+        // The number of areas that each affected area is replaced with, minus one.
+        let growth: usize = self
+            .list
+            .iter()
+            .filter(|area| area.start < end && start < area.end)
+            .map(|area| {
+                let pieces = (area.start < start) as usize + (end < area.end) as usize;
+                (pieces + keep as usize).saturating_sub(1)
+            })
+            .sum();
+        if self.list.len() + growth > MAX_AREAS {
+            return Err(MappingError::TooManyAreas);
+        }
+
+        let old = core::mem::replace(&mut self.list, ArrayVec::new());
+        for area in old.iter() {
+            let Some(middle) = area.clamp(start, end) else {
+                self.list.push(*area);
+                continue;
+            };
+
+            if let Some(before) = area.clamp(area.start, start) {
+                self.list.push(before);
+            }
+            if let Some(middle) = f(&middle) {
+                self.list.push(middle);
+            }
+            if let Some(after) = area.clamp(end, area.end) {
+                self.list.push(after);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that `start..end` is a valid, non-empty range of pages.
+fn check_range(start: usize, end: usize) -> Result<(), MappingError> {
+    if start >= end || start % 4096 != 0 || end % 4096 != 0 {
+        Err(MappingError::InvalidRange)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! The processors that run the kernel.
+//!
+//! Only the bootstrap processor is started: the application processors stay halted as the
+//! firmware left them. There are no inter-processor interrupts, and the shootdown of TLB
+//! entries only flushes the local TLB.
+//!
+//! The `cpu` command lists the processors. It refuses to take the bootstrap processor offline,
+//! which leaves nothing to take offline until the others are started.
+
+use core::fmt::Write;
+use core::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use core::sync::atomic::{AtomicBool, AtomicU32};
+
+use crate::drivers::lapic;
+use crate::shell::{parse_u32, split_command, usage, Command, Shell};
+use crate::utility::{Column, Table};
+use crate::{printk, TERMINAL};
+
+/// The number of processors that the kernel supports.
+///
+/// Processors are identified by the ID of their local APIC, which must be below this.
+pub const MAX_CPUS: usize = 32;
+
+/// The identifiers of the processors that run the kernel, one bit per local APIC ID.
+static ONLINE: AtomicU32 = AtomicU32::new(0);
+
+/// The identifier of the bootstrap processor, once it is known.
+static BOOTSTRAP: AtomicU32 = AtomicU32::new(0);
+
+/// Whether the bootstrap processor was recorded with [`set_online`].
+static HAS_BOOTSTRAP: AtomicBool = AtomicBool::new(false);
+
+/// Returns the identifier of the current processor.
+///
+/// This is the ID of its local APIC, or 0 when it has not been initialized.
+#[inline]
+pub fn current_id() -> u32 {
+    lapic::id().unwrap_or(0)
+}
+
+/// Records that the processor `id` runs the kernel.
+///
+/// The first processor that is recorded is the bootstrap processor.
+pub fn set_online(id: u32) {
+    if (id as usize) < MAX_CPUS {
+        if !HAS_BOOTSTRAP.swap(true, Relaxed) {
+            BOOTSTRAP.store(id, Relaxed);
+        }
+        ONLINE.fetch_or(1 << id, Release);
+    }
+}
+
+/// Returns the identifier of the bootstrap processor, which starts the others.
+#[inline]
+pub fn bootstrap_id() -> u32 {
+    BOOTSTRAP.load(Relaxed)
+}
+
+/// An error that might occur while taking a processor offline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfflineError {
+    /// The processor is the bootstrap processor, which handles the interrupts of the devices.
+    Bootstrap(u32),
+    /// The processor does not run the kernel.
+    NoSuchCpu(u32),
+}
+
+impl core::fmt::Display for OfflineError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Bootstrap(id) => write!(
+                f,
+                "cpu {id} is the bootstrap processor and cannot be taken offline",
+            ),
+            Self::NoSuchCpu(id) => write!(f, "cpu {id} does not run the kernel"),
+        }
+    }
+}
+
+/// Takes the processor `id` offline.
+///
+/// Only the bootstrap processor runs the kernel, and it cannot be taken offline, so this always
+/// fails for now.
+pub fn set_offline(id: u32) -> Result<(), OfflineError> {
+    if id == bootstrap_id() {
+        Err(OfflineError::Bootstrap(id))
+    } else {
+        Err(OfflineError::NoSuchCpu(id))
+    }
+}
+
+/// Returns the identifiers of the processors that run the kernel, one bit per ID.
+#[inline]
+pub fn online_mask() -> u32 {
+    ONLINE.load(Acquire)
+}
+
+/// Returns the number of processors that run the kernel.
+#[inline]
+pub fn online_count() -> u32 {
+    online_mask().count_ones().max(1)
+}
+
+/// The `cpu` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"cpu",
+    summary: "list the processors",
+    usage: "cpu [offline <id>]",
+    details: "Without arguments, lists the processors that run the kernel, along with the\n\
+              frequency and the count of their tick. Only the bootstrap processor is started,\n\
+              and `offline` refuses to take it offline.",
+    handler: cpu,
+};
+
+/// The `cpu` command.
+fn cpu(shell: &mut Shell, args: &[u8]) {
+    let (action, rest) = split_command(args);
+    let (id, rest) = split_command(rest);
+    match action {
+        b"" if id.is_empty() => list(),
+        b"offline" => match parse_u32(id).filter(|_| rest.is_empty()) {
+            Some(id) => {
+                if let Err(err) = set_offline(id) {
+                    printk!("cpu: {err}\n");
+                    shell.fail();
+                }
+            }
+            None => {
+                printk!("usage: {}\n", usage(b"cpu"));
+                shell.fail();
+            }
+        },
+        _ => {
+            printk!("usage: {}\n", usage(b"cpu"));
+            shell.fail();
+        }
+    }
+}
+
+/// Lists the processors that run the kernel.
+fn list() {
+    let mut term = TERMINAL.lock();
+    let mask = online_mask();
+    if mask == 0 {
+        let _ = writeln!(term, "the local APIC is not initialized");
+        return;
+    }
+
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::right("ID", 3),
+            Column::left("ROLE", 4),
+            Column::right("TICK HZ", 7),
+            Column::right("TICKS", 10),
+        ],
+    );
+    let _ = table.header();
+    for id in (0..MAX_CPUS as u32).filter(|id| mask & (1 << id) != 0) {
+        let hz = lapic::cpu_timer_hz(id);
+        let hz: &dyn core::fmt::Display = match &hz {
+            Some(hz) => hz,
+            None => &"-",
+        };
+        let _ = table.row([
+            &id,
+            &if id == bootstrap_id() { "bsp" } else { "ap" },
+            hz,
+            &lapic::cpu_ticks(id),
+        ]);
+    }
+}
@@ -0,0 +1,139 @@
+//! The stacks on which the kernel runs once it is initialized.
+//!
+//! The kernel starts on a small static stack. Once the page allocator is available, a larger
+//! stack is mapped right below the window in which the page tables are mapped, where memory
+//! is never identity mapped. The page below it is left unmapped, so that an overflow faults
+//! instead of silently corrupting the memory that lies there. The kernel stacks of the other
+//! threads are mapped below it, each with its own guard page.
+
+use core::arch::asm;
+
+use crate::state::Allocator;
+
+use super::paging::vma::{Area, Backing};
+use super::paging::{self, kmem, MappingError, PageTableFlags, KERNEL_AREAS};
+
+/// The size of the kernel stack, in bytes.
+pub const KERNEL_STACK_SIZE: usize = 64 * 1024;
+
+/// The virtual address of the top of the kernel stack.
+///
+/// The page below the window of the page tables is left unmapped.
+pub const KERNEL_STACK_TOP: usize = paging::PAGE_TABLES_WINDOW - 0x1000;
+
+/// The virtual address of the guard page, right below the kernel stack.
+pub const GUARD_PAGE: usize = KERNEL_STACK_TOP - KERNEL_STACK_SIZE - 0x1000;
+
+/// The size of the kernel stack of a thread, in bytes.
+pub const THREAD_STACK_SIZE: usize = 16 * 1024;
+
+/// The number of thread stacks that can be mapped.
+pub const MAX_THREAD_STACKS: usize = 32;
+
+/// The lowest address of the thread stacks.
+///
+/// Each stack is preceded by a guard page.
+pub const THREAD_STACKS_START: usize =
+    GUARD_PAGE - MAX_THREAD_STACKS * (THREAD_STACK_SIZE + 0x1000);
+
+/// Maps the kernel stack, and returns the address of its top.
+///
+/// The stack and its guard page are reserved in [`KERNEL_AREAS`]. This fails if the identity
+/// mapping already covers the addresses of the stacks.
+pub fn allocate(allocator: &mut Allocator) -> Result<usize, MappingError> {
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+
+    KERNEL_AREAS.lock().insert(Area {
+        start: GUARD_PAGE,
+        end: GUARD_PAGE + 0x1000,
+        flags: PageTableFlags::empty(),
+        backing: Backing::Guard,
+        name: "[guard]",
+        owner: None,
+    })?;
+    kmem::alloc_and_map_at(
+        allocator,
+        GUARD_PAGE + 0x1000,
+        KERNEL_STACK_SIZE,
+        flags,
+        "[stack]",
+    )?;
+    Ok(KERNEL_STACK_TOP)
+}
+
+/// Returns the address of the guard page of the thread stack in `slot`, which is followed by the
+/// stack itself.
+fn thread_stack_guard(slot: usize) -> usize {
+    THREAD_STACKS_START + slot * (THREAD_STACK_SIZE + 0x1000)
+}
+
+/// Maps the thread stack in `slot`, and returns the address of its top.
+///
+/// `slot` must be less than [`MAX_THREAD_STACKS`]. The stack and its guard page are reserved in
+/// [`KERNEL_AREAS`].
+pub fn allocate_thread_stack(
+    allocator: &mut Allocator,
+    slot: usize,
+) -> Result<usize, MappingError> {
+    let guard = thread_stack_guard(slot);
+    let top = guard + 0x1000 + THREAD_STACK_SIZE;
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+
+    KERNEL_AREAS.lock().insert(Area {
+        start: guard,
+        end: guard + 0x1000,
+        flags: PageTableFlags::empty(),
+        backing: Backing::Guard,
+        name: "[guard]",
+        owner: None,
+    })?;
+    let stack = kmem::alloc_and_map_at(
+        allocator,
+        guard + 0x1000,
+        THREAD_STACK_SIZE,
+        flags,
+        "[thread stack]",
+    );
+    if let Err(err) = stack {
+        let _ = KERNEL_AREAS.lock().remove(guard, guard + 0x1000, |_| ());
+        return Err(err);
+    }
+    Ok(top)
+}
+
+/// Unmaps the thread stack in `slot`, and releases its frames.
+pub fn deallocate_thread_stack(allocator: &mut Allocator, slot: usize) {
+    let guard = thread_stack_guard(slot);
+    let _ = KERNEL_AREAS.lock().remove(guard, guard + 0x1000, |_| ());
+    kmem::unmap_and_free(allocator, guard + 0x1000, THREAD_STACK_SIZE);
+}
+
+/// Switches to the stack whose top is `top`, and calls `f` with `arg0` and `arg1` on it.
+///
+/// # Safety
+///
+/// `top` must be the top of a valid stack. Nothing that lives on the current stack may be
+/// referenced by `f`, unless the current stack remains valid.
+pub unsafe fn switch(
+    top: usize,
+    f: extern "C" fn(usize, usize) -> !,
+    arg0: usize,
+    arg1: usize,
+) -> ! {
+    asm!(
+        "
+        mov esp, {top}
+        xor ebp, ebp
+        sub esp, 8
+        push {arg1}
+        push {arg0}
+        call {f}
+        ud2
+        ",
+        top = in(reg) top,
+        arg0 = in(reg) arg0,
+        arg1 = in(reg) arg1,
+        f = in(reg) f,
+        options(noreturn),
+    );
+}
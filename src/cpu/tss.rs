@@ -0,0 +1,185 @@
+//! Defines the Task State Segment (TSS) that the kernel will use.
+//!
+//! The kernel does not use hardware task switching. The TSS is only used to provide the stack
+//! used when an interrupt occurs in user mode, and the I/O permission bitmap which controls the
+//! ports that user mode code may access directly.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+/// The number of I/O ports whose access can be granted to user processes.
+///
+/// Like on Linux, only the first 1024 ports can be granted individually. Accesses to the other
+/// ports always trap.
+pub const IO_PORTS: usize = 1024;
+
+/// The total number of I/O ports.
+const ALL_IO_PORTS: usize = 65536;
+
+/// The layout of a 32-bit Task State Segment.
+#[repr(C, packed)]
+struct TaskStateSegment {
+    link: u32,
+    esp0: u32,
+    ss0: u32,
+    esp1: u32,
+    ss1: u32,
+    esp2: u32,
+    ss2: u32,
+    cr3: u32,
+    eip: u32,
+    eflags: u32,
+    eax: u32,
+    ecx: u32,
+    edx: u32,
+    ebx: u32,
+    esp: u32,
+    ebp: u32,
+    esi: u32,
+    edi: u32,
+    es: u32,
+    cs: u32,
+    ss: u32,
+    ds: u32,
+    fs: u32,
+    gs: u32,
+    ldtr: u32,
+    trap: u16,
+    /// The offset of the I/O permission bitmap from the start of the TSS.
+    iomap_base: u16,
+}
+
+/// The TSS, along with its I/O permission bitmap.
+#[repr(C, packed)]
+struct Tss {
+    tss: TaskStateSegment,
+    /// A set bit denies user mode code the access to the corresponding port.
+    io_bitmap: [u8; ALL_IO_PORTS / 8],
+    /// The CPU may read one byte past the bitmap, which must have all its bits set.
+    terminator: u8,
+}
+
+/// The global TSS.
+///
+/// Until a process is granted some ports, every access from user mode traps.
+static mut TSS: Tss = Tss {
+    tss: TaskStateSegment {
+        link: 0,
+        esp0: 0,
+        ss0: super::gdt::KERNEL_DATA_SEGMENT as u32,
+        esp1: 0,
+        ss1: 0,
+        esp2: 0,
+        ss2: 0,
+        cr3: 0,
+        eip: 0,
+        eflags: 0,
+        eax: 0,
+        ecx: 0,
+        edx: 0,
+        ebx: 0,
+        esp: 0,
+        ebp: 0,
+        esi: 0,
+        edi: 0,
+        es: 0,
+        cs: 0,
+        ss: 0,
+        ds: 0,
+        fs: 0,
+        gs: 0,
+        ldtr: 0,
+        trap: 0,
+        iomap_base: core::mem::size_of::<TaskStateSegment>() as u16,
+    },
+    io_bitmap: [0xFF; ALL_IO_PORTS / 8],
+    terminator: 0xFF,
+};
+
+/// Creates the GDT descriptor of the TSS.
+pub fn descriptor() -> u64 {
+    let base = unsafe { addr_of!(TSS) } as u64;
+    let limit = core::mem::size_of::<Tss>() as u64 - 1;
+
+    let mut val = 0;
+    val |= limit & 0xFFFF;
+    val |= (base & 0xFF_FFFF) << 16;
+    // access byte: present, DPL 0, 32-bit available TSS
+    val |= 0x89 << 40;
+    val |= ((limit >> 16) & 0xF) << 48;
+    val |= ((base >> 24) & 0xFF) << 56;
+    val
+}
+
+/// Sets the stack that the CPU switches to when an interrupt occurs in user mode, or when a
+/// system call is made with `sysenter`.
+///
+/// # Safety
+///
+/// `esp0` must be the top of a valid stack.
+pub unsafe fn set_kernel_stack(esp0: u32) {
+    addr_of_mut!(TSS.tss.esp0).write_unaligned(esp0);
+    super::idt::set_sysenter_stack(esp0);
+}
+
+/// Returns the top of the stack used when an interrupt occurs in user mode.
+pub fn kernel_stack() -> u32 {
+    unsafe { addr_of!(TSS.tss.esp0).read_unaligned() }
+}
+
+/// The I/O ports that a process is allowed to access.
+#[derive(Clone)]
+pub struct IoPermissions {
+    /// Uses the same format as the I/O permission bitmap: a set bit denies the access to the
+    /// corresponding port.
+    bitmap: [u8; IO_PORTS / 8],
+}
+
+impl IoPermissions {
+    /// Creates a new [`IoPermissions`] instance, which does not allow accessing any port.
+    pub const fn new() -> Self {
+        Self {
+            bitmap: [0xFF; IO_PORTS / 8],
+        }
+    }
+
+    /// Allows or denies the access to the ports `from..from + count`.
+    ///
+    /// Returns `false` if the range goes past [`IO_PORTS`], in which case nothing is changed.
+    pub fn set(&mut self, from: usize, count: usize, allowed: bool) -> bool {
+        let Some(end) = from.checked_add(count).filter(|&end| end <= IO_PORTS) else {
+            return false;
+        };
+
+        for port in from..end {
+            let mask = 1 << (port % 8);
+            if allowed {
+                self.bitmap[port / 8] &= !mask;
+            } else {
+                self.bitmap[port / 8] |= mask;
+            }
+        }
+
+        true
+    }
+
+    /// Returns whether the access to the provided port is allowed.
+    pub fn is_allowed(&self, port: u16) -> bool {
+        let port = port as usize;
+        port < IO_PORTS && self.bitmap[port / 8] & (1 << (port % 8)) == 0
+    }
+}
+
+impl Default for IoPermissions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Loads the permissions of the process that is about to run in the I/O permission bitmap.
+pub fn load_io_permissions(perms: &IoPermissions) {
+    unsafe {
+        let bitmap = addr_of_mut!(TSS.io_bitmap) as *mut u8;
+        core::ptr::copy_nonoverlapping(perms.bitmap.as_ptr(), bitmap, perms.bitmap.len());
+    }
+}
@@ -0,0 +1,220 @@
+//! Builds a compact, machine-readable summary of a kernel panic.
+//!
+//! The summary is displayed on the panic screen as base32 text so that crashes on machines
+//! without a serial port can still be reported accurately by photographing the screen.
+
+use core::arch::asm;
+use core::fmt::{Display, Write};
+use core::panic::PanicInfo;
+
+use crate::utility::instr::EFlags;
+use crate::utility::{ArrayVec, Base32};
+
+/// The maximum number of return addresses recorded in a crash dump.
+const MAX_FRAMES: usize = 8;
+
+/// The maximum distance between the frame of the panic handler and the frames that are
+/// walked to build the backtrace.
+///
+/// This prevents the stack walk from wandering through random memory when a frame pointer is
+/// corrupted.
+const MAX_STACK_WALK: u32 = 0x10000;
+
+/// The first word of a crash dump.
+const MAGIC: u32 = u32::from_le_bytes(*b"KCD1");
+
+/// The size of a crash dump, in bytes.
+const SIZE: usize = core::mem::size_of::<CrashDump>();
+
+/// A compact summary of the state of the kernel when it panicked.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct CrashDump {
+    /// Always [`MAGIC`]. This identifies the format of the dump.
+    pub magic: u32,
+    /// The value of the ESP register in the panic handler.
+    pub esp: u32,
+    /// The value of the EBP register in the panic handler.
+    pub ebp: u32,
+    /// The value of the EFLAGS register in the panic handler.
+    pub eflags: u32,
+    /// The value of the CR0 register.
+    pub cr0: u32,
+    /// The value of the CR2 register (the address of the last page fault).
+    pub cr2: u32,
+    /// The value of the CR3 register (the current page directory).
+    pub cr3: u32,
+    /// The FNV-1a hash of the file in which the panic occurred.
+    pub file_hash: u32,
+    /// The line (upper 16 bits) and column (lower 16 bits) at which the panic occurred.
+    pub line_column: u32,
+    /// The FNV-1a hash of the panic message.
+    pub message_hash: u32,
+    /// The return addresses found by walking the stack, starting with the most recent one.
+    ///
+    /// Unused entries are zero.
+    pub frames: [u32; MAX_FRAMES],
+    /// The FNV-1a hash of all the previous fields.
+    pub checksum: u32,
+}
+
+impl CrashDump {
+    /// Captures the current state of the CPU, along with information about the provided panic.
+    ///
+    /// This function is meant to be called from the panic handler itself, as early as
+    /// possible.
+    #[inline(always)]
+    pub fn capture(info: &PanicInfo) -> Self {
+        let esp: u32;
+        let ebp: u32;
+        let cr0: u32;
+        let cr2: u32;
+        let cr3: u32;
+
+        unsafe {
+            asm!(
+                "
+                mov {esp}, esp
+                mov {ebp}, ebp
+                ",
+                esp = out(reg) esp,
+                ebp = out(reg) ebp,
+                options(nomem, nostack, preserves_flags),
+            );
+            asm!(
+                "
+                mov {cr0}, cr0
+                mov {cr2}, cr2
+                mov {cr3}, cr3
+                ",
+                cr0 = out(reg) cr0,
+                cr2 = out(reg) cr2,
+                cr3 = out(reg) cr3,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+
+        let (file_hash, line_column) = match info.location() {
+            Some(loc) => (
+                fnv1a(loc.file().as_bytes()),
+                (loc.line() & 0xFFFF) << 16 | (loc.column() & 0xFFFF),
+            ),
+            None => (0, 0),
+        };
+
+        let mut message_hash = Fnv1a::new();
+        if let Some(msg) = info.message() {
+            let _ = write!(message_hash, "{msg}");
+        }
+
+        let mut dump = Self {
+            magic: MAGIC,
+            esp,
+            ebp,
+            eflags: EFlags::read().bits(),
+            cr0,
+            cr2,
+            cr3,
+            file_hash,
+            line_column,
+            message_hash: message_hash.0,
+            frames: unsafe { backtrace(ebp) },
+            checksum: 0,
+        };
+
+        dump.checksum = fnv1a(&dump.to_bytes()[..SIZE - 4]);
+        dump
+    }
+
+    /// Returns the raw bytes of the crash dump.
+    #[inline]
+    pub fn to_bytes(self) -> [u8; SIZE] {
+        // SAFETY: the struct is `repr(C)` and only made of `u32`s, so it has no padding.
+        unsafe { core::mem::transmute::<Self, [u8; SIZE]>(self) }
+    }
+}
+
+impl Display for CrashDump {
+    /// Displays the crash dump as lines of base32 groups.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        const GROUP_LEN: usize = 8;
+        const GROUPS_PER_LINE: usize = 6;
+
+        let mut encoded = ArrayVec::<u8, { (SIZE * 8).div_ceil(5) }>::new();
+        let _ = write!(encoded, "{}", Base32(&self.to_bytes()));
+
+        for (i, group) in encoded.chunks(GROUP_LEN).enumerate() {
+            if i != 0 {
+                f.write_char(if i % GROUPS_PER_LINE == 0 { '\n' } else { ' ' })?;
+            }
+            f.write_str(core::str::from_utf8(group).unwrap_or("?"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Walks the stack using the frame pointers, starting at the frame pointed to by `ebp`.
+///
+/// # Safety
+///
+/// The memory above `ebp` (up to [`MAX_STACK_WALK`] bytes) must be mapped.
+unsafe fn backtrace(ebp: u32) -> [u32; MAX_FRAMES] {
+    let mut frames = [0; MAX_FRAMES];
+    let mut fp = ebp;
+
+    for slot in &mut frames {
+        if fp == 0 || fp % 4 != 0 || fp.wrapping_sub(ebp) >= MAX_STACK_WALK {
+            break;
+        }
+
+        // Each frame starts with the frame pointer of the caller, followed by the return
+        // address.
+        let next = *(fp as *const u32);
+        let ret = *((fp + 4) as *const u32);
+
+        if ret == 0 {
+            break;
+        }
+        *slot = ret;
+
+        // The stack grows downwards, so the frames of the callers are at higher addresses.
+        if next <= fp {
+            break;
+        }
+        fp = next;
+    }
+
+    frames
+}
+
+/// Computes the FNV-1a hash of the provided bytes.
+pub fn fnv1a(bytes: &[u8]) -> u32 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(bytes);
+    hasher.0
+}
+
+/// A FNV-1a hasher that can be written to with [`core::fmt::Write`].
+struct Fnv1a(u32);
+
+impl Fnv1a {
+    /// Creates a new [`Fnv1a`] hasher.
+    const fn new() -> Self {
+        Self(0x811C_9DC5)
+    }
+
+    /// Feeds the provided bytes to the hasher.
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u32).wrapping_mul(0x0100_0193);
+        }
+    }
+}
+
+impl Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! Keeps a record of the last kernel panic in a page of memory that survives warm reboots.
+//!
+//! The page is taken from the top of the largest available memory segment and is never handed
+//! out to the allocator. When the kernel panics, a [`CrashRecord`] is written to it. During the
+//! next boot, the record is read back, displayed, and cleared.
+
+use core::fmt::{Display, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::crash_dump::CrashDump;
+use crate::utility::{crc32, ArrayVec};
+
+/// The first word of a valid crash record.
+const MAGIC: u32 = u32::from_le_bytes(*b"KCR2");
+
+/// The maximum length of the file name stored in a crash record.
+const MAX_FILE_LEN: usize = 64;
+
+/// The maximum length of the panic message stored in a crash record.
+const MAX_MESSAGE_LEN: usize = 160;
+
+/// The physical address of the page that holds the crash record, or 0 if it has not been
+/// set up yet.
+static RECORD_ADDRESS: AtomicU32 = AtomicU32::new(0);
+
+/// Information about a kernel panic, stored across reboots.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct CrashRecord {
+    /// Always [`MAGIC`] for a valid record.
+    magic: u32,
+    /// The CRC32 of the fields that follow.
+    checksum: u32,
+    /// The line at which the panic occurred.
+    line: u32,
+    /// The column at which the panic occurred.
+    column: u32,
+    /// The number of bytes used in `file`.
+    file_len: u32,
+    /// The number of bytes used in `message`.
+    message_len: u32,
+    /// The file in which the panic occurred (possibly truncated).
+    file: [u8; MAX_FILE_LEN],
+    /// The panic message (possibly truncated).
+    message: [u8; MAX_MESSAGE_LEN],
+    /// The crash dump that was captured by the panic handler.
+    dump: CrashDump,
+}
+
+impl CrashRecord {
+    /// Returns the bytes covered by the checksum.
+    fn checked_bytes(&self) -> &[u8] {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                core::mem::size_of::<Self>(),
+            )
+        };
+        &bytes[8..]
+    }
+
+    /// Returns whether the record is valid.
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && self.checksum == crc32(self.checked_bytes())
+            && self.file_len as usize <= MAX_FILE_LEN
+            && self.message_len as usize <= MAX_MESSAGE_LEN
+    }
+
+    /// Returns the crash dump captured when the kernel panicked.
+    #[inline]
+    pub fn dump(&self) -> &CrashDump {
+        &self.dump
+    }
+}
+
+impl Display for CrashRecord {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let file = &self.file[..self.file_len as usize];
+        let message = &self.message[..self.message_len as usize];
+
+        write!(
+            f,
+            "previous boot crashed at {}:{}:{}: {}",
+            core::str::from_utf8(file).unwrap_or("<invalid utf-8>"),
+            self.line,
+            self.column,
+            core::str::from_utf8(message).unwrap_or("<invalid utf-8>"),
+        )
+    }
+}
+
+/// Sets up the page that holds the crash record, returning the record left by the previous
+/// boot, if any.
+///
+/// The record is cleared, so that it is only reported once.
+///
+/// # Safety
+///
+/// `page` must be the physical address of a page that is accessible and that is not used by
+/// anything else for the whole lifetime of the kernel.
+pub unsafe fn init(page: u32) -> Option<CrashRecord> {
+    assert!(core::mem::size_of::<CrashRecord>() <= 0x1000);
+
+    let record = &mut *(page as *mut CrashRecord);
+    let previous = record.is_valid().then_some(*record);
+    record.magic = 0;
+
+    RECORD_ADDRESS.store(page, Relaxed);
+
+    previous
+}
+
+/// Stores information about the provided panic in the crash record.
+///
+/// This function does nothing if [`init`] was not called.
+pub fn store(info: &PanicInfo, dump: &CrashDump) {
+    let address = RECORD_ADDRESS.load(Relaxed);
+    if address == 0 {
+        return;
+    }
+
+    let mut file = ArrayVec::<u8, MAX_FILE_LEN>::new();
+    let (line, column) = match info.location() {
+        Some(loc) => {
+            let _ = file.write_str(loc.file());
+            (loc.line(), loc.column())
+        }
+        None => (0, 0),
+    };
+
+    let mut message = ArrayVec::<u8, MAX_MESSAGE_LEN>::new();
+    if let Some(msg) = info.message() {
+        // Errors only indicate that the message was truncated.
+        let _ = write!(message, "{msg}");
+    }
+
+    let mut record = CrashRecord {
+        magic: MAGIC,
+        checksum: 0,
+        line,
+        column,
+        file_len: file.len() as u32,
+        message_len: message.len() as u32,
+        file: [0; MAX_FILE_LEN],
+        message: [0; MAX_MESSAGE_LEN],
+        dump: *dump,
+    };
+    record.file[..file.len()].copy_from_slice(&file);
+    record.message[..message.len()].copy_from_slice(&message);
+    record.checksum = crc32(record.checked_bytes());
+
+    unsafe {
+        core::ptr::write_volatile(address as *mut CrashRecord, record);
+
+        // Make sure that the record reaches the memory before the CPU is reset.
+        core::arch::asm!("wbinvd", options(nostack, preserves_flags));
+    }
+}
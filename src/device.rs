@@ -0,0 +1,147 @@
+//! The registry of the devices found by the drivers.
+//!
+//! Drivers register a [`Device`] once they found and initialized the hardware they drive. The
+//! registry is what the rest of the kernel knows about the devices: each of them gets a node in
+//! `/dev` (see [`DevFs`](crate::fs::DevFs)), and the `lsdev` command lists them.
+
+use crate::fs::FsError;
+use crate::shell::{Command, Shell};
+use crate::utility::{ArrayVec, Column, Mutex, Table};
+use crate::{log, TERMINAL};
+
+/// The maximum number of devices that can be registered.
+pub const MAX_DEVICES: usize = 32;
+
+/// The kind of a [`Device`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceClass {
+    /// A device that is not backed by hardware, such as `null`.
+    Memory,
+    /// A terminal on which the user types commands.
+    Console,
+    /// A serial port.
+    Serial,
+    /// A device through which the user gives input, such as a mouse.
+    Input,
+    /// A controller to which other devices are connected.
+    Controller,
+}
+
+impl DeviceClass {
+    /// Returns the name of the class.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Memory => "memory",
+            Self::Console => "console",
+            Self::Serial => "serial",
+            Self::Input => "input",
+            Self::Controller => "controller",
+        }
+    }
+}
+
+/// The operations supported by a [`Device`].
+///
+/// A device has no size: what is read from it is produced as it is read, and what is written to
+/// it is consumed right away.
+pub trait DeviceOps: Sync {
+    /// Reads from the device.
+    ///
+    /// Returns the number of bytes that were read, which is 0 at the end of the device.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let _ = buf;
+        Err(FsError::Unsupported)
+    }
+
+    /// Writes to the device.
+    ///
+    /// Returns the number of bytes that were written.
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        let _ = buf;
+        Err(FsError::Unsupported)
+    }
+}
+
+/// The operations of the devices that can be neither read nor written, such as controllers.
+pub struct NoOps;
+
+impl DeviceOps for NoOps {}
+
+/// A device registered by a driver.
+#[derive(Clone, Copy)]
+pub struct Device {
+    /// The name of the device, which is also the name of its node in `/dev`.
+    pub name: &'static str,
+    /// The kind of the device.
+    pub class: DeviceClass,
+    /// The operations supported by the device.
+    pub ops: &'static dyn DeviceOps,
+}
+
+/// The devices that were registered, in order.
+///
+/// Devices are never removed, so their index identifies them.
+static DEVICES: Mutex<ArrayVec<Device, MAX_DEVICES>> = Mutex::new(ArrayVec::new());
+
+/// Registers a device.
+///
+/// Returns `false` if a device with the same name is registered already, or if too many
+/// devices are.
+pub fn register(device: Device) -> bool {
+    let mut devices = DEVICES.lock();
+    if devices.iter().any(|d| d.name == device.name) {
+        log!("A device named `{}` is already registered.\n", device.name);
+        return false;
+    }
+    if devices.try_push(device).is_err() {
+        log!("Too many devices to register `{}`.\n", device.name);
+        return false;
+    }
+    log!(
+        "Registered the {} device `{}`.\n",
+        device.class.name(),
+        device.name
+    );
+    true
+}
+
+/// Returns the device with index `index`.
+pub fn get(index: usize) -> Option<Device> {
+    DEVICES.lock().get(index).copied()
+}
+
+/// Returns the index of the device named `name`.
+pub fn find(name: &[u8]) -> Option<usize> {
+    DEVICES
+        .lock()
+        .iter()
+        .position(|d| d.name.as_bytes() == name)
+}
+
+/// Returns the devices that were registered.
+pub fn all() -> ArrayVec<Device, MAX_DEVICES> {
+    DEVICES.lock().iter().copied().collect()
+}
+
+/// The `lsdev` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"lsdev",
+    summary: "list the devices that were found",
+    usage: "lsdev",
+    details: "Lists the devices registered by the drivers, which all have a node in `/dev`.",
+    handler: lsdev,
+};
+
+/// The `lsdev` command.
+fn lsdev(_shell: &mut Shell, _args: &[u8]) {
+    let devices = all();
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [Column::left("NAME", 10), Column::left("CLASS", 10)],
+    );
+    let _ = table.header();
+    for device in devices.iter() {
+        let _ = table.row([&device.name, &device.class.name()]);
+    }
+}
@@ -1,11 +1,13 @@
 //! This module defines various error functions that are used throughout the kernel.
 
-use core::fmt::Write;
+use core::fmt::{Display, Write};
 use core::panic::PanicInfo;
 
-use crate::drivers::{ps2, vga};
-use crate::utility::instr::{cli, hlt, outb, pause};
-use crate::{log, TERMINAL};
+use crate::crash_dump::CrashDump;
+use crate::crash_record;
+use crate::drivers::{ps2, serial, speaker};
+use crate::utility::instr::{cli, pause, EFlags};
+use crate::{kthread, log, power, sched, TERMINAL};
 
 /// Kills the kernel with an appropriate message indicating that the system has run
 /// out of memory.
@@ -13,35 +15,33 @@ use crate::{log, TERMINAL};
 /// This function should only be called during initialization, as it is not really
 /// possible to recover from lacking memory.
 ///
-/// However, once the kernel is running normally, memory errors should be handled
-/// properly and recovered from.
+/// Once the kernel is running normally, memory errors are handled by the caller, and
+/// reported to the [`oom`](crate::oom) module.
 #[inline]
 pub fn oom() -> ! {
     die("please download more RAM");
 }
 
-/// Restarts the CPU.
-pub fn reset_cpu() -> ! {
-    // This is probably just triggering a tripple fault. The documentation online does not
-    // seem to agree on what this does exactly. The proper way to do this would be to
-    // use the ACPI, but it's a bit out of scope.
-    unsafe { outb(0xCF9, 0xE) };
-
-    loop {
-        hlt();
-    }
-}
-
 /// This function is called when something in the kernel panics.
 ///
 /// If the control flow of the kernel ever reaches this point, it means that something
 /// went terribly wrong and the kernel may be in an inconsistent state.
+///
+/// A kernel thread that panicked with interrupts enabled holds no lock, and is terminated
+/// alone (see [`kthread`]).
 #[panic_handler]
 #[cold]
 #[inline(never)]
 fn die_and_catch_fire(info: &PanicInfo) -> ! {
+    if EFlags::read().intersects(EFlags::INTERRUPT) && sched::is_current_expendable() {
+        kthread::die(info);
+    }
+
     cli();
 
+    let dump = CrashDump::capture(info);
+    crash_record::store(info, &dump);
+
     // SAFETY:
     //  We just made sure that no interrupts can occur, meaning that this mutable reference
     //  at most overlaps with the current thread (if the lock was helf while the panic
@@ -50,10 +50,17 @@ fn die_and_catch_fire(info: &PanicInfo) -> ! {
     //  to optimize this in a harmful way are slim.
     let term = unsafe { TERMINAL.get_mut_unchecked() };
 
-    vga::cursor_hide();
-    term.set_color(vga::Color::Red);
+    term.end_paging();
+    term.set_cursor_enabled(false);
+    term.set_color(term.theme().error);
+    term.set_prompt(b"");
     term.clear_cmdline();
 
+    // The messages logged before the serial port was initialized would be lost otherwise.
+    if !serial::is_initialized() {
+        let _ = serial::init();
+    }
+
     // Write a message explaining what happened:
     log!("\n\nKERNEL PANIC:\n{}\n", info);
 
@@ -75,26 +82,35 @@ fn die_and_catch_fire(info: &PanicInfo) -> ! {
         let _ = writeln!(term, "> MESSAGE:\n{}", msg);
     }
 
+    log!("CRASH DUMP:\n{dump}\n");
+    let _ = writeln!(term, "> CRASH DUMP:\n{dump}");
+
+    speaker::chime(speaker::PANIC_CHIME);
+
     wait_any_key();
-    reset_cpu();
+    power::reboot();
 }
 
 /// Function called when something in the kernel goes wrong, but without it being
 /// a bug.
 ///
 /// For example, if the kernel cannot initialize itself because of a lack of working
-/// memory, this function will be called.
+/// memory, this function will be called. `error` is either a message, or a
+/// [`KernelError`](crate::error::KernelError).
 ///
 /// # Panics
 ///
 /// This function panics if the terminal is currently locked.
 #[cold]
-pub fn die(error: &str) -> ! {
+pub fn die(error: impl Display) -> ! {
     cli();
 
     {
         let mut term = TERMINAL.lock();
-        term.set_color(vga::Color::Red);
+        term.end_paging();
+        let error_color = term.theme().error;
+        term.set_color(error_color);
+        term.set_prompt(b"");
         term.clear_cmdline();
         let _ = writeln!(
             term,
@@ -105,7 +121,7 @@ pub fn die(error: &str) -> ! {
     }
 
     wait_any_key();
-    reset_cpu();
+    power::reboot();
 }
 
 /// Blocks the execution of the current thread until the user presses any key.
@@ -121,10 +137,17 @@ fn wait_any_key() {
             pause();
         }
 
+        // Bytes sent by the mouse are not scan-codes.
+        let status = ps2::status();
+        let scancode = ps2::read_data();
+        if ps2::is_mouse_data(status) {
+            continue;
+        }
+
         // If the most significant bit is set, then the scancode is a MAKE code
         // instead of a BREAK code. This avoid continuing unintentionally when
         // the user releases a key.
-        if ps2::read_data() & 0x80 == 0 {
+        if scancode & 0x80 == 0 {
             break;
         }
     }
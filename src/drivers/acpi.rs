@@ -0,0 +1,158 @@
+//! A minimal reader for the ACPI tables provided by the firmware.
+//!
+//! Only the parts of the specification needed by the kernel are implemented. Notably, AML is
+//! not interpreted at all.
+
+/// The signature of the Root System Description Pointer.
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
+/// The signature of the Fixed ACPI Description Table.
+const FADT_SIGNATURE: &[u8; 4] = b"FACP";
+
+/// The size of the header shared by all the system description tables.
+const SDT_HEADER_SIZE: usize = 36;
+
+/// The bit of the `flags` field of the FADT indicating that the reset register is supported.
+const FADT_RESET_REG_SUPPORTED: u32 = 1 << 10;
+
+/// The address space of a register described by the ACPI tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    /// The register is memory-mapped.
+    Memory,
+    /// The register is an I/O port.
+    Io,
+}
+
+/// The register that resets the system when written to.
+#[derive(Debug, Clone, Copy)]
+pub struct ResetRegister {
+    /// The address space of the register.
+    pub space: AddressSpace,
+    /// The address of the register within its address space.
+    pub address: u32,
+    /// The value to write to the register.
+    pub value: u8,
+}
+
+impl ResetRegister {
+    /// Writes the reset value to the register.
+    ///
+    /// Memory-mapped registers are written at their physical address, which must be identity
+    /// mapped. Otherwise, the register must be written through a mapping made with
+    /// [`map_mmio`](crate::cpu::paging::mmio::map_mmio).
+    ///
+    /// # Safety
+    ///
+    /// This resets the system.
+    pub unsafe fn write(&self) {
+        match self.space {
+            AddressSpace::Memory => core::ptr::write_volatile(self.address as *mut u8, self.value),
+            AddressSpace::Io => crate::utility::instr::outb(self.address as u16, self.value),
+        }
+    }
+}
+
+/// Looks for the reset register described by the FADT.
+///
+/// # Safety
+///
+/// The ACPI tables are read from their physical address. This function must be called while
+/// paging is disabled, or while those addresses are identity-mapped.
+pub unsafe fn find_reset_register() -> Option<ResetRegister> {
+    let fadt = find_table(FADT_SIGNATURE)?;
+    let len = read_u32(fadt + 4) as usize;
+
+    // The reset register was introduced in ACPI 2.0.
+    if len < 129 || read_u32(fadt + 112) & FADT_RESET_REG_SUPPORTED == 0 {
+        return None;
+    }
+
+    let space = match read_u8(fadt + 116) {
+        0 => AddressSpace::Memory,
+        1 => AddressSpace::Io,
+        // The PCI configuration space is not supported.
+        _ => return None,
+    };
+
+    let address = read_u32(fadt + 120);
+    if read_u32(fadt + 124) != 0 || address == 0 {
+        return None;
+    }
+
+    Some(ResetRegister {
+        space,
+        address,
+        value: read_u8(fadt + 128),
+    })
+}
+
+/// Finds the system description table with the provided signature.
+unsafe fn find_table(signature: &[u8; 4]) -> Option<usize> {
+    let rsdp = find_rsdp()?;
+    let rsdt = read_u32(rsdp + 16) as usize;
+
+    if !is_valid_table(rsdt) {
+        return None;
+    }
+
+    let len = read_u32(rsdt + 4) as usize;
+    let entries = (len - SDT_HEADER_SIZE) / 4;
+
+    (0..entries)
+        .map(|i| read_u32(rsdt + SDT_HEADER_SIZE + i * 4) as usize)
+        .find(|&table| bytes(table, 4) == signature && is_valid_table(table))
+}
+
+/// Finds the Root System Description Pointer.
+///
+/// It is either in the first KiB of the Extended BIOS Data Area, or in the BIOS read-only
+/// memory, between `0xE0000` and `0xFFFFF`. It is always aligned to 16 bytes.
+unsafe fn find_rsdp() -> Option<usize> {
+    let ebda = (read_u16(0x40E) as usize) << 4;
+
+    let is_rsdp = |addr: usize| bytes(addr, 8) == RSDP_SIGNATURE && checksum(addr, 20) == 0;
+
+    (ebda..ebda + 1024)
+        .step_by(16)
+        .chain((0xE0000..0x100000).step_by(16))
+        .find(|&addr| addr != 0 && is_rsdp(addr))
+}
+
+/// Returns whether the system description table at the provided address has a valid length
+/// and checksum.
+unsafe fn is_valid_table(addr: usize) -> bool {
+    if addr == 0 {
+        return false;
+    }
+
+    let len = read_u32(addr + 4) as usize;
+    len >= SDT_HEADER_SIZE && checksum(addr, len) == 0
+}
+
+/// Computes the sum of the provided bytes, modulo 256.
+unsafe fn checksum(addr: usize, len: usize) -> u8 {
+    bytes(addr, len)
+        .iter()
+        .fold(0u8, |acc, &b| acc.wrapping_add(b))
+}
+
+/// Returns the bytes at the provided physical address.
+unsafe fn bytes<'a>(addr: usize, len: usize) -> &'a [u8] {
+    core::slice::from_raw_parts(addr as *const u8, len)
+}
+
+/// Reads a `u8` at the provided physical address.
+unsafe fn read_u8(addr: usize) -> u8 {
+    core::ptr::read_volatile(addr as *const u8)
+}
+
+/// Reads a `u16` at the provided physical address.
+unsafe fn read_u16(addr: usize) -> u16 {
+    core::ptr::read_unaligned(addr as *const u16)
+}
+
+/// Reads a `u32` at the provided physical address.
+unsafe fn read_u32(addr: usize) -> u32 {
+    core::ptr::read_unaligned(addr as *const u32)
+}
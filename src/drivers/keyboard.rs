@@ -0,0 +1,198 @@
+//! A driver for the PS/2 keyboard, usually connected to the first port of the PS/2 controller.
+//!
+//! The scan-codes it sends are decoded by the [`terminal`](crate::terminal). This module only
+//! configures the keyboard, and picks out of its bytes the ones that are not scan-codes.
+//!
+//! A keyboard tests itself every time it is plugged in (or a KVM switch selects this machine
+//! again), and sends the result of the test. It forgets its settings in the process, so they
+//! are sent again when the result of the test is received.
+
+use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicBool, AtomicU32};
+
+use bitflags::bitflags;
+
+use super::ps2::{self, DeviceKind, Port};
+use crate::device::{self, Device, DeviceClass, NoOps};
+use crate::error::KernelError;
+use crate::utility::RestoreInterrupts;
+use crate::{kthread, log, TERMINAL};
+
+/// The command that sets the state of the LEDs of the keyboard.
+const SET_LEDS: u8 = 0xED;
+
+/// The command that selects the scan-code set the keyboard sends.
+const SET_SCANCODE_SET: u8 = 0xF0;
+
+/// The command that sets the delay and rate at which held keys repeat.
+const SET_TYPEMATIC: u8 = 0xF3;
+
+/// The command that makes the keyboard send scan-codes.
+const ENABLE_SCANNING: u8 = 0xF4;
+
+/// The repeat settings sent with [`SET_TYPEMATIC`]: keys repeat 30 times per second after
+/// being held for 500 ms.
+const TYPEMATIC: u8 = 0x20;
+
+/// The byte sent by the keyboard when it passed its self-test.
+const SELF_TEST_PASSED: u8 = 0xAA;
+
+/// The byte sent by the keyboard when it failed its self-test.
+const SELF_TEST_FAILED: u8 = 0xFC;
+
+/// The bytes sent by the keyboard when its buffer overflowed or a key could not be detected.
+const KEY_ERRORS: [u8; 2] = [0x00, 0xFF];
+
+/// The scan-code that precedes extended scan-codes, in both scan-code sets.
+const EXTENDED: u8 = 0xE0;
+
+/// The scan-code that precedes the sequence sent for the pause key.
+const PAUSE: u8 = 0xE1;
+
+/// The scan-code that precedes release scan-codes in the scan-code set 2.
+const RELEASED: u8 = 0xF0;
+
+/// The scan-code of the left shift key in the scan-code set 1.
+const LEFT_SHIFT_PRESSED: u8 = 0x2A;
+
+bitflags! {
+    /// The LEDs of the keyboard.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Leds: u8 {
+        /// The LED of the scroll lock key.
+        const SCROLL_LOCK = 1 << 0;
+        /// The LED of the num lock key.
+        const NUM_LOCK = 1 << 1;
+        /// The LED of the caps lock key.
+        const CAPS_LOCK = 1 << 2;
+    }
+}
+
+/// What a byte received from the keyboard means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Byte {
+    /// A scan-code, for the terminal.
+    Scancode,
+    /// The keyboard passed its self-test: it was plugged in and must be configured again.
+    Plugged,
+    /// The keyboard reported an error.
+    Error,
+}
+
+/// Whether the controller translates the scan-codes to the scan-code set 1.
+static TRANSLATED: AtomicBool = AtomicBool::new(true);
+
+/// Whether the previous byte was a prefix, after which the next one is always a scan-code.
+static AFTER_PREFIX: AtomicBool = AtomicBool::new(false);
+
+/// Whether the left shift key is pressed, in which case the scan-code set 1 sends the same
+/// byte as the result of a successful self-test when it is released.
+static LEFT_SHIFT: AtomicBool = AtomicBool::new(false);
+
+/// The number of times the keyboard was plugged in since the kernel booted.
+static PLUGGED: AtomicU32 = AtomicU32::new(0);
+
+/// Sets whether the controller translates the scan-codes to the scan-code set 1.
+pub fn set_translated(translated: bool) {
+    TRANSLATED.store(translated, Relaxed);
+}
+
+/// Returns the number of times the keyboard was plugged in since the kernel booted.
+pub fn plugged_count() -> u32 {
+    PLUGGED.load(Relaxed)
+}
+
+/// Tells what `byte`, just received from the keyboard, means.
+///
+/// This is called by the keyboard interrupt handler for every byte.
+pub fn classify(byte: u8) -> Byte {
+    if AFTER_PREFIX.swap(false, Relaxed) {
+        return Byte::Scancode;
+    }
+
+    let translated = TRANSLATED.load(Relaxed);
+    match byte {
+        EXTENDED | PAUSE => AFTER_PREFIX.store(true, Relaxed),
+        RELEASED if !translated => AFTER_PREFIX.store(true, Relaxed),
+        LEFT_SHIFT_PRESSED if translated => LEFT_SHIFT.store(true, Relaxed),
+        SELF_TEST_PASSED if translated && LEFT_SHIFT.swap(false, Relaxed) => (),
+        SELF_TEST_PASSED => return Byte::Plugged,
+        SELF_TEST_FAILED => return Byte::Error,
+        _ if KEY_ERRORS.contains(&byte) => return Byte::Error,
+        _ => (),
+    }
+    Byte::Scancode
+}
+
+/// Handles a byte from the keyboard that is not a scan-code.
+///
+/// This is called by the keyboard interrupt handler.
+pub fn handle(byte: u8, kind: Byte) {
+    match kind {
+        Byte::Scancode => (),
+        Byte::Plugged => {
+            LEFT_SHIFT.store(false, Relaxed);
+            PLUGGED.fetch_add(1, Relaxed);
+            if !kthread::defer(replugged) {
+                log!("Failed to queue the configuration of the keyboard.\n");
+            }
+        }
+        Byte::Error if byte == SELF_TEST_FAILED => log!("The keyboard failed its self-test.\n"),
+        Byte::Error => log!("The keyboard reported an error ({byte:#04x}).\n"),
+    }
+}
+
+/// Configures the keyboard connected to `port`: its scan-code set, its LEDs and how keys repeat.
+///
+/// The keyboard sends scan-codes of the set 2, which the controller translates to the set 1 if it
+/// was asked to. Fails with [`KernelError::NoDevice`] if the keyboard does not acknowledge one of
+/// the commands.
+pub fn configure(port: Port, leds: Leds) -> Result<(), KernelError> {
+    // The acknowledgements would be taken by the interrupt handler otherwise.
+    let _restore = RestoreInterrupts::without_interrupts();
+    let ok = [
+        SET_SCANCODE_SET,
+        2,
+        SET_TYPEMATIC,
+        TYPEMATIC,
+        SET_LEDS,
+        leds.bits(),
+        ENABLE_SCANNING,
+    ]
+    .into_iter()
+    .all(|byte| ps2::send(port, byte));
+    if ok {
+        Ok(())
+    } else {
+        Err(KernelError::NoDevice)
+    }
+}
+
+/// Configures the keyboard connected to `port`, with its LEDs matching the state of the
+/// terminal, and registers it.
+pub fn init(port: Port) -> Result<(), KernelError> {
+    let leds = TERMINAL.lock().keyboard_leds();
+    configure(port, leds)?;
+    if device::find(b"keyboard").is_none() {
+        device::register(Device {
+            name: "keyboard",
+            class: DeviceClass::Input,
+            ops: &NoOps,
+        });
+    }
+    Ok(())
+}
+
+/// Configures the keyboard again after it was plugged in.
+///
+/// This runs on the `kworker` thread.
+fn replugged() {
+    let port = ps2::find(DeviceKind::Keyboard).unwrap_or(Port::First);
+    match init(port) {
+        Ok(()) => log!(
+            "Keyboard plugged in ({} times so far), configured again.\n",
+            plugged_count()
+        ),
+        Err(err) => log!("Keyboard plugged in, but failed to configure it: {err}.\n"),
+    }
+}
@@ -0,0 +1,243 @@
+//! The driving code for the local APIC of the processor.
+//!
+//! The devices keep interrupting through the PIC: the local APIC is configured in virtual-wire
+//! mode, and forwards its requests to the processor. Only the bootstrap processor is started,
+//! so no inter-processor interrupt is ever sent.
+//!
+//! Its timer is the tick of the processor: it charges the CPU time and samples the profiler,
+//! while the PIT keeps the wall-clock. It is calibrated against the PIT, and each processor
+//! may run it at its own frequency.
+
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::cpu::idt::{LAPIC_TIMER_VECTOR, SPURIOUS_VECTOR};
+use crate::cpu::paging::mmio::{self, VolatileMmio};
+use crate::cpu::smp::{self, MAX_CPUS};
+use crate::drivers::speaker;
+use crate::error::KernelError;
+use crate::log;
+use crate::state::Allocator;
+use crate::utility::instr::Msr;
+use crate::utility::OnceCell;
+
+/// The size of the registers of the local APIC, in bytes.
+const REGISTERS_SIZE: usize = 0x400;
+
+/// The register that holds the ID of the local APIC, in its last byte.
+const REG_ID: usize = 0x20;
+/// The register written to signal the end of an interrupt.
+const REG_EOI: usize = 0xB0;
+/// The register that configures the spurious interrupt vector, and enables the local APIC.
+const REG_SPURIOUS: usize = 0xF0;
+/// The local vector table entry of the timer.
+const REG_LVT_TIMER: usize = 0x320;
+/// The local vector table entry of the LINT0 pin, to which the PIC is connected.
+const REG_LVT_LINT0: usize = 0x350;
+/// The local vector table entry of the LINT1 pin, to which the NMI line is connected.
+const REG_LVT_LINT1: usize = 0x360;
+/// The count from which the timer starts.
+const REG_TIMER_INITIAL: usize = 0x380;
+/// The current count of the timer, which raises an interrupt when it reaches zero.
+const REG_TIMER_CURRENT: usize = 0x390;
+/// The register that configures by how much the bus clock is divided for the timer.
+const REG_TIMER_DIVIDE: usize = 0x3E0;
+
+/// The bit of `IA32_APIC_BASE` that enables the local APIC.
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+/// The bits of `IA32_APIC_BASE` that hold the physical address of the registers.
+const APIC_BASE_ADDRESS: u64 = 0xF_FFFF_F000;
+
+/// The bit of the spurious interrupt vector register that enables the local APIC.
+const SPURIOUS_ENABLE: u32 = 1 << 8;
+
+/// The delivery mode of the local vector table that forwards the interrupts of the PIC.
+const LVT_EXTINT: u32 = 0b111 << 8;
+/// The delivery mode of the local vector table that delivers non-maskable interrupts.
+const LVT_NMI: u32 = 0b100 << 8;
+/// The bit of a local vector table entry that masks its interrupt.
+const LVT_MASKED: u32 = 1 << 16;
+/// The bit of the local vector table entry of the timer that reloads it once it reaches zero.
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+/// The value of the divide register that divides the bus clock by 16.
+const TIMER_DIVIDE_16: u32 = 0b0011;
+
+/// The number of milliseconds during which the timer is compared to the PIT.
+const CALIBRATION_MS: u32 = 10;
+
+/// The frequency of the timer when none is requested, in hertz.
+pub const DEFAULT_TIMER_HZ: u32 = 1000;
+
+/// The registers of the local APIC.
+///
+/// The processors do not share it: each one accesses its own local APIC at the same address.
+static REGISTERS: OnceCell<VolatileMmio> = OnceCell::new();
+
+/// The number of times the timer counts down per millisecond.
+///
+/// The processors share the bus clock, so the calibration of the first one holds for all.
+static TIMER_COUNTS_PER_MS: AtomicU32 = AtomicU32::new(0);
+
+/// The frequency of the timer of each processor, in hertz, or 0 when it is stopped.
+static TIMER_HZ: [AtomicU32; MAX_CPUS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; MAX_CPUS]
+};
+
+/// The number of ticks of the timer of each processor.
+static TIMER_TICKS: [AtomicU32; MAX_CPUS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; MAX_CPUS]
+};
+
+/// Returns the registers of the local APIC, if it is initialized.
+#[inline]
+fn registers() -> Option<&'static VolatileMmio> {
+    REGISTERS.get()
+}
+
+/// Initializes the local APIC of the current processor.
+///
+/// Its registers are mapped in the kernel's address space, and the missing page tables are
+/// allocated from `allocator`. Fails with [`KernelError::NoDevice`] if the processor has no
+/// local APIC.
+pub fn init(allocator: &mut Allocator) -> Result<(), KernelError> {
+    let base = Msr::ApicBase.read().ok_or(KernelError::NoDevice)?;
+    if base & APIC_BASE_ENABLE == 0 {
+        unsafe { Msr::ApicBase.write(base | APIC_BASE_ENABLE) };
+    }
+    if registers().is_some() {
+        return Err(KernelError::Busy);
+    }
+
+    let registers = mmio::map_mmio(allocator, base & APIC_BASE_ADDRESS, REGISTERS_SIZE)?;
+    unsafe {
+        registers.write::<u32>(REG_LVT_LINT0, LVT_EXTINT);
+        registers.write::<u32>(REG_LVT_LINT1, LVT_NMI);
+        registers.write::<u32>(REG_SPURIOUS, SPURIOUS_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+    let _ = REGISTERS.set(registers);
+
+    let id = id().unwrap_or(0);
+    smp::set_online(id);
+    log!("Local APIC: ID {id}\n");
+    Ok(())
+}
+
+/// Returns the ID of the local APIC of the current processor, if it is initialized.
+#[inline]
+pub fn id() -> Option<u32> {
+    registers().map(|registers| unsafe { registers.read::<u32>(REG_ID) } >> 24)
+}
+
+/// Signals the end of an interrupt that was delivered by the local APIC.
+///
+/// This is not needed for the interrupts of the PIC, nor for spurious interrupts.
+#[inline]
+pub fn end_of_interrupt() {
+    if let Some(registers) = registers() {
+        unsafe { registers.write::<u32>(REG_EOI, 0) };
+    }
+}
+
+/// Returns the index of the current processor in the per-processor arrays of this module.
+#[inline]
+fn cpu_index() -> usize {
+    (smp::current_id() as usize).min(MAX_CPUS - 1)
+}
+
+/// Measures how fast the timer counts down, by comparing it to the PIT.
+///
+/// This uses the channel 2 of the PIT, and must not be called while the speaker plays.
+/// Returns the number of counts per millisecond.
+fn calibrate_timer(registers: &VolatileMmio) -> Result<u32, KernelError> {
+    let elapsed = unsafe {
+        registers.write::<u32>(REG_LVT_TIMER, LVT_MASKED);
+        registers.write::<u32>(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+        registers.write::<u32>(REG_TIMER_INITIAL, u32::MAX);
+        let timed = speaker::wait_silently(CALIBRATION_MS);
+        let current = registers.read::<u32>(REG_TIMER_CURRENT);
+        registers.write::<u32>(REG_TIMER_INITIAL, 0);
+        if !timed {
+            return Err(KernelError::NoDevice);
+        }
+        u32::MAX - current
+    };
+
+    match elapsed / CALIBRATION_MS {
+        0 => Err(KernelError::NoDevice),
+        counts => Ok(counts),
+    }
+}
+
+/// Starts the timer of the current processor, raising `hz` interrupts per second.
+///
+/// The timer is calibrated against the PIT the first time. Fails with
+/// [`KernelError::InvalidArgument`] if the timer cannot run at that frequency.
+pub fn init_timer(hz: u32) -> Result<(), KernelError> {
+    let registers = registers().ok_or(KernelError::NoDevice)?;
+
+    let mut counts_per_ms = TIMER_COUNTS_PER_MS.load(Relaxed);
+    if counts_per_ms == 0 {
+        counts_per_ms = calibrate_timer(registers)?;
+        TIMER_COUNTS_PER_MS.store(counts_per_ms, Relaxed);
+    }
+
+    let initial = (counts_per_ms as u64 * 1000)
+        .checked_div(hz as u64)
+        .filter(|&count| count != 0 && count <= u32::MAX as u64)
+        .ok_or(KernelError::InvalidArgument)?;
+    unsafe {
+        registers.write::<u32>(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+        registers.write::<u32>(
+            REG_LVT_TIMER,
+            LVT_TIMER_PERIODIC | LAPIC_TIMER_VECTOR as u32,
+        );
+        registers.write::<u32>(REG_TIMER_INITIAL, initial as u32);
+    }
+    TIMER_HZ[cpu_index()].store(hz, Relaxed);
+
+    log!("Local APIC timer: {hz} Hz ({counts_per_ms} counts per ms)\n");
+    Ok(())
+}
+
+/// Returns the frequency of the timer of the current processor, in hertz, if it runs.
+#[inline]
+pub fn timer_hz() -> Option<u32> {
+    cpu_timer_hz(smp::current_id())
+}
+
+/// Returns the frequency of the timer of the processor `id`, in hertz, if it runs.
+pub fn cpu_timer_hz(id: u32) -> Option<u32> {
+    match TIMER_HZ.get(id as usize)?.load(Relaxed) {
+        0 => None,
+        hz => Some(hz),
+    }
+}
+
+/// Returns the number of ticks of the timer of the processor `id`.
+pub fn cpu_ticks(id: u32) -> u32 {
+    TIMER_TICKS
+        .get(id as usize)
+        .map_or(0, |ticks| ticks.load(Relaxed))
+}
+
+/// Returns the number of nanoseconds between two ticks of the timer of the current processor,
+/// if it runs.
+#[inline]
+pub fn timer_interval_ns() -> Option<u32> {
+    timer_hz().map(|hz| 1_000_000_000 / hz)
+}
+
+/// Counts a tick of the timer of the current processor, and returns the number of ticks so far.
+///
+/// This function is meant to be called from the timer interrupt handler.
+#[inline]
+pub fn count_tick() -> u32 {
+    TIMER_TICKS[cpu_index()]
+        .fetch_add(1, Relaxed)
+        .wrapping_add(1)
+}
@@ -1,7 +1,13 @@
 //! This modules contains the code for the internal drivers used by the kernel.
 
+pub mod acpi;
+pub mod keyboard;
+pub mod lapic;
+pub mod mouse;
 pub mod pic;
 pub mod pit;
 pub mod ps2;
+pub mod rtc;
 pub mod serial;
+pub mod speaker;
 pub mod vga;
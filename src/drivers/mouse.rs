@@ -0,0 +1,178 @@
+//! A driver for the PS/2 mouse, usually connected to the second port of the PS/2 controller.
+//!
+//! The mouse sends a 3-byte packet every time it moves or a button changes state. Mice that
+//! have a wheel send a fourth byte once the IntelliMouse extension is enabled.
+
+use bitflags::bitflags;
+
+use super::ps2::{self, Port};
+use crate::device::{self, Device, DeviceClass, NoOps};
+use crate::error::KernelError;
+
+/// The command that resets the settings of the mouse to their default values.
+const SET_DEFAULTS: u8 = 0xF6;
+
+/// The command that makes the mouse send movement packets.
+const ENABLE_REPORTING: u8 = 0xF4;
+
+/// The command that sets the number of packets the mouse sends per second.
+const SET_SAMPLE_RATE: u8 = 0xF3;
+
+/// The command that asks the mouse for its identifier.
+const GET_DEVICE_ID: u8 = 0xF2;
+
+/// The identifier of mice that support the IntelliMouse extension.
+const INTELLIMOUSE_ID: u8 = 3;
+
+/// The format of the packets sent by the mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// 3-byte packets, without wheel movements.
+    Standard,
+    /// 4-byte packets, whose last byte holds the movement of the wheel.
+    Wheel,
+}
+
+impl Protocol {
+    /// Returns the number of bytes of a packet.
+    #[inline]
+    pub const fn packet_len(self) -> u8 {
+        match self {
+            Self::Standard => 3,
+            Self::Wheel => 4,
+        }
+    }
+}
+
+/// Enables the PS/2 mouse connected to `port`.
+///
+/// Returns the format of its packets if a mouse answered, and [`KernelError::NoDevice`]
+/// otherwise. When it did, the IRQ of the port is raised every time a byte of a packet is
+/// received.
+///
+/// # Remarks
+///
+/// This function must be called with interrupts disabled, as the responses of the mouse would
+/// otherwise be taken by the interrupt handlers.
+pub fn init(port: Port) -> Result<Protocol, KernelError> {
+    if !ps2::send(port, SET_DEFAULTS) {
+        return Err(KernelError::NoDevice);
+    }
+
+    // This magic sequence of sample rates enables the wheel of mice that have one. The mouse
+    // then reports a different identifier.
+    let knocked = [200, 100, 80]
+        .into_iter()
+        .all(|rate| ps2::send(port, SET_SAMPLE_RATE) && ps2::send(port, rate));
+    let protocol = if knocked
+        && ps2::send(port, GET_DEVICE_ID)
+        && ps2::read_response() == Some(INTELLIMOUSE_ID)
+    {
+        Protocol::Wheel
+    } else {
+        Protocol::Standard
+    };
+
+    if !ps2::send(port, ENABLE_REPORTING) {
+        return Err(KernelError::NoDevice);
+    }
+
+    device::register(Device {
+        name: "mouse",
+        class: DeviceClass::Input,
+        ops: &NoOps,
+    });
+    Ok(protocol)
+}
+
+bitflags! {
+    /// The buttons of the mouse.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Buttons: u8 {
+        /// The left button.
+        const LEFT = 1 << 0;
+        /// The right button.
+        const RIGHT = 1 << 1;
+        /// The middle button.
+        const MIDDLE = 1 << 2;
+    }
+}
+
+/// A packet sent by the mouse.
+#[derive(Clone, Copy, Debug)]
+pub struct Packet {
+    /// The horizontal movement of the mouse, positive to the right.
+    pub dx: i16,
+    /// The vertical movement of the mouse, positive upwards.
+    pub dy: i16,
+    /// The movement of the wheel, positive towards the user.
+    pub dz: i8,
+    /// The buttons that are currently pressed.
+    pub buttons: Buttons,
+}
+
+/// Assembles the bytes received from the mouse into packets.
+pub struct Decoder {
+    /// The bytes of the packet being received.
+    bytes: [u8; 4],
+    /// The number of bytes of `bytes` that were received.
+    len: u8,
+    /// The format of the packets.
+    protocol: Protocol,
+}
+
+impl Decoder {
+    /// The bit of the first byte of a packet that is always set.
+    ///
+    /// This is used to find the start of packets again after a byte is lost.
+    const ALWAYS_ONE: u8 = 1 << 3;
+
+    /// Creates a new [`Decoder`] instance, for packets of the provided format.
+    pub const fn new(protocol: Protocol) -> Self {
+        Self {
+            bytes: [0; 4],
+            len: 0,
+            protocol,
+        }
+    }
+
+    /// Feeds a byte received from the mouse to the decoder, returning the packet it completes,
+    /// if any.
+    pub fn advance(&mut self, byte: u8) -> Option<Packet> {
+        if self.len == 0 && byte & Self::ALWAYS_ONE == 0 {
+            // We are out of sync with the mouse.
+            return None;
+        }
+
+        self.bytes[self.len as usize] = byte;
+        self.len += 1;
+        if self.len < self.protocol.packet_len() {
+            return None;
+        }
+        self.len = 0;
+
+        let [flags, x, y, z] = self.bytes;
+
+        // The movement is a 9-bit two's complement number, whose sign is stored in the first
+        // byte. When it overflowed, the value is meaningless.
+        let delta = |value: u8, sign: u8, overflow: u8| {
+            if flags & overflow != 0 {
+                0
+            } else if flags & sign != 0 {
+                value as i16 - 0x100
+            } else {
+                value as i16
+            }
+        };
+
+        Some(Packet {
+            dx: delta(x, 1 << 4, 1 << 6),
+            dy: delta(y, 1 << 5, 1 << 7),
+            dz: match self.protocol {
+                Protocol::Standard => 0,
+                Protocol::Wheel => z as i8,
+            },
+            buttons: Buttons::from_bits_truncate(flags),
+        })
+    }
+}
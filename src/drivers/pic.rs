@@ -1,11 +1,16 @@
 //! The driving code for the Programmable Interrupt Controller.
 
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering::Relaxed;
+
 use bitflags::bitflags;
 
 use crate::cpu::idt::PIC_OFFSET;
-use crate::utility::instr::outb;
+use crate::error::KernelError;
+use crate::utility::instr::{inb, outb};
 
 /// A PIC (Programmable Interrupt Controller).
+#[derive(Clone, Copy)]
 struct Pic {
     /// The command port of the PIC.
     cmd: u16,
@@ -37,10 +42,22 @@ impl Pic {
     pub fn write(self, data: u8) {
         unsafe { outb(self.data, data) }
     }
+
+    /// Reads the data register of the PIC, which holds its IRQ mask once it is initialized.
+    #[inline]
+    pub fn read(self) -> u8 {
+        unsafe { inb(self.data) }
+    }
 }
 
+/// The IRQ mask written to the PICs to check that they are there.
+const PROBE_MASK: u8 = 0xA5;
+
 /// Initializes the PIC.
-pub fn init() {
+///
+/// Fails with [`KernelError::NoDevice`] if the PICs do not keep the IRQ mask written to them.
+/// All the IRQs are masked when this function returns successfully.
+pub fn init() -> Result<(), KernelError> {
     // ICW stands for "Initialization Command Word" btw.
 
     // Start the initialization sequence by sending the initialization command to both PICs.
@@ -79,11 +96,38 @@ pub fn init() {
     wait_a_bit();
     Pic::SLAVE.write(0x01);
     wait_a_bit();
+
+    // Nothing answers on the ports of missing PICs, so the mask cannot be read back.
+    for pic in [Pic::MASTER, Pic::SLAVE] {
+        pic.write(PROBE_MASK);
+        wait_a_bit();
+        let ok = pic.read() == PROBE_MASK;
+        pic.write(0xFF);
+        if !ok {
+            return Err(KernelError::NoDevice);
+        }
+    }
+    Ok(())
+}
+
+/// The number of times each IRQ was acknowledged with [`end_of_interrupt`].
+static COUNTS: [AtomicU32; 16] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; 16]
+};
+
+/// Returns the number of times the provided IRQ was received.
+#[inline]
+pub fn interrupt_count(irq: Irq) -> u32 {
+    COUNTS[irq as usize].load(Relaxed)
 }
 
 /// Send an END-OF-INTERRUPT command to the PIC for the provided IRQ.
 #[inline]
 pub fn end_of_interrupt(irq: Irq) {
+    COUNTS[irq as usize].fetch_add(1, Relaxed);
+
     // EOI is bit 5 of the operation command word (OCW2).
     // That word is sent to the command register.
 
@@ -159,6 +203,28 @@ pub enum Irq {
     Ata2,
 }
 
+impl Irq {
+    /// All the IRQs, in order.
+    pub const ALL: [Self; 16] = [
+        Self::Timer,
+        Self::Keyboard,
+        Self::Cascade,
+        Self::Com2,
+        Self::Com1,
+        Self::Lpt2,
+        Self::Floppy,
+        Self::Lpt1,
+        Self::RealTimeClock,
+        Self::Periph1,
+        Self::Periph2,
+        Self::Periph3,
+        Self::Mouse,
+        Self::Fpu,
+        Self::Ata1,
+        Self::Ata2,
+    ];
+}
+
 bitflags! {
     /// A set of IRQs.
     #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -7,6 +7,7 @@ use bitflags::bitflags;
 
 use crate::log;
 use crate::utility::instr::outb;
+use crate::utility::Fixed;
 
 bitflags! {
     /// The command codes that can be sent to the PIT.
@@ -14,6 +15,10 @@ bitflags! {
         /// Indicates that the PIT is configured to send a one-time interrupt on IRQ0.
         const CHANNEL_0 = 0b00 << 6;
 
+        /// Indicates that the command configures the channel 2, which is connected to the PC
+        /// speaker.
+        const CHANNEL_2 = 0b10 << 6;
+
         /// Data transfered from/to the PIT is read as a sequence of two bytes to make a 16-bit
         /// word.
         ///
@@ -23,6 +28,8 @@ bitflags! {
         /// Indicates that the PIT should send an interrupt at a certain frequency.
         const RATE_GENERATOR = 0b010 << 1;
 
+        /// Indicates that the output of the channel should be a square wave.
+        const SQUARE_WAVE = 0b011 << 1;
     }
 }
 
@@ -32,6 +39,9 @@ const COMMAND_PORT: u16 = 0x43;
 /// The data port of the PIT.
 const DATA_PORT: u16 = 0x40;
 
+/// The data port of the channel 2 of the PIT.
+const CHANNEL_2_DATA_PORT: u16 = 0x42;
+
 /// Sends a commant to the PIT.
 #[inline]
 fn command(cmd: PitCmd) {
@@ -91,8 +101,8 @@ fn freq_to_reload_value(freq: u64) -> u64 {
 }
 
 /// Computes the frequency that the PIT will generate with the specified reload value.
-fn reload_value_to_freq(rl: u64) -> f64 {
-    3579545.0 / (3.0 * rl as f64)
+fn reload_value_to_freq(rl: u64) -> Fixed {
+    Fixed::from_ratio(3579545, 3 * rl)
 }
 
 /// Computes the number of nanoseconds between two interrupts, for the provided
@@ -126,6 +136,21 @@ pub fn interval_ns() -> u32 {
     INTERVAL_NS.load(Relaxed)
 }
 
+/// Configures the channel 2 of the PIT to generate a square wave of the requested frequency.
+///
+/// The output of the channel is connected to the PC speaker (see the
+/// [`speaker`](super::speaker) driver).
+pub fn set_channel_2_frequency(freq: u32) {
+    let reload_value = freq_to_reload_value(freq as u64);
+
+    command(PitCmd::CHANNEL_2 | PitCmd::ACCESS_MODE_LO_HI | PitCmd::SQUARE_WAVE);
+    // A reload value of 0x10000 is sent as 0, which the PIT interprets as 0x10000.
+    unsafe {
+        outb(CHANNEL_2_DATA_PORT, (reload_value & 0xFF) as u8);
+        outb(CHANNEL_2_DATA_PORT, ((reload_value >> 8) & 0xFF) as u8);
+    }
+}
+
 /// Initializes the PIT.
 ///
 /// # Remarks
@@ -1,8 +1,14 @@
 //! An implementation of a PS/2 controller.
 
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering::Relaxed;
+
 use bitflags::bitflags;
 
-use crate::utility::instr::{inb, outb};
+use crate::device::{self, Device, DeviceClass, NoOps};
+use crate::error::KernelError;
+use crate::log;
+use crate::utility::instr::{inb, outb, pause};
 
 /// The I/O port of the PS/2 controller command register.
 const COMMAND_PORT: u16 = 0x64;
@@ -10,6 +16,160 @@ const COMMAND_PORT: u16 = 0x64;
 /// The I/O port of the PS/2 controller data register.
 const DATA_PORT: u16 = 0x60;
 
+/// The command that reads the configuration byte of the controller.
+const READ_CONFIG: u8 = 0x20;
+
+/// The command that writes the configuration byte of the controller.
+const WRITE_CONFIG: u8 = 0x60;
+
+/// The command that makes the controller test itself.
+const SELF_TEST: u8 = 0xAA;
+
+/// The response of the controller to [`SELF_TEST`] when it works.
+const SELF_TEST_PASSED: u8 = 0x55;
+
+/// The command that enables the second PS/2 port.
+const ENABLE_SECOND_PORT: u8 = 0xA8;
+
+/// The command that sends the next byte written to the data register to the device connected
+/// to the second PS/2 port, instead of the first one.
+const WRITE_SECOND_PORT: u8 = 0xD4;
+
+/// The command that makes a PS/2 device stop sending data.
+const DISABLE_SCANNING: u8 = 0xF5;
+
+/// The command that makes a PS/2 device send data again.
+const ENABLE_SCANNING: u8 = 0xF4;
+
+/// The command that asks a PS/2 device for its identifier.
+const IDENTIFY: u8 = 0xF2;
+
+/// The byte sent by PS/2 devices to acknowledge a command.
+const ACK: u8 = 0xFA;
+
+/// The number of times the status register is polled before giving up on the controller.
+const POLL_ATTEMPTS: u32 = 100_000;
+
+/// A port of the PS/2 controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// The first port, whose data raises IRQ1.
+    First,
+    /// The second port, whose data raises IRQ12.
+    Second,
+}
+
+impl Port {
+    /// Both ports.
+    pub const ALL: [Self; 2] = [Self::First, Self::Second];
+}
+
+/// The kind of device connected to a port, as identified by [`identify_ports`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    /// A keyboard.
+    Keyboard,
+    /// A mouse.
+    Mouse,
+    /// A device that answered with an unknown identifier.
+    Unknown,
+}
+
+impl DeviceKind {
+    /// Returns the kind of device whose identifier is `id`, which holds the bytes it answered
+    /// [`IDENTIFY`] with.
+    fn from_id(id: &[u8]) -> Self {
+        match id {
+            // Old AT keyboards send nothing, and MF2 keyboards send `0xAB` followed by a byte
+            // that depends on the translation.
+            [] | [0xAB, _] => Self::Keyboard,
+            // Standard mice, mice with a wheel, and mice with five buttons.
+            [0x00 | 0x03 | 0x04] => Self::Mouse,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The kind of device connected to each port, or [`NO_DEVICE`].
+static KINDS: [AtomicU8; 2] = [AtomicU8::new(NO_DEVICE), AtomicU8::new(NO_DEVICE)];
+
+/// The value of [`KINDS`] for ports to which no device is connected.
+const NO_DEVICE: u8 = u8::MAX;
+
+/// Returns the kind of device connected to `port`, if any.
+pub fn kind(port: Port) -> Option<DeviceKind> {
+    match KINDS[port as usize].load(Relaxed) {
+        0 => Some(DeviceKind::Keyboard),
+        1 => Some(DeviceKind::Mouse),
+        2 => Some(DeviceKind::Unknown),
+        _ => None,
+    }
+}
+
+/// Returns the port to which a device of kind `kind` is connected, if any.
+pub fn find(kind: DeviceKind) -> Option<Port> {
+    Port::ALL
+        .into_iter()
+        .find(|&port| self::kind(port) == Some(kind))
+}
+
+/// Enables the second port, and identifies the devices connected to both ports.
+///
+/// The translation of the first port is disabled if it is not a keyboard, as it would garble
+/// the bytes of other devices.
+///
+/// # Remarks
+///
+/// This function must be called with interrupts disabled, like [`read_config`].
+pub fn identify_ports() {
+    let Some(config) = read_config() else {
+        log!("Failed to read the configuration of the PS/2 controller.\n");
+        return;
+    };
+    let translated = config.contains(PS2Config::FIRST_PORT_TRANSLATION);
+    if translated && !write_config(config - PS2Config::FIRST_PORT_TRANSLATION) {
+        log!("Failed to disable the translation of the PS/2 controller.\n");
+    }
+    let second = enable_second_port();
+
+    for port in Port::ALL {
+        let kind = if port == Port::First || second {
+            identify(port)
+        } else {
+            None
+        };
+        KINDS[port as usize].store(kind.map_or(NO_DEVICE, |k| k as u8), Relaxed);
+        match kind {
+            Some(kind) => log!("PS/2 {port:?} port: {kind:?}.\n"),
+            None => log!("PS/2 {port:?} port: no device.\n"),
+        }
+    }
+
+    if translated && kind(Port::First) == Some(DeviceKind::Keyboard) {
+        match read_config() {
+            Some(config) if write_config(config | PS2Config::FIRST_PORT_TRANSLATION) => (),
+            _ => log!("Failed to enable the translation of the PS/2 controller again.\n"),
+        }
+    }
+}
+
+/// Asks the device connected to `port` for its identifier.
+///
+/// Returns `None` if no device answers.
+fn identify(port: Port) -> Option<DeviceKind> {
+    if !send(port, DISABLE_SCANNING) {
+        return None;
+    }
+    let kind = send(port, IDENTIFY).then(|| match read_response() {
+        None => DeviceKind::from_id(&[]),
+        // MF2 keyboards send a second byte.
+        Some(0xAB) => DeviceKind::from_id(&[0xAB, read_response().unwrap_or(0)]),
+        Some(byte) => DeviceKind::from_id(&[byte]),
+    });
+    send(port, ENABLE_SCANNING);
+    kind
+}
+
 /// Reads the status register of the PS/2 controller.
 #[inline]
 pub fn status() -> PS2Status {
@@ -26,6 +186,56 @@ pub fn is_output_buffer_full() -> bool {
     status().intersects(PS2Status::OUTPUT_BUFFER_FULL)
 }
 
+/// Returns the port from which the byte in the output buffer comes.
+#[inline]
+pub fn port_of(status: PS2Status) -> Port {
+    if status.contains(PS2Status::OUTPUT_BUFFER_FULL | PS2Status::AUX_OUTPUT_BUFFER_FULL) {
+        Port::Second
+    } else {
+        Port::First
+    }
+}
+
+/// Returns whether the byte in the output buffer comes from the mouse.
+///
+/// This is used to tell bytes sent by the mouse apart from scan-codes when polling the
+/// controller.
+#[inline]
+pub fn is_mouse_data(status: PS2Status) -> bool {
+    kind(port_of(status)) == Some(DeviceKind::Mouse)
+}
+
+/// Reads and drops the bytes waiting in the output buffer of the controller, and returns how
+/// many there were.
+///
+/// At most 16 bytes are read, so that a device that keeps sending cannot hold the caller.
+pub fn flush_output() -> u32 {
+    let mut count = 0;
+    while count < 16 && is_output_buffer_full() {
+        read_data();
+        count += 1;
+    }
+    count
+}
+
+/// Reads and drops the bytes of `port` waiting in the output buffer of the controller, and
+/// returns how many there were.
+///
+/// This stops at the first byte that comes from the other port, which is left for its own
+/// interrupt handler. Like [`flush_output`], at most 16 bytes are read.
+pub fn flush_port_output(port: Port) -> u32 {
+    let mut count = 0;
+    while count < 16 {
+        let status = status();
+        if !status.intersects(PS2Status::OUTPUT_BUFFER_FULL) || port_of(status) != port {
+            break;
+        }
+        read_data();
+        count += 1;
+    }
+    count
+}
+
 /// Sends a command to the PS/2 controller.
 #[inline]
 pub fn command(cmd: u8) {
@@ -51,6 +261,168 @@ pub fn write_data(data: u8) {
     unsafe { outb(DATA_PORT, data) }
 }
 
+/// Waits until the controller is ready to receive a command or data.
+///
+/// Returns whether the controller became ready before the timeout.
+fn wait_input_empty() -> bool {
+    (0..POLL_ATTEMPTS).any(|_| {
+        let ready = !status().intersects(PS2Status::INPUT_BUFFER_FULL);
+        if !ready {
+            pause();
+        }
+        ready
+    })
+}
+
+/// Waits until the controller has data available in its output buffer.
+///
+/// Returns whether data became available before the timeout.
+fn wait_output_full() -> bool {
+    (0..POLL_ATTEMPTS).any(|_| {
+        let ready = is_output_buffer_full();
+        if !ready {
+            pause();
+        }
+        ready
+    })
+}
+
+/// Checks that a working PS/2 controller is there.
+///
+/// Fails with [`KernelError::TimedOut`] if the controller does not answer, and with
+/// [`KernelError::NoDevice`] if it fails its self-test.
+///
+/// # Remarks
+///
+/// This function must be called with interrupts disabled, like [`read_config`].
+pub fn init() -> Result<(), KernelError> {
+    // Drop whatever the devices sent before, as it would be taken for the responses of the
+    // controller.
+    flush_output();
+
+    // Some controllers are reset by their self-test, so the configuration is restored after it.
+    let config = read_config().ok_or(KernelError::TimedOut)?;
+    if !wait_input_empty() {
+        return Err(KernelError::TimedOut);
+    }
+    command(SELF_TEST);
+    let passed = read_response().ok_or(KernelError::TimedOut)? == SELF_TEST_PASSED;
+    if !write_config(config) {
+        return Err(KernelError::TimedOut);
+    }
+    if !passed {
+        return Err(KernelError::NoDevice);
+    }
+
+    device::register(Device {
+        name: "ps2",
+        class: DeviceClass::Controller,
+        ops: &NoOps,
+    });
+    Ok(())
+}
+
+/// Reads the configuration byte of the controller.
+///
+/// # Remarks
+///
+/// This function must be called with interrupts disabled, as the response of the controller
+/// would otherwise be taken by the keyboard interrupt handler.
+pub fn read_config() -> Option<PS2Config> {
+    if !wait_input_empty() {
+        return None;
+    }
+    command(READ_CONFIG);
+    if !wait_output_full() {
+        return None;
+    }
+    Some(PS2Config::from_bits_retain(read_data()))
+}
+
+/// Writes the configuration byte of the controller.
+///
+/// Returns whether the controller accepted the new configuration.
+pub fn write_config(config: PS2Config) -> bool {
+    if !wait_input_empty() {
+        return false;
+    }
+    command(WRITE_CONFIG);
+    if !wait_input_empty() {
+        return false;
+    }
+    write_data(config.bits());
+    true
+}
+
+/// Enables the second PS/2 port, and makes it generate interrupts.
+///
+/// Returns whether the controller accepted the new configuration.
+///
+/// # Remarks
+///
+/// This function must be called with interrupts disabled, like [`read_config`].
+pub fn enable_second_port() -> bool {
+    if !wait_input_empty() {
+        return false;
+    }
+    command(ENABLE_SECOND_PORT);
+
+    let Some(mut config) = read_config() else {
+        return false;
+    };
+    config.insert(PS2Config::SECOND_PORT_INTERRUPT);
+    config.remove(PS2Config::SECOND_PORT_CLOCK_DISABLED);
+    write_config(config)
+}
+
+/// Sends a command to the device connected to `port`, and waits for it to be acknowledged.
+///
+/// Returns whether the device acknowledged the command.
+///
+/// # Remarks
+///
+/// This function must be called with interrupts disabled, like [`read_config`].
+pub fn send(port: Port, cmd: u8) -> bool {
+    if !wait_input_empty() {
+        return false;
+    }
+    if port == Port::Second {
+        command(WRITE_SECOND_PORT);
+        if !wait_input_empty() {
+            return false;
+        }
+    }
+    write_data(cmd);
+    wait_output_full() && read_data() == ACK
+}
+
+/// Reads a byte sent by a PS/2 device in response to a command.
+///
+/// `None` is returned if the device did not answer in time.
+pub fn read_response() -> Option<u8> {
+    wait_output_full().then(read_data)
+}
+
+bitflags! {
+    /// Represents the configuration byte of the PS/2 controller.
+    #[derive(Clone, Copy, Debug)]
+    pub struct PS2Config: u8 {
+        /// Whether the first PS/2 port generates interrupts (IRQ1).
+        const FIRST_PORT_INTERRUPT = 1 << 0;
+        /// Whether the second PS/2 port generates interrupts (IRQ12).
+        const SECOND_PORT_INTERRUPT = 1 << 1;
+        /// Set by the firmware once the system passed its POST.
+        const SYSTEM = 1 << 2;
+        /// Whether the clock of the first PS/2 port is disabled.
+        const FIRST_PORT_CLOCK_DISABLED = 1 << 4;
+        /// Whether the clock of the second PS/2 port is disabled.
+        const SECOND_PORT_CLOCK_DISABLED = 1 << 5;
+        /// Whether the controller translates the scan-codes of the first port to the scan-code
+        /// set 1.
+        const FIRST_PORT_TRANSLATION = 1 << 6;
+    }
+}
+
 bitflags! {
     /// Represents the status register of the PS/2 controller.
     #[derive(Clone, Copy, Debug)]
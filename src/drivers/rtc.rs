@@ -0,0 +1,195 @@
+//! A driver for the Real-Time Clock (RTC) of the CMOS.
+//!
+//! See the [OSDev Wiki](https://wiki.osdev.org/CMOS).
+
+use crate::utility::instr::{inb, outb, pause};
+use crate::utility::RestoreInterrupts;
+
+/// The port used to select a CMOS register.
+const ADDRESS_PORT: u16 = 0x70;
+
+/// The port used to read the selected CMOS register.
+const DATA_PORT: u16 = 0x71;
+
+/// The bit of the address port that disables non-maskable interrupts.
+const DISABLE_NMI: u8 = 0x80;
+
+/// The register holding the seconds.
+pub const SECONDS: u8 = 0x00;
+/// The register holding the minutes.
+pub const MINUTES: u8 = 0x02;
+/// The register holding the hours.
+pub const HOURS: u8 = 0x04;
+/// The register holding the day of the month.
+pub const DAY: u8 = 0x07;
+/// The register holding the month.
+pub const MONTH: u8 = 0x08;
+/// The register holding the last two digits of the year.
+pub const YEAR: u8 = 0x09;
+/// The status register A.
+pub const STATUS_A: u8 = 0x0A;
+/// The status register B.
+pub const STATUS_B: u8 = 0x0B;
+
+/// The bit of the status register A that is set while the RTC updates its registers.
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+
+/// The bit of the status register B that stops the updates of the time registers, so that
+/// they can be set.
+const SET: u8 = 0x80;
+
+/// The bit of the status register B that is set when the time registers are in binary rather
+/// than BCD.
+const BINARY_MODE: u8 = 0x04;
+
+/// The bit of the status register B that is set when the hours are in the 24-hour format.
+const HOUR_24_MODE: u8 = 0x02;
+
+/// The bit of the hours register that is set for PM hours, in the 12-hour format.
+const PM: u8 = 0x80;
+
+/// Reads the provided CMOS register.
+///
+/// # Remarks
+///
+/// Non-maskable interrupts are disabled while the register is being read. Interrupts should be
+/// disabled too, as a CMOS access cannot be interrupted by another one.
+pub fn read(register: u8) -> u8 {
+    unsafe {
+        outb(ADDRESS_PORT, DISABLE_NMI | register);
+        inb(DATA_PORT)
+    }
+}
+
+/// Writes the provided CMOS register.
+///
+/// The same remarks as for [`read`] apply.
+pub fn write(register: u8, value: u8) {
+    unsafe {
+        outb(ADDRESS_PORT, DISABLE_NMI | register);
+        outb(DATA_PORT, value);
+    }
+}
+
+/// Reads the raw values of the time registers, in the order seconds, minutes, hours, day,
+/// month and year.
+///
+/// The values are returned in the format used by the RTC (usually BCD).
+pub fn read_raw_time() -> [u8; 6] {
+    const REGISTERS: [u8; 6] = [SECONDS, MINUTES, HOURS, DAY, MONTH, YEAR];
+
+    // The registers may be inconsistent while they are being updated.
+    while read(STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+        pause();
+    }
+
+    REGISTERS.map(read)
+}
+
+/// A time read from the RTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcTime {
+    /// The seconds (0-59).
+    pub second: u8,
+    /// The minutes (0-59).
+    pub minute: u8,
+    /// The hours, in the 24-hour format (0-23).
+    pub hour: u8,
+    /// The day of the month (1-31).
+    pub day: u8,
+    /// The month (1-12).
+    pub month: u8,
+    /// The year.
+    pub year: u16,
+}
+
+/// Converts a BCD value to binary.
+#[inline]
+pub fn bcd_to_binary(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Converts a binary value (0-99) to BCD.
+#[inline]
+pub fn binary_to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Reads the current time from the RTC, whatever the format of its registers.
+///
+/// # Remarks
+///
+/// The century register is not standard, so the year is assumed to be in the 2000s.
+pub fn read_time() -> RtcTime {
+    // Reading the same values twice ensures that no update happened in between.
+    let mut raw = read_raw_time();
+    loop {
+        let again = read_raw_time();
+        if again == raw {
+            break;
+        }
+        raw = again;
+    }
+
+    let status = read(STATUS_B);
+    let decode = |value: u8| {
+        if status & BINARY_MODE != 0 {
+            value
+        } else {
+            bcd_to_binary(value)
+        }
+    };
+
+    let mut hour = decode(raw[2] & !PM);
+    if status & HOUR_24_MODE == 0 {
+        hour %= 12;
+        if raw[2] & PM != 0 {
+            hour += 12;
+        }
+    }
+
+    RtcTime {
+        second: decode(raw[0]),
+        minute: decode(raw[1]),
+        hour,
+        day: decode(raw[3]),
+        month: decode(raw[4]),
+        year: 2000 + decode(raw[5]) as u16,
+    }
+}
+
+/// Sets the time of the RTC, in the format its registers use.
+///
+/// Only the last two digits of the year are stored. Interrupts are disabled while the
+/// registers are written, and the RTC does not update them in the meantime.
+pub fn write_time(time: RtcTime) {
+    let _without_interrupts = RestoreInterrupts::without_interrupts();
+
+    let status = read(STATUS_B);
+    let encode = |value: u8| {
+        if status & BINARY_MODE != 0 {
+            value
+        } else {
+            binary_to_bcd(value)
+        }
+    };
+
+    let hour = if status & HOUR_24_MODE != 0 {
+        encode(time.hour)
+    } else {
+        let pm = if time.hour >= 12 { PM } else { 0 };
+        match time.hour % 12 {
+            0 => encode(12) | pm,
+            hour => encode(hour) | pm,
+        }
+    };
+
+    write(STATUS_B, status | SET);
+    write(SECONDS, encode(time.second));
+    write(MINUTES, encode(time.minute));
+    write(HOURS, hour);
+    write(DAY, encode(time.day));
+    write(MONTH, encode(time.month));
+    write(YEAR, encode((time.year % 100) as u8));
+    write(STATUS_B, status & !SET);
+}
@@ -1,54 +1,71 @@
-//! A simple serial I/O driver.
+//! A driver for the serial ports (UARTs compatible with the 16550).
+//!
+//! The first serial port, COM1, is where the kernel logs its messages. The other ones are
+//! detected at boot and registered as devices, for the uses that need a dedicated line.
+
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::{Acquire, Release};
 
 use bitflags::bitflags;
 
+use crate::device::{self, Device, DeviceClass, DeviceOps};
+use crate::error::KernelError;
+use crate::fs::FsError;
+use crate::log;
 use crate::utility::instr::{inb, outb, pause};
+use crate::utility::Mutex;
 
-/// Base address of the COM1 serial port used in this module for logging.
-const PORT: u16 = 0x3F8;
+/// The offset of the data register, through which bytes are sent and received.
+///
+/// When the DLAB is set, this is the low byte of the baud-rate divisor instead.
+const DATA: u16 = 0;
 
-/// The register responsible for requesting the serial port to operate in interrupt (or polling)
-/// mode.
+/// The offset of the register responsible for requesting the serial port to operate in
+/// interrupt (or polling) mode.
+///
+/// When the DLAB is set, this is the high byte of the baud-rate divisor instead.
 ///
 /// See the [OSDev Wiki](https://wiki.osdev.org/Serial_Ports#Interrupt_enable_register).
-const INTERRUPT_ENABLE: u16 = PORT + 1;
+const INTERRUPT_ENABLE: u16 = 1;
+
+/// The offset of the FIFO-control register.
+const FIFO_CONTROL: u16 = 2;
 
-/// The line-control register.
+/// The offset of the line-control register.
 ///
 /// This is used to configure the protocol of the serial port.
-const LINE_CONTROL: u16 = PORT + 3;
+const LINE_CONTROL: u16 = 3;
 
-/// The model-control register.
+/// The offset of the modem-control register.
 ///
 /// This is used to configure how the serial port is used.
-const MODEM_CONTROL: u16 = PORT + 4;
+const MODEM_CONTROL: u16 = 4;
 
-/// The line-status register.
+/// The offset of the line-status register.
 ///
 /// This is used to determine whether the serial port is ready to send more data, among
 /// other things.
-const LINE_STATUS: u16 = PORT + 5;
+const LINE_STATUS: u16 = 5;
+
+/// The offset of the scratch register, which the UART does not use but keeps what is written
+/// to it.
+const SCRATCH: u16 = 7;
 
 /// The bit responsible for enabling the DLAB (Divisor Latch Access Bit) in the line-control
 /// register.
 const DLAB: u8 = 0x80;
 
-/// The parity bits in the line-control register that indicate that no parity bit should be used
-/// in the protocol.
-const PARITY_NONE: u8 = 0x00;
-
 /// The bits in the line-control register that indicate that the serial port should use 8-bit
 /// of data.
 const DATA_LENGTH_8BITS: u8 = 0x03;
 
-/// The bits in the line-control register that indicate that the serial port should use 1 stop
-/// bit.
-const STOP_BIT_1: u8 = 0x00;
+/// The bit in the line-control register that indicates that the serial port should use 2 stop
+/// bits instead of 1.
+const STOP_BITS_2: u8 = 0x04;
 
-/// A good default value for the line-control register. Basically every single emulator ever
-/// uses those settings, which increases the chances of being able to use the serial port
-/// without too much hassle.
-const DEFAULT_LINE_CONTROL: u8 = PARITY_NONE | DATA_LENGTH_8BITS | STOP_BIT_1;
+/// Enables the FIFO buffers and clears them, with a 14-byte threshold, when written to the
+/// FIFO-control register.
+const FIFO_ENABLE_14: u8 = 0xC7;
 
 /// Controls the DTR pin when set on the modem-control register.
 const DATA_TERMINAL_READY: u8 = 0x01;
@@ -56,76 +73,415 @@ const DATA_TERMINAL_READY: u8 = 0x01;
 /// Controls the RTS pin when set on the modem-control register.
 const REQUEST_TO_SEND: u8 = 0x02;
 
-/// Initializes the serial port driver.
-pub fn init() {
-    // The following is adapted from the OSDev Wiki (this has to be the most copy-pasted code
-    // of the whole wiki lol).
-    //
-    //     https://wiki.osdev.org/Serial_Ports#Initialization
-    //     https://en.wikipedia.org/wiki/Serial_port
-    //
+/// Controls the OUT1 pin when set on the modem-control register.
+const OUT1: u8 = 0x04;
+
+/// Controls the OUT2 pin when set on the modem-control register.
+const OUT2: u8 = 0x08;
 
-    // Make sure that the serial port won't attempt to send interrupts to the CPU. If we need
-    // to determine whether the serial port is ready to send data, we will poll it instead.
-    disable_interrupts();
+/// Makes the serial port receive the bytes it sends when set on the modem-control register.
+const LOOPBACK: u8 = 0x10;
+
+/// The byte sent through the serial port in loopback mode to check that it works.
+const LOOPBACK_PROBE: u8 = 0xAE;
+
+/// The bytes written to the scratch register to check that a UART is there.
+const SCRATCH_PROBES: [u8; 2] = [0x55, 0xAA];
+
+/// The frequency at which the UART runs, divided by 16: the baud rate reached with a divisor
+/// of 1.
+const MAX_BAUD_RATE: u32 = 115_200;
+
+/// The parity bit sent after the data bits of each character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    /// No parity bit.
+    None,
+    /// The parity bit makes the number of set bits odd.
+    Odd,
+    /// The parity bit makes the number of set bits even.
+    Even,
+}
+
+impl Parity {
+    /// Returns the bits of the line-control register that select the parity.
+    fn line_control(self) -> u8 {
+        match self {
+            Self::None => 0x00,
+            Self::Odd => 0x08,
+            Self::Even => 0x18,
+        }
+    }
 
-    // Set the baud rate divisor to 3 (for a total of 38400 bauds).
-    // This is generally a good default for the use-case of simply logging messages.
-    set_baud_rate_divisor(3);
+    /// Returns the parity named `name` (`none`, `odd` or `even`), if any.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"none" => Some(Self::None),
+            b"odd" => Some(Self::Odd),
+            b"even" => Some(Self::Even),
+            _ => None,
+        }
+    }
+}
 
-    // Configure the serial port to use the default settings.
-    set_default_line_control();
+/// The protocol of a serial port. Characters always have 8 data bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartConfig {
+    /// The number of bits sent per second.
+    ///
+    /// It must divide 115200.
+    pub baud_rate: u32,
+    /// The parity bit.
+    pub parity: Parity,
+    /// Whether two stop bits are sent instead of one.
+    pub two_stop_bits: bool,
+}
 
-    // Enable the FIFO buffer of the serial port, with a 14-byte threshold.
-    enable_fifo();
+impl UartConfig {
+    /// A good default configuration: 38400 bauds, 8 data bits, no parity and 1 stop bit.
+    /// Basically every single emulator ever uses those settings, which increases the chances of
+    /// being able to use the serial port without too much hassle.
+    pub const DEFAULT: Self = Self {
+        baud_rate: 38400,
+        parity: Parity::None,
+        two_stop_bits: false,
+    };
+
+    /// Returns the baud-rate divisor of the configuration, if its baud rate can be reached.
+    fn divisor(&self) -> Option<u16> {
+        if self.baud_rate == 0 || MAX_BAUD_RATE % self.baud_rate != 0 {
+            return None;
+        }
+        u16::try_from(MAX_BAUD_RATE / self.baud_rate).ok()
+    }
+}
 
-    // Finish the handshake with the serial port by writing the `DATA_TERMINAL_READY` and
-    // `REQUEST_TO_SEND` bits to the modem-control register.
-    // This is needed to actually enable the serial port.
-    finish_handshake();
+/// A UART compatible with the 16550, identified by the base of its I/O ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uart {
+    /// The first I/O port of the UART.
+    base: u16,
+}
+
+impl Uart {
+    /// The first serial port, used for logging.
+    pub const COM1: Self = Self::new(0x3F8);
+    /// The second serial port.
+    pub const COM2: Self = Self::new(0x2F8);
+    /// The third serial port.
+    pub const COM3: Self = Self::new(0x3E8);
+    /// The fourth serial port.
+    pub const COM4: Self = Self::new(0x2E8);
+
+    /// The standard serial ports, in order.
+    pub const ALL: [Self; 4] = [Self::COM1, Self::COM2, Self::COM3, Self::COM4];
+
+    /// Creates a new [`Uart`] whose I/O ports start at `base`.
+    #[inline]
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    /// Returns the first I/O port of the UART.
+    #[inline]
+    pub fn base(self) -> u16 {
+        self.base
+    }
+
+    /// Reads the register at `offset`.
+    #[inline]
+    fn read(self, offset: u16) -> u8 {
+        unsafe { inb(self.base + offset) }
+    }
+
+    /// Writes the register at `offset`.
+    #[inline]
+    fn write(self, offset: u16, value: u8) {
+        unsafe { outb(self.base + offset, value) }
+    }
+
+    /// Returns whether a UART seems to be there, which is the case when its scratch register
+    /// keeps what is written to it.
+    pub fn detect(self) -> bool {
+        SCRATCH_PROBES.into_iter().all(|probe| {
+            self.write(SCRATCH, probe);
+            self.read(SCRATCH) == probe
+        })
+    }
+
+    /// Configures the UART to use the protocol `config`, and checks that it works.
+    ///
+    /// Fails with [`KernelError::InvalidArgument`] if the baud rate cannot be reached, and with
+    /// [`KernelError::NoDevice`] if the UART does not receive what it sends in loopback mode.
+    pub fn configure(self, config: UartConfig) -> Result<(), KernelError> {
+        // The following is adapted from the OSDev Wiki (this has to be the most copy-pasted
+        // code of the whole wiki lol).
+        //
+        //     https://wiki.osdev.org/Serial_Ports#Initialization
+        //     https://en.wikipedia.org/wiki/Serial_port
+        //
+        let divisor = config.divisor().ok_or(KernelError::InvalidArgument)?;
+
+        // Make sure that the serial port won't attempt to send interrupts to the CPU. If we
+        // need to determine whether the serial port is ready to send data, we will poll it
+        // instead.
+        self.write(INTERRUPT_ENABLE, 0x00);
+
+        // Set the baud-rate divisor. This clobbers the line-control register.
+        self.write(LINE_CONTROL, DLAB);
+        self.write(DATA, divisor as u8);
+        self.write(INTERRUPT_ENABLE, (divisor >> 8) as u8);
+
+        let mut line_control = DATA_LENGTH_8BITS | config.parity.line_control();
+        if config.two_stop_bits {
+            line_control |= STOP_BITS_2;
+        }
+        self.write(LINE_CONTROL, line_control);
+
+        self.write(FIFO_CONTROL, FIFO_ENABLE_14);
+
+        // Send a byte to ourselves to check that a serial port is actually there.
+        self.write(MODEM_CONTROL, LOOPBACK | OUT1 | OUT2 | REQUEST_TO_SEND);
+        self.write(DATA, LOOPBACK_PROBE);
+        if self.read(DATA) != LOOPBACK_PROBE {
+            return Err(KernelError::NoDevice);
+        }
+
+        // Finish the handshake with the serial port by writing the `DATA_TERMINAL_READY` and
+        // `REQUEST_TO_SEND` bits to the modem-control register.
+        // This is needed to actually enable the serial port.
+        self.write(MODEM_CONTROL, DATA_TERMINAL_READY | REQUEST_TO_SEND);
+        Ok(())
+    }
+
+    /// Returns the current status of the UART.
+    #[inline]
+    pub fn status(self) -> SerialStatus {
+        SerialStatus::from_bits_retain(self.read(LINE_STATUS))
+    }
+
+    /// Returns whether the UART is ready to send more data.
+    #[inline]
+    pub fn ready_to_send(self) -> bool {
+        self.status().intersects(SerialStatus::TRANSMITTER_EMPTY)
+    }
+
+    /// Writes a byte to the UART, eventually waiting for the transmitter to be ready to send
+    /// more data.
+    pub fn write_byte(self, byte: u8) {
+        while !self.ready_to_send() {
+            pause();
+        }
+        self.write(DATA, byte);
+    }
+
+    /// Writes the provided bytes through the UART.
+    pub fn write_bytes(self, bytes: &[u8]) {
+        bytes.iter().for_each(|&byte| self.write_byte(byte));
+    }
+
+    /// Returns the byte the UART received, if any.
+    pub fn read_byte(self) -> Option<u8> {
+        self.status()
+            .intersects(SerialStatus::DATA_READY)
+            .then(|| self.read(DATA))
+    }
+
+    /// Waits until all the data written to the UART has been sent.
+    pub fn flush(self) {
+        while !self.status().intersects(SerialStatus::TRANSMITTER_IDLE) {
+            pause();
+        }
+    }
+}
+
+impl DeviceOps for Uart {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut len = 0;
+        while let Some(slot) = buf.get_mut(len) {
+            match self.read_byte() {
+                Some(byte) => *slot = byte,
+                None => break,
+            }
+            len += 1;
+        }
+        match len {
+            0 if !buf.is_empty() => Err(FsError::WouldBlock),
+            len => Ok(len),
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        self.write_bytes(buf);
+        Ok(buf.len())
+    }
+}
+
+/// The names of the standard serial ports in the device registry.
+const NAMES: [&str; 4] = ["ttyS0", "ttyS1", "ttyS2", "ttyS3"];
+
+/// The standard serial ports, as referenced by the device registry.
+static PORTS: [Uart; 4] = Uart::ALL;
+
+/// Detects and configures the serial ports other than COM1, and registers those that work.
+///
+/// They use the [default configuration](UartConfig::DEFAULT).
+pub fn init_ports() {
+    for (i, uart) in PORTS.iter().enumerate().skip(1) {
+        if !uart.detect() {
+            continue;
+        }
+        match uart.configure(UartConfig::DEFAULT) {
+            Ok(()) => {
+                device::register(Device {
+                    name: NAMES[i],
+                    class: DeviceClass::Serial,
+                    ops: uart,
+                });
+            }
+            Err(err) => log!("COM{} is not working: {err}.\n", i + 1),
+        }
+    }
+}
+
+/// Returns the serial port registered as `name`, such as `ttyS1`.
+pub fn find(name: &[u8]) -> Option<Uart> {
+    NAMES
+        .iter()
+        .position(|n| n.as_bytes() == name)
+        .filter(|_| device::find(name).is_some())
+        .map(|i| PORTS[i])
+}
+
+/// The number of bytes of the messages logged before the serial port is initialized that are
+/// kept until it is.
+///
+/// When more is logged, the oldest bytes are lost.
+pub const EARLY_LOG_SIZE: usize = 4096;
+
+/// Whether [`init`] was called.
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// The messages logged before the serial port was initialized.
+static EARLY_LOG: Mutex<EarlyLog> = Mutex::new(EarlyLog {
+    bytes: [0; EARLY_LOG_SIZE],
+    start: 0,
+    len: 0,
+    lost: 0,
+});
+
+/// A ring buffer holding the messages logged before the serial port is initialized.
+struct EarlyLog {
+    /// The bytes of the messages.
+    bytes: [u8; EARLY_LOG_SIZE],
+    /// The index of the oldest byte.
+    start: usize,
+    /// The number of bytes in the buffer.
+    len: usize,
+    /// The number of bytes that were overwritten.
+    lost: usize,
+}
+
+impl core::fmt::Write for EarlyLog {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for &byte in s.as_bytes() {
+            self.bytes[(self.start + self.len) % EARLY_LOG_SIZE] = byte;
+            if self.len == EARLY_LOG_SIZE {
+                self.start = (self.start + 1) % EARLY_LOG_SIZE;
+                self.lost += 1;
+            } else {
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether the serial port was initialized.
+#[inline]
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Acquire)
+}
+
+/// Initializes the first serial port, which is used for logging.
+///
+/// The messages that were logged until then are sent through the serial port.
+///
+/// Fails with [`KernelError::NoDevice`] if no working serial port answers. The messages keep
+/// being buffered in that case.
+pub fn init() -> Result<(), KernelError> {
+    Uart::COM1.configure(UartConfig::DEFAULT)?;
+
+    let mut early = EARLY_LOG.lock();
+    INITIALIZED.store(true, Release);
+    if early.lost != 0 {
+        let _ = core::fmt::Write::write_fmt(
+            &mut Serial,
+            format_args!("[{} bytes of early log lost]\n", early.lost),
+        );
+    }
+    let (start, len) = (early.start, early.len);
+    let (tail, head) = early.bytes.split_at(start);
+    write_bytes(&head[..len.min(head.len())]);
+    write_bytes(&tail[..len.saturating_sub(head.len())]);
+    early.len = 0;
+    drop(early);
+
+    device::register(Device {
+        name: NAMES[0],
+        class: DeviceClass::Serial,
+        ops: &PORTS[0],
+    });
+    Ok(())
 }
 
 bitflags! {
     /// Defines the status bits for the serial port.
     #[derive(Clone, Copy, Debug)]
     pub struct SerialStatus: u8 {
+        /// Indicates that a byte was received and can be read.
+        const DATA_READY = 0x01;
         /// Indicates that the transmitter is not doing anything. When this bit is set,
         /// it's possible to write to the serial port without risking to lose data.
         const TRANSMITTER_EMPTY = 0x20;
+        /// Indicates that the transmitter is not doing anything *and* that all the data
+        /// written so far has actually been sent.
+        const TRANSMITTER_IDLE = 0x40;
     }
 }
 
-/// Returns the current status of the serial port.
+/// Returns the current status of the first serial port.
 #[inline]
 pub fn status() -> SerialStatus {
-    let raw = unsafe { inb(LINE_STATUS) };
-    SerialStatus::from_bits_retain(raw)
+    Uart::COM1.status()
 }
 
-/// Returns whether the serial port is ready to send more data.
+/// Returns whether the first serial port is ready to send more data.
 #[inline]
 pub fn ready_to_send() -> bool {
-    status().intersects(SerialStatus::TRANSMITTER_EMPTY)
+    Uart::COM1.ready_to_send()
 }
 
-/// Writes a byte to the serial port, eventually waiting for the transmitter to be ready
+/// Writes a byte to the first serial port, eventually waiting for the transmitter to be ready
 /// to send more data.
+#[inline]
 pub fn write_byte(byte: u8) {
-    while !ready_to_send() {
-        pause();
-    }
+    Uart::COM1.write_byte(byte);
+}
 
-    unsafe {
-        outb(PORT, byte);
-    }
+/// Waits until all the data written to the first serial port has been sent.
+#[inline]
+pub fn flush() {
+    Uart::COM1.flush();
 }
 
-/// Writes the provided bytes through the serial port.
+/// Writes the provided bytes through the first serial port.
+#[inline]
 pub fn write_bytes(bytes: &[u8]) {
-    bytes.iter().copied().for_each(write_byte);
+    Uart::COM1.write_bytes(bytes);
 }
 
-/// A simple struct that implements [`core::fmt::Write`].
+/// A simple struct that implements [`core::fmt::Write`] for the first serial port.
 #[derive(Debug, Clone, Copy)]
 pub struct Serial;
 
@@ -137,59 +493,16 @@ impl core::fmt::Write for Serial {
     }
 }
 
-/// Ensures that the serial port won't attempt to send interrupts to the CPU.
-fn disable_interrupts() {
-    unsafe {
-        outb(INTERRUPT_ENABLE, 0x00);
-    }
-}
-
-/// Sets the baud-rate divisor of the serial port.
-///
-/// # Remarks
-///
-/// This function clobbers the line-control register.
-fn set_baud_rate_divisor(divisor: u16) {
-    unsafe {
-        outb(LINE_CONTROL, DLAB);
-
-        // +0 is the low byte
-        // +1 is the high byte
-        outb(PORT, divisor as u8);
-        outb(PORT + 1, (divisor >> 8) as u8);
-    }
-}
-
-/// Configures the protocol of the serial port to use the default settings.
-fn set_default_line_control() {
-    unsafe {
-        outb(LINE_CONTROL, DEFAULT_LINE_CONTROL);
-    }
-}
-
-/// Enables the FIFO buffer of the serial port, with a 14-byte threshold.
-fn enable_fifo() {
-    // MISSING_DOC: Not sure where to find the documentation for this.
-    // This line is straight up copied from the OSDev Wiki, but I'm not sure
-    // where they got it from.
-
-    unsafe {
-        outb(MODEM_CONTROL, 0xC7);
-    }
-}
-
-/// Finish the handshake with the serial port by writing the `DATA_TERMINAL_READY` and
-/// `REQUEST_TO_SEND` bits to the modem-control register.
-fn finish_handshake() {
-    unsafe {
-        outb(MODEM_CONTROL, DATA_TERMINAL_READY | REQUEST_TO_SEND);
-    }
-}
-
 /// Only used in the log macro.
+///
+/// Messages logged before [`init`] is called are kept until it is.
 #[doc(hidden)]
 #[cfg(feature = "log_serial")]
 #[inline]
 pub fn __log(msg: core::fmt::Arguments) {
-    let _ = core::fmt::Write::write_fmt(&mut Serial, msg);
+    if is_initialized() {
+        let _ = core::fmt::Write::write_fmt(&mut Serial, msg);
+    } else {
+        let _ = core::fmt::Write::write_fmt(&mut *EARLY_LOG.lock(), msg);
+    }
 }
@@ -0,0 +1,146 @@
+//! A driver for the PC speaker.
+//!
+//! The speaker is driven by the channel 2 of the PIT, whose output is gated through the
+//! keyboard controller's port B.
+//!
+//! See the [OSDev Wiki](https://wiki.osdev.org/PC_Speaker).
+
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::drivers::pit;
+use crate::timer;
+use crate::utility::instr::{inb, outb, pause};
+
+/// The port B of the keyboard controller, which controls the PC speaker.
+const PORT_B: u16 = 0x61;
+
+/// The bit of port B that connects the channel 2 of the PIT to its clock.
+const TIMER_2_GATE: u8 = 1 << 0;
+/// The bit of port B that connects the output of the channel 2 of the PIT to the speaker.
+const SPEAKER_DATA: u8 = 1 << 1;
+/// The bit of port B that reflects the current output of the channel 2 of the PIT.
+const TIMER_2_OUTPUT: u8 = 1 << 5;
+
+/// The maximum number of times the output of the PIT is polled before giving up on waiting for
+/// it to change.
+const POLL_ATTEMPTS: u32 = 1_000_000;
+
+/// The frequency of the channel 2 of the PIT while [`wait_silently`] counts its periods.
+const SILENT_FREQ: u32 = 1000;
+
+/// The timer tick at which the tone started by the last call to [`beep`] ends.
+static STOP_AT: AtomicU32 = AtomicU32::new(0);
+
+/// A note of a chime: a frequency in hertz and a duration in milliseconds.
+pub type Note = (u32, u32);
+
+/// The chime played once the kernel has finished booting.
+pub const BOOT_CHIME: &[Note] = &[(660, 60), (880, 90)];
+
+/// The chime played when the kernel panics.
+pub const PANIC_CHIME: &[Note] = &[(440, 150), (220, 300)];
+
+/// Starts playing a tone of the provided frequency, in hertz.
+pub fn play(freq: u32) {
+    pit::set_channel_2_frequency(freq);
+
+    unsafe {
+        let value = inb(PORT_B);
+        outb(PORT_B, value | TIMER_2_GATE | SPEAKER_DATA);
+    }
+}
+
+/// Stops the speaker.
+pub fn stop() {
+    unsafe {
+        let value = inb(PORT_B);
+        outb(PORT_B, value & !(TIMER_2_GATE | SPEAKER_DATA));
+    }
+}
+
+/// Plays a tone of the provided frequency for `duration_ms` milliseconds.
+///
+/// This function returns immediately: the speaker is stopped by the timer interrupt. If no
+/// timer callback can be registered, the tone is played synchronously instead.
+///
+/// A tone that replaces another one before it ended is not cut short by the callback of the
+/// previous one.
+pub fn beep(freq: u32, duration_ms: u32) {
+    let stop_at = timer::now().wrapping_add(timer::ms_to_ticks(duration_ms));
+    STOP_AT.store(stop_at, Relaxed);
+    play(freq);
+
+    if timer::register_once(duration_ms, stop_if_due).is_none() {
+        let _ = wait_periods(freq, duration_ms);
+        stop();
+    }
+}
+
+/// The callback registered by [`beep`]: stops the speaker unless a later tone is still
+/// playing.
+fn stop_if_due() {
+    // The tick count may wrap around.
+    if timer::now().wrapping_sub(STOP_AT.load(Relaxed)) as i32 >= 0 {
+        stop();
+    }
+}
+
+/// Waits for `duration_ms` milliseconds by counting the periods of the channel 2 of the PIT,
+/// without connecting it to the speaker.
+///
+/// Returns whether the output of the channel kept changing, which means that the wait was
+/// timed. This replaces the tone that was playing, if any.
+pub fn wait_silently(duration_ms: u32) -> bool {
+    pit::set_channel_2_frequency(SILENT_FREQ);
+
+    unsafe {
+        let value = inb(PORT_B);
+        outb(PORT_B, (value | TIMER_2_GATE) & !SPEAKER_DATA);
+    }
+    let waited = wait_periods(SILENT_FREQ, duration_ms);
+    stop();
+    waited
+}
+
+/// Plays the provided notes, one after the other.
+///
+/// This function does not rely on interrupts, making it suitable for use when they are
+/// disabled (for example, when the kernel panics).
+pub fn chime(notes: &[Note]) {
+    for &(freq, duration_ms) in notes {
+        play(freq);
+        let _ = wait_periods(freq, duration_ms);
+    }
+    stop();
+}
+
+/// Waits for `duration_ms` milliseconds by counting the periods of the square wave currently
+/// generated at `freq` hertz by the channel 2 of the PIT.
+///
+/// Returns `false` if the output stopped changing before the end.
+fn wait_periods(freq: u32, duration_ms: u32) -> bool {
+    let periods = freq as u64 * duration_ms as u64 / 1000;
+
+    for _ in 0..periods {
+        // Wait for a rising edge of the output.
+        if !wait_output(false) || !wait_output(true) {
+            // The output does not seem to change. There is no point in waiting forever.
+            return false;
+        }
+    }
+    true
+}
+
+/// Waits until the output of the channel 2 of the PIT is `high`.
+///
+/// Returns `false` if the output did not reach the requested state in time.
+fn wait_output(high: bool) -> bool {
+    for _ in 0..POLL_ATTEMPTS {
+        if (unsafe { inb(PORT_B) } & TIMER_2_OUTPUT != 0) == high {
+            return true;
+        }
+        pause();
+    }
+    false
+}
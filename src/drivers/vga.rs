@@ -30,10 +30,9 @@ impl VgaBuffer {
     #[inline]
     pub unsafe fn putc_unchecked(&mut self, c: VgaChar, x: u32, y: u32, fg: Color, bg: Color) {
         let offset = y * WIDTH + x;
-        let value = (c.as_u8() as u16) | ((bg as u16) << 12) | ((fg as u16) << 8);
 
         unsafe {
-            *ADDRESS.add(offset as usize) = value;
+            *ADDRESS.add(offset as usize) = cell(c, fg, bg);
         }
     }
 
@@ -51,6 +50,21 @@ impl VgaBuffer {
         }
     }
 
+    /// Copies a run of cells to the VGA buffer, starting at the provided coordinates.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the cells do not fit on the row `y`.
+    #[inline]
+    pub fn write_row(&mut self, x: u32, y: u32, cells: &[u16]) {
+        assert!(y < HEIGHT && x as usize + cells.len() <= WIDTH as usize);
+
+        unsafe {
+            let dst = ADDRESS.add((y * WIDTH + x) as usize);
+            core::ptr::copy_nonoverlapping(cells.as_ptr(), dst, cells.len());
+        }
+    }
+
     /// Returns a shared slice reference over the underlying buffer.
     #[inline(always)]
     pub fn buffer(&self) -> &[u16] {
@@ -64,6 +78,12 @@ impl VgaBuffer {
     }
 }
 
+/// Returns the value of a cell of the VGA buffer that displays `c` with the provided colors.
+#[inline(always)]
+pub fn cell(c: VgaChar, fg: Color, bg: Color) -> u16 {
+    (c.as_u8() as u16) | ((bg as u16) << 12) | ((fg as u16) << 8)
+}
+
 /// The width of the VGA buffer.
 pub const WIDTH: u32 = 80;
 /// The height of the VGA buffer.
@@ -100,6 +120,15 @@ impl Color {
     pub fn iter_all() -> impl Iterator<Item = Self> {
         (0u8..=15u8).map(|i| unsafe { core::mem::transmute(i) })
     }
+
+    /// Returns the index of the closest color in the ANSI palette.
+    ///
+    /// Indices 0 to 7 are the regular colors, and 8 to 15 are their bright variants.
+    pub fn ansi_index(self) -> u8 {
+        // The VGA palette swaps the red and blue bits compared to the ANSI palette.
+        let c = self as u8;
+        (c & 0b1010) | (c & 0b0001) << 2 | (c & 0b0100) >> 2
+    }
 }
 
 /// Updates the appearance of the cursor.
@@ -160,6 +189,14 @@ impl VgaChar {
         self.0.get()
     }
 
+    /// Creates a [`VgaChar`] from its byte value.
+    ///
+    /// Returns `None` for the null byte, which is not a valid character.
+    #[inline(always)]
+    pub fn from_u8(byte: u8) -> Option<Self> {
+        NonZeroU8::new(byte).map(Self)
+    }
+
     /// Returns an iterator over all available characters.
     #[inline]
     pub fn iter_all() -> impl Iterator<Item = Self> {
@@ -0,0 +1,268 @@
+//! The errors that the subsystems of the kernel report.
+//!
+//! Every subsystem has its own error type, which tells precisely what went wrong. They all
+//! convert into a [`KernelError`], so that errors can be propagated across subsystems with `?`.
+//! At the system call boundary, a [`KernelError`] becomes the error code returned to the user
+//! program, which is the same as on Linux.
+
+use core::fmt::{self, Display};
+
+use crate::cpu::paging::MappingError;
+use crate::fs::FsError;
+use crate::mqueue::QueueError;
+use crate::reaper::WaitError;
+use crate::sched::SpawnError;
+use crate::state::OutOfMemory;
+use crate::utility::CantLock;
+
+/// An error that might occur anywhere in the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelError {
+    /// The operation is not permitted.
+    NotPermitted,
+    /// The requested file or object does not exist.
+    NotFound,
+    /// The operation was interrupted.
+    Interrupted,
+    /// The process has no child to wait for.
+    NoChild,
+    /// The operation would block, or a resource is temporarily exhausted.
+    WouldBlock,
+    /// There is not enough memory.
+    OutOfMemory,
+    /// An address is invalid.
+    BadAddress,
+    /// The resource is being used.
+    Busy,
+    /// The file or object already exists.
+    AlreadyExists,
+    /// The device is not there, or does not work.
+    NoDevice,
+    /// A component of a path is not a directory.
+    NotADirectory,
+    /// The operation cannot be performed on a directory.
+    IsADirectory,
+    /// An argument is invalid.
+    InvalidArgument,
+    /// There is no space left to create a file or object.
+    NoSpace,
+    /// A name is too long.
+    NameTooLong,
+    /// The operation is not implemented.
+    NotImplemented,
+    /// A message is too long.
+    MessageTooLong,
+    /// The operation is not supported by the object.
+    Unsupported,
+    /// The address is already used by another socket.
+    AddressInUse,
+    /// No route leads to the destination.
+    NetworkUnreachable,
+    /// The connection was reset by the peer.
+    ConnectionReset,
+    /// The socket is not connected.
+    NotConnected,
+    /// The operation timed out.
+    TimedOut,
+    /// The peer refused the connection.
+    ConnectionRefused,
+}
+
+impl KernelError {
+    /// Every error.
+    pub const ALL: [Self; 24] = [
+        Self::NotPermitted,
+        Self::NotFound,
+        Self::Interrupted,
+        Self::NoChild,
+        Self::WouldBlock,
+        Self::OutOfMemory,
+        Self::BadAddress,
+        Self::Busy,
+        Self::AlreadyExists,
+        Self::NoDevice,
+        Self::NotADirectory,
+        Self::IsADirectory,
+        Self::InvalidArgument,
+        Self::NoSpace,
+        Self::NameTooLong,
+        Self::NotImplemented,
+        Self::MessageTooLong,
+        Self::Unsupported,
+        Self::AddressInUse,
+        Self::NetworkUnreachable,
+        Self::ConnectionReset,
+        Self::NotConnected,
+        Self::TimedOut,
+        Self::ConnectionRefused,
+    ];
+
+    /// Returns the error code of the error, as on Linux.
+    pub fn errno(self) -> isize {
+        match self {
+            Self::NotPermitted => 1,
+            Self::NotFound => 2,
+            Self::Interrupted => 4,
+            Self::NoChild => 10,
+            Self::WouldBlock => 11,
+            Self::OutOfMemory => 12,
+            Self::BadAddress => 14,
+            Self::Busy => 16,
+            Self::AlreadyExists => 17,
+            Self::NoDevice => 19,
+            Self::NotADirectory => 20,
+            Self::IsADirectory => 21,
+            Self::InvalidArgument => 22,
+            Self::NoSpace => 28,
+            Self::NameTooLong => 36,
+            Self::NotImplemented => 38,
+            Self::MessageTooLong => 90,
+            Self::Unsupported => 95,
+            Self::AddressInUse => 98,
+            Self::NetworkUnreachable => 101,
+            Self::ConnectionReset => 104,
+            Self::NotConnected => 107,
+            Self::TimedOut => 110,
+            Self::ConnectionRefused => 111,
+        }
+    }
+
+    /// Returns the error whose code is `errno`, if any.
+    pub fn from_errno(errno: isize) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.errno() == errno)
+    }
+
+    /// Returns the name of the error code, such as `ENOMEM`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NotPermitted => "EPERM",
+            Self::NotFound => "ENOENT",
+            Self::Interrupted => "EINTR",
+            Self::NoChild => "ECHILD",
+            Self::WouldBlock => "EAGAIN",
+            Self::OutOfMemory => "ENOMEM",
+            Self::BadAddress => "EFAULT",
+            Self::Busy => "EBUSY",
+            Self::AlreadyExists => "EEXIST",
+            Self::NoDevice => "ENODEV",
+            Self::NotADirectory => "ENOTDIR",
+            Self::IsADirectory => "EISDIR",
+            Self::InvalidArgument => "EINVAL",
+            Self::NoSpace => "ENOSPC",
+            Self::NameTooLong => "ENAMETOOLONG",
+            Self::NotImplemented => "ENOSYS",
+            Self::MessageTooLong => "EMSGSIZE",
+            Self::Unsupported => "EOPNOTSUPP",
+            Self::AddressInUse => "EADDRINUSE",
+            Self::NetworkUnreachable => "ENETUNREACH",
+            Self::ConnectionReset => "ECONNRESET",
+            Self::NotConnected => "ENOTCONN",
+            Self::TimedOut => "ETIMEDOUT",
+            Self::ConnectionRefused => "ECONNREFUSED",
+        }
+    }
+}
+
+impl Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NotPermitted => "operation not permitted",
+            Self::NotFound => "no such file or directory",
+            Self::Interrupted => "interrupted",
+            Self::NoChild => "no child processes",
+            Self::WouldBlock => "resource temporarily unavailable",
+            Self::OutOfMemory => "out of memory",
+            Self::BadAddress => "bad address",
+            Self::Busy => "device or resource busy",
+            Self::AlreadyExists => "file exists",
+            Self::NoDevice => "no such device",
+            Self::NotADirectory => "not a directory",
+            Self::IsADirectory => "is a directory",
+            Self::InvalidArgument => "invalid argument",
+            Self::NoSpace => "no space left on device",
+            Self::NameTooLong => "name too long",
+            Self::NotImplemented => "function not implemented",
+            Self::MessageTooLong => "message too long",
+            Self::Unsupported => "operation not supported",
+            Self::AddressInUse => "address already in use",
+            Self::NetworkUnreachable => "network is unreachable",
+            Self::ConnectionReset => "connection reset by peer",
+            Self::NotConnected => "not connected",
+            Self::TimedOut => "timed out",
+            Self::ConnectionRefused => "connection refused",
+        })
+    }
+}
+
+impl From<OutOfMemory> for KernelError {
+    #[inline(always)]
+    fn from(_value: OutOfMemory) -> Self {
+        Self::OutOfMemory
+    }
+}
+
+impl From<CantLock> for KernelError {
+    #[inline(always)]
+    fn from(_value: CantLock) -> Self {
+        Self::Busy
+    }
+}
+
+impl From<MappingError> for KernelError {
+    fn from(value: MappingError) -> Self {
+        match value {
+            MappingError::OutOfMemory | MappingError::TooManyAreas => Self::OutOfMemory,
+            MappingError::AlreadyMapped | MappingError::InvalidRange => Self::InvalidArgument,
+        }
+    }
+}
+
+impl From<FsError> for KernelError {
+    fn from(value: FsError) -> Self {
+        match value {
+            FsError::NotFound => Self::NotFound,
+            FsError::NotADirectory => Self::NotADirectory,
+            FsError::IsADirectory => Self::IsADirectory,
+            FsError::AlreadyExists => Self::AlreadyExists,
+            FsError::NameTooLong => Self::NameTooLong,
+            FsError::NoSpace => Self::NoSpace,
+            FsError::Unsupported => Self::Unsupported,
+            FsError::WouldBlock => Self::WouldBlock,
+            FsError::Busy => Self::Busy,
+        }
+    }
+}
+
+impl From<QueueError> for KernelError {
+    fn from(value: QueueError) -> Self {
+        match value {
+            QueueError::NotFound => Self::NotFound,
+            QueueError::AlreadyExists => Self::AlreadyExists,
+            QueueError::NameTooLong => Self::NameTooLong,
+            QueueError::NoSpace => Self::NoSpace,
+            QueueError::InvalidCapacity => Self::InvalidArgument,
+            QueueError::MessageTooLong => Self::MessageTooLong,
+            QueueError::WouldBlock => Self::WouldBlock,
+            QueueError::TimedOut => Self::TimedOut,
+        }
+    }
+}
+
+impl From<SpawnError> for KernelError {
+    fn from(value: SpawnError) -> Self {
+        match value {
+            SpawnError::TooManyThreads => Self::WouldBlock,
+            SpawnError::OutOfMemory => Self::OutOfMemory,
+        }
+    }
+}
+
+impl From<WaitError> for KernelError {
+    fn from(value: WaitError) -> Self {
+        match value {
+            WaitError::NoChild => Self::NoChild,
+            WaitError::WouldBlock => Self::WouldBlock,
+            WaitError::Interrupted => Self::Interrupted,
+        }
+    }
+}
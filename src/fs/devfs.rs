@@ -0,0 +1,148 @@
+//! A file-system that exposes the devices of the system as files.
+
+use crate::device::{self, Device, DeviceClass, DeviceOps};
+use crate::{log, rng, TERMINAL};
+
+use super::{FileSystem, FsError, Metadata, NodeId, NodeKind};
+
+/// Discards what is written to it, and is always at its end.
+struct Null;
+
+impl DeviceOps for Null {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        Ok(buf.len())
+    }
+}
+
+/// Discards what is written to it, and reads as zeros.
+struct Zero;
+
+impl DeviceOps for Zero {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        Ok(buf.len())
+    }
+}
+
+/// Reads as pseudo-random bytes. What is written to it is mixed into its state.
+struct Random;
+
+impl DeviceOps for Random {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        rng::fill_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        rng::add_entropy(buf);
+        Ok(buf.len())
+    }
+}
+
+/// The terminal: what is written is displayed, and the input of its [`Tty`] is read.
+///
+/// [`Tty`]: crate::terminal::Tty
+struct Console;
+
+impl DeviceOps for Console {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        // The terminal is locked while the output of a command is redirected.
+        match TERMINAL.try_lock() {
+            Ok(mut term) => term.tty_mut().read(buf).ok_or(FsError::WouldBlock),
+            Err(_) => Err(FsError::Busy),
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        let Ok(mut term) = TERMINAL.try_lock() else {
+            return Err(FsError::Busy);
+        };
+        term.write_bytes(buf);
+        Ok(buf.len())
+    }
+}
+
+/// Registers the devices that are not backed by hardware, and the terminal.
+pub fn register_devices() {
+    let devices: [(&str, DeviceClass, &'static dyn DeviceOps); 5] = [
+        ("null", DeviceClass::Memory, &Null),
+        ("zero", DeviceClass::Memory, &Zero),
+        ("random", DeviceClass::Memory, &Random),
+        ("console", DeviceClass::Console, &Console),
+        ("tty", DeviceClass::Console, &Console),
+    ];
+    for (name, class, ops) in devices {
+        if !device::register(Device { name, class, ops }) {
+            log!("Failed to register /dev/{name}.\n");
+        }
+    }
+}
+
+/// A file-system that contains one node per registered device, meant to be mounted at `/dev`.
+///
+/// The root directory has ID 0. The ID of a device is its index in the registry, plus one.
+pub struct DevFs;
+
+impl DevFs {
+    /// Returns the device `node`.
+    fn device(node: NodeId) -> Result<Device, FsError> {
+        match node.0 {
+            0 => Err(FsError::IsADirectory),
+            id => device::get(id as usize - 1).ok_or(FsError::NotFound),
+        }
+    }
+}
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    fn lookup(&self, dir: NodeId, name: &[u8]) -> Result<NodeId, FsError> {
+        if dir.0 != 0 {
+            return Err(FsError::NotADirectory);
+        }
+
+        match name {
+            b".." => Ok(dir),
+            _ => device::find(name)
+                .map(|i| NodeId(i as u32 + 1))
+                .ok_or(FsError::NotFound),
+        }
+    }
+
+    fn metadata(&self, node: NodeId) -> Result<Metadata, FsError> {
+        let kind = match Self::device(node) {
+            Ok(_) => NodeKind::Device,
+            Err(FsError::IsADirectory) => NodeKind::Directory,
+            Err(err) => return Err(err),
+        };
+
+        Ok(Metadata { kind, size: 0 })
+    }
+
+    fn read(&self, node: NodeId, _offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        Self::device(node)?.ops.read(buf)
+    }
+
+    fn write(&self, node: NodeId, _offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        Self::device(node)?.ops.write(buf)
+    }
+
+    fn truncate(&self, node: NodeId, _len: usize) -> Result<(), FsError> {
+        // Devices have no size, but they may be opened like files that are overwritten.
+        Self::device(node).map(|_| ())
+    }
+}
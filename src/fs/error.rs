@@ -0,0 +1,40 @@
+use core::fmt::Display;
+
+/// An error that might occur while manipulating the file-system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// The requested file or directory does not exist.
+    NotFound,
+    /// A component of the path is not a directory.
+    NotADirectory,
+    /// The operation cannot be performed on a directory.
+    IsADirectory,
+    /// A file with the same name already exists.
+    AlreadyExists,
+    /// A path or a file name is too long.
+    NameTooLong,
+    /// The file-system has no more space available.
+    NoSpace,
+    /// The operation is not supported by the file-system.
+    Unsupported,
+    /// The device has no data available yet.
+    WouldBlock,
+    /// The device is being used by the kernel.
+    Busy,
+}
+
+impl Display for FsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::NotFound => "no such file or directory",
+            Self::NotADirectory => "not a directory",
+            Self::IsADirectory => "is a directory",
+            Self::AlreadyExists => "file exists",
+            Self::NameTooLong => "name too long",
+            Self::NoSpace => "no space left on device",
+            Self::Unsupported => "operation not supported",
+            Self::WouldBlock => "resource temporarily unavailable",
+            Self::Busy => "device or resource busy",
+        })
+    }
+}
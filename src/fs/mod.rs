@@ -0,0 +1,320 @@
+//! The virtual file-system of the kernel.
+//!
+//! File-systems implement the [`FileSystem`] trait and are mounted at a specific path. When a
+//! path is looked up, the mount point with the longest matching prefix is selected, and the rest
+//! of the path is resolved by the mounted file-system itself.
+
+#[cfg(feature = "fs")]
+mod devfs;
+#[cfg(feature = "fs")]
+mod procfs;
+#[cfg(feature = "fs")]
+mod ramfs;
+
+mod error;
+
+pub mod path;
+
+use crate::log;
+use crate::utility::{ArrayVec, Mutex};
+
+#[cfg(feature = "fs")]
+pub use self::devfs::*;
+#[cfg(feature = "fs")]
+pub use self::procfs::*;
+#[cfg(feature = "fs")]
+pub use self::ramfs::*;
+
+pub use self::error::*;
+
+use self::path::PathBuf;
+
+/// The ID of a node within a file-system.
+///
+/// Its meaning is only known to the file-system that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeId(pub u32);
+
+/// The kind of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// A device, which has no size and whose content is produced as it is read.
+    Device,
+}
+
+/// Information about a node.
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    /// The kind of the node.
+    pub kind: NodeKind,
+    /// The size of the node, in bytes.
+    pub size: usize,
+}
+
+/// A file-system that can be mounted in the virtual file-system.
+pub trait FileSystem: Sync {
+    /// Returns the name of the file-system.
+    fn name(&self) -> &'static str;
+
+    /// Returns the ID of the root directory of the file-system.
+    fn root(&self) -> NodeId;
+
+    /// Looks up the entry named `name` in the directory `dir`.
+    fn lookup(&self, dir: NodeId, name: &[u8]) -> Result<NodeId, FsError>;
+
+    /// Returns information about the provided node.
+    fn metadata(&self, node: NodeId) -> Result<Metadata, FsError>;
+
+    /// Creates a new node named `name` in the directory `dir`.
+    fn create(&self, dir: NodeId, name: &[u8], kind: NodeKind) -> Result<NodeId, FsError> {
+        let _ = (dir, name, kind);
+        Err(FsError::Unsupported)
+    }
+
+    /// Reads the content of the file `node`, starting at `offset`.
+    ///
+    /// Returns the number of bytes that were read, which is 0 at the end of the file.
+    fn read(&self, node: NodeId, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        let _ = (node, offset, buf);
+        Err(FsError::Unsupported)
+    }
+
+    /// Writes to the file `node`, starting at `offset` and growing the file if needed.
+    ///
+    /// Returns the number of bytes that were written.
+    fn write(&self, node: NodeId, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        let _ = (node, offset, buf);
+        Err(FsError::Unsupported)
+    }
+
+    /// Sets the size of the file `node`. Bytes added at the end are zero.
+    fn truncate(&self, node: NodeId, len: usize) -> Result<(), FsError> {
+        let _ = (node, len);
+        Err(FsError::Unsupported)
+    }
+}
+
+/// A node of the virtual file-system.
+#[derive(Clone, Copy)]
+pub struct Node {
+    /// The file-system that owns the node.
+    pub fs: &'static dyn FileSystem,
+    /// The ID of the node within its file-system.
+    pub id: NodeId,
+}
+
+impl Node {
+    /// Returns information about the node.
+    #[inline]
+    pub fn metadata(&self) -> Result<Metadata, FsError> {
+        self.fs.metadata(self.id)
+    }
+
+    /// Returns whether the node is a directory.
+    #[inline]
+    pub fn is_dir(&self) -> bool {
+        self.metadata().is_ok_and(|m| m.kind == NodeKind::Directory)
+    }
+
+    /// Reads the content of the file, starting at `offset`. See [`FileSystem::read`].
+    #[inline]
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        self.fs.read(self.id, offset, buf)
+    }
+
+    /// Writes to the file, starting at `offset`. See [`FileSystem::write`].
+    #[inline]
+    pub fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        self.fs.write(self.id, offset, buf)
+    }
+
+    /// Sets the size of the file. See [`FileSystem::truncate`].
+    #[inline]
+    pub fn truncate(&self, len: usize) -> Result<(), FsError> {
+        self.fs.truncate(self.id, len)
+    }
+}
+
+/// Writes to a file sequentially.
+///
+/// The first error that occurs is remembered, and nothing is written after it.
+pub struct FileWriter {
+    /// The file being written.
+    node: Node,
+    /// The offset at which the next bytes are written.
+    offset: usize,
+    /// The first error that occurred, if any.
+    error: Option<FsError>,
+}
+
+impl FileWriter {
+    /// Creates a new [`FileWriter`] that starts writing at `offset`.
+    pub fn new(node: Node, offset: usize) -> Self {
+        Self {
+            node,
+            offset,
+            error: None,
+        }
+    }
+
+    /// Writes some bytes at the end of what was written so far.
+    pub fn write_bytes(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() && self.error.is_none() {
+            match self.node.write(self.offset, bytes) {
+                Ok(0) => self.error = Some(FsError::NoSpace),
+                Ok(n) => {
+                    self.offset += n;
+                    bytes = &bytes[n..];
+                }
+                Err(err) => self.error = Some(err),
+            }
+        }
+    }
+
+    /// Returns the first error that occurred, if any.
+    #[inline]
+    pub fn error(&self) -> Option<FsError> {
+        self.error
+    }
+}
+
+impl core::fmt::Write for FileWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes());
+        match self.error {
+            Some(_) => Err(core::fmt::Error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A file-system mounted somewhere in the virtual file-system.
+struct Mount {
+    /// The normalized path at which the file-system is mounted.
+    path: PathBuf,
+    /// The mounted file-system.
+    fs: &'static dyn FileSystem,
+}
+
+/// The maximum number of file-systems that can be mounted at the same time.
+const MAX_MOUNTS: usize = 8;
+
+/// The list of mounted file-systems.
+static MOUNTS: Mutex<ArrayVec<Mount, MAX_MOUNTS>> = Mutex::new(ArrayVec::new());
+
+/// The root file-system.
+#[cfg(feature = "fs")]
+static ROOT_FS: RamFs = RamFs::new();
+
+/// The file-system mounted at `/dev`.
+#[cfg(feature = "fs")]
+static DEV_FS: DevFs = DevFs;
+
+/// The file-system mounted at `/proc`.
+#[cfg(feature = "fs")]
+static PROC_FS: ProcFs = ProcFs;
+
+/// Mounts a file-system at the provided normalized path.
+pub fn mount(path: &[u8], fs: &'static dyn FileSystem) -> Result<(), FsError> {
+    let mut mounts = MOUNTS.lock();
+
+    if mounts.iter().any(|m| *m.path == *path) {
+        return Err(FsError::AlreadyExists);
+    }
+
+    let mount = Mount {
+        path: ArrayVec::from_slice_truncated(path),
+        fs,
+    };
+
+    mounts.try_push(mount).map_err(|_| FsError::NoSpace)
+}
+
+/// Looks up the node at the provided normalized path.
+pub fn lookup(path: &[u8]) -> Result<Node, FsError> {
+    let (fs, rest) = {
+        let mounts = MOUNTS.lock();
+        let mount = mounts
+            .iter()
+            .filter(|m| path::starts_with(path, &m.path))
+            .max_by_key(|m| m.path.len())
+            .ok_or(FsError::NotFound)?;
+        let rest = if &*mount.path == b"/" {
+            path
+        } else {
+            &path[mount.path.len()..]
+        };
+        (mount.fs, rest)
+    };
+
+    let mut node = Node { fs, id: fs.root() };
+
+    for component in path::components(rest) {
+        if !node.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+
+        node.id = fs.lookup(node.id, component)?;
+    }
+
+    Ok(node)
+}
+
+/// Creates a node at the provided normalized path.
+pub fn create(path: &[u8], kind: NodeKind) -> Result<Node, FsError> {
+    let (parent, name) = path::split_last(path).ok_or(FsError::AlreadyExists)?;
+    let dir = lookup(parent)?;
+    if !dir.is_dir() {
+        return Err(FsError::NotADirectory);
+    }
+
+    let id = dir.fs.create(dir.id, name, kind)?;
+    Ok(Node { fs: dir.fs, id })
+}
+
+/// Resolves `path` relative to the normalized path `cwd` and looks it up.
+///
+/// On success, the normalized path of the node is returned along with the node itself.
+pub fn resolve(cwd: &[u8], path: &[u8]) -> Result<(PathBuf, Node), FsError> {
+    let path = path::resolve(cwd, path)?;
+    let node = lookup(&path)?;
+    Ok((path, node))
+}
+
+/// Initializes the virtual file-system.
+///
+/// This creates the root file-system and the standard directories. Without the `fs` feature,
+/// nothing is mounted, and every lookup fails.
+#[cfg(feature = "fs")]
+pub fn init() {
+    log!("Initializing the virtual file-system...\n");
+
+    devfs::register_devices();
+
+    let root = ROOT_FS.root();
+    for dir in [b"dev" as &[u8], b"etc", b"home", b"proc", b"tmp"] {
+        if let Err(err) = ROOT_FS.create(root, dir, NodeKind::Directory) {
+            log!("Failed to create a standard directory: {err}\n");
+        }
+    }
+
+    if let Err(err) = mount(b"/", &ROOT_FS) {
+        log!("Failed to mount the root file-system: {err}\n");
+    }
+    if let Err(err) = mount(b"/dev", &DEV_FS) {
+        log!("Failed to mount the device file-system: {err}\n");
+    }
+    if let Err(err) = mount(b"/proc", &PROC_FS) {
+        log!("Failed to mount the process file-system: {err}\n");
+    }
+}
+
+/// See the other definition.
+#[cfg(not(feature = "fs"))]
+pub fn init() {
+    log!("The file-systems are not compiled in.\n");
+}
@@ -0,0 +1,174 @@
+//! Functions to manipulate paths.
+//!
+//! Paths are simple byte strings. Components are separated by `/`, and a path starting with `/`
+//! is absolute. A *normalized* path is absolute and contains no empty, `.` or `..` components.
+
+use crate::utility::ArrayVec;
+
+use super::FsError;
+
+/// The maximum length of a path, in bytes.
+pub const MAX_PATH_LEN: usize = 128;
+
+/// An owned path.
+pub type PathBuf = ArrayVec<u8, MAX_PATH_LEN>;
+
+/// Returns the path of the root directory.
+pub fn root() -> PathBuf {
+    ArrayVec::from_slice_truncated(b"/")
+}
+
+/// Returns an iterator over the components of the provided path.
+///
+/// Empty and `.` components are skipped.
+pub fn components(path: &[u8]) -> impl '_ + Iterator<Item = &[u8]> {
+    path.split(|&c| c == b'/')
+        .filter(|&c| !c.is_empty() && c != b".")
+}
+
+/// Resolves `path` relative to `cwd`, returning a normalized path.
+///
+/// `cwd` must be a normalized path. If `path` is absolute, `cwd` is ignored.
+///
+/// # Errors
+///
+/// This function fails with [`FsError::NameTooLong`] if the resulting path does not fit in a
+/// [`PathBuf`].
+pub fn resolve(cwd: &[u8], path: &[u8]) -> Result<PathBuf, FsError> {
+    let mut ret = PathBuf::new();
+
+    // `ret` is built without the root `/`, which is added back at the end if needed.
+    if !path.starts_with(b"/") {
+        push_components(&mut ret, cwd)?;
+    }
+    push_components(&mut ret, path)?;
+
+    if ret.is_empty() {
+        ret.push(b'/');
+    }
+
+    Ok(ret)
+}
+
+/// Pushes the components of `path` to `ret`, interpreting `..` components.
+fn push_components(ret: &mut PathBuf, path: &[u8]) -> Result<(), FsError> {
+    for component in components(path) {
+        if component == b".." {
+            pop_component(ret);
+            continue;
+        }
+
+        if ret.len() + 1 + component.len() > ret.capacity() {
+            return Err(FsError::NameTooLong);
+        }
+
+        ret.push(b'/');
+        ret.extend_from_slice(component);
+    }
+
+    Ok(())
+}
+
+/// Removes the last component of the provided path.
+fn pop_component(path: &mut PathBuf) {
+    let start = path.iter().rposition(|&c| c == b'/').unwrap_or(0);
+    path.remove_range(start..);
+}
+
+/// Splits a normalized path into the path of its parent directory and its last component.
+///
+/// `None` is returned for the root directory.
+pub fn split_last(path: &[u8]) -> Option<(&[u8], &[u8])> {
+    let i = path.iter().rposition(|&c| c == b'/')?;
+    let name = &path[i + 1..];
+    if name.is_empty() {
+        return None;
+    }
+
+    match i {
+        0 => Some((b"/", name)),
+        _ => Some((&path[..i], name)),
+    }
+}
+
+/// Returns whether `prefix` is a normalized path that contains `path`.
+///
+/// `/a` contains `/a` and `/a/b`, but not `/ab`.
+pub fn starts_with(path: &[u8], prefix: &[u8]) -> bool {
+    if prefix == b"/" {
+        return true;
+    }
+
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest[0] == b'/',
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resolves `path` relative to `cwd`, and returns the result as a string.
+    fn resolved(cwd: &str, path: &str) -> String {
+        let path = resolve(cwd.as_bytes(), path.as_bytes()).unwrap();
+        String::from_utf8(path.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn resolve_absolute_ignores_cwd() {
+        assert_eq!(resolved("/a/b", "/c"), "/c");
+        assert_eq!(resolved("/a/b", "/"), "/");
+    }
+
+    #[test]
+    fn resolve_relative() {
+        assert_eq!(resolved("/", "a"), "/a");
+        assert_eq!(resolved("/a", "b/c"), "/a/b/c");
+        assert_eq!(resolved("/a", ""), "/a");
+    }
+
+    #[test]
+    fn resolve_parent_stops_at_root() {
+        assert_eq!(resolved("/a/b", ".."), "/a");
+        assert_eq!(resolved("/a/b", "../.."), "/");
+        assert_eq!(resolved("/", ".."), "/");
+        assert_eq!(resolved("/a", "../../../b"), "/b");
+        assert_eq!(resolved("/", "/../a/../../b"), "/b");
+    }
+
+    #[test]
+    fn resolve_skips_empty_and_current_components() {
+        assert_eq!(resolved("/", "//a///b"), "/a/b");
+        assert_eq!(resolved("/", "/a/b/"), "/a/b");
+        assert_eq!(resolved("/a", "./b/."), "/a/b");
+        assert_eq!(resolved("/a", "."), "/a");
+        assert_eq!(resolved("/a", "./"), "/a");
+    }
+
+    #[test]
+    fn resolve_too_long() {
+        let long = [b'a'; MAX_PATH_LEN];
+        assert_eq!(resolve(b"/", &long).err(), Some(FsError::NameTooLong));
+
+        // The root `/` of the result counts too.
+        let fits = [b'a'; MAX_PATH_LEN - 1];
+        assert_eq!(resolve(b"/", &fits).unwrap().len(), MAX_PATH_LEN);
+    }
+
+    #[test]
+    fn split_last_of_normalized_paths() {
+        assert_eq!(split_last(b"/"), None);
+        assert_eq!(split_last(b"/a"), Some((&b"/"[..], &b"a"[..])));
+        assert_eq!(split_last(b"/a/b"), Some((&b"/a"[..], &b"b"[..])));
+    }
+
+    #[test]
+    fn starts_with_whole_components() {
+        assert!(starts_with(b"/a", b"/"));
+        assert!(starts_with(b"/a", b"/a"));
+        assert!(starts_with(b"/a/b", b"/a"));
+        assert!(!starts_with(b"/ab", b"/a"));
+        assert!(!starts_with(b"/a", b"/a/b"));
+    }
+}
@@ -0,0 +1,264 @@
+//! A file-system that exposes information about the kernel as files.
+//!
+//! The content of the files is generated whenever they are read, from the global state of the
+//! kernel.
+
+use core::arch::x86::__cpuid;
+use core::fmt::Write;
+
+use crate::cpu::{idt, paging};
+use crate::drivers::pic::{self, Irq};
+use crate::drivers::pit;
+use crate::state::{ProcessId, GLOBAL};
+use crate::timer;
+
+use super::{FileSystem, FsError, Metadata, NodeId, NodeKind};
+
+/// A file of a [`ProcFs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    /// `/proc/meminfo`: the amount of physical memory.
+    MemInfo,
+    /// `/proc/uptime`: the time elapsed since the system booted, in seconds.
+    Uptime,
+    /// `/proc/interrupts`: the number of times each IRQ was received.
+    Interrupts,
+    /// `/proc/cpuinfo`: the processor, and the features of it that the kernel uses.
+    CpuInfo,
+    /// `/proc/<pid>/status`: information about a process.
+    Status(ProcessId),
+}
+
+/// The files of the root directory, by name.
+const ROOT_FILES: [(&[u8], u32); 4] = [
+    (b"meminfo", 1),
+    (b"uptime", 2),
+    (b"interrupts", 3),
+    (b"cpuinfo", 4),
+];
+
+/// The index of the `status` file within the directory of a process.
+const STATUS: u32 = 1;
+
+/// Returns the ID of the node at `index` within the directory of `pid`.
+///
+/// The node at index 0 is the directory itself.
+fn process_node(pid: ProcessId, index: u32) -> NodeId {
+    NodeId((pid + 1) << 8 | index)
+}
+
+/// A node of a [`ProcFs`], decoded from its ID.
+enum ProcNode {
+    /// The root directory.
+    Root,
+    /// The directory of a process.
+    Process(ProcessId),
+    /// A file.
+    File(Entry),
+}
+
+/// Decodes the ID of a node, checking that it still exists.
+fn decode(node: NodeId) -> Result<ProcNode, FsError> {
+    let (dir, index) = (node.0 >> 8, node.0 & 0xFF);
+
+    let node = match (dir, index) {
+        (0, 0) => return Ok(ProcNode::Root),
+        (0, 1) => ProcNode::File(Entry::MemInfo),
+        (0, 2) => ProcNode::File(Entry::Uptime),
+        (0, 3) => ProcNode::File(Entry::Interrupts),
+        (0, 4) => ProcNode::File(Entry::CpuInfo),
+        (0, _) => return Err(FsError::NotFound),
+        (pid, 0) => ProcNode::Process(pid - 1),
+        (pid, STATUS) => ProcNode::File(Entry::Status(pid - 1)),
+        _ => return Err(FsError::NotFound),
+    };
+
+    // The processes may have exited since the node was looked up.
+    if let ProcNode::Process(pid) | ProcNode::File(Entry::Status(pid)) = node {
+        if !process_exists(pid) {
+            return Err(FsError::NotFound);
+        }
+    }
+
+    Ok(node)
+}
+
+/// Returns whether the process `pid` exists.
+fn process_exists(pid: ProcessId) -> bool {
+    GLOBAL
+        .get()
+        .is_some_and(|glob| glob.processes.lock().get(pid).is_some())
+}
+
+/// Parses the name of the directory of a process.
+fn parse_pid(name: &[u8]) -> Option<ProcessId> {
+    // Leading zeros would give several names to the same directory.
+    if name.is_empty() || (name[0] == b'0' && name.len() > 1) {
+        return None;
+    }
+    core::str::from_utf8(name).ok()?.parse().ok()
+}
+
+/// Generates the content of a file.
+fn generate(entry: Entry, out: &mut dyn Write) -> core::fmt::Result {
+    let Some(glob) = GLOBAL.get() else {
+        return Ok(());
+    };
+
+    match entry {
+        Entry::MemInfo => {
+            let total = glob.system_info.total_memory / 1024;
+            let free = glob.allocator.lock().remaining_memory() / 1024;
+            writeln!(out, "MemTotal: {total:>10} kB")?;
+            writeln!(out, "MemFree:  {free:>10} kB")
+        }
+        Entry::Uptime => {
+            let ms = timer::now() as u64 * pit::interval_ns() as u64 / 1_000_000;
+            writeln!(out, "{}.{:02}", ms / 1000, ms % 1000 / 10)
+        }
+        Entry::Interrupts => {
+            for irq in Irq::ALL {
+                writeln!(
+                    out,
+                    "{:>3}: {:>10}  {irq:?}",
+                    irq as u8,
+                    pic::interrupt_count(irq),
+                )?;
+            }
+            Ok(())
+        }
+        Entry::CpuInfo => {
+            let vendor = unsafe { __cpuid(0) };
+            let mut name = [0; 12];
+            name[..4].copy_from_slice(&vendor.ebx.to_le_bytes());
+            name[4..8].copy_from_slice(&vendor.edx.to_le_bytes());
+            name[8..].copy_from_slice(&vendor.ecx.to_le_bytes());
+            let eax = unsafe { __cpuid(1).eax };
+
+            writeln!(
+                out,
+                "vendor_id: {}",
+                core::str::from_utf8(&name).unwrap_or("?")
+            )?;
+            writeln!(out, "cpu family: {}", (eax >> 8) & 0xF)?;
+            writeln!(out, "model:      {}", (eax >> 4) & 0xF)?;
+            writeln!(out, "stepping:   {}", eax & 0xF)?;
+            writeln!(out, "paging:     {}", paging_mode())?;
+            writeln!(out, "syscall:    {}", idt::syscall_mechanism().name())
+        }
+        Entry::Status(pid) => {
+            let processes = glob.processes.lock();
+            let Some(process) = processes.get(pid) else {
+                return Ok(());
+            };
+            writeln!(out, "Pid:  {pid}")?;
+            writeln!(out, "PPid: {}", process.parent)?;
+            writeln!(out, "State: {}", process.state.name())?;
+            writeln!(out, "Uid:  {}", process.owner)?;
+            writeln!(out, "UTime: {}", process.cpu.user)?;
+            writeln!(out, "KTime: {}", process.cpu.kernel)
+        }
+    }
+}
+
+/// Returns the name of the paging mode in use.
+fn paging_mode() -> &'static str {
+    if paging::is_pae_enabled() {
+        "pae"
+    } else if paging::pse36::is_enabled() {
+        "legacy (pse-36)"
+    } else {
+        "legacy"
+    }
+}
+
+/// Keeps the part of a formatted output that starts at a given offset.
+struct Window<'a> {
+    /// The buffer in which the kept bytes are written.
+    buf: &'a mut [u8],
+    /// The offset of the first byte to keep.
+    offset: usize,
+    /// The number of bytes that were formatted so far.
+    pos: usize,
+    /// The number of bytes that were written to `buf`.
+    len: usize,
+}
+
+impl Write for Window<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.pos >= self.offset && self.len < self.buf.len() {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+            self.pos += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A file-system that exposes runtime information about the kernel, meant to be mounted at
+/// `/proc`.
+///
+/// The root directory contains the global files, and one directory per process, named after
+/// its ID.
+pub struct ProcFs;
+
+impl FileSystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    fn lookup(&self, dir: NodeId, name: &[u8]) -> Result<NodeId, FsError> {
+        match decode(dir)? {
+            ProcNode::Root => match name {
+                b".." => Ok(dir),
+                _ => {
+                    if let Some(&(_, id)) = ROOT_FILES.iter().find(|(n, _)| *n == name) {
+                        return Ok(NodeId(id));
+                    }
+                    match parse_pid(name) {
+                        Some(pid) if process_exists(pid) => Ok(process_node(pid, 0)),
+                        _ => Err(FsError::NotFound),
+                    }
+                }
+            },
+            ProcNode::Process(pid) => match name {
+                b".." => Ok(self.root()),
+                b"status" => Ok(process_node(pid, STATUS)),
+                _ => Err(FsError::NotFound),
+            },
+            ProcNode::File(_) => Err(FsError::NotADirectory),
+        }
+    }
+
+    fn metadata(&self, node: NodeId) -> Result<Metadata, FsError> {
+        // Files are generated when they are read: their size is not known in advance.
+        let kind = match decode(node)? {
+            ProcNode::Root | ProcNode::Process(_) => NodeKind::Directory,
+            ProcNode::File(_) => NodeKind::File,
+        };
+
+        Ok(Metadata { kind, size: 0 })
+    }
+
+    fn read(&self, node: NodeId, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        let ProcNode::File(entry) = decode(node)? else {
+            return Err(FsError::IsADirectory);
+        };
+
+        let mut window = Window {
+            buf,
+            offset,
+            pos: 0,
+            len: 0,
+        };
+        // Formatting into a window cannot fail.
+        let _ = generate(entry, &mut window);
+        Ok(window.len)
+    }
+}
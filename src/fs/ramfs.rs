@@ -0,0 +1,353 @@
+//! A file-system that keeps its files in memory.
+//!
+//! The content of the files is stored in pages allocated on demand, in the identity-mapped
+//! memory when possible and above 4 GiB once it runs out. Nothing survives a reboot.
+
+use core::ops::{Deref, DerefMut};
+
+use crate::cpu::paging::pse36::HighMapping;
+use crate::cpu::paging::PageTableFlags;
+use crate::oom;
+use crate::state::{FrameOwner, GLOBAL, HIGH_FRAME_SIZE};
+use crate::utility::{ArrayVec, Mutex};
+
+use super::{FileSystem, FsError, Metadata, NodeId, NodeKind};
+
+/// The maximum number of nodes that a [`RamFs`] can hold.
+const MAX_NODES: usize = 64;
+
+/// The maximum length of the name of a node.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// The size of the pages in which the content of files is stored.
+const PAGE_SIZE: usize = 0x1000;
+
+/// The maximum number of pages of a file.
+const MAX_FILE_PAGES: usize = 16;
+
+/// The maximum size of a file, in bytes.
+pub const MAX_FILE_SIZE: usize = MAX_FILE_PAGES * PAGE_SIZE;
+
+/// A physical page that holds a part of the content of a file.
+#[derive(Clone, Copy)]
+enum Page {
+    /// A page in the identity-mapped memory.
+    Low(u32),
+    /// A page above 4 GiB, used once the rest of the memory is exhausted.
+    ///
+    /// It must be mapped through PSE-36 before being accessed.
+    High(u64),
+}
+
+/// The content of a [`Page`], mapped for as long as this value lives.
+struct MappedPage {
+    /// The mapping of the frame that contains the page, if it is not identity-mapped.
+    _mapping: Option<HighMapping>,
+    /// The content of the page.
+    ptr: *mut [u8; PAGE_SIZE],
+}
+
+impl Deref for MappedPage {
+    type Target = [u8; PAGE_SIZE];
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl DerefMut for MappedPage {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl Page {
+    /// Maps the page.
+    ///
+    /// This fails if the page lies above 4 GiB and every slot of the PSE-36 window is taken.
+    fn map(self) -> Result<MappedPage, FsError> {
+        match self {
+            // SAFETY: physical memory is identity mapped.
+            Self::Low(page) => Ok(MappedPage {
+                _mapping: None,
+                ptr: page as *mut [u8; PAGE_SIZE],
+            }),
+            Self::High(page) => {
+                let frame = page & !(HIGH_FRAME_SIZE - 1);
+                let mapping =
+                    HighMapping::new(frame, PageTableFlags::WRITABLE).ok_or(FsError::Busy)?;
+                let ptr = unsafe { mapping.as_ptr().add((page - frame) as usize) };
+                Ok(MappedPage {
+                    _mapping: Some(mapping),
+                    ptr: ptr as *mut [u8; PAGE_SIZE],
+                })
+            }
+        }
+    }
+}
+
+/// A node stored in a [`RamFs`].
+struct RamNode {
+    /// The name of the node within its parent directory.
+    name: ArrayVec<u8, MAX_NAME_LEN>,
+    /// The directory that contains the node.
+    ///
+    /// The root directory is its own parent.
+    parent: NodeId,
+    /// The kind of the node.
+    kind: NodeKind,
+    /// The size of the file, in bytes. This is always 0 for directories.
+    size: usize,
+    /// The physical pages that hold the content of the file.
+    ///
+    /// The bytes that lie past the end of the file are always zero.
+    pages: ArrayVec<Page, MAX_FILE_PAGES>,
+}
+
+impl RamNode {
+    /// Returns the content of the page at `index` in the file.
+    fn page(&self, index: usize) -> Result<MappedPage, FsError> {
+        self.pages[index].map()
+    }
+
+    /// Makes sure that the file has enough pages to hold `len` bytes.
+    fn reserve(&mut self, len: usize) -> Result<(), FsError> {
+        if len > MAX_FILE_SIZE {
+            return Err(FsError::NoSpace);
+        }
+
+        while self.pages.len() * PAGE_SIZE < len {
+            let mut allocator = GLOBAL.get().ok_or(FsError::NoSpace)?.allocator.lock();
+            if let Ok(page) = allocator.allocate_zeroed(FrameOwner::FileSystem) {
+                self.pages.push(Page::Low(page));
+                continue;
+            }
+
+            // Once the identity-mapped memory is exhausted, fall back to the memory that
+            // PSE-36 makes reachable.
+            let high = allocator.allocate_high_page();
+            drop(allocator);
+            let Ok(high) = high else {
+                oom::report("growing a file");
+                return Err(FsError::NoSpace);
+            };
+            match Page::High(high).map() {
+                Ok(mut content) => content.fill(0),
+                Err(err) => {
+                    GLOBAL
+                        .get()
+                        .unwrap()
+                        .allocator
+                        .lock()
+                        .deallocate_high_page(high);
+                    return Err(err);
+                }
+            }
+            self.pages.push(Page::High(high));
+        }
+
+        Ok(())
+    }
+
+    /// Sets the size of the file, releasing the pages that are no longer needed.
+    fn set_size(&mut self, len: usize) -> Result<(), FsError> {
+        self.reserve(len)?;
+
+        if len < self.size {
+            let keep = len.div_ceil(PAGE_SIZE);
+            if len % PAGE_SIZE != 0 {
+                self.page(keep - 1)?[len % PAGE_SIZE..].fill(0);
+            }
+
+            let mut allocator = GLOBAL.get().unwrap().allocator.lock();
+            while self.pages.len() > keep {
+                match self.pages.pop().unwrap() {
+                    Page::Low(page) => allocator.deallocate(page),
+                    Page::High(page) => allocator.deallocate_high_page(page),
+                }
+            }
+        }
+
+        self.size = len;
+        Ok(())
+    }
+}
+
+/// A simple file-system that lives entirely in memory.
+///
+/// Node IDs are indices into a fixed-size table. The root directory always has ID 0.
+pub struct RamFs {
+    /// The nodes of the file-system.
+    nodes: Mutex<[Option<RamNode>; MAX_NODES]>,
+}
+
+impl RamFs {
+    /// Creates a new [`RamFs`] that only contains an empty root directory.
+    pub const fn new() -> Self {
+        const EMPTY: Option<RamNode> = None;
+
+        let mut nodes = [EMPTY; MAX_NODES];
+        nodes[0] = Some(RamNode {
+            name: ArrayVec::new(),
+            parent: NodeId(0),
+            kind: NodeKind::Directory,
+            size: 0,
+            pages: ArrayVec::new(),
+        });
+
+        Self {
+            nodes: Mutex::new(nodes),
+        }
+    }
+}
+
+/// Returns the file `node`, if it exists.
+fn file(nodes: &mut [Option<RamNode>], node: NodeId) -> Result<&mut RamNode, FsError> {
+    let node = nodes
+        .get_mut(node.0 as usize)
+        .and_then(Option::as_mut)
+        .ok_or(FsError::NotFound)?;
+
+    match node.kind {
+        NodeKind::File => Ok(node),
+        NodeKind::Directory => Err(FsError::IsADirectory),
+        NodeKind::Device => Err(FsError::Unsupported),
+    }
+}
+
+/// Finds the child of `parent` named `name`.
+fn find_child(nodes: &[Option<RamNode>], parent: NodeId, name: &[u8]) -> Option<NodeId> {
+    nodes
+        .iter()
+        .enumerate()
+        // The root directory is its own parent, but it's not its own child.
+        .skip(1)
+        .find(|(_, n)| {
+            n.as_ref()
+                .is_some_and(|n| n.parent == parent && *n.name == *name)
+        })
+        .map(|(i, _)| NodeId(i as u32))
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    fn lookup(&self, dir: NodeId, name: &[u8]) -> Result<NodeId, FsError> {
+        let nodes = self.nodes.lock();
+        let node = nodes
+            .get(dir.0 as usize)
+            .and_then(Option::as_ref)
+            .ok_or(FsError::NotFound)?;
+
+        if node.kind != NodeKind::Directory {
+            return Err(FsError::NotADirectory);
+        }
+
+        match name {
+            b".." => Ok(node.parent),
+            _ => find_child(&nodes[..], dir, name).ok_or(FsError::NotFound),
+        }
+    }
+
+    fn metadata(&self, node: NodeId) -> Result<Metadata, FsError> {
+        let nodes = self.nodes.lock();
+        let node = nodes
+            .get(node.0 as usize)
+            .and_then(Option::as_ref)
+            .ok_or(FsError::NotFound)?;
+
+        Ok(Metadata {
+            kind: node.kind,
+            size: node.size,
+        })
+    }
+
+    fn create(&self, parent: NodeId, name: &[u8], kind: NodeKind) -> Result<NodeId, FsError> {
+        if kind == NodeKind::Device {
+            return Err(FsError::Unsupported);
+        }
+        if name.len() > MAX_NAME_LEN {
+            return Err(FsError::NameTooLong);
+        }
+
+        let mut nodes = self.nodes.lock();
+
+        match nodes.get(parent.0 as usize) {
+            Some(Some(p)) if p.kind == NodeKind::Directory => (),
+            Some(Some(_)) => return Err(FsError::NotADirectory),
+            _ => return Err(FsError::NotFound),
+        }
+
+        if find_child(&nodes[..], parent, name).is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let index = nodes
+            .iter()
+            .position(Option::is_none)
+            .ok_or(FsError::NoSpace)?;
+
+        nodes[index] = Some(RamNode {
+            name: ArrayVec::from_slice_truncated(name),
+            parent,
+            kind,
+            size: 0,
+            pages: ArrayVec::new(),
+        });
+
+        Ok(NodeId(index as u32))
+    }
+
+    fn read(&self, node: NodeId, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut nodes = self.nodes.lock();
+        let file = file(&mut nodes[..], node)?;
+
+        let end = file.size.min(offset.saturating_add(buf.len()));
+        let mut pos = offset;
+        while pos < end {
+            let in_page = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - in_page).min(end - pos);
+            buf[pos - offset..][..len]
+                .copy_from_slice(&file.page(pos / PAGE_SIZE)?[in_page..][..len]);
+            pos += len;
+        }
+
+        Ok(end.saturating_sub(offset))
+    }
+
+    fn write(&self, node: NodeId, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        let mut nodes = self.nodes.lock();
+        let file = file(&mut nodes[..], node)?;
+
+        let end = offset.saturating_add(buf.len()).min(MAX_FILE_SIZE);
+        if end <= offset && !buf.is_empty() {
+            return Err(FsError::NoSpace);
+        }
+        file.reserve(end)?;
+
+        let mut pos = offset;
+        while pos < end {
+            let in_page = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - in_page).min(end - pos);
+            file.page(pos / PAGE_SIZE)?[in_page..][..len]
+                .copy_from_slice(&buf[pos - offset..][..len]);
+            pos += len;
+        }
+
+        file.size = file.size.max(end);
+        Ok(end.saturating_sub(offset))
+    }
+
+    fn truncate(&self, node: NodeId, len: usize) -> Result<(), FsError> {
+        let mut nodes = self.nodes.lock();
+        file(&mut nodes[..], node)?.set_size(len)
+    }
+}
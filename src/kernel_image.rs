@@ -0,0 +1,103 @@
+//! Provides the boundaries of the kernel image and of its sections.
+//!
+//! The symbols used here are defined by the linker script. Every section starts and ends on a
+//! 4 KiB boundary, which allows mapping each of them with its own permissions.
+
+use core::ops::Range;
+
+use crate::cpu::paging::PageTableFlags;
+
+extern "C" {
+    static __kernel_start: u8;
+    static __kernel_end: u8;
+    static __text_start: u8;
+    static __text_end: u8;
+    static __rodata_start: u8;
+    static __rodata_end: u8;
+    static __data_start: u8;
+    static __data_end: u8;
+    static __bss_start: u8;
+    static __bss_end: u8;
+}
+
+/// Returns the range of addresses between two symbols of the linker script.
+#[inline(always)]
+fn range(start: &u8, end: &u8) -> Range<usize> {
+    start as *const u8 as usize..end as *const u8 as usize
+}
+
+/// Returns the addresses occupied by the whole kernel image.
+pub fn image() -> Range<usize> {
+    unsafe { range(&__kernel_start, &__kernel_end) }
+}
+
+/// Returns the addresses of the code of the kernel.
+pub fn text() -> Range<usize> {
+    unsafe { range(&__text_start, &__text_end) }
+}
+
+/// Returns the addresses of the read-only data of the kernel.
+pub fn rodata() -> Range<usize> {
+    unsafe { range(&__rodata_start, &__rodata_end) }
+}
+
+/// Returns the addresses of the initialized data of the kernel.
+pub fn data() -> Range<usize> {
+    unsafe { range(&__data_start, &__data_end) }
+}
+
+/// Returns the addresses of the zero-initialized data of the kernel.
+pub fn bss() -> Range<usize> {
+    unsafe { range(&__bss_start, &__bss_end) }
+}
+
+/// A section of the kernel image.
+#[derive(Debug, Clone, Copy)]
+pub struct Section {
+    /// The name of the section.
+    pub name: &'static str,
+    /// The address of the first byte of the section.
+    pub start: usize,
+    /// The address of the byte following the section.
+    pub end: usize,
+    /// Whether the section may be written to.
+    pub writable: bool,
+    /// Whether the section contains code.
+    pub executable: bool,
+}
+
+impl Section {
+    /// Returns the addresses occupied by the section.
+    #[inline(always)]
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Returns the page table flags with which the section should be mapped.
+    ///
+    /// No section is both writable and executable.
+    pub fn flags(&self) -> PageTableFlags {
+        let mut flags = PageTableFlags::empty();
+        flags.set(PageTableFlags::WRITABLE, self.writable);
+        flags.set(PageTableFlags::NO_EXECUTE, !self.executable);
+        flags
+    }
+}
+
+/// Returns the sections of the kernel image, sorted by address.
+pub fn sections() -> [Section; 4] {
+    let section = |name, range: Range<usize>, writable, executable| Section {
+        name,
+        start: range.start,
+        end: range.end,
+        writable,
+        executable,
+    };
+
+    [
+        section(".text", text(), false, true),
+        section(".rodata", rodata(), false, false),
+        section(".data", data(), true, false),
+        section(".bss", bss(), true, false),
+    ]
+}
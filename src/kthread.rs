@@ -0,0 +1,90 @@
+//! Kernel threads, which run functions of the kernel in the background.
+//!
+//! A kernel thread belongs to the init process and only ever runs in the kernel. It is
+//! scheduled like any other thread, so it may block without stopping the rest of the system.
+//!
+//! The functions queued with [`defer`] run one after the other on the `kworker` thread, which
+//! lets interrupt handlers hand over work that takes too long to run with interrupts disabled.
+//!
+//! When a kernel thread panics while it holds no lock, only that thread is terminated. Since
+//! holding a mutex disables interrupts, the panics that happen with interrupts enabled are the
+//! ones that can be isolated. Other panics still bring the whole kernel down.
+
+use core::panic::PanicInfo;
+
+use crate::sched::{self, SpawnError, ThreadId};
+use crate::state::{WaitQueue, INIT};
+use crate::utility::{ArrayVec, Mutex, RestoreInterrupts};
+use crate::{log, printk};
+
+/// The maximum number of functions that can wait to run on the `kworker` thread.
+pub const MAX_DEFERRED: usize = 16;
+
+/// The functions waiting to run on the `kworker` thread, in the order in which they were
+/// queued.
+static DEFERRED: Mutex<ArrayVec<fn(), MAX_DEFERRED>> = Mutex::new(ArrayVec::new());
+
+/// The `kworker` thread, waiting for functions to run.
+static WORKER: WaitQueue = WaitQueue::new();
+
+/// Creates a kernel thread named `name`, which runs `f`.
+///
+/// The thread exits when `f` returns.
+pub fn spawn(name: &'static str, f: fn()) -> Result<ThreadId, SpawnError> {
+    sched::spawn_kernel(INIT, name, run, f as usize)
+}
+
+/// The entry point of the kernel threads, which calls the function at `f`.
+extern "C" fn run(f: usize) {
+    let f: fn() = unsafe { core::mem::transmute(f) };
+    f();
+}
+
+/// Queues `f` to run on the `kworker` thread.
+///
+/// This may be called from interrupt handlers. Returns `false` if too many functions are
+/// already waiting.
+pub fn defer(f: fn()) -> bool {
+    if DEFERRED.lock().try_push(f).is_err() {
+        return false;
+    }
+    WORKER.wake_all();
+    true
+}
+
+/// Creates the `kworker` thread.
+///
+/// The functions queued before are run once the thread is scheduled.
+pub fn init() {
+    if spawn("kworker", worker).is_err() {
+        log!("Failed to create the kworker thread.\n");
+    }
+}
+
+/// The `kworker` thread, which runs the functions queued with [`defer`].
+fn worker() {
+    loop {
+        // A function queued between the check and the moment the thread blocks would wait for
+        // the next one if interrupts were enabled.
+        let restore = RestoreInterrupts::without_interrupts();
+        let work = core::mem::take(&mut *DEFERRED.lock());
+        if work.is_empty() {
+            WORKER.wait(None);
+        }
+        drop(restore);
+
+        work.iter().for_each(|f| f());
+    }
+}
+
+/// Terminates the kernel thread that is running after it panicked.
+///
+/// This must only be called from the panic handler, with interrupts enabled when the panic
+/// occurred, and for a thread that [is expendable](sched::is_current_expendable).
+pub fn die(info: &PanicInfo) -> ! {
+    let name = sched::current_name();
+    let tid = sched::current();
+    log!("\n\nTHREAD PANIC ({name}, tid {tid}):\n{info}\n");
+    printk!("kernel thread `{name}` ({tid}) panicked: {info}\n");
+    sched::exit(1);
+}
@@ -9,35 +9,62 @@
     decl_macro,
     abi_x86_interrupt,
     panic_info_message,
-    pointer_is_aligned
+    pointer_is_aligned,
+    stdsimd
 )]
 #![allow(dead_code)]
 
+mod bench;
+mod boot_info;
+mod cmdline;
 mod cpu;
+mod crash_dump;
+mod crash_record;
+mod device;
 mod die;
 mod drivers;
+mod error;
+mod fs;
+mod kernel_image;
+mod kthread;
+mod memtest;
+mod mqueue;
 mod multiboot;
+#[cfg(feature = "net")]
+mod net;
+mod oom;
+mod power;
+mod profiler;
+mod reaper;
+mod rng;
+mod sched;
 mod shell;
+mod shm;
 mod state;
 mod terminal;
+mod time;
+mod timer;
+mod top;
+mod trace;
 mod utility;
 
 use core::arch::asm;
-use core::ffi::CStr;
 use core::fmt::Write;
 use core::mem::MaybeUninit;
 use core::sync::atomic::AtomicU32;
 
-use crate::drivers::pit;
+use crate::drivers::{pit, ps2};
 use crate::shell::Shell;
 use crate::state::{Process, Processes};
 
+use self::boot_info::{BootInfo, MAX_BOOT_MODULES};
 use self::die::{die, oom};
-use self::drivers::{pic, serial, vga};
+use self::drivers::{lapic, pic, serial, vga};
+use self::error::KernelError;
 use self::multiboot::MultibootInfo;
-use self::state::{Allocator, Global, SystemInfo};
-use self::terminal::Terminal;
-use self::utility::instr::{hlt, sti};
+use self::state::{Allocator, DriverStatus, Drivers, FrameTags, Global, MemoryRegion, SystemInfo};
+use self::terminal::{ScancodeSet, Terminal, Theme};
+use self::utility::instr::sti;
 use self::utility::{ArrayVec, HumanBytes, InitAllocator, Mutex};
 
 /// The global terminal. It needs to be locked in order to be used.
@@ -53,6 +80,7 @@ pub macro printk($($args:tt)*) {{
 
 /// Only used in the [`log!`] macro.
 #[doc(hidden)]
+#[cfg_attr(not(feature = "log_serial"), allow(unused_variables))]
 fn __log(msg: core::fmt::Arguments) {
     #[cfg(feature = "log_serial")]
     crate::drivers::serial::__log(msg);
@@ -66,16 +94,27 @@ pub macro log($($args:tt)*) {{
 /// The header that the bootloader will run to determine the features that the kernel wants.
 #[link_section = ".multiboot_header"]
 #[used]
-static MULTIBOOT_HEADER: multiboot::Header =
-    multiboot::Header::new(multiboot::HeaderFlags::MEMORY_MAP);
+static MULTIBOOT_HEADER: multiboot::Header = multiboot::Header::new(
+    multiboot::HeaderFlags::MEMORY_MAP.union(multiboot::HeaderFlags::ALIGN_MODULES),
+);
 
 /// The size of the initial stack. See [`INIT_STACK`] for more information.
-const INIT_STACK_SIZE: usize = 0x2000;
+const INIT_STACK_SIZE: usize = 0x4000;
 /// The initial stack used up until a proper allocator is available. It should not need to be too
 /// large; just enough to get the kernel to a point where it can allocate physical memory
 /// dynamically.
+///
+/// Once the kernel is initialized, it moves to the larger stack of [`cpu::stack`], and this one
+/// is only used to handle the interrupts raised in user mode (see [`cpu::tss`]). It remains the
+/// stack of the shell if the kernel stack cannot be mapped.
 static mut INIT_STACK: [MaybeUninit<u8>; INIT_STACK_SIZE] = MaybeUninit::uninit_array();
 
+/// The name of the boot module that contains the boot script. See [`Shell::run_script`].
+const BOOT_SCRIPT_MODULE: &[u8] = b"rc";
+
+/// The number of milliseconds between two blinks of the terminal's cursor.
+const CURSOR_BLINK_PERIOD_MS: u32 = 500;
+
 /// This function is called by the bootloader.
 ///
 /// It assumes that the protocol used is "multiboot" (first version, not multiboot2).
@@ -94,6 +133,18 @@ unsafe extern "C" fn entry_point() {
         cmp eax, {eax_magic}
         jne 2f
         ",
+        // Clear the .bss section, which holds the initial stack. Bootloaders are not required
+        // to do it, and the statics it contains must start out zeroed. The boundaries are
+        // defined by the linker script, and are aligned to 4 KiB.
+        "
+        lea edi, [__bss_start]
+        lea ecx, [__bss_end]
+        sub ecx, edi
+        shr ecx, 2
+        xor eax, eax
+        cld
+        rep stosd
+        ",
         // Setup the stack pointer.
         // The Grub bootloader actually provides a seemingly valid stack pointer, but it's
         // better to set it up ourselves to avoid relying on the bootloader for too long.
@@ -129,10 +180,11 @@ unsafe extern "C" fn entry_point() {
 ///
 /// This function may only be called once by the `entry_point` function defined above.
 unsafe extern "C" fn entry_point2(info: &MultibootInfo) {
-    // Initialize the terminal and set up the cursor. Doing this now avoid as much as possible
-    // screen flickering while the kernel is initializing.
-    serial::init();
-    vga::cursor_show(15, 15);
+    // Initialize the terminal. Doing this now avoid as much as possible screen flickering while
+    // the kernel is initializing.
+    let mut drivers = Drivers::new();
+    record_driver(&mut drivers, "serial", serial::init());
+    serial::init_ports();
     TERMINAL.lock().reset();
 
     log!(
@@ -141,38 +193,66 @@ unsafe extern "C" fn entry_point2(info: &MultibootInfo) {
         INIT_STACK.as_ptr() as usize + INIT_STACK_SIZE
     );
 
-    // Get the name of the bootloader name.
-    let bootloader_name = if info.flags.intersects(multiboot::InfoFlags::BOOTLOADER_NAME) {
-        let name = CStr::from_ptr(info.bootloader_name);
-        log!("Bootloader: {:?}\n", name);
-        Some(name.to_bytes())
-    } else {
-        log!("Bootloader has not provided its name.\n");
+    // Read the information provided by the bootloader.
+    let boot_info = BootInfo::from_multiboot(info);
+    let cmdline = ArrayVec::from_slice_truncated(boot_info.cmdline);
+
+    if let Some(name) = cmdline::get(&cmdline, b"theme") {
+        match Theme::find(name) {
+            Some(theme) => TERMINAL.lock().set_theme(theme),
+            None => log!("Unknown theme requested on the command-line.\n"),
+        }
+    }
+
+    // The boot script is run by the shell once the kernel is initialized. The module stays where
+    // the bootloader loaded it, and its pages are never handed to the allocators.
+    let boot_script = if cmdline::has_flag(&cmdline, b"norc") {
+        log!("The boot script is disabled.\n");
         None
+    } else {
+        boot_info
+            .modules
+            .iter()
+            .find(|module| module.name() == BOOT_SCRIPT_MODULE)
+            .map(|module| module.data())
     };
+    // The kernel image and the boot modules must not be handed to the allocators.
+    let image = kernel_image::image();
+    let image = (image.start as u32, image.end as u32);
+    let reserved = boot_info
+        .modules
+        .iter()
+        .map(|module| (module.start, module.end))
+        .chain(Some(image))
+        .map(|(start, end)| (start & !0xFFF, (end + 0xFFF) & !0xFFF))
+        .collect::<ArrayVec<_, { MAX_BOOT_MODULES + 1 }>>();
 
     // Initialize the CPU and other hardware components.
     log!("Initializing the CPU...\n");
     cpu::gdt::init();
+    cpu::tss::set_kernel_stack(INIT_STACK.as_ptr() as u32 + INIT_STACK_SIZE as u32);
     cpu::idt::init();
-    pic::init();
-    pic::set_irq_mask(!(pic::Irqs::KEYBOARD | pic::Irqs::TIMER));
+    cpu::fpu::init();
+    if record_driver(&mut drivers, "pic", pic::init()) {
+        pic::set_irq_mask(!(pic::Irqs::KEYBOARD | pic::Irqs::TIMER));
+    } else {
+        log!("No interrupt will be received from the devices.\n");
+    }
     pit::init();
 
     // Read the memory map.
     log!("Reading the memory map...\n");
-    if !info.flags.intersects(multiboot::InfoFlags::MEMORY_MAP) {
-        TERMINAL.lock().set_color(vga::Color::Red);
+    let memmap = &boot_info.memory_map;
+    if memmap.is_empty() {
         die("the bootloader did not provid a memory map");
     }
-    let memmap = multiboot::MemMapIter::new(info.mmap_addr, info.mmap_length);
-    let total_memory = available_memory(memmap.clone())
+    let total_memory = available_memory(memmap)
         .map(|(start, end)| end - start)
         .sum::<u32>();
-    let largest_segment = available_memory(memmap.clone())
+    let largest_segment = available_memory(memmap)
         .max_by_key(|&(start, end)| end - start)
         .unwrap_or_else(|| die("found no memory"));
-    let mut upper_bound = available_memory(memmap.clone())
+    let mut upper_bound = available_memory(memmap)
         .map(|(_, end)| end)
         .max()
         .unwrap_or_else(|| die("found no memory"));
@@ -188,46 +268,170 @@ unsafe extern "C" fn entry_point2(info: &MultibootInfo) {
         largest = HumanBytes((largest_segment.1 - largest_segment.0) as u64),
     );
 
+    // Reserve the last page of the largest segment for the crash record. It has to be at the
+    // same place on every boot for the record to survive reboots.
+    let crash_record_page = (largest_segment.1 & !0xFFF) - 0x1000;
+    let previous_crash = crash_record::init(crash_record_page);
+    if let Some(crash) = &previous_crash {
+        log!("{crash}\nCRASH DUMP:\n{}\n", crash.dump());
+    }
+
+    // The ACPI tables are read before paging is enabled, as they are not necessarily located
+    // in memory that will be mapped.
+    match drivers::acpi::find_reset_register() {
+        Some(reg) => {
+            log!("ACPI reset register: {reg:?}\n");
+            power::set_acpi_reset_register(reg);
+        }
+        None => log!("No ACPI reset register found.\n"),
+    }
+    if let Some(name) = cmdline::get(&cmdline, b"oom") {
+        match oom::Policy::from_name(name) {
+            Some(policy) => oom::set_policy(policy),
+            None => log!("Unknown OOM policy requested on the command-line.\n"),
+        }
+    }
+    if let Some(list) = cmdline::get(&cmdline, b"reboot") {
+        match power::parse_reboot_order(list) {
+            Some((methods, count)) => power::set_reboot_order(&methods[..count]),
+            None => log!("Unknown reboot method requested on the command-line.\n"),
+        }
+    }
+
     // Create the boot allocator that will be used to set up everything else.
-    let mut init_allocator =
-        unsafe { InitAllocator::new(largest_segment.0 as usize, largest_segment.1 as usize) };
+    let (init_start, init_end) = largest_gap((largest_segment.0, crash_record_page), &reserved);
+    let mut init_allocator = unsafe { InitAllocator::new(init_start as usize, init_end as usize) };
 
     log!("Setting up the kernel's address-space (mapping up to {upper_bound:#x})\n");
-    cpu::paging::init(&mut init_allocator, upper_bound);
+    cpu::paging::init(
+        &mut init_allocator,
+        upper_bound,
+        cmdline::has_flag(&cmdline, b"pae"),
+        if cmdline::has_flag(&cmdline, b"nohuge") {
+            cpu::paging::HugePages::Forbid
+        } else {
+            cpu::paging::HugePages::Prefer
+        },
+    );
 
     log!("Initializing the physical memory allocator...\n");
     // Go through the available segments and compute the total amount of memory
     // that needs to be tracked.
     let iter = available_memory(memmap)
         .map(|(start, end)| ((start + 0xFFF) & !0xFFF, end & !0xFFF))
-        .flat_map(|(start, end)| (start..end).step_by(0x1000));
+        .flat_map(|(start, end)| (start..end).step_by(0x1000))
+        .filter(|&page| page != crash_record_page)
+        .filter(|&page| {
+            !reserved
+                .iter()
+                .any(|&(start, end)| (start..end).contains(&page))
+        });
     let allocator_storage = init_allocator.allocate_slice(iter.clone().count());
     log!(
         "The allocator can track up to {} physical pages.\n",
         allocator_storage.len()
     );
-    let mut allocator = Allocator::new(allocator_storage);
+    let tags = FrameTags::new(init_allocator.allocate_slice((upper_bound >> 12) as usize));
+    let mut allocator = Allocator::new(allocator_storage, tags);
 
-    for page in iter {
+    // The memory of the boot allocator is given to the allocator once it is no longer needed.
+    for page in iter.filter(|&page| !(init_start..init_end).contains(&page)) {
         debug_assert!(page % 0x1000 == 0);
         allocator.deallocate(page);
     }
 
-    let processes = Processes::new(&mut init_allocator, Process::new(0, 0));
+    let mut processes = Processes::new(
+        &mut init_allocator,
+        Process::new(state::INIT, 0, cpu::paging::spaces::kernel()),
+    );
 
+    let used = init_end as usize - init_allocator.top();
+    let leftover = init_allocator.finish();
+    let leftover = ((leftover.start + 0xFFF) & !0xFFF)..(leftover.end & !0xFFF);
+    for page in leftover.clone().step_by(0x1000) {
+        allocator.deallocate(page as u32);
+    }
     log!(
-        "Finished utilizing the boot allocator (used: {}, remaining: {})\n",
-        HumanBytes((largest_segment.1 - init_allocator.top() as u32) as u64),
-        HumanBytes((total_memory - (largest_segment.1 - init_allocator.top() as u32)) as u64)
+        "Finished utilizing the boot allocator (used: {}, reclaimed: {})\n",
+        HumanBytes(used as u64),
+        HumanBytes(leftover.len() as u64),
     );
 
+    if cmdline::has_flag(&cmdline, b"memtest") {
+        log!("Testing the free memory...\n");
+        let report = memtest::run(&mut allocator, true);
+        log!(
+            "{} frames tested, {} bad frames removed.\n",
+            report.tested,
+            report.bad,
+        );
+    }
+
+    match cpu::paging::check::self_test(&mut allocator) {
+        Ok(()) => log!("The page table self-test passed.\n"),
+        Err(failure) => log!("The page table self-test failed ({failure}).\n"),
+    }
+
+    // With PSE-36, the memory above 4 GiB can still be allocated as 4 MiB frames.
+    let mut high_memory = 0;
+    if cpu::paging::pse36::is_enabled() {
+        for (start, end) in high_memory_regions(memmap) {
+            high_memory += allocator.add_high_region(start, end);
+        }
+        log!(
+            "PSE-36 makes {} of memory above 4 GiB usable.\n",
+            HumanBytes(high_memory)
+        );
+    }
+    let unreachable_memory = unreachable_memory(memmap) - high_memory;
+    if unreachable_memory != 0 {
+        log!(
+            "Ignoring {} of memory above 4 GiB.\n",
+            HumanBytes(unreachable_memory)
+        );
+    }
+
+    power::map_acpi_reset_register(&mut allocator);
+    if record_driver(&mut drivers, "lapic", lapic::init(&mut allocator)) {
+        let hz = cmdline::get(&cmdline, b"tick_hz")
+            .and_then(shell::parse_u32)
+            .unwrap_or(lapic::DEFAULT_TIMER_HZ);
+        if let Err(err) = lapic::init_timer(hz) {
+            log!("Failed to start the local APIC timer: {err}.\n");
+        }
+    }
+
+    // The kernel moves to a larger stack once it is initialized.
+    let kernel_stack = match cpu::stack::allocate(&mut allocator) {
+        Ok(top) => Some(top),
+        Err(err) => {
+            log!("Failed to map the kernel stack: {err:?}\n");
+            None
+        }
+    };
+
+    // Every process shares the kernel's address space, which is charged to the init process.
+    let init = processes.current_mut();
+    init.memory.map(upper_bound >> 12, false);
+    if kernel_stack.is_some() {
+        init.memory
+            .map((cpu::stack::KERNEL_STACK_SIZE >> 12) as u32, true);
+    }
+
     // Write the global state.
     log!("Initilizing the global state...\n");
     crate::state::GLOBAL
         .set(Global {
             system_info: SystemInfo {
                 total_memory,
-                bootloader_name: bootloader_name.map(ArrayVec::from_slice_truncated),
+                unreachable_memory,
+                high_memory,
+                bootloader_name: boot_info
+                    .bootloader_name
+                    .map(ArrayVec::from_slice_truncated),
+                cmdline,
+                memory_map: boot_info.memory_map,
+                drivers: Mutex::new(drivers),
                 tick_count: AtomicU32::new(0),
             },
             allocator: Mutex::new(allocator),
@@ -236,42 +440,300 @@ unsafe extern "C" fn entry_point2(info: &MultibootInfo) {
         .ok()
         .expect("global state already initialized");
 
+    fs::init();
+
+    // Register the shell commands of the subsystems that are compiled in.
+    let commands = [
+        &profiler::COMMAND,
+        &trace::COMMAND,
+        &memtest::COMMAND,
+        &shm::COMMAND,
+        &mqueue::COMMAND,
+        &sched::COMMAND,
+        &top::COMMAND,
+        &oom::COMMAND,
+        &device::COMMAND,
+        &cpu::smp::COMMAND,
+        &bench::COMMAND,
+    ];
+    #[cfg(feature = "net")]
+    let commands = commands.into_iter().chain([
+        &net::COMMAND,
+        &net::PING_COMMAND,
+        &net::NETSTAT_COMMAND,
+        &net::TELNETD_COMMAND,
+        &net::HTTPD_COMMAND,
+        &net::NTP_COMMAND,
+    ]);
+    for command in commands {
+        if !shell::register(command) {
+            log!("Failed to register a shell command.\n");
+        }
+    }
+
+    // Configure the keyboard, and enable the mouse, which is used to select text and scroll
+    // through the output. Both are connected to the PS/2 controller.
+    let system_info = &crate::state::GLOBAL.get().unwrap().system_info;
+    if record_driver(&mut system_info.drivers.lock(), "ps2", ps2::init()) {
+        init_ps2_devices(system_info);
+    } else {
+        log!("The keyboard and the mouse are unavailable.\n");
+    }
+
+    time::init();
+    log!("The time is {}.\n", time::now());
+
+    // Make the cursor of the terminal blink.
+    if timer::register(CURSOR_BLINK_PERIOD_MS, || TERMINAL.lock().blink_cursor()).is_none() {
+        log!("Failed to register the cursor blinking callback.\n");
+    }
+
+    #[cfg(feature = "net")]
+    net::init(&system_info.cmdline);
+
     // Enable interrupts.
     log!("Enabling interrupts...\n");
     sti();
 
     log!("Kernel initialized.\n");
+    drivers::speaker::chime(drivers::speaker::BOOT_CHIME);
 
     let _ = TERMINAL.lock().write_str(include_str!("welcome.txt"));
 
+    if let Some(crash) = &previous_crash {
+        let mut term = TERMINAL.lock();
+        let error_color = term.theme().error;
+        term.set_color(error_color);
+        let _ = writeln!(term, "{crash}");
+        term.reset_color();
+    }
+
+    let (script, script_len) = boot_script.map_or((0, 0), |s| (s.as_ptr() as usize, s.len()));
+    match kernel_stack {
+        Some(top) => {
+            log!(
+                "Switching to the kernel stack ({}).\n",
+                HumanBytes(cpu::stack::KERNEL_STACK_SIZE as u64)
+            );
+            cpu::stack::switch(top, run_shell, script, script_len)
+        }
+        None => run_shell(script, script_len),
+    }
+}
+
+/// Runs the shell, after the boot script of `script_len` bytes at `script`, if it is not null.
+///
+/// This is the last part of the initialization of the kernel, which runs on the kernel stack.
+extern "C" fn run_shell(script: usize, script_len: usize) -> ! {
+    // The boot module stays where the bootloader loaded it.
+    let boot_script = (script != 0)
+        .then(|| unsafe { core::slice::from_raw_parts(script as *const u8, script_len) });
+    let system_info = &crate::state::GLOBAL.get().unwrap().system_info;
+    sched::init();
+    kthread::init();
+
     let mut shell = Shell::default();
+    if let Some(format) = cmdline::get(&system_info.cmdline, b"ps1") {
+        shell.set_prompt_format(format);
+    }
+    shell.refresh_prompt(&mut TERMINAL.lock());
+
+    if let Some(script) = boot_script {
+        log!("Running the boot script...\n");
+        shell.run_script(script);
+    }
+
     loop {
-        hlt();
-        TERMINAL.lock().take_buffered_scancodes(&mut shell);
+        terminal::wait_for_input(None);
+        let mut term = TERMINAL.lock();
+        term.take_buffered_scancodes(&mut shell);
+        term.take_buffered_mouse_bytes();
+        drop(term);
         shell.run();
     }
 }
 
+/// Initializes the devices connected to the ports of the PS/2 controller, depending on what they
+/// were identified as.
+///
+/// This function must be called with interrupts disabled.
+fn init_ps2_devices(system_info: &SystemInfo) {
+    ps2::identify_ports();
+
+    // The controller only translates the scan-codes of the first port.
+    let scancode_set = match ps2::find(ps2::DeviceKind::Keyboard) {
+        Some(ps2::Port::Second) => ScancodeSet::Set2,
+        _ => keyboard_scancode_set(&system_info.cmdline),
+    };
+    log!("Using the scan-code set {scancode_set:?}.\n");
+    TERMINAL.lock().set_scancode_set(scancode_set);
+    drivers::keyboard::set_translated(scancode_set == ScancodeSet::Set1);
+
+    let mut irqs = pic::Irqs::KEYBOARD | pic::Irqs::TIMER;
+    for port in ps2::Port::ALL {
+        let result = match ps2::kind(port) {
+            Some(ps2::DeviceKind::Keyboard) => {
+                let result = drivers::keyboard::init(port);
+                record_driver(&mut system_info.drivers.lock(), "keyboard", result)
+            }
+            Some(ps2::DeviceKind::Mouse) => {
+                let mouse = drivers::mouse::init(port);
+                if let Ok(protocol) = mouse {
+                    log!("PS/2 mouse enabled ({protocol:?} protocol).\n");
+                    TERMINAL.lock().set_mouse_protocol(protocol);
+                }
+                record_driver(&mut system_info.drivers.lock(), "mouse", mouse.map(|_| ()))
+            }
+            Some(ps2::DeviceKind::Unknown) | None => false,
+        };
+        if result && port == ps2::Port::Second {
+            irqs |= pic::Irqs::CASCADE | pic::Irqs::MOUSE;
+        }
+    }
+    pic::set_irq_mask(!irqs);
+
+    for (name, kind) in [
+        ("keyboard", ps2::DeviceKind::Keyboard),
+        ("mouse", ps2::DeviceKind::Mouse),
+    ] {
+        if ps2::find(kind).is_none() {
+            record_driver(
+                &mut system_info.drivers.lock(),
+                name,
+                Err(KernelError::NoDevice),
+            );
+        }
+    }
+}
+
+/// Records in `drivers` how the initialization of the driver `name` went.
+///
+/// Returns whether it succeeded.
+fn record_driver(
+    drivers: &mut Drivers,
+    name: &'static str,
+    result: Result<(), KernelError>,
+) -> bool {
+    if let Err(err) = result {
+        log!("Failed to initialize the {name} driver: {err}.\n");
+    }
+    if drivers.try_push(DriverStatus { name, result }).is_err() {
+        log!("Too many drivers to record the {name} driver.\n");
+    }
+    result.is_ok()
+}
+
+/// Determines the scan-code set that the keyboard sends.
+///
+/// The `scancodes=1` and `scancodes=2` options of the command-line respectively enable and
+/// disable the translation of the PS/2 controller. Without them, the current configuration of
+/// the controller is used.
+///
+/// This function must be called with interrupts disabled.
+fn keyboard_scancode_set(cmdline: &[u8]) -> ScancodeSet {
+    let Some(mut config) = ps2::read_config() else {
+        log!("Failed to read the configuration of the PS/2 controller.\n");
+        return ScancodeSet::Set1;
+    };
+
+    let translation = match cmdline::get(cmdline, b"scancodes") {
+        Some(b"1") => Some(true),
+        Some(b"2") => Some(false),
+        Some(_) => {
+            log!("Unknown scan-code set requested on the command-line.\n");
+            None
+        }
+        None => None,
+    };
+
+    if let Some(translation) = translation {
+        let mut new_config = config;
+        new_config.set(ps2::PS2Config::FIRST_PORT_TRANSLATION, translation);
+        if ps2::write_config(new_config) {
+            config = new_config;
+        } else {
+            log!("Failed to configure the PS/2 controller.\n");
+        }
+    }
+
+    if config.intersects(ps2::PS2Config::FIRST_PORT_TRANSLATION) {
+        ScancodeSet::Set1
+    } else {
+        ScancodeSet::Set2
+    }
+}
+
+/// Returns the largest part of `segment` that does not overlap any of the `reserved` ranges.
+///
+/// All ranges are half-open.
+fn largest_gap(segment: (u32, u32), reserved: &[(u32, u32)]) -> (u32, u32) {
+    let (seg_start, seg_end) = segment;
+    let mut best = (seg_start, seg_start);
+
+    // A gap always starts at the beginning of the segment or at the end of a reserved range.
+    let starts = core::iter::once(seg_start).chain(reserved.iter().map(|&(_, end)| end));
+    for start in starts {
+        if !(seg_start..seg_end).contains(&start)
+            || reserved.iter().any(|&(s, e)| (s..e).contains(&start))
+        {
+            continue;
+        }
+
+        let end = reserved
+            .iter()
+            .map(|&(s, _)| s)
+            .filter(|&s| s > start)
+            .fold(seg_end, u32::min);
+        if end - start > best.1 - best.0 {
+            best = (start, end);
+        }
+    }
+
+    best
+}
+
+/// The end of the physical memory that the kernel can use.
+///
+/// Memory above 4 GiB is not accessible without PAE, and the last 12 MiB below it are left
+/// out: the kernel stacks and the page tables are mapped there.
+const PHYSICAL_MEMORY_END: u64 = 0xFF40_0000;
+
 /// Returns an iterator over the segments that are available for use.
-fn available_memory(base: multiboot::MemMapIter) -> impl '_ + Clone + Iterator<Item = (u32, u32)> {
-    base
+fn available_memory(base: &[MemoryRegion]) -> impl '_ + Clone + Iterator<Item = (u32, u32)> {
+    base.iter()
         // Only keep memory that is marked as AVAILABLE.
-        .filter(|e| e.ty == multiboot::MemMapType::AVAILABLE)
-        // Convert the segments to a more convenient format.
-        .map(|e| {
-            (
-                e.addr_low as u64 | (e.addr_high as u64) << 32,
-                e.len_low as u64 | (e.len_high as u64) << 32,
-            )
-        })
+        .filter(|r| r.ty == multiboot::MemMapType::AVAILABLE)
         // Memory bellow 1 MiB is usually used by some other hardware (such as VGA)
-        // and should be avoided. Also, memory above 4 GiB is not accessible on x86.
-        .filter(|&(addr, _)| addr >= 0x100000 && addr <= u32::MAX as u64)
+        // and should be avoided.
+        .filter(|r| r.start >= 0x100000 && r.start < PHYSICAL_MEMORY_END)
         // If the segment bleeds above the 4 GiB limit, truncate it.
-        .map(|(addr, len)| {
-            (
-                addr as u32,
-                addr.checked_add(len).unwrap_or(u32::MAX as u64) as u32,
-            )
+        .map(|r| {
+            let end = r.start.saturating_add(r.len).min(PHYSICAL_MEMORY_END);
+            (r.start as u32, end as u32)
+        })
+        .filter(|&(start, end)| start < end)
+}
+
+/// Returns the amount of available memory that lies above [`PHYSICAL_MEMORY_END`], and that
+/// the kernel cannot use.
+fn unreachable_memory(base: &[MemoryRegion]) -> u64 {
+    base.iter()
+        .filter(|r| r.ty == multiboot::MemMapType::AVAILABLE)
+        .map(|r| {
+            let start = r.start.max(PHYSICAL_MEMORY_END);
+            r.start.saturating_add(r.len).saturating_sub(start)
+        })
+        .sum()
+}
+
+/// Returns the regions of available memory above 4 GiB that can be reached with PSE-36.
+fn high_memory_regions(base: &[MemoryRegion]) -> impl '_ + Iterator<Item = (u64, u64)> {
+    let limit = cpu::paging::pse36::physical_memory_end();
+    base.iter()
+        .filter(|r| r.ty == multiboot::MemMapType::AVAILABLE)
+        .map(move |r| {
+            let start = r.start.max(1 << 32);
+            (start, r.start.saturating_add(r.len).min(limit))
         })
+        .filter(|&(start, end)| start < end)
 }
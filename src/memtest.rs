@@ -0,0 +1,153 @@
+//! A test of the physical memory.
+//!
+//! The frames that the allocator considers free are written with a few patterns and read back.
+//! Frames that do not return what was written are removed from the allocator, so that they are
+//! never handed out again. The test runs at boot when the `memtest` flag is passed on the
+//! command-line, and at any time with the `memtest` command.
+
+use core::fmt::Write;
+
+use crate::shell::{usage, Command, Shell};
+use crate::state::{Allocator, GLOBAL};
+use crate::utility::{ArrayVec, Mutex};
+use crate::{printk, TERMINAL};
+
+/// The number of 32-bit words in a frame.
+const WORDS: usize = 1024;
+
+/// The maximum number of bad frames that are remembered.
+///
+/// More frames may be blacklisted, but only the first ones are listed.
+pub const MAX_BAD_FRAMES: usize = 32;
+
+/// The frames that failed a test so far.
+static BAD_FRAMES: Mutex<ArrayVec<u32, MAX_BAD_FRAMES>> = Mutex::new(ArrayVec::new());
+
+/// Where the content of a frame is saved during a non-destructive test.
+static SAVED: Mutex<[u32; WORDS]> = Mutex::new([0; WORDS]);
+
+/// The result of a memory test.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Report {
+    /// The number of frames that were tested.
+    pub tested: usize,
+    /// The number of frames that failed, and were removed from the allocator.
+    pub bad: usize,
+}
+
+/// Returns the value of the word at `index` in the walking-ones pattern.
+#[inline]
+fn walking_one(index: usize) -> u32 {
+    1 << (index % 32)
+}
+
+/// Writes `pattern` to every word of `frame`, and checks that it can be read back.
+fn check(frame: *mut u32, pattern: impl Fn(usize) -> u32) -> bool {
+    for i in 0..WORDS {
+        unsafe { frame.add(i).write_volatile(pattern(i)) };
+    }
+    (0..WORDS).all(|i| unsafe { frame.add(i).read_volatile() } == pattern(i))
+}
+
+/// Tests the frame at the physical address `page`, and returns whether it works.
+///
+/// The frame is tested with walking ones, walking zeros, and its own addresses written in each
+/// word. When `destructive` is not set, its content is restored afterwards.
+pub fn test_frame(page: u32, destructive: bool) -> bool {
+    // Physical memory is identity mapped.
+    let frame = page as *mut u32;
+
+    let mut saved = SAVED.lock();
+    if !destructive {
+        for (i, word) in saved.iter_mut().enumerate() {
+            *word = unsafe { frame.add(i).read_volatile() };
+        }
+    }
+
+    let ok = check(frame, walking_one)
+        && check(frame, |i| !walking_one(i))
+        && check(frame, |i| page + i as u32 * 4);
+
+    if !destructive {
+        for (i, &word) in saved.iter().enumerate() {
+            unsafe { frame.add(i).write_volatile(word) };
+        }
+    }
+
+    ok
+}
+
+/// Tests every frame that `allocator` considers free, and removes the bad ones from it.
+pub fn run(allocator: &mut Allocator, destructive: bool) -> Report {
+    let mut report = Report::default();
+    let mut bad_frames = BAD_FRAMES.lock();
+
+    allocator.retain(|page| {
+        report.tested += 1;
+        if test_frame(page, destructive) {
+            return true;
+        }
+
+        report.bad += 1;
+        let _ = bad_frames.try_push(page);
+        false
+    });
+
+    report
+}
+
+/// Returns the frames that failed a test so far.
+///
+/// Only the first [`MAX_BAD_FRAMES`] frames are listed.
+pub fn bad_frames() -> ArrayVec<u32, MAX_BAD_FRAMES> {
+    BAD_FRAMES.lock().clone()
+}
+
+/// The `memtest` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"memtest",
+    summary: "test the free physical memory",
+    usage: "memtest [destructive|bad]",
+    details: "Every free frame is written with a few patterns and read back. Frames that fail\n\
+              are never allocated again. The content of the frames is preserved, unless\n\
+              `destructive` is passed. `bad` lists the frames that failed so far.\n\
+              Interrupts are disabled while the test runs.",
+    handler: memtest,
+};
+
+/// The `memtest` command.
+fn memtest(shell: &mut Shell, args: &[u8]) {
+    let destructive = match args {
+        b"" => false,
+        b"destructive" => true,
+        b"bad" => {
+            let mut term = TERMINAL.lock();
+            for page in bad_frames().iter() {
+                let _ = writeln!(term, "{page:#010x}");
+            }
+            return;
+        }
+        _ => {
+            printk!("usage: {}\n", usage(b"memtest"));
+            shell.fail();
+            return;
+        }
+    };
+
+    if !shell.is_super_user() {
+        printk!("memtest: only the super-user may test the memory\n");
+        shell.fail();
+        return;
+    }
+
+    printk!("testing the free memory...\n");
+    let report = run(&mut GLOBAL.get().unwrap().allocator.lock(), destructive);
+    printk!(
+        "{} frames tested, {} bad frames removed\n",
+        report.tested,
+        report.bad,
+    );
+    if report.bad != 0 {
+        shell.fail();
+    }
+}
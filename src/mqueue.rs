@@ -0,0 +1,267 @@
+//! Named message queues.
+//!
+//! A queue holds a bounded number of messages, each with a priority. Messages of higher
+//! priority are received first, and messages of the same priority are received in the order
+//! in which they were sent. Receiving from an empty queue blocks until a message is sent, or
+//! until a timeout expires. Sending to a full queue fails.
+
+use core::fmt::{self, Display};
+
+use crate::shell::{Command, Shell};
+use crate::state::WaitQueue;
+use crate::utility::{ArrayVec, Column, Mutex, Table};
+use crate::{time, TERMINAL};
+
+/// The maximum number of message queues that can exist at once.
+pub const MAX_QUEUES: usize = 8;
+
+/// The maximum number of messages a queue can hold.
+pub const MAX_MESSAGES: usize = 16;
+
+/// The maximum size of a message, in bytes.
+pub const MAX_MESSAGE_SIZE: usize = 128;
+
+/// The maximum length of the name of a message queue.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// The ID of a message queue.
+///
+/// This is the index of the queue in the list of queues. The ID of a queue that was removed
+/// may be reused by another one.
+pub type QueueId = u32;
+
+/// An error that might occur while using a message queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueError {
+    /// The queue does not exist.
+    NotFound,
+    /// A queue with the same name already exists.
+    AlreadyExists,
+    /// The name of the queue is too long.
+    NameTooLong,
+    /// Too many queues exist already.
+    NoSpace,
+    /// The capacity of the queue is invalid.
+    InvalidCapacity,
+    /// The message does not fit in a queue, or in the buffer of the receiver.
+    MessageTooLong,
+    /// The queue is full, or empty, and the operation would block.
+    WouldBlock,
+    /// No message was received before the timeout expired.
+    TimedOut,
+}
+
+impl Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NotFound => "no such queue",
+            Self::AlreadyExists => "queue exists",
+            Self::NameTooLong => "name too long",
+            Self::NoSpace => "too many queues",
+            Self::InvalidCapacity => "invalid capacity",
+            Self::MessageTooLong => "message too long",
+            Self::WouldBlock => "resource temporarily unavailable",
+            Self::TimedOut => "timed out",
+        })
+    }
+}
+
+/// A message waiting in a queue.
+struct Message {
+    /// The priority of the message.
+    priority: u8,
+    /// The content of the message.
+    data: ArrayVec<u8, MAX_MESSAGE_SIZE>,
+}
+
+/// A message queue.
+struct Queue {
+    /// The name of the queue.
+    name: ArrayVec<u8, MAX_NAME_LEN>,
+    /// The maximum number of messages in the queue.
+    capacity: u8,
+    /// The messages of the queue, in the order in which they are received.
+    messages: ArrayVec<Message, MAX_MESSAGES>,
+}
+
+/// The message queues.
+static QUEUES: Mutex<[Option<Queue>; MAX_QUEUES]> = {
+    const NONE: Option<Queue> = None;
+    Mutex::new([NONE; MAX_QUEUES])
+};
+
+/// The threads waiting for a message, for each queue.
+static RECEIVERS: [WaitQueue; MAX_QUEUES] = {
+    // The constant is only used to initialize the array.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: WaitQueue = WaitQueue::new();
+    [EMPTY; MAX_QUEUES]
+};
+
+/// Opens the queue named `name`, and returns its ID.
+///
+/// When `capacity` is zero, the queue must exist. Otherwise, a new queue that can hold
+/// `capacity` messages is created.
+pub fn open(name: &[u8], capacity: usize) -> Result<QueueId, QueueError> {
+    if name.is_empty() || name.contains(&b'/') {
+        return Err(QueueError::NotFound);
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err(QueueError::NameTooLong);
+    }
+
+    let mut queues = QUEUES.lock();
+    let existing = queues
+        .iter()
+        .position(|q| q.as_ref().is_some_and(|q| *q.name == *name));
+    match existing {
+        Some(id) if capacity == 0 => return Ok(id as QueueId),
+        Some(_) => return Err(QueueError::AlreadyExists),
+        None if capacity == 0 => return Err(QueueError::NotFound),
+        None if capacity > MAX_MESSAGES => return Err(QueueError::InvalidCapacity),
+        None => (),
+    }
+
+    let slot = queues
+        .iter()
+        .position(Option::is_none)
+        .ok_or(QueueError::NoSpace)?;
+    queues[slot] = Some(Queue {
+        name: ArrayVec::from_slice_truncated(name),
+        capacity: capacity as u8,
+        messages: ArrayVec::new(),
+    });
+    Ok(slot as QueueId)
+}
+
+/// Removes the queue named `name`, along with the messages it holds.
+///
+/// The threads waiting for a message are woken up, and fail to receive one.
+pub fn unlink(name: &[u8]) -> Result<(), QueueError> {
+    let mut queues = QUEUES.lock();
+    let id = queues
+        .iter()
+        .position(|q| q.as_ref().is_some_and(|q| *q.name == *name))
+        .ok_or(QueueError::NotFound)?;
+    queues[id] = None;
+    drop(queues);
+
+    RECEIVERS[id].wake_all();
+    Ok(())
+}
+
+/// Sends a message to the queue `id`.
+pub fn send(id: QueueId, data: &[u8], priority: u8) -> Result<(), QueueError> {
+    let mut queues = QUEUES.lock();
+    let queue = queues
+        .get_mut(id as usize)
+        .and_then(Option::as_mut)
+        .ok_or(QueueError::NotFound)?;
+
+    if data.len() > MAX_MESSAGE_SIZE {
+        return Err(QueueError::MessageTooLong);
+    }
+    if queue.messages.len() >= queue.capacity as usize {
+        return Err(QueueError::WouldBlock);
+    }
+
+    // The message goes after the ones with the same priority.
+    let index = queue.messages.partition_point(|m| m.priority >= priority);
+    let message = Message {
+        priority,
+        data: ArrayVec::from_slice_truncated(data),
+    };
+    if queue.messages.try_insert(index, message).is_err() {
+        return Err(QueueError::WouldBlock);
+    }
+    drop(queues);
+
+    RECEIVERS[id as usize].wake_all();
+    Ok(())
+}
+
+/// Removes the first message of the queue `id`, copies it to `buf`, and returns its length
+/// and priority.
+///
+/// When the queue is empty, this waits for at most `timeout_ms` milliseconds, or forever if it
+/// is `None`.
+pub fn receive(
+    id: QueueId,
+    buf: &mut [u8],
+    timeout_ms: Option<u32>,
+) -> Result<(usize, u8), QueueError> {
+    let deadline =
+        timeout_ms.map(|ms| time::monotonic_ns() + ms as u64 * (time::NANOS_PER_SECOND / 1000));
+    let receivers = RECEIVERS.get(id as usize).ok_or(QueueError::NotFound)?;
+
+    loop {
+        let mut queues = QUEUES.lock();
+        let queue = queues
+            .get_mut(id as usize)
+            .and_then(Option::as_mut)
+            .ok_or(QueueError::NotFound)?;
+
+        if let Some(message) = queue.messages.first() {
+            let len = message.data.len();
+            let dst = buf.get_mut(..len).ok_or(QueueError::MessageTooLong)?;
+            dst.copy_from_slice(&message.data);
+            let priority = message.priority;
+            queue.messages.remove_range(..1);
+            return Ok((len, priority));
+        }
+        drop(queues);
+
+        if timeout_ms == Some(0) {
+            return Err(QueueError::WouldBlock);
+        }
+        if !receivers.wait(deadline) {
+            let expired = deadline.is_some_and(|d| time::monotonic_ns() >= d);
+            return Err(if expired {
+                QueueError::TimedOut
+            } else {
+                QueueError::WouldBlock
+            });
+        }
+    }
+}
+
+/// The `mq` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"mq",
+    summary: "list the message queues",
+    usage: "mq",
+    details: "Lists the message queues, along with the number of messages they hold and the\n\
+              number of threads waiting for a message.",
+    handler: mq,
+};
+
+/// The `mq` command.
+fn mq(_shell: &mut Shell, _args: &[u8]) {
+    let queues = QUEUES.lock();
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::right("ID", 3),
+            Column::left("NAME", MAX_NAME_LEN),
+            Column::right("MESSAGES", 8),
+            Column::right("CAPACITY", 8),
+            Column::right("WAITING", 7),
+        ],
+    );
+
+    let _ = table.header();
+    for (id, queue) in queues.iter().enumerate() {
+        let Some(queue) = queue else {
+            continue;
+        };
+
+        let _ = table.row([
+            &id,
+            &core::str::from_utf8(&queue.name).unwrap_or("?"),
+            &queue.messages.len(),
+            &queue.capacity,
+            &RECEIVERS[id].waiters().len(),
+        ]);
+    }
+}
@@ -180,18 +180,34 @@ pub struct MemMapEntry {
 
 /// The type of the memory map entry.
 #[repr(transparent)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MemMapType(pub u32);
 
 impl MemMapType {
     /// The memory region is available for general purpose use.
     pub const AVAILABLE: MemMapType = MemMapType(1);
+    /// The memory region is reserved and must not be used.
+    pub const RESERVED: MemMapType = MemMapType(2);
     /// The memory region is useable but holds ACPI information.
     pub const ACPI_RECLAIMABLE: MemMapType = MemMapType(3);
     /// Memory that must be preserved when the system is hibernated or suspended.
     pub const PRESERVED: MemMapType = MemMapType(4);
     /// The memory region is defective and should not be used.
     pub const DEFECTIVE: MemMapType = MemMapType(5);
+
+    /// Returns a human-readable name for the type.
+    ///
+    /// Unknown types must be treated as reserved.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AVAILABLE => "available",
+            Self::RESERVED => "reserved",
+            Self::ACPI_RECLAIMABLE => "acpi reclaimable",
+            Self::PRESERVED => "preserved",
+            Self::DEFECTIVE => "defective",
+            _ => "unknown",
+        }
+    }
 }
 
 /// An iterator over the memory map entries.
@@ -0,0 +1,91 @@
+use core::fmt::{self, Display};
+
+use crate::shell::parse_u32;
+
+/// An IPv4 address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    /// The address `0.0.0.0`, which stands for any address.
+    pub const UNSPECIFIED: Self = Self([0; 4]);
+
+    /// The address `255.255.255.255`, which reaches every host of the local network.
+    pub const BROADCAST: Self = Self([255; 4]);
+
+    /// Returns the address as a big-endian integer.
+    #[inline]
+    pub fn to_bits(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    /// Creates an address from a big-endian integer.
+    #[inline]
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits.to_be_bytes())
+    }
+
+    /// Returns whether the first `prefix_len` bits of the address are those of `network`.
+    pub fn is_in(self, network: Self, prefix_len: u8) -> bool {
+        (self.to_bits() ^ network.to_bits()) & mask(prefix_len) == 0
+    }
+
+    /// Parses an address such as `10.0.0.2`.
+    pub fn parse(s: &[u8]) -> Option<Self> {
+        let mut octets = [0; 4];
+        let mut parts = s.split(|&c| c == b'.');
+        for octet in &mut octets {
+            *octet = parse_u32(parts.next()?)?.try_into().ok()?;
+        }
+        parts.next().is_none().then_some(Self(octets))
+    }
+}
+
+impl Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{a}.{b}.{c}.{d}")
+    }
+}
+
+/// The address of a socket: an IPv4 address and a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SocketAddr {
+    /// The address of the host.
+    pub addr: Ipv4Addr,
+    /// The port on the host.
+    pub port: u16,
+}
+
+impl SocketAddr {
+    /// Creates a new [`SocketAddr`] instance.
+    #[inline]
+    pub const fn new(addr: Ipv4Addr, port: u16) -> Self {
+        Self { addr, port }
+    }
+}
+
+impl Display for SocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.addr, self.port)
+    }
+}
+
+/// Returns the network mask made of `prefix_len` leading ones.
+pub fn mask(prefix_len: u8) -> u32 {
+    match prefix_len {
+        0 => 0,
+        n => u32::MAX << (32 - n.min(32) as u32),
+    }
+}
+
+/// Parses an address followed by the length of its network prefix, such as `10.0.0.0/24`.
+///
+/// Without a prefix length, the address is taken alone (`/32`).
+pub fn parse_cidr(s: &[u8]) -> Option<(Ipv4Addr, u8)> {
+    let (addr, prefix_len) = match s.iter().position(|&c| c == b'/') {
+        Some(i) => (&s[..i], parse_u32(&s[i + 1..]).filter(|&n| n <= 32)? as u8),
+        None => (s, 32),
+    };
+    Some((Ipv4Addr::parse(addr)?, prefix_len))
+}
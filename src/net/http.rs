@@ -0,0 +1,199 @@
+//! A minimal HTTP/1.0 server, which serves a status page generated from the state of the
+//! kernel.
+//!
+//! Only `GET` and `HEAD` requests for `/` are answered with the page. The connection is closed
+//! after each response, which marks the end of the body.
+
+use core::fmt::Write;
+
+use crate::drivers::pic::{self, Irq};
+use crate::drivers::pit;
+use crate::shell::{split_command, Command, Shell};
+use crate::state::{user_name, CpuTime, ProcessId, UserId, GLOBAL};
+use crate::timer;
+use crate::utility::{ArrayVec, Column, HumanBytes, Table};
+
+use super::server::{Server, Writer};
+use super::tcp::{self, SocketId};
+
+/// The port the server listens on by default.
+const DEFAULT_PORT: u16 = 80;
+
+/// The maximum length of a request, headers included.
+///
+/// The rest of longer requests is ignored.
+const MAX_REQUEST_LEN: usize = 512;
+
+/// The maximum number of processes listed on the status page.
+const MAX_PROCESSES: usize = 64;
+
+/// The HTTP server.
+static SERVER: Server = Server::new("httpd", "khttpd", DEFAULT_PORT, false, serve, respond);
+
+/// What the status page tells about a process.
+struct ProcessRow {
+    /// The ID of the process.
+    pid: ProcessId,
+    /// The ID of the parent of the process.
+    parent: ProcessId,
+    /// The name of the state of the process.
+    state: &'static str,
+    /// The owner of the process.
+    owner: UserId,
+    /// The CPU time of the process, in ticks.
+    cpu: CpuTime,
+    /// The number of pages of the process that are backed by frames.
+    resident: u32,
+}
+
+/// The `khttpd` thread.
+fn serve() {
+    SERVER.serve();
+}
+
+/// Reads the request of the client connected to `socket`, and answers it.
+fn respond(socket: SocketId, _user: UserId) {
+    let mut request = ArrayVec::<u8, MAX_REQUEST_LEN>::new();
+    let mut received = [0; 128];
+    while !request.is_full() && !ends_headers(&request) {
+        let count = match tcp::recv(socket, &mut received) {
+            Ok(0) | Err(_) => break,
+            Ok(count) => count,
+        };
+        let count = count.min(request.capacity() - request.len());
+        request.extend_from_slice(&received[..count]);
+    }
+
+    let line = request.split(|&c| c == b'\n').next().unwrap_or(&[]);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let (method, rest) = split_command(line);
+    let (path, version) = split_command(rest);
+
+    let mut out = Writer::new(socket);
+    let head = method == b"HEAD";
+    if !version.starts_with(b"HTTP/") {
+        reply(&mut out, "400 Bad Request", head);
+    } else if method != b"GET" && !head {
+        reply(&mut out, "501 Not Implemented", head);
+    } else if path != b"/" {
+        reply(&mut out, "404 Not Found", head);
+    } else {
+        write_headers(&mut out, "200 OK");
+        if !head {
+            let _ = status_page(&mut out);
+        }
+    }
+    out.flush();
+}
+
+/// Returns whether `request` contains the empty line that ends the headers.
+fn ends_headers(request: &[u8]) -> bool {
+    request.windows(4).any(|w| w == b"\r\n\r\n") || request.windows(2).any(|w| w == b"\n\n")
+}
+
+/// Writes the status line and the headers of a response.
+fn write_headers(out: &mut Writer, status: &str) {
+    let _ = write!(
+        out,
+        "HTTP/1.0 {status}\r\n\
+         Content-Type: text/plain; charset=us-ascii\r\n\
+         Connection: close\r\n\
+         \r\n"
+    );
+}
+
+/// Writes a response whose body is its status.
+fn reply(out: &mut Writer, status: &str, head: bool) {
+    write_headers(out, status);
+    if !head {
+        let _ = writeln!(out, "{status}");
+    }
+}
+
+/// Writes the status page.
+fn status_page(out: &mut Writer) -> core::fmt::Result {
+    let Some(glob) = GLOBAL.get() else {
+        return Ok(());
+    };
+
+    // Sending may block: the state of the kernel is copied before anything is written.
+    let ms = timer::now() as u64 * pit::interval_ns() as u64 / 1_000_000;
+    let total = glob.system_info.total_memory as u64;
+    let free = glob.allocator.lock().remaining_memory() as u64;
+    let mut processes = ArrayVec::<ProcessRow, MAX_PROCESSES>::new();
+    for (pid, process) in glob.processes.lock().iter() {
+        let row = ProcessRow {
+            pid,
+            parent: process.parent,
+            state: process.state.name(),
+            owner: process.owner,
+            cpu: process.cpu,
+            resident: process.memory.resident,
+        };
+        if processes.try_push(row).is_err() {
+            break;
+        }
+    }
+    let mut interrupts = [0; 16];
+    for (count, irq) in interrupts.iter_mut().zip(Irq::ALL) {
+        *count = pic::interrupt_count(irq);
+    }
+
+    writeln!(out, "uptime: {}.{:02} s", ms / 1000, ms % 1000 / 10)?;
+    writeln!(
+        out,
+        "memory: {} free of {}",
+        HumanBytes(free),
+        HumanBytes(total),
+    )?;
+
+    writeln!(out, "\nprocesses:")?;
+    let mut table = Table::new(
+        out,
+        [
+            Column::right("PID", 5),
+            Column::right("PPID", 5),
+            Column::left("STATE", 8),
+            Column::left("USER", 8),
+            Column::right("UTIME", 8),
+            Column::right("KTIME", 8),
+            Column::right("RSS", 8),
+        ],
+    );
+    table.header()?;
+    for p in processes.iter() {
+        table.row([
+            &p.pid,
+            &p.parent,
+            &p.state,
+            &user_name(p.owner).unwrap_or("?"),
+            &p.cpu.user,
+            &p.cpu.kernel,
+            &HumanBytes(p.resident as u64 * 4096),
+        ])?;
+    }
+
+    writeln!(out, "\ninterrupts:")?;
+    for (irq, count) in Irq::ALL.into_iter().zip(interrupts) {
+        writeln!(out, "{:>3}: {count:>10}  {irq:?}", irq as u8)?;
+    }
+    Ok(())
+}
+
+/// The `httpd` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"httpd",
+    summary: "serve a status page over HTTP",
+    usage: "httpd [start | <port> | stop] [interface]",
+    details: "Without arguments, tells whether the server is running. `httpd start` serves a\n\
+              plain-text page with the uptime, the memory, the processes and the interrupt\n\
+              counters on port 80, and `httpd <port>` on another port, such as with\n\
+              `curl http://10.0.0.2/`. With the name of an interface, only its address is\n\
+              served. `httpd stop` stops it. Requires being the super-user.",
+    handler: httpd,
+};
+
+/// The `httpd` command.
+fn httpd(shell: &mut Shell, args: &[u8]) {
+    SERVER.command(shell, args);
+}
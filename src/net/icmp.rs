@@ -0,0 +1,187 @@
+use crate::error::KernelError;
+use crate::shell::{parse_u32, split_command, usage, Command, Shell};
+use crate::state::WaitQueue;
+use crate::utility::{internet_checksum, Mutex};
+use crate::{printk, time, TERMINAL};
+
+use super::addr::Ipv4Addr;
+use super::ipv4::{self, Header, PROTOCOL_ICMP};
+use super::MAX_MTU;
+
+/// The type of the ICMP messages that answer [`ECHO_REQUEST`] messages.
+const ECHO_REPLY: u8 = 0;
+
+/// The type of the ICMP messages sent by `ping`.
+const ECHO_REQUEST: u8 = 8;
+
+/// The length of the header of echo messages.
+const ECHO_HEADER_LEN: usize = 8;
+
+/// The number of bytes of data sent in each echo request.
+const ECHO_DATA_LEN: usize = 56;
+
+/// The identifier of the echo requests sent by the kernel.
+const ECHO_ID: u16 = 0x6B66;
+
+/// The echo request waiting for its reply.
+static PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+
+/// The thread waiting for the reply to the pending echo request.
+static REPLIED: WaitQueue = WaitQueue::new();
+
+/// An echo request waiting for its reply.
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    /// The host the request was sent to.
+    dst: Ipv4Addr,
+    /// The sequence number of the request.
+    seq: u16,
+    /// Whether the reply was received.
+    replied: bool,
+}
+
+/// Handles an ICMP message received in a packet whose header is `header`.
+pub fn receive(header: &Header, message: &[u8]) {
+    if message.len() < ECHO_HEADER_LEN || internet_checksum(message) != 0 {
+        return;
+    }
+
+    match message[0] {
+        ECHO_REQUEST if message[1] == 0 => {
+            let mut reply = [0; MAX_MTU];
+            let reply = &mut reply[..message.len()];
+            reply.copy_from_slice(message);
+            reply[0] = ECHO_REPLY;
+            reply[2..4].fill(0);
+            let checksum = internet_checksum(reply);
+            reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+            let _ = ipv4::send(header.src, PROTOCOL_ICMP, reply);
+        }
+        ECHO_REPLY => {
+            let id = u16::from_be_bytes([message[4], message[5]]);
+            let seq = u16::from_be_bytes([message[6], message[7]]);
+            let mut pending = PENDING.lock();
+            match &mut *pending {
+                Some(p) if id == ECHO_ID && p.seq == seq && p.dst == header.src => {
+                    p.replied = true;
+                }
+                _ => return,
+            }
+            drop(pending);
+            REPLIED.wake_all();
+        }
+        _ => (),
+    }
+}
+
+/// Sends an echo request with sequence number `seq` to `dst`, and waits for its reply for at
+/// most `timeout_ns` nanoseconds.
+///
+/// Returns the time it took to get the reply, in nanoseconds. Only one request may wait for
+/// its reply at a time.
+pub fn ping(dst: Ipv4Addr, seq: u16, timeout_ns: u64) -> Result<u64, KernelError> {
+    {
+        let mut pending = PENDING.lock();
+        if pending.is_some() {
+            return Err(KernelError::Busy);
+        }
+        *pending = Some(Pending {
+            dst,
+            seq,
+            replied: false,
+        });
+    }
+
+    let mut request = [0; ECHO_HEADER_LEN + ECHO_DATA_LEN];
+    request[0] = ECHO_REQUEST;
+    request[4..6].copy_from_slice(&ECHO_ID.to_be_bytes());
+    request[6..8].copy_from_slice(&seq.to_be_bytes());
+    for (i, byte) in request[ECHO_HEADER_LEN..].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let checksum = internet_checksum(&request);
+    request[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+    let start = time::monotonic_ns();
+    let deadline = start + timeout_ns;
+    let result = ipv4::send(dst, PROTOCOL_ICMP, &request).and_then(|()| loop {
+        if PENDING.lock().is_some_and(|p| p.replied) {
+            break Ok(time::monotonic_ns() - start);
+        }
+        if time::monotonic_ns() >= deadline {
+            break Err(KernelError::TimedOut);
+        }
+        REPLIED.wait(Some(deadline));
+    });
+    *PENDING.lock() = None;
+    result
+}
+
+/// The `ping` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"ping",
+    summary: "send echo requests to a host",
+    usage: "ping <address> [count]",
+    details: "Sends `count` (4 by default) ICMP echo requests to the host, one per second, and\n\
+              prints the time it took to get each reply. Press `q` to stop early.",
+    handler: ping_command,
+};
+
+/// The `ping` command.
+fn ping_command(shell: &mut Shell, args: &[u8]) {
+    let (addr, count) = split_command(args);
+    let addr = Ipv4Addr::parse(addr);
+    let count = if count.is_empty() {
+        Some(4)
+    } else {
+        parse_u32(count)
+    };
+    let (Some(addr), Some(count)) = (addr, count) else {
+        printk!("usage: {}\n", usage(b"ping"));
+        shell.fail();
+        return;
+    };
+
+    let mut received = 0;
+    for seq in 0..count {
+        if seq != 0 && !wait(time::NANOS_PER_SECOND) {
+            break;
+        }
+        match ping(addr, seq as u16, time::NANOS_PER_SECOND) {
+            Ok(ns) => {
+                received += 1;
+                printk!(
+                    "{} bytes from {addr}: seq={seq} time={}.{:03} ms\n",
+                    ECHO_HEADER_LEN + ECHO_DATA_LEN,
+                    ns / 1_000_000,
+                    ns / 1000 % 1000,
+                );
+            }
+            Err(KernelError::TimedOut) => printk!("no reply from {addr}: seq={seq}\n"),
+            Err(err) => {
+                printk!("ping: {err}\n");
+                shell.fail();
+                return;
+            }
+        }
+    }
+    if received == 0 {
+        shell.fail();
+    }
+}
+
+/// Lets the other threads run for `ns` nanoseconds.
+///
+/// Returns `false` if the user asked to quit in the meantime.
+fn wait(ns: u64) -> bool {
+    let deadline = time::monotonic_ns() + ns;
+    loop {
+        if TERMINAL.lock().take_quit_request() {
+            return false;
+        }
+        if time::monotonic_ns() >= deadline {
+            return true;
+        }
+        crate::terminal::wait_for_input(Some(deadline));
+    }
+}
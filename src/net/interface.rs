@@ -0,0 +1,246 @@
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::drivers::serial::{self, Uart};
+use crate::error::KernelError;
+use crate::utility::{ArrayVec, Mutex};
+use crate::{device, kthread, log, sched, time};
+
+use super::addr::{mask, Ipv4Addr};
+use super::route::{self, Route};
+use super::{ipv4, slip, MAX_MTU};
+
+/// The maximum number of network interfaces.
+pub const MAX_INTERFACES: usize = 4;
+
+/// The names of the interfaces, by index.
+const NAMES: [&str; MAX_INTERFACES] = ["sl0", "sl1", "sl2", "sl3"];
+
+/// The time between two polls of the links for received packets, in nanoseconds.
+const POLL_PERIOD_NS: u64 = time::NANOS_PER_SECOND / 1000;
+
+/// The link through which an interface sends and receives its packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    /// A serial line, using SLIP.
+    Slip(Uart),
+}
+
+impl Link {
+    /// Returns the name of the kind of link.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Slip(_) => "slip",
+        }
+    }
+
+    /// Returns the largest packet that can be sent through the link.
+    pub fn mtu(self) -> usize {
+        match self {
+            Self::Slip(_) => slip::MTU,
+        }
+    }
+
+    /// Sends `packet` through the link.
+    fn send(self, packet: &[u8]) {
+        match self {
+            Self::Slip(uart) => slip::send(uart, packet),
+        }
+    }
+}
+
+/// The counters of an interface.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    /// The number of packets received.
+    pub rx_packets: u32,
+    /// The number of packets sent.
+    pub tx_packets: u32,
+    /// The number of packets that were received but dropped because they were invalid.
+    pub rx_errors: u32,
+}
+
+/// A network interface, which has an address on the network its link leads to.
+pub struct Interface {
+    /// The name of the interface.
+    pub name: &'static str,
+    /// The name of the device the link goes through.
+    pub device: &'static str,
+    /// The link of the interface.
+    pub link: Link,
+    /// The address of the interface.
+    pub addr: Ipv4Addr,
+    /// The length of the prefix of the network the interface is on.
+    pub prefix_len: u8,
+    /// The counters of the interface.
+    pub stats: Stats,
+    /// Assembles the bytes received on SLIP links into packets.
+    decoder: slip::Decoder,
+}
+
+impl Interface {
+    /// Copies a packet received by the interface to `out`, and returns its length.
+    ///
+    /// `Some(0)` is returned for invalid packets, which are counted and dropped.
+    fn poll(&mut self, out: &mut [u8; MAX_MTU]) -> Option<usize> {
+        let Link::Slip(uart) = self.link;
+        while let Some(byte) = uart.read_byte() {
+            match self.decoder.advance(byte) {
+                None => (),
+                Some(0) => {
+                    self.stats.rx_errors += 1;
+                    return Some(0);
+                }
+                Some(len) => {
+                    out[..len].copy_from_slice(self.decoder.packet(len));
+                    self.stats.rx_packets += 1;
+                    return Some(len);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The network interfaces, by index.
+///
+/// Interfaces are never removed, so their index identifies them.
+static INTERFACES: Mutex<ArrayVec<Interface, MAX_INTERFACES>> = Mutex::new(ArrayVec::new());
+
+/// Whether the `knet` thread, which polls the links, was created.
+static POLLING: AtomicBool = AtomicBool::new(false);
+
+/// Creates an interface with the address `addr/prefix_len`, which uses SLIP over the serial
+/// port registered as `tty`, and adds a route to its network.
+///
+/// Returns the index of the interface.
+pub fn attach_slip(tty: &[u8], addr: Ipv4Addr, prefix_len: u8) -> Result<usize, KernelError> {
+    let uart = serial::find(tty).ok_or(KernelError::NotFound)?;
+    // The first serial port carries the log.
+    if uart == Uart::COM1 {
+        return Err(KernelError::Busy);
+    }
+    let device = device::find(tty)
+        .and_then(device::get)
+        .ok_or(KernelError::NotFound)?
+        .name;
+
+    let mut interfaces = INTERFACES.lock();
+    if interfaces.iter().any(|i| i.device == device) {
+        return Err(KernelError::Busy);
+    }
+    let index = interfaces.len();
+    let interface = Interface {
+        name: NAMES[index.min(MAX_INTERFACES - 1)],
+        device,
+        link: Link::Slip(uart),
+        addr,
+        prefix_len,
+        stats: Stats::default(),
+        decoder: slip::Decoder::new(),
+    };
+    interfaces
+        .try_push(interface)
+        .map_err(|_| KernelError::NoSpace)?;
+    drop(interfaces);
+
+    let network = Ipv4Addr::from_bits(addr.to_bits() & mask(prefix_len));
+    let route = Route {
+        dest: network,
+        prefix_len,
+        gateway: None,
+        interface: index,
+    };
+    if let Err(err) = route::add(route) {
+        log!("Failed to add a route to {network}/{prefix_len}: {err}.\n");
+    }
+
+    if !POLLING.swap(true, Relaxed) && kthread::spawn("knet", poll).is_err() {
+        POLLING.store(false, Relaxed);
+        log!("Failed to create the knet thread.\n");
+    }
+    log!(
+        "{}: SLIP over {device}, address {addr}/{prefix_len}.\n",
+        NAMES[index]
+    );
+    Ok(index)
+}
+
+/// Returns the index of the interface named `name`.
+pub fn find(name: &[u8]) -> Option<usize> {
+    INTERFACES
+        .lock()
+        .iter()
+        .position(|i| i.name.as_bytes() == name)
+}
+
+/// Returns the index of the interface whose address is `addr`.
+pub fn find_by_addr(addr: Ipv4Addr) -> Option<usize> {
+    INTERFACES.lock().iter().position(|i| i.addr == addr)
+}
+
+/// Returns the name of the interface `index`.
+pub fn name(index: usize) -> Option<&'static str> {
+    INTERFACES.lock().get(index).map(|i| i.name)
+}
+
+/// Returns the address of the interface `index`.
+pub fn addr(index: usize) -> Option<Ipv4Addr> {
+    INTERFACES.lock().get(index).map(|i| i.addr)
+}
+
+/// Returns whether `addr` is the address of an interface, or a broadcast address of its
+/// network.
+pub fn is_local(addr: Ipv4Addr) -> bool {
+    addr == Ipv4Addr::BROADCAST
+        || INTERFACES.lock().iter().any(|i| {
+            let broadcast = i.addr.to_bits() | !mask(i.prefix_len);
+            addr == i.addr || (i.prefix_len < 31 && addr.to_bits() == broadcast)
+        })
+}
+
+/// Calls `f` with every interface.
+pub fn for_each(f: impl FnMut(&Interface)) {
+    INTERFACES.lock().iter().for_each(f);
+}
+
+/// Sends `packet` through the interface `index`.
+///
+/// Fails with [`KernelError::MessageTooLong`] if the packet is larger than the MTU of its
+/// link.
+pub fn transmit(index: usize, packet: &[u8]) -> Result<(), KernelError> {
+    let mut interfaces = INTERFACES.lock();
+    let interface = interfaces.get_mut(index).ok_or(KernelError::NotFound)?;
+    let link = interface.link;
+    if packet.len() > link.mtu() {
+        return Err(KernelError::MessageTooLong);
+    }
+    interface.stats.tx_packets += 1;
+    drop(interfaces);
+
+    // Sending takes a while on slow links, so it is done without the lock.
+    link.send(packet);
+    Ok(())
+}
+
+/// The `knet` thread, which hands the packets received by the interfaces to the IP layer.
+fn poll() {
+    let mut packet = [0; MAX_MTU];
+    loop {
+        let count = INTERFACES.lock().len();
+        for index in 0..count {
+            loop {
+                let received = INTERFACES
+                    .lock()
+                    .get_mut(index)
+                    .and_then(|i| i.poll(&mut packet));
+                match received {
+                    None => break,
+                    Some(0) => (),
+                    Some(len) => ipv4::receive(index, &packet[..len]),
+                }
+            }
+        }
+        sched::block(Some(time::monotonic_ns() + POLL_PERIOD_NS));
+    }
+}
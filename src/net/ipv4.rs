@@ -0,0 +1,143 @@
+use core::sync::atomic::AtomicU16;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::error::KernelError;
+use crate::utility::{internet_checksum, InternetChecksum};
+
+use super::addr::Ipv4Addr;
+use super::{icmp, interface, route, tcp, udp, MAX_MTU};
+
+/// The protocol number of ICMP.
+pub const PROTOCOL_ICMP: u8 = 1;
+
+/// The protocol number of TCP.
+pub const PROTOCOL_TCP: u8 = 6;
+
+/// The protocol number of UDP.
+pub const PROTOCOL_UDP: u8 = 17;
+
+/// The length of the headers sent by the kernel, which have no options.
+pub const HEADER_LEN: usize = 20;
+
+/// The time to live of the packets sent by the kernel.
+const DEFAULT_TTL: u8 = 64;
+
+/// The flag of the IP header telling that more fragments follow.
+const MORE_FRAGMENTS: u16 = 1 << 13;
+
+/// The mask of the fragment offset, in the field that also holds the flags.
+const FRAGMENT_OFFSET: u16 = 0x1FFF;
+
+/// The identification of the next packet sent.
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+/// The fields of the header of a received packet that the upper layers care about.
+#[derive(Debug, Clone, Copy)]
+pub struct Header {
+    /// The address of the sender.
+    pub src: Ipv4Addr,
+    /// The address of the destination.
+    pub dst: Ipv4Addr,
+    /// The protocol of the payload.
+    pub protocol: u8,
+    /// The index of the interface the packet was received on.
+    pub interface: usize,
+}
+
+/// Handles a packet received on the interface `interface`.
+///
+/// Invalid packets, fragments, and packets that are not meant for this host are dropped.
+pub fn receive(interface: usize, packet: &[u8]) {
+    let Some((header, payload)) = parse(interface, packet) else {
+        return;
+    };
+    if !interface::is_local(header.dst) {
+        return;
+    }
+
+    match header.protocol {
+        PROTOCOL_ICMP => icmp::receive(&header, payload),
+        PROTOCOL_TCP => tcp::receive(&header, payload),
+        PROTOCOL_UDP => udp::receive(&header, payload),
+        _ => (),
+    }
+}
+
+/// Checks the header of `packet`, and splits it into its header and its payload.
+fn parse(interface: usize, packet: &[u8]) -> Option<(Header, &[u8])> {
+    let &[version_ihl, _, ..] = packet else {
+        return None;
+    };
+    let header_len = (version_ihl & 0x0F) as usize * 4;
+    if version_ihl >> 4 != 4 || header_len < HEADER_LEN || packet.len() < header_len {
+        return None;
+    }
+    if internet_checksum(&packet[..header_len]) != 0 {
+        return None;
+    }
+
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    let fragment = u16::from_be_bytes([packet[6], packet[7]]);
+    if total_len < header_len || total_len > packet.len() {
+        return None;
+    }
+    // Fragments are not reassembled.
+    if fragment & (MORE_FRAGMENTS | FRAGMENT_OFFSET) != 0 {
+        return None;
+    }
+
+    let header = Header {
+        src: Ipv4Addr(packet[12..16].try_into().unwrap()),
+        dst: Ipv4Addr(packet[16..20].try_into().unwrap()),
+        protocol: packet[9],
+        interface,
+    };
+    Some((header, &packet[header_len..total_len]))
+}
+
+/// Returns the checksum of `payload`, sent from `src` to `dst` with the protocol number
+/// `protocol`, as computed by TCP and UDP.
+pub fn checksum(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> u16 {
+    let mut checksum = InternetChecksum::new();
+    checksum.update(&src.0);
+    checksum.update(&dst.0);
+    checksum.update(&[0, protocol]);
+    checksum.update(&(payload.len() as u16).to_be_bytes());
+    checksum.update(payload);
+    checksum.finish()
+}
+
+/// Returns the address packets sent to `dst` come from: the one of the interface its route
+/// goes through.
+pub fn source_for(dst: Ipv4Addr) -> Result<Ipv4Addr, KernelError> {
+    route::lookup(dst)
+        .and_then(|route| interface::addr(route.interface))
+        .ok_or(KernelError::NetworkUnreachable)
+}
+
+/// Sends `payload` to `dst` with the protocol number `protocol`.
+///
+/// The packet goes through the interface of the route to `dst`, and comes from its address.
+pub fn send(dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> Result<(), KernelError> {
+    let route = route::lookup(dst).ok_or(KernelError::NetworkUnreachable)?;
+    let src = interface::addr(route.interface).ok_or(KernelError::NetworkUnreachable)?;
+    let total_len = HEADER_LEN + payload.len();
+    if total_len > MAX_MTU {
+        return Err(KernelError::MessageTooLong);
+    }
+
+    let mut packet = [0; MAX_MTU];
+    packet[0] = 0x45; // version 4, 5 words of header
+    packet[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+    packet[4..6].copy_from_slice(&NEXT_ID.fetch_add(1, Relaxed).to_be_bytes());
+    packet[8] = DEFAULT_TTL;
+    packet[9] = protocol;
+    packet[12..16].copy_from_slice(&src.0);
+    packet[16..20].copy_from_slice(&dst.0);
+    let checksum = internet_checksum(&packet[..HEADER_LEN]);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    packet[HEADER_LEN..total_len].copy_from_slice(payload);
+
+    // Links are point-to-point, so the gateway of the route needs no link-level address.
+    interface::transmit(route.interface, &packet[..total_len])
+}
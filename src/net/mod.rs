@@ -0,0 +1,208 @@
+//! A minimal IPv4 stack.
+//!
+//! Packets are sent and received through network interfaces, each of which has a link and an
+//! address. The only kind of link is a serial line using SLIP, which lets machines without a
+//! supported network card reach the host they are connected to. The routing table selects the
+//! interface through which each packet goes.
+//!
+//! The links are polled by the `knet` thread, which is created once the first interface is.
+//!
+//! On top of IP, TCP connections are made through sockets, which both the kernel and user
+//! programs (through system calls) use. The kernel uses them to serve remote shells and a
+//! status page. UDP only carries the queries of the NTP client.
+
+mod addr;
+mod http;
+mod icmp;
+mod interface;
+mod ipv4;
+mod route;
+mod server;
+mod slip;
+mod sntp;
+pub mod tcp;
+mod telnet;
+mod udp;
+
+pub use self::addr::*;
+pub use self::http::COMMAND as HTTPD_COMMAND;
+pub use self::icmp::COMMAND as PING_COMMAND;
+pub use self::sntp::COMMAND as NTP_COMMAND;
+pub use self::tcp::{release_process, COMMAND as NETSTAT_COMMAND};
+pub use self::telnet::COMMAND as TELNETD_COMMAND;
+
+use self::route::Route;
+use crate::shell::{split_command, usage, Command, Shell};
+use crate::utility::{Column, Table};
+use crate::{printk, TERMINAL};
+
+/// The largest packet the links can send.
+pub const MAX_MTU: usize = slip::MTU;
+
+/// Starts the timers of the network stack, and configures the NTP client from the kernel
+/// command-line.
+pub fn init(cmdline: &[u8]) {
+    tcp::init();
+    sntp::init(cmdline);
+}
+
+/// The `ip` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"ip",
+    summary: "configure the network interfaces and the routing table",
+    usage: "ip [slip <tty> <address>/<prefix> | route [add <network>/<prefix> <interface> [via <gateway>] | del <network>/<prefix>]]",
+    details: "Without arguments, lists the network interfaces. `ip slip` creates an interface\n\
+              that sends packets over a serial port with SLIP, such as `ip slip ttyS1\n\
+              10.0.0.2/24`. On the host, the other end is attached with `slattach -p slip`.\n\
+              `ip route` lists, adds or removes routes. Changing the configuration requires\n\
+              being the super-user.",
+    handler: ip,
+};
+
+/// The `ip` command.
+fn ip(shell: &mut Shell, args: &[u8]) {
+    let (subcommand, args) = split_command(args);
+    let read_only = subcommand.is_empty() || (subcommand == b"route" && args.is_empty());
+    if !read_only && !shell.is_super_user() {
+        printk!("ip: permission denied\n");
+        shell.fail();
+        return;
+    }
+
+    let ok = match subcommand {
+        b"" => {
+            list_interfaces();
+            true
+        }
+        b"slip" => slip_command(args),
+        b"route" => route_command(args),
+        _ => false,
+    };
+    if !ok {
+        shell.fail();
+    }
+}
+
+/// Prints the network interfaces.
+fn list_interfaces() {
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::left("NAME", 5),
+            Column::left("LINK", 5),
+            Column::left("DEVICE", 7),
+            Column::left("ADDRESS", 18),
+            Column::right("RX", 8),
+            Column::right("TX", 8),
+            Column::right("ERRORS", 7),
+        ],
+    );
+    let _ = table.header();
+    interface::for_each(|i| {
+        let _ = table.row([
+            &i.name,
+            &i.link.name(),
+            &i.device,
+            &format_args!("{}/{}", i.addr, i.prefix_len),
+            &i.stats.rx_packets,
+            &i.stats.tx_packets,
+            &i.stats.rx_errors,
+        ]);
+    });
+}
+
+/// The `ip slip` command.
+fn slip_command(args: &[u8]) -> bool {
+    let (tty, addr) = split_command(args);
+    let Some((addr, prefix_len)) = parse_cidr(addr) else {
+        printk!("usage: {}\n", usage(b"ip"));
+        return false;
+    };
+    match interface::attach_slip(tty, addr, prefix_len) {
+        Ok(_) => true,
+        Err(err) => {
+            printk!("ip: {}: {err}\n", core::str::from_utf8(tty).unwrap_or("?"));
+            false
+        }
+    }
+}
+
+/// The `ip route` command.
+fn route_command(args: &[u8]) -> bool {
+    let (action, args) = split_command(args);
+    match action {
+        b"" => {
+            list_routes();
+            true
+        }
+        b"add" => {
+            let (network, args) = split_command(args);
+            let (name, args) = split_command(args);
+            let gateway = match split_command(args) {
+                (b"", _) => Some(None),
+                (b"via", gateway) => Ipv4Addr::parse(gateway).map(Some),
+                _ => None,
+            };
+            let (Some((dest, prefix_len)), Some(gateway)) = (parse_cidr(network), gateway) else {
+                printk!("usage: {}\n", usage(b"ip"));
+                return false;
+            };
+            let Some(interface) = interface::find(name) else {
+                printk!("ip: no such interface\n");
+                return false;
+            };
+            let route = Route {
+                dest,
+                prefix_len,
+                gateway,
+                interface,
+            };
+            match route::add(route) {
+                Ok(()) => true,
+                Err(err) => {
+                    printk!("ip: {err}\n");
+                    false
+                }
+            }
+        }
+        b"del" => match parse_cidr(args) {
+            Some((dest, prefix_len)) if route::remove(dest, prefix_len) => true,
+            Some(_) => {
+                printk!("ip: no such route\n");
+                false
+            }
+            None => {
+                printk!("usage: {}\n", usage(b"ip"));
+                false
+            }
+        },
+        _ => {
+            printk!("usage: {}\n", usage(b"ip"));
+            false
+        }
+    }
+}
+
+/// Prints the routing table.
+fn list_routes() {
+    let routes = route::all();
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::left("DESTINATION", 18),
+            Column::left("GATEWAY", 15),
+            Column::left("INTERFACE", 9),
+        ],
+    );
+    let _ = table.header();
+    for route in routes.iter() {
+        let gateway = route.gateway.unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let _ = table.row([
+            &format_args!("{}/{}", route.dest, route.prefix_len),
+            &gateway,
+            &interface::name(route.interface).unwrap_or("?"),
+        ]);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::error::KernelError;
+use crate::utility::{ArrayVec, Mutex};
+
+use super::addr::{mask, Ipv4Addr};
+
+/// The maximum number of routes in the routing table.
+pub const MAX_ROUTES: usize = 16;
+
+/// A route of the routing table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    /// The network that the route leads to.
+    pub dest: Ipv4Addr,
+    /// The length of the prefix of the network.
+    pub prefix_len: u8,
+    /// The host packets are sent to, when the network is not directly reachable.
+    pub gateway: Option<Ipv4Addr>,
+    /// The index of the interface the packets are sent through.
+    pub interface: usize,
+}
+
+/// The routing table.
+static ROUTES: Mutex<ArrayVec<Route, MAX_ROUTES>> = Mutex::new(ArrayVec::new());
+
+/// Adds a route to the routing table.
+///
+/// The host bits of the destination are cleared. Fails if a route to the same network exists
+/// already, or if the table is full.
+pub fn add(mut route: Route) -> Result<(), KernelError> {
+    route.dest = Ipv4Addr::from_bits(route.dest.to_bits() & mask(route.prefix_len));
+    let mut routes = ROUTES.lock();
+    if routes
+        .iter()
+        .any(|r| r.dest == route.dest && r.prefix_len == route.prefix_len)
+    {
+        return Err(KernelError::AlreadyExists);
+    }
+    routes.try_push(route).map_err(|_| KernelError::NoSpace)
+}
+
+/// Removes the route to the network `dest/prefix_len`.
+///
+/// Returns whether there was one.
+pub fn remove(dest: Ipv4Addr, prefix_len: u8) -> bool {
+    let dest = Ipv4Addr::from_bits(dest.to_bits() & mask(prefix_len));
+    let mut routes = ROUTES.lock();
+    let Some(index) = routes
+        .iter()
+        .position(|r| r.dest == dest && r.prefix_len == prefix_len)
+    else {
+        return false;
+    };
+    routes.remove_range(index..=index);
+    true
+}
+
+/// Returns the route that packets sent to `dest` follow: the one with the longest prefix that
+/// matches, whatever the kind of link of its interface.
+pub fn lookup(dest: Ipv4Addr) -> Option<Route> {
+    ROUTES
+        .lock()
+        .iter()
+        .filter(|r| dest.is_in(r.dest, r.prefix_len))
+        .max_by_key(|r| r.prefix_len)
+        .copied()
+}
+
+/// Returns the routes of the routing table.
+pub fn all() -> ArrayVec<Route, MAX_ROUTES> {
+    ROUTES.lock().iter().copied().collect()
+}
@@ -0,0 +1,295 @@
+//! The servers of the kernel.
+//!
+//! A server listens on a TCP port and handles its clients one after the other on its own
+//! kernel thread, while the others wait in the backlog of the listening socket. Servers are
+//! started and stopped from the shell, with a command of the same name.
+//!
+//! The servers that give their clients a shell must be bound to a single interface, and run
+//! the shells as `nobody` unless they are started with `--insecure-root`. Either way, these
+//! shells refuse the commands that affect the machine or the console.
+
+use core::fmt::{self, Write};
+
+use crate::error::KernelError;
+use crate::shell::{parse_u32, split_command, usage, Shell};
+use crate::state::{UserId, NOBODY};
+use crate::utility::{ArrayVec, Mutex};
+use crate::{kthread, log, printk};
+
+use super::addr::{Ipv4Addr, SocketAddr};
+use super::interface;
+use super::tcp::{self, SocketId, MAX_BACKLOG};
+
+/// The size of the buffer of a [`Writer`].
+const WRITER_LEN: usize = 256;
+
+/// The state of a running server.
+#[derive(Debug, Clone, Copy)]
+struct Listener {
+    /// The listening socket.
+    socket: SocketId,
+    /// The address the server listens on.
+    addr: SocketAddr,
+    /// The user its clients are handled as.
+    user: UserId,
+    /// Whether the server was asked to stop.
+    stopping: bool,
+}
+
+/// A server, which runs a function for each client connecting to its port.
+pub struct Server {
+    /// The name of the server, which is also the name of its shell command.
+    name: &'static str,
+    /// The name of the thread of the server.
+    thread: &'static str,
+    /// The port the server listens on when none is given.
+    default_port: u16,
+    /// Whether the server gives its clients a shell.
+    shell: bool,
+    /// The function the thread of the server runs, which must call [`Server::serve`].
+    entry: fn(),
+    /// Handles a client, given its socket and the user it is handled as.
+    handler: fn(SocketId, UserId),
+    /// The socket the server listens on, while it runs.
+    listener: Mutex<Option<Listener>>,
+}
+
+impl Server {
+    /// Creates a new [`Server`] instance, which is not running.
+    ///
+    /// `shell` tells whether the server gives its clients a shell. See the
+    /// [module documentation](self).
+    pub const fn new(
+        name: &'static str,
+        thread: &'static str,
+        default_port: u16,
+        shell: bool,
+        entry: fn(),
+        handler: fn(SocketId, UserId),
+    ) -> Self {
+        Self {
+            name,
+            thread,
+            default_port,
+            shell,
+            entry,
+            handler,
+            listener: Mutex::new(None),
+        }
+    }
+
+    /// Starts the server on `addr`, handling its clients as `user`.
+    fn start(&self, addr: SocketAddr, user: UserId) -> Result<(), KernelError> {
+        let mut listener = self.listener.lock();
+        if listener.is_some() {
+            return Err(KernelError::Busy);
+        }
+        let socket = tcp::open()?;
+        let result = tcp::bind(socket, addr).and_then(|()| tcp::listen(socket, MAX_BACKLOG as u32));
+        if let Err(err) = result {
+            let _ = tcp::close(socket);
+            return Err(err);
+        }
+        *listener = Some(Listener {
+            socket,
+            addr,
+            user,
+            stopping: false,
+        });
+        drop(listener);
+
+        if kthread::spawn(self.thread, self.entry).is_err() {
+            *self.listener.lock() = None;
+            let _ = tcp::close(socket);
+            return Err(KernelError::NoSpace);
+        }
+        Ok(())
+    }
+
+    /// Returns the address the server listens on, while it runs.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.listener.lock().map(|l| l.addr)
+    }
+
+    /// Stops the server.
+    ///
+    /// The client being served, if any, is not interrupted.
+    fn stop(&self) -> Result<(), KernelError> {
+        let mut guard = self.listener.lock();
+        let listener = match &mut *guard {
+            Some(listener) if !listener.stopping => listener,
+            _ => return Err(KernelError::NotFound),
+        };
+        listener.stopping = true;
+        let socket = listener.socket;
+        drop(guard);
+        // This makes `accept` fail in the thread of the server, which then exits.
+        tcp::close(socket)
+    }
+
+    /// Serves the clients of the server one after the other, until it is stopped.
+    pub fn serve(&self) {
+        loop {
+            let Some(listener) = *self.listener.lock() else {
+                return;
+            };
+            if listener.stopping {
+                break;
+            }
+            let (client, peer) = match tcp::accept(listener.socket) {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    if !self.listener.lock().is_some_and(|l| l.stopping) {
+                        log!("{}: failed to accept a connection: {err}.\n", self.name);
+                        let _ = tcp::close(listener.socket);
+                    }
+                    break;
+                }
+            };
+
+            log!("{}: connection from {peer}.\n", self.name);
+            (self.handler)(client, listener.user);
+            let _ = tcp::close(client);
+            log!("{}: {peer} disconnected.\n", self.name);
+        }
+        *self.listener.lock() = None;
+    }
+
+    /// Runs the shell command of the server.
+    ///
+    /// Without arguments, tells whether the server is running. `start` starts it on its
+    /// default port, a number starts it on that port, and `stop` stops it. When starting, the
+    /// name of an interface restricts the server to its address. Starting and stopping require
+    /// being the super-user.
+    pub fn command(&self, shell: &mut Shell, args: &[u8]) {
+        let name = self.name;
+        let (arg, mut rest) = split_command(args);
+        let mut interface = None;
+        let mut insecure_root = false;
+        while !rest.is_empty() {
+            let (word, next) = split_command(rest);
+            match word {
+                b"--insecure-root" if self.shell => insecure_root = true,
+                _ if interface.is_none() && !word.starts_with(b"-") => interface = Some(word),
+                _ => return usage_error(shell, name),
+            }
+            rest = next;
+        }
+        if matches!(arg, b"" | b"stop") && (interface.is_some() || insecure_root) {
+            return usage_error(shell, name);
+        }
+        if arg.is_empty() {
+            match *self.listener.lock() {
+                Some(l) if !l.stopping => printk!("{name}: listening on {}\n", l.addr),
+                Some(_) => printk!("{name}: stopping\n"),
+                None => printk!("{name}: not running\n"),
+            }
+            return;
+        }
+        if !shell.is_super_user() {
+            printk!("{name}: permission denied\n");
+            shell.fail();
+            return;
+        }
+
+        let port = match arg {
+            b"stop" => None,
+            b"start" => Some(self.default_port),
+            _ => match parse_u32(arg).and_then(|p| u16::try_from(p).ok()) {
+                Some(port) if port != 0 => Some(port),
+                _ => return usage_error(shell, name),
+            },
+        };
+        let result = match port {
+            None => self.stop(),
+            Some(port) => {
+                let addr = match interface {
+                    Some(iface) => match interface::find(iface).and_then(interface::addr) {
+                        Some(addr) => addr,
+                        None => {
+                            let iface = core::str::from_utf8(iface).unwrap_or("<invalid utf-8>");
+                            printk!("{name}: {iface}: no such interface\n");
+                            shell.fail();
+                            return;
+                        }
+                    },
+                    // A shell must not become reachable from every network by accident.
+                    None if self.shell => {
+                        printk!("{name}: the interface to listen on must be given\n");
+                        shell.fail();
+                        return;
+                    }
+                    None => Ipv4Addr::UNSPECIFIED,
+                };
+                let user = if self.shell && !insecure_root {
+                    NOBODY
+                } else {
+                    shell.user()
+                };
+                self.start(SocketAddr::new(addr, port), user)
+            }
+        };
+        if let Err(err) = result {
+            printk!("{name}: {err}\n");
+            shell.fail();
+        }
+    }
+}
+
+/// Prints the usage of the server command `name`, and makes the command fail.
+fn usage_error(shell: &mut Shell, name: &str) {
+    printk!("usage: {}\n", usage(name.as_bytes()));
+    shell.fail();
+}
+
+/// Buffers the bytes sent through a connection.
+pub struct Writer {
+    /// The socket of the connection.
+    socket: SocketId,
+    /// The bytes that were not sent yet.
+    buffer: ArrayVec<u8, WRITER_LEN>,
+    /// Whether sending failed, in which case the connection is lost.
+    failed: bool,
+}
+
+impl Writer {
+    /// Creates a new [`Writer`] instance, which sends to `socket`.
+    pub const fn new(socket: SocketId) -> Self {
+        Self {
+            socket,
+            buffer: ArrayVec::new(),
+            failed: false,
+        }
+    }
+
+    /// Queues bytes to be sent.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.buffer.is_full() {
+                self.flush();
+            }
+            self.buffer.push(byte);
+        }
+    }
+
+    /// Sends the queued bytes.
+    pub fn flush(&mut self) {
+        if !self.failed && tcp::send(self.socket, &self.buffer).is_err() {
+            self.failed = true;
+        }
+        self.buffer.clear();
+    }
+
+    /// Returns whether sending failed.
+    #[inline]
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+impl Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+//! SLIP (RFC 1055), which sends IP packets over a serial line.
+//!
+//! Each packet is followed by an [`END`] byte. The [`END`] and [`ESC`] bytes of the packet are
+//! replaced by two-byte sequences, so that the end of the packets can always be found. On the
+//! host, the other end of the line is attached with `slattach -p slip`.
+
+use crate::drivers::serial::Uart;
+
+/// The byte that ends a packet.
+const END: u8 = 0xC0;
+
+/// The byte that starts the sequences replacing [`END`] and [`ESC`] bytes.
+const ESC: u8 = 0xDB;
+
+/// Stands for an [`END`] byte of the packet, after an [`ESC`] byte.
+const ESC_END: u8 = 0xDC;
+
+/// Stands for an [`ESC`] byte of the packet, after an [`ESC`] byte.
+const ESC_ESC: u8 = 0xDD;
+
+/// The largest packet sent or received over SLIP, which is the usual MTU of SLIP lines.
+pub const MTU: usize = 1006;
+
+/// Sends `packet` through `uart`.
+pub fn send(uart: Uart, packet: &[u8]) {
+    // Starting with an END byte flushes the noise that may have been received by the other
+    // end since the previous packet.
+    uart.write_byte(END);
+    for &byte in packet {
+        match byte {
+            END => uart.write_bytes(&[ESC, ESC_END]),
+            ESC => uart.write_bytes(&[ESC, ESC_ESC]),
+            _ => uart.write_byte(byte),
+        }
+    }
+    uart.write_byte(END);
+}
+
+/// Assembles the bytes received from a serial line into packets.
+pub struct Decoder {
+    /// The bytes of the packet being received.
+    packet: [u8; MTU],
+    /// The number of bytes of `packet` that were received.
+    len: usize,
+    /// Whether the previous byte was [`ESC`].
+    escaped: bool,
+    /// Whether the packet being received is too large, and is being dropped.
+    overflowed: bool,
+}
+
+impl Decoder {
+    /// Creates a new [`Decoder`] instance, waiting for the start of a packet.
+    pub const fn new() -> Self {
+        Self {
+            packet: [0; MTU],
+            len: 0,
+            escaped: false,
+            overflowed: false,
+        }
+    }
+
+    /// Feeds a byte received from the serial line to the decoder.
+    ///
+    /// Returns the length of the packet when `byte` ends it, after which the packet can be read
+    /// with [`packet`](Self::packet). `Some(0)` is returned for packets that were dropped
+    /// because they were too large.
+    pub fn advance(&mut self, byte: u8) -> Option<usize> {
+        if byte == END {
+            let (len, overflowed) = (self.len, self.overflowed);
+            self.len = 0;
+            self.escaped = false;
+            self.overflowed = false;
+            return match (len, overflowed) {
+                (_, true) => Some(0),
+                // Empty packets are the separators sent before packets.
+                (0, false) => None,
+                (len, false) => Some(len),
+            };
+        }
+
+        let byte = match (core::mem::take(&mut self.escaped), byte) {
+            (false, ESC) => {
+                self.escaped = true;
+                return None;
+            }
+            (true, ESC_END) => END,
+            (true, ESC_ESC) => ESC,
+            (_, byte) => byte,
+        };
+        match self.packet.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+            }
+            None => self.overflowed = true,
+        }
+        None
+    }
+
+    /// Returns the last packet that was received, whose length was returned by
+    /// [`advance`](Self::advance).
+    #[inline]
+    pub fn packet(&self, len: usize) -> &[u8] {
+        &self.packet[..len]
+    }
+}
@@ -0,0 +1,295 @@
+//! A client of the Simple Network Time Protocol, which keeps the wall clock in sync with an
+//! NTP server.
+//!
+//! The server is queried every [`POLL_PERIOD_NS`], and again after [`RETRY_PERIOD_NS`] when it
+//! does not reply. The offset it measures is passed to [`time::adjust`], which slews the wall
+//! clock when the offset is small, and steps it otherwise.
+
+use crate::shell::{split_command, usage, Command, Shell};
+use crate::trace::trace;
+use crate::utility::Mutex;
+use crate::{cmdline, kthread, log, printk, time, timer};
+
+use super::addr::{Ipv4Addr, SocketAddr};
+use super::udp;
+
+/// The port of the client, which is also the port of NTP servers.
+pub const CLIENT_PORT: u16 = 123;
+
+/// The port of NTP servers.
+const SERVER_PORT: u16 = 123;
+
+/// The length of NTP messages without extensions.
+const MESSAGE_LEN: usize = 48;
+
+/// The mode of the messages sent by clients.
+const MODE_CLIENT: u8 = 3;
+
+/// The mode of the messages sent by servers.
+const MODE_SERVER: u8 = 4;
+
+/// The version of the protocol.
+const VERSION: u8 = 4;
+
+/// The leap indicator of servers whose clock is not synchronized.
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+
+/// The number of seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const UNIX_EPOCH: u64 = 2_208_988_800;
+
+/// The time between two successful queries, in nanoseconds.
+const POLL_PERIOD_NS: u64 = 1024 * time::NANOS_PER_SECOND;
+
+/// The time after which a query that got no reply is sent again, in nanoseconds.
+const RETRY_PERIOD_NS: u64 = 16 * time::NANOS_PER_SECOND;
+
+/// The period at which the timer checks whether a query is due.
+const TIMER_PERIOD_MS: u32 = 1000;
+
+/// The state of the client.
+static CLIENT: Mutex<Client> = Mutex::new(Client {
+    server: None,
+    next_query: 0,
+    pending: None,
+    last: None,
+});
+
+/// The state of the client.
+struct Client {
+    /// The server that is queried, if any.
+    server: Option<Ipv4Addr>,
+    /// The value of the monotonic clock at which the next query is sent.
+    next_query: u64,
+    /// The query waiting for its reply, if any.
+    pending: Option<Query>,
+    /// The outcome of the last successful query, if any.
+    last: Option<Sample>,
+}
+
+/// A query waiting for its reply.
+#[derive(Debug, Clone, Copy)]
+struct Query {
+    /// The transmit timestamp of the request, which the reply echoes.
+    timestamp: u64,
+    /// The Unix time at which the request was sent, in nanoseconds.
+    sent: u64,
+}
+
+/// What a successful query measured.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// The value of the monotonic clock when the reply was received.
+    received: u64,
+    /// The offset of the wall clock, in nanoseconds: positive when it was late.
+    offset: i64,
+    /// The round-trip delay of the query, in nanoseconds.
+    delay: i64,
+    /// The stratum of the server.
+    stratum: u8,
+    /// How the wall clock was corrected.
+    adjustment: time::Adjustment,
+}
+
+/// Converts a Unix time in nanoseconds to an NTP timestamp.
+fn to_ntp(unix_ns: u64) -> u64 {
+    let secs = unix_ns / time::NANOS_PER_SECOND + UNIX_EPOCH;
+    let fraction = ((unix_ns % time::NANOS_PER_SECOND) << 32) / time::NANOS_PER_SECOND;
+    secs << 32 | fraction
+}
+
+/// Converts an NTP timestamp to a Unix time in nanoseconds.
+fn from_ntp(timestamp: u64) -> u64 {
+    let secs = (timestamp >> 32).saturating_sub(UNIX_EPOCH);
+    let fraction = ((timestamp & 0xFFFF_FFFF) * time::NANOS_PER_SECOND) >> 32;
+    secs * time::NANOS_PER_SECOND + fraction
+}
+
+/// Reads the timestamp at `offset` in `message`.
+fn timestamp(message: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(message[offset..offset + 8].try_into().unwrap())
+}
+
+/// Configures the server from the `ntp=<address>` option of the kernel command-line, and
+/// starts the timer that sends the queries.
+pub fn init(cmdline: &[u8]) {
+    if let Some(addr) = cmdline::get(cmdline, b"ntp") {
+        match Ipv4Addr::parse(addr) {
+            Some(addr) => CLIENT.lock().server = Some(addr),
+            None => log!("Invalid NTP server requested on the command-line.\n"),
+        }
+    }
+
+    if timer::register(TIMER_PERIOD_MS, check_timer).is_none() {
+        log!("Failed to register the NTP timer.\n");
+    }
+}
+
+/// Queues [`query`] if a query is due.
+///
+/// This is called from the timer interrupt.
+fn check_timer() {
+    let mut client = CLIENT.lock();
+    let now = time::monotonic_ns();
+    if client.server.is_none() || now < client.next_query {
+        return;
+    }
+    client.next_query = now + RETRY_PERIOD_NS;
+    drop(client);
+    kthread::defer(query);
+}
+
+/// Sends a request to the server.
+fn query() {
+    let sent = time::unix_time_ns();
+    let timestamp = to_ntp(sent);
+    let mut client = CLIENT.lock();
+    let Some(server) = client.server else {
+        return;
+    };
+    client.pending = Some(Query { timestamp, sent });
+    drop(client);
+
+    let mut request = [0; MESSAGE_LEN];
+    request[0] = VERSION << 3 | MODE_CLIENT;
+    request[40..48].copy_from_slice(&timestamp.to_be_bytes());
+    if let Err(err) = udp::send(CLIENT_PORT, SocketAddr::new(server, SERVER_PORT), &request) {
+        trace!("ntp", "cannot reach {server}: {err}");
+    }
+}
+
+/// Handles a message received from `src`.
+pub fn receive(src: SocketAddr, message: &[u8]) {
+    let received = time::unix_time_ns();
+    if message.len() < MESSAGE_LEN || src.port != SERVER_PORT {
+        return;
+    }
+    let (leap, mode, stratum) = (message[0] >> 6, message[0] & 7, message[1]);
+
+    let mut client = CLIENT.lock();
+    let Some(query) = client.pending else {
+        return;
+    };
+    if client.server != Some(src.addr) || mode != MODE_SERVER {
+        return;
+    }
+    if timestamp(message, 24) != query.timestamp {
+        return;
+    }
+    client.pending = None;
+    // Unsynchronized servers and "kiss-o'-death" replies are ignored until the next poll.
+    if leap == LEAP_UNSYNCHRONIZED || stratum == 0 || stratum >= 16 {
+        return;
+    }
+
+    let (t1, t4) = (query.sent as i128, received as i128);
+    let t2 = from_ntp(timestamp(message, 32)) as i128;
+    let t3 = from_ntp(timestamp(message, 40)) as i128;
+    let offset = ((t2 - t1) + (t3 - t4)) / 2;
+    let delay = (t4 - t1) - (t3 - t2);
+    let Ok(offset) = i64::try_from(offset) else {
+        return;
+    };
+    let now = time::monotonic_ns();
+    client.next_query = now + POLL_PERIOD_NS;
+    drop(client);
+
+    let adjustment = time::adjust(offset);
+    if adjustment == time::Adjustment::Stepped {
+        log!("ntp: stepped the clock by {} ms.\n", offset / 1_000_000);
+    }
+    trace!("ntp", "offset {offset} ns, {adjustment:?}");
+    CLIENT.lock().last = Some(Sample {
+        received: now,
+        offset,
+        delay: delay as i64,
+        stratum,
+        adjustment,
+    });
+}
+
+/// The `ntp` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"ntp",
+    summary: "keep the wall clock in sync with an NTP server",
+    usage: "ntp [server <address> | sync | off]",
+    details: "Without arguments, shows the server and the outcome of the last query. `ntp\n\
+              server` sets the server, which can also be set at boot with `ntp=<address>`, and\n\
+              queries it right away. `ntp sync` queries it again, and `ntp off` stops querying\n\
+              it. Offsets up to 128 ms are slewed, larger ones step the clock. Changing the\n\
+              configuration requires being the super-user.",
+    handler: ntp,
+};
+
+/// The `ntp` command.
+fn ntp(shell: &mut Shell, args: &[u8]) {
+    let (subcommand, args) = split_command(args);
+    if subcommand.is_empty() {
+        show_status();
+        return;
+    }
+    if !shell.is_super_user() {
+        printk!("ntp: permission denied\n");
+        shell.fail();
+        return;
+    }
+
+    let mut client = CLIENT.lock();
+    match (subcommand, Ipv4Addr::parse(args)) {
+        (b"server", Some(addr)) => {
+            client.server = Some(addr);
+            client.pending = None;
+            client.last = None;
+            client.next_query = 0;
+        }
+        (b"sync", _) if args.is_empty() => match client.server {
+            Some(_) => client.next_query = 0,
+            None => {
+                drop(client);
+                printk!("ntp: no server\n");
+                shell.fail();
+            }
+        },
+        (b"off", _) if args.is_empty() => {
+            client.server = None;
+            client.pending = None;
+        }
+        _ => {
+            drop(client);
+            printk!("usage: {}\n", usage(b"ntp"));
+            shell.fail();
+        }
+    }
+}
+
+/// Prints the server and the outcome of the last query.
+fn show_status() {
+    let client = CLIENT.lock();
+    let (server, last) = (client.server, client.last);
+    drop(client);
+
+    match server {
+        Some(server) => printk!("server: {server}\n"),
+        None => printk!("server: none\n"),
+    }
+    match last {
+        Some(last) => {
+            let ago = (time::monotonic_ns() - last.received) / time::NANOS_PER_SECOND;
+            let sign = if last.offset < 0 { "-" } else { "+" };
+            let offset = last.offset.unsigned_abs() / 1000;
+            let delay = last.delay.max(0) as u64 / 1000;
+            printk!(
+                "last sync: {ago} s ago, stratum {}, offset {sign}{}.{:03} ms ({}), delay {}.{:03} ms\n",
+                last.stratum,
+                offset / 1000,
+                offset % 1000,
+                match last.adjustment {
+                    time::Adjustment::Slewed => "slewed",
+                    time::Adjustment::Stepped => "stepped",
+                },
+                delay / 1000,
+                delay % 1000,
+            );
+        }
+        None => printk!("last sync: never\n"),
+    }
+}
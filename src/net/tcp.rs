@@ -0,0 +1,1188 @@
+use core::fmt::{self, Display};
+use core::ops::Range;
+use core::sync::atomic::AtomicU16;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::error::KernelError;
+use crate::shell::{Command, Shell};
+use crate::state::{ProcessId, WaitQueue, GLOBAL};
+use crate::utility::{ArrayVec, Column, Mutex, Table};
+use crate::{kthread, log, rng, time, timer, TERMINAL};
+
+use super::addr::{Ipv4Addr, SocketAddr};
+use super::ipv4::{self, Header, PROTOCOL_TCP};
+use super::{interface, MAX_MTU};
+
+/// The maximum number of TCP sockets, including the connections waiting to be accepted.
+pub const MAX_SOCKETS: usize = 8;
+
+/// The size of the send buffer and of the receive buffer of each socket, in bytes.
+///
+/// The receive window advertised to the peer is the free space of the receive buffer.
+pub const BUFFER_SIZE: usize = 2048;
+
+/// The maximum number of connections waiting to be accepted on a listening socket.
+pub const MAX_BACKLOG: u8 = 4;
+
+/// The length of the headers sent by the kernel, without options.
+const HEADER_LEN: usize = 20;
+
+/// The largest payload of the segments that the links can carry.
+const MAX_SEGMENT: usize = MAX_MTU - ipv4::HEADER_LEN - HEADER_LEN;
+
+/// The maximum segment size assumed when the peer does not announce one.
+const DEFAULT_MSS: u16 = 536;
+
+/// The flag of the last segment sent by a host.
+const FIN: u8 = 0x01;
+/// The flag of the segments that open a connection.
+const SYN: u8 = 0x02;
+/// The flag of the segments that abort a connection.
+const RST: u8 = 0x04;
+/// The flag asking the receiver to hand the data to the application right away.
+const PSH: u8 = 0x08;
+/// The flag telling that the acknowledgement number is valid.
+const ACK: u8 = 0x10;
+
+/// The option that ends the list of options.
+const OPTION_END: u8 = 0;
+/// The option used to align the following ones.
+const OPTION_NOP: u8 = 1;
+/// The option announcing the maximum segment size, found in `SYN` segments.
+const OPTION_MSS: u8 = 2;
+
+/// The period of the timer that checks for expired retransmission timers.
+const TIMER_PERIOD_MS: u32 = 100;
+
+/// The retransmission timeout of a new connection, in nanoseconds.
+const INITIAL_RTO_NS: u64 = time::NANOS_PER_SECOND;
+
+/// The retransmission timeout never grows past this, in nanoseconds.
+const MAX_RTO_NS: u64 = 32 * time::NANOS_PER_SECOND;
+
+/// The number of retransmissions after which a connection is dropped.
+const MAX_RETRIES: u8 = 8;
+
+/// The time a connection stays in [`State::TimeWait`], in nanoseconds.
+///
+/// This is much shorter than the usual two minutes, as the hosts the kernel talks to are on
+/// the other end of a serial line.
+const TIME_WAIT_NS: u64 = 4 * time::NANOS_PER_SECOND;
+
+/// The ports given to the sockets that are not bound explicitly.
+const EPHEMERAL_PORTS: Range<u16> = 49152..u16::MAX;
+
+/// The next ephemeral port to try.
+static NEXT_PORT: AtomicU16 = AtomicU16::new(EPHEMERAL_PORTS.start);
+
+/// The ID of a socket.
+///
+/// This is the index of the socket in the list of sockets. The ID of a socket that was closed
+/// may be reused by another one.
+pub type SocketId = u32;
+
+/// The state of a TCP connection, as described by RFC 793.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// The socket is not connected, or the connection is over.
+    Closed,
+    /// The socket accepts connections.
+    Listen,
+    /// A `SYN` was sent, and the reply of the peer is awaited.
+    SynSent,
+    /// A `SYN` was received and answered, and the acknowledgement of the peer is awaited.
+    SynReceived,
+    /// The connection is open in both directions.
+    Established,
+    /// The socket was closed, and its `FIN` is not acknowledged yet.
+    FinWait1,
+    /// The `FIN` of the socket was acknowledged, and the one of the peer is awaited.
+    FinWait2,
+    /// The peer closed its side, and the socket may still send data.
+    CloseWait,
+    /// Both sides sent their `FIN` at the same time.
+    Closing,
+    /// The peer closed its side first, and the `FIN` of the socket is not acknowledged yet.
+    LastAck,
+    /// The connection is over, and the socket waits in case the peer retransmits its `FIN`.
+    TimeWait,
+}
+
+impl State {
+    /// Returns the name of the state, as in RFC 793.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Closed => "CLOSED",
+            Self::Listen => "LISTEN",
+            Self::SynSent => "SYN-SENT",
+            Self::SynReceived => "SYN-RECEIVED",
+            Self::Established => "ESTABLISHED",
+            Self::FinWait1 => "FIN-WAIT-1",
+            Self::FinWait2 => "FIN-WAIT-2",
+            Self::CloseWait => "CLOSE-WAIT",
+            Self::Closing => "CLOSING",
+            Self::LastAck => "LAST-ACK",
+            Self::TimeWait => "TIME-WAIT",
+        }
+    }
+
+    /// Returns whether data received in this state is handed to the application.
+    fn receives(self) -> bool {
+        matches!(self, Self::Established | Self::FinWait1 | Self::FinWait2)
+    }
+
+    /// Returns whether the application may send data in this state.
+    fn sends(self) -> bool {
+        matches!(self, Self::Established | Self::CloseWait)
+    }
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A segment ready to be sent.
+struct Segment {
+    /// The host the segment is sent to.
+    dst: Ipv4Addr,
+    /// The length of the segment, header included.
+    len: usize,
+    /// The segment.
+    buf: [u8; HEADER_LEN + MAX_SEGMENT],
+}
+
+impl Segment {
+    /// Builds a segment sent from `src` to `dst`.
+    ///
+    /// `SYN` segments announce the maximum segment size of the kernel.
+    fn new(src: SocketAddr, dst: SocketAddr, fields: Fields, data: &[u8]) -> Self {
+        let header_len = if fields.flags & SYN != 0 {
+            HEADER_LEN + 4
+        } else {
+            HEADER_LEN
+        };
+        let len = header_len + data.len();
+
+        let mut buf = [0; HEADER_LEN + MAX_SEGMENT];
+        buf[0..2].copy_from_slice(&src.port.to_be_bytes());
+        buf[2..4].copy_from_slice(&dst.port.to_be_bytes());
+        buf[4..8].copy_from_slice(&fields.seq.to_be_bytes());
+        buf[8..12].copy_from_slice(&fields.ack.to_be_bytes());
+        buf[12] = (header_len as u8 / 4) << 4;
+        buf[13] = fields.flags;
+        buf[14..16].copy_from_slice(&fields.window.to_be_bytes());
+        if fields.flags & SYN != 0 {
+            buf[20] = OPTION_MSS;
+            buf[21] = 4;
+            buf[22..24].copy_from_slice(&(MAX_SEGMENT as u16).to_be_bytes());
+        }
+        buf[header_len..len].copy_from_slice(data);
+        let checksum = ipv4::checksum(src.addr, dst.addr, PROTOCOL_TCP, &buf[..len]);
+        buf[16..18].copy_from_slice(&checksum.to_be_bytes());
+
+        Self {
+            dst: dst.addr,
+            len,
+            buf,
+        }
+    }
+
+    /// Sends the segment.
+    ///
+    /// Lost segments are retransmitted, so errors are ignored.
+    fn transmit(&self) {
+        let _ = ipv4::send(self.dst, PROTOCOL_TCP, &self.buf[..self.len]);
+    }
+}
+
+/// The fields of a segment header that change from one segment to the other.
+#[derive(Debug, Clone, Copy)]
+struct Fields {
+    /// The sequence number of the first byte of the segment.
+    seq: u32,
+    /// The next sequence number expected from the receiver of the segment.
+    ack: u32,
+    /// The flags of the segment.
+    flags: u8,
+    /// The free space of the receive buffer of the sender.
+    window: u16,
+}
+
+/// A received segment.
+struct Incoming<'a> {
+    /// The socket that sent the segment.
+    src: SocketAddr,
+    /// The socket the segment is sent to.
+    dst: SocketAddr,
+    /// The fields of the header.
+    fields: Fields,
+    /// The maximum segment size announced by the sender, in `SYN` segments.
+    mss: Option<u16>,
+    /// The payload of the segment.
+    data: &'a [u8],
+}
+
+impl Incoming<'_> {
+    /// Returns whether the segment has the flags `flags`.
+    #[inline]
+    fn has(&self, flags: u8) -> bool {
+        self.fields.flags & flags != 0
+    }
+
+    /// Returns the number of sequence numbers taken by the segment.
+    fn seq_len(&self) -> u32 {
+        self.data.len() as u32 + self.has(SYN) as u32 + self.has(FIN) as u32
+    }
+
+    /// Returns the `RST` segment that answers this one, when it reached no socket.
+    fn reset(&self) -> Segment {
+        let fields = if self.has(ACK) {
+            Fields {
+                seq: self.fields.ack,
+                ack: 0,
+                flags: RST,
+                window: 0,
+            }
+        } else {
+            Fields {
+                seq: 0,
+                ack: self.fields.seq.wrapping_add(self.seq_len()),
+                flags: RST | ACK,
+                window: 0,
+            }
+        };
+        Segment::new(self.dst, self.src, fields, &[])
+    }
+}
+
+/// Checks the header of `segment`, received in a packet whose header is `header`.
+fn parse<'a>(header: &Header, segment: &'a [u8]) -> Option<Incoming<'a>> {
+    if segment.len() < HEADER_LEN
+        || ipv4::checksum(header.src, header.dst, PROTOCOL_TCP, segment) != 0
+    {
+        return None;
+    }
+    let header_len = (segment[12] >> 4) as usize * 4;
+    if header_len < HEADER_LEN || header_len > segment.len() {
+        return None;
+    }
+
+    let mut mss = None;
+    let mut options = &segment[HEADER_LEN..header_len];
+    while let &[kind, ref rest @ ..] = options {
+        match kind {
+            OPTION_END => break,
+            OPTION_NOP => options = rest,
+            _ => {
+                let len = *rest.first()? as usize;
+                if len < 2 || len > options.len() {
+                    return None;
+                }
+                if kind == OPTION_MSS && len == 4 {
+                    mss = Some(u16::from_be_bytes([options[2], options[3]]));
+                }
+                options = &options[len..];
+            }
+        }
+    }
+
+    let word = |i: usize| u32::from_be_bytes(segment[i..i + 4].try_into().unwrap());
+    let half = |i: usize| u16::from_be_bytes([segment[i], segment[i + 1]]);
+    Some(Incoming {
+        src: SocketAddr::new(header.src, half(0)),
+        dst: SocketAddr::new(header.dst, half(2)),
+        fields: Fields {
+            seq: word(4),
+            ack: word(8),
+            flags: segment[13],
+            window: half(14),
+        },
+        mss,
+        data: &segment[header_len..],
+    })
+}
+
+/// A TCP socket, along with its connection.
+struct Socket {
+    /// The process that created the socket, if any.
+    owner: Option<ProcessId>,
+    /// Whether the owner closed the socket, which is removed once its connection is over.
+    closed: bool,
+    /// The listening socket that received the connection, until it is accepted.
+    listener: Option<usize>,
+    /// The maximum number of connections waiting to be accepted, when listening.
+    backlog: u8,
+    /// The state of the connection.
+    state: State,
+    /// The error that ended the connection, if any.
+    error: Option<KernelError>,
+    /// The address the socket is bound to.
+    local: SocketAddr,
+    /// The address of the peer.
+    remote: SocketAddr,
+
+    /// The initial sequence number of the socket, taken by its `SYN`.
+    iss: u32,
+    /// The first sequence number that the peer did not acknowledge.
+    snd_una: u32,
+    /// The sequence number of the next byte to send.
+    snd_nxt: u32,
+    /// The number of bytes the peer accepts past `snd_una`.
+    snd_wnd: u16,
+    /// The largest segment the peer accepts.
+    mss: u16,
+    /// Whether a `FIN` is sent once the send buffer is empty.
+    fin_queued: bool,
+    /// The data that was not acknowledged yet, starting at `snd_una`.
+    send: ArrayVec<u8, BUFFER_SIZE>,
+
+    /// The next sequence number expected from the peer.
+    rcv_nxt: u32,
+    /// Whether the peer sent its `FIN`.
+    fin_received: bool,
+    /// The data received and not read by the application yet.
+    recv: ArrayVec<u8, BUFFER_SIZE>,
+
+    /// When the retransmission timer or the time-wait timer expires, in nanoseconds.
+    deadline: Option<u64>,
+    /// The current retransmission timeout, in nanoseconds.
+    rto: u64,
+    /// The number of retransmissions since the peer last acknowledged data.
+    retries: u8,
+}
+
+impl Socket {
+    /// Creates a socket that is not bound nor connected.
+    fn new(owner: Option<ProcessId>) -> Self {
+        Self {
+            owner,
+            closed: false,
+            listener: None,
+            backlog: 0,
+            state: State::Closed,
+            error: None,
+            local: SocketAddr::default(),
+            remote: SocketAddr::default(),
+            iss: 0,
+            snd_una: 0,
+            snd_nxt: 0,
+            snd_wnd: 0,
+            mss: DEFAULT_MSS,
+            fin_queued: false,
+            send: ArrayVec::new(),
+            rcv_nxt: 0,
+            fin_received: false,
+            recv: ArrayVec::new(),
+            deadline: None,
+            rto: INITIAL_RTO_NS,
+            retries: 0,
+        }
+    }
+
+    /// Returns the free space of the receive buffer.
+    fn window(&self) -> u16 {
+        (BUFFER_SIZE - self.recv.len()) as u16
+    }
+
+    /// Returns a segment with no data, the sequence number `seq`, and the flags `flags`.
+    fn control(&self, seq: u32, flags: u8) -> Segment {
+        let fields = Fields {
+            seq,
+            ack: self.rcv_nxt,
+            flags,
+            window: self.window(),
+        };
+        Segment::new(self.local, self.remote, fields, &[])
+    }
+
+    /// Returns a segment that acknowledges what was received.
+    fn ack(&self) -> Segment {
+        self.control(self.snd_nxt, ACK)
+    }
+
+    /// Starts the handshake: picks the initial sequence number, and moves to `state`.
+    fn open(&mut self, state: State) {
+        self.iss = rng::rand_u32();
+        self.snd_una = self.iss;
+        self.snd_nxt = self.iss.wrapping_add(1);
+        self.state = state;
+        self.arm();
+    }
+
+    /// Records what a `SYN` of the peer announced.
+    fn synchronize(&mut self, segment: &Incoming) {
+        self.rcv_nxt = segment.fields.seq.wrapping_add(1);
+        self.snd_wnd = segment.fields.window;
+        self.mss = segment
+            .mss
+            .unwrap_or(DEFAULT_MSS)
+            .min(MAX_SEGMENT as u16)
+            .max(1);
+    }
+
+    /// Starts the retransmission timer, unless it is running already.
+    fn arm(&mut self) {
+        if self.deadline.is_none() {
+            self.deadline = Some(time::monotonic_ns() + self.rto);
+        }
+    }
+
+    /// Ends the connection because of `err`.
+    fn fail(&mut self, err: KernelError) {
+        self.state = State::Closed;
+        self.error = Some(err);
+        self.deadline = None;
+    }
+
+    /// Enters [`State::TimeWait`].
+    fn time_wait(&mut self) {
+        self.state = State::TimeWait;
+        self.deadline = Some(time::monotonic_ns() + TIME_WAIT_NS);
+    }
+
+    /// Returns whether the `FIN` of the socket was sent.
+    fn fin_sent(&self) -> bool {
+        self.fin_queued && self.snd_nxt.wrapping_sub(self.snd_una) as usize > self.send.len()
+    }
+
+    /// Returns whether the socket can be removed: its connection is over, and nobody will use
+    /// it again.
+    fn is_dead(&self) -> bool {
+        self.state == State::Closed && (self.closed || self.listener.is_some())
+    }
+
+    /// Handles a segment received while in [`State::SynSent`].
+    fn syn_sent(&mut self, segment: &Incoming) -> Option<Segment> {
+        let acceptable = segment.fields.ack == self.snd_nxt;
+        if segment.has(ACK) && !acceptable {
+            return (!segment.has(RST)).then(|| segment.reset());
+        }
+        if segment.has(RST) {
+            if segment.has(ACK) {
+                self.fail(KernelError::ConnectionRefused);
+            }
+            return None;
+        }
+        // Simultaneous opens are not supported.
+        if !segment.has(SYN) || !segment.has(ACK) {
+            return None;
+        }
+
+        self.synchronize(segment);
+        self.snd_una = segment.fields.ack;
+        self.state = State::Established;
+        self.settle();
+        Some(self.ack())
+    }
+
+    /// Resets the retransmission timer once the peer acknowledged everything.
+    fn settle(&mut self) {
+        self.deadline = None;
+        self.rto = INITIAL_RTO_NS;
+        self.retries = 0;
+    }
+
+    /// Handles a segment received once the connection is synchronized.
+    fn synchronized(&mut self, segment: &Incoming) -> Option<Segment> {
+        // Segments that arrive out of order are dropped, and the peer retransmits them.
+        let offset = segment.fields.seq.wrapping_sub(self.rcv_nxt);
+        let acceptable = match segment.seq_len() {
+            0 => offset == 0 || offset < self.window() as u32,
+            _ => offset == 0,
+        };
+        if !acceptable {
+            return (!segment.has(RST)).then(|| self.ack());
+        }
+
+        if segment.has(RST) {
+            match self.listener {
+                Some(_) => self.state = State::Closed,
+                None => self.fail(KernelError::ConnectionReset),
+            }
+            return None;
+        }
+        if segment.has(SYN) {
+            let reset = self.control(self.snd_nxt, RST);
+            self.fail(KernelError::ConnectionReset);
+            return Some(reset);
+        }
+        if !segment.has(ACK) {
+            return None;
+        }
+
+        let ack = segment.fields.ack;
+        let in_flight = self.snd_nxt.wrapping_sub(self.snd_una);
+        let acked = ack.wrapping_sub(self.snd_una);
+        if acked > in_flight {
+            // The peer acknowledges something that was not sent.
+            return Some(match self.state {
+                State::SynReceived => segment.reset(),
+                _ => self.ack(),
+            });
+        }
+
+        if self.state == State::SynReceived {
+            if acked == 0 {
+                return Some(segment.reset());
+            }
+            self.snd_una = ack;
+            self.state = State::Established;
+            self.settle();
+        } else if acked != 0 {
+            let fin_sent = self.fin_sent();
+            let data = (acked as usize).min(self.send.len());
+            self.send.remove_range(..data);
+            self.snd_una = ack;
+            self.settle();
+            if acked != in_flight {
+                self.arm();
+            }
+
+            if fin_sent && acked == in_flight {
+                match self.state {
+                    State::FinWait1 => self.state = State::FinWait2,
+                    State::Closing => self.time_wait(),
+                    State::LastAck => self.state = State::Closed,
+                    _ => (),
+                }
+            }
+        }
+        self.snd_wnd = segment.fields.window;
+
+        let mut reply = None;
+        let mut accepted = 0;
+        if !segment.data.is_empty() && self.state.receives() {
+            // What does not fit in the receive buffer is retransmitted by the peer.
+            accepted = segment.data.len().min(self.window() as usize);
+            self.recv.extend_from_slice(&segment.data[..accepted]);
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(accepted as u32);
+            reply = Some(());
+        }
+        if segment.has(FIN) && accepted == segment.data.len() && self.state.receives() {
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            self.fin_received = true;
+            match self.state {
+                State::Established => self.state = State::CloseWait,
+                State::FinWait1 => self.state = State::Closing,
+                _ => self.time_wait(),
+            }
+            reply = Some(());
+        }
+        reply.map(|()| self.ack())
+    }
+
+    /// Returns the next segment that carries data, or the `FIN` of the socket.
+    ///
+    /// The data sent is bounded by the window of the peer. When the peer has no room
+    /// left, single bytes are sent to probe for its window to reopen.
+    fn next_segment(&mut self) -> Option<Segment> {
+        if !matches!(
+            self.state,
+            State::Established
+                | State::CloseWait
+                | State::FinWait1
+                | State::Closing
+                | State::LastAck
+        ) {
+            return None;
+        }
+
+        let sent = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+        if sent < self.send.len() {
+            let usable = (self.snd_wnd as usize).saturating_sub(sent);
+            let usable = if usable == 0 && sent == 0 { 1 } else { usable };
+            let len = (self.send.len() - sent).min(usable).min(self.mss as usize);
+            if len == 0 {
+                return None;
+            }
+
+            let fields = Fields {
+                seq: self.snd_nxt,
+                ack: self.rcv_nxt,
+                flags: ACK | PSH,
+                window: self.window(),
+            };
+            let segment = Segment::new(
+                self.local,
+                self.remote,
+                fields,
+                &self.send[sent..sent + len],
+            );
+            self.snd_nxt = self.snd_nxt.wrapping_add(len as u32);
+            self.arm();
+            return Some(segment);
+        }
+
+        if !self.fin_queued || sent != self.send.len() {
+            return None;
+        }
+        let segment = self.control(self.snd_nxt, FIN | ACK);
+        self.snd_nxt = self.snd_nxt.wrapping_add(1);
+        match self.state {
+            State::Established => self.state = State::FinWait1,
+            State::CloseWait => self.state = State::LastAck,
+            _ => (),
+        }
+        self.arm();
+        Some(segment)
+    }
+
+    /// Handles the expiration of the timer of the socket.
+    ///
+    /// Everything that was not acknowledged is sent again, and the timeout doubles.
+    fn expire(&mut self) -> Option<Segment> {
+        self.deadline = None;
+        if self.state == State::TimeWait {
+            self.state = State::Closed;
+            return None;
+        }
+
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.fail(KernelError::TimedOut);
+            return None;
+        }
+        self.rto = (self.rto * 2).min(MAX_RTO_NS);
+        self.arm();
+
+        match self.state {
+            State::SynSent => Some(self.control(self.iss, SYN)),
+            State::SynReceived => Some(self.control(self.iss, SYN | ACK)),
+            _ => {
+                self.snd_nxt = self.snd_una;
+                None
+            }
+        }
+    }
+}
+
+/// The sockets.
+static SOCKETS: Mutex<[Option<Socket>; MAX_SOCKETS]> = {
+    const NONE: Option<Socket> = None;
+    Mutex::new([NONE; MAX_SOCKETS])
+};
+
+/// The threads waiting for something to happen on each socket.
+static EVENTS: [WaitQueue; MAX_SOCKETS] = {
+    // The constant is only used to initialize the array.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: WaitQueue = WaitQueue::new();
+    [EMPTY; MAX_SOCKETS]
+};
+
+/// Starts the timer that retransmits the segments that were not acknowledged in time.
+pub fn init() {
+    if timer::register(TIMER_PERIOD_MS, check_timers).is_none() {
+        log!("Failed to register the TCP retransmission timer.\n");
+    }
+}
+
+/// Queues [`expire_timers`] if the timer of a socket expired.
+///
+/// This is called from the timer interrupt.
+fn check_timers() {
+    let now = time::monotonic_ns();
+    let expired = SOCKETS
+        .lock()
+        .iter()
+        .flatten()
+        .any(|s| s.deadline.is_some_and(|d| d <= now));
+    if expired {
+        kthread::defer(expire_timers);
+    }
+}
+
+/// Handles the timers of the sockets that expired.
+fn expire_timers() {
+    let now = time::monotonic_ns();
+    for id in 0..MAX_SOCKETS {
+        let mut sockets = SOCKETS.lock();
+        let Some(socket) = sockets[id].as_mut() else {
+            continue;
+        };
+        if !socket.deadline.is_some_and(|d| d <= now) {
+            continue;
+        }
+        let segment = socket.expire();
+        if socket.is_dead() {
+            sockets[id] = None;
+        }
+        drop(sockets);
+
+        EVENTS[id].wake_all();
+        if let Some(segment) = segment {
+            segment.transmit();
+        }
+        flush(id);
+    }
+}
+
+/// Sends the data of the socket `id` that its peer has room for.
+///
+/// The lock is released while each segment is sent, which takes a while on slow links.
+fn flush(id: usize) {
+    loop {
+        let segment = SOCKETS.lock()[id].as_mut().and_then(Socket::next_segment);
+        match segment {
+            Some(segment) => segment.transmit(),
+            None => break,
+        }
+    }
+}
+
+/// Returns the socket that receives the segments sent to `dst` by `src`.
+///
+/// Connected sockets come first, so that the segments of accepted connections do not reach
+/// the socket that listens on the same port.
+fn find(sockets: &[Option<Socket>], src: SocketAddr, dst: SocketAddr) -> Option<usize> {
+    let connected = sockets.iter().position(|s| {
+        s.as_ref().is_some_and(|s| {
+            !matches!(s.state, State::Closed | State::Listen) && s.local == dst && s.remote == src
+        })
+    });
+    connected.or_else(|| {
+        sockets.iter().position(|s| {
+            s.as_ref().is_some_and(|s| {
+                s.state == State::Listen
+                    && s.local.port == dst.port
+                    && (s.local.addr == Ipv4Addr::UNSPECIFIED || s.local.addr == dst.addr)
+            })
+        })
+    })
+}
+
+/// Handles a TCP segment received in a packet whose header is `header`.
+pub fn receive(header: &Header, segment: &[u8]) {
+    let Some(segment) = parse(header, segment) else {
+        return;
+    };
+
+    let mut sockets = SOCKETS.lock();
+    let Some(id) = find(&*sockets, segment.src, segment.dst) else {
+        drop(sockets);
+        if !segment.has(RST) {
+            segment.reset().transmit();
+        }
+        return;
+    };
+
+    let socket = sockets[id].as_mut().unwrap();
+    let reply = match socket.state {
+        State::Listen => new_connection(&mut sockets, id, &segment),
+        State::SynSent => socket.syn_sent(&segment),
+        _ => socket.synchronized(&segment),
+    };
+    let listener = sockets[id].as_ref().and_then(|s| s.listener);
+    if sockets[id].as_ref().is_some_and(Socket::is_dead) {
+        sockets[id] = None;
+    }
+    drop(sockets);
+
+    EVENTS[id].wake_all();
+    if let Some(listener) = listener {
+        EVENTS[listener].wake_all();
+    }
+    if let Some(reply) = reply {
+        reply.transmit();
+    }
+    flush(id);
+}
+
+/// Handles a segment received by the listening socket `id`.
+///
+/// A `SYN` creates a new socket, which is accepted once its handshake completes.
+fn new_connection(
+    sockets: &mut [Option<Socket>; MAX_SOCKETS],
+    id: usize,
+    segment: &Incoming,
+) -> Option<Segment> {
+    if segment.has(RST) {
+        return None;
+    }
+    if segment.has(ACK) {
+        return Some(segment.reset());
+    }
+    if !segment.has(SYN) {
+        return None;
+    }
+
+    let listener = sockets[id].as_ref().unwrap();
+    let (owner, backlog) = (listener.owner, listener.backlog);
+    let pending = sockets
+        .iter()
+        .flatten()
+        .filter(|s| s.listener == Some(id))
+        .count();
+    // When there is no room, the peer sends its `SYN` again later.
+    if pending >= backlog as usize {
+        return None;
+    }
+    let slot = sockets.iter().position(Option::is_none)?;
+
+    sockets[slot] = Some(Socket::new(owner));
+    let socket = sockets[slot].as_mut().unwrap();
+    socket.listener = Some(id);
+    socket.local = segment.dst;
+    socket.remote = segment.src;
+    socket.synchronize(segment);
+    socket.open(State::SynReceived);
+    Some(socket.control(socket.iss, SYN | ACK))
+}
+
+/// Returns the process that makes the current system call, if any.
+fn current_process() -> Option<ProcessId> {
+    GLOBAL.get().map(|glob| glob.processes.lock().current_id())
+}
+
+/// Creates a socket, and returns its ID.
+pub fn open() -> Result<SocketId, KernelError> {
+    let mut sockets = SOCKETS.lock();
+    let slot = sockets
+        .iter()
+        .position(Option::is_none)
+        .ok_or(KernelError::NoSpace)?;
+    sockets[slot] = Some(Socket::new(current_process()));
+    Ok(slot as SocketId)
+}
+
+/// Returns the socket `id`, unless it was closed.
+fn get(
+    sockets: &mut [Option<Socket>; MAX_SOCKETS],
+    id: SocketId,
+) -> Result<&mut Socket, KernelError> {
+    sockets
+        .get_mut(id as usize)
+        .and_then(Option::as_mut)
+        .filter(|s| !s.closed && s.listener.is_none())
+        .ok_or(KernelError::NotFound)
+}
+
+/// Returns whether a socket other than `except` is bound to `port`.
+fn is_port_used(sockets: &[Option<Socket>], port: u16, except: usize) -> bool {
+    sockets
+        .iter()
+        .enumerate()
+        .any(|(i, s)| i != except && s.as_ref().is_some_and(|s| s.local.port == port))
+}
+
+/// Returns an ephemeral port that no socket is bound to.
+fn ephemeral_port(sockets: &[Option<Socket>], except: usize) -> Result<u16, KernelError> {
+    for _ in 0..MAX_SOCKETS + 1 {
+        let port = NEXT_PORT.fetch_add(1, Relaxed);
+        if !EPHEMERAL_PORTS.contains(&port) {
+            NEXT_PORT.store(EPHEMERAL_PORTS.start, Relaxed);
+            continue;
+        }
+        if !is_port_used(sockets, port, except) {
+            return Ok(port);
+        }
+    }
+    Err(KernelError::AddressInUse)
+}
+
+/// Binds the socket `id` to `local`.
+///
+/// The address must be unspecified or the one of an interface. A port of zero picks an
+/// ephemeral port.
+pub fn bind(id: SocketId, local: SocketAddr) -> Result<(), KernelError> {
+    if local.addr != Ipv4Addr::UNSPECIFIED && interface::find_by_addr(local.addr).is_none() {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    let mut sockets = SOCKETS.lock();
+    let socket = get(&mut sockets, id)?;
+    if socket.state != State::Closed || socket.local.port != 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    let port = match local.port {
+        0 => ephemeral_port(&*sockets, id as usize)?,
+        port if is_port_used(&*sockets, port, id as usize) => {
+            return Err(KernelError::AddressInUse)
+        }
+        port => port,
+    };
+    sockets[id as usize].as_mut().unwrap().local = SocketAddr::new(local.addr, port);
+    Ok(())
+}
+
+/// Makes the socket `id` accept connections, at most `backlog` of which may wait to be
+/// accepted.
+///
+/// A socket that is not bound gets an ephemeral port.
+pub fn listen(id: SocketId, backlog: u32) -> Result<(), KernelError> {
+    let mut sockets = SOCKETS.lock();
+    let socket = get(&mut sockets, id)?;
+    if socket.state != State::Closed || socket.error.is_some() {
+        return Err(KernelError::InvalidArgument);
+    }
+    if socket.local.port == 0 {
+        let port = ephemeral_port(&*sockets, id as usize)?;
+        sockets[id as usize].as_mut().unwrap().local.port = port;
+    }
+
+    let socket = sockets[id as usize].as_mut().unwrap();
+    socket.backlog = backlog.clamp(1, MAX_BACKLOG as u32) as u8;
+    socket.state = State::Listen;
+    Ok(())
+}
+
+/// Waits for a connection to be established on the listening socket `id`, and returns the ID
+/// of its socket along with the address of the peer.
+pub fn accept(id: SocketId) -> Result<(SocketId, SocketAddr), KernelError> {
+    loop {
+        let mut sockets = SOCKETS.lock();
+        if get(&mut sockets, id)?.state != State::Listen {
+            return Err(KernelError::InvalidArgument);
+        }
+        let established = sockets.iter().position(|s| {
+            s.as_ref().is_some_and(|s| {
+                s.listener == Some(id as usize) && !matches!(s.state, State::SynReceived)
+            })
+        });
+        if let Some(child) = established {
+            let socket = sockets[child].as_mut().unwrap();
+            socket.listener = None;
+            return Ok((child as SocketId, socket.remote));
+        }
+        drop(sockets);
+
+        if !EVENTS[id as usize].wait(None) {
+            return Err(KernelError::Interrupted);
+        }
+    }
+}
+
+/// Connects the socket `id` to `remote`, and waits for the handshake to complete.
+///
+/// A socket that is not bound gets an ephemeral port, and the address of the interface
+/// through which `remote` is reached.
+pub fn connect(id: SocketId, remote: SocketAddr) -> Result<(), KernelError> {
+    if remote.port == 0 || remote.addr == Ipv4Addr::UNSPECIFIED {
+        return Err(KernelError::InvalidArgument);
+    }
+    let src = ipv4::source_for(remote.addr)?;
+
+    let mut sockets = SOCKETS.lock();
+    let socket = get(&mut sockets, id)?;
+    if socket.state != State::Closed || socket.error.is_some() {
+        return Err(KernelError::InvalidArgument);
+    }
+    let port = match socket.local.port {
+        0 => ephemeral_port(&*sockets, id as usize)?,
+        port => port,
+    };
+    let socket = sockets[id as usize].as_mut().unwrap();
+    socket.local = SocketAddr::new(src, port);
+    socket.remote = remote;
+    socket.open(State::SynSent);
+    let syn = socket.control(socket.iss, SYN);
+    drop(sockets);
+    syn.transmit();
+
+    loop {
+        let mut sockets = SOCKETS.lock();
+        let socket = get(&mut sockets, id)?;
+        match socket.state {
+            State::SynSent => (),
+            State::Closed => return Err(socket.error.unwrap_or(KernelError::ConnectionRefused)),
+            _ => return Ok(()),
+        }
+        drop(sockets);
+
+        if !EVENTS[id as usize].wait(None) {
+            return Err(KernelError::Interrupted);
+        }
+    }
+}
+
+/// Sends `data` through the connection of the socket `id`.
+///
+/// This waits until all of the data is in the send buffer.
+pub fn send(id: SocketId, mut data: &[u8]) -> Result<usize, KernelError> {
+    let len = data.len();
+    while !data.is_empty() {
+        let mut sockets = SOCKETS.lock();
+        let socket = get(&mut sockets, id)?;
+        if !socket.state.sends() || socket.fin_queued {
+            return Err(socket.error.unwrap_or(KernelError::NotConnected));
+        }
+        let count = data.len().min(BUFFER_SIZE - socket.send.len());
+        socket.send.extend_from_slice(&data[..count]);
+        data = &data[count..];
+        drop(sockets);
+
+        flush(id as usize);
+        if !data.is_empty() && !EVENTS[id as usize].wait(None) {
+            return Err(KernelError::Interrupted);
+        }
+    }
+    Ok(len)
+}
+
+/// Receives data from the connection of the socket `id`, and returns its length.
+///
+/// This waits until some data is received. Zero is returned once the peer closed its side of
+/// the connection.
+pub fn recv(id: SocketId, buf: &mut [u8]) -> Result<usize, KernelError> {
+    loop {
+        let mut sockets = SOCKETS.lock();
+        let socket = get(&mut sockets, id)?;
+        if !socket.recv.is_empty() || buf.is_empty() {
+            let was_full = (socket.window() as usize) < socket.mss as usize;
+            let count = buf.len().min(socket.recv.len());
+            buf[..count].copy_from_slice(&socket.recv[..count]);
+            socket.recv.remove_range(..count);
+
+            // Tell the peer that the window reopened.
+            let update = (was_full && socket.state.receives()).then(|| socket.ack());
+            drop(sockets);
+            if let Some(update) = update {
+                update.transmit();
+            }
+            return Ok(count);
+        }
+        if socket.fin_received {
+            return Ok(0);
+        }
+        match socket.state {
+            State::Closed | State::Listen => {
+                return Err(socket.error.unwrap_or(KernelError::NotConnected))
+            }
+            _ => (),
+        }
+        drop(sockets);
+
+        if !EVENTS[id as usize].wait(None) {
+            return Err(KernelError::Interrupted);
+        }
+    }
+}
+
+/// Closes the socket `id`.
+///
+/// The data that was not sent yet is still sent, followed by a `FIN`. The connections that
+/// a listening socket did not accept are reset.
+pub fn close(id: SocketId) -> Result<(), KernelError> {
+    let mut sockets = SOCKETS.lock();
+    let socket = get(&mut sockets, id)?;
+    socket.closed = true;
+    let id = id as usize;
+    match socket.state {
+        State::Listen | State::SynSent => socket.state = State::Closed,
+        State::Established | State::CloseWait => socket.fin_queued = true,
+        _ => (),
+    }
+    if socket.is_dead() {
+        sockets[id] = None;
+    }
+
+    let mut pending = ArrayVec::<usize, MAX_SOCKETS>::new();
+    for (i, s) in sockets.iter().enumerate() {
+        if s.as_ref().is_some_and(|s| s.listener == Some(id)) {
+            pending.push(i);
+        }
+    }
+    drop(sockets);
+
+    for &child in pending.iter() {
+        let reset = SOCKETS.lock()[child]
+            .take()
+            .map(|s| s.control(s.snd_nxt, RST));
+        if let Some(reset) = reset {
+            reset.transmit();
+        }
+    }
+
+    EVENTS[id].wake_all();
+    flush(id);
+    Ok(())
+}
+
+/// Closes the sockets that the process `id` did not close before exiting.
+pub fn release_process(id: ProcessId) {
+    for socket in 0..MAX_SOCKETS as SocketId {
+        let owned = SOCKETS.lock()[socket as usize]
+            .as_ref()
+            .is_some_and(|s| s.owner == Some(id) && !s.closed && s.listener.is_none());
+        if owned {
+            let _ = close(socket);
+        }
+    }
+}
+
+/// Information about a socket, for the `netstat` command.
+#[derive(Debug, Clone, Copy)]
+pub struct SocketInfo {
+    /// The ID of the socket.
+    pub id: SocketId,
+    /// The state of its connection.
+    pub state: State,
+    /// The address the socket is bound to.
+    pub local: SocketAddr,
+    /// The address of the peer.
+    pub remote: SocketAddr,
+    /// The number of bytes waiting to be read.
+    pub received: usize,
+    /// The number of bytes that were not acknowledged yet.
+    pub unacked: usize,
+}
+
+/// Returns information about every socket.
+pub fn all() -> ArrayVec<SocketInfo, MAX_SOCKETS> {
+    SOCKETS
+        .lock()
+        .iter()
+        .enumerate()
+        .filter_map(|(id, s)| {
+            let s = s.as_ref()?;
+            Some(SocketInfo {
+                id: id as SocketId,
+                state: s.state,
+                local: s.local,
+                remote: s.remote,
+                received: s.recv.len(),
+                unacked: s.send.len(),
+            })
+        })
+        .collect()
+}
+
+/// The `netstat` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"netstat",
+    summary: "list the TCP sockets",
+    usage: "netstat",
+    details: "Lists the TCP sockets, along with the state of their connection, the number of\n\
+              bytes waiting to be read, and the number of bytes not acknowledged by the peer.",
+    handler: netstat,
+};
+
+/// The `netstat` command.
+fn netstat(_shell: &mut Shell, _args: &[u8]) {
+    let sockets = all();
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::right("ID", 3),
+            Column::left("STATE", 12),
+            Column::left("LOCAL", 21),
+            Column::left("REMOTE", 21),
+            Column::right("RECV-Q", 6),
+            Column::right("SEND-Q", 6),
+        ],
+    );
+    let _ = table.header();
+    for socket in sockets.iter() {
+        let _ = table.row([
+            &socket.id,
+            &socket.state,
+            &socket.local,
+            &socket.remote,
+            &socket.received,
+            &socket.unacked,
+        ]);
+    }
+}
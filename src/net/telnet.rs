@@ -0,0 +1,256 @@
+//! A remote shell, served over TCP with the Telnet protocol.
+//!
+//! Each client gets its own shell, which runs as `nobody` unless the server was started with
+//! `--insecure-root`, and which only accepts the commands that leave the machine and its
+//! console alone. There is no authentication. Plain TCP clients such as `nc` work too: they
+//! simply never ask the server to echo what they type.
+//!
+//! The output of a command is captured by the terminal while it runs, and sent to the client
+//! once it completes. Anything else written to the terminal in the meantime is sent along.
+
+use core::fmt::{self, Write};
+
+use crate::shell::{split_command, Command, Shell};
+use crate::state::UserId;
+use crate::terminal::{AnsiConsole, Console, LineEditor, LineEvent, MAX_LINE_LEN};
+use crate::utility::ArrayVec;
+use crate::TERMINAL;
+
+use super::interface;
+use super::server::{Server, Writer};
+use super::tcp::{self, SocketId};
+
+/// The port the server listens on by default.
+const DEFAULT_PORT: u16 = 23;
+
+/// "Interpret as command": the byte that starts a Telnet command.
+const IAC: u8 = 255;
+/// Asks the peer not to use an option.
+const DONT: u8 = 254;
+/// Asks the peer to use an option.
+const DO: u8 = 253;
+/// Refuses to use an option.
+const WONT: u8 = 252;
+/// Offers to use an option.
+const WILL: u8 = 251;
+/// Starts the subnegotiation of an option.
+const SB: u8 = 250;
+/// Ends the subnegotiation of an option.
+const SE: u8 = 240;
+
+/// The option that lets the server echo what the client types.
+const OPT_ECHO: u8 = 1;
+/// The option that removes the "go ahead" signals, so that characters are sent as they are
+/// typed.
+const OPT_SGA: u8 = 3;
+
+/// The telnet server.
+static SERVER: Server = Server::new("telnetd", "ktelnetd", DEFAULT_PORT, true, serve, session);
+
+/// What a byte received from a client turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Input {
+    /// A byte typed by the user.
+    Data(u8),
+    /// A request about an option: the verb ([`DO`], [`DONT`], [`WILL`] or [`WONT`]) and the
+    /// option.
+    Negotiation(u8, u8),
+    /// Nothing yet, or a command that is ignored.
+    None,
+}
+
+/// The state of a [`Decoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    /// Receiving data.
+    Data,
+    /// Receiving data, right after a carriage return.
+    CarriageReturn,
+    /// Received [`IAC`].
+    Command,
+    /// Received a verb, waiting for its option.
+    Option(u8),
+    /// Within a subnegotiation, which is ignored.
+    Subnegotiation,
+    /// Received [`IAC`] within a subnegotiation.
+    SubnegotiationCommand,
+}
+
+/// Separates the data sent by a client from its Telnet commands.
+///
+/// A line ends with `CR LF` or `CR NUL` in Telnet, so the byte following a carriage return is
+/// dropped if it is one of those.
+struct Decoder {
+    state: DecoderState,
+}
+
+impl Decoder {
+    /// Creates a new [`Decoder`] instance.
+    const fn new() -> Self {
+        Self {
+            state: DecoderState::Data,
+        }
+    }
+
+    /// Processes a byte received from the client.
+    fn advance(&mut self, byte: u8) -> Input {
+        use DecoderState::*;
+
+        let (state, input) = match (self.state, byte) {
+            (CarriageReturn, b'\n' | 0) => (Data, Input::None),
+            (Data | CarriageReturn, IAC) => (Command, Input::None),
+            (Data | CarriageReturn, b'\r') => (CarriageReturn, Input::Data(byte)),
+            (Data | CarriageReturn, _) => (Data, Input::Data(byte)),
+            (Command, IAC) => (Data, Input::Data(IAC)),
+            (Command, DO | DONT | WILL | WONT) => (Option(byte), Input::None),
+            (Command, SB) => (Subnegotiation, Input::None),
+            (Command, _) => (Data, Input::None),
+            (Option(verb), _) => (Data, Input::Negotiation(verb, byte)),
+            (Subnegotiation, IAC) => (SubnegotiationCommand, Input::None),
+            (Subnegotiation, _) => (Subnegotiation, Input::None),
+            (SubnegotiationCommand, SE) => (Data, Input::None),
+            (SubnegotiationCommand, _) => (Subnegotiation, Input::None),
+        };
+        self.state = state;
+        input
+    }
+}
+
+/// Sends the output of a session, turning line feeds into `CR LF`.
+struct Output(Writer);
+
+impl Output {
+    /// Queues text to be sent, turning line feeds into `CR LF`, and escaping [`IAC`].
+    fn write_text(&mut self, text: &[u8]) {
+        for &byte in text {
+            match byte {
+                b'\n' => self.0.write_bytes(b"\r\n"),
+                IAC => self.0.write_bytes(&[IAC, IAC]),
+                _ => self.0.write_bytes(&[byte]),
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_text(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The `ktelnetd` thread.
+fn serve() {
+    SERVER.serve();
+}
+
+/// Runs a shell for the client connected to `socket`, until it leaves.
+fn session(socket: SocketId, user: UserId) {
+    // The terminal of the session is named after the interface the server listens on.
+    let tty_name = SERVER
+        .addr()
+        .and_then(|addr| interface::find_by_addr(addr.addr))
+        .and_then(interface::name)
+        .unwrap_or("net");
+    let mut out = Output(Writer::new(socket));
+    let mut shell = Shell::remote(tty_name, user);
+    let mut editor = LineEditor::new();
+    let mut decoder = Decoder::new();
+    // Whether the client agreed to let the server echo what it types.
+    let mut echo = false;
+
+    out.0
+        .write_bytes(&[IAC, WILL, OPT_ECHO, IAC, WILL, OPT_SGA]);
+    let _ = writeln!(out, "kfs remote shell on {tty_name}; `exit` to disconnect.");
+    out.write_text(&shell.prompt());
+
+    let mut received = [0; 128];
+    'session: loop {
+        out.0.flush();
+        if out.0.failed() {
+            break;
+        }
+        let count = match tcp::recv(socket, &mut received) {
+            Ok(0) | Err(_) => break,
+            Ok(count) => count,
+        };
+
+        for &byte in &received[..count] {
+            let byte = match decoder.advance(byte) {
+                Input::Data(byte) => byte,
+                Input::Negotiation(verb, option) => {
+                    match (verb, option) {
+                        (DO, OPT_ECHO) => echo = true,
+                        (DONT, OPT_ECHO) => echo = false,
+                        (DO, OPT_SGA) | (DONT, _) | (WONT, _) => (),
+                        (DO, _) => out.0.write_bytes(&[IAC, WONT, option]),
+                        (_, _) => out.0.write_bytes(&[IAC, DONT, option]),
+                    }
+                    continue;
+                }
+                Input::None => continue,
+            };
+
+            match editor.feed(byte) {
+                LineEvent::Edited if echo => {
+                    AnsiConsole(&mut out).draw_line(&shell.prompt(), &editor);
+                }
+                LineEvent::Submitted => {
+                    if echo {
+                        out.write_text(b"\n");
+                    }
+                    let mut line = ArrayVec::<u8, MAX_LINE_LEN>::new();
+                    line.extend_from_slice(editor.line());
+                    editor.clear();
+                    if matches!(split_command(&line).0, b"exit" | b"logout") {
+                        break 'session;
+                    }
+                    execute(&mut shell, &line, &mut out);
+                    out.write_text(&shell.prompt());
+                }
+                LineEvent::EndOfFile => break 'session,
+                _ => (),
+            }
+        }
+    }
+    out.0.flush();
+}
+
+/// Executes a command-line in `shell`, and sends its output to the client.
+fn execute(shell: &mut Shell, line: &[u8], out: &mut Output) {
+    TERMINAL.lock().begin_capture();
+    shell.execute(line);
+    let truncated = TERMINAL.lock().end_capture();
+
+    let mut chunk = [0; 256];
+    loop {
+        let count = TERMINAL.lock().take_captured(&mut chunk);
+        if count == 0 {
+            break;
+        }
+        out.write_text(&chunk[..count]);
+    }
+    if truncated {
+        let _ = writeln!(out, "(output truncated)");
+    }
+}
+
+/// The `telnetd` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"telnetd",
+    summary: "serve remote shells over TCP",
+    usage: "telnetd [start | <port> | stop] [interface] [--insecure-root]",
+    details: "Without arguments, tells whether the server is running. `telnetd start sl0`\n\
+              listens for Telnet or plain TCP clients on port 23 of the interface `sl0`, and\n\
+              `telnetd <port> sl0` on another port. Each client gets a shell, one at a time,\n\
+              which only runs the commands that do not affect the machine or the console.\n\
+              There is no authentication, so the shells run as `nobody`; `--insecure-root`\n\
+              runs them as the current user instead. `telnetd stop` stops listening. Requires\n\
+              being the super-user.",
+    handler: telnetd,
+};
+
+/// The `telnetd` command.
+fn telnetd(shell: &mut Shell, args: &[u8]) {
+    SERVER.command(shell, args);
+}
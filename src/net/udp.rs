@@ -0,0 +1,57 @@
+use crate::error::KernelError;
+
+use super::addr::SocketAddr;
+use super::ipv4::{self, Header, PROTOCOL_UDP};
+use super::{sntp, MAX_MTU};
+
+/// The length of the header of UDP datagrams.
+const HEADER_LEN: usize = 8;
+
+/// Handles a UDP datagram received in a packet whose header is `header`.
+///
+/// There are no UDP sockets: datagrams are handed to the kernel service that owns their
+/// destination port, and dropped when there is none.
+pub fn receive(header: &Header, datagram: &[u8]) {
+    if datagram.len() < HEADER_LEN {
+        return;
+    }
+    let len = u16::from_be_bytes([datagram[4], datagram[5]]) as usize;
+    if len < HEADER_LEN || len > datagram.len() {
+        return;
+    }
+    let datagram = &datagram[..len];
+    // A checksum of zero means that the sender did not compute it.
+    let checksum = u16::from_be_bytes([datagram[6], datagram[7]]);
+    if checksum != 0 && ipv4::checksum(header.src, header.dst, PROTOCOL_UDP, datagram) != 0 {
+        return;
+    }
+
+    let src = SocketAddr::new(header.src, u16::from_be_bytes([datagram[0], datagram[1]]));
+    let dst_port = u16::from_be_bytes([datagram[2], datagram[3]]);
+    if dst_port == sntp::CLIENT_PORT {
+        sntp::receive(src, &datagram[HEADER_LEN..]);
+    }
+}
+
+/// Sends `payload` from the port `src_port` to `dst`.
+pub fn send(src_port: u16, dst: SocketAddr, payload: &[u8]) -> Result<(), KernelError> {
+    let len = HEADER_LEN + payload.len();
+    if len > MAX_MTU - ipv4::HEADER_LEN {
+        return Err(KernelError::MessageTooLong);
+    }
+    let src = ipv4::source_for(dst.addr)?;
+
+    let mut datagram = [0; MAX_MTU];
+    let datagram = &mut datagram[..len];
+    datagram[0..2].copy_from_slice(&src_port.to_be_bytes());
+    datagram[2..4].copy_from_slice(&dst.port.to_be_bytes());
+    datagram[4..6].copy_from_slice(&(len as u16).to_be_bytes());
+    datagram[HEADER_LEN..].copy_from_slice(payload);
+    // Zero would mean that there is no checksum, and is sent with the other representation.
+    let checksum = match ipv4::checksum(src, dst.addr, PROTOCOL_UDP, datagram) {
+        0 => 0xFFFF,
+        checksum => checksum,
+    };
+    datagram[6..8].copy_from_slice(&checksum.to_be_bytes());
+    ipv4::send(dst.addr, PROTOCOL_UDP, datagram)
+}
@@ -0,0 +1,135 @@
+//! What the kernel does when it runs out of physical memory.
+//!
+//! Once the kernel is initialized, running out of memory is not fatal: the allocation that
+//! failed is reported to its caller, which fails in turn, and the event is logged. With the
+//! `kill` policy, the largest process is also killed, so that the following allocations can
+//! succeed once it exited. The init process, which runs the shell and the kernel threads, is
+//! never killed. Only the allocations made while the kernel initializes itself still bring it
+//! down (see [`die::oom`](crate::die::oom)).
+
+use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicBool, AtomicU32};
+
+use crate::shell::{usage, Command, Shell};
+use crate::state::{ProcessId, ProcessState, Signal, GLOBAL, INIT};
+use crate::{log, printk, reaper};
+
+/// What is done when an allocation fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// The allocation fails, and nothing else happens.
+    Fail,
+    /// The allocation fails, and the process that uses the most memory is killed.
+    Kill,
+}
+
+impl Policy {
+    /// Returns the name of the policy.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fail => "fail",
+            Self::Kill => "kill",
+        }
+    }
+
+    /// Returns the policy named `name`, if any.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"fail" => Some(Self::Fail),
+            b"kill" => Some(Self::Kill),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the policy is [`Policy::Kill`].
+static KILL: AtomicBool = AtomicBool::new(false);
+
+/// The number of allocations that failed.
+static FAILURES: AtomicU32 = AtomicU32::new(0);
+
+/// The number of processes that were killed to release memory.
+static KILLED: AtomicU32 = AtomicU32::new(0);
+
+/// Returns the policy in use.
+pub fn policy() -> Policy {
+    if KILL.load(Relaxed) {
+        Policy::Kill
+    } else {
+        Policy::Fail
+    }
+}
+
+/// Sets the policy in use.
+pub fn set_policy(policy: Policy) {
+    KILL.store(policy == Policy::Kill, Relaxed);
+}
+
+/// Records that an allocation made while `what` failed, and applies the policy.
+///
+/// This must not be called while the processes or the scheduler are locked.
+pub fn report(what: &str) {
+    let failures = FAILURES.fetch_add(1, Relaxed) + 1;
+    log!("Out of memory while {what} ({failures} failures so far).\n");
+    if policy() != Policy::Kill {
+        return;
+    }
+
+    let Some(victim) = largest_process() else {
+        log!("No process can be killed to release memory.\n");
+        return;
+    };
+    if reaper::kill(victim) {
+        KILLED.fetch_add(1, Relaxed);
+        log!("Killed process {victim} to release memory.\n");
+    }
+}
+
+/// Returns the process that has the most resident pages, among those that may be killed.
+fn largest_process() -> Option<ProcessId> {
+    let processes = GLOBAL.get()?.processes.lock();
+    processes
+        .iter()
+        .filter(|&(id, p)| {
+            id != INIT && p.state == ProcessState::Alive && !p.signals.is_pending(Signal::Kill)
+        })
+        .max_by_key(|(_, p)| (p.memory.resident, p.memory.mapped))
+        .map(|(id, _)| id)
+}
+
+/// The `oom` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"oom",
+    summary: "print or change what happens when memory runs out",
+    usage: "oom [fail|kill]",
+    details: "With `fail`, the allocations that cannot be satisfied fail. With `kill`, the\n\
+              process that uses the most memory is also killed. Without arguments, prints the\n\
+              policy and how many allocations failed. Changing the policy requires being the\n\
+              super-user.",
+    handler: oom,
+};
+
+/// The `oom` command.
+fn oom(shell: &mut Shell, args: &[u8]) {
+    if args.is_empty() {
+        printk!(
+            "policy: {}, failed allocations: {}, killed processes: {}\n",
+            policy().name(),
+            FAILURES.load(Relaxed),
+            KILLED.load(Relaxed),
+        );
+        return;
+    }
+
+    let Some(new) = Policy::from_name(args) else {
+        printk!("usage: {}\n", usage(b"oom"));
+        shell.fail();
+        return;
+    };
+    if !shell.is_super_user() {
+        printk!("oom: permission denied\n");
+        shell.fail();
+        return;
+    }
+    set_policy(new);
+}
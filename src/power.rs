@@ -0,0 +1,237 @@
+//! Functions to reboot or halt the system.
+
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::cpu::paging::mmio::{self, VolatileMmio};
+use crate::drivers::acpi::{self, ResetRegister};
+use crate::drivers::{pic, ps2, serial, speaker, vga};
+use crate::state::Allocator;
+use crate::utility::instr::{cli, hlt, outb, pause};
+use crate::utility::OnceCell;
+use crate::{log, TERMINAL};
+
+/// A method that can be used to reboot the system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RebootMethod {
+    /// Pulses the reset line of the CPU through the PS/2 controller.
+    Keyboard = 1,
+    /// Writes to the reset register described by the ACPI tables.
+    Acpi = 2,
+    /// Writes to the reset control register of the chipset (port `0xCF9`).
+    Cf9 = 3,
+    /// Triggers a triple fault by loading an empty IDT and raising an exception.
+    TripleFault = 4,
+}
+
+impl RebootMethod {
+    /// All the reboot methods, in their default order.
+    pub const ALL: [Self; 4] = [Self::Keyboard, Self::Acpi, Self::Cf9, Self::TripleFault];
+
+    /// Returns the name of the method.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Keyboard => "kbd",
+            Self::Acpi => "acpi",
+            Self::Cf9 => "cf9",
+            Self::TripleFault => "triple",
+        }
+    }
+
+    /// Finds the method with the provided name.
+    pub fn find(name: &[u8]) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.name().as_bytes() == name)
+    }
+
+    /// Converts the value returned by `self as u8` back into a [`RebootMethod`].
+    fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|&m| m as u8 == value)
+    }
+
+    /// Attempts to reboot the system using this method.
+    ///
+    /// If this function returns, the method did not work.
+    fn attempt(self) {
+        match self {
+            Self::Keyboard => {
+                // Wait for the controller to accept a command.
+                for _ in 0..RESET_DELAY {
+                    if !ps2::status().intersects(ps2::PS2Status::INPUT_BUFFER_FULL) {
+                        break;
+                    }
+                    pause();
+                }
+                ps2::command(0xFE);
+            }
+            Self::Acpi => match (ACPI_RESET.get(), ACPI_RESET_MMIO.get()) {
+                (Some(reg), Some(mmio)) => unsafe { mmio.write(0, reg.value) },
+                (Some(reg), None) => unsafe { reg.write() },
+                (None, _) => return,
+            },
+            // Request a full reset (bit 3) of the CPU (bit 2).
+            Self::Cf9 => unsafe { outb(0xCF9, 0xE) },
+            Self::TripleFault => unsafe { triple_fault() },
+        }
+
+        // Give the hardware some time to actually reset the system.
+        for _ in 0..RESET_DELAY {
+            pause();
+        }
+    }
+}
+
+/// The number of iterations to wait for a reboot method to take effect before trying the next
+/// one.
+const RESET_DELAY: u32 = 1_000_000;
+
+/// The order in which reboot methods are attempted.
+///
+/// Each byte is a [`RebootMethod`], starting with the least significant byte. A zero byte
+/// ends the list.
+static ORDER: AtomicU32 = AtomicU32::new(u32::from_le_bytes([1, 2, 3, 4]));
+
+/// The reset register described by the ACPI tables, if any.
+static ACPI_RESET: OnceCell<ResetRegister> = OnceCell::new();
+
+/// The mapping of the reset register described by the ACPI tables, when it is memory-mapped.
+static ACPI_RESET_MMIO: OnceCell<VolatileMmio> = OnceCell::new();
+
+/// Sets the reset register described by the ACPI tables.
+pub fn set_acpi_reset_register(reg: ResetRegister) {
+    let _ = ACPI_RESET.set(reg);
+}
+
+/// Maps the reset register described by the ACPI tables, if it is memory-mapped.
+///
+/// This must be called once paging is enabled, as the register is not necessarily identity
+/// mapped. The missing page tables are allocated from `allocator`.
+pub fn map_acpi_reset_register(allocator: &mut Allocator) {
+    let Some(reg) = ACPI_RESET.get() else {
+        return;
+    };
+    if reg.space != acpi::AddressSpace::Memory {
+        return;
+    }
+    match mmio::map_mmio(allocator, reg.address as u64, 1) {
+        Ok(mapping) => _ = ACPI_RESET_MMIO.set(mapping),
+        Err(err) => log!("Failed to map the ACPI reset register: {err:?}\n"),
+    }
+}
+
+/// Sets the order in which reboot methods are attempted.
+///
+/// Only the first four methods are taken into account.
+pub fn set_reboot_order(methods: &[RebootMethod]) {
+    let mut bytes = [0u8; 4];
+    for (byte, &method) in bytes.iter_mut().zip(methods) {
+        *byte = method as u8;
+    }
+    ORDER.store(u32::from_le_bytes(bytes), Relaxed);
+}
+
+/// Returns an iterator over the reboot methods, in the order they are attempted.
+pub fn reboot_order() -> impl Iterator<Item = RebootMethod> {
+    ORDER
+        .load(Relaxed)
+        .to_le_bytes()
+        .into_iter()
+        .map_while(RebootMethod::from_u8)
+}
+
+/// Parses a comma-separated list of reboot methods, such as `kbd,cf9`.
+///
+/// `None` is returned if one of the methods is unknown.
+pub fn parse_reboot_order(list: &[u8]) -> Option<([RebootMethod; 4], usize)> {
+    let mut methods = [RebootMethod::Keyboard; 4];
+    let mut count = 0;
+
+    for name in list.split(|&c| c == b',') {
+        let method = RebootMethod::find(name)?;
+        if count < methods.len() {
+            methods[count] = method;
+            count += 1;
+        }
+    }
+
+    Some((methods, count))
+}
+
+/// Reboots the system.
+///
+/// The devices are stopped first and pending serial output is flushed. The configured methods
+/// are then attempted in order (see [`set_reboot_order`]). If all of them fail, the CPU is
+/// halted.
+pub fn reboot() -> ! {
+    cli();
+    pic::set_irq_mask(pic::Irqs::all());
+    speaker::stop();
+
+    log!("Rebooting...\n");
+    serial::flush();
+
+    for method in reboot_order() {
+        method.attempt();
+    }
+
+    loop {
+        hlt();
+    }
+}
+
+/// Stops the system.
+///
+/// All maskable interrupts are disabled, pending serial output is flushed, and a message
+/// indicating that the computer can be turned off is displayed. The CPU then stays idle
+/// forever. Only non-maskable interrupts can wake it up.
+pub fn halt() -> ! {
+    cli();
+    pic::set_irq_mask(pic::Irqs::all());
+
+    log!("The system is halted.\n");
+    serial::flush();
+
+    {
+        let mut term = TERMINAL.lock();
+        term.end_paging();
+        term.set_cursor_enabled(false);
+        term.set_prompt(b"");
+        term.reset();
+
+        const MESSAGE: &str = "It is now safe to turn off your computer.";
+        let padding = (vga::WIDTH as usize - MESSAGE.len()) / 2;
+        for _ in 0..vga::HEIGHT / 2 {
+            term.insert_linefeed();
+        }
+        for _ in 0..padding {
+            term.write_vga_char(vga::VgaChar::SPACE);
+        }
+        let _ = core::fmt::Write::write_str(&mut *term, MESSAGE);
+    }
+
+    loop {
+        hlt();
+    }
+}
+
+/// Triggers a triple fault.
+///
+/// An empty IDT is loaded, so the exception cannot be handled, and neither can the resulting
+/// double fault.
+unsafe fn triple_fault() {
+    #[repr(C, packed)]
+    struct IdtDescriptor {
+        limit: u16,
+        base: u32,
+    }
+
+    let empty = IdtDescriptor { limit: 0, base: 0 };
+
+    core::arch::asm!(
+        "
+        lidt [{}]
+        int3
+        ",
+        in(reg) &empty,
+    );
+}
@@ -0,0 +1,259 @@
+//! A sampling profiler for the kernel.
+//!
+//! While the profiler runs, the timer interrupt records the address of the instruction it
+//! interrupted. The addresses that are sampled most often are those where the kernel spends
+//! most of its time.
+//!
+//! # Remarks
+//!
+//! The kernel has no symbol table yet, so the samples are reported as raw addresses. They can
+//! be resolved with `addr2line` on the kernel image.
+
+use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicBool, AtomicU32};
+
+use core::fmt::Write;
+
+use crate::cpu::idt::InterruptStackFrame;
+use crate::shell::{parse_u32, split_command, usage, Command, Shell};
+use crate::utility::{Address, ArrayVec, Column, Fixed, Mutex, Table};
+use crate::TERMINAL;
+
+/// The number of distinct addresses that can be recorded.
+const BUCKETS: usize = 512;
+
+/// Whether the profiler is currently running.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The number of timer ticks between two samples.
+static DIVISOR: AtomicU32 = AtomicU32::new(1);
+
+/// The samples recorded since the profiler was last started.
+static PROFILE: Mutex<Profile> = Mutex::new(Profile::new());
+
+/// The number of times an address was sampled.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    /// The address of the interrupted instruction.
+    pub address: u32,
+    /// The number of times the address was sampled.
+    pub count: u32,
+}
+
+/// Statistics about the samples recorded by the profiler.
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    /// The total number of samples.
+    pub total: u32,
+    /// The number of samples taken while user mode code was running.
+    ///
+    /// Those are counted, but their address is not recorded.
+    pub user: u32,
+    /// The number of samples whose address could not be recorded because every bucket was
+    /// already taken.
+    pub dropped: u32,
+}
+
+/// The samples recorded by the profiler.
+struct Profile {
+    /// A hash table of the sampled addresses, using linear probing.
+    ///
+    /// The address 0 marks an empty bucket.
+    buckets: [Sample; BUCKETS],
+    /// Statistics about the samples.
+    summary: Summary,
+}
+
+impl Profile {
+    /// Creates a new, empty [`Profile`] instance.
+    const fn new() -> Self {
+        Self {
+            buckets: [Sample {
+                address: 0,
+                count: 0,
+            }; BUCKETS],
+            summary: Summary {
+                total: 0,
+                user: 0,
+                dropped: 0,
+            },
+        }
+    }
+
+    /// Records a sample of the provided address.
+    fn record(&mut self, address: u32) {
+        self.summary.total += 1;
+
+        // Fibonacci hashing spreads nearby addresses over the whole table.
+        let hash = address.wrapping_mul(0x9E37_79B9) as usize % BUCKETS;
+        for i in 0..BUCKETS {
+            let bucket = &mut self.buckets[(hash + i) % BUCKETS];
+            if bucket.address == address || bucket.address == 0 {
+                bucket.address = address;
+                bucket.count += 1;
+                return;
+            }
+        }
+
+        self.summary.dropped += 1;
+    }
+}
+
+/// Starts the profiler, taking a sample every `divisor` timer ticks.
+///
+/// The samples recorded by a previous run are discarded.
+pub fn start(divisor: u32) {
+    RUNNING.store(false, Relaxed);
+    *PROFILE.lock() = Profile::new();
+    DIVISOR.store(divisor.max(1), Relaxed);
+    RUNNING.store(true, Relaxed);
+}
+
+/// Stops the profiler.
+///
+/// The samples recorded so far are kept until the profiler is started again.
+pub fn stop() {
+    RUNNING.store(false, Relaxed);
+}
+
+/// Returns whether the profiler is currently running.
+#[inline]
+pub fn is_running() -> bool {
+    RUNNING.load(Relaxed)
+}
+
+/// Returns the number of timer ticks between two samples.
+#[inline]
+pub fn divisor() -> u32 {
+    DIVISOR.load(Relaxed)
+}
+
+/// Records the instruction interrupted by the timer, if the profiler is running.
+///
+/// This function is meant to be called from the timer interrupt handler, with the tick that
+/// is being handled.
+pub fn sample(frame: &InterruptStackFrame, tick: u32) {
+    if !is_running() || tick % divisor() != 0 {
+        return;
+    }
+
+    let mut profile = PROFILE.lock();
+    if frame.cs & 3 != 0 {
+        profile.summary.total += 1;
+        profile.summary.user += 1;
+    } else {
+        profile.record(frame.ip);
+    }
+}
+
+/// Returns statistics about the samples recorded so far.
+pub fn summary() -> Summary {
+    PROFILE.lock().summary
+}
+
+/// Returns the `N` addresses that were sampled most often, starting with the hottest one.
+pub fn hottest<const N: usize>() -> ArrayVec<Sample, N> {
+    let profile = PROFILE.lock();
+    let mut hottest = ArrayVec::<Sample, N>::new();
+
+    for &sample in profile.buckets.iter().filter(|s| s.count != 0) {
+        let index = hottest
+            .iter()
+            .position(|s| s.count < sample.count)
+            .unwrap_or(hottest.len());
+        if index >= N {
+            continue;
+        }
+        if hottest.is_full() {
+            hottest.pop();
+        }
+        let _ = hottest.try_insert(index, sample);
+    }
+
+    hottest
+}
+
+/// The `profile` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"profile",
+    summary: "sample where the kernel spends its time",
+    usage: "profile [start [divisor]|stop|report [count]]",
+    details: "`start` takes a sample every `divisor` timer ticks, `stop` stops sampling and\n\
+              `report` prints the `count` addresses that were sampled most often.",
+    handler: profile,
+};
+
+/// The `profile` command.
+fn profile(shell: &mut Shell, args: &[u8]) {
+    let mut term = TERMINAL.lock();
+    let (subcommand, arg) = split_command(args);
+
+    match subcommand {
+        b"" => {
+            let state = if is_running() { "running" } else { "stopped" };
+            let summary = summary();
+            let _ = writeln!(term, "profiler: {state} ({} samples)", summary.total);
+        }
+        b"start" => {
+            let Some(divisor) = (if arg.is_empty() {
+                Some(1)
+            } else {
+                parse_u32(arg).filter(|&d| d != 0)
+            }) else {
+                let _ = writeln!(term, "profile: the divisor must be a positive integer");
+                shell.fail();
+                return;
+            };
+            start(divisor);
+            let _ = writeln!(term, "profiler started (1 sample every {divisor} ticks)");
+        }
+        b"stop" => {
+            stop();
+            let _ = writeln!(term, "profiler stopped");
+        }
+        b"report" => {
+            let count = if arg.is_empty() {
+                Some(10)
+            } else {
+                parse_u32(arg)
+            };
+            let Some(count) = count.filter(|&c| c != 0) else {
+                let _ = writeln!(term, "profile: the count must be a positive integer");
+                shell.fail();
+                return;
+            };
+
+            let summary = summary();
+            let _ = writeln!(
+                term,
+                "{} samples ({} in user mode, {} dropped)",
+                summary.total, summary.user, summary.dropped,
+            );
+            if summary.total == 0 {
+                return;
+            }
+
+            let mut table = Table::new(
+                &mut *term,
+                [
+                    Column::left("ADDRESS", 10),
+                    Column::right("SAMPLES", 8),
+                    Column::right("SHARE", 7),
+                ],
+            );
+            let _ = table.header();
+            for sample in hottest::<32>().iter().take(count as usize) {
+                let share = Fixed::from_ratio(sample.count as u64 * 100, summary.total as u64);
+                let _ = table.row([
+                    &Address(sample.address),
+                    &sample.count,
+                    &format_args!("{share:.1}%"),
+                ]);
+            }
+        }
+        _ => {
+            let _ = writeln!(term, "usage: {}", usage(b"profile"));
+            shell.fail();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! The end of the life of processes.
+//!
+//! A process exits when its last thread does. The memory it mapped is released right away,
+//! and its children are adopted by [`INIT`]. The process itself stays in the list of processes
+//! as a zombie, which only holds its exit status, until its parent collects it with [`wait`].
+//!
+//! The init process runs the shell and never waits for its children: the zombies it adopts are
+//! reaped by the `kworker` thread instead, so that they cannot fill the list of processes.
+//!
+//! A process is killed with [`kill`]. Its threads are all suspended in the kernel, either
+//! waiting or about to return from a system call, so they exit as soon as they run again.
+
+use crate::cpu::paging::{mmap, spaces};
+#[cfg(feature = "net")]
+use crate::net;
+use crate::state::{
+    ProcessId, ProcessState, Processes, ReceivedSignal, Signal, WaitQueue, GLOBAL, INIT,
+};
+use crate::{kthread, log, sched};
+
+/// The exit status of the processes that were killed, which is the one shells report for a
+/// process killed by `SIGKILL`.
+pub const KILLED_STATUS: u8 = 128 + 9;
+
+/// The threads waiting for a child of their process to exit.
+static CHILD_EXITED: WaitQueue = WaitQueue::new();
+
+/// An error that might occur while waiting for a child process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    /// The process has no child that matches.
+    NoChild,
+    /// No matching child exited yet, and the caller asked not to block.
+    WouldBlock,
+    /// The current process was killed while waiting.
+    Interrupted,
+}
+
+/// Records that the process `id` exited with `status`.
+///
+/// This is called by the last thread of the process, before it exits. The memory of the
+/// process is released along with its address space, and its parent is woken up.
+pub fn exit(id: ProcessId, status: u8) {
+    let Some(glob) = GLOBAL.get() else {
+        return;
+    };
+    mmap::release_process(id);
+    #[cfg(feature = "net")]
+    net::release_process(id);
+
+    let mut processes = glob.processes.lock();
+    processes.reparent_children(id);
+    let mut address_space = spaces::kernel();
+    if let Some(process) = processes.get_mut(id) {
+        process.state = ProcessState::Zombie { status };
+        // The thread keeps running in the kernel's address space until it exits.
+        core::mem::swap(&mut process.address_space, &mut address_space);
+    }
+    let orphans = find_orphan(&processes).is_some();
+    drop(processes);
+    spaces::release(&mut glob.allocator.lock(), address_space);
+
+    log!("process {id} exited with status {status}\n");
+    if orphans && !kthread::defer(reap_orphans) {
+        log!("Failed to queue the reaping of orphaned processes.\n");
+    }
+    CHILD_EXITED.wake_all();
+}
+
+/// Kills the process `id`: its threads exit the next time they run.
+///
+/// Returns `false` if the process does not exist, already exited, or is [`INIT`], which can
+/// never be killed.
+pub fn kill(id: ProcessId) -> bool {
+    let Some(glob) = GLOBAL.get() else {
+        return false;
+    };
+    let mut processes = glob.processes.lock();
+    let Some(process) = processes.get_mut(id) else {
+        return false;
+    };
+    if id == INIT || process.state != ProcessState::Alive {
+        return false;
+    }
+    let _ = process
+        .signals
+        .schedule(Signal::Kill, ReceivedSignal { sent_by: None });
+    drop(processes);
+
+    sched::wake_process(id);
+    true
+}
+
+/// Returns whether the current process was killed.
+pub fn is_current_killed() -> bool {
+    GLOBAL.get().is_some_and(|glob| {
+        glob.processes
+            .lock()
+            .current()
+            .signals
+            .is_pending(Signal::Kill)
+    })
+}
+
+/// Returns a zombie whose parent is [`INIT`], if any.
+fn find_orphan(processes: &Processes) -> Option<ProcessId> {
+    processes
+        .iter()
+        .find(|&(id, p)| {
+            id != INIT && p.parent == INIT && matches!(p.state, ProcessState::Zombie { .. })
+        })
+        .map(|(id, _)| id)
+}
+
+/// Removes the zombies whose parent is [`INIT`] from the list of processes.
+///
+/// This runs on the `kworker` thread, which belongs to the init process.
+fn reap_orphans() {
+    let mut processes = GLOBAL.get().unwrap().processes.lock();
+    while let Some(id) = find_orphan(&processes) {
+        if processes.remove(id).is_none() {
+            break;
+        }
+    }
+}
+
+/// Waits for a child of the current process to exit, removes it from the list of processes,
+/// and returns its ID and exit status.
+///
+/// When `pid` is set, only that child is waited for. When `block` is not set, this fails with
+/// [`WaitError::WouldBlock`] instead of waiting.
+pub fn wait(pid: Option<ProcessId>, block: bool) -> Result<(ProcessId, u8), WaitError> {
+    let glob = GLOBAL.get().unwrap();
+    loop {
+        let mut processes = glob.processes.lock();
+        let parent = processes.current_id();
+        let (mut found, mut zombie) = (false, None);
+        for (id, process) in processes.iter() {
+            if id == parent || process.parent != parent || pid.is_some_and(|pid| pid != id) {
+                continue;
+            }
+            found = true;
+            if let ProcessState::Zombie { status } = process.state {
+                zombie = Some((id, status));
+                break;
+            }
+        }
+
+        if let Some((id, status)) = zombie {
+            processes.remove(id);
+            return Ok((id, status));
+        }
+        drop(processes);
+
+        if !found {
+            return Err(WaitError::NoChild);
+        }
+        if is_current_killed() {
+            return Err(WaitError::Interrupted);
+        }
+        if !block {
+            return Err(WaitError::WouldBlock);
+        }
+        if !CHILD_EXITED.wait(None) {
+            // Too many threads are waiting already.
+            sched::yield_now();
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! The pseudo-random number generator of the kernel.
+//!
+//! The generator is seeded from the RTC and the time-stamp counter the first time it is used.
+//! Every number it produces also mixes in fresh noise from the hardware: the output of RDRAND
+//! when the CPU supports it, and the time-stamp counter otherwise. The timing of keystrokes is
+//! mixed into its state as it comes with [`add_event_timing`].
+//!
+//! None of this is cryptographically secure: the generator is only meant to make its output
+//! hard to predict from one boot to another.
+
+use crate::drivers::rtc;
+use crate::timer;
+use crate::utility::instr::{has_rdrand, has_tsc, rdrand, rdtsc};
+use crate::utility::{Mutex, OnceCell};
+
+/// The increment of the state of the generator, from SplitMix64.
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// The number of times RDRAND is attempted before giving up, as recommended by Intel.
+const RDRAND_RETRIES: usize = 10;
+
+/// Whether the CPU supports the RDTSC instruction.
+static HAS_TSC: OnceCell<bool> = OnceCell::new();
+
+/// Whether the CPU supports the RDRAND instruction.
+static HAS_RDRAND: OnceCell<bool> = OnceCell::new();
+
+/// The state of the generator, once it is seeded.
+static STATE: Mutex<Option<u64>> = Mutex::new(None);
+
+/// Spreads every input bit of `z` over the whole output.
+///
+/// This is the finalizer of SplitMix64.
+fn finalize(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Mixes `value` into `state`.
+pub fn mix(state: u64, value: u64) -> u64 {
+    finalize((state ^ value).wrapping_add(GOLDEN_GAMMA))
+}
+
+/// Returns the current value of the time-stamp counter, or 0 if it is not available.
+fn timestamp() -> u64 {
+    if *HAS_TSC.get_or_init(has_tsc) {
+        unsafe { rdtsc() }
+    } else {
+        0
+    }
+}
+
+/// Returns some fresh noise from the hardware.
+fn hardware_noise() -> u64 {
+    if *HAS_RDRAND.get_or_init(has_rdrand) {
+        let mut values = (0..RDRAND_RETRIES).filter_map(|_| unsafe { rdrand() });
+        if let (Some(hi), Some(lo)) = (values.next(), values.next()) {
+            return (hi as u64) << 32 | lo as u64;
+        }
+    }
+
+    timestamp()
+}
+
+/// Gathers some entropy from the hardware to seed the generator.
+pub fn boot_entropy() -> u64 {
+    let mut state = 0;
+
+    for value in rtc::read_raw_time() {
+        state = mix(state, value as u64);
+    }
+
+    // The low bits of the TSC depend on how long the firmware and the bootloader took to run,
+    // which varies on every boot.
+    mix(state, hardware_noise())
+}
+
+/// Runs `f` with the state of the generator, seeding it if needed.
+fn with_state<R>(f: impl FnOnce(&mut u64) -> R) -> R {
+    let mut state = STATE.lock();
+    f(state.get_or_insert_with(boot_entropy))
+}
+
+/// Mixes the time at which an unpredictable event occurred, such as a keystroke, into the
+/// state of the generator.
+///
+/// `value` describes the event, such as the scan-code of the key.
+pub fn add_event_timing(value: u32) {
+    let time = timestamp() ^ (timer::now() as u64) << 32;
+    with_state(|state| *state = mix(*state, time ^ value as u64));
+}
+
+/// Mixes arbitrary bytes into the state of the generator.
+pub fn add_entropy(bytes: &[u8]) {
+    with_state(|state| {
+        for chunk in bytes.chunks(8) {
+            let mut value = [0; 8];
+            value[..chunk.len()].copy_from_slice(chunk);
+            *state = mix(*state, u64::from_ne_bytes(value));
+        }
+    });
+}
+
+/// Returns the next output of the generator.
+fn next(state: &mut u64) -> u64 {
+    *state = (*state ^ hardware_noise()).wrapping_add(GOLDEN_GAMMA);
+    finalize(*state)
+}
+
+/// Returns a random 64-bit number.
+pub fn rand_u64() -> u64 {
+    with_state(next)
+}
+
+/// Returns a random 32-bit number.
+pub fn rand_u32() -> u32 {
+    rand_u64() as u32
+}
+
+/// Fills `buf` with random bytes.
+pub fn fill_bytes(buf: &mut [u8]) {
+    with_state(|state| {
+        for chunk in buf.chunks_mut(8) {
+            chunk.copy_from_slice(&next(state).to_ne_bytes()[..chunk.len()]);
+        }
+    });
+}
@@ -0,0 +1,743 @@
+//! The scheduler, which shares the CPU between threads.
+//!
+//! A thread is what runs on the CPU: it has its own registers and kernel stack, and belongs to
+//! a process, whose address space, signals and resources it shares with the other threads of
+//! the process. Threads run until they yield, block, or exit. When no thread is ready, the idle
+//! thread runs: it halts the CPU until an interrupt arrives, and lets the threads that the
+//! interrupt made ready run.
+//!
+//! Every thread has a nice value, from which its static priority is derived. The threads that
+//! are ready wait in the run queue of their priority, and the first thread of the highest
+//! priority queue runs next, so that threads of the same priority run in round-robin order. To
+//! prevent a busy thread from starving the ones of lower priority, the threads that remain in a
+//! queue are periodically moved to the queue above it (see [`BOOST_PERIOD_NS`]). A thread gets
+//! its static priority back once it runs.
+
+use core::arch::asm;
+use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicBool, AtomicU32};
+
+use crate::cpu::fpu::{self, FpuState};
+use crate::cpu::gdt::{USER_CODE_SEGMENT, USER_DATA_SEGMENT};
+use crate::cpu::paging::MappingError;
+use crate::cpu::{stack, tss};
+use crate::drivers::{lapic, pit};
+use crate::shell::{Command, Shell};
+use crate::state::{self, ProcessId, GLOBAL, INIT};
+use crate::utility::instr::{cli, hlt, sti};
+use crate::utility::{ArrayVec, Column, Mutex, RestoreInterrupts, Table};
+use crate::{oom, reaper, time, TERMINAL};
+
+/// The ID of a thread.
+///
+/// This is also the slot of its kernel stack. The ID of a thread that exited may be reused by
+/// another one.
+pub type ThreadId = u32;
+
+/// The maximum number of threads that can exist at once.
+pub const MAX_THREADS: usize = stack::MAX_THREAD_STACKS;
+
+/// The thread that initialized the kernel, and runs the shell.
+pub const BOOT_THREAD: ThreadId = 0;
+
+/// The lowest nice value, which gives the highest priority.
+pub const MIN_NICE: i8 = -20;
+
+/// The highest nice value, which gives the lowest priority.
+pub const MAX_NICE: i8 = 19;
+
+/// The number of priorities, and of run queues.
+const PRIORITIES: usize = (MAX_NICE - MIN_NICE) as usize + 1;
+
+/// How often the threads waiting in a run queue are moved to the queue above it, in
+/// nanoseconds.
+///
+/// A ready thread of the lowest priority runs after at most `PRIORITIES` periods.
+pub const BOOST_PERIOD_NS: u64 = 100 * time::NANOS_PER_SECOND / 1000;
+
+/// Returns the priority of the threads whose nice value is `nice`.
+///
+/// The priority is the index of a run queue: 0 is the highest priority.
+fn static_priority(nice: i8) -> u8 {
+    (nice.clamp(MIN_NICE, MAX_NICE) - MIN_NICE) as u8
+}
+
+/// The state of a thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    /// The thread is running on the CPU.
+    Running,
+    /// The thread is waiting for the CPU.
+    Ready,
+    /// The thread is waiting for an event, or for its deadline.
+    Blocked,
+    /// The thread exited, and its kernel stack is about to be released.
+    Exited,
+}
+
+impl ThreadState {
+    /// Returns the name of the state.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Ready => "ready",
+            Self::Blocked => "blocked",
+            Self::Exited => "exited",
+        }
+    }
+}
+
+/// A thread.
+struct Thread {
+    /// The name of the thread, empty for the threads of user programs.
+    name: &'static str,
+    /// The process that the thread belongs to.
+    process: ProcessId,
+    /// The state of the thread.
+    state: ThreadState,
+    /// When the thread is blocked, the value of the monotonic clock at which it becomes ready
+    /// again.
+    deadline: Option<u64>,
+    /// The stack pointer of the thread, saved when it stopped running.
+    context: usize,
+    /// The top of the kernel stack of the thread.
+    kernel_stack: u32,
+    /// The nice value of the thread.
+    nice: i8,
+    /// The priority of the thread, which may be higher than its static priority while it waits
+    /// in a run queue.
+    priority: u8,
+    /// The state of the FPU of the thread, switched lazily (see [`fpu`]).
+    fpu: FpuState,
+}
+
+/// A list of threads that are ready, in the order in which they run.
+type RunQueue = ArrayVec<ThreadId, MAX_THREADS>;
+
+/// The threads, and the one that is running.
+struct Scheduler {
+    /// The threads, indexed by their ID.
+    threads: [Option<Thread>; MAX_THREADS],
+    /// The threads that are ready, for each priority.
+    queues: [RunQueue; PRIORITIES],
+    /// The thread that is running.
+    current: ThreadId,
+    /// The thread that runs when no other thread is ready, once it is created.
+    ///
+    /// It is never in a run queue.
+    idle: Option<ThreadId>,
+    /// The value of the monotonic clock at which the threads in the run queues are boosted
+    /// next.
+    next_boost: u64,
+}
+
+impl Scheduler {
+    /// Makes the thread `id` ready, and puts it at the end of the run queue of its static
+    /// priority.
+    fn enqueue(&mut self, id: ThreadId) {
+        let Some(thread) = &mut self.threads[id as usize] else {
+            return;
+        };
+        thread.state = ThreadState::Ready;
+        thread.deadline = None;
+        thread.priority = static_priority(thread.nice);
+        // A queue can hold every thread.
+        self.queues[thread.priority as usize].push(id);
+    }
+
+    /// Removes the thread `id` from the run queue it waits in, if any.
+    fn remove_from_queue(&mut self, id: ThreadId) {
+        let Some(thread) = &self.threads[id as usize] else {
+            return;
+        };
+        let queue = &mut self.queues[thread.priority as usize];
+        if let Some(index) = queue.iter().position(|&t| t == id) {
+            queue.remove_range(index..=index);
+        }
+    }
+
+    /// Removes the first thread of the highest priority run queue, and returns it.
+    fn dequeue(&mut self) -> Option<ThreadId> {
+        let queue = self.queues.iter_mut().find(|q| !q.is_empty())?;
+        let id = queue[0];
+        queue.remove_range(..1);
+        Some(id)
+    }
+
+    /// Makes the blocked threads whose deadline passed ready.
+    fn wake_expired(&mut self, now: u64) {
+        for id in 0..MAX_THREADS as ThreadId {
+            let expired = self.threads[id as usize].as_ref().is_some_and(|t| {
+                t.state == ThreadState::Blocked && t.deadline.is_some_and(|d| now >= d)
+            });
+            if expired {
+                self.enqueue(id);
+            }
+        }
+    }
+
+    /// Moves the threads of every run queue to the end of the queue above it.
+    fn boost(&mut self) {
+        for priority in 1..PRIORITIES {
+            let (above, below) = self.queues.split_at_mut(priority);
+            let (above, below) = (&mut above[priority - 1], &mut below[0]);
+            for &id in below.iter() {
+                above.push(id);
+                if let Some(thread) = &mut self.threads[id as usize] {
+                    thread.priority = (priority - 1) as u8;
+                }
+            }
+            below.clear();
+        }
+    }
+}
+
+/// Whether the idle thread is running.
+static IDLE: AtomicBool = AtomicBool::new(false);
+
+/// The number of timer ticks during which the idle thread ran.
+static IDLE_TICKS: AtomicU32 = AtomicU32::new(0);
+
+/// The state of the scheduler.
+static SCHEDULER: Mutex<Scheduler> = {
+    const NONE: Option<Thread> = None;
+    const EMPTY: RunQueue = ArrayVec::new();
+    Mutex::new(Scheduler {
+        threads: [NONE; MAX_THREADS],
+        queues: [EMPTY; PRIORITIES],
+        current: BOOT_THREAD,
+        idle: None,
+        next_boost: 0,
+    })
+};
+
+/// Registers the thread that is running as the boot thread, which belongs to the init process,
+/// and creates the idle thread.
+///
+/// This must be called once the kernel stack used by interrupts is set.
+pub fn init() {
+    SCHEDULER.lock().threads[BOOT_THREAD as usize] = Some(Thread {
+        name: "shell",
+        process: INIT,
+        state: ThreadState::Running,
+        deadline: None,
+        context: 0,
+        kernel_stack: tss::kernel_stack(),
+        nice: 0,
+        priority: static_priority(0),
+        fpu: FpuState::new(),
+    });
+    if let Some(thread) = &mut SCHEDULER.lock().threads[BOOT_THREAD as usize] {
+        // The slot of the thread never moves, as the scheduler is a static.
+        unsafe { fpu::switch_to(&mut thread.fpu) };
+    }
+
+    let idle = spawn_kernel(INIT, "idle", idle_main, 0).expect("failed to create the idle thread");
+    let mut scheduler = SCHEDULER.lock();
+    scheduler.remove_from_queue(idle);
+    scheduler.idle = Some(idle);
+}
+
+/// The idle thread, which zeroes free pages in advance and waits for interrupts once there
+/// are enough of them.
+extern "C" fn idle_main(_: usize) {
+    loop {
+        sti();
+        let zeroed = GLOBAL
+            .get()
+            .is_some_and(|glob| state::zero_free_page(&glob.allocator));
+        if !zeroed {
+            hlt();
+        }
+        yield_now();
+    }
+}
+
+/// Returns the ID of the thread that is running.
+pub fn current() -> ThreadId {
+    SCHEDULER.lock().current
+}
+
+/// Returns whether the thread that is running may be terminated without bringing the rest of
+/// the kernel down, which is the case of every thread but the boot and idle threads.
+pub fn is_current_expendable() -> bool {
+    let scheduler = SCHEDULER.lock();
+    scheduler.current != BOOT_THREAD && Some(scheduler.current) != scheduler.idle
+}
+
+/// Returns the name of the thread that is running.
+pub fn current_name() -> &'static str {
+    let scheduler = SCHEDULER.lock();
+    scheduler.threads[scheduler.current as usize]
+        .as_ref()
+        .map_or("", |t| t.name)
+}
+
+/// Returns the nice value of the thread that is running.
+pub fn nice() -> i8 {
+    let scheduler = SCHEDULER.lock();
+    scheduler.threads[scheduler.current as usize]
+        .as_ref()
+        .map_or(0, |t| t.nice)
+}
+
+/// Sets the nice value of the thread that is running.
+///
+/// The value is clamped between [`MIN_NICE`] and [`MAX_NICE`].
+pub fn set_nice(nice: i8) {
+    let mut scheduler = SCHEDULER.lock();
+    let current = scheduler.current;
+    if let Some(thread) = &mut scheduler.threads[current as usize] {
+        thread.nice = nice.clamp(MIN_NICE, MAX_NICE);
+    }
+}
+
+/// Sets the nice value of every thread of `process`.
+///
+/// The value is clamped between [`MIN_NICE`] and [`MAX_NICE`]. Returns whether the process has
+/// any thread.
+pub fn set_process_nice(process: ProcessId, nice: i8) -> bool {
+    let mut scheduler = SCHEDULER.lock();
+    let mut found = false;
+    for id in 0..MAX_THREADS as ThreadId {
+        let Some(thread) = &mut scheduler.threads[id as usize] else {
+            continue;
+        };
+        if thread.process != process {
+            continue;
+        }
+
+        found = true;
+        thread.nice = nice.clamp(MIN_NICE, MAX_NICE);
+        if thread.state == ThreadState::Ready {
+            scheduler.remove_from_queue(id);
+            scheduler.enqueue(id);
+        }
+    }
+    found
+}
+
+/// Returns the nice value of the threads of `process`, if it has any.
+pub fn process_nice(process: ProcessId) -> Option<i8> {
+    let scheduler = SCHEDULER.lock();
+    let thread = scheduler
+        .threads
+        .iter()
+        .flatten()
+        .find(|t| t.process == process)?;
+    Some(thread.nice)
+}
+
+/// An error that might occur while creating a thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// Too many threads exist already.
+    TooManyThreads,
+    /// The kernel stack of the thread could not be mapped.
+    OutOfMemory,
+}
+
+/// Information about a thread.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadInfo {
+    /// The ID of the thread.
+    pub id: ThreadId,
+    /// The name of the thread, empty for the threads of user programs.
+    pub name: &'static str,
+    /// The process that the thread belongs to.
+    pub process: ProcessId,
+    /// The state of the thread.
+    pub state: ThreadState,
+    /// The nice value of the thread.
+    pub nice: i8,
+    /// The priority of the thread, 0 being the highest.
+    pub priority: u8,
+}
+
+/// Calls `f` for every thread, in increasing order of ID.
+pub fn for_each_thread(mut f: impl FnMut(ThreadInfo)) {
+    let scheduler = SCHEDULER.lock();
+    for (id, thread) in scheduler.threads.iter().enumerate() {
+        if let Some(thread) = thread {
+            f(ThreadInfo {
+                id: id as ThreadId,
+                name: thread.name,
+                process: thread.process,
+                state: thread.state,
+                nice: thread.nice,
+                priority: thread.priority,
+            });
+        }
+    }
+}
+
+/// The `threads` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"threads",
+    summary: "list the threads",
+    usage: "threads",
+    details: "Lists the threads, along with the process they belong to, their state, their nice\n\
+              value, their current priority (0 is the highest) and their name. The priority of\n\
+              a thread rises while it waits for the CPU. The threads of user programs have no\n\
+              name.",
+    handler: threads,
+};
+
+/// The `threads` command.
+fn threads(_shell: &mut Shell, _args: &[u8]) {
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::right("TID", 3),
+            Column::right("PID", 5),
+            Column::left("STATE", 7),
+            Column::right("NI", 3),
+            Column::right("PRI", 3),
+            Column::left("NAME", 16),
+        ],
+    );
+
+    let _ = table.header();
+    for_each_thread(|thread| {
+        let _ = table.row([
+            &thread.id,
+            &thread.process,
+            &thread.state.name(),
+            &thread.nice,
+            &thread.priority,
+            &if thread.name.is_empty() {
+                "-"
+            } else {
+                thread.name
+            },
+        ]);
+    });
+}
+
+/// Where a new thread starts running.
+enum Entry {
+    /// A function of the kernel, called with an argument.
+    Kernel(extern "C" fn(usize), usize),
+    /// An instruction of a user program, with its stack pointer.
+    User { ip: usize, sp: usize },
+}
+
+/// Creates a new thread named `name` in `process`, which runs `f(arg)` in the kernel.
+///
+/// The thread exits when `f` returns.
+pub fn spawn_kernel(
+    process: ProcessId,
+    name: &'static str,
+    f: extern "C" fn(usize),
+    arg: usize,
+) -> Result<ThreadId, SpawnError> {
+    spawn(process, name, Entry::Kernel(f, arg))
+}
+
+/// Creates a new thread in `process`, which starts running the user program at `ip` in user
+/// mode, with its stack pointer set to `sp`.
+pub fn spawn_user(process: ProcessId, ip: usize, sp: usize) -> Result<ThreadId, SpawnError> {
+    spawn(process, "", Entry::User { ip, sp })
+}
+
+/// Creates a new thread in `process`, which starts running at `entry`.
+///
+/// The thread inherits the nice value of the thread that creates it.
+fn spawn(process: ProcessId, name: &'static str, entry: Entry) -> Result<ThreadId, SpawnError> {
+    let mut scheduler = SCHEDULER.lock();
+    let id = scheduler
+        .threads
+        .iter()
+        .position(Option::is_none)
+        .ok_or(SpawnError::TooManyThreads)?;
+
+    let glob = GLOBAL.get().unwrap();
+    let top = match stack::allocate_thread_stack(&mut glob.allocator.lock(), id) {
+        Ok(top) => top,
+        Err(err) => {
+            drop(scheduler);
+            if matches!(err, MappingError::OutOfMemory) {
+                oom::report("creating a thread");
+            }
+            return Err(SpawnError::OutOfMemory);
+        }
+    };
+
+    // The initial stack is the one `switch_context` expects, returning to the start function
+    // of the thread with its two arguments.
+    let (start, arg0, arg1) = match entry {
+        Entry::Kernel(f, arg) => (kernel_thread_start as usize, f as usize, arg),
+        Entry::User { ip, sp } => (user_thread_start as usize, ip, sp),
+    };
+    let initial = [0, 0, 0, 0, start, 0, arg0, arg1];
+    let context = top - core::mem::size_of_val(&initial);
+    unsafe { (context as *mut [usize; 8]).write(initial) };
+
+    let nice = scheduler.threads[scheduler.current as usize]
+        .as_ref()
+        .map_or(0, |t| t.nice);
+    scheduler.threads[id] = Some(Thread {
+        name,
+        process,
+        state: ThreadState::Ready,
+        deadline: None,
+        context,
+        kernel_stack: top as u32,
+        nice,
+        priority: static_priority(nice),
+        fpu: FpuState::new(),
+    });
+    scheduler.enqueue(id as ThreadId);
+    Ok(id as ThreadId)
+}
+
+/// The start function of a kernel thread.
+extern "C" fn kernel_thread_start(f: extern "C" fn(usize), arg: usize) -> ! {
+    sti();
+    f(arg);
+    exit(0);
+}
+
+/// The start function of a user thread.
+extern "C" fn user_thread_start(ip: usize, sp: usize) -> ! {
+    if reaper::is_current_killed() {
+        exit(reaper::KILLED_STATUS);
+    }
+
+    unsafe {
+        asm!(
+            "
+            mov ds, {data:x}
+            mov es, {data:x}
+            push {data}
+            push {sp}
+            push 0x202
+            push {code}
+            push {ip}
+            iretd
+            ",
+            data = in(reg) (USER_DATA_SEGMENT | 3) as u32,
+            code = const USER_CODE_SEGMENT | 3,
+            sp = in(reg) sp,
+            ip = in(reg) ip,
+            options(noreturn),
+        );
+    }
+}
+
+/// Charges a timer tick to the process that was interrupted by it, or to the idle time.
+///
+/// This function is meant to be called from the timer interrupt handler. `user` is set when
+/// the interrupted code ran in user mode.
+pub fn account_tick(user: bool) {
+    if IDLE.load(Relaxed) {
+        IDLE_TICKS.fetch_add(1, Relaxed);
+        return;
+    }
+
+    let Some(glob) = GLOBAL.get() else {
+        return;
+    };
+    let mut processes = glob.processes.lock();
+    let cpu = &mut processes.current_mut().cpu;
+    if user {
+        cpu.user = cpu.user.wrapping_add(1);
+    } else {
+        cpu.kernel = cpu.kernel.wrapping_add(1);
+    }
+}
+
+/// Returns the number of nanoseconds between two ticks charged by [`account_tick`].
+///
+/// The ticks come from the timer of the local APIC when it runs, and from the PIT otherwise.
+pub fn tick_interval_ns() -> u32 {
+    lapic::timer_interval_ns().unwrap_or_else(pit::interval_ns)
+}
+
+/// Returns the number of timer ticks during which the idle thread ran.
+pub fn idle_ticks() -> u32 {
+    IDLE_TICKS.load(Relaxed)
+}
+
+/// Lets the other threads that are ready run before the current one.
+pub fn yield_now() {
+    reschedule(ThreadState::Ready, None);
+}
+
+/// Blocks the current thread until it is woken up with [`wake`], or until the monotonic clock
+/// reaches `deadline` (in nanoseconds).
+pub fn block(deadline: Option<u64>) {
+    reschedule(ThreadState::Blocked, deadline);
+}
+
+/// Makes the thread `id` ready if it is blocked.
+pub fn wake(id: ThreadId) {
+    let mut scheduler = SCHEDULER.lock();
+    let blocked = scheduler
+        .threads
+        .get(id as usize)
+        .and_then(Option::as_ref)
+        .is_some_and(|t| t.state == ThreadState::Blocked);
+    if blocked {
+        scheduler.enqueue(id);
+    }
+}
+
+/// Makes every blocked thread of `process` ready.
+pub fn wake_process(process: ProcessId) {
+    let mut scheduler = SCHEDULER.lock();
+    for id in 0..MAX_THREADS as ThreadId {
+        let blocked = scheduler.threads[id as usize]
+            .as_ref()
+            .is_some_and(|t| t.process == process && t.state == ThreadState::Blocked);
+        if blocked {
+            scheduler.enqueue(id);
+        }
+    }
+}
+
+/// Terminates the current thread.
+///
+/// When it is the last thread of its process, the process exits with `status` (see
+/// [`reaper::exit`]). The boot thread never exits: it is blocked forever instead.
+pub fn exit(status: u8) -> ! {
+    let scheduler = SCHEDULER.lock();
+    let current = scheduler.current;
+    let process = scheduler.threads[current as usize]
+        .as_ref()
+        .map_or(INIT, |t| t.process);
+    let last = !scheduler.threads.iter().enumerate().any(|(id, t)| {
+        id != current as usize
+            && t.as_ref()
+                .is_some_and(|t| t.process == process && t.state != ThreadState::Exited)
+    });
+    drop(scheduler);
+
+    if last && process != INIT {
+        reaper::exit(process, status);
+    }
+
+    loop {
+        if current == BOOT_THREAD {
+            block(None);
+        } else {
+            reschedule(ThreadState::Exited, None);
+        }
+    }
+}
+
+/// Puts the current thread in `state`, and runs the next thread that is ready.
+///
+/// This returns once the current thread runs again.
+fn reschedule(state: ThreadState, deadline: Option<u64>) {
+    let _restore = RestoreInterrupts::without_interrupts();
+
+    let mut scheduler = SCHEDULER.lock();
+    let current = scheduler.current;
+    if state == ThreadState::Ready && Some(current) != scheduler.idle {
+        scheduler.enqueue(current);
+    } else if let Some(thread) = &mut scheduler.threads[current as usize] {
+        thread.state = state;
+        thread.deadline = deadline;
+    }
+
+    loop {
+        reap(&mut scheduler);
+
+        let now = time::monotonic_ns();
+        scheduler.wake_expired(now);
+        if now >= scheduler.next_boost {
+            scheduler.boost();
+            scheduler.next_boost = now + BOOST_PERIOD_NS;
+        }
+
+        let Some(next) = scheduler.dequeue().or(scheduler.idle) else {
+            // The idle thread is not created yet: nothing can run before the next interrupt.
+            drop(scheduler);
+            sti();
+            hlt();
+            cli();
+            scheduler = SCHEDULER.lock();
+            continue;
+        };
+        IDLE.store(Some(next) == scheduler.idle, Relaxed);
+
+        let thread = scheduler.threads[next as usize].as_mut().unwrap();
+        thread.state = ThreadState::Running;
+        thread.priority = static_priority(thread.nice);
+        if next == current {
+            return;
+        }
+
+        let (process, to, kernel_stack) = (thread.process, thread.context, thread.kernel_stack);
+        let fpu = &mut thread.fpu as *mut FpuState;
+        let from = match &mut scheduler.threads[current as usize] {
+            Some(thread) => &mut thread.context as *mut usize,
+            None => unreachable!("the current thread does not exist"),
+        };
+        scheduler.current = next;
+        drop(scheduler);
+
+        if let Some(glob) = GLOBAL.get() {
+            glob.processes.lock().set_current(process);
+        }
+        unsafe {
+            tss::set_kernel_stack(kernel_stack);
+            fpu::switch_to(fpu);
+            switch_context(from, to);
+        }
+        return;
+    }
+}
+
+/// Releases the threads that exited, except the current one, whose stack is still in use.
+fn reap(scheduler: &mut Scheduler) {
+    let current = scheduler.current as usize;
+    for (id, slot) in scheduler.threads.iter_mut().enumerate() {
+        if id == current
+            || !slot
+                .as_ref()
+                .is_some_and(|t| t.state == ThreadState::Exited)
+        {
+            continue;
+        }
+
+        if let Some(thread) = slot {
+            fpu::forget(&mut thread.fpu);
+        }
+        *slot = None;
+        if let Some(glob) = GLOBAL.get() {
+            stack::deallocate_thread_stack(&mut glob.allocator.lock(), id);
+        }
+    }
+}
+
+/// Saves the registers of the current thread on its stack, stores its stack pointer in `from`,
+/// and resumes the thread whose stack pointer is `to`.
+///
+/// # Safety
+///
+/// `to` must be the stack pointer of a thread that was saved by this function, or an initial
+/// stack built by [`spawn`].
+#[naked]
+unsafe extern "C" fn switch_context(from: *mut usize, to: usize) {
+    asm!(
+        "
+        mov eax, [esp + 4]
+        mov ecx, [esp + 8]
+        push ebp
+        push ebx
+        push esi
+        push edi
+        mov [eax], esp
+        mov esp, ecx
+        pop edi
+        pop esi
+        pop ebx
+        pop ebp
+        ret
+        ",
+        options(noreturn)
+    );
+}
@@ -1,47 +1,960 @@
 //! Provides a simple shell implementation.
 
+mod alias;
+mod jobs;
+
 use core::arch::asm;
-use core::fmt::Write;
+use core::fmt::{Display, Write};
+
+use crate::cpu::paging::vma::Backing;
+use crate::cpu::paging::{self, PageTableFlags};
+use crate::drivers::{serial, speaker, vga};
+use crate::fs::{self, path};
+use crate::power::{self, RebootMethod};
+use crate::state::{
+    self, Amount, Environment, FrameOwner, ProcessId, ReceivedSignal, Resource, Signal,
+    StraceOutput, UserId, GLOBAL, MAX_CALLERS,
+};
+use crate::terminal::{
+    Action, Chord, CursorStyle, ReadLine, Terminal, Theme, TtyModes, INPUT_BUFFER_SIZE,
+    MAX_BINDINGS, MAX_FILTER_LEN,
+};
+use crate::trace::trace;
+use crate::utility::instr::{read_cr2, read_cr3, Cr0, Cr4, EFlags, Msr};
+use crate::utility::{Address, ArrayVec, Column, HumanBytes, Mutex, Table};
+use crate::{kernel_image, log, printk, sched, time, TERMINAL};
+
+use self::alias::Aliases;
+
+/// The default format of the prompt. See [`Shell::set_prompt_format`].
+const DEFAULT_PROMPT: &[u8] = b"\\u@\\h:\\l:\\w\\$ ";
 
-use crate::die::reset_cpu;
-use crate::drivers::vga;
-use crate::state::GLOBAL;
-use crate::terminal::{ReadLine, Terminal};
-use crate::utility::HumanBytes;
-use crate::{printk, TERMINAL};
+/// The name of the host, as displayed in the prompt.
+const HOSTNAME: &str = "kfs";
+
+/// The name of the terminal the shell is running on, as displayed in the prompt.
+const TTY_NAME: &str = "tty1";
 
 /// A simple implementation of the [`ReadLine`] trait for the terminal.
-#[derive(Default)]
 pub struct Shell {
     /// The index of the command to be executed.
-    to_execute: Option<usize>,
+    to_execute: Option<&'static Command>,
+    /// The arguments passed to the command to be executed.
+    args: ArrayVec<u8, { vga::WIDTH as usize }>,
+    /// The pattern used to filter the output of the command to be executed.
+    ///
+    /// When empty, the output is not filtered.
+    filter: ArrayVec<u8, MAX_FILTER_LEN>,
+    /// The format of the prompt. See [`Shell::set_prompt_format`].
+    prompt_format: ArrayVec<u8, { vga::WIDTH as usize }>,
+    /// The user the shell is running as.
+    user: UserId,
+    /// The current working directory of the shell.
+    cwd: path::PathBuf,
+    /// Whether the last command that was executed failed.
+    failed: bool,
+    /// The aliases defined with the `alias` command.
+    aliases: Aliases,
+    /// The variables defined with the `set` command.
+    env: Environment,
+    /// The file the output of the command to be executed is written to, if any.
+    redirect: Option<Redirect>,
+    /// Whether the command to be executed runs in the background, as a job.
+    background: bool,
+    /// The name of the terminal the shell is running on, as displayed in the prompt.
+    tty_name: &'static str,
+    /// Whether the shell runs on the console, whose prompt it keeps up to date.
+    console: bool,
+}
+
+/// A file the output of a command is written to, with `> file` or `>> file`.
+struct Redirect {
+    /// The normalized path of the file.
+    path: path::PathBuf,
+    /// Whether the output is appended to the file, rather than replacing its content.
+    append: bool,
+}
+
+impl Redirect {
+    /// Opens the file, creating it if it does not exist.
+    fn open(&self) -> Result<fs::FileWriter, fs::FsError> {
+        let node = match fs::lookup(&self.path) {
+            Err(fs::FsError::NotFound) => fs::create(&self.path, fs::NodeKind::File)?,
+            node => node?,
+        };
+
+        let offset = if self.append {
+            node.metadata()?.size
+        } else {
+            node.truncate(0)?;
+            0
+        };
+
+        Ok(fs::FileWriter::new(node, offset))
+    }
+}
+
+/// Writes the output that the terminal kept for a redirection to `writer`, and returns the
+/// first error that occurred.
+///
+/// The terminal is only locked while each chunk is taken, as writing to the file may print
+/// messages.
+fn write_redirected(mut writer: fs::FileWriter) -> Option<fs::FsError> {
+    let mut chunk = [0; 512];
+    loop {
+        let count = TERMINAL.lock().take_redirected(&mut chunk);
+        if count == 0 {
+            return writer.error();
+        }
+        writer.write_bytes(&chunk[..count]);
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self {
+            to_execute: None,
+            args: ArrayVec::new(),
+            filter: ArrayVec::new(),
+            prompt_format: ArrayVec::from_slice_truncated(DEFAULT_PROMPT),
+            user: state::ROOT,
+            cwd: path::root(),
+            failed: false,
+            aliases: Aliases::default(),
+            env: Environment::default(),
+            redirect: None,
+            background: false,
+            tty_name: TTY_NAME,
+            console: true,
+        }
+    }
 }
 
 impl Shell {
+    /// Creates a shell that runs as `user` for a remote terminal named `tty_name`.
+    ///
+    /// Such a shell leaves the prompt of the console alone: its owner displays
+    /// [`Shell::prompt`] itself, and runs command-lines with [`Shell::execute`]. It only runs
+    /// the commands listed in [`REMOTE_COMMANDS`].
+    pub fn remote(tty_name: &'static str, user: UserId) -> Self {
+        Self {
+            user,
+            tty_name,
+            console: false,
+            ..Self::default()
+        }
+    }
+
+    /// Executes a command-line right away.
+    ///
+    /// Errors that prevent the command from running are written to the terminal.
+    pub fn execute(&mut self, cmdline: &[u8]) {
+        let cmdline = trim_end(trim_start(cmdline));
+        if cmdline.is_empty() {
+            return;
+        }
+        match self.prepare(cmdline) {
+            Ok(()) => self.run(),
+            Err(err) => {
+                self.fail();
+                let _ = writeln!(TERMINAL.lock(), "{err}");
+            }
+        }
+    }
+
     /// Runs the shell.
     pub fn run(&mut self) {
-        if let Some(to_execute) = self.to_execute.take() {
-            let (_, handler) = COMMANDS[to_execute];
-            handler();
+        if let Some(command) = self.to_execute.take() {
+            let Command { name, handler, .. } = *command;
+            let args = core::mem::take(&mut self.args);
+            trace!(
+                "shell",
+                "running `{}`",
+                core::str::from_utf8(name).unwrap_or("?")
+            );
+            self.failed = false;
+
+            if core::mem::take(&mut self.background) {
+                let mut term = TERMINAL.lock();
+                match jobs::start(self, command, &args) {
+                    Ok(job) => {
+                        let _ = writeln!(term, "[{}] {}", job.number, job.thread);
+                    }
+                    Err(err) => {
+                        let _ = writeln!(term, "{}", CommandError::Job(err));
+                        self.fail();
+                    }
+                }
+                self.refresh_prompt(&mut term);
+                return;
+            }
+
+            let writer = match self.redirect.take().map(|r| r.open()) {
+                Some(Ok(writer)) => Some(writer),
+                Some(Err(err)) => {
+                    let mut term = TERMINAL.lock();
+                    let _ = writeln!(term, "{}", CommandError::Redirect(err));
+                    self.fail();
+                    self.refresh_prompt(&mut term);
+                    return;
+                }
+                None => None,
+            };
+
+            let mut term = TERMINAL.lock();
+            term.begin_paging(&self.filter);
+            if writer.is_some() {
+                term.begin_redirect();
+            }
+            drop(term);
+
+            handler(self, &args);
+
+            let mut term = TERMINAL.lock();
+            term.end_paging();
+            let truncated = writer.is_some() && term.end_redirect();
+            drop(term);
+
+            // The file is written once the command released its locks.
+            let error = writer
+                .and_then(write_redirected)
+                .or(truncated.then_some(fs::FsError::NoSpace));
+            let mut term = TERMINAL.lock();
+            if let Some(err) = error {
+                let _ = writeln!(term, "{}", CommandError::Redirect(err));
+                self.fail();
+            }
+            self.refresh_prompt(&mut term);
+        }
+    }
+
+    /// Runs the commands of a script, one per line.
+    ///
+    /// Empty lines and lines starting with `#` are ignored, and every command is echoed before
+    /// it runs. The script stops at the first command that is invalid or that fails.
+    pub fn run_script(&mut self, script: &[u8]) {
+        for (index, line) in script.split(|&c| c == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let line = trim_end(trim_start(line));
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+
+            let text = core::str::from_utf8(line).unwrap_or("<invalid utf-8>");
+            let _ = writeln!(TERMINAL.lock(), "+ {text}");
+
+            let error = match self.prepare(line) {
+                Ok(()) => {
+                    self.run();
+                    self.failed.then_some(CommandError::Failed)
+                }
+                Err(err) => Some(err),
+            };
+
+            if let Some(err) = error {
+                let mut term = TERMINAL.lock();
+                let error_color = term.theme().error;
+                term.set_color(error_color);
+                let _ = writeln!(
+                    term,
+                    "script: line {}: {err}; the rest of the script is skipped",
+                    index + 1,
+                );
+                term.reset_color();
+                return;
+            }
+        }
+    }
+
+    /// Prepares a command-line to be executed the next time the shell runs.
+    ///
+    /// If the command is an alias, it is replaced by the command-line the alias expands to.
+    /// Aliases are only expanded once, so an alias may refer to a command of the same name.
+    /// Variables are then expanded. See [`expand_variables`].
+    fn prepare(&mut self, cmdline: &[u8]) -> Result<(), CommandError> {
+        let mut expanded = ArrayVec::<u8, { 2 * vga::WIDTH as usize }>::new();
+        let (name, rest) = split_command(cmdline);
+        let cmdline = match self.aliases.get(name) {
+            Some(value) => {
+                if value.len() + 1 + rest.len() > expanded.capacity() {
+                    return Err(CommandError::TooManyArguments);
+                }
+                expanded.extend_from_slice(value);
+                expanded.extend_from_slice(b" ");
+                expanded.extend_from_slice(rest);
+                &expanded
+            }
+            None => cmdline,
+        };
+
+        let mut with_variables = ArrayVec::<u8, { 2 * vga::WIDTH as usize }>::new();
+        expand_variables(&self.env, cmdline, &mut with_variables)
+            .ok_or(CommandError::TooManyArguments)?;
+        let cmdline = trim_end(&with_variables);
+        let (cmdline, background) = match cmdline.strip_suffix(b"&") {
+            Some(cmdline) => (trim_end(cmdline), true),
+            None => (cmdline, false),
+        };
+
+        let (cmdline, redirect) = split_redirect(cmdline).ok_or(CommandError::InvalidRedirect)?;
+        let (cmdline, filter) = split_filter(cmdline).ok_or(CommandError::InvalidFilter)?;
+        if filter.len() > MAX_FILTER_LEN {
+            return Err(CommandError::FilterTooLong);
+        }
+        if background && !self.console {
+            // The output of the job would go to the console rather than to the remote terminal.
+            return Err(CommandError::RemoteJob);
+        }
+        if background && (redirect.is_some() || !filter.is_empty()) {
+            return Err(CommandError::BackgroundOutput);
         }
+
+        let redirect = match redirect {
+            Some((path, append)) => Some(Redirect {
+                path: path::resolve(&self.cwd, path).map_err(CommandError::Redirect)?,
+                append,
+            }),
+            None => None,
+        };
+
+        let (name, args) = split_command(cmdline);
+        if args.len() > self.args.capacity() {
+            return Err(CommandError::TooManyArguments);
+        }
+
+        let command = find_command(name).ok_or(CommandError::UnknownCommand)?;
+        if !self.console && !REMOTE_COMMANDS.contains(&command.name) {
+            return Err(CommandError::NotRemote);
+        }
+        self.to_execute = Some(command);
+        self.args.clear();
+        self.args.extend_from_slice(args);
+        self.filter.clear();
+        self.filter.extend_from_slice(filter);
+        self.redirect = redirect;
+        self.background = background;
+        Ok(())
+    }
+
+    /// Records that the command being executed failed.
+    ///
+    /// This stops the script that is running the command, if any.
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    /// Returns whether the shell runs as the super-user.
+    #[inline]
+    pub fn is_super_user(&self) -> bool {
+        self.user == state::ROOT
+    }
+
+    /// Returns the user the shell runs as.
+    #[inline]
+    pub fn user(&self) -> UserId {
+        self.user
+    }
+
+    /// Schedules a command to be executed without arguments the next time the shell runs.
+    fn schedule(&mut self, name: &[u8]) {
+        self.to_execute = find_command(name);
+        self.args.clear();
+        self.filter.clear();
+        self.background = false;
+    }
+
+    /// Sets the format of the prompt.
+    ///
+    /// The following escape sequences are expanded when the prompt is rendered:
+    ///
+    /// - `\u`: the name of the current user.
+    /// - `\h`: the name of the host.
+    /// - `\l`: the name of the terminal.
+    /// - `\w`: the current working directory.
+    /// - `\$`: `#` for the super-user, `$` otherwise.
+    /// - `\\`: a backslash.
+    pub fn set_prompt_format(&mut self, format: &[u8]) {
+        self.prompt_format = ArrayVec::from_slice_truncated(format);
+    }
+
+    /// Renders the prompt and sends it to the terminal, if the shell runs on the console.
+    pub fn refresh_prompt(&self, term: &mut Terminal) {
+        if self.console {
+            term.set_prompt(&self.prompt());
+        }
+    }
+
+    /// Renders the prompt.
+    pub fn prompt(&self) -> ArrayVec<u8, { vga::WIDTH as usize }> {
+        let mut prompt = ArrayVec::<u8, { vga::WIDTH as usize }>::new();
+        let mut push = |s: &[u8]| {
+            for &c in s {
+                let _ = prompt.try_push(c);
+            }
+        };
+
+        let mut chars = self.prompt_format.iter();
+        while let Some(&c) = chars.next() {
+            if c != b'\\' {
+                push(&[c]);
+                continue;
+            }
+
+            match chars.next() {
+                Some(b'u') => push(state::user_name(self.user).unwrap_or("?").as_bytes()),
+                Some(b'h') => push(HOSTNAME.as_bytes()),
+                Some(b'l') => push(self.tty_name.as_bytes()),
+                Some(b'w') => push(&self.cwd),
+                Some(b'$') if self.user == state::ROOT => push(b"#"),
+                Some(b'$') => push(b"$"),
+                Some(&other) => push(&[b'\\', other]),
+                None => push(b"\\"),
+            }
+        }
+
+        prompt
     }
 }
 
-/// The list of available commands.
-#[allow(clippy::type_complexity)]
-const COMMANDS: &[(&[u8], fn())] = &[
-    (b"help", help),
-    (b"clear", clear),
-    (b"font", font),
-    (b"system", system),
-    (b"panic", panic),
-    (b"restart", restart),
-    (b"syscall", syscall),
+/// An error that prevents a command-line from being executed successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommandError {
+    /// The filter applied to the output of the command is invalid.
+    InvalidFilter,
+    /// The filter applied to the output of the command is too long.
+    FilterTooLong,
+    /// The arguments of the command do not fit in the shell's buffer.
+    TooManyArguments,
+    /// No command has the requested name.
+    UnknownCommand,
+    /// The output of a command meant to run in the background is redirected or filtered.
+    BackgroundOutput,
+    /// A remote shell was asked to run a command in the background.
+    RemoteJob,
+    /// A remote shell was asked to run a command that is not in [`REMOTE_COMMANDS`].
+    NotRemote,
+    /// The command cannot run in the background.
+    Job(jobs::JobError),
+    /// The path after `>` or `>>` is missing or invalid.
+    InvalidRedirect,
+    /// The output of the command cannot be written to the requested file.
+    Redirect(fs::FsError),
+    /// The command reported an error.
+    Failed,
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::InvalidFilter => "invalid filter; expected `| grep <pattern>`",
+            Self::FilterTooLong => "the filter is too long",
+            Self::TooManyArguments => "the arguments are too long",
+            Self::UnknownCommand => "unknown command",
+            Self::BackgroundOutput => {
+                "the output of a background command cannot be redirected or filtered"
+            }
+            Self::RemoteJob => "background commands can only run on the console",
+            Self::NotRemote => "this command can only run on the console",
+            Self::Job(err) => return write!(f, "cannot start the job: {err}"),
+            Self::InvalidRedirect => "invalid redirection; expected `> <file>` or `>> <file>`",
+            Self::Redirect(err) => return write!(f, "cannot redirect the output: {err}"),
+            Self::Failed => "the command failed",
+        })
+    }
+}
+
+/// A command of the shell.
+pub struct Command {
+    /// The name the command is invoked with.
+    pub name: &'static [u8],
+    /// A short description of the command, listed by `help`.
+    pub summary: &'static str,
+    /// The arguments the command accepts, such as `help [command]`.
+    pub usage: &'static str,
+    /// A longer description of the command, printed by `help <command>`.
+    pub details: &'static str,
+    /// The function that runs the command.
+    ///
+    /// It receives the shell that is running it, and the arguments that were passed to it
+    /// (everything after the command name, with leading spaces removed).
+    pub handler: fn(&mut Shell, &[u8]),
+}
+
+/// The commands that remote shells may run.
+///
+/// They only print information, or change the state of the shell itself. Notably, none of
+/// them stops the machine, changes the console, or runs another command.
+const REMOTE_COMMANDS: &[&[u8]] = &[
+    b"help", b"system", b"free", b"ps", b"date", b"cal", b"mmap", b"frames", b"pmap", b"prompt",
+    b"alias", b"unalias", b"set", b"unset", b"env", b"echo", b"cat", b"cd", b"pwd", b"netstat",
+    b"threads", b"mq", b"shm", b"lsdev",
 ];
 
+/// The maximum number of commands that can be registered with [`register`].
+const MAX_REGISTERED_COMMANDS: usize = 24;
+
+/// The commands registered by other subsystems, listed after the built-in ones.
+static REGISTERED: Mutex<ArrayVec<&'static Command, MAX_REGISTERED_COMMANDS>> =
+    Mutex::new(ArrayVec::new());
+
+/// Registers a command provided by another subsystem.
+///
+/// Returns `false` if a command with the same name already exists, or if too many commands
+/// were registered.
+pub fn register(command: &'static Command) -> bool {
+    if find_command(command.name).is_some() {
+        return false;
+    }
+    REGISTERED.lock().try_push(command).is_ok()
+}
+
+/// Returns an iterator over the available commands, built-in ones first.
+fn commands() -> impl Iterator<Item = &'static Command> {
+    let registered = REGISTERED.lock().clone();
+    COMMANDS
+        .iter()
+        .chain((0..registered.len()).map(move |i| registered[i]))
+}
+
+/// The built-in commands.
+const COMMANDS: &[Command] = &[
+    Command {
+        name: b"help",
+        summary: "display this help",
+        usage: "help [command]",
+        details: "Lists the available commands, or describes the provided one.",
+        handler: help,
+    },
+    Command {
+        name: b"clear",
+        summary: "clear the console",
+        usage: "clear",
+        details: "",
+        handler: clear,
+    },
+    Command {
+        name: b"font",
+        summary: "print all available characters",
+        usage: "font",
+        details: "Prints the characters and the colors that the VGA can display.",
+        handler: font,
+    },
+    Command {
+        name: b"system",
+        summary: "print information about the system",
+        usage: "system",
+        details: "Prints the name of the bootloader and the amount of memory.",
+        handler: system,
+    },
+    Command {
+        name: b"free",
+        summary: "print the amount of free memory",
+        usage: "free",
+        details: "Prints the total and remaining amount of memory below 4 GiB, and of the memory\n\
+                  above it that is reachable through PSE-36.",
+        handler: free,
+    },
+    Command {
+        name: b"ps",
+        summary: "list the processes",
+        usage: "ps",
+        details:
+            "Lists the processes, along with their parent, their owner, their state, and the\n\
+                  memory they use.",
+        handler: ps,
+    },
+    Command {
+        name: b"date",
+        summary: "print or set the date and time",
+        usage: "date [set <YYYY-MM-DD> <HH:MM:SS>]",
+        details: "Times are in UTC. Setting the date also sets the hardware clock, which\n\
+                  requires being the super-user.",
+        handler: date,
+    },
+    Command {
+        name: b"cal",
+        summary: "print a calendar of the current month",
+        usage: "cal",
+        details: "",
+        handler: cal,
+    },
+    Command {
+        name: b"mmap",
+        summary: "print the memory map reported by the firmware",
+        usage: "mmap",
+        details: "",
+        handler: mmap,
+    },
+    Command {
+        name: b"frames",
+        summary: "print the physical frames used by each part of the kernel",
+        usage: "frames [callers]",
+        details: "`callers` lists the places from which the allocated frames were allocated.",
+        handler: frames,
+    },
+    Command {
+        name: b"pmap",
+        summary: "print the memory map of a process",
+        usage: "pmap [pid]",
+        details: "Prints the areas of the address space of the process (the current one by\n\
+                  default), followed by the number of pages it maps and of those that are\n\
+                  resident, and by the number of 4 KiB and huge pages in its page tables.\n\
+                  Every process currently shares the kernel's address space.",
+        handler: pmap,
+    },
+    Command {
+        name: b"vmcheck",
+        summary: "check the page tables of a process",
+        usage: "vmcheck [pid]",
+        details: "Walks the page tables of the address space of the process (the current one by\n\
+                  default), and reports the entries with reserved bits, the tables and pages\n\
+                  outside of available memory, the kernel pages accessible from user mode, and\n\
+                  the pages that do not match an area.",
+        handler: vmcheck,
+    },
+    Command {
+        name: b"protections",
+        summary: "print the permissions of the kernel's sections",
+        usage: "protections",
+        details: "The permissions are read back from the page tables.",
+        handler: protections,
+    },
+    Command {
+        name: b"regs",
+        summary: "print the control registers and the MSRs",
+        usage: "regs",
+        details: "",
+        handler: regs,
+    },
+    Command {
+        name: b"panic",
+        summary: "cause a kernel panic",
+        usage: "panic",
+        details: "",
+        handler: panic,
+    },
+    Command {
+        name: b"restart",
+        summary: "restart the system",
+        usage: "restart [method,...]",
+        details: "Tries the provided methods in order: kbd (keyboard controller), acpi (reset\n\
+                  register), cf9 (reset control register) and triple (triple fault). Without\n\
+                  methods, those configured with `reboot=` on the kernel command-line are used.",
+        handler: restart,
+    },
+    Command {
+        name: b"halt",
+        summary: "stop the system so that it can be turned off",
+        usage: "halt",
+        details: "",
+        handler: halt,
+    },
+    Command {
+        name: b"beep",
+        summary: "play a tone on the PC speaker",
+        usage: "beep [hz] [ms]",
+        details: "Plays a tone of the provided frequency (880 Hz by default) for the provided\n\
+                  duration (200 ms by default).",
+        handler: beep,
+    },
+    Command {
+        name: b"bind",
+        summary: "list or change the key bindings",
+        usage: "bind [chord] [action|none]",
+        details: "Without arguments, lists the key bindings. With a chord, prints the action it\n\
+                  is bound to, binds it to another action, or unbinds it with `none`.\n\
+                  Example: `bind ctrl+f1 clear`.",
+        handler: bind,
+    },
+    Command {
+        name: b"syscall",
+        summary: "perform a system call",
+        usage: "syscall",
+        details: "",
+        handler: syscall,
+    },
+    Command {
+        name: b"strace",
+        summary: "run a command, tracing the system calls it makes",
+        usage: "strace [-s] <command> [args...]",
+        details: "Every system call is printed with its arguments and the value it returns.\n\
+                  With `-s`, the trace is sent to the serial port instead of the terminal.",
+        handler: strace,
+    },
+    Command {
+        name: b"nice",
+        summary: "run a command with another nice value",
+        usage: "nice [-n <adjustment>] [command [args...]]",
+        details: "Adds the adjustment (10 by default) to the nice value of the shell while the\n\
+                  command runs. Without a command, prints the nice value of the shell. Only the\n\
+                  super-user may lower a nice value.",
+        handler: nice,
+    },
+    Command {
+        name: b"renice",
+        summary: "change the nice value of a process",
+        usage: "renice <nice> <pid>",
+        details: "Nice values range from -20 (the highest priority) to 19 (the lowest). Only the\n\
+                  super-user may lower a nice value.",
+        handler: renice,
+    },
+    Command {
+        name: b"ulimit",
+        summary: "print or change the resource limits of a user",
+        usage: "ulimit [uid] [processes|memory <limit>|unlimited]",
+        details: "Without a resource, prints the limits of the user (the one of the shell by\n\
+                  default) and how much of each resource their processes use. Memory limits are\n\
+                  in KiB. Only the super-user may raise a limit, or change those of another user.",
+        handler: ulimit,
+    },
+    Command {
+        name: b"cursor",
+        summary: "change the cursor",
+        usage: "cursor [block|underline|blink|steady]",
+        details: "",
+        handler: cursor,
+    },
+    Command {
+        name: b"stty",
+        summary: "print or change the modes of the terminal",
+        usage: "stty [echo|-echo]",
+        details: "`-echo` hides what is typed on the command-line.",
+        handler: stty,
+    },
+    Command {
+        name: b"theme",
+        summary: "list the color themes or select one",
+        usage: "theme [name]",
+        details: "",
+        handler: theme,
+    },
+    Command {
+        name: b"prompt",
+        summary: "print or change the format of the prompt",
+        usage: "prompt [format]",
+        details: "The format may contain \\u (user), \\h (host), \\l (terminal), \\w (working\n\
+                  directory), \\$ (`#` for the super-user, `$` otherwise) and \\\\ (backslash).",
+        handler: prompt,
+    },
+    Command {
+        name: b"alias",
+        summary: "list the aliases or define one",
+        usage: "alias [name[=command]]",
+        details: "An alias is replaced by its command-line when it is used as a command.\n\
+                  Example: `alias ll=ls -l`.",
+        handler: alias,
+    },
+    Command {
+        name: b"unalias",
+        summary: "remove an alias",
+        usage: "unalias <name>",
+        details: "",
+        handler: unalias,
+    },
+    Command {
+        name: b"jobs",
+        summary: "list the background commands",
+        usage: "jobs",
+        details: "A command runs in the background when its command-line ends with `&`. The\n\
+                  jobs that finished are listed once, then forgotten.",
+        handler: jobs,
+    },
+    Command {
+        name: b"fg",
+        summary: "wait for a background command",
+        usage: "fg [job]",
+        details: "Waits until the job finishes, the most recent one by default.",
+        handler: fg,
+    },
+    Command {
+        name: b"set",
+        summary: "list the variables or set one",
+        usage: "set [name=value]",
+        details: "`$NAME` and `${NAME}` are replaced by the value of the variable `NAME`.",
+        handler: set,
+    },
+    Command {
+        name: b"unset",
+        summary: "remove a variable",
+        usage: "unset <name>",
+        details: "",
+        handler: unset,
+    },
+    Command {
+        name: b"env",
+        summary: "print the variables",
+        usage: "env",
+        details: "",
+        handler: env,
+    },
+    Command {
+        name: b"echo",
+        summary: "print a line of text",
+        usage: "echo [text]",
+        details: "",
+        handler: echo,
+    },
+    Command {
+        name: b"cat",
+        summary: "print the content of a file",
+        usage: "cat <file>",
+        details: "",
+        handler: cat,
+    },
+    Command {
+        name: b"cd",
+        summary: "change the current working directory",
+        usage: "cd [path]",
+        details: "Without a path, goes back to the root directory.",
+        handler: cd,
+    },
+    Command {
+        name: b"pwd",
+        summary: "print the current working directory",
+        usage: "pwd",
+        details: "",
+        handler: pwd,
+    },
+    Command {
+        name: b"screenshot",
+        summary: "dump the screen to the serial port",
+        usage: "screenshot",
+        details: "",
+        handler: screenshot,
+    },
+];
+
+/// Finds the command named `name`.
+fn find_command(name: &[u8]) -> Option<&'static Command> {
+    commands().find(|cmd| cmd.name == name)
+}
+
+/// Returns the arguments accepted by the command named `name`, as displayed after `usage:`.
+pub fn usage(name: &[u8]) -> &'static str {
+    find_command(name).map_or("", |cmd| cmd.usage)
+}
+
+/// Expands the variables of a command-line into `out`.
+///
+/// `$NAME` and `${NAME}` are replaced by the value of the variable `NAME`, or by nothing if it
+/// is not set. A `$` that is not followed by a name is kept as is.
+///
+/// `None` is returned if the expanded command-line does not fit in `out`.
+fn expand_variables<const N: usize>(
+    env: &Environment,
+    mut cmdline: &[u8],
+    out: &mut ArrayVec<u8, N>,
+) -> Option<()> {
+    let mut push =
+        |s: &[u8]| (out.len() + s.len() <= out.capacity()).then(|| out.extend_from_slice(s));
+
+    while let Some(i) = cmdline.iter().position(|&c| c == b'$') {
+        push(&cmdline[..i])?;
+        let rest = &cmdline[i + 1..];
+
+        let (name, rest) = match rest {
+            [b'{', inner @ ..] if inner.contains(&b'}') => {
+                let end = inner.iter().position(|&c| c == b'}').unwrap();
+                (&inner[..end], &inner[end + 1..])
+            }
+            _ => {
+                let end = rest
+                    .iter()
+                    .position(|&c| !state::is_variable_name_char(c))
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+
+        if name.is_empty() {
+            push(b"$")?;
+        } else {
+            push(env.get(name).unwrap_or(&[]))?;
+        }
+        cmdline = rest;
+    }
+
+    push(cmdline)
+}
+
+/// Splits a command-line into the command itself and the file its output is written to.
+///
+/// The file is introduced by `> <file>` to replace its content, or `>> <file>` to append to
+/// it. The file is returned along with whether the output is appended. `None` is returned if
+/// the redirection is invalid.
+#[allow(clippy::type_complexity)]
+fn split_redirect(cmdline: &[u8]) -> Option<(&[u8], Option<(&[u8], bool)>)> {
+    let Some(i) = cmdline.iter().rposition(|&c| c == b'>') else {
+        return Some((cmdline, None));
+    };
+
+    let path = trim_start(&cmdline[i + 1..]);
+    if path.is_empty() || path.contains(&b' ') {
+        return None;
+    }
+
+    match cmdline[..i].strip_suffix(b">") {
+        Some(rest) => Some((trim_end(rest), Some((path, true)))),
+        None => Some((trim_end(&cmdline[..i]), Some((path, false)))),
+    }
+}
+
+/// Splits a command-line into the command itself and the filter applied to its output.
+///
+/// The filter is introduced by `| grep <pattern>`. `None` is returned if the filter is invalid.
+fn split_filter(cmdline: &[u8]) -> Option<(&[u8], &[u8])> {
+    let Some(i) = cmdline.iter().position(|&c| c == b'|') else {
+        return Some((cmdline, &[]));
+    };
+
+    match split_command(&cmdline[i + 1..]) {
+        (b"grep", pattern) if !pattern.is_empty() => {
+            Some((trim_end(&cmdline[..i]), trim_end(pattern)))
+        }
+        _ => None,
+    }
+}
+
+/// Splits a command-line into the name of the command and its arguments.
+pub fn split_command(cmdline: &[u8]) -> (&[u8], &[u8]) {
+    let cmdline = trim_start(cmdline);
+    match cmdline.iter().position(|&c| c == b' ') {
+        Some(i) => (&cmdline[..i], trim_start(&cmdline[i..])),
+        None => (cmdline, &[]),
+    }
+}
+
+/// Removes the trailing spaces of the provided slice.
+pub fn trim_end(mut s: &[u8]) -> &[u8] {
+    while let [rest @ .., b' '] = s {
+        s = rest;
+    }
+    s
+}
+
+/// Removes the leading spaces of the provided slice.
+pub fn trim_start(mut s: &[u8]) -> &[u8] {
+    while let [b' ', rest @ ..] = s {
+        s = rest;
+    }
+    s
+}
+
 impl ReadLine for Shell {
     fn submit(&mut self, term: &mut Terminal) {
-        self.to_execute = COMMANDS.iter().position(|&(cmd, _)| term.cmdline() == cmd);
+        let mut line = [0; INPUT_BUFFER_SIZE];
+        let Some(n) = term.tty_mut().read(&mut line) else {
+            return;
+        };
+        let line = line[..n].strip_suffix(b"\n").unwrap_or(&line[..n]);
+
+        self.to_execute = None;
+        match self.prepare(line) {
+            Ok(()) | Err(CommandError::UnknownCommand) => (),
+            Err(err) => {
+                let _ = writeln!(term, "{err}");
+            }
+        }
     }
 
     fn auto_complete(&mut self, term: &mut Terminal) {
@@ -49,37 +962,88 @@ impl ReadLine for Shell {
             return;
         }
 
-        for (cmd, _) in COMMANDS {
-            if cmd.starts_with(term.cmdline()) {
-                term.cmdline_mut().clear();
-                term.cmdline_mut().extend_from_slice(cmd);
-                term.set_cmdline_cursor(term.cmdline().len());
+        for cmd in commands() {
+            if cmd.name.starts_with(term.cmdline()) {
+                term.tty_mut().editor_mut().set_line(cmd.name);
                 term.refresh_cmdline();
             }
         }
     }
+
+    fn end_of_file(&mut self, term: &mut Terminal) {
+        // The shell is the only program: it cannot exit.
+        let _ = term.tty_mut().read(&mut []);
+        let _ = writeln!(term, "use `halt` to stop the system");
+    }
+
+    fn action(&mut self, term: &mut Terminal, action: Action) {
+        match action {
+            Action::Screenshot => {
+                let _ = term.screenshot(&mut serial::Serial);
+            }
+            // Those are run like commands, as they need the terminal to be unlocked.
+            Action::Reboot => self.schedule(b"restart"),
+            Action::Halt => self.schedule(b"halt"),
+            Action::InterruptInit => {
+                let mut processes = GLOBAL.get().unwrap().processes.lock();
+                let init = processes
+                    .get_mut(state::INIT)
+                    .expect("the init process is gone");
+                let signal = ReceivedSignal { sent_by: None };
+                if !init.signals.schedule(Signal::Int, signal) {
+                    log!("SIGINT is already pending for the init process.\n");
+                }
+            }
+            _ => (),
+        }
+    }
 }
 
 /// The `help` command.
-pub fn help() {
+pub fn help(shell: &mut Shell, args: &[u8]) {
     let mut term = TERMINAL.lock();
-    term.insert_linefeed();
-    let _ = term.write_str(include_str!("help.txt"));
+    let args = trim_end(args);
+
+    if args.is_empty() {
+        let _ = writeln!(term, "\nThe following commands are available:");
+        for cmd in commands() {
+            let name = core::str::from_utf8(cmd.name).unwrap_or("?");
+            let _ = writeln!(term, " - {name:<12} {}", cmd.summary);
+        }
+        let _ = writeln!(
+            term,
+            "\nType `help <command>` to learn more about a command."
+        );
+        let _ = term.write_str(include_str!("help.txt"));
+        return;
+    }
+
+    let Some(cmd) = find_command(args) else {
+        let name = core::str::from_utf8(args).unwrap_or("<invalid utf-8>");
+        let _ = writeln!(term, "help: {name}: unknown command");
+        shell.fail();
+        return;
+    };
+
+    let name = core::str::from_utf8(cmd.name).unwrap_or("?");
+    let _ = writeln!(term, "{name} - {}", cmd.summary);
+    let _ = writeln!(term, "usage: {}", cmd.usage);
+    if !cmd.details.is_empty() {
+        let _ = writeln!(term, "\n{}", cmd.details);
+    }
 }
 
 /// The `clear` command.
-pub fn clear() {
+pub fn clear(_shell: &mut Shell, _args: &[u8]) {
     TERMINAL.lock().reset();
 }
 
 /// The `font` command.
-pub fn font() {
+pub fn font(_shell: &mut Shell, _args: &[u8]) {
     let mut term = TERMINAL.lock();
 
     let _ = term.write_str("\nAvailable characters:\n");
-    for i in vga::VgaChar::iter_all() {
-        term.write_vga_char(i);
-    }
+    term.write_vga_chars(vga::VgaChar::iter_all());
 
     let _ = term.write_str("\n\nAvailable colors:\n");
     for c in vga::Color::iter_all() {
@@ -87,16 +1051,22 @@ pub fn font() {
         term.write_vga_char(vga::VgaChar::FULL_BLOCK);
         term.write_vga_char(vga::VgaChar::FULL_BLOCK);
     }
-    term.set_color(vga::Color::White);
+    term.reset_color();
     term.insert_linefeed();
 }
 
 /// The `system` command.
-pub fn system() {
+pub fn system(_shell: &mut Shell, _args: &[u8]) {
     let glob = GLOBAL.get().unwrap();
 
     let total_memory = glob.system_info.total_memory;
-    let remaining_memory = glob.allocator.lock().remaining_memory() as u64;
+    let (remaining_memory, zeroed_memory) = {
+        let allocator = glob.allocator.lock();
+        (
+            allocator.remaining_memory() as u64,
+            allocator.zeroed_memory() as u64,
+        )
+    };
     let bootloader_name = glob
         .system_info
         .bootloader_name
@@ -110,33 +1080,571 @@ pub fn system() {
         \n\
       	total memory: {memory} ({memory_b} bytes)\n\
         remaining memory: {remaining} ({remaining_b} bytes)\n\
+        zeroed in advance: {zeroed}\n\
        	",
         memory = HumanBytes(total_memory as u64),
         memory_b = total_memory,
         remaining = HumanBytes(remaining_memory),
         remaining_b = remaining_memory,
+        zeroed = HumanBytes(zeroed_memory),
+    );
+
+    if glob.system_info.high_memory != 0 {
+        let remaining = glob.allocator.lock().remaining_high_memory();
+        printk!(
+            "high memory: {} ({} remaining, through PSE-36)\n",
+            HumanBytes(glob.system_info.high_memory),
+            HumanBytes(remaining),
+        );
+    }
+
+    let unreachable = glob.system_info.unreachable_memory;
+    if unreachable != 0 {
+        printk!(
+            "unreachable memory: {} ({unreachable} bytes, above 4 GiB)\n",
+            HumanBytes(unreachable),
+        );
+    }
+
+    let drivers = glob.system_info.drivers.lock();
+    for driver in drivers.iter() {
+        if let Err(err) = driver.result {
+            printk!("missing device: {} ({err})\n", driver.name);
+        }
+    }
+}
+
+/// Parses `N` numbers separated by `sep`, such as `12:30:00`.
+fn parse_fields<const N: usize>(s: &[u8], sep: u8) -> Option<[u32; N]> {
+    let mut fields = s.split(|&c| c == sep);
+    let mut ret = [0; N];
+    for field in &mut ret {
+        *field = parse_u32(fields.next()?)?;
+    }
+    fields.next().is_none().then_some(ret)
+}
+
+/// Parses a date such as `2024-03-01 12:30:00`.
+fn parse_date(s: &[u8]) -> Option<time::DateTime> {
+    let (date, time) = split_command(s);
+    let [year, month, day] = parse_fields(date, b'-')?;
+    let [hour, minute, second] = parse_fields(trim_end(time), b':')?;
+
+    Some(time::DateTime {
+        year: year.try_into().ok()?,
+        month: month.try_into().ok()?,
+        day: day.try_into().ok()?,
+        hour: hour.try_into().ok()?,
+        minute: minute.try_into().ok()?,
+        second: second.try_into().ok()?,
+        nanosecond: 0,
+    })
+}
+
+/// The `date` command.
+pub fn date(shell: &mut Shell, args: &[u8]) {
+    let (sub, rest) = split_command(args);
+    match sub {
+        b"" => printk!("{}\n", time::now()),
+        b"set" => {
+            if shell.user != state::ROOT {
+                printk!("date: only the super-user may set the date\n");
+                shell.fail();
+                return;
+            }
+
+            match parse_date(rest) {
+                Some(date) if time::set(date) => printk!("{}\n", time::now()),
+                Some(_) => {
+                    printk!("date: the date must exist and lie between 2000 and 2099\n");
+                    shell.fail();
+                }
+                None => {
+                    printk!("usage: {}\n", usage(b"date"));
+                    shell.fail();
+                }
+            }
+        }
+        _ => {
+            printk!("usage: {}\n", usage(b"date"));
+            shell.fail();
+        }
+    }
+}
+
+/// The `cal` command.
+pub fn cal(_shell: &mut Shell, _args: &[u8]) {
+    let now = time::now();
+    let first = time::DateTime { day: 1, ..now }.weekday();
+    let days = time::days_in_month(now.year, now.month);
+
+    let mut term = TERMINAL.lock();
+    let title = time::MONTH_NAMES[now.month as usize - 1];
+    // The title is centered above the 26 columns of the calendar.
+    let pad = (26 - title.len() - 5) / 2;
+    let _ = writeln!(term, "{:pad$}{title} {}", "", now.year);
+
+    let names = ["Su", "Mo", "Tu", "We", "Th", "Fr", "Sa"];
+    let mut table = Table::new(&mut *term, names.map(|name| Column::right(name, 2)));
+    let _ = table.header();
+
+    // The cells before the first day of the month and after its last day are left empty.
+    let mut day = 1 - first as i32;
+    while day <= days as i32 {
+        let week: [Option<i32>; 7] = core::array::from_fn(|i| {
+            let d = day + i as i32;
+            (1..=days as i32).contains(&d).then_some(d)
+        });
+        let _ = table.row(core::array::from_fn(|i| match &week[i] {
+            Some(d) => d as &dyn core::fmt::Display,
+            None => &"",
+        }));
+        day += 7;
+    }
+}
+
+/// The `mmap` command.
+pub fn mmap(_shell: &mut Shell, _args: &[u8]) {
+    let glob = GLOBAL.get().unwrap();
+    let mut term = TERMINAL.lock();
+
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::left("START", 12),
+            Column::left("END", 12),
+            Column::right("SIZE", 11),
+            Column::left("TYPE", 16),
+        ],
+    );
+
+    let _ = table.header();
+    for region in glob.system_info.memory_map.iter() {
+        let _ = table.row([
+            &format_args!("{:#012x}", region.start),
+            &format_args!("{:#012x}", region.end().unwrap_or(u64::MAX)),
+            &HumanBytes(region.len),
+            &region.ty.name(),
+        ]);
+    }
+}
+
+/// The `free` command.
+pub fn free(_shell: &mut Shell, _args: &[u8]) {
+    let glob = GLOBAL.get().unwrap();
+    let (remaining, zeroed, remaining_high) = {
+        let allocator = glob.allocator.lock();
+        (
+            allocator.remaining_memory() as u64,
+            allocator.zeroed_memory() as u64,
+            allocator.remaining_high_memory(),
+        )
+    };
+    let total = glob.system_info.total_memory as u64;
+    let total_high = glob.system_info.high_memory;
+
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::left("", 4),
+            Column::right("TOTAL", 11),
+            Column::right("USED", 11),
+            Column::right("FREE", 11),
+            Column::right("ZEROED", 11),
+        ],
+    );
+
+    let _ = table.header();
+    let _ = table.row([
+        &"low",
+        &HumanBytes(total),
+        &HumanBytes(total.saturating_sub(remaining)),
+        &HumanBytes(remaining),
+        &HumanBytes(zeroed),
+    ]);
+    if total_high != 0 {
+        let _ = table.row([
+            &"high",
+            &HumanBytes(total_high),
+            &HumanBytes(total_high.saturating_sub(remaining_high)),
+            &HumanBytes(remaining_high),
+            &"-",
+        ]);
+    }
+}
+
+/// The `ps` command.
+pub fn ps(_shell: &mut Shell, _args: &[u8]) {
+    let glob = GLOBAL.get().unwrap();
+    let processes = glob.processes.lock();
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::right("PID", 5),
+            Column::right("PPID", 5),
+            Column::left("USER", 8),
+            Column::left("STATE", 6),
+            Column::right("RES", 11),
+            Column::right("VIRT", 11),
+        ],
+    );
+
+    let _ = table.header();
+    for (pid, process) in processes.iter() {
+        let _ = table.row([
+            &pid,
+            &process.parent,
+            &state::user_name(process.owner).unwrap_or("?"),
+            &process.state.name(),
+            &HumanBytes(process.memory.resident as u64 * 4096),
+            &HumanBytes(process.memory.mapped as u64 * 4096),
+        ]);
+    }
+}
+
+/// The `frames` command.
+pub fn frames(shell: &mut Shell, args: &[u8]) {
+    // The counts are copied, so that the allocator is not locked while they are printed.
+    let (by_owner, by_caller, callers) = {
+        let allocator = GLOBAL.get().unwrap().allocator.lock();
+        let tags = allocator.tags();
+        (
+            tags.count_by_owner(),
+            tags.count_by_caller(),
+            ArrayVec::<_, MAX_CALLERS>::from_slice_truncated(tags.callers()),
+        )
+    };
+    let mut term = TERMINAL.lock();
+
+    match args {
+        b"" => {
+            let mut table = Table::new(
+                &mut *term,
+                [
+                    Column::left("OWNER", 12),
+                    Column::right("FRAMES", 8),
+                    Column::right("SIZE", 11),
+                ],
+            );
+
+            let _ = table.header();
+            for (owner, count) in FrameOwner::ALL.into_iter().zip(by_owner) {
+                if owner == FrameOwner::Reserved || count == 0 {
+                    continue;
+                }
+                let _ = table.row([&owner.name(), &count, &HumanBytes(count as u64 * 4096)]);
+            }
+        }
+        b"callers" => {
+            let mut table = Table::new(
+                &mut *term,
+                [Column::left("CALLER", 40), Column::right("FRAMES", 8)],
+            );
+
+            let _ = table.header();
+            for (caller, &count) in callers.iter().zip(by_caller.iter()) {
+                if count != 0 {
+                    let _ = table.row([caller, &count]);
+                }
+            }
+            if by_caller[MAX_CALLERS] != 0 {
+                let _ = table.row([&"<unknown>", &by_caller[MAX_CALLERS]]);
+            }
+        }
+        _ => {
+            drop(term);
+            printk!("usage: {}\n", usage(b"frames"));
+            shell.fail();
+        }
+    }
+}
+
+/// The `pmap` command.
+pub fn pmap(shell: &mut Shell, args: &[u8]) {
+    let glob = GLOBAL.get().unwrap();
+    let processes = glob.processes.lock();
+    let process = match args {
+        b"" => Some(processes.current()),
+        _ => parse_u32(args).and_then(|pid| processes.get(pid as ProcessId)),
+    };
+    let Some(process) = process else {
+        drop(processes);
+        printk!("pmap: no such process\n");
+        shell.fail();
+        return;
+    };
+    let memory = process.memory;
+    let pages = paging::spaces::page_counts(process.address_space);
+    drop(processes);
+
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::left("START", 10),
+            Column::left("END", 10),
+            Column::right("SIZE", 11),
+            Column::left("PERM", 4),
+            Column::left("MODE", 6),
+            Column::left("BACKING", 9),
+            Column::left("NAME", 12),
+        ],
+    );
+
+    let _ = table.header();
+    for area in paging::KERNEL_AREAS.lock().iter() {
+        let perm = match (
+            area.flags.contains(PageTableFlags::WRITABLE),
+            area.flags.contains(PageTableFlags::NO_EXECUTE),
+        ) {
+            _ if area.backing == Backing::Guard => "---",
+            (false, false) => "r-x",
+            (false, true) => "r--",
+            (true, false) => "rwx",
+            (true, true) => "rw-",
+        };
+        let mode = if area.flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+            "user"
+        } else {
+            "kernel"
+        };
+
+        let _ = table.row([
+            &format_args!("{:#010x}", area.start),
+            &format_args!("{:#010x}", area.end),
+            &HumanBytes(area.len() as u64),
+            &perm,
+            &mode,
+            &area.backing.name(),
+            &area.name,
+        ]);
+    }
+
+    let _ = writeln!(
+        term,
+        "mapped: {} pages ({}), resident: {} pages ({})",
+        memory.mapped,
+        HumanBytes(memory.mapped as u64 * 4096),
+        memory.resident,
+        HumanBytes(memory.resident as u64 * 4096),
+    );
+    let _ = writeln!(
+        term,
+        "pages: {} of 4 KiB, {} of {}",
+        pages.small,
+        pages.huge,
+        HumanBytes(paging::huge_page_size() as u64),
+    );
+}
+
+/// The `vmcheck` command.
+pub fn vmcheck(shell: &mut Shell, args: &[u8]) {
+    /// The maximum number of violations that are printed.
+    const MAX_PRINTED: usize = 16;
+
+    let glob = GLOBAL.get().unwrap();
+    let processes = glob.processes.lock();
+    let process = match args {
+        b"" => Some(processes.current()),
+        _ => parse_u32(args).and_then(|pid| processes.get(pid as ProcessId)),
+    };
+    let Some(process) = process else {
+        drop(processes);
+        printk!("vmcheck: no such process\n");
+        shell.fail();
+        return;
+    };
+    let root = process.address_space;
+    drop(processes);
+
+    let mut found = ArrayVec::<_, MAX_PRINTED>::new();
+    let mut count = 0usize;
+    paging::check::check(root, &glob.system_info.memory_map, |virt, violation| {
+        count += 1;
+        let _ = found.try_push((virt, violation));
+    });
+
+    let mut term = TERMINAL.lock();
+    for (virt, violation) in found.iter() {
+        let _ = writeln!(term, "{:#010x}: {violation}", virt);
+    }
+    if count > found.len() {
+        let _ = writeln!(term, "... and {} more", count - found.len());
+    }
+    let _ = writeln!(term, "{count} violation(s) found");
+    drop(term);
+
+    if count != 0 {
+        shell.fail();
+    }
+}
+
+/// The `protections` command.
+pub fn protections(_shell: &mut Shell, _args: &[u8]) {
+    let mut term = TERMINAL.lock();
+
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::left("SECTION", 8),
+            Column::left("START", 10),
+            Column::left("END", 10),
+            Column::right("SIZE", 11),
+            Column::left("ACCESS", 8),
+        ],
     );
+
+    let _ = table.header();
+    for section in kernel_image::sections() {
+        // The permissions are read back from the page tables rather than from the section
+        // itself, in case the mapping does not match what was requested.
+        let mask = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let mut pages = section
+            .range()
+            .step_by(0x1000)
+            .map(|page| paging::current_flags(page).map(|flags| flags & mask));
+        let first = pages.next().flatten();
+
+        let access = match first {
+            _ if pages.any(|flags| flags.map(|f| f.bits()) != first.map(|f| f.bits())) => "mixed",
+            None => "unmapped",
+            Some(flags) => match (
+                flags.contains(PageTableFlags::WRITABLE),
+                flags.contains(PageTableFlags::NO_EXECUTE),
+            ) {
+                (false, false) => "r-x",
+                (false, true) => "r--",
+                (true, false) => "rwx",
+                (true, true) => "rw-",
+            },
+        };
+
+        let _ = table.row([
+            &section.name,
+            &Address(section.start as u32),
+            &Address(section.end as u32),
+            &HumanBytes((section.end - section.start) as u64),
+            &access,
+        ]);
+    }
+}
+
+/// The `regs` command.
+pub fn regs(_shell: &mut Shell, _args: &[u8]) {
+    /// Prints the value of a register, followed by the names of the flags that are set.
+    fn print_flags<F>(term: &mut Terminal, name: &str, flags: F)
+    where
+        F: bitflags::Flags<Bits = u32>,
+    {
+        let _ = write!(term, "{name:<8}{:#010x} ", flags.bits());
+        for (flag, _) in flags.iter_names() {
+            let _ = write!(term, " {flag}");
+        }
+        let _ = writeln!(term);
+    }
+
+    let mut term = TERMINAL.lock();
+
+    print_flags(&mut term, "CR0", Cr0::read());
+    let _ = writeln!(term, "{:<8}{}", "CR2", Address(read_cr2()));
+    let _ = writeln!(term, "{:<8}{}", "CR3", Address(read_cr3()));
+    print_flags(&mut term, "CR4", Cr4::read());
+    print_flags(&mut term, "EFLAGS", EFlags::read());
+
+    let _ = writeln!(term);
+    for msr in Msr::ALL {
+        match msr.read() {
+            Some(value) => writeln!(term, "{:<18}{value:#018x}", msr.name()),
+            None => writeln!(term, "{:<18}not supported", msr.name()),
+        }
+        .ok();
+    }
+}
+
+/// The `beep` command.
+pub fn beep(shell: &mut Shell, args: &[u8]) {
+    let mut args = args.split(|&c| c == b' ').filter(|a| !a.is_empty());
+
+    let freq = match args.next().map(parse_u32) {
+        None => 880,
+        Some(Some(freq)) if (20..=20_000).contains(&freq) => freq,
+        Some(_) => {
+            printk!("beep: the frequency must be between 20 and 20000 Hz\n");
+            shell.fail();
+            return;
+        }
+    };
+    let duration = match args.next().map(parse_u32) {
+        None => 200,
+        Some(Some(duration)) if duration <= 10_000 => duration,
+        Some(_) => {
+            printk!("beep: the duration must be at most 10000 ms\n");
+            shell.fail();
+            return;
+        }
+    };
+
+    speaker::beep(freq, duration);
+}
+
+/// Parses a decimal integer.
+pub fn parse_u32(s: &[u8]) -> Option<u32> {
+    if s.is_empty() {
+        return None;
+    }
+
+    s.iter().try_fold(0u32, |acc, &c| {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        acc.checked_mul(10)?.checked_add((c - b'0') as u32)
+    })
 }
 
 /// The `panic` command.
-pub fn panic() {
+pub fn panic(_shell: &mut Shell, _args: &[u8]) {
     panic!("why would they add this command in the first place???");
 }
 
 /// The `restart` command.
-pub fn restart() {
-    reset_cpu();
+pub fn restart(shell: &mut Shell, args: &[u8]) {
+    if args.is_empty() {
+        power::reboot();
+    }
+
+    match power::parse_reboot_order(args) {
+        Some((methods, count)) if count > 0 => {
+            power::set_reboot_order(&methods[..count]);
+            power::reboot();
+        }
+        _ => {
+            printk!("usage: {}\navailable methods:", usage(b"restart"));
+            for method in RebootMethod::ALL {
+                printk!(" {}", method.name());
+            }
+            printk!("\n");
+            shell.fail();
+        }
+    }
+}
+
+/// The `halt` command.
+pub fn halt(_shell: &mut Shell, _args: &[u8]) {
+    power::halt();
 }
 
 /// The `syscall` command.
-pub fn syscall() {
-    printk!("Sending syscall 0x1 with arguments 0x2, 0x3, 0x4\n");
+pub fn syscall(_shell: &mut Shell, _args: &[u8]) {
+    printk!("Sending syscall 0x0 with arguments 0x2, 0x3, 0x4\n");
 
+    // No system call uses the number 0, which ends up in the debug handler.
     let ret: u32;
     unsafe {
         asm!(
             "int 0x80",
-            inlateout("eax") 0x1 => ret,
+            inlateout("eax") 0x0 => ret,
             in("ebx") 0x2,
             in("ecx") 0x3,
             in("edx") 0x4,
@@ -145,3 +1653,557 @@ pub fn syscall() {
 
     printk!("syscall returned: {:#x}\n", ret);
 }
+
+/// The `strace` command.
+pub fn strace(shell: &mut Shell, args: &[u8]) {
+    let (output, args) = match split_command(args) {
+        (b"-s", rest) => (StraceOutput::Serial, rest),
+        _ => (StraceOutput::Terminal, args),
+    };
+    let (name, rest) = split_command(args);
+    if name.is_empty() {
+        printk!("usage: {}\n", usage(b"strace"));
+        shell.fail();
+        return;
+    }
+    let Some(command) = find_command(name) else {
+        printk!(
+            "strace: unknown command `{}`\n",
+            core::str::from_utf8(name).unwrap_or("?")
+        );
+        shell.fail();
+        return;
+    };
+
+    // The shell runs as the current process, which is traced for as long as the command runs.
+    let processes = &GLOBAL.get().unwrap().processes;
+    processes.lock().current_mut().strace = Some(output);
+    (command.handler)(shell, rest);
+    processes.lock().current_mut().strace = None;
+}
+
+/// Parses a nice value, or an adjustment of one, which may be signed.
+///
+/// Values out of range are clamped, like on Linux.
+fn parse_nice(s: &[u8]) -> Option<i8> {
+    let (negative, digits) = match s {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        _ => (false, s),
+    };
+    let value = parse_u32(digits)?.min(i8::MAX as u32) as i8;
+    Some(if negative { -value } else { value })
+}
+
+/// The `nice` command.
+pub fn nice(shell: &mut Shell, args: &[u8]) {
+    let old = sched::nice();
+    let (adjustment, args) = match split_command(args) {
+        (b"-n", rest) => {
+            let (value, rest) = split_command(rest);
+            (parse_nice(value), rest)
+        }
+        _ => (Some(10), args),
+    };
+    let Some(adjustment) = adjustment else {
+        printk!("usage: {}\n", usage(b"nice"));
+        shell.fail();
+        return;
+    };
+
+    let (name, rest) = split_command(args);
+    if name.is_empty() {
+        printk!("{old}\n");
+        return;
+    }
+    let Some(command) = find_command(name) else {
+        printk!(
+            "nice: unknown command `{}`\n",
+            core::str::from_utf8(name).unwrap_or("?")
+        );
+        shell.fail();
+        return;
+    };
+
+    let new = old.saturating_add(adjustment);
+    if new < old && !shell.is_super_user() {
+        printk!("nice: permission denied\n");
+        shell.fail();
+        return;
+    }
+
+    // The command runs in the thread of the shell, whose nice value is restored afterwards.
+    sched::set_nice(new);
+    (command.handler)(shell, rest);
+    sched::set_nice(old);
+}
+
+/// The `renice` command.
+pub fn renice(shell: &mut Shell, args: &[u8]) {
+    let (value, pid) = split_command(args);
+    let (Some(new), Some(pid)) = (parse_nice(value), parse_u32(pid)) else {
+        printk!("usage: {}\n", usage(b"renice"));
+        shell.fail();
+        return;
+    };
+
+    let Some(old) = sched::process_nice(pid as ProcessId) else {
+        printk!("renice: {pid}: no such process\n");
+        shell.fail();
+        return;
+    };
+    let new = new.clamp(sched::MIN_NICE, sched::MAX_NICE);
+    if new < old && !shell.is_super_user() {
+        printk!("renice: {pid}: permission denied\n");
+        shell.fail();
+        return;
+    }
+
+    sched::set_process_nice(pid as ProcessId, new);
+    printk!("{pid}: old nice value {old}, new nice value {new}\n");
+}
+
+/// The `ulimit` command.
+pub fn ulimit(shell: &mut Shell, args: &[u8]) {
+    let (first, rest) = split_command(args);
+    let (user, args) = match parse_u32(first) {
+        Some(uid) => (uid as UserId, rest),
+        None => (shell.user, args),
+    };
+
+    let (name, value) = split_command(args);
+    if name.is_empty() {
+        let limits = state::limits(user);
+        let processes = GLOBAL.get().unwrap().processes.lock();
+        let mut term = TERMINAL.lock();
+        let mut table = Table::new(
+            &mut *term,
+            [
+                Column::left("RESOURCE", 9),
+                Column::right("USED", 11),
+                Column::right("LIMIT", 11),
+            ],
+        );
+        let _ = table.header();
+        for resource in Resource::ALL {
+            let used = Amount(resource, state::usage(&processes, user, resource));
+            let limit = limits.get(resource).map(|limit| Amount(resource, limit));
+            let _ = table.row([
+                &resource.name(),
+                &used,
+                limit.as_ref().map_or(&"unlimited" as &dyn Display, |l| l),
+            ]);
+        }
+        return;
+    }
+
+    let limit = match value {
+        b"unlimited" => Some(None),
+        _ => parse_u32(value).map(Some),
+    };
+    let (Some(resource), Some(limit)) = (Resource::from_name(name), limit) else {
+        printk!("usage: {}\n", usage(b"ulimit"));
+        shell.fail();
+        return;
+    };
+    let limit = match resource {
+        Resource::Processes => limit,
+        Resource::Memory => limit.map(|kib| kib / 4),
+    };
+
+    let old = state::limits(user).get(resource);
+    let raised = match (old, limit) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(old), Some(new)) => new > old,
+    };
+    if (raised || user != shell.user) && !shell.is_super_user() {
+        printk!("ulimit: permission denied\n");
+        shell.fail();
+        return;
+    }
+
+    if !state::set_limit(user, resource, limit) {
+        printk!("ulimit: too many users have limits\n");
+        shell.fail();
+    }
+}
+
+/// The `cursor` command.
+pub fn cursor(shell: &mut Shell, args: &[u8]) {
+    let mut term = TERMINAL.lock();
+
+    match args {
+        b"" => {
+            let style = term.cursor_style().name();
+            let blinking = if term.cursor_blinking() {
+                "blinking"
+            } else {
+                "steady"
+            };
+            let _ = writeln!(term, "cursor: {style} ({blinking})");
+        }
+        b"block" => term.set_cursor_style(CursorStyle::Block),
+        b"underline" => term.set_cursor_style(CursorStyle::Underline),
+        b"blink" => term.set_cursor_blinking(true),
+        b"steady" => term.set_cursor_blinking(false),
+        _ => {
+            let _ = writeln!(term, "usage: {}", usage(b"cursor"));
+            shell.fail();
+        }
+    }
+}
+
+/// The `stty` command.
+pub fn stty(shell: &mut Shell, args: &[u8]) {
+    let mut term = TERMINAL.lock();
+    let mut modes = term.tty().modes();
+
+    match args {
+        b"" => {
+            let flag = |mode| if modes.contains(mode) { "" } else { "-" };
+            let _ = writeln!(
+                term,
+                "{}icanon {}echo",
+                flag(TtyModes::CANONICAL),
+                flag(TtyModes::ECHO),
+            );
+            return;
+        }
+        b"echo" => modes.insert(TtyModes::ECHO),
+        b"-echo" => modes.remove(TtyModes::ECHO),
+        _ => {
+            let _ = writeln!(term, "usage: {}", usage(b"stty"));
+            shell.fail();
+            return;
+        }
+    }
+
+    term.tty_mut().set_modes(modes);
+    term.refresh_cmdline();
+}
+
+/// The `bind` command.
+pub fn bind(shell: &mut Shell, args: &[u8]) {
+    let mut term = TERMINAL.lock();
+    let (chord, action) = split_command(args);
+
+    if chord.is_empty() {
+        let bindings = term.keymap().iter().collect::<ArrayVec<_, MAX_BINDINGS>>();
+        let mut table = Table::new(
+            &mut *term,
+            [Column::left("CHORD", 24), Column::left("ACTION", 12)],
+        );
+        let _ = table.header();
+        for binding in bindings.iter() {
+            let _ = table.row([&binding.chord, &binding.action.name()]);
+        }
+        return;
+    }
+
+    let Some(chord) = Chord::parse(chord) else {
+        let _ = writeln!(
+            term,
+            "bind: unknown key chord; expected e.g. `ctrl+alt+del`"
+        );
+        shell.fail();
+        return;
+    };
+
+    match trim_end(action) {
+        b"" => {
+            let _ = match term.keymap().lookup(chord) {
+                Some(action) => writeln!(term, "{chord}: {}", action.name()),
+                None => writeln!(term, "{chord} is not bound"),
+            };
+        }
+        b"none" => {
+            if !term.keymap_mut().unbind(chord) {
+                let _ = writeln!(term, "{chord} is not bound");
+                shell.fail();
+            }
+        }
+        name => match Action::find(name) {
+            Some(action) => {
+                if !term.keymap_mut().bind(chord, action) {
+                    let _ = writeln!(term, "bind: too many key bindings");
+                    shell.fail();
+                }
+            }
+            None => {
+                let _ = write!(term, "bind: unknown action; available actions:");
+                for action in Action::ALL {
+                    let _ = write!(term, " {}", action.name());
+                }
+                let _ = writeln!(term, " none");
+                shell.fail();
+            }
+        },
+    }
+}
+
+/// The `theme` command.
+pub fn theme(shell: &mut Shell, args: &[u8]) {
+    let mut term = TERMINAL.lock();
+
+    if args.is_empty() {
+        let current = term.theme().name;
+        let _ = writeln!(term, "current theme: {current}");
+        let _ = write!(term, "available themes:");
+        for theme in Theme::ALL {
+            let _ = write!(term, " {}", theme.name);
+        }
+        term.insert_linefeed();
+        return;
+    }
+
+    match Theme::find(args) {
+        Some(theme) => term.set_theme(theme),
+        None => {
+            let _ = writeln!(term, "unknown theme; type `theme` to list available themes");
+            shell.fail();
+        }
+    }
+}
+
+/// The `prompt` command.
+pub fn prompt(shell: &mut Shell, args: &[u8]) {
+    if args.is_empty() {
+        let format = core::str::from_utf8(&shell.prompt_format).unwrap_or("<invalid utf-8>");
+        printk!("prompt: {format:?}\n");
+        return;
+    }
+
+    shell.set_prompt_format(args);
+}
+
+/// The `alias` command.
+pub fn alias(shell: &mut Shell, args: &[u8]) {
+    let args = trim_end(args);
+    let mut term = TERMINAL.lock();
+
+    if args.is_empty() {
+        for alias in shell.aliases.iter() {
+            let name = core::str::from_utf8(&alias.key).unwrap_or("<invalid utf-8>");
+            let value = core::str::from_utf8(&alias.value).unwrap_or("<invalid utf-8>");
+            let _ = writeln!(term, "alias {name}={value}");
+        }
+        return;
+    }
+
+    let Some(i) = args.iter().position(|&c| c == b'=') else {
+        let name = core::str::from_utf8(args).unwrap_or("<invalid utf-8>");
+        match shell.aliases.get(args) {
+            Some(value) => {
+                let value = core::str::from_utf8(value).unwrap_or("<invalid utf-8>");
+                let _ = writeln!(term, "alias {name}={value}");
+            }
+            None => {
+                let _ = writeln!(term, "alias: {name}: not found");
+                shell.fail();
+            }
+        }
+        return;
+    };
+
+    let value = trim_end(trim_start(&args[i + 1..]));
+    if value.is_empty() {
+        let _ = writeln!(term, "usage: {}", usage(b"alias"));
+        shell.fail();
+        return;
+    }
+    if let Err(err) = shell.aliases.define(&args[..i], value) {
+        let _ = writeln!(term, "alias: {err}");
+        shell.fail();
+    }
+}
+
+/// The `unalias` command.
+pub fn unalias(shell: &mut Shell, args: &[u8]) {
+    let args = trim_end(args);
+    if args.is_empty() {
+        printk!("usage: {}\n", usage(b"unalias"));
+        shell.fail();
+    } else if !shell.aliases.remove(args) {
+        let name = core::str::from_utf8(args).unwrap_or("<invalid utf-8>");
+        printk!("unalias: {name}: not found\n");
+        shell.fail();
+    }
+}
+
+/// The `jobs` command.
+pub fn jobs(_shell: &mut Shell, _args: &[u8]) {
+    let jobs = jobs::list();
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::right("JOB", 3),
+            Column::right("TID", 3),
+            Column::left("STATE", 7),
+            Column::left("COMMAND", 40),
+        ],
+    );
+
+    let _ = table.header();
+    for job in jobs.iter() {
+        let _ = table.row([
+            &job.number,
+            &job.thread,
+            &job.state.name(),
+            &core::str::from_utf8(&job.cmdline).unwrap_or("<invalid utf-8>"),
+        ]);
+    }
+}
+
+/// The `fg` command.
+pub fn fg(shell: &mut Shell, args: &[u8]) {
+    let args = trim_end(args);
+    let args = args.strip_prefix(b"%").unwrap_or(args);
+    let number = match args {
+        [] => None,
+        _ => match parse_u32(args) {
+            Some(number) => Some(number as usize),
+            None => {
+                printk!("usage: {}\n", usage(b"fg"));
+                shell.fail();
+                return;
+            }
+        },
+    };
+
+    match jobs::wait(number) {
+        Ok(job) => {
+            if job.state == jobs::JobState::Failed {
+                shell.fail();
+            }
+        }
+        Err(err) => {
+            printk!("fg: {err}\n");
+            shell.fail();
+        }
+    }
+}
+
+/// The `set` command.
+pub fn set(shell: &mut Shell, args: &[u8]) {
+    let args = trim_end(args);
+    if args.is_empty() {
+        return env(shell, args);
+    }
+
+    let Some(i) = args.iter().position(|&c| c == b'=') else {
+        printk!("usage: {}\n", usage(b"set"));
+        shell.fail();
+        return;
+    };
+
+    if let Err(err) = shell.env.set(&args[..i], trim_start(&args[i + 1..])) {
+        printk!("set: {err}\n");
+        shell.fail();
+    }
+}
+
+/// The `unset` command.
+pub fn unset(shell: &mut Shell, args: &[u8]) {
+    let args = trim_end(args);
+    if args.is_empty() {
+        printk!("usage: {}\n", usage(b"unset"));
+        shell.fail();
+    } else if !shell.env.remove(args) {
+        let name = core::str::from_utf8(args).unwrap_or("<invalid utf-8>");
+        printk!("unset: {name}: not set\n");
+        shell.fail();
+    }
+}
+
+/// The `env` command.
+pub fn env(shell: &mut Shell, _args: &[u8]) {
+    let mut term = TERMINAL.lock();
+    for var in shell.env.iter() {
+        let name = core::str::from_utf8(&var.key).unwrap_or("<invalid utf-8>");
+        let value = core::str::from_utf8(&var.value).unwrap_or("<invalid utf-8>");
+        let _ = writeln!(term, "{name}={value}");
+    }
+}
+
+/// The `echo` command.
+pub fn echo(_shell: &mut Shell, args: &[u8]) {
+    let text = core::str::from_utf8(args).unwrap_or("<invalid utf-8>");
+    printk!("{text}\n");
+}
+
+/// The `cat` command.
+pub fn cat(shell: &mut Shell, args: &[u8]) {
+    let args = trim_end(args);
+    if args.is_empty() {
+        printk!("usage: {}\n", usage(b"cat"));
+        shell.fail();
+        return;
+    }
+
+    let node = match fs::resolve(&shell.cwd, args) {
+        Ok((_, node)) => node,
+        Err(err) => {
+            printk!("cat: {err}\n");
+            shell.fail();
+            return;
+        }
+    };
+
+    // Devices have no end: only what they have to offer right away is printed.
+    let is_device = node
+        .metadata()
+        .is_ok_and(|m| m.kind == fs::NodeKind::Device);
+
+    let mut buf = [0u8; 256];
+    let mut offset = 0;
+    loop {
+        let count = match node.read(offset, &mut buf) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(err) => {
+                printk!("cat: {err}\n");
+                shell.fail();
+                return;
+            }
+        };
+        offset += count;
+
+        TERMINAL.lock().write_bytes(&buf[..count]);
+
+        if is_device {
+            break;
+        }
+    }
+}
+
+/// The `cd` command.
+pub fn cd(shell: &mut Shell, args: &[u8]) {
+    let target = if args.is_empty() { b"/" as &[u8] } else { args };
+
+    match fs::resolve(&shell.cwd, target) {
+        Ok((path, node)) if node.is_dir() => shell.cwd = path,
+        Ok(_) => {
+            printk!("cd: {}\n", fs::FsError::NotADirectory);
+            shell.fail();
+        }
+        Err(err) => {
+            printk!("cd: {err}\n");
+            shell.fail();
+        }
+    }
+}
+
+/// The `pwd` command.
+pub fn pwd(shell: &mut Shell, _args: &[u8]) {
+    let cwd = core::str::from_utf8(&shell.cwd).unwrap_or("<invalid utf-8>");
+    printk!("{cwd}\n");
+}
+
+/// The `screenshot` command.
+pub fn screenshot(_shell: &mut Shell, _args: &[u8]) {
+    if TERMINAL.lock().screenshot(&mut serial::Serial).is_ok() {
+        printk!("the screen was dumped to the serial port\n");
+    }
+}
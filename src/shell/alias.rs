@@ -0,0 +1,71 @@
+//! The aliases defined with the `alias` command.
+
+use crate::utility::{KeyValue, KeyValueError, KeyValueTable};
+
+/// The maximum number of aliases.
+pub const MAX_ALIASES: usize = 16;
+
+/// The maximum length of the name of an alias.
+pub const MAX_NAME_LEN: usize = 16;
+
+/// The maximum length of the command-line an alias expands to.
+pub const MAX_VALUE_LEN: usize = 64;
+
+/// An error that might occur while defining an alias.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasError {
+    /// The name is empty, too long, or contains a space.
+    InvalidName,
+    /// The expanded command-line is too long.
+    ValueTooLong,
+    /// No more aliases can be defined.
+    TooManyAliases,
+}
+
+impl core::fmt::Display for AliasError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::InvalidName => "invalid alias name",
+            Self::ValueTooLong => "the command-line is too long",
+            Self::TooManyAliases => "too many aliases",
+        })
+    }
+}
+
+/// The table of the aliases of a shell: their names, and the command-lines they expand to.
+#[derive(Default)]
+pub struct Aliases(KeyValueTable<MAX_NAME_LEN, MAX_VALUE_LEN, MAX_ALIASES>);
+
+impl Aliases {
+    /// Returns the command-line the alias named `name` expands to, if it is defined.
+    #[inline]
+    pub fn get(&self, name: &[u8]) -> Option<&[u8]> {
+        self.0.get(name)
+    }
+
+    /// Defines an alias, replacing the previous definition of the same name.
+    pub fn define(&mut self, name: &[u8], value: &[u8]) -> Result<(), AliasError> {
+        if name.is_empty() || name.contains(&b' ') {
+            return Err(AliasError::InvalidName);
+        }
+        self.0.insert(name, value).map_err(|err| match err {
+            KeyValueError::KeyTooLong => AliasError::InvalidName,
+            KeyValueError::ValueTooLong => AliasError::ValueTooLong,
+            KeyValueError::Full => AliasError::TooManyAliases,
+        })
+    }
+
+    /// Removes the alias named `name`.
+    ///
+    /// Returns whether it was defined.
+    #[inline]
+    pub fn remove(&mut self, name: &[u8]) -> bool {
+        self.0.remove(name)
+    }
+
+    /// Returns an iterator over the aliases, as `(name, command-line)` entries.
+    #[inline]
+    pub fn iter(&self) -> impl '_ + Iterator<Item = &KeyValue<MAX_NAME_LEN, MAX_VALUE_LEN>> {
+        self.0.iter()
+    }
+}
@@ -0,0 +1,260 @@
+//! The commands that run in the background, started with a trailing `&`.
+//!
+//! Every job runs on a kernel thread of its own, with a shell that runs as the same user and
+//! in the same working directory as the shell that started it. The output of the job goes to
+//! the terminal, along with the rest.
+
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::drivers::vga;
+use crate::fs::path;
+use crate::printk;
+use crate::sched::{self, SpawnError, ThreadId};
+use crate::state::{UserId, WaitQueue, INIT};
+use crate::time;
+use crate::utility::{ArrayVec, Mutex, RestoreInterrupts};
+
+use super::{Command, Shell};
+
+/// The maximum number of jobs that can exist at once.
+pub const MAX_JOBS: usize = 8;
+
+/// How often a thread waiting for a job checks whether its thread is still alive, in
+/// nanoseconds.
+const POLL_INTERVAL_NS: u64 = 100 * time::NANOS_PER_SECOND / 1000;
+
+/// The jobs, indexed by their number minus one.
+static JOBS: Mutex<[Option<Slot>; MAX_JOBS]> = Mutex::new({
+    const NONE: Option<Slot> = None;
+    [NONE; MAX_JOBS]
+});
+
+/// The number of jobs started so far, used to find the most recent one.
+static STARTED: AtomicU32 = AtomicU32::new(0);
+
+/// The threads waiting for a job to finish.
+static FINISHED: WaitQueue = WaitQueue::new();
+
+/// The state of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    /// The command is still running.
+    Running,
+    /// The command completed successfully.
+    Done,
+    /// The command reported an error, or its thread panicked.
+    Failed,
+}
+
+impl JobState {
+    /// Returns the name of the state, as displayed by `jobs`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// A command running in the background.
+#[derive(Clone)]
+pub struct Job {
+    /// The number of the job, starting at 1.
+    pub number: usize,
+    /// The command-line of the job, without the trailing `&`.
+    pub cmdline: ArrayVec<u8, { vga::WIDTH as usize }>,
+    /// The thread that runs the job.
+    pub thread: ThreadId,
+    /// The state of the job.
+    pub state: JobState,
+}
+
+/// A slot of [`JOBS`].
+struct Slot {
+    /// The job.
+    job: Job,
+    /// The order in which the job was started.
+    order: u32,
+    /// What the thread of the job needs to run it, until it takes it.
+    launch: Option<Launch>,
+}
+
+/// What the thread of a job needs to run its command.
+struct Launch {
+    /// The command to run.
+    command: &'static Command,
+    /// The arguments passed to the command.
+    args: ArrayVec<u8, { vga::WIDTH as usize }>,
+    /// The user the command runs as.
+    user: UserId,
+    /// The working directory of the command.
+    cwd: path::PathBuf,
+}
+
+/// An error that might occur while starting a job or waiting for one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobError {
+    /// Too many jobs exist already.
+    TooManyJobs,
+    /// The thread of the job could not be created.
+    Spawn(SpawnError),
+    /// No job has the requested number.
+    NoSuchJob,
+}
+
+impl core::fmt::Display for JobError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::TooManyJobs => "too many jobs",
+            Self::Spawn(SpawnError::TooManyThreads) => "too many threads",
+            Self::Spawn(SpawnError::OutOfMemory) => "out of memory",
+            Self::NoSuchJob => "no such job",
+        })
+    }
+}
+
+/// Starts running `command` with `args` in the background, on behalf of `shell`.
+///
+/// The slots of the jobs that finished are reused. Returns the started job.
+pub fn start(shell: &Shell, command: &'static Command, args: &[u8]) -> Result<Job, JobError> {
+    let mut cmdline = ArrayVec::<u8, { vga::WIDTH as usize }>::new();
+    for &c in command.name.iter().chain(b" ").chain(args) {
+        let _ = cmdline.try_push(c);
+    }
+    let cmdline = ArrayVec::from_slice_truncated(super::trim_end(&cmdline));
+
+    let mut jobs = JOBS.lock();
+    let index = jobs
+        .iter()
+        .position(|slot| {
+            slot.as_ref()
+                .map_or(true, |s| s.job.state != JobState::Running)
+        })
+        .ok_or(JobError::TooManyJobs)?;
+    let thread = sched::spawn_kernel(INIT, "job", run, index).map_err(JobError::Spawn)?;
+
+    let job = Job {
+        number: index + 1,
+        cmdline,
+        thread,
+        state: JobState::Running,
+    };
+    jobs[index] = Some(Slot {
+        job: job.clone(),
+        order: STARTED.fetch_add(1, Relaxed),
+        launch: Some(Launch {
+            command,
+            args: ArrayVec::from_slice_truncated(args),
+            user: shell.user,
+            cwd: shell.cwd.clone(),
+        }),
+    });
+    Ok(job)
+}
+
+/// The entry point of the thread of the job at `index` in [`JOBS`].
+extern "C" fn run(index: usize) {
+    let Some(launch) = JOBS.lock()[index].as_mut().and_then(|s| s.launch.take()) else {
+        return;
+    };
+
+    let mut shell = Shell {
+        user: launch.user,
+        cwd: launch.cwd,
+        console: false,
+        ..Shell::default()
+    };
+    (launch.command.handler)(&mut shell, &launch.args);
+
+    let state = if shell.failed {
+        JobState::Failed
+    } else {
+        JobState::Done
+    };
+    let mut jobs = JOBS.lock();
+    if let Some(slot) = &mut jobs[index] {
+        slot.job.state = state;
+        let cmdline = core::str::from_utf8(&slot.job.cmdline).unwrap_or("<invalid utf-8>");
+        printk!("[{}] {}  {cmdline}\n", slot.job.number, state.name());
+    }
+    drop(jobs);
+    FINISHED.wake_all();
+}
+
+/// Marks the running jobs whose thread no longer exists as failed.
+///
+/// This happens when the thread of a job panics.
+fn reap_dead(jobs: &mut [Option<Slot>]) {
+    let mut alive = ArrayVec::<ThreadId, MAX_JOBS>::new();
+    sched::for_each_thread(|thread| {
+        if jobs.iter().flatten().any(|s| s.job.thread == thread.id) {
+            let _ = alive.try_push(thread.id);
+        }
+    });
+
+    for slot in jobs.iter_mut().flatten() {
+        if slot.job.state == JobState::Running && !alive.contains(&slot.job.thread) {
+            slot.job.state = JobState::Failed;
+        }
+    }
+}
+
+/// Returns the jobs, in increasing order of number, and forgets the ones that finished.
+pub fn list() -> ArrayVec<Job, MAX_JOBS> {
+    let mut jobs = JOBS.lock();
+    reap_dead(&mut jobs[..]);
+
+    let mut ret = ArrayVec::new();
+    for slot in jobs.iter_mut() {
+        let Some(job) = slot.as_ref().map(|s| s.job.clone()) else {
+            continue;
+        };
+        if job.state != JobState::Running {
+            *slot = None;
+        }
+        ret.push(job);
+    }
+    ret
+}
+
+/// Waits until the job numbered `number` finishes, or the most recent job if `number` is
+/// `None`, then forgets it.
+///
+/// Returns the job, in its final state.
+pub fn wait(number: Option<usize>) -> Result<Job, JobError> {
+    let index = {
+        let jobs = JOBS.lock();
+        match number {
+            Some(number) => number
+                .checked_sub(1)
+                .filter(|&i| jobs.get(i).is_some_and(Option::is_some))
+                .ok_or(JobError::NoSuchJob)?,
+            None => {
+                jobs.iter()
+                    .enumerate()
+                    .filter_map(|(i, slot)| Some((i, slot.as_ref()?.order)))
+                    .max_by_key(|&(_, order)| order)
+                    .ok_or(JobError::NoSuchJob)?
+                    .0
+            }
+        }
+    };
+
+    loop {
+        // A job that finishes between the check and the moment the thread blocks would only
+        // be noticed at the next poll if interrupts were enabled.
+        let restore = RestoreInterrupts::without_interrupts();
+        let mut jobs = JOBS.lock();
+        reap_dead(&mut jobs[..]);
+        let slot = jobs[index].as_ref().ok_or(JobError::NoSuchJob)?;
+        if slot.job.state != JobState::Running {
+            return Ok(jobs[index].take().unwrap().job);
+        }
+        drop(jobs);
+
+        FINISHED.wait(Some(time::monotonic_ns() + POLL_INTERVAL_NS));
+        drop(restore);
+    }
+}
@@ -0,0 +1,211 @@
+//! Named shared-memory objects.
+//!
+//! An object is a set of physical frames that processes map in their address space with
+//! `mmap`, passing the ID of the object in place of a file descriptor. Every process that maps
+//! the same object sees the same memory. An object lives until its name is unlinked and the
+//! last page that maps it is unmapped.
+
+use crate::cpu::paging::MappingError;
+use crate::fs::FsError;
+use crate::shell::{Command, Shell};
+use crate::state::{FrameOwner, GLOBAL};
+use crate::utility::{ArrayVec, Column, HumanBytes, Mutex, Table};
+use crate::{oom, TERMINAL};
+
+/// The maximum number of shared-memory objects that can exist at once.
+pub const MAX_OBJECTS: usize = 16;
+
+/// The maximum size of a shared-memory object, in pages.
+pub const MAX_PAGES: usize = 128;
+
+/// The maximum length of the name of a shared-memory object.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// The ID of a shared-memory object.
+///
+/// This is the index of the object in the list of objects. The ID of an object that was
+/// destroyed may be reused by another one.
+pub type ObjectId = u32;
+
+/// A shared-memory object.
+struct Object {
+    /// The name of the object.
+    name: ArrayVec<u8, MAX_NAME_LEN>,
+    /// The frames that hold the content of the object.
+    frames: ArrayVec<u32, MAX_PAGES>,
+    /// The number of pages that map the object.
+    mappings: u32,
+    /// Whether the object can still be opened by its name.
+    linked: bool,
+}
+
+/// The shared-memory objects.
+static OBJECTS: Mutex<[Option<Object>; MAX_OBJECTS]> = {
+    const NONE: Option<Object> = None;
+    Mutex::new([NONE; MAX_OBJECTS])
+};
+
+/// Returns the ID of the object named `name`, if it exists.
+fn find(objects: &[Option<Object>], name: &[u8]) -> Option<ObjectId> {
+    objects
+        .iter()
+        .position(|o| o.as_ref().is_some_and(|o| o.linked && *o.name == *name))
+        .map(|id| id as ObjectId)
+}
+
+/// Opens the object named `name`, and returns its ID.
+///
+/// When `size` is zero, the object must exist. Otherwise, a new object of `size` bytes, filled
+/// with zeros, is created.
+pub fn open(name: &[u8], size: usize) -> Result<ObjectId, FsError> {
+    if name.is_empty() || name.contains(&b'/') {
+        return Err(FsError::NotFound);
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err(FsError::NameTooLong);
+    }
+
+    let mut objects = OBJECTS.lock();
+    let existing = find(&*objects, name);
+    if size == 0 {
+        return existing.ok_or(FsError::NotFound);
+    } else if existing.is_some() {
+        return Err(FsError::AlreadyExists);
+    }
+
+    let pages = size.div_ceil(4096);
+    if pages > MAX_PAGES {
+        return Err(FsError::NoSpace);
+    }
+    let slot = objects
+        .iter()
+        .position(Option::is_none)
+        .ok_or(FsError::NoSpace)?;
+
+    let mut frames = ArrayVec::new();
+    let mut allocator = GLOBAL.get().unwrap().allocator.lock();
+    for _ in 0..pages {
+        let Ok(frame) = allocator.allocate_zeroed(FrameOwner::Shared) else {
+            frames.iter().for_each(|&frame| allocator.deallocate(frame));
+            drop(allocator);
+            oom::report("creating a shared-memory object");
+            return Err(FsError::NoSpace);
+        };
+        frames.push(frame);
+    }
+    drop(allocator);
+
+    objects[slot] = Some(Object {
+        name: ArrayVec::from_slice_truncated(name),
+        frames,
+        mappings: 0,
+        linked: true,
+    });
+    Ok(slot as ObjectId)
+}
+
+/// Removes the name of the object named `name`.
+///
+/// The object is destroyed once it is no longer mapped.
+pub fn unlink(name: &[u8]) -> Result<(), FsError> {
+    let mut objects = OBJECTS.lock();
+    let id = find(&*objects, name).ok_or(FsError::NotFound)?;
+    if let Some(object) = &mut objects[id as usize] {
+        object.linked = false;
+    }
+    destroy_if_unused(&mut objects[id as usize]);
+    Ok(())
+}
+
+/// Records that `pages` pages of the object `id`, starting at `offset`, are being mapped, and
+/// returns the frames that hold them.
+pub fn acquire(
+    id: ObjectId,
+    offset: usize,
+    pages: usize,
+) -> Result<ArrayVec<u32, MAX_PAGES>, MappingError> {
+    let mut objects = OBJECTS.lock();
+    let object = objects
+        .get_mut(id as usize)
+        .and_then(Option::as_mut)
+        .ok_or(MappingError::InvalidRange)?;
+
+    let first = offset / 4096;
+    let frames = first
+        .checked_add(pages)
+        .and_then(|end| object.frames.get(first..end))
+        .filter(|_| offset % 4096 == 0)
+        .ok_or(MappingError::InvalidRange)?;
+
+    object.mappings += pages as u32;
+    Ok(ArrayVec::from_slice_truncated(frames))
+}
+
+/// Records that `pages` pages of the object `id` were unmapped.
+pub fn release(id: ObjectId, pages: usize) {
+    let mut objects = OBJECTS.lock();
+    let Some(slot) = objects.get_mut(id as usize) else {
+        return;
+    };
+    if let Some(object) = slot {
+        object.mappings = object.mappings.saturating_sub(pages as u32);
+    }
+    destroy_if_unused(slot);
+}
+
+/// Destroys the object in `slot` if it is unlinked and no longer mapped.
+fn destroy_if_unused(slot: &mut Option<Object>) {
+    if slot.as_ref().is_some_and(|o| o.linked || o.mappings != 0) {
+        return;
+    }
+
+    if let Some(object) = slot.take() {
+        let mut allocator = GLOBAL.get().unwrap().allocator.lock();
+        object
+            .frames
+            .iter()
+            .for_each(|&frame| allocator.deallocate(frame));
+    }
+}
+
+/// The `shm` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"shm",
+    summary: "list the shared-memory objects",
+    usage: "shm",
+    details: "Lists the shared-memory objects, along with their size and the number of pages\n\
+              that map them. Objects whose name was unlinked are destroyed once they are no\n\
+              longer mapped.",
+    handler: shm,
+};
+
+/// The `shm` command.
+fn shm(_shell: &mut Shell, _args: &[u8]) {
+    let objects = OBJECTS.lock();
+    let mut term = TERMINAL.lock();
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::right("ID", 3),
+            Column::left("NAME", MAX_NAME_LEN),
+            Column::right("SIZE", 11),
+            Column::right("MAPPED", 6),
+            Column::left("LINKED", 6),
+        ],
+    );
+
+    let _ = table.header();
+    for (id, object) in objects.iter().enumerate() {
+        let Some(object) = object else {
+            continue;
+        };
+
+        let _ = table.row([
+            &id,
+            &core::str::from_utf8(&object.name).unwrap_or("?"),
+            &HumanBytes(object.frames.len() as u64 * 4096),
+            &object.mappings,
+            &if object.linked { "yes" } else { "no" },
+        ]);
+    }
+}
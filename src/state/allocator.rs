@@ -1,22 +1,66 @@
 use core::fmt::Display;
 use core::mem::MaybeUninit;
+use core::panic::Location;
+
+use crate::utility::{zero_pages, ArrayVec, Mutex};
+
+use super::{FrameOwner, FrameTags};
+
+/// The size of the frames located above 4 GiB.
+pub const HIGH_FRAME_SIZE: u64 = 4 * 1024 * 1024;
+
+/// The maximum number of regions of memory above 4 GiB tracked by the [`Allocator`].
+pub const MAX_HIGH_REGIONS: usize = 8;
+
+/// The maximum number of frames above 4 GiB that can be deallocated out of order.
+pub const MAX_FREED_HIGH_FRAMES: usize = 64;
+
+/// The maximum number of pages above 4 GiB that can be deallocated out of order.
+pub const MAX_FREED_HIGH_PAGES: usize = 1024;
+
+/// The maximum number of free pages that are kept zeroed in advance.
+pub const MAX_ZEROED_PAGES: usize = 64;
 
 /// A physical page allocator.
 ///
-/// This allocator operates on a page granularity.
+/// This allocator operates on a page granularity. Every page it hands out is tagged with its
+/// owner (see [`FrameTags`]).
+///
+/// A few of the free pages are zeroed in advance by the idle thread (see [`zero_free_page`]),
+/// so that [`allocate_zeroed`](Self::allocate_zeroed) usually does not have to.
 pub struct Allocator {
     /// The list of pages that are available for allocation.
     pages: &'static mut [MaybeUninit<u32>],
     /// The number of pages that are available.
     len: usize,
+    /// The free pages that were zeroed, which are not part of `pages`.
+    zeroed: ArrayVec<u32, MAX_ZEROED_PAGES>,
+    /// The owner of each page.
+    tags: FrameTags,
+    /// The regions of memory above 4 GiB, as `(next, end)` pairs of 4 MiB-aligned addresses.
+    ///
+    /// Frames are allocated from the start of the regions.
+    high_regions: ArrayVec<(u64, u64), MAX_HIGH_REGIONS>,
+    /// The frames above 4 GiB that were deallocated.
+    freed_high_frames: ArrayVec<u64, MAX_FREED_HIGH_FRAMES>,
+    /// The part of a frame above 4 GiB that is being split into pages, as a `(next, end)` pair.
+    high_pages: (u64, u64),
+    /// The pages above 4 GiB that were deallocated.
+    freed_high_pages: ArrayVec<u64, MAX_FREED_HIGH_PAGES>,
 }
 
 impl Allocator {
     /// Creates a new [`Allocator`] with the provided backing storage.
-    pub fn new(storage: &'static mut [MaybeUninit<u32>]) -> Self {
+    pub fn new(storage: &'static mut [MaybeUninit<u32>], tags: FrameTags) -> Self {
         Self {
             pages: storage,
             len: 0,
+            zeroed: ArrayVec::new(),
+            tags,
+            high_regions: ArrayVec::new(),
+            freed_high_frames: ArrayVec::new(),
+            high_pages: (0, 0),
+            freed_high_pages: ArrayVec::new(),
         }
     }
 
@@ -44,27 +88,219 @@ impl Allocator {
         unsafe {
             self.pages.get_unchecked_mut(self.len).write(page);
         }
+        self.tags.set(page, FrameOwner::Free, None);
 
         self.len += 1;
     }
 
-    /// Allocates a page and returns its physical address.
+    /// Allocates a page on behalf of `owner` and returns its physical address.
+    ///
+    /// The caller is recorded in the tag of the page.
     #[inline]
-    pub fn allocate(&mut self) -> Result<u32, OutOfMemory> {
+    #[track_caller]
+    pub fn allocate(&mut self, owner: FrameOwner) -> Result<u32, OutOfMemory> {
+        let page = match self.pop() {
+            Some(page) => page,
+            None => self.zeroed.pop().ok_or(OutOfMemory)?,
+        };
+        self.tags.set(page, owner, Some(Location::caller()));
+        Ok(page)
+    }
+
+    /// Allocates a page filled with zeros on behalf of `owner` and returns its physical
+    /// address.
+    ///
+    /// The page comes from the ones that were zeroed in advance when possible. Otherwise, it
+    /// is zeroed now through the identity map.
+    #[track_caller]
+    pub fn allocate_zeroed(&mut self, owner: FrameOwner) -> Result<u32, OutOfMemory> {
+        let page = match self.zeroed.pop() {
+            Some(page) => page,
+            None => {
+                let page = self.pop().ok_or(OutOfMemory)?;
+                unsafe { zero_pages(page as *mut u8, 0x1000) };
+                page
+            }
+        };
+        self.tags.set(page, owner, Some(Location::caller()));
+        Ok(page)
+    }
+
+    /// Removes the last page of the list of available pages.
+    #[inline]
+    fn pop(&mut self) -> Option<u32> {
         if self.len == 0 {
-            return Err(OutOfMemory);
+            return None;
         }
-
         self.len -= 1;
+        Some(unsafe { self.pages.get_unchecked(self.len).assume_init() })
+    }
 
-        Ok(unsafe { self.pages.get_unchecked(self.len).assume_init() })
+    /// Takes a free page that should be zeroed in advance, if fewer than
+    /// [`MAX_ZEROED_PAGES`] are.
+    ///
+    /// The page must be handed back with [`add_zeroed`](Self::add_zeroed) once it is zeroed.
+    fn take_for_zeroing(&mut self) -> Option<u32> {
+        if self.zeroed.is_full() {
+            return None;
+        }
+        self.pop()
+    }
+
+    /// Adds a page returned by [`take_for_zeroing`](Self::take_for_zeroing), now that it is
+    /// filled with zeros.
+    fn add_zeroed(&mut self, page: u32) {
+        if self.zeroed.try_push(page).is_err() {
+            self.deallocate(page);
+        }
+    }
+
+    /// Removes the free pages for which `keep` returns `false`.
+    ///
+    /// Removed pages are tagged as [`Bad`](FrameOwner::Bad), and never allocated again. The
+    /// pages that were zeroed in advance are tested too, and must be zeroed again.
+    pub fn retain(&mut self, mut keep: impl FnMut(u32) -> bool) {
+        while let Some(page) = self.zeroed.pop() {
+            self.deallocate(page);
+        }
+
+        let mut i = 0;
+        while i < self.len {
+            let page = unsafe { self.pages.get_unchecked(i).assume_init() };
+            if keep(page) {
+                i += 1;
+            } else {
+                self.len -= 1;
+                self.pages.swap(i, self.len);
+                self.tags.set(page, FrameOwner::Bad, None);
+            }
+        }
     }
 
     /// Returns the total amount of tracked memory, in bytes.
     #[inline]
     pub fn remaining_memory(&self) -> usize {
-        self.len * 0x1000
+        (self.len + self.zeroed.len()) * 0x1000
+    }
+
+    /// Returns the amount of free memory that was zeroed in advance, in bytes.
+    #[inline]
+    pub fn zeroed_memory(&self) -> usize {
+        self.zeroed.len() * 0x1000
+    }
+
+    /// Returns the owner of each page.
+    #[inline(always)]
+    pub fn tags(&self) -> &FrameTags {
+        &self.tags
+    }
+
+    /// Adds a region of memory above 4 GiB, from which 4 MiB frames can be allocated.
+    ///
+    /// The region is shrunk to the 4 MiB frames it contains. Returns the amount of memory that
+    /// was added, which is zero if too many regions were already added.
+    pub fn add_high_region(&mut self, start: u64, end: u64) -> u64 {
+        let start = start.next_multiple_of(HIGH_FRAME_SIZE);
+        let end = end & !(HIGH_FRAME_SIZE - 1);
+        if start >= end || self.high_regions.try_push((start, end)).is_err() {
+            return 0;
+        }
+        end - start
+    }
+
+    /// Allocates a 4 MiB frame above 4 GiB and returns its physical address.
+    ///
+    /// Such frames are not identity mapped. They must be mapped with
+    /// [`HighMapping`](crate::cpu::paging::pse36::HighMapping) before being accessed.
+    pub fn allocate_high(&mut self) -> Result<u64, OutOfMemory> {
+        if let Some(frame) = self.freed_high_frames.pop() {
+            return Ok(frame);
+        }
+
+        let (next, end) = self
+            .high_regions
+            .iter_mut()
+            .find(|(next, end)| next < end)
+            .ok_or(OutOfMemory)?;
+        let frame = *next;
+        *next += HIGH_FRAME_SIZE;
+        debug_assert!(*next <= *end);
+        Ok(frame)
+    }
+
+    /// Deallocates a 4 MiB frame returned by [`allocate_high`](Self::allocate_high).
+    ///
+    /// # Panics
+    ///
+    /// This function panics if too many frames were deallocated out of order.
+    pub fn deallocate_high(&mut self, frame: u64) {
+        if let Some((next, _)) = self
+            .high_regions
+            .iter_mut()
+            .find(|(next, _)| *next == frame + HIGH_FRAME_SIZE)
+        {
+            *next = frame;
+            return;
+        }
+
+        assert!(
+            self.freed_high_frames.try_push(frame).is_ok(),
+            "out of memory for the allocator"
+        );
+    }
+
+    /// Allocates a page above 4 GiB and returns its physical address.
+    ///
+    /// The pages are split from 4 MiB frames returned by [`allocate_high`](Self::allocate_high),
+    /// which are never given back. Like those, the page must be mapped before being accessed,
+    /// and it is not zeroed.
+    pub fn allocate_high_page(&mut self) -> Result<u64, OutOfMemory> {
+        if let Some(page) = self.freed_high_pages.pop() {
+            return Ok(page);
+        }
+
+        if self.high_pages.0 == self.high_pages.1 {
+            let frame = self.allocate_high()?;
+            self.high_pages = (frame, frame + HIGH_FRAME_SIZE);
+        }
+        let page = self.high_pages.0;
+        self.high_pages.0 += 0x1000;
+        Ok(page)
+    }
+
+    /// Deallocates a page returned by [`allocate_high_page`](Self::allocate_high_page).
+    ///
+    /// # Panics
+    ///
+    /// This function panics if too many pages were deallocated.
+    pub fn deallocate_high_page(&mut self, page: u64) {
+        assert!(
+            self.freed_high_pages.try_push(page).is_ok(),
+            "out of memory for the allocator"
+        );
     }
+
+    /// Returns the amount of memory above 4 GiB that can still be allocated, in bytes.
+    pub fn remaining_high_memory(&self) -> u64 {
+        let regions: u64 = self.high_regions.iter().map(|(next, end)| end - next).sum();
+        let pages =
+            (self.high_pages.1 - self.high_pages.0) + self.freed_high_pages.len() as u64 * 0x1000;
+        regions + self.freed_high_frames.len() as u64 * HIGH_FRAME_SIZE + pages
+    }
+}
+
+/// Zeroes one of the free pages of `allocator` in advance, and returns whether there was one to
+/// zero.
+///
+/// This is meant for the idle thread. The page is zeroed while the allocator is unlocked.
+pub fn zero_free_page(allocator: &Mutex<Allocator>) -> bool {
+    let Some(page) = allocator.lock().take_for_zeroing() else {
+        return false;
+    };
+    // Physical memory is identity mapped.
+    unsafe { zero_pages(page as *mut u8, 0x1000) };
+    allocator.lock().add_zeroed(page);
+    true
 }
 
 /// An error that occurs when memory cannot be allocated.
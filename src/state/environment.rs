@@ -0,0 +1,78 @@
+use crate::utility::{KeyValue, KeyValueError, KeyValueTable};
+
+/// The maximum number of variables in an [`Environment`].
+pub const MAX_VARIABLES: usize = 16;
+
+/// The maximum length of the name of a variable.
+pub const MAX_VARIABLE_NAME_LEN: usize = 16;
+
+/// The maximum length of the value of a variable.
+pub const MAX_VARIABLE_VALUE_LEN: usize = 64;
+
+/// An error that might occur while setting a variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvError {
+    /// The name is empty, too long, or contains characters other than letters, digits and
+    /// underscores.
+    InvalidName,
+    /// The value is too long.
+    ValueTooLong,
+    /// No more variables can be set.
+    TooManyVariables,
+}
+
+impl core::fmt::Display for EnvError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::InvalidName => "invalid variable name",
+            Self::ValueTooLong => "the value is too long",
+            Self::TooManyVariables => "too many variables",
+        })
+    }
+}
+
+/// Returns whether `c` may appear in the name of a variable.
+#[inline]
+pub fn is_variable_name_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// A set of variables, such as `HOME=/`.
+#[derive(Default)]
+pub struct Environment(KeyValueTable<MAX_VARIABLE_NAME_LEN, MAX_VARIABLE_VALUE_LEN, MAX_VARIABLES>);
+
+impl Environment {
+    /// Returns the value of the variable named `name`, if it is set.
+    #[inline]
+    pub fn get(&self, name: &[u8]) -> Option<&[u8]> {
+        self.0.get(name)
+    }
+
+    /// Sets the value of a variable, replacing its previous value.
+    pub fn set(&mut self, name: &[u8], value: &[u8]) -> Result<(), EnvError> {
+        if name.is_empty() || !name.iter().copied().all(is_variable_name_char) {
+            return Err(EnvError::InvalidName);
+        }
+        self.0.insert(name, value).map_err(|err| match err {
+            KeyValueError::KeyTooLong => EnvError::InvalidName,
+            KeyValueError::ValueTooLong => EnvError::ValueTooLong,
+            KeyValueError::Full => EnvError::TooManyVariables,
+        })
+    }
+
+    /// Removes the variable named `name`.
+    ///
+    /// Returns whether it was set.
+    #[inline]
+    pub fn remove(&mut self, name: &[u8]) -> bool {
+        self.0.remove(name)
+    }
+
+    /// Returns an iterator over the variables, as `(name, value)` entries.
+    #[inline]
+    pub fn iter(
+        &self,
+    ) -> impl '_ + Iterator<Item = &KeyValue<MAX_VARIABLE_NAME_LEN, MAX_VARIABLE_VALUE_LEN>> {
+        self.0.iter()
+    }
+}
@@ -0,0 +1,184 @@
+use core::mem::MaybeUninit;
+use core::panic::Location;
+
+use crate::utility::ArrayVec;
+
+/// The maximum number of distinct callers that can be recorded in [`FrameTags`].
+///
+/// Allocations made from other places are tagged with their owner only.
+pub const MAX_CALLERS: usize = 254;
+
+/// The part of the kernel that owns a physical frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameOwner {
+    /// The frame is not managed by the allocator (kernel image, boot allocations, holes).
+    Reserved,
+    /// The frame can be allocated.
+    Free,
+    /// The frame failed a memory test, and is never allocated again.
+    Bad,
+    /// The frame holds the content of a file.
+    FileSystem,
+    /// The frame holds a page table.
+    PageTable,
+    /// The frame holds the memory of a process.
+    Process,
+    /// The frame holds a shared-memory object.
+    Shared,
+    /// The frame is used by another part of the kernel.
+    Kernel,
+}
+
+impl FrameOwner {
+    /// All the owners, in the order in which they are listed.
+    pub const ALL: [Self; 8] = [
+        Self::Reserved,
+        Self::Free,
+        Self::Bad,
+        Self::FileSystem,
+        Self::PageTable,
+        Self::Process,
+        Self::Shared,
+        Self::Kernel,
+    ];
+
+    /// Returns the name of the owner.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Reserved => "reserved",
+            Self::Free => "free",
+            Self::Bad => "bad",
+            Self::FileSystem => "filesystem",
+            Self::PageTable => "page-table",
+            Self::Process => "process",
+            Self::Shared => "shared",
+            Self::Kernel => "kernel",
+        }
+    }
+}
+
+/// The tag of a physical frame.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTag {
+    /// The owner of the frame.
+    pub owner: FrameOwner,
+    /// The index of the caller that allocated the frame in [`FrameTags`], plus one.
+    ///
+    /// Zero means that the caller is unknown.
+    caller: u8,
+}
+
+impl FrameTag {
+    /// The tag of a frame that is not managed by the allocator.
+    pub const RESERVED: Self = Self {
+        owner: FrameOwner::Reserved,
+        caller: 0,
+    };
+}
+
+/// A side table that records the owner of every physical frame, for debugging purposes.
+///
+/// The table is indexed by page number. Frames that lie past its end are not tagged.
+pub struct FrameTags {
+    /// The tag of each frame.
+    tags: &'static mut [FrameTag],
+    /// The places from which frames were allocated.
+    callers: ArrayVec<&'static Location<'static>, MAX_CALLERS>,
+}
+
+impl FrameTags {
+    /// Creates a new [`FrameTags`] instance with the provided backing storage.
+    ///
+    /// Every frame starts out [`Reserved`](FrameOwner::Reserved).
+    pub fn new(storage: &'static mut [MaybeUninit<FrameTag>]) -> Self {
+        for tag in storage.iter_mut() {
+            tag.write(FrameTag::RESERVED);
+        }
+
+        // SAFETY: every element was just initialized.
+        let tags = unsafe { &mut *(storage as *mut [MaybeUninit<FrameTag>] as *mut [FrameTag]) };
+        Self {
+            tags,
+            callers: ArrayVec::new(),
+        }
+    }
+
+    /// Returns the tag of the frame at `page`.
+    pub fn get(&self, page: u32) -> FrameTag {
+        self.tags
+            .get(page as usize >> 12)
+            .copied()
+            .unwrap_or(FrameTag::RESERVED)
+    }
+
+    /// Tags the frame at `page`, optionally recording the place that allocated it.
+    pub fn set(
+        &mut self,
+        page: u32,
+        owner: FrameOwner,
+        caller: Option<&'static Location<'static>>,
+    ) {
+        let caller = caller.map_or(0, |caller| self.caller_index(caller));
+        if let Some(tag) = self.tags.get_mut(page as usize >> 12) {
+            *tag = FrameTag { owner, caller };
+        }
+    }
+
+    /// Returns the place that allocated a frame with the provided tag, if it is known.
+    pub fn caller(&self, tag: FrameTag) -> Option<&'static Location<'static>> {
+        self.callers
+            .get(tag.caller.checked_sub(1)? as usize)
+            .copied()
+    }
+
+    /// Returns the number of frames that belong to each owner, in the order of
+    /// [`FrameOwner::ALL`].
+    pub fn count_by_owner(&self) -> [usize; FrameOwner::ALL.len()] {
+        let mut counts = [0; FrameOwner::ALL.len()];
+        for tag in self.tags.iter() {
+            counts[tag.owner as usize] += 1;
+        }
+        counts
+    }
+
+    /// Returns the number of allocated frames that were allocated from each caller, in the
+    /// order of [`callers`](Self::callers).
+    ///
+    /// The last element counts the frames whose caller is unknown.
+    pub fn count_by_caller(&self) -> [usize; MAX_CALLERS + 1] {
+        let mut counts = [0; MAX_CALLERS + 1];
+        for tag in self.tags.iter().filter(|tag| is_allocated(tag.owner)) {
+            match tag.caller.checked_sub(1) {
+                Some(index) => counts[index as usize] += 1,
+                None => counts[MAX_CALLERS] += 1,
+            }
+        }
+        counts
+    }
+
+    /// Returns the places from which frames were allocated so far.
+    pub fn callers(&self) -> &[&'static Location<'static>] {
+        &self.callers
+    }
+
+    /// Returns the index of `caller` plus one, recording it if it is new.
+    ///
+    /// Zero is returned if too many callers were already recorded.
+    fn caller_index(&mut self, caller: &'static Location<'static>) -> u8 {
+        let index = match self.callers.iter().position(|&c| c == caller) {
+            Some(index) => index,
+            None if self.callers.try_push(caller).is_ok() => self.callers.len() - 1,
+            None => return 0,
+        };
+        index as u8 + 1
+    }
+}
+
+/// Returns whether frames that belong to `owner` were handed out by the allocator.
+pub fn is_allocated(owner: FrameOwner) -> bool {
+    !matches!(
+        owner,
+        FrameOwner::Reserved | FrameOwner::Free | FrameOwner::Bad
+    )
+}
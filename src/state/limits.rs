@@ -0,0 +1,122 @@
+use core::fmt::{self, Display};
+
+use crate::utility::{ArrayVec, HumanBytes, Mutex};
+
+use super::{Processes, UserId};
+
+/// The number of processes a user may own when no limit was set for them.
+pub const DEFAULT_MAX_PROCESSES: u32 = 256;
+
+/// The maximum number of users whose limits differ from [`Limits::DEFAULT`].
+pub const MAX_LIMITED_USERS: usize = 16;
+
+/// A resource whose use is limited for each user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    /// The number of processes owned by the user, including the zombies.
+    Processes,
+    /// The number of pages mapped by the processes of the user.
+    Memory,
+}
+
+impl Resource {
+    /// The number of resources.
+    pub const COUNT: usize = 2;
+
+    /// Every resource.
+    pub const ALL: [Self; Self::COUNT] = [Self::Processes, Self::Memory];
+
+    /// Returns the name of the resource.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Processes => "processes",
+            Self::Memory => "memory",
+        }
+    }
+
+    /// Returns the resource named `name`, if any.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.name().as_bytes() == name)
+    }
+}
+
+/// Formats an amount of a [`Resource`], such as `12` processes or `4 KiB` of memory.
+pub struct Amount(pub Resource, pub u32);
+
+impl Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Resource::Processes => Display::fmt(&self.1, f),
+            Resource::Memory => Display::fmt(&HumanBytes(self.1 as u64 * 4096), f),
+        }
+    }
+}
+
+/// The limits of a user, `None` meaning that a resource is unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    limits: [Option<u32>; Resource::COUNT],
+}
+
+impl Limits {
+    /// The limits of the users for which no limit was set.
+    pub const DEFAULT: Self = Self {
+        limits: [Some(DEFAULT_MAX_PROCESSES), None],
+    };
+
+    /// Returns the limit of `resource`.
+    #[inline]
+    pub fn get(&self, resource: Resource) -> Option<u32> {
+        self.limits[resource as usize]
+    }
+}
+
+/// The limits of the users, for those that differ from [`Limits::DEFAULT`].
+static LIMITS: Mutex<ArrayVec<(UserId, Limits), MAX_LIMITED_USERS>> = Mutex::new(ArrayVec::new());
+
+/// Returns the limits of `user`.
+pub fn limits(user: UserId) -> Limits {
+    LIMITS
+        .lock()
+        .iter()
+        .find(|&&(id, _)| id == user)
+        .map_or(Limits::DEFAULT, |&(_, limits)| limits)
+}
+
+/// Sets the limit of `resource` for `user`.
+///
+/// Returns `false` if the limits of too many users differ from the default ones already.
+pub fn set_limit(user: UserId, resource: Resource, limit: Option<u32>) -> bool {
+    let mut all = LIMITS.lock();
+    let index = match all.iter().position(|&(id, _)| id == user) {
+        Some(index) => index,
+        None if all.try_push((user, Limits::DEFAULT)).is_ok() => all.len() - 1,
+        None => return false,
+    };
+
+    let limits = &mut all[index].1;
+    limits.limits[resource as usize] = limit;
+    if *limits == Limits::DEFAULT {
+        all.remove_range(index..=index);
+    }
+    true
+}
+
+/// Returns how much of `resource` the processes of `user` use.
+pub fn usage(processes: &Processes, user: UserId, resource: Resource) -> u32 {
+    processes
+        .iter()
+        .filter(|(_, p)| p.owner == user)
+        .map(|(_, p)| match resource {
+            Resource::Processes => 1,
+            Resource::Memory => p.memory.mapped,
+        })
+        .fold(0, u32::saturating_add)
+}
+
+/// Returns whether the processes of `user` may use `amount` more of `resource`.
+pub fn allows(processes: &Processes, user: UserId, resource: Resource, amount: u32) -> bool {
+    limits(user).get(resource).map_or(true, |limit| {
+        usage(processes, user, resource).saturating_add(amount) <= limit
+    })
+}
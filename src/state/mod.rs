@@ -1,17 +1,25 @@
 //! Defines the structures used in the kernel's global state.
 
 mod allocator;
+mod environment;
+mod frame_tags;
+mod limits;
 mod process;
 mod system_info;
 mod user;
+mod wait_queue;
 
 use crate::utility::Mutex;
 use crate::utility::OnceCell;
 
 pub use self::allocator::*;
+pub use self::environment::*;
+pub use self::frame_tags::*;
+pub use self::limits::*;
 pub use self::process::*;
 pub use self::system_info::*;
 pub use self::user::*;
+pub use self::wait_queue::*;
 
 /// The global state of the kernel.
 ///
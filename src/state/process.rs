@@ -1,5 +1,7 @@
 use core::mem::MaybeUninit;
 
+use crate::cpu::paging::spaces;
+use crate::cpu::tss::IoPermissions;
 use crate::utility::InitAllocator;
 
 use super::UserId;
@@ -32,8 +34,94 @@ impl Processes {
             current: 0,
         }
     }
+
+    /// Returns the ID of the process that is currently running.
+    #[inline(always)]
+    pub fn current_id(&self) -> ProcessId {
+        self.current
+    }
+
+    /// Makes `id` the process that is currently running, and loads its I/O permissions and its
+    /// address space.
+    ///
+    /// Nothing is changed if the process does not exist.
+    pub fn set_current(&mut self, id: ProcessId) {
+        if let Some(process) = self.get(id) {
+            crate::cpu::tss::load_io_permissions(&process.io_permissions);
+            // Processes only run in the kernel's address space, or in one created for them.
+            unsafe { spaces::activate(process.address_space) };
+            self.current = id;
+        }
+    }
+
+    /// Returns the process that is currently running.
+    #[inline]
+    pub fn current(&self) -> &Process {
+        self.processes[self.current as usize]
+            .as_ref()
+            .expect("the current process does not exist")
+    }
+
+    /// Returns the process that is currently running.
+    #[inline]
+    pub fn current_mut(&mut self) -> &mut Process {
+        self.processes[self.current as usize]
+            .as_mut()
+            .expect("the current process does not exist")
+    }
+
+    /// Returns the process with the provided ID, if it exists.
+    #[inline]
+    pub fn get(&self, id: ProcessId) -> Option<&Process> {
+        self.processes.get(id as usize)?.as_ref()
+    }
+
+    /// Returns an iterator over the processes, along with their IDs.
+    pub fn iter(&self) -> impl '_ + Iterator<Item = (ProcessId, &Process)> {
+        self.processes
+            .iter()
+            .enumerate()
+            .filter_map(|(id, p)| Some((id as ProcessId, p.as_ref()?)))
+    }
+
+    /// Returns the process with the provided ID, if it exists.
+    #[inline]
+    pub fn get_mut(&mut self, id: ProcessId) -> Option<&mut Process> {
+        self.processes.get_mut(id as usize)?.as_mut()
+    }
+
+    /// Adds `process` to the list, and returns its ID.
+    ///
+    /// `None` is returned if the list is full.
+    pub fn insert(&mut self, process: Process) -> Option<ProcessId> {
+        let id = self.processes.iter().position(Option::is_none)?;
+        self.processes[id] = Some(process);
+        Some(id as ProcessId)
+    }
+
+    /// Removes the process with the provided ID from the list, and returns it.
+    ///
+    /// The current process and [`INIT`] are never removed.
+    pub fn remove(&mut self, id: ProcessId) -> Option<Process> {
+        if id == self.current || id == INIT {
+            return None;
+        }
+        self.processes.get_mut(id as usize)?.take()
+    }
+
+    /// Makes [`INIT`] the parent of the children of `id`.
+    pub fn reparent_children(&mut self, id: ProcessId) {
+        for process in self.processes.iter_mut().flatten() {
+            if process.parent == id {
+                process.parent = INIT;
+            }
+        }
+    }
 }
 
+/// The ID of the first process, which is created by the kernel at boot.
+pub const INIT: ProcessId = 0;
+
 /// The ID of the process.
 pub type ProcessId = u32;
 
@@ -41,19 +129,123 @@ pub type ProcessId = u32;
 pub struct Process {
     /// The ID of the parent.
     pub parent: ProcessId,
+    /// Whether the process is running, or exited and waits to be reaped.
+    pub state: ProcessState,
     /// The signals that the process has eventually received.
     pub signals: Signals,
     /// The ID of the user that created the process.
     pub owner: UserId,
+    /// The physical address of the root table of the address space of the process.
+    ///
+    /// This is the one of its parent, unless it was given a new one (see [`spaces`]).
+    pub address_space: u32,
+    /// The I/O ports that the process may access directly.
+    pub io_permissions: IoPermissions,
+    /// Where the system calls of the process are logged, if they are traced.
+    pub strace: Option<StraceOutput>,
+    /// The memory used by the process.
+    pub memory: MemoryStats,
+    /// The time the process spent on the CPU.
+    pub cpu: CpuTime,
+}
+
+/// The state of a [`Process`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    /// The process has threads that did not exit yet.
+    Alive,
+    /// The last thread of the process exited with `status`.
+    ///
+    /// The resources of the process were released, but it stays in the list so that its
+    /// parent can retrieve its exit status.
+    Zombie {
+        /// The exit status of the process.
+        status: u8,
+    },
+}
+
+impl ProcessState {
+    /// Returns the name of the state.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Alive => "alive",
+            Self::Zombie { .. } => "zombie",
+        }
+    }
+}
+
+/// The time a [`Process`] spent on the CPU, in timer ticks.
+///
+/// Every timer tick is charged to the process that was interrupted by it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuTime {
+    /// The number of ticks during which the process ran in user mode.
+    pub user: u32,
+    /// The number of ticks during which the process ran in the kernel.
+    pub kernel: u32,
+}
+
+impl CpuTime {
+    /// Returns the total number of ticks.
+    #[inline]
+    pub fn total(&self) -> u32 {
+        self.user.wrapping_add(self.kernel)
+    }
+}
+
+/// The memory used by a [`Process`], in pages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryStats {
+    /// The number of pages mapped in the address space of the process.
+    pub mapped: u32,
+    /// The number of mapped pages that are backed by frames allocated for the process.
+    pub resident: u32,
+}
+
+impl MemoryStats {
+    /// Records that `pages` pages were mapped. `resident` is set when frames were allocated for
+    /// them.
+    pub fn map(&mut self, pages: u32, resident: bool) {
+        self.mapped += pages;
+        if resident {
+            self.resident += pages;
+        }
+    }
+
+    /// Records that `pages` pages were unmapped. `resident` is set when their frames were
+    /// released.
+    pub fn unmap(&mut self, pages: u32, resident: bool) {
+        self.mapped = self.mapped.saturating_sub(pages);
+        if resident {
+            self.resident = self.resident.saturating_sub(pages);
+        }
+    }
+}
+
+/// Where the system calls of a traced [`Process`] are logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StraceOutput {
+    /// The system calls are printed to the terminal.
+    Terminal,
+    /// The system calls are logged to the serial port.
+    Serial,
 }
 
 impl Process {
-    /// Creates a new empty [`Process`] instance.
-    pub fn new(parent: ProcessId, owner: UserId) -> Self {
+    /// Creates a new empty [`Process`] instance, which runs in `address_space`.
+    ///
+    /// The address space must have been [acquired](spaces::acquire) for the process.
+    pub fn new(parent: ProcessId, owner: UserId, address_space: u32) -> Self {
         Self {
             parent,
+            state: ProcessState::Alive,
             signals: Signals::default(),
             owner,
+            address_space,
+            io_permissions: IoPermissions::new(),
+            strace: None,
+            memory: MemoryStats::default(),
+            cpu: CpuTime::default(),
         }
     }
 }
@@ -81,6 +273,12 @@ impl Signals {
         self.received[idx] = Some(received_signal);
         true
     }
+
+    /// Returns whether `signal` was received, and is not handled yet.
+    #[inline]
+    pub fn is_pending(&self, signal: Signal) -> bool {
+        self.received[signal as usize].is_some()
+    }
 }
 
 /// Information about a received signal.
@@ -96,9 +294,14 @@ pub struct ReceivedSignal {
 pub enum Signal {
     /// The **SIGINT** signal.
     Int,
+    /// The **SIGKILL** signal.
+    ///
+    /// The threads of the process exit the next time they return from a system call, or stop
+    /// waiting.
+    Kill,
 }
 
 impl Signal {
     /// The number of signals.
-    pub const COUNT: usize = 1;
+    pub const COUNT: usize = 2;
 }
@@ -1,13 +1,68 @@
 use core::sync::atomic::AtomicU32;
 
-use crate::utility::ArrayVec;
+use crate::error::KernelError;
+use crate::multiboot::MemMapType;
+use crate::utility::{ArrayVec, Mutex};
+
+/// The maximum number of entries of the memory map that are kept in [`SystemInfo`].
+pub const MAX_MEMORY_REGIONS: usize = 128;
+
+/// The maximum number of drivers whose initialization is recorded in [`SystemInfo`].
+pub const MAX_DRIVERS: usize = 16;
+
+/// How the initialization of a driver went.
+#[derive(Debug, Clone, Copy)]
+pub struct DriverStatus {
+    /// The name of the driver.
+    pub name: &'static str,
+    /// The result of its initialization.
+    pub result: Result<(), KernelError>,
+}
+
+/// The drivers whose initialization was attempted, in order.
+pub type Drivers = ArrayVec<DriverStatus, MAX_DRIVERS>;
+
+/// A region of physical memory, as reported by the firmware.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryRegion {
+    /// The address of the first byte of the region.
+    pub start: u64,
+    /// The size of the region, in bytes.
+    pub len: u64,
+    /// The type of the region.
+    pub ty: MemMapType,
+}
+
+impl MemoryRegion {
+    /// Returns the address of the byte that follows the region, or [`None`] if the region
+    /// extends past the end of the 64-bit address space.
+    #[inline]
+    pub fn end(&self) -> Option<u64> {
+        self.start.checked_add(self.len)
+    }
+}
 
 /// Stores information about the system.
 pub struct SystemInfo {
     /// The total amount of memory available, in bytes.
     pub total_memory: u32,
+    /// The amount of memory reported as available by the bootloader, but that lies too high in
+    /// the physical address space for the kernel to use it.
+    pub unreachable_memory: u64,
+    /// The amount of memory above 4 GiB that can be allocated as 4 MiB frames through PSE-36.
+    pub high_memory: u64,
     /// The name of the bootloader.
     pub bootloader_name: Option<ArrayVec<u8, 62>>,
+    /// The command-line that the bootloader passed to the kernel.
+    ///
+    /// See the [`cmdline`](crate::cmdline) module to parse it.
+    pub cmdline: ArrayVec<u8, 255>,
+    /// The memory map provided by the bootloader, including regions that are not available.
+    pub memory_map: ArrayVec<MemoryRegion, MAX_MEMORY_REGIONS>,
+    /// The drivers that were initialized while the kernel booted.
+    ///
+    /// The kernel keeps going without the devices whose driver failed to initialize.
+    pub drivers: Mutex<Drivers>,
     /// The total number of ticks since the system was started.
     ///
     /// If a tick is a millisecond, this value will overflow after 49.7 days.
@@ -1,2 +1,19 @@
 /// The ID of a user.
 pub type UserId = u32;
+
+/// The ID of the super-user.
+pub const ROOT: UserId = 0;
+
+/// The ID of the user without any privilege.
+pub const NOBODY: UserId = 65534;
+
+/// The list of known users, along with their names.
+const USERS: &[(UserId, &str)] = &[(ROOT, "root"), (NOBODY, "nobody")];
+
+/// Returns the name of the provided user, if it is known.
+pub fn user_name(id: UserId) -> Option<&'static str> {
+    USERS
+        .iter()
+        .find(|&&(uid, _)| uid == id)
+        .map(|&(_, name)| name)
+}
@@ -0,0 +1,100 @@
+use crate::reaper;
+use crate::sched::{self, ThreadId};
+use crate::time;
+use crate::utility::{ArrayVec, Mutex, RestoreInterrupts};
+
+/// The maximum number of threads that can wait on a [`WaitQueue`] at once.
+pub const MAX_WAITERS: usize = 8;
+
+/// A thread waiting on a [`WaitQueue`].
+#[derive(Debug, Clone, Copy)]
+struct Waiter {
+    /// The ID of the waiting thread.
+    thread: ThreadId,
+    /// Whether the thread was woken up.
+    woken: bool,
+}
+
+/// A list of threads waiting for an event.
+///
+/// A waiting thread is blocked, and the other threads run until it is woken up, or until its
+/// deadline passes.
+pub struct WaitQueue {
+    waiters: Mutex<ArrayVec<Waiter, MAX_WAITERS>>,
+}
+
+impl WaitQueue {
+    /// Creates a new, empty [`WaitQueue`] instance.
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(ArrayVec::new()),
+        }
+    }
+
+    /// Blocks the current thread until it is woken up, or until the monotonic clock reaches
+    /// `deadline` (in nanoseconds).
+    ///
+    /// Returns whether the thread was woken up. `false` is also returned if the queue is
+    /// full, or if the process of the thread was killed.
+    pub fn wait(&self, deadline: Option<u64>) -> bool {
+        let thread = sched::current();
+        let waiter = Waiter {
+            thread,
+            woken: false,
+        };
+        if self.waiters.lock().try_push(waiter).is_err() {
+            return false;
+        }
+
+        // Interrupts stay disabled between the check and the moment the thread blocks, so that
+        // a wake-up cannot be missed.
+        let restore = RestoreInterrupts::without_interrupts();
+        let woken = loop {
+            if self.is_woken(thread) {
+                break true;
+            }
+            if reaper::is_current_killed() {
+                break false;
+            }
+            if deadline.is_some_and(|deadline| time::monotonic_ns() >= deadline) {
+                break false;
+            }
+
+            sched::block(deadline);
+        };
+        drop(restore);
+
+        let mut waiters = self.waiters.lock();
+        if let Some(index) = waiters.iter().position(|w| w.thread == thread) {
+            waiters.remove_range(index..=index);
+        }
+        drop(waiters);
+        woken
+    }
+
+    /// Returns whether `thread` was woken up.
+    fn is_woken(&self, thread: ThreadId) -> bool {
+        self.waiters
+            .lock()
+            .iter()
+            .any(|w| w.thread == thread && w.woken)
+    }
+
+    /// Wakes up every thread waiting on the queue.
+    pub fn wake_all(&self) {
+        let mut waiters = self.waiters.lock();
+        for waiter in waiters.iter_mut() {
+            waiter.woken = true;
+            sched::wake(waiter.thread);
+        }
+    }
+
+    /// Returns the threads waiting on the queue.
+    pub fn waiters(&self) -> ArrayVec<ThreadId, MAX_WAITERS> {
+        let mut ret = ArrayVec::new();
+        for waiter in self.waiters.lock().iter() {
+            ret.push(waiter.thread);
+        }
+        ret
+    }
+}
@@ -0,0 +1,298 @@
+//! The table that maps key chords to the actions of the terminal.
+
+use core::fmt::{Display, Formatter, Result};
+
+use bitflags::bitflags;
+
+use super::layouts::Modifiers;
+use super::Key;
+
+/// The maximum number of key bindings.
+pub const MAX_BINDINGS: usize = 32;
+
+bitflags! {
+    /// The modifiers that must be held for a [`Chord`] to be pressed.
+    ///
+    /// Unlike [`Modifiers`], the left and right keys are not told apart.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ChordModifiers: u8 {
+        /// One of the **CONTROL** keys.
+        const CONTROL = 1 << 0;
+        /// One of the **ALT** keys.
+        const ALT = 1 << 1;
+        /// One of the **SHIFT** keys.
+        const SHIFT = 1 << 2;
+    }
+}
+
+/// A key pressed while some modifiers are held, such as **CTRL + ALT + DEL**.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    /// The modifiers that are held.
+    pub modifiers: ChordModifiers,
+    /// The key that is pressed.
+    ///
+    /// Letters are always lowercase: **SHIFT** is part of the modifiers.
+    pub key: Key,
+}
+
+impl Chord {
+    /// Creates a new [`Chord`].
+    pub const fn new(modifiers: ChordModifiers, key: Key) -> Self {
+        Self {
+            modifiers,
+            key: match key {
+                Key::Char(c) => Key::Char(c.to_ascii_lowercase()),
+                key => key,
+            },
+        }
+    }
+
+    /// Creates the [`Chord`] that corresponds to a key produced by the layout.
+    pub fn from_key(key: Key, modifiers: Modifiers) -> Self {
+        let mut held = ChordModifiers::empty();
+        held.set(ChordModifiers::CONTROL, modifiers.has_control());
+        held.set(ChordModifiers::ALT, modifiers.has_alt());
+        held.set(ChordModifiers::SHIFT, modifiers.has_shift());
+        Self::new(held, key)
+    }
+
+    /// Parses a chord, such as `ctrl+alt+del` or `shift+insert`.
+    ///
+    /// Names are case-insensitive.
+    pub fn parse(s: &[u8]) -> Option<Self> {
+        let mut parts = s.split(|&c| c == b'+');
+        let key = parts.next_back()?;
+        let mut modifiers = ChordModifiers::empty();
+
+        for part in parts {
+            let modifier = if is_any_of(part, &[b"ctrl", b"control"]) {
+                ChordModifiers::CONTROL
+            } else if is_any_of(part, &[b"alt"]) {
+                ChordModifiers::ALT
+            } else if is_any_of(part, &[b"shift"]) {
+                ChordModifiers::SHIFT
+            } else {
+                return None;
+            };
+            modifiers.insert(modifier);
+        }
+
+        Some(Self::new(modifiers, parse_key(key)?))
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for (name, _) in self.modifiers.iter_names() {
+            match name {
+                "CONTROL" => f.write_str("Ctrl+")?,
+                "ALT" => f.write_str("Alt+")?,
+                _ => f.write_str("Shift+")?,
+            }
+        }
+
+        match self.key {
+            Key::Char(' ') => f.write_str("Space"),
+            Key::Char('\n') => f.write_str("Enter"),
+            Key::Char('\t') => f.write_str("Tab"),
+            Key::Char('\x08') => f.write_str("Backspace"),
+            Key::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            Key::PrintScreen => f.write_str("PrintScreen"),
+            Key::Pause => f.write_str("Pause"),
+            Key::Insert => f.write_str("Insert"),
+            Key::Delete => f.write_str("Delete"),
+            Key::Function(n) => write!(f, "F{n}"),
+        }
+    }
+}
+
+/// Returns whether `s` is one of `names`, ignoring the case.
+fn is_any_of(s: &[u8], names: &[&[u8]]) -> bool {
+    names.iter().any(|name| s.eq_ignore_ascii_case(name))
+}
+
+/// The names of the keys that do not produce a printable character.
+const KEY_NAMES: [(&[u8], Key); 11] = [
+    (b"space", Key::Char(' ')),
+    (b"enter", Key::Char('\n')),
+    (b"tab", Key::Char('\t')),
+    (b"backspace", Key::Char('\x08')),
+    (b"printscreen", Key::PrintScreen),
+    (b"prtsc", Key::PrintScreen),
+    (b"pause", Key::Pause),
+    (b"insert", Key::Insert),
+    (b"ins", Key::Insert),
+    (b"delete", Key::Delete),
+    (b"del", Key::Delete),
+];
+
+/// Parses the name of a key.
+fn parse_key(s: &[u8]) -> Option<Key> {
+    if let Some(&(_, key)) = KEY_NAMES
+        .iter()
+        .find(|(name, _)| s.eq_ignore_ascii_case(name))
+    {
+        return Some(key);
+    }
+
+    match s {
+        [b'f' | b'F', n @ ..] if !n.is_empty() => {
+            let n = core::str::from_utf8(n).ok()?.parse().ok()?;
+            (1..=12).contains(&n).then_some(Key::Function(n))
+        }
+        &[c] if c.is_ascii_graphic() => Some(Key::Char(c as char)),
+        _ => None,
+    }
+}
+
+/// An action that can be bound to a [`Chord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Clears the screen.
+    ClearScreen,
+    /// Clears the command-line.
+    ClearLine,
+    /// Types the content of the clipboard into the command-line.
+    Paste,
+    /// Auto-completes the command-line.
+    Complete,
+    /// Dumps the screen to the serial port.
+    Screenshot,
+    /// Restarts the system.
+    Reboot,
+    /// Stops the system.
+    Halt,
+    /// Sends the **SIGINT** signal to the init process.
+    InterruptInit,
+}
+
+impl Action {
+    /// All the actions, in the order in which they are listed.
+    pub const ALL: [Self; 8] = [
+        Self::ClearScreen,
+        Self::ClearLine,
+        Self::Paste,
+        Self::Complete,
+        Self::Screenshot,
+        Self::Reboot,
+        Self::Halt,
+        Self::InterruptInit,
+    ];
+
+    /// Returns the name of the action.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ClearScreen => "clear",
+            Self::ClearLine => "clear-line",
+            Self::Paste => "paste",
+            Self::Complete => "complete",
+            Self::Screenshot => "screenshot",
+            Self::Reboot => "reboot",
+            Self::Halt => "halt",
+            Self::InterruptInit => "sigint-init",
+        }
+    }
+
+    /// Finds an action by name.
+    pub fn find(name: &[u8]) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name().as_bytes() == name)
+    }
+}
+
+/// A [`Chord`] bound to an [`Action`].
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    /// The chord that triggers the action.
+    pub chord: Chord,
+    /// The action that is triggered.
+    pub action: Action,
+}
+
+/// The bindings of a new [`Keymap`].
+const DEFAULT_BINDINGS: [Binding; 5] = {
+    const fn bind(modifiers: ChordModifiers, key: Key, action: Action) -> Binding {
+        Binding {
+            chord: Chord::new(modifiers, key),
+            action,
+        }
+    }
+
+    const CTRL: ChordModifiers = ChordModifiers::CONTROL;
+    const NONE: ChordModifiers = ChordModifiers::empty();
+
+    [
+        bind(CTRL, Key::Char('c'), Action::ClearLine),
+        bind(CTRL, Key::Char('l'), Action::ClearScreen),
+        bind(ChordModifiers::SHIFT, Key::Insert, Action::Paste),
+        bind(NONE, Key::PrintScreen, Action::Screenshot),
+        bind(CTRL.union(ChordModifiers::ALT), Key::Delete, Action::Reboot),
+    ]
+};
+
+/// Maps key chords to actions.
+pub struct Keymap {
+    /// The bindings, in the order in which they were added.
+    bindings: [Option<Binding>; MAX_BINDINGS],
+}
+
+impl Keymap {
+    /// Creates a new [`Keymap`] with the default bindings.
+    pub const fn new() -> Self {
+        let mut bindings = [None; MAX_BINDINGS];
+        let mut i = 0;
+        while i < DEFAULT_BINDINGS.len() {
+            bindings[i] = Some(DEFAULT_BINDINGS[i]);
+            i += 1;
+        }
+        Self { bindings }
+    }
+
+    /// Returns the action bound to the provided chord, if any.
+    pub fn lookup(&self, chord: Chord) -> Option<Action> {
+        self.iter()
+            .find(|b| b.chord == chord)
+            .map(|binding| binding.action)
+    }
+
+    /// Binds a chord to an action, replacing the action it was previously bound to.
+    ///
+    /// Returns `false` if the keymap is full.
+    pub fn bind(&mut self, chord: Chord, action: Action) -> bool {
+        let slot = match self.position(chord) {
+            Some(index) => index,
+            None => match self.bindings.iter().position(Option::is_none) {
+                Some(index) => index,
+                None => return false,
+            },
+        };
+
+        self.bindings[slot] = Some(Binding { chord, action });
+        true
+    }
+
+    /// Removes the binding of the provided chord.
+    ///
+    /// Returns whether the chord was bound.
+    pub fn unbind(&mut self, chord: Chord) -> bool {
+        match self.position(chord) {
+            Some(index) => {
+                self.bindings[index] = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns an iterator over the bindings.
+    pub fn iter(&self) -> impl '_ + Iterator<Item = Binding> {
+        self.bindings.iter().flatten().copied()
+    }
+
+    /// Returns the index of the binding of the provided chord.
+    fn position(&self, chord: Chord) -> Option<usize> {
+        self.bindings
+            .iter()
+            .position(|b| b.is_some_and(|b| b.chord == chord))
+    }
+}
@@ -1,11 +1,44 @@
 //! This module contains the keyboard layouts supported by the kernel.
 
 mod qwerty;
+mod set2;
 
 pub use self::qwerty::Qwerty;
+pub use self::set2::Set2;
 
 use bitflags::bitflags;
 
+/// A key produced by a keyboard layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A key that produces a character.
+    Char(char),
+    /// The **PRINT SCREEN** key.
+    PrintScreen,
+    /// The **PAUSE** key (**BREAK** when CONTROL is held).
+    Pause,
+    /// The **INSERT** key.
+    Insert,
+    /// The **DELETE** key.
+    Delete,
+    /// One of the function keys, from **F1** to **F12**.
+    Function(u8),
+}
+
+/// The scan-code set sent by the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSet {
+    /// The scan-code set 1.
+    ///
+    /// This is what the PS/2 controller produces when its translation is enabled.
+    Set1,
+    /// The scan-code set 2.
+    ///
+    /// This is the native set of most keyboards, received when the translation of the PS/2
+    /// controller is disabled.
+    Set2,
+}
+
 bitflags! {
     /// Keeps track of the state of certain special keys, such as CONTROL or SHIFT.
     #[derive(Default, Clone, Copy)]
@@ -1,6 +1,6 @@
 use bitflags::bitflags;
 
-use super::Modifiers;
+use super::{Key, Modifiers};
 
 /// The current state of the state machine.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -10,6 +10,10 @@ enum State {
     Neutral,
     /// The E0 escape code has been received.
     E0,
+    /// The E1 escape code has been received. This starts the sequence of the Pause key.
+    E1,
+    /// The E1 escape code has been received, followed by the second byte of the sequence.
+    E1Second,
 }
 
 bitflags! {
@@ -49,11 +53,21 @@ impl Qwerty {
         self.modifiers
     }
 
-    /// Advances the state of the state machine with a new scan-code. If a character can
-    /// be produced, it is returned in a [`Some(_)`] variant.
+    /// Forgets the keys that are held and any escape sequence in progress, keeping the state
+    /// of the lock keys.
     ///
-    /// If no character could be produced, [`None`] is returned instead.
-    pub fn advance(&mut self, scancode: u8) -> Option<char> {
+    /// This is used when scan-codes were lost, as the releases of the keys may be among them.
+    pub fn release_keys(&mut self) {
+        self.modifiers &= Modifiers::CAPS_LOCK | Modifiers::NUM_LOCK;
+        self.state = State::Neutral;
+        self.flags = Flags::empty();
+    }
+
+    /// Advances the state of the state machine with a new scan-code. If a key can be
+    /// produced, it is returned in a [`Some(_)`] variant.
+    ///
+    /// If no key could be produced, [`None`] is returned instead.
+    pub fn advance(&mut self, scancode: u8) -> Option<Key> {
         use State::*;
 
         let st = self.state;
@@ -61,9 +75,37 @@ impl Qwerty {
         // Parse the current escape sequence.
         self.state = match (st, scancode) {
             (Neutral, 0xE0) => E0,
+            (Neutral, 0xE1) => E1,
+            (E1, 0x1D | 0x9D) => E1Second,
             _ => Neutral,
         };
 
+        match (st, scancode) {
+            // Pause is `E1 1D 45` when pressed, and `E1 9D C5` when released. It has no
+            // repeat.
+            (E1Second, 0x45) => Some(Key::Pause),
+            (E1 | E1Second, _) => None,
+            // Print Screen is `E0 2A E0 37` when pressed, and `E0 B7 E0 AA` when released. The
+            // `E0 2A` and `E0 AA` parts are "fake shifts" that must not change the modifiers.
+            (E0, 0x37) => Some(Key::PrintScreen),
+            (E0, 0x52) => Some(Key::Insert),
+            (Neutral, 0x52) if !self.modifiers.num_locked() => Some(Key::Insert),
+            (E0, 0x53) => Some(Key::Delete),
+            (Neutral, 0x53) if !self.modifiers.num_locked() => Some(Key::Delete),
+            (Neutral, 0x3B..=0x44) => Some(Key::Function(scancode - 0x3A)),
+            (Neutral, 0x57 | 0x58) => Some(Key::Function(scancode - 0x57 + 11)),
+            (E0, 0x2A | 0xAA | 0x36 | 0xB6) => None,
+            _ => self.advance_char(st, scancode).map(Key::Char),
+        }
+    }
+
+    /// Advances the state of the state machine with a scan-code that is not part of a special
+    /// multi-byte sequence.
+    ///
+    /// `st` is the state of the state machine before the scan-code was received.
+    fn advance_char(&mut self, st: State, scancode: u8) -> Option<char> {
+        use State::*;
+
         match (st, scancode) {
             // Update modifiers.
             (Neutral, 0x2A) => {
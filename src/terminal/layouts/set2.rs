@@ -0,0 +1,140 @@
+/// Converts scan-codes of the scan-code set 2 into scan-codes of the scan-code set 1.
+///
+/// Keyboard layouts only understand set 1. When the PS/2 controller translates scan-codes
+/// itself, the keyboard appears to send set 1 scan-codes and no conversion is needed. When the
+/// translation is disabled, the keyboard sends its native set 2 scan-codes, and this state
+/// machine performs the same translation as the controller would have.
+pub struct Set2 {
+    /// Whether the previous byte was the `0xF0` break prefix.
+    breaking: bool,
+}
+
+impl Set2 {
+    /// Returns a new instance of the [`Set2`] struct.
+    pub const fn new() -> Self {
+        Self { breaking: false }
+    }
+
+    /// Advances the state of the state machine with a new byte received from the keyboard.
+    ///
+    /// If the byte produces a set 1 scan-code, it is returned.
+    pub fn translate(&mut self, byte: u8) -> Option<u8> {
+        match byte {
+            // The break prefix applies to the next byte.
+            0xF0 => {
+                self.breaking = true;
+                None
+            }
+            // Escape prefixes are the same in both sets. They are never preceded by the break
+            // prefix.
+            0xE0 | 0xE1 => {
+                self.breaking = false;
+                Some(byte)
+            }
+            _ => {
+                let breaking = core::mem::replace(&mut self.breaking, false);
+                let code = to_set1(byte)?;
+                Some(if breaking { code | 0x80 } else { code })
+            }
+        }
+    }
+}
+
+/// Returns the set 1 scan-code corresponding to the provided set 2 scan-code.
+///
+/// Extended keys (preceded by `0xE0`) use the same table, as the prefix is kept as-is.
+fn to_set1(code: u8) -> Option<u8> {
+    Some(match code {
+        0x01 => 0x43, // F9
+        0x03 => 0x3F, // F5
+        0x04 => 0x3D, // F3
+        0x05 => 0x3B, // F1
+        0x06 => 0x3C, // F2
+        0x07 => 0x58, // F12
+        0x09 => 0x44, // F10
+        0x0A => 0x42, // F8
+        0x0B => 0x40, // F6
+        0x0C => 0x3E, // F4
+        0x0D => 0x0F, // Tab
+        0x0E => 0x29, // `
+        0x11 => 0x38, // Left Alt (Right Alt when extended)
+        0x12 => 0x2A, // Left Shift
+        0x14 => 0x1D, // Left Control (Right Control when extended)
+        0x15 => 0x10, // Q
+        0x16 => 0x02, // 1
+        0x1A => 0x2C, // Z
+        0x1B => 0x1F, // S
+        0x1C => 0x1E, // A
+        0x1D => 0x11, // W
+        0x1E => 0x03, // 2
+        0x1F => 0x5B, // Left GUI (extended)
+        0x21 => 0x2E, // C
+        0x22 => 0x2D, // X
+        0x23 => 0x20, // D
+        0x24 => 0x12, // E
+        0x25 => 0x05, // 4
+        0x26 => 0x04, // 3
+        0x27 => 0x5C, // Right GUI (extended)
+        0x29 => 0x39, // Space
+        0x2A => 0x2F, // V
+        0x2B => 0x21, // F
+        0x2C => 0x14, // T
+        0x2D => 0x13, // R
+        0x2E => 0x06, // 5
+        0x2F => 0x5D, // Apps (extended)
+        0x31 => 0x31, // N
+        0x32 => 0x30, // B
+        0x33 => 0x23, // H
+        0x34 => 0x22, // G
+        0x35 => 0x15, // Y
+        0x36 => 0x07, // 6
+        0x3A => 0x32, // M
+        0x3B => 0x24, // J
+        0x3C => 0x16, // U
+        0x3D => 0x08, // 7
+        0x3E => 0x09, // 8
+        0x41 => 0x33, // ,
+        0x42 => 0x25, // K
+        0x43 => 0x17, // I
+        0x44 => 0x18, // O
+        0x45 => 0x0B, // 0
+        0x46 => 0x0A, // 9
+        0x49 => 0x34, // .
+        0x4A => 0x35, // /
+        0x4B => 0x26, // L
+        0x4C => 0x27, // ;
+        0x4D => 0x19, // P
+        0x4E => 0x0C, // -
+        0x52 => 0x28, // '
+        0x54 => 0x1A, // [
+        0x55 => 0x0D, // =
+        0x58 => 0x3A, // Caps Lock
+        0x59 => 0x36, // Right Shift
+        0x5A => 0x1C, // Enter
+        0x5B => 0x1B, // ]
+        0x5D => 0x2B, // \
+        0x66 => 0x0E, // Backspace
+        0x69 => 0x4F, // Keypad 1 (End when extended)
+        0x6B => 0x4B, // Keypad 4 (Left when extended)
+        0x6C => 0x47, // Keypad 7 (Home when extended)
+        0x70 => 0x52, // Keypad 0 (Insert when extended)
+        0x71 => 0x53, // Keypad . (Delete when extended)
+        0x72 => 0x50, // Keypad 2 (Down when extended)
+        0x73 => 0x4C, // Keypad 5
+        0x74 => 0x4D, // Keypad 6 (Right when extended)
+        0x75 => 0x48, // Keypad 8 (Up when extended)
+        0x76 => 0x01, // Escape
+        0x77 => 0x45, // Num Lock
+        0x78 => 0x57, // F11
+        0x79 => 0x4E, // Keypad +
+        0x7A => 0x51, // Keypad 3 (Page Down when extended)
+        0x7B => 0x4A, // Keypad -
+        0x7C => 0x37, // Keypad * (Print Screen when extended)
+        0x7D => 0x49, // Keypad 9 (Page Up when extended)
+        0x7E => 0x46, // Scroll Lock
+        0x83 => 0x41, // F7
+        // Anything else is either an unknown key, or a response to a command (such as the
+        // ACK byte, 0xFA) that is not meant for the layout.
+        _ => return None,
+    })
+}
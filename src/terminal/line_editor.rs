@@ -0,0 +1,207 @@
+//! The editing logic of a command-line, independent of the device it is displayed on.
+
+use core::fmt::Write;
+
+use crate::utility::ArrayVec;
+
+/// The maximum length of a line, in bytes.
+pub const MAX_LINE_LEN: usize = crate::drivers::vga::WIDTH as usize;
+
+/// The control character that removes the word before the cursor (**CTRL + W**).
+pub const DELETE_WORD: u8 = 0x17;
+
+/// The control character that signals the end of the input (**CTRL + D**).
+pub const END_OF_FILE: u8 = 0x04;
+
+/// What happened to the line after a byte was fed to a [`LineEditor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEvent {
+    /// The line was modified and must be drawn again.
+    Edited,
+    /// The user submitted the line.
+    Submitted,
+    /// The user asked for the line to be auto-completed.
+    Complete,
+    /// The user signaled the end of the input on an empty line.
+    EndOfFile,
+    /// The byte had no effect.
+    Ignored,
+}
+
+/// A line being edited by the user.
+///
+/// The editor never waits for input: bytes are fed to it one at a time with
+/// [`feed`](Self::feed), and the caller decides what to do with the resulting [`LineEvent`].
+pub struct LineEditor {
+    /// The content of the line.
+    line: ArrayVec<u8, MAX_LINE_LEN>,
+    /// The position of the cursor within the line.
+    cursor: u8,
+    /// The maximum number of bytes the line may contain.
+    max_len: u8,
+}
+
+impl LineEditor {
+    /// Creates a new, empty [`LineEditor`] instance.
+    pub const fn new() -> Self {
+        Self {
+            line: ArrayVec::new(),
+            cursor: 0,
+            max_len: MAX_LINE_LEN as u8,
+        }
+    }
+
+    /// Processes a byte typed by the user.
+    ///
+    /// Printable characters are inserted at the cursor. Backspace (`0x08` or `0x7F`) and
+    /// [`DELETE_WORD`] remove what is before the cursor, a line feed or a carriage return
+    /// submits the line, and a tab requests auto-completion. [`END_OF_FILE`] is only reported
+    /// when the line is empty.
+    pub fn feed(&mut self, byte: u8) -> LineEvent {
+        let edited = match byte {
+            b'\n' | b'\r' => return LineEvent::Submitted,
+            b'\t' => return LineEvent::Complete,
+            END_OF_FILE if self.line.is_empty() => return LineEvent::EndOfFile,
+            0x08 | 0x7F => self.delete(false),
+            DELETE_WORD => self.delete(true),
+            _ if byte.is_ascii_control() => false,
+            _ => self.insert(byte),
+        };
+
+        if edited {
+            LineEvent::Edited
+        } else {
+            LineEvent::Ignored
+        }
+    }
+
+    /// Inserts a byte at the cursor.
+    ///
+    /// Returns `false` if the line is full.
+    pub fn insert(&mut self, byte: u8) -> bool {
+        if self.line.len() >= self.max_len as usize
+            || self.line.try_insert(self.cursor as usize, byte).is_err()
+        {
+            return false;
+        }
+
+        self.cursor += 1;
+        true
+    }
+
+    /// Removes the character before the cursor, or the whole word before it when `word` is
+    /// set.
+    ///
+    /// Returns whether something was removed.
+    pub fn delete(&mut self, word: bool) -> bool {
+        let cur = self.cursor as usize;
+        if cur == 0 {
+            return false;
+        }
+
+        let start = if word {
+            find_start_of_last_word(&self.line[..cur])
+        } else {
+            cur - 1
+        };
+
+        self.line.remove_range(start..cur);
+        self.cursor -= (cur - start) as u8;
+        true
+    }
+
+    /// Removes the whole line.
+    pub fn clear(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+    }
+
+    /// Replaces the content of the line, and moves the cursor to its end.
+    ///
+    /// The new content is truncated if it is too long.
+    pub fn set_line(&mut self, line: &[u8]) {
+        self.line = ArrayVec::from_slice_truncated(&line[..line.len().min(self.max_len())]);
+        self.cursor = self.line.len() as u8;
+    }
+
+    /// Returns the content of the line.
+    #[inline(always)]
+    pub fn line(&self) -> &[u8] {
+        &self.line
+    }
+
+    /// Returns the position of the cursor within the line.
+    #[inline(always)]
+    pub fn cursor(&self) -> usize {
+        self.cursor as usize
+    }
+
+    /// Moves the cursor, clamping it to the end of the line.
+    pub fn set_cursor(&mut self, pos: usize) {
+        self.cursor = pos.min(self.line.len()) as u8;
+    }
+
+    /// Returns the maximum number of bytes the line may contain.
+    #[inline(always)]
+    pub fn max_len(&self) -> usize {
+        self.max_len as usize
+    }
+
+    /// Sets the maximum number of bytes the line may contain, truncating it if needed.
+    pub fn set_max_len(&mut self, max: usize) {
+        self.max_len = max.min(MAX_LINE_LEN) as u8;
+        if self.line.len() > self.max_len() {
+            self.line.remove_range(self.max_len()..);
+        }
+        self.set_cursor(self.cursor());
+    }
+}
+
+/// A device on which a line being edited is displayed.
+pub trait Console {
+    /// Draws `prompt` followed by the line being edited by `editor`, with the cursor at its
+    /// position.
+    fn draw_line(&mut self, prompt: &[u8], editor: &LineEditor);
+}
+
+/// A [`Console`] that draws the line with ANSI escape sequences, such as a serial terminal.
+pub struct AnsiConsole<W>(pub W);
+
+impl<W: Write> Console for AnsiConsole<W> {
+    fn draw_line(&mut self, prompt: &[u8], editor: &LineEditor) {
+        let prompt = core::str::from_utf8(prompt).unwrap_or("");
+        let line = core::str::from_utf8(editor.line()).unwrap_or("");
+        let back = line.len() - editor.cursor();
+
+        // Errors can only come from the device itself, and there is nothing to do about them.
+        let _ = write!(self.0, "\r{prompt}{line}\x1B[K");
+        if back != 0 {
+            let _ = write!(self.0, "\x1B[{back}D");
+        }
+    }
+}
+
+/// Returns the index of the first character of the last word.
+///
+/// If no word is found, 0 is returned.
+fn find_start_of_last_word(s: &[u8]) -> usize {
+    let mut i = s.len();
+
+    // Skip initial whitespaces.
+    while i > 0 {
+        i -= 1;
+        if s[i] != b' ' {
+            break;
+        }
+    }
+
+    // Skip the last word.
+    while i > 0 {
+        i -= 1;
+        if s[i] == b' ' {
+            return i + 1;
+        }
+    }
+
+    i
+}
@@ -1,11 +1,47 @@
 //! This module provides a simple terminal implementation backed by the VGA buffer.
 
+mod keymap;
 mod layouts;
+mod line_editor;
+mod scrollback;
+mod selection;
+mod theme;
+mod tty;
 
 use core::fmt::Write;
 
+use crate::drivers::keyboard;
+use crate::drivers::mouse::{self, Buttons, Packet, Protocol};
+use crate::drivers::ps2;
 use crate::drivers::vga::{self, Color, VgaBuffer, VgaChar, HEIGHT, WIDTH};
-use crate::utility::ArrayVec;
+use crate::state::WaitQueue;
+use crate::utility::instr::pause;
+use crate::utility::{fill_u16, ArrayVec, RestoreInterrupts, RingBuffer};
+use crate::TERMINAL;
+
+pub use self::keymap::*;
+pub use self::layouts::{Key, ScancodeSet};
+pub use self::line_editor::*;
+pub use self::scrollback::*;
+pub use self::selection::*;
+pub use self::theme::*;
+pub use self::tty::*;
+
+/// The threads waiting for the keyboard or the mouse to send input to the terminal.
+///
+/// The interrupt handlers wake them up once they buffered a byte.
+pub static INPUT: WaitQueue = WaitQueue::new();
+
+/// Blocks the current thread until the terminal has buffered input from the keyboard or from
+/// the mouse, or until the monotonic clock reaches `deadline` (in nanoseconds).
+pub fn wait_for_input(deadline: Option<u64>) {
+    // Input that arrives between the check and the moment the thread blocks would be missed
+    // if interrupts were enabled.
+    let _restore = RestoreInterrupts::without_interrupts();
+    if !TERMINAL.lock().has_buffered_input() {
+        INPUT.wait(deadline);
+    }
+}
 
 /// Contains the state of the terminal.
 pub struct Terminal {
@@ -21,18 +57,160 @@ pub struct Terminal {
 
     /// The current foreground color.
     foreground: Color,
+    /// Whether characters that cannot be displayed are replaced instead of failing the write
+    /// operation.
+    ///
+    /// See [`Terminal::set_lossy`].
+    lossy: bool,
+    /// The theme used to pick the default colors of the terminal.
+    theme: &'static Theme,
 
-    /// The current command line.
-    cmdline: ArrayVec<u8, { WIDTH as usize }>,
-    /// The position of the user's cursor within the command-line.
-    cmdline_cursor: u8,
+    /// The prompt displayed before the command-line.
+    ///
+    /// The prompt is not part of the editable region of the command-line.
+    prompt: ArrayVec<u8, MAX_PROMPT_LEN>,
+    /// The line discipline of the keyboard input, which holds the command-line being edited.
+    tty: Tty,
 
-    /// A bunch of scan-codes that have been received from the keyboard.
+    /// The scan-codes received from the keyboard that were not processed yet.
+    scancode_buffer: RingBuffer<u8, SCANCODE_BUFFER_LEN>,
+    /// When scan-codes were dropped because the buffer was full, the number of buffered
+    /// scan-codes that were received before the last of them.
     ///
-    /// This is a bounded queue.
-    scancode_buffer: ArrayVec<u8, 8>,
+    /// The keys that are held are released once these are processed, as their releases may
+    /// have been dropped.
+    scancode_gap: Option<usize>,
 
     layout: layouts::Qwerty,
+    /// The scan-code set sent by the keyboard.
+    scancode_set: ScancodeSet,
+    /// Converts set 2 scan-codes into set 1 scan-codes for the layout.
+    set2: layouts::Set2,
+
+    /// The style of the cursor drawn on the command-line.
+    cursor_style: CursorStyle,
+    /// Whether the cursor should blink.
+    cursor_blinking: bool,
+    /// Whether the cursor is currently in the "on" phase of its blinking cycle.
+    cursor_phase: bool,
+    /// Whether the cursor should be drawn at all.
+    cursor_enabled: bool,
+
+    /// The state of the pager, if the output of the terminal is currently being paged.
+    pager: Option<Pager>,
+    /// The output kept while it is redirected to a file, waiting to be written to it.
+    redirected: KeptOutput<REDIRECT_LEN>,
+    /// The output kept while capturing, waiting to be taken.
+    captured: KeptOutput<CAPTURE_LEN>,
+
+    /// A bunch of bytes that have been received from the mouse.
+    ///
+    /// This is a bounded queue.
+    mouse_buffer: ArrayVec<u8, 24>,
+    /// Assembles the bytes received from the mouse into packets.
+    mouse: mouse::Decoder,
+    /// The position of the mouse pointer, in units of [`POINTER_SCALE`] per cell.
+    pointer: (u32, u32),
+    /// The buttons of the mouse that were pressed in the last packet.
+    buttons: Buttons,
+    /// Whether the cell under the mouse pointer is currently drawn with inverted colors.
+    pointer_drawn: bool,
+    /// The region of the screen currently selected with the mouse.
+    selection: Option<Selection>,
+    /// Whether the cells of the selection are currently drawn with inverted colors.
+    selection_drawn: bool,
+    /// The text that was last copied from the screen.
+    clipboard: ArrayVec<u8, CLIPBOARD_LEN>,
+    /// The lines that went past the top of the screen.
+    scrollback: Scrollback,
+    /// The actions bound to key chords.
+    keymap: Keymap,
+}
+
+/// Output of the terminal kept in memory instead of being displayed.
+struct KeptOutput<const N: usize> {
+    /// Whether the output is currently kept.
+    active: bool,
+    /// Whether some of the output was dropped because `bytes` was full.
+    truncated: bool,
+    /// The output kept so far, waiting to be taken.
+    bytes: ArrayVec<u8, N>,
+}
+
+impl<const N: usize> KeptOutput<N> {
+    /// Creates a new, inactive [`KeptOutput`] instance.
+    const fn new() -> Self {
+        Self {
+            active: false,
+            truncated: false,
+            bytes: ArrayVec::new(),
+        }
+    }
+
+    /// Starts keeping the output, dropping what was kept before.
+    fn begin(&mut self) {
+        self.active = true;
+        self.truncated = false;
+        self.bytes.clear();
+    }
+
+    /// Stops keeping the output, and returns whether some of it was dropped.
+    fn end(&mut self) -> bool {
+        self.active = false;
+        self.truncated
+    }
+
+    /// Adds a byte to the output.
+    fn push(&mut self, byte: u8) {
+        if self.bytes.try_push(byte).is_err() {
+            self.truncated = true;
+        }
+    }
+
+    /// Moves the beginning of the output to `buf`, and returns its length.
+    fn take(&mut self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.bytes.len());
+        buf[..count].copy_from_slice(&self.bytes[..count]);
+        self.bytes.remove_range(..count);
+        count
+    }
+}
+
+/// The state of the pager of the terminal.
+///
+/// While the pager is active, the terminal stops after each screenful of output and waits
+/// for the user to request more.
+struct Pager {
+    /// The number of lines that were scrolled since the last pause.
+    lines: u32,
+    /// Whether the user asked to discard the rest of the output.
+    quit: bool,
+    /// When non-empty, only the lines that contain this pattern are displayed.
+    filter: ArrayVec<u8, MAX_FILTER_LEN>,
+    /// The line currently being written, when a filter is set.
+    ///
+    /// Lines are only displayed once they are complete, as the filter needs to see the whole
+    /// line before deciding whether it should be displayed.
+    line: ArrayVec<(VgaChar, Color), MAX_FILTERED_LINE_LEN>,
+}
+
+/// The style of the cursor drawn by the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorStyle {
+    /// The cell under the cursor is drawn with inverted colors.
+    Block,
+    /// An underline is drawn under the cell. This uses the hardware cursor of the VGA.
+    Underline,
+}
+
+impl CursorStyle {
+    /// Returns the name of the cursor style.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Underline => "underline",
+        }
+    }
 }
 
 impl Terminal {
@@ -41,34 +219,75 @@ impl Terminal {
         Self {
             screen,
             cursor: 0,
-            foreground: Color::White,
+            foreground: Theme::DEFAULT.foreground,
+            lossy: true,
+            theme: &Theme::DEFAULT,
 
-            cmdline: ArrayVec::new(),
-            cmdline_cursor: 0,
+            prompt: ArrayVec::new(),
+            tty: Tty::new(),
 
-            scancode_buffer: ArrayVec::new(),
+            scancode_buffer: RingBuffer::new(),
+            scancode_gap: None,
 
             layout: layouts::Qwerty::new(),
+            scancode_set: ScancodeSet::Set1,
+            set2: layouts::Set2::new(),
+
+            cursor_style: CursorStyle::Block,
+            cursor_blinking: true,
+            cursor_phase: true,
+            cursor_enabled: true,
+
+            pager: None,
+            redirected: KeptOutput::new(),
+            captured: KeptOutput::new(),
+
+            mouse_buffer: ArrayVec::new(),
+            mouse: mouse::Decoder::new(Protocol::Standard),
+            pointer: (0, 0),
+            buttons: Buttons::empty(),
+            pointer_drawn: false,
+            selection: None,
+            selection_drawn: false,
+            clipboard: ArrayVec::new(),
+            scrollback: Scrollback::new(),
+            keymap: Keymap::new(),
         }
     }
 
     /// Re-initializes the terminal.
     pub fn reset(&mut self) {
-        self.cmdline.clear();
+        self.restore_screen();
+        self.tty.editor_mut().clear();
         self.cursor = 0;
-        self.screen.buffer_mut().fill(CLEAR_VALUE);
-        vga::cursor_move(0, HEIGHT - 1);
+        let blank = self.blank();
+        fill_u16(self.screen.buffer_mut(), blank);
+        self.refresh_cmdline();
+
+        if let Some(pager) = &mut self.pager {
+            pager.lines = 0;
+        }
     }
 
+    /// Clears the command-line, leaving only the prompt.
     pub fn clear_cmdline(&mut self) {
-        self.cmdline.clear();
-        self.cmdline_cursor = 0;
+        self.tty.editor_mut().clear();
+        self.refresh_cmdline();
+    }
 
-        let w = WIDTH as usize;
-        let h = HEIGHT as usize;
-        self.screen.buffer_mut()[w * (h - 1)..].fill(CLEAR_VALUE);
+    /// Sets the prompt displayed before the command-line.
+    ///
+    /// If the prompt is too long, it is truncated.
+    pub fn set_prompt(&mut self, prompt: &[u8]) {
+        self.prompt = ArrayVec::from_slice_truncated(prompt);
 
-        vga::cursor_move(0, HEIGHT - 1);
+        // Make sure that the command-line still fits on the screen. One cell is always kept
+        // free at the end of the line for the cursor.
+        self.tty
+            .editor_mut()
+            .set_max_len(WIDTH as usize - self.prompt.len() - 1);
+
+        self.refresh_cmdline();
     }
 
     /// Scrolls the content of the terminal up by one line.
@@ -76,8 +295,14 @@ impl Terminal {
         let w = WIDTH as usize;
         let h = HEIGHT as usize;
 
+        self.restore_screen();
+        self.scrollback.push(&self.screen.buffer()[..w]);
         self.screen.buffer_mut().copy_within(w..w * (h - 1), 0);
-        self.screen.buffer_mut()[w * (h - 2)..w * (h - 1)].fill(CLEAR_VALUE);
+        let blank = self.blank();
+        fill_u16(
+            &mut self.screen.buffer_mut()[w * (h - 2)..w * (h - 1)],
+            blank,
+        );
     }
 
     /// Inserts a line feed.
@@ -85,26 +310,322 @@ impl Terminal {
     /// This function does not necessarily scroll the terminal immediately. It only
     /// buffers the new line once for the next time a character is written.
     pub fn insert_linefeed(&mut self) {
+        match &self.pager {
+            Some(pager) if pager.quit => (),
+            Some(pager) if !pager.filter.is_empty() => self.flush_filtered_line(true),
+            _ => self.put_linefeed(),
+        }
+    }
+
+    /// Writes a character to the terminal.
+    pub fn write_vga_char(&mut self, c: VgaChar) {
+        let fg = self.foreground;
+        match &mut self.pager {
+            Some(pager) if pager.quit => (),
+            Some(pager) if !pager.filter.is_empty() => {
+                // Characters that do not fit in the line buffer are dropped.
+                let _ = pager.line.try_push((c, fg));
+            }
+            _ => self.put_char(c, fg),
+        }
+    }
+
+    /// Writes a sequence of characters to the terminal.
+    ///
+    /// Unlike calling [`write_vga_char`](Self::write_vga_char) for each of them, whole rows are
+    /// written to the screen at once.
+    pub fn write_vga_chars(&mut self, chars: impl Iterator<Item = VgaChar>) {
+        if self.writes_to_screen() {
+            self.put_row(chars);
+        } else {
+            chars.for_each(|c| self.write_vga_char(c));
+        }
+    }
+
+    /// Writes raw bytes to the terminal, as with [`write_vga_chars`](Self::write_vga_chars).
+    ///
+    /// Line feeds start a new line, and bytes that cannot be displayed are replaced by `?`.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for (i, line) in bytes.split(|&b| b == b'\n').enumerate() {
+            if i != 0 {
+                self.insert_linefeed();
+            }
+            let chars = line
+                .iter()
+                .map(|&b| VgaChar::from_u8(b).unwrap_or(VgaChar::QUESTION));
+            self.write_vga_chars(chars);
+        }
+    }
+
+    /// Returns whether characters are written directly to the screen, rather than to the
+    /// filter of the pager, a redirection or a capture.
+    fn writes_to_screen(&self) -> bool {
+        !self.redirected.active
+            && !self.captured.active
+            && self
+                .pager
+                .as_ref()
+                .map_or(true, |pager| !pager.quit && pager.filter.is_empty())
+    }
+
+    /// Like [`put_char`](Self::put_char) for each character, but composes the cells of each row
+    /// in a buffer and copies them to the screen at once.
+    ///
+    /// This must only be called when [`writes_to_screen`](Self::writes_to_screen) is true.
+    /// Returns `false` if the user asked the pager to discard the rest of the output.
+    fn put_row(&mut self, chars: impl Iterator<Item = VgaChar>) -> bool {
+        let (fg, bg) = (self.foreground, self.theme.background);
+        let mut row = [0u16; WIDTH as usize];
+        let mut len = 0;
+
+        for c in chars {
+            if self.cursor == WIDTH {
+                self.flush_row(&row[..len]);
+                len = 0;
+                self.cursor = 0;
+                self.new_line();
+                if self.pager.as_ref().is_some_and(|p| p.quit) {
+                    return false;
+                }
+            }
+
+            row[len] = vga::cell(c, fg, bg);
+            len += 1;
+            self.cursor += 1;
+        }
+
+        self.flush_row(&row[..len]);
+        true
+    }
+
+    /// Copies the cells composed by [`put_row`](Self::put_row) to the screen, right before the
+    /// cursor.
+    fn flush_row(&mut self, cells: &[u16]) {
+        if cells.is_empty() {
+            return;
+        }
+        self.restore_screen();
+        self.screen
+            .write_row(self.cursor - cells.len() as u32, HEIGHT - 2, cells);
+    }
+
+    /// Like [`insert_linefeed`](Self::insert_linefeed), but bypasses the filter of the pager.
+    fn put_linefeed(&mut self) {
+        if self.redirected.active {
+            self.redirected.push(b'\n');
+            return;
+        }
+        if self.captured.active {
+            self.captured.push(b'\n');
+            return;
+        }
+
         if self.cursor == WIDTH {
-            self.scroll_once();
+            self.new_line();
         }
 
         self.cursor = WIDTH;
     }
 
-    /// Writes a character to the terminal.
-    pub fn write_vga_char(&mut self, c: VgaChar) {
+    /// Like [`write_vga_char`](Self::write_vga_char), but bypasses the filter of the pager.
+    fn put_char(&mut self, c: VgaChar, fg: Color) {
+        if self.redirected.active {
+            self.redirected.push(c.as_u8());
+            return;
+        }
+        if self.captured.active {
+            self.captured.push(c.as_u8());
+            return;
+        }
+
         if self.cursor == WIDTH {
             self.cursor = 0;
-            self.scroll_once();
+            self.new_line();
         }
 
+        if self.pager.as_ref().is_some_and(|p| p.quit) {
+            return;
+        }
+
+        self.restore_screen();
         self.screen
-            .putc(c, self.cursor, HEIGHT - 2, self.foreground, Color::Black);
+            .putc(c, self.cursor, HEIGHT - 2, fg, self.theme.background);
 
         self.cursor += 1;
     }
 
+    /// Starts a new line, pausing first if the pager is active and a screenful of output has
+    /// been written since the last pause.
+    fn new_line(&mut self) {
+        if self.pager.as_ref().is_some_and(|p| p.lines >= HEIGHT - 1) {
+            self.wait_for_more();
+        }
+
+        match &mut self.pager {
+            Some(pager) if pager.quit => return,
+            Some(pager) => pager.lines += 1,
+            None => (),
+        }
+
+        self.scroll_once();
+    }
+
+    /// Starts paging the output of the terminal.
+    ///
+    /// When `filter` is non-empty, only the lines that contain it are displayed. The pager
+    /// remains active until [`end_paging`](Self::end_paging) is called.
+    pub fn begin_paging(&mut self, filter: &[u8]) {
+        self.pager = Some(Pager {
+            lines: 0,
+            quit: false,
+            filter: ArrayVec::from_slice_truncated(filter),
+            line: ArrayVec::new(),
+        });
+    }
+
+    /// Stops paging the output of the terminal.
+    ///
+    /// If a line was being written and matches the filter of the pager, it is displayed.
+    pub fn end_paging(&mut self) {
+        if self.pager.as_ref().is_some_and(|p| !p.filter.is_empty()) {
+            self.flush_filtered_line(false);
+        }
+        self.pager = None;
+    }
+
+    /// Keeps the output of the terminal in memory, to be written to a file, until
+    /// [`end_redirect`](Self::end_redirect) is called.
+    ///
+    /// Characters are kept with the code page of the VGA, which matches ASCII. The filter of
+    /// the pager still applies. Nothing is written to the file while the terminal is locked,
+    /// as writing may need locks that the command holds: the owner of the redirection writes
+    /// the output with [`take_redirected`](Self::take_redirected) once the command is done.
+    pub fn begin_redirect(&mut self) {
+        self.redirected.begin();
+    }
+
+    /// Displays the output of the terminal again.
+    ///
+    /// Returns whether some of the redirected output was dropped, because more than
+    /// [`REDIRECT_LEN`] bytes were written.
+    pub fn end_redirect(&mut self) -> bool {
+        self.redirected.end()
+    }
+
+    /// Moves the beginning of the redirected output to `buf`, and returns its length.
+    pub fn take_redirected(&mut self, buf: &mut [u8]) -> usize {
+        self.redirected.take(buf)
+    }
+
+    /// Keeps the output of the terminal in memory instead of displaying it, until
+    /// [`end_capture`](Self::end_capture) is called.
+    ///
+    /// Redirecting the output to a file takes precedence, and the filter of the pager still
+    /// applies. The captured output is read with [`take_captured`](Self::take_captured), and
+    /// what does not fit in memory is dropped.
+    pub fn begin_capture(&mut self) {
+        self.captured.begin();
+    }
+
+    /// Displays the output of the terminal again.
+    ///
+    /// Returns whether some of the captured output was dropped.
+    pub fn end_capture(&mut self) -> bool {
+        self.captured.end()
+    }
+
+    /// Moves the beginning of the captured output to `buf`, and returns its length.
+    pub fn take_captured(&mut self, buf: &mut [u8]) -> usize {
+        self.captured.take(buf)
+    }
+
+    /// Displays the line buffered by the pager if it matches its filter, and clears it.
+    ///
+    /// When `linefeed` is set, a line feed is inserted after the line.
+    fn flush_filtered_line(&mut self, linefeed: bool) {
+        let Some(pager) = &mut self.pager else {
+            return;
+        };
+
+        let line = core::mem::take(&mut pager.line);
+        let filter = &pager.filter;
+        let matches = line.windows(filter.len()).any(|w| {
+            w.iter()
+                .zip(filter.iter())
+                .all(|(&(c, _), &f)| c.as_u8() == f)
+        });
+
+        if !matches {
+            return;
+        }
+
+        for &(c, fg) in line.iter() {
+            self.put_char(c, fg);
+        }
+        if linefeed {
+            self.put_linefeed();
+        }
+    }
+
+    /// Displays a message on the command-line and waits until the user asks for more output.
+    ///
+    /// # Notes
+    ///
+    /// This function polls the PS/2 controller directly. Because the terminal is only accessed
+    /// through a lock, interrupts are disabled and the keyboard interrupt handler cannot
+    /// compete for the scan-codes.
+    fn wait_for_more(&mut self) {
+        const MESSAGE: &str = "-- more -- (space: next page, enter: next line, q: quit)";
+
+        self.restore_screen();
+
+        let w = WIDTH as usize;
+        let h = HEIGHT as usize;
+        let blank = (self.theme.status_background as u16) << 12;
+        fill_u16(&mut self.screen.buffer_mut()[w * (h - 1)..], blank);
+        for (x, c) in MESSAGE.chars().enumerate() {
+            self.screen.putc(
+                VgaChar::from_char(c).unwrap_or(VgaChar::QUESTION),
+                x as u32,
+                HEIGHT - 1,
+                self.theme.status_foreground,
+                self.theme.status_background,
+            );
+        }
+        vga::cursor_hide();
+
+        let lines = loop {
+            while !ps2::is_output_buffer_full() {
+                pause();
+            }
+
+            // Bytes sent by the mouse are kept for later.
+            let status = ps2::status();
+            let byte = ps2::read_data();
+            if ps2::is_mouse_data(status) {
+                let _ = self.mouse_buffer.try_push(byte);
+                continue;
+            }
+
+            match self.decode(byte) {
+                Some(Key::Char(' ')) => break Some(0),
+                Some(Key::Char('\n')) => break Some(HEIGHT - 2),
+                Some(Key::Char('q' | 'Q')) => break None,
+                Some(Key::Char('c' | 'C')) if self.layout.modifiers().has_control() => break None,
+                _ => (),
+            }
+        };
+
+        if let Some(pager) = &mut self.pager {
+            match lines {
+                Some(lines) => pager.lines = lines,
+                None => pager.quit = true,
+            }
+        }
+
+        self.refresh_cmdline();
+    }
+
     /// Sets the foreground color of the terminal.
     ///
     /// This only affects subsequent characters written to the terminal.
@@ -113,25 +634,177 @@ impl Terminal {
         self.foreground = color;
     }
 
+    /// Resets the foreground color of the terminal to the default color of the theme.
+    #[inline(always)]
+    pub fn reset_color(&mut self) {
+        self.foreground = self.theme.foreground;
+    }
+
+    /// Sets whether characters that cannot be displayed are replaced.
+    ///
+    /// In lossy mode (the default), characters that are missing from the VGA character set are
+    /// transliterated to similar characters when possible (`’` becomes `'`, `…` becomes `...`),
+    /// or replaced by `■` otherwise. When the lossy mode is disabled, writing such a character
+    /// fails with [`core::fmt::Error`].
+    #[inline(always)]
+    pub fn set_lossy(&mut self, lossy: bool) {
+        self.lossy = lossy;
+    }
+
+    /// Returns the theme currently used by the terminal.
+    #[inline(always)]
+    pub fn theme(&self) -> &'static Theme {
+        self.theme
+    }
+
+    /// Changes the theme of the terminal.
+    ///
+    /// The content already on screen is re-colored: cells that used the default colors of the
+    /// previous theme now use the colors of the new one.
+    pub fn set_theme(&mut self, theme: &'static Theme) {
+        let old = self.theme;
+
+        self.restore_screen();
+        for cell in self.screen.buffer_mut() {
+            let mut fg = ((*cell >> 8) & 0xF) as u8;
+            let mut bg = ((*cell >> 12) & 0xF) as u8;
+
+            if fg == old.foreground as u8 {
+                fg = theme.foreground as u8;
+            }
+            if bg == old.background as u8 {
+                bg = theme.background as u8;
+            }
+
+            *cell = (*cell & 0xFF) | (fg as u16) << 8 | (bg as u16) << 12;
+        }
+
+        if self.foreground == old.foreground {
+            self.foreground = theme.foreground;
+        }
+        self.theme = theme;
+        self.refresh_cmdline();
+    }
+
+    /// Returns the value of an empty cell, using the colors of the current theme.
+    #[inline]
+    fn blank(&self) -> u16 {
+        (self.theme.background as u16) << 12 | (self.theme.foreground as u16) << 8
+    }
+
     /// Refreshes the written content of the command-line.
     ///
     /// This function should be called whenever the command-line is modified.
     pub fn refresh_cmdline(&mut self) {
-        for (x, &c) in self.cmdline.iter().enumerate() {
+        self.restore_screen();
+        for (x, &c) in self.prompt.iter().enumerate() {
+            self.screen.putc(
+                VgaChar::from_char(c as char).unwrap_or(VgaChar::QUESTION),
+                x as u32,
+                HEIGHT - 1,
+                self.theme.prompt,
+                self.theme.background,
+            );
+        }
+        let start = self.prompt.len();
+        let line = self.tty.echoed_line();
+        for (x, &c) in line.iter().enumerate() {
             self.screen.putc(
                 VgaChar::from_char(c as char)
                     .expect("found an invalid VGA character in the command line"),
-                x as u32,
+                (start + x) as u32,
                 HEIGHT - 1,
-                Color::White,
-                Color::Black,
+                self.theme.foreground,
+                self.theme.background,
             );
         }
         let w = WIDTH as usize;
         let h = HEIGHT as usize;
-        let len = self.cmdline.len();
-        self.screen.buffer_mut()[w * (h - 1) + len..].fill(CLEAR_VALUE);
-        vga::cursor_move(self.cmdline_cursor as u32, HEIGHT - 1);
+        let len = start + line.len();
+        let blank = self.blank();
+        fill_u16(&mut self.screen.buffer_mut()[w * (h - 1) + len..], blank);
+
+        // Typing something should make the cursor visible immediately, even if it was in the
+        // "off" phase of its blinking cycle.
+        self.cursor_phase = true;
+        self.draw_cursor();
+    }
+
+    /// Draws the cursor at its current position on the command-line.
+    fn draw_cursor(&mut self) {
+        let line = self.tty.echoed_line();
+        let cursor = self.tty.editor().cursor().min(line.len());
+        let x = (self.prompt.len() + cursor) as u32;
+        let c = line
+            .get(cursor)
+            .and_then(|&c| VgaChar::from_char(c as char))
+            .unwrap_or(VgaChar::SPACE);
+
+        let visible = self.cursor_enabled && (self.cursor_phase || !self.cursor_blinking);
+        let fg = self.theme.foreground;
+        let bg = self.theme.background;
+
+        match self.cursor_style {
+            CursorStyle::Block if visible => {
+                self.screen.putc(c, x, HEIGHT - 1, bg, fg);
+            }
+            _ => self.screen.putc(c, x, HEIGHT - 1, fg, bg),
+        }
+
+        // The mouse pointer remains visible over the cursor.
+        if self.pointer_drawn && self.pointer_cell() == (x, HEIGHT - 1) {
+            self.invert_cell(x, HEIGHT - 1);
+        }
+
+        if visible && self.cursor_style == CursorStyle::Underline {
+            vga::cursor_show(14, 15);
+            vga::cursor_move(x, HEIGHT - 1);
+        } else {
+            vga::cursor_hide();
+        }
+    }
+
+    /// Sets the style of the cursor.
+    pub fn set_cursor_style(&mut self, style: CursorStyle) {
+        self.cursor_style = style;
+        self.draw_cursor();
+    }
+
+    /// Returns the current style of the cursor.
+    #[inline(always)]
+    pub fn cursor_style(&self) -> CursorStyle {
+        self.cursor_style
+    }
+
+    /// Sets whether the cursor should blink.
+    pub fn set_cursor_blinking(&mut self, blinking: bool) {
+        self.cursor_blinking = blinking;
+        self.cursor_phase = true;
+        self.draw_cursor();
+    }
+
+    /// Returns whether the cursor is currently blinking.
+    #[inline(always)]
+    pub fn cursor_blinking(&self) -> bool {
+        self.cursor_blinking
+    }
+
+    /// Sets whether the cursor should be drawn at all.
+    pub fn set_cursor_enabled(&mut self, enabled: bool) {
+        self.cursor_enabled = enabled;
+        self.draw_cursor();
+    }
+
+    /// Advances the blinking cycle of the cursor.
+    ///
+    /// This function is meant to be called periodically by a timer callback.
+    pub fn blink_cursor(&mut self) {
+        if !self.cursor_blinking || !self.cursor_enabled {
+            return;
+        }
+
+        self.cursor_phase = !self.cursor_phase;
+        self.draw_cursor();
     }
 
     /// Inserts a new character into the command-line.
@@ -140,17 +813,11 @@ impl Terminal {
     ///
     /// This function returns whether the character could be inserted into the command-line.
     pub fn type_in(&mut self, c: u8) -> bool {
-        if self
-            .cmdline
-            .try_insert(self.cmdline_cursor as usize, c)
-            .is_err()
-        {
+        if !self.tty.editor_mut().insert(c) {
             return false;
         }
 
-        self.cmdline_cursor += 1;
         self.refresh_cmdline();
-
         true
     }
 
@@ -158,22 +825,9 @@ impl Terminal {
     ///
     /// When `bulk` is set, a whole word is removed.
     pub fn type_out(&mut self, bulk: bool) {
-        let cur = self.cmdline_cursor as usize;
-
-        if cur == 0 {
-            return;
+        if self.tty.editor_mut().delete(bulk) {
+            self.refresh_cmdline();
         }
-
-        let start = if bulk {
-            find_start_of_last_word(&self.cmdline[..cur])
-        } else {
-            cur - 1
-        };
-
-        self.cmdline.remove_range(start..cur);
-        self.cmdline_cursor -= (cur - start) as u8;
-
-        self.refresh_cmdline();
     }
 
     /// Caches the provided scan-code for later processing.
@@ -187,68 +841,444 @@ impl Terminal {
     /// it fails when the internal buffer is full.
     #[must_use = "the function might've failed to take the scan-code"]
     pub fn buffer_scancode(&mut self, scancode: u8) -> bool {
-        self.scancode_buffer.try_push(scancode).is_ok()
+        if self.scancode_buffer.try_push(scancode).is_ok() {
+            return true;
+        }
+        self.scancode_gap = Some(self.scancode_buffer.len());
+        false
+    }
+
+    /// Returns whether scan-codes were dropped since the buffered ones were last processed.
+    #[inline]
+    pub fn has_dropped_scancodes(&self) -> bool {
+        self.scancode_gap.is_some()
+    }
+
+    /// Pops the oldest buffered scan-code.
+    ///
+    /// When scan-codes were dropped right before it, the keys that are held are released
+    /// first.
+    fn pop_scancode(&mut self) -> Option<u8> {
+        if self.scancode_gap == Some(0) {
+            self.scancode_gap = None;
+            self.layout.release_keys();
+            self.set2 = layouts::Set2::new();
+        }
+
+        let scancode = self.scancode_buffer.pop()?;
+        if let Some(gap) = &mut self.scancode_gap {
+            *gap -= 1;
+        }
+        Some(scancode)
     }
 
     /// Takes a scan-code and processes it.
     ///
     /// This function ignores the internal buffer and processes the scan-code immediately.
     pub fn take_scancode(&mut self, scancode: u8, readline: &mut dyn ReadLine) {
-        let Some(c) = self.layout.advance(scancode) else {
+        let Some(key) = self.decode(scancode) else {
             return;
         };
 
-        // Process special characters. Those are used to control the terminal itself.
-        match c {
-            '\x08' => self.type_out(self.layout.modifiers().has_control()),
-            'l' | 'L' if self.layout.modifiers().has_control() => self.reset(),
-            'c' | 'C' if self.layout.modifiers().has_control() => self.clear_cmdline(),
-            '\n' => {
+        let chord = Chord::from_key(key, self.layout.modifiers());
+        if let Some(action) = self.keymap.lookup(chord) {
+            return self.perform(action, readline);
+        }
+
+        let c = match key {
+            Key::Char(c) => c,
+            key => return readline.special_key(self, key),
+        };
+
+        // The line discipline only deals with bytes, so the chords that hold **CONTROL** are
+        // sent as control characters.
+        let control = self.layout.modifiers().has_control();
+        let byte = match c {
+            '\x08' if control => DELETE_WORD,
+            c if control && c.is_ascii_alphabetic() => c.to_ascii_uppercase() as u8 & 0x1F,
+            c => c as u8,
+        };
+
+        match self.tty.receive(byte) {
+            LineEvent::Edited => self.refresh_cmdline(),
+            LineEvent::Submitted => {
+                if !self.tty.modes().contains(TtyModes::CANONICAL)
+                    && self.tty.modes().contains(TtyModes::ECHO)
+                {
+                    let _ = self.write_char(c);
+                }
                 readline.submit(self);
-                self.clear_cmdline();
+                self.refresh_cmdline();
             }
-            '\t' => readline.auto_complete(self),
-            _ => {
-                self.type_in(c as u8);
+            LineEvent::Complete => readline.auto_complete(self),
+            LineEvent::EndOfFile => readline.end_of_file(self),
+            LineEvent::Ignored => (),
+        }
+    }
+
+    /// Performs an action bound to a key chord.
+    ///
+    /// The actions that do not concern the terminal itself are forwarded to `readline`.
+    fn perform(&mut self, action: Action, readline: &mut dyn ReadLine) {
+        match action {
+            Action::ClearScreen => self.reset(),
+            Action::ClearLine => self.clear_cmdline(),
+            Action::Paste => self.paste(),
+            Action::Complete => readline.auto_complete(self),
+            _ => readline.action(self, action),
+        }
+    }
+
+    /// Returns the table that maps key chords to actions.
+    #[inline(always)]
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Returns an exclusive reference to the table that maps key chords to actions.
+    #[inline(always)]
+    pub fn keymap_mut(&mut self) -> &mut Keymap {
+        &mut self.keymap
+    }
+
+    /// Writes the content of the screen to `out`, using ANSI escape sequences to reproduce the
+    /// colors of each cell.
+    ///
+    /// Escape sequences are only emitted when the colors change.
+    pub fn screenshot(&self, out: &mut dyn Write) -> core::fmt::Result {
+        let mut last = None;
+
+        for row in self.screen.buffer().chunks(WIDTH as usize) {
+            for &cell in row {
+                let colors = (cell >> 8) as u8;
+                if last != Some(colors) {
+                    let fg = colors & 0xF;
+                    let bg = colors >> 4;
+                    write!(out, "\x1B[{};{}m", ansi_color(fg, 30), ansi_color(bg, 40))?;
+                    last = Some(colors);
+                }
+
+                let c = VgaChar::from_u8(cell as u8).map_or(' ', VgaChar::as_char);
+                out.write_char(if c == '\0' { ' ' } else { c })?;
             }
+
+            // The colors are reset before the end of the line so that the background does not
+            // bleed into the rest of the line in the host terminal.
+            out.write_str("\x1B[0m\r\n")?;
+            last = None;
         }
+
+        Ok(())
+    }
+
+    /// Sets the scan-code set sent by the keyboard.
+    pub fn set_scancode_set(&mut self, set: ScancodeSet) {
+        self.scancode_set = set;
+        self.set2 = layouts::Set2::new();
+    }
+
+    /// Feeds a byte received from the keyboard to the layout, returning the key it produces,
+    /// if any.
+    fn decode(&mut self, scancode: u8) -> Option<Key> {
+        let scancode = match self.scancode_set {
+            ScancodeSet::Set1 => scancode,
+            ScancodeSet::Set2 => self.set2.translate(scancode)?,
+        };
+
+        self.layout.advance(scancode)
     }
 
-    /// Processes the scan-codes that were buffered so far.
+    /// Processes the scan-codes that were buffered so far, up to [`SCANCODE_BUDGET`] of them.
+    ///
+    /// The others are kept for the next call, so that a burst of input does not keep the
+    /// caller from its other work. [`has_buffered_input`](Self::has_buffered_input) remains
+    /// true until they are all processed.
     pub fn take_buffered_scancodes(&mut self, readline: &mut dyn ReadLine) {
-        for i in 0..self.scancode_buffer.len() {
-            let scancode = unsafe { *self.scancode_buffer.get_unchecked(i) };
+        for _ in 0..SCANCODE_BUDGET {
+            let Some(scancode) = self.pop_scancode() else {
+                break;
+            };
             self.take_scancode(scancode, readline);
         }
-        self.scancode_buffer.clear();
     }
 
-    /// Returns an exclusive reference to the command-line buffer.
+    /// Decodes the scan-codes that were buffered so far, and returns whether one of them asks
+    /// to quit (`q`, or **CONTROL-C**).
+    ///
+    /// The other keys are discarded. This is meant for commands that keep running until they
+    /// are told to stop.
+    pub fn take_quit_request(&mut self) -> bool {
+        let mut quit = false;
+        while let Some(scancode) = self.pop_scancode() {
+            let control = self.layout.modifiers().has_control();
+            match self.decode(scancode) {
+                Some(Key::Char('q' | 'Q')) => quit = true,
+                Some(Key::Char('c' | 'C')) if control => quit = true,
+                _ => (),
+            }
+        }
+        quit
+    }
+
+    /// Returns whether scan-codes or bytes from the mouse were buffered and not processed yet.
+    pub fn has_buffered_input(&self) -> bool {
+        !self.scancode_buffer.is_empty() || !self.mouse_buffer.is_empty()
+    }
+
+    /// Caches the provided byte received from the mouse for later processing.
+    ///
+    /// Like [`buffer_scancode`](Self::buffer_scancode), this function is meant to be used
+    /// within the interrupt handler.
+    ///
+    /// # Returns
+    ///
+    /// This function returns whether the byte could be taken into account.
+    #[must_use = "the function might've failed to take the byte"]
+    pub fn buffer_mouse_byte(&mut self, byte: u8) -> bool {
+        self.mouse_buffer.try_push(byte).is_ok()
+    }
+
+    /// Returns the LEDs of the keyboard that should be lit, according to the state of the lock
+    /// keys.
+    pub fn keyboard_leds(&self) -> keyboard::Leds {
+        let modifiers = self.layout.modifiers();
+        let mut leds = keyboard::Leds::empty();
+        leds.set(keyboard::Leds::NUM_LOCK, modifiers.num_locked());
+        leds.set(
+            keyboard::Leds::CAPS_LOCK,
+            modifiers.intersects(layouts::Modifiers::CAPS_LOCK),
+        );
+        leds
+    }
+
+    /// Sets the format of the packets sent by the mouse.
+    pub fn set_mouse_protocol(&mut self, protocol: Protocol) {
+        self.mouse = mouse::Decoder::new(protocol);
+    }
+
+    /// Processes the bytes received from the mouse that were buffered so far.
+    pub fn take_buffered_mouse_bytes(&mut self) {
+        for i in 0..self.mouse_buffer.len() {
+            let byte = unsafe { *self.mouse_buffer.get_unchecked(i) };
+            if let Some(packet) = self.mouse.advance(byte) {
+                self.take_mouse_packet(packet);
+            }
+        }
+        self.mouse_buffer.clear();
+    }
+
+    /// Moves the mouse pointer and updates the selection according to a packet sent by the
+    /// mouse.
+    ///
+    /// Dragging the mouse with its left button pressed selects a region of the screen, which
+    /// is copied to the clipboard when the button is released. The selection is rectangular
+    /// when **ALT** is held as the selection starts. The wheel scrolls through the lines that
+    /// went past the top of the screen.
+    fn take_mouse_packet(&mut self, packet: Packet) {
+        if self.pointer_drawn {
+            self.invert_cell(self.pointer_cell().0, self.pointer_cell().1);
+        }
+
+        if packet.dz != 0 {
+            self.clear_selection();
+            let offset = self
+                .scrollback
+                .offset()
+                .saturating_add_signed(-(packet.dz as isize) * WHEEL_LINES);
+            let rows = (WIDTH * (HEIGHT - 1)) as usize;
+            self.scrollback
+                .scroll_to(offset, &mut self.screen.buffer_mut()[..rows]);
+        }
+
+        // The vertical axis of the mouse points upwards.
+        let (x, y) = self.pointer;
+        self.pointer = (
+            x.saturating_add_signed(packet.dx as i32)
+                .min(WIDTH * POINTER_SCALE.0 - 1),
+            y.saturating_add_signed(-(packet.dy as i32))
+                .min(HEIGHT * POINTER_SCALE.1 - 1),
+        );
+
+        let pressed = packet.buttons.difference(self.buttons);
+        let released = self.buttons.difference(packet.buttons);
+        self.buttons = packet.buttons;
+
+        // The command-line cannot be selected.
+        let (x, y) = self.pointer_cell();
+        let y = y.min(HEIGHT - 2);
+
+        if pressed.contains(Buttons::LEFT) {
+            self.clear_selection();
+            let mode = if self.layout.modifiers().has_alt() {
+                SelectionMode::Rectangular
+            } else {
+                SelectionMode::Linear
+            };
+            self.selection = Some(Selection::new(x, y, mode));
+            self.toggle_selection();
+        } else if self.buttons.contains(Buttons::LEFT) && self.selection.is_some() {
+            self.toggle_selection();
+            if let Some(selection) = &mut self.selection {
+                selection.extend_to(x, y);
+            }
+            self.toggle_selection();
+        }
+
+        if released.contains(Buttons::LEFT) {
+            match self.selection {
+                // A simple click only clears the previous selection.
+                Some(selection) if selection.is_single_cell() => self.clear_selection(),
+                Some(selection) => self.copy(selection),
+                None => (),
+            }
+        }
+
+        let (x, y) = self.pointer_cell();
+        self.invert_cell(x, y);
+        self.pointer_drawn = true;
+    }
+
+    /// Returns the cell under the mouse pointer.
+    #[inline]
+    fn pointer_cell(&self) -> (u32, u32) {
+        (
+            self.pointer.0 / POINTER_SCALE.0,
+            self.pointer.1 / POINTER_SCALE.1,
+        )
+    }
+
+    /// Swaps the foreground and background colors of a cell.
+    fn invert_cell(&mut self, x: u32, y: u32) {
+        let cell = &mut self.screen.buffer_mut()[(y * WIDTH + x) as usize];
+        *cell = (*cell & 0xFF) | (*cell >> 4) & 0x0F00 | (*cell << 4) & 0xF000;
+    }
+
+    /// Inverts the colors of the cells of the selection, if any.
+    fn toggle_selection(&mut self) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+
+        for y in selection.rows() {
+            for x in selection.columns(y) {
+                self.invert_cell(x, y);
+            }
+        }
+        self.selection_drawn = !self.selection_drawn;
+    }
+
+    /// Removes the selection, restoring the colors of its cells.
+    fn clear_selection(&mut self) {
+        if self.selection_drawn {
+            self.toggle_selection();
+        }
+        self.selection = None;
+    }
+
+    /// Removes the mouse pointer and the selection from the screen, and scrolls the view back
+    /// to the bottom.
+    ///
+    /// This must be called before the content of the screen changes, as the inverted cells
+    /// would otherwise be moved or overwritten. The pointer is drawn again the next time the
+    /// mouse moves.
+    fn restore_screen(&mut self) {
+        if self.pointer_drawn {
+            self.invert_cell(self.pointer_cell().0, self.pointer_cell().1);
+            self.pointer_drawn = false;
+        }
+        if self.selection.is_some() {
+            self.clear_selection();
+        }
+        if self.scrollback.offset() != 0 {
+            let rows = (WIDTH * (HEIGHT - 1)) as usize;
+            self.scrollback
+                .scroll_to(0, &mut self.screen.buffer_mut()[..rows]);
+        }
+    }
+
+    /// Copies the text of the provided region of the screen to the clipboard.
+    ///
+    /// Trailing spaces are removed from every row, and rows are separated by line feeds.
+    fn copy(&mut self, selection: Selection) {
+        self.clipboard.clear();
+
+        for y in selection.rows() {
+            let row = &self.screen.buffer()[(y * WIDTH) as usize..][..WIDTH as usize];
+            let columns = selection.columns(y);
+            let text = &row[columns.start as usize..columns.end as usize];
+
+            let len = text
+                .iter()
+                .rposition(|&cell| !matches!(cell as u8, 0 | b' '))
+                .map_or(0, |i| i + 1);
+            for &cell in &text[..len] {
+                let c = match VgaChar::from_u8(cell as u8).map_or(' ', VgaChar::as_char) {
+                    '\0' => ' ',
+                    c if c == ' ' || c.is_ascii_graphic() => c,
+                    _ => '?',
+                };
+                let _ = self.clipboard.try_push(c as u8);
+            }
+
+            if y != *selection.rows().end() {
+                let _ = self.clipboard.try_push(b'\n');
+            }
+        }
+    }
+
+    /// Returns the content of the clipboard.
+    #[inline(always)]
+    pub fn clipboard(&self) -> &[u8] {
+        &self.clipboard
+    }
+
+    /// Types the content of the clipboard into the command-line.
+    ///
+    /// Line feeds are replaced by spaces. The text is truncated if it does not fit in the
+    /// command-line.
+    pub fn paste(&mut self) {
+        for i in 0..self.clipboard.len() {
+            let c = match self.clipboard[i] {
+                b'\n' => b' ',
+                c => c,
+            };
+            if !self.type_in(c) {
+                break;
+            }
+        }
+    }
+
+    /// Returns the line discipline of the keyboard input.
+    #[inline(always)]
+    pub fn tty(&self) -> &Tty {
+        &self.tty
+    }
+
+    /// Returns an exclusive reference to the line discipline of the keyboard input.
+    ///
+    /// [`refresh_cmdline`](Self::refresh_cmdline) must be called after the command-line is
+    /// modified.
     #[inline(always)]
-    pub fn cmdline_mut(&mut self) -> &mut ArrayVec<u8, { WIDTH as usize }> {
-        &mut self.cmdline
+    pub fn tty_mut(&mut self) -> &mut Tty {
+        &mut self.tty
     }
 
     /// Returns a shared reference to the command-line buffer.
     #[inline(always)]
     pub fn cmdline(&self) -> &[u8] {
-        &self.cmdline
+        self.tty.editor().line()
     }
 
     /// Returns the position of the command-line cursor.
     #[inline(always)]
     pub fn cmdline_cursor(&self) -> usize {
-        self.cmdline_cursor as usize
+        self.tty.editor().cursor()
     }
 
     /// Sets the position of the command-line cursor.
     #[inline(always)]
     pub fn set_cmdline_cursor(&mut self, pos: usize) {
-        if pos > self.cmdline.len() {
-            self.cmdline_cursor = self.cmdline.len() as u8;
-        } else {
-            self.cmdline_cursor = pos as u8;
-        }
+        self.tty.editor_mut().set_cursor(pos);
     }
 }
 
@@ -259,51 +1289,169 @@ impl Write for Terminal {
             return Ok(());
         }
 
-        let c = VgaChar::from_char(c).ok_or(core::fmt::Error)?;
-        self.write_vga_char(c);
+        vga_chars(c, self.lossy)
+            .ok_or(core::fmt::Error)?
+            .for_each(|c| self.write_vga_char(c));
         Ok(())
     }
 
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        s.chars().try_for_each(|c| self.write_char(c))?;
+        if !self.writes_to_screen() {
+            return s.chars().try_for_each(|c| self.write_char(c));
+        }
+
+        let lossy = self.lossy;
+        for (i, line) in s.split('\n').enumerate() {
+            if i != 0 {
+                self.put_linefeed();
+            }
+
+            let mut invalid = false;
+            let chars = line
+                .chars()
+                .map_while(|c| {
+                    let chars = vga_chars(c, lossy);
+                    invalid = chars.is_none();
+                    chars
+                })
+                .flatten();
+            if !self.put_row(chars) {
+                return Ok(());
+            }
+            if invalid {
+                return Err(core::fmt::Error);
+            }
+        }
+
         Ok(())
     }
 }
 
-/// The value used when clearing the terminal.
-const CLEAR_VALUE: u16 = 0x0F00;
-
-/// Returns the index of the first character of the last word.
+/// Returns the VGA characters that display `c`.
 ///
-/// If no word is found, 0 is returned.
-fn find_start_of_last_word(s: &[u8]) -> usize {
-    let mut i = s.len();
+/// Characters that are missing from the VGA character set are replaced as described in
+/// [`Terminal::set_lossy`], or `None` is returned if `lossy` is not set.
+fn vga_chars(c: char, lossy: bool) -> Option<impl Iterator<Item = VgaChar>> {
+    let (single, text) = match VgaChar::from_char(c) {
+        Some(c) => (Some(c), ""),
+        None if !lossy => return None,
+        None => match transliterate(c) {
+            Some(s) => (None, s),
+            None => (Some(VgaChar::SOLID_BLOCK), ""),
+        },
+    };
+    Some(
+        single
+            .into_iter()
+            .chain(text.chars().filter_map(VgaChar::from_char)),
+    )
+}
 
-    // Skip initial whitespaces.
-    while i > 0 {
-        i -= 1;
-        if s[i] != b' ' {
-            break;
-        }
+/// Returns the ANSI SGR parameter that selects the provided VGA color.
+///
+/// `base` is 30 for foreground colors and 40 for background colors.
+fn ansi_color(vga: u8, base: u8) -> u8 {
+    // SAFETY: the value is masked to 4 bits, and all 16 values are valid colors.
+    let index = unsafe { core::mem::transmute::<u8, Color>(vga & 0xF) }.ansi_index();
+    if index < 8 {
+        base + index
+    } else {
+        // Bright colors use 90 and 100 instead of 30 and 40.
+        base + 60 + (index - 8)
     }
+}
 
-    // Skip the last word.
-    while i > 0 {
-        i -= 1;
-        if s[i] == b' ' {
-            return i + 1;
-        }
-    }
+/// The number of scan-codes that can be buffered before they are processed.
+const SCANCODE_BUFFER_LEN: usize = 128;
+
+/// The maximum number of scan-codes processed by each call to
+/// [`Terminal::take_buffered_scancodes`].
+pub const SCANCODE_BUDGET: usize = 32;
+
+/// The maximum length of the filter of the pager.
+pub const MAX_FILTER_LEN: usize = 32;
+
+/// The maximum length of a line that can be filtered by the pager.
+///
+/// Longer lines are truncated.
+const MAX_FILTERED_LINE_LEN: usize = 3 * WIDTH as usize;
+
+/// The number of units the mouse must move by for the pointer to move to the next cell,
+/// horizontally and vertically.
+const POINTER_SCALE: (u32, u32) = (8, 16);
 
-    i
+/// The number of lines scrolled by each step of the mouse wheel.
+const WHEEL_LINES: isize = 3;
+
+/// The maximum length of the text in the clipboard.
+///
+/// This is enough to hold the whole screen, along with a line feed after each row.
+const CLIPBOARD_LEN: usize = (WIDTH as usize + 1) * HEIGHT as usize;
+
+/// The maximum length of the output kept by [`Terminal::begin_redirect`].
+///
+/// This is the size of the largest file of the RAM file-system.
+pub const REDIRECT_LEN: usize = 64 * 1024;
+
+/// The maximum length of the output kept by [`Terminal::begin_capture`].
+const CAPTURE_LEN: usize = 4096;
+
+/// The maximum length of the prompt displayed before the command-line.
+///
+/// This ensures that at least a few characters can always be typed in the command-line.
+const MAX_PROMPT_LEN: usize = WIDTH as usize / 2;
+
+/// Returns an ASCII approximation of a character that is missing from the VGA character set.
+///
+/// `None` is returned if no approximation is known.
+fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\r' | '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{FEFF}' => "",
+        '\t' => "    ",
+        '\u{00A0}' | '\u{2002}'..='\u{200A}' | '\u{202F}' => " ",
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => "'",
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => "\"",
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+        '\u{2026}' => "...",
+        '\u{2039}' => "<",
+        '\u{203A}' => ">",
+        '\u{21D2}' => "=>",
+        '\u{2260}' => "!=",
+        '×' => "x",
+        '©' => "(c)",
+        '®' => "(R)",
+        '™' => "TM",
+        '€' => "EUR",
+        'À' | 'Á' | 'Â' | 'Ã' => "A",
+        'È' | 'Ê' | 'Ë' => "E",
+        'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' => "O",
+        'Ù' | 'Ú' | 'Û' => "U",
+        'ã' => "a",
+        'õ' => "o",
+        'œ' => "oe",
+        'Œ' => "OE",
+        _ => return None,
+    })
 }
 
 /// Allows to customize the behavior of the terminal.
 #[allow(unused_variables)]
 pub trait ReadLine {
-    /// Called when the user submits the current command-line value.
+    /// Called when input is available to be read from the [`Tty`] of the terminal.
     fn submit(&mut self, term: &mut Terminal) {}
 
     /// Called when the user requests help for the current command-line value.
     fn auto_complete(&mut self, term: &mut Terminal) {}
+
+    /// Called when the user signals the end of the input on an empty command-line.
+    fn end_of_file(&mut self, term: &mut Terminal) {}
+
+    /// Called when the user presses a key that does not produce a character, and that is not
+    /// bound to any action.
+    fn special_key(&mut self, term: &mut Terminal, key: Key) {}
+
+    /// Called when the user presses a key chord bound to an action that the terminal does not
+    /// perform itself.
+    fn action(&mut self, term: &mut Terminal, action: Action) {}
 }
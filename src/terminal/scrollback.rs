@@ -0,0 +1,87 @@
+//! The lines that went past the top of the screen.
+
+use crate::drivers::vga::{HEIGHT, WIDTH};
+
+/// The number of lines kept in the scrollback buffer.
+pub const SCROLLBACK_LINES: usize = 256;
+
+/// The number of rows of the screen in which the output of the terminal is written.
+const ROWS: usize = HEIGHT as usize - 1;
+
+/// A line of the screen, as stored in the VGA buffer.
+type Line = [u16; WIDTH as usize];
+
+/// Remembers the lines that scrolled off the screen, so that they can be displayed again.
+pub struct Scrollback {
+    /// The lines, stored as a ring buffer.
+    lines: [Line; SCROLLBACK_LINES],
+    /// The index of the slot in which the next line will be written.
+    head: usize,
+    /// The number of lines that were stored.
+    len: usize,
+    /// The number of lines by which the view is currently scrolled back.
+    offset: usize,
+    /// The content of the screen when the view was scrolled back, restored when it comes back
+    /// to the bottom.
+    live: [Line; ROWS],
+}
+
+impl Scrollback {
+    /// Creates a new, empty [`Scrollback`] instance.
+    pub const fn new() -> Self {
+        Self {
+            lines: [[0; WIDTH as usize]; SCROLLBACK_LINES],
+            head: 0,
+            len: 0,
+            offset: 0,
+            live: [[0; WIDTH as usize]; ROWS],
+        }
+    }
+
+    /// Records a line that is about to scroll off the screen.
+    ///
+    /// The oldest line is forgotten when the buffer is full.
+    pub fn push(&mut self, line: &[u16]) {
+        self.lines[self.head].copy_from_slice(line);
+        self.head = (self.head + 1) % SCROLLBACK_LINES;
+        self.len = (self.len + 1).min(SCROLLBACK_LINES);
+    }
+
+    /// Returns the number of lines by which the view is currently scrolled back.
+    #[inline(always)]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the line that was recorded `back` lines ago, starting at 1.
+    fn line(&self, back: usize) -> &Line {
+        &self.lines[(self.head + SCROLLBACK_LINES - back) % SCROLLBACK_LINES]
+    }
+
+    /// Scrolls the view to `offset` lines above the bottom, and draws it on `screen`.
+    ///
+    /// `screen` contains the rows of the screen in which the output is written. The offset is
+    /// clamped to the number of recorded lines.
+    pub fn scroll_to(&mut self, offset: usize, screen: &mut [u16]) {
+        let offset = offset.min(self.len);
+        if offset == self.offset {
+            return;
+        }
+
+        if self.offset == 0 {
+            for (live, row) in self.live.iter_mut().zip(screen.chunks(WIDTH as usize)) {
+                live.copy_from_slice(row);
+            }
+        }
+        self.offset = offset;
+
+        for (r, row) in screen.chunks_mut(WIDTH as usize).enumerate() {
+            let line = if r < offset {
+                self.line(offset - r)
+            } else {
+                &self.live[r - offset]
+            };
+            row.copy_from_slice(line);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Regions of the screen selected with the mouse.
+
+use core::ops::{Range, RangeInclusive};
+
+use crate::drivers::vga::WIDTH;
+
+/// The shape of a selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// The selection follows the text, from the first cell to the last one, as when reading.
+    Linear,
+    /// The selection is the rectangle of which the first and last cells are two corners.
+    Rectangular,
+}
+
+/// A region of the screen selected with the mouse.
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
+    /// The cell on which the selection started.
+    anchor: (u32, u32),
+    /// The cell on which the selection currently ends.
+    end: (u32, u32),
+    /// The shape of the selection.
+    mode: SelectionMode,
+}
+
+impl Selection {
+    /// Creates a new [`Selection`] that only contains the cell at `(x, y)`.
+    pub fn new(x: u32, y: u32, mode: SelectionMode) -> Self {
+        Self {
+            anchor: (x, y),
+            end: (x, y),
+            mode,
+        }
+    }
+
+    /// Moves the end of the selection to the cell at `(x, y)`.
+    #[inline]
+    pub fn extend_to(&mut self, x: u32, y: u32) {
+        self.end = (x, y);
+    }
+
+    /// Returns whether the selection only contains the cell on which it started.
+    #[inline]
+    pub fn is_single_cell(&self) -> bool {
+        self.anchor == self.end
+    }
+
+    /// Returns the rows covered by the selection.
+    pub fn rows(&self) -> RangeInclusive<u32> {
+        self.anchor.1.min(self.end.1)..=self.anchor.1.max(self.end.1)
+    }
+
+    /// Returns the columns of row `y` that are part of the selection.
+    ///
+    /// The returned range is empty if the row is not covered by the selection.
+    pub fn columns(&self, y: u32) -> Range<u32> {
+        if !self.rows().contains(&y) {
+            return 0..0;
+        }
+
+        match self.mode {
+            SelectionMode::Rectangular => {
+                self.anchor.0.min(self.end.0)..self.anchor.0.max(self.end.0) + 1
+            }
+            SelectionMode::Linear => {
+                // Cells are ordered as when reading: by row first, then by column.
+                let (first, last) = if (self.anchor.1, self.anchor.0) <= (self.end.1, self.end.0) {
+                    (self.anchor, self.end)
+                } else {
+                    (self.end, self.anchor)
+                };
+
+                let start = if y == first.1 { first.0 } else { 0 };
+                let end = if y == last.1 { last.0 + 1 } else { WIDTH };
+                start..end
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Defines the color themes that the terminal can use.
+
+use crate::drivers::vga::Color;
+
+/// A set of colors used by the terminal and the rest of the kernel when writing to the screen.
+#[derive(Debug)]
+pub struct Theme {
+    /// The name of the theme.
+    pub name: &'static str,
+    /// The default color of the text.
+    pub foreground: Color,
+    /// The color of the background.
+    pub background: Color,
+    /// The color used to report errors (including kernel panics).
+    pub error: Color,
+    /// The color of the prompt of the command-line.
+    pub prompt: Color,
+    /// The color of the text of the status bar.
+    pub status_foreground: Color,
+    /// The color of the background of the status bar.
+    pub status_background: Color,
+}
+
+impl Theme {
+    /// The default theme.
+    pub const DEFAULT: Self = Self {
+        name: "default",
+        foreground: Color::White,
+        background: Color::Black,
+        error: Color::Red,
+        prompt: Color::LightGreen,
+        status_foreground: Color::Black,
+        status_background: Color::LightGray,
+    };
+
+    /// A theme reminiscent of old blue screens.
+    pub const BLUE: Self = Self {
+        name: "blue",
+        foreground: Color::White,
+        background: Color::Blue,
+        error: Color::LightRed,
+        prompt: Color::Yellow,
+        status_foreground: Color::Blue,
+        status_background: Color::LightGray,
+    };
+
+    /// A theme with dark text on a light background.
+    pub const LIGHT: Self = Self {
+        name: "light",
+        foreground: Color::Black,
+        background: Color::LightGray,
+        error: Color::Red,
+        prompt: Color::Blue,
+        status_foreground: Color::White,
+        status_background: Color::DarkGray,
+    };
+
+    /// Green text on a black background.
+    pub const MATRIX: Self = Self {
+        name: "matrix",
+        foreground: Color::LightGreen,
+        background: Color::Black,
+        error: Color::LightRed,
+        prompt: Color::Green,
+        status_foreground: Color::Black,
+        status_background: Color::Green,
+    };
+
+    /// Amber text on a black background, like old monochrome monitors.
+    pub const AMBER: Self = Self {
+        name: "amber",
+        foreground: Color::Yellow,
+        background: Color::Black,
+        error: Color::LightRed,
+        prompt: Color::Brown,
+        status_foreground: Color::Black,
+        status_background: Color::Brown,
+    };
+
+    /// The list of all available themes.
+    pub const ALL: &'static [Self] = &[
+        Self::DEFAULT,
+        Self::BLUE,
+        Self::LIGHT,
+        Self::MATRIX,
+        Self::AMBER,
+    ];
+
+    /// Finds the theme with the provided name.
+    pub fn find(name: &[u8]) -> Option<&'static Self> {
+        Self::ALL.iter().find(|t| t.name.as_bytes() == name)
+    }
+}
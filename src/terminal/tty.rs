@@ -0,0 +1,152 @@
+//! The line discipline that sits between the keyboard and the programs reading from it.
+
+use bitflags::bitflags;
+
+use super::{LineEditor, LineEvent};
+use crate::utility::ArrayVec;
+
+/// The number of bytes that can be waiting to be read from a [`Tty`].
+pub const INPUT_BUFFER_SIZE: usize = 128;
+
+bitflags! {
+    /// The modes of a [`Tty`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TtyModes: u8 {
+        /// The input is edited by the user and only becomes available once a whole line is
+        /// submitted. Otherwise, bytes are available as soon as they are received.
+        const CANONICAL = 1 << 0;
+        /// The input is displayed as it is typed.
+        const ECHO = 1 << 1;
+    }
+}
+
+/// A terminal device, as seen by the programs that read from it.
+///
+/// Bytes received from the input drivers are fed to [`receive`](Self::receive), and the
+/// resulting input is buffered until it is [read](Self::read).
+pub struct Tty {
+    /// The current modes of the device.
+    modes: TtyModes,
+    /// The line being edited, in canonical mode.
+    editor: LineEditor,
+    /// The input that is waiting to be read.
+    input: ArrayVec<u8, INPUT_BUFFER_SIZE>,
+    /// Whether the end of the input was signaled and not read yet.
+    eof: bool,
+}
+
+impl Tty {
+    /// Creates a new [`Tty`] in canonical mode, with echo enabled.
+    pub const fn new() -> Self {
+        Self {
+            modes: TtyModes::CANONICAL.union(TtyModes::ECHO),
+            editor: LineEditor::new(),
+            input: ArrayVec::new(),
+            eof: false,
+        }
+    }
+
+    /// Returns the current modes of the device.
+    #[inline(always)]
+    pub fn modes(&self) -> TtyModes {
+        self.modes
+    }
+
+    /// Changes the modes of the device.
+    ///
+    /// When canonical mode is left, the line being edited becomes available as it is.
+    pub fn set_modes(&mut self, modes: TtyModes) {
+        if self.modes.contains(TtyModes::CANONICAL) && !modes.contains(TtyModes::CANONICAL) {
+            let room = INPUT_BUFFER_SIZE - self.input.len();
+            let line = self.editor.line();
+            self.input.extend_from_slice(&line[..line.len().min(room)]);
+            self.editor.clear();
+        }
+        self.modes = modes;
+    }
+
+    /// Processes a byte received from an input driver.
+    ///
+    /// In canonical mode, [`LineEvent::Submitted`] is returned once the line is available.
+    /// If there is no room for it, the line is kept in the editor and nothing happens. In raw
+    /// mode, every byte is submitted on its own and is dropped if the buffer is full.
+    pub fn receive(&mut self, byte: u8) -> LineEvent {
+        if !self.modes.contains(TtyModes::CANONICAL) {
+            return match self.input.try_push(byte) {
+                Ok(()) => LineEvent::Submitted,
+                Err(_) => LineEvent::Ignored,
+            };
+        }
+
+        let event = self.editor.feed(byte);
+        match event {
+            LineEvent::Submitted => {
+                let line = self.editor.line();
+                if self.input.len() + line.len() + 1 > INPUT_BUFFER_SIZE {
+                    return LineEvent::Ignored;
+                }
+                self.input.extend_from_slice(line);
+                self.input.push(b'\n');
+                self.editor.clear();
+            }
+            LineEvent::EndOfFile => self.eof = true,
+            _ => (),
+        }
+        event
+    }
+
+    /// Reads the input that is available into `buf`.
+    ///
+    /// In canonical mode, at most one line is returned, including its line feed.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes that were read, or `None` if no input is available yet. `Some(0)`
+    /// is returned once when the end of the input is reached.
+    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if self.input.is_empty() {
+            return core::mem::take(&mut self.eof).then_some(0);
+        }
+
+        let mut n = buf.len().min(self.input.len());
+        if self.modes.contains(TtyModes::CANONICAL) {
+            if let Some(end) = self.input.iter().position(|&c| c == b'\n') {
+                n = n.min(end + 1);
+            }
+        }
+
+        buf[..n].copy_from_slice(&self.input[..n]);
+        self.input.remove_range(..n);
+        Some(n)
+    }
+
+    /// Discards the input that is waiting to be read, along with the line being edited.
+    pub fn flush(&mut self) {
+        self.input.clear();
+        self.editor.clear();
+        self.eof = false;
+    }
+
+    /// Returns the part of the line being edited that is displayed.
+    ///
+    /// Nothing is displayed when echo is disabled.
+    pub fn echoed_line(&self) -> &[u8] {
+        if self.modes.contains(TtyModes::ECHO) {
+            self.editor.line()
+        } else {
+            &[]
+        }
+    }
+
+    /// Returns the line being edited.
+    #[inline(always)]
+    pub fn editor(&self) -> &LineEditor {
+        &self.editor
+    }
+
+    /// Returns an exclusive reference to the line being edited.
+    #[inline(always)]
+    pub fn editor_mut(&mut self) -> &mut LineEditor {
+        &mut self.editor
+    }
+}
@@ -0,0 +1,342 @@
+//! The clocks of the kernel.
+//!
+//! The monotonic clock counts the time elapsed since the PIT started ticking. The wall clock
+//! is the time read from the RTC at boot, advanced by the monotonic clock. The PIT drifts
+//! slightly, so the wall clock is compared with the RTC every [`RESYNC_PERIOD_MS`], and set
+//! again when they disagree by more than a second. The monotonic clock is never corrected.
+//!
+//! The wall clock may also be corrected with [`adjust`] by a more precise reference, such as
+//! an NTP server. Small offsets are slewed, by running the wall clock slightly faster or
+//! slower until they are absorbed, so that it does not jump. From then on, the RTC is set from
+//! the wall clock instead.
+
+use core::fmt::{Display, Formatter};
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::drivers::{pit, rtc};
+use crate::trace::trace;
+use crate::utility::Mutex;
+use crate::{kthread, log, timer};
+
+/// The number of nanoseconds in a second.
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// The period at which the wall clock is compared with the RTC.
+pub const RESYNC_PERIOD_MS: u32 = 60_000;
+
+/// The largest offset that [`adjust`] slews rather than steps, in nanoseconds.
+pub const MAX_SLEW_NS: u64 = 128_000_000;
+
+/// The rate at which offsets are slewed, in nanoseconds per second.
+const SLEW_RATE_NS: u64 = 500_000;
+
+/// The wall clock.
+static WALL_CLOCK: Mutex<WallClock> = Mutex::new(WallClock {
+    epoch_offset: 0,
+    slew: 0,
+    slew_start: 0,
+});
+
+/// Whether the wall clock was corrected with [`adjust`], in which case the RTC follows it.
+static DISCIPLINED: AtomicBool = AtomicBool::new(false);
+
+/// The state of the wall clock.
+struct WallClock {
+    /// The Unix time at which the monotonic clock was zero, in nanoseconds.
+    epoch_offset: u64,
+    /// The correction being slewed, in nanoseconds.
+    slew: i64,
+    /// The value of the monotonic clock when the slew started.
+    slew_start: u64,
+}
+
+impl WallClock {
+    /// Returns the part of the slew that was applied by the time `monotonic` was reached.
+    fn slewed(&self, monotonic: u64) -> i64 {
+        let elapsed = monotonic.saturating_sub(self.slew_start);
+        let max = (elapsed as u128 * SLEW_RATE_NS as u128 / NANOS_PER_SECOND as u128) as u64;
+        let applied = self.slew.unsigned_abs().min(max) as i64;
+        if self.slew < 0 {
+            -applied
+        } else {
+            applied
+        }
+    }
+
+    /// Returns the Unix time at which the monotonic clock read `monotonic`, in nanoseconds.
+    fn unix_ns(&self, monotonic: u64) -> u64 {
+        (self.epoch_offset + monotonic).saturating_add_signed(self.slewed(monotonic))
+    }
+}
+
+/// How [`adjust`] corrected the wall clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjustment {
+    /// The offset is being absorbed progressively.
+    Slewed,
+    /// The wall clock jumped.
+    Stepped,
+}
+
+/// A date and a time of day, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    /// The year.
+    pub year: u16,
+    /// The month (1-12).
+    pub month: u8,
+    /// The day of the month (1-31).
+    pub day: u8,
+    /// The hours (0-23).
+    pub hour: u8,
+    /// The minutes (0-59).
+    pub minute: u8,
+    /// The seconds (0-59).
+    pub second: u8,
+    /// The nanoseconds within the second.
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    /// Creates the [`DateTime`] of the provided Unix time, in nanoseconds.
+    pub fn from_unix_ns(ns: u64) -> Self {
+        let secs = ns / NANOS_PER_SECOND;
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let in_day = secs % 86_400;
+
+        Self {
+            year: year as u16,
+            month,
+            day,
+            hour: (in_day / 3600) as u8,
+            minute: (in_day / 60 % 60) as u8,
+            second: (in_day % 60) as u8,
+            nanosecond: (ns % NANOS_PER_SECOND) as u32,
+        }
+    }
+
+    /// Returns whether the date exists, such as 2024-02-29 but not 2023-02-29.
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=days_in_month(self.year, self.month)).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+            && self.nanosecond < NANOS_PER_SECOND as u32
+    }
+
+    /// Returns the day of the week, from 0 (Sunday) to 6 (Saturday).
+    pub fn weekday(&self) -> u8 {
+        // The epoch was a Thursday.
+        (days_from_civil(self.year as i64, self.month, self.day) + 4).rem_euclid(7) as u8
+    }
+
+    /// Returns the Unix time of this date, in seconds.
+    ///
+    /// Dates before 1970 are clamped to the epoch.
+    pub fn to_unix(self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month, self.day);
+        let secs =
+            days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        secs.max(0) as u64
+    }
+}
+
+impl From<rtc::RtcTime> for DateTime {
+    fn from(t: rtc::RtcTime) -> Self {
+        Self {
+            year: t.year,
+            month: t.month,
+            day: t.day,
+            hour: t.hour,
+            minute: t.minute,
+            second: t.second,
+            nanosecond: 0,
+        }
+    }
+}
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second,
+        )
+    }
+}
+
+/// The names of the months, starting with January.
+pub const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Returns whether `year` is a leap year.
+#[inline]
+pub fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Returns the number of days of a month (1-12).
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days between the epoch and the provided date.
+///
+/// See Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the date that lies `days` days after the epoch, as `(year, month, day)`.
+///
+/// This is the inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Returns the time elapsed since the PIT started ticking, in nanoseconds.
+#[inline]
+pub fn monotonic_ns() -> u64 {
+    timer::now() as u64 * pit::interval_ns() as u64
+}
+
+/// Returns the current Unix time, in nanoseconds.
+pub fn unix_time_ns() -> u64 {
+    WALL_CLOCK.lock().unix_ns(monotonic_ns())
+}
+
+/// Returns the current date and time.
+pub fn now() -> DateTime {
+    DateTime::from_unix_ns(unix_time_ns())
+}
+
+/// Sets the wall clock to the time of the RTC.
+fn sync_with_rtc(rtc_secs: u64) {
+    let mut clock = WALL_CLOCK.lock();
+    clock.epoch_offset = (rtc_secs * NANOS_PER_SECOND).saturating_sub(monotonic_ns());
+    clock.slew = 0;
+}
+
+/// Corrects the wall clock, which is `offset_ns` nanoseconds late (or early, when negative)
+/// according to a more precise reference.
+///
+/// Offsets up to [`MAX_SLEW_NS`] are slewed, and replace the correction being slewed, if any.
+/// The wall clock is stepped for larger ones.
+pub fn adjust(offset_ns: i64) -> Adjustment {
+    let now = monotonic_ns();
+    let mut clock = WALL_CLOCK.lock();
+    let unix = clock.unix_ns(now);
+    let adjustment = if offset_ns.unsigned_abs() <= MAX_SLEW_NS {
+        clock.epoch_offset = unix - now;
+        clock.slew = offset_ns;
+        clock.slew_start = now;
+        Adjustment::Slewed
+    } else {
+        clock.epoch_offset = unix.saturating_add_signed(offset_ns).saturating_sub(now);
+        clock.slew = 0;
+        Adjustment::Stepped
+    };
+    drop(clock);
+
+    DISCIPLINED.store(true, Relaxed);
+    adjustment
+}
+
+/// Sets the wall clock and the RTC to the provided date.
+///
+/// The RTC only stores years from 2000 to 2099: `false` is returned for other dates, and for
+/// dates that do not exist.
+pub fn set(date: DateTime) -> bool {
+    if !date.is_valid() || !(2000..=2099).contains(&date.year) {
+        return false;
+    }
+
+    write_rtc(date);
+    sync_with_rtc(date.to_unix());
+    DISCIPLINED.store(false, Relaxed);
+    true
+}
+
+/// Writes `date` to the RTC.
+fn write_rtc(date: DateTime) {
+    rtc::write_time(rtc::RtcTime {
+        second: date.second,
+        minute: date.minute,
+        hour: date.hour,
+        day: date.day,
+        month: date.month,
+        year: date.year,
+    });
+}
+
+/// Corrects the drift of the wall clock, if any.
+///
+/// Once the wall clock was corrected with [`adjust`], the RTC is set from it instead.
+///
+/// This function is called periodically on the `kworker` thread, as reading the RTC takes a
+/// while.
+fn resync() {
+    if DISCIPLINED.load(Relaxed) {
+        let date = now();
+        if (2000..=2099).contains(&date.year) {
+            write_rtc(date);
+        }
+        return;
+    }
+
+    let rtc_secs = DateTime::from(rtc::read_time()).to_unix();
+    let drift = (unix_time_ns() / NANOS_PER_SECOND) as i64 - rtc_secs as i64;
+
+    // The RTC only counts whole seconds, so a difference of one second is expected.
+    if drift.abs() > 1 {
+        trace!("time", "the wall clock drifted by {drift} s");
+        sync_with_rtc(rtc_secs);
+    }
+}
+
+/// Initializes the wall clock from the RTC.
+///
+/// The PIT must be initialized beforehand.
+pub fn init() {
+    sync_with_rtc(DateTime::from(rtc::read_time()).to_unix());
+
+    let schedule_resync = || _ = kthread::defer(resync);
+    if timer::register(RESYNC_PERIOD_MS, schedule_resync).is_none() {
+        log!("Failed to register the clock synchronization callback.\n");
+    }
+}
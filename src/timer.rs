@@ -0,0 +1,130 @@
+//! Allows the rest of the kernel to run callbacks periodically from the timer interrupt.
+
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering::Relaxed;
+
+use crate::drivers::pit;
+use crate::utility::{ArrayVec, Mutex};
+
+/// The maximum number of callbacks that can be registered at the same time.
+const MAX_CALLBACKS: usize = 8;
+
+/// A callback registered with [`register`] or [`register_once`].
+#[derive(Clone, Copy)]
+struct Callback {
+    /// The number of ticks between two calls to the callback.
+    ///
+    /// For callbacks that are only called once, this is the tick at which it is due.
+    period: u32,
+    /// Whether the callback is removed after being called.
+    once: bool,
+    /// The function to call.
+    function: fn(),
+}
+
+impl Callback {
+    /// Returns whether the callback is due at tick `now`.
+    fn is_due(&self, now: u32) -> bool {
+        if self.once {
+            // The tick count may wrap around.
+            now.wrapping_sub(self.period) as i32 >= 0
+        } else {
+            now % self.period == 0
+        }
+    }
+}
+
+/// The list of registered callbacks.
+static CALLBACKS: Mutex<[Option<Callback>; MAX_CALLBACKS]> = Mutex::new([None; MAX_CALLBACKS]);
+
+/// The last tick passed to [`tick`].
+static NOW: AtomicU32 = AtomicU32::new(0);
+
+/// The ID of a callback registered with [`register`] or [`register_once`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbackId(usize);
+
+/// Registers a function to be called every `period_ms` milliseconds.
+///
+/// # Remarks
+///
+/// The callback is called from within the timer interrupt handler, meaning that it should not
+/// take too long to complete. It must also not attempt to lock a mutex that's held by the code
+/// it interrupted (this is generally not an issue as locking a mutex disables interrupts).
+///
+/// # Returns
+///
+/// This function returns the ID of the callback, or [`None`] if too many callbacks are already
+/// registered.
+pub fn register(period_ms: u32, function: fn()) -> Option<CallbackId> {
+    insert(Callback {
+        period: ms_to_ticks(period_ms),
+        once: false,
+        function,
+    })
+}
+
+/// Registers a function to be called once, `delay_ms` milliseconds from now.
+///
+/// The same remarks as for [`register`] apply. The callback is unregistered automatically
+/// before it is called.
+pub fn register_once(delay_ms: u32, function: fn()) -> Option<CallbackId> {
+    insert(Callback {
+        period: NOW.load(Relaxed).wrapping_add(ms_to_ticks(delay_ms)),
+        once: true,
+        function,
+    })
+}
+
+/// Inserts a callback in the first free slot.
+fn insert(callback: Callback) -> Option<CallbackId> {
+    let mut callbacks = CALLBACKS.lock();
+    let index = callbacks.iter().position(Option::is_none)?;
+    callbacks[index] = Some(callback);
+    Some(CallbackId(index))
+}
+
+/// Unregisters a callback previously registered with [`register`] or [`register_once`].
+pub fn unregister(id: CallbackId) {
+    CALLBACKS.lock()[id.0] = None;
+}
+
+/// Returns the last tick handled by the timer interrupt.
+#[inline]
+pub fn now() -> u32 {
+    NOW.load(Relaxed)
+}
+
+/// Converts a number of milliseconds to a number of timer ticks.
+///
+/// The result is always at least one tick.
+pub fn ms_to_ticks(ms: u32) -> u32 {
+    let interval_ns = pit::interval_ns().max(1) as u64;
+    let ticks = ms as u64 * 1_000_000 / interval_ns;
+    ticks.clamp(1, u32::MAX as u64) as u32
+}
+
+/// Calls the callbacks that are due at tick `now`.
+///
+/// This function is meant to be called by the timer interrupt handler.
+pub fn tick(now: u32) {
+    NOW.store(now, Relaxed);
+
+    // Collect the callbacks that need to be called before actually calling them. This allows
+    // the callbacks to register or unregister callbacks themselves.
+    let mut due = ArrayVec::<fn(), MAX_CALLBACKS>::new();
+    for slot in CALLBACKS.lock().iter_mut() {
+        let Some(callback) = slot else {
+            continue;
+        };
+
+        if callback.is_due(now) {
+            due.push(callback.function);
+            if callback.once {
+                *slot = None;
+            }
+        }
+    }
+
+    due.iter().for_each(|f| f());
+}
@@ -0,0 +1,264 @@
+//! The `top` command, which shows how the processes use the CPU.
+//!
+//! The screen is redrawn periodically with the processes sorted by the share of the CPU they
+//! used since the previous refresh. Timer ticks are charged to the process they interrupt (see
+//! [`sched::account_tick`]), so the shares are only as precise as the timer.
+
+use core::fmt::Write;
+
+use crate::drivers::vga;
+use crate::sched;
+use crate::shell::{parse_u32, split_command, usage, Command, Shell};
+use crate::state::{user_name, CpuTime, ProcessId, UserId, GLOBAL};
+use crate::utility::{ArrayVec, Column, Fixed, HumanBytes, Table};
+use crate::{printk, terminal, time, timer, TERMINAL};
+
+/// The maximum number of processes that are tracked between two refreshes.
+const MAX_TRACKED: usize = 64;
+
+/// The number of lines displayed above the list of processes.
+const HEADER_LINES: u32 = 3;
+
+/// The number of processes that fit on the screen, below the header and the header of the
+/// table, and above the command-line.
+const MAX_ROWS: usize = (vga::HEIGHT - HEADER_LINES - 2) as usize;
+
+/// The CPU time of a process, recorded at a refresh.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// The ID of the process.
+    pid: ProcessId,
+    /// The CPU time of the process.
+    cpu: CpuTime,
+}
+
+/// What is displayed about a process.
+struct Row {
+    /// The ID of the process.
+    pid: ProcessId,
+    /// The owner of the process.
+    owner: UserId,
+    /// The number of threads of the process.
+    threads: u32,
+    /// The nice value of the threads of the process.
+    nice: i8,
+    /// The number of ticks the process used since the previous refresh.
+    ticks: u32,
+    /// The CPU time of the process since it was created.
+    cpu: CpuTime,
+    /// The number of pages mapped by the process.
+    mapped: u32,
+    /// The number of mapped pages that are resident.
+    resident: u32,
+}
+
+/// The counters recorded at a refresh.
+struct Snapshot {
+    /// The value of the tick counter.
+    tick: u32,
+    /// The number of idle ticks.
+    idle: u32,
+    /// The CPU time of every process.
+    samples: ArrayVec<Sample, MAX_TRACKED>,
+}
+
+impl Snapshot {
+    /// A snapshot taken when the system booted, so that the first refresh shows the averages
+    /// since then.
+    const BOOT: Self = Self {
+        tick: 0,
+        idle: 0,
+        samples: ArrayVec::new(),
+    };
+
+    /// Returns the CPU time that `pid` had when the snapshot was taken.
+    fn cpu(&self, pid: ProcessId) -> CpuTime {
+        self.samples
+            .iter()
+            .find(|s| s.pid == pid)
+            .map_or(CpuTime::default(), |s| s.cpu)
+    }
+}
+
+/// Formats a number of ticks as a duration, such as `1:02.35`.
+struct Ticks(u32);
+
+impl core::fmt::Display for Ticks {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let cs = self.0 as u64 * sched::tick_interval_ns() as u64 / 10_000_000;
+        write!(f, "{}:{:02}.{:02}", cs / 6000, cs / 100 % 60, cs % 100)
+    }
+}
+
+/// The `top` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"top",
+    summary: "show the processes that use the CPU",
+    usage: "top [-d <seconds>] [-n <count>]",
+    details: "Redraws the list of processes every `seconds` seconds (1 by default), sorted by\n\
+              the share of the CPU they used since the previous refresh, and stops after\n\
+              `count` refreshes. Press `q` to quit.",
+    handler: top,
+};
+
+/// The `top` command.
+fn top(shell: &mut Shell, mut args: &[u8]) {
+    let (mut delay, mut count) = (1, None);
+    loop {
+        let (option, rest) = split_command(args);
+        let (value, rest) = split_command(rest);
+        let slot = match option {
+            b"" => break,
+            b"-d" => &mut delay,
+            b"-n" => count.insert(0),
+            _ => {
+                print_usage(shell);
+                return;
+            }
+        };
+        match parse_u32(value) {
+            Some(value) => *slot = value,
+            None => {
+                print_usage(shell);
+                return;
+            }
+        }
+        args = rest;
+    }
+
+    let mut previous = Snapshot::BOOT;
+    let mut refreshes = 0;
+    loop {
+        previous = draw(&previous);
+        refreshes += 1;
+        if count.is_some_and(|count| refreshes >= count) {
+            break;
+        }
+        if !wait(delay.max(1) as u64 * time::NANOS_PER_SECOND) {
+            break;
+        }
+    }
+}
+
+/// Prints the usage of the command, and records that it failed.
+fn print_usage(shell: &mut Shell) {
+    printk!("usage: {}\n", usage(b"top"));
+    shell.fail();
+}
+
+/// Lets the other threads run for `ns` nanoseconds.
+///
+/// Returns `false` if the user asked to quit in the meantime.
+fn wait(ns: u64) -> bool {
+    let deadline = time::monotonic_ns() + ns;
+    loop {
+        if TERMINAL.lock().take_quit_request() {
+            return false;
+        }
+        if time::monotonic_ns() >= deadline {
+            return true;
+        }
+        terminal::wait_for_input(Some(deadline));
+    }
+}
+
+/// Redraws the screen with the CPU time used since `previous`, and returns a new snapshot.
+fn draw(previous: &Snapshot) -> Snapshot {
+    let glob = GLOBAL.get().unwrap();
+    let mut snapshot = Snapshot {
+        tick: timer::now(),
+        idle: sched::idle_ticks(),
+        samples: ArrayVec::new(),
+    };
+
+    let mut rows = ArrayVec::<Row, MAX_TRACKED>::new();
+    let (mut user, mut kernel) = (0u32, 0u32);
+    for (pid, process) in glob.processes.lock().iter() {
+        let old = previous.cpu(pid);
+        let (du, dk) = (
+            process.cpu.user.wrapping_sub(old.user),
+            process.cpu.kernel.wrapping_sub(old.kernel),
+        );
+        user = user.wrapping_add(du);
+        kernel = kernel.wrapping_add(dk);
+
+        let sample = Sample {
+            pid,
+            cpu: process.cpu,
+        };
+        let row = Row {
+            pid,
+            owner: process.owner,
+            threads: 0,
+            nice: 0,
+            ticks: du.wrapping_add(dk),
+            cpu: process.cpu,
+            mapped: process.memory.mapped,
+            resident: process.memory.resident,
+        };
+        if snapshot.samples.try_push(sample).is_err() || rows.try_push(row).is_err() {
+            break;
+        }
+    }
+
+    let mut total_threads = 0;
+    sched::for_each_thread(|thread| {
+        total_threads += 1;
+        if let Some(row) = rows.iter_mut().find(|r| r.pid == thread.process) {
+            row.threads += 1;
+            row.nice = thread.nice;
+        }
+    });
+    rows.sort_unstable_by(|a, b| b.ticks.cmp(&a.ticks).then(a.pid.cmp(&b.pid)));
+
+    let elapsed = snapshot.tick.wrapping_sub(previous.tick).max(1) as u64;
+    let idle = snapshot.idle.wrapping_sub(previous.idle);
+    let share = |ticks: u32| Fixed::from_ratio(ticks as u64 * 100, elapsed);
+
+    let mut term = TERMINAL.lock();
+    term.reset();
+    let _ = writeln!(
+        term,
+        "top - up {}, {} processes, {} threads (q: quit)",
+        Ticks(snapshot.tick),
+        rows.len(),
+        total_threads,
+    );
+    let _ = writeln!(
+        term,
+        "CPU: {:.1}% user, {:.1}% kernel, {:.1}% idle",
+        share(user),
+        share(kernel),
+        share(idle),
+    );
+    let _ = writeln!(term);
+
+    let mut table = Table::new(
+        &mut *term,
+        [
+            Column::right("PID", 5),
+            Column::left("USER", 8),
+            Column::right("THR", 3),
+            Column::right("NI", 3),
+            Column::right("%CPU", 6),
+            Column::right("TIME", 10),
+            Column::right("RES", 11),
+            Column::right("VIRT", 11),
+        ],
+    );
+    let _ = table.header();
+    for row in rows.iter().take(MAX_ROWS) {
+        let _ = table.row([
+            &row.pid,
+            &user_name(row.owner).unwrap_or("?"),
+            &row.threads,
+            &row.nice,
+            &format_args!("{:.1}", share(row.ticks)),
+            &Ticks(row.cpu.total()),
+            &HumanBytes(row.resident as u64 * 4096),
+            &HumanBytes(row.mapped as u64 * 4096),
+        ]);
+    }
+
+    snapshot
+}
@@ -0,0 +1,214 @@
+//! A lightweight event tracing subsystem.
+//!
+//! Events are recorded with the [`trace!`] macro in a ring buffer, along with a timestamp read
+//! from the time-stamp counter. Only the most recent events are kept. They can be displayed
+//! with the `trace` command, or exported through the serial port to be analyzed offline.
+
+use core::fmt::{Arguments, Write};
+
+use crate::drivers::serial;
+use crate::shell::{usage, Command, Shell};
+use crate::utility::instr::{has_tsc, rdtsc};
+use crate::utility::{ArrayVec, Column, Mutex, OnceCell, Table};
+use crate::{printk, timer, TERMINAL};
+
+/// The number of events kept in the ring buffer.
+pub const MAX_EVENTS: usize = 128;
+
+/// The maximum length of the message of an event.
+///
+/// Longer messages are truncated.
+pub const MAX_MESSAGE_LEN: usize = 48;
+
+/// Whether the time-stamp counter can be used to timestamp the events.
+static HAS_TSC: OnceCell<bool> = OnceCell::new();
+
+/// The events recorded so far.
+static EVENTS: Mutex<Ring> = Mutex::new(Ring::new());
+
+/// Records an event in the trace ring.
+///
+/// The first argument is the name of the subsystem that emitted the event. The other arguments
+/// are formatted like [`format_args!`].
+pub macro trace($subsys:expr, $($args:tt)*) {{
+	$crate::trace::record($subsys, ::core::format_args!($($args)*));
+}}
+
+/// An event recorded by [`trace!`].
+#[derive(Clone)]
+pub struct Event {
+    /// The sequence number of the event.
+    ///
+    /// Events are numbered in the order in which they were recorded, starting at 0.
+    pub seq: u32,
+    /// The time at which the event was recorded.
+    ///
+    /// This is a number of CPU cycles if the time-stamp counter is available, and a number of
+    /// timer ticks otherwise.
+    pub timestamp: u64,
+    /// The subsystem that emitted the event.
+    pub subsys: &'static str,
+    /// The message of the event.
+    message: ArrayVec<u8, MAX_MESSAGE_LEN>,
+}
+
+impl Event {
+    /// Returns the message of the event.
+    pub fn message(&self) -> &str {
+        // Truncated messages never end in the middle of a character.
+        core::str::from_utf8(&self.message).unwrap_or("?")
+    }
+}
+
+/// The ring buffer in which events are recorded.
+struct Ring {
+    /// The events, indexed by their sequence number modulo [`MAX_EVENTS`].
+    events: [Option<Event>; MAX_EVENTS],
+    /// The sequence number of the next event.
+    next: u32,
+}
+
+impl Ring {
+    /// Creates a new, empty [`Ring`] instance.
+    const fn new() -> Self {
+        const NONE: Option<Event> = None;
+
+        Self {
+            events: [NONE; MAX_EVENTS],
+            next: 0,
+        }
+    }
+}
+
+/// Returns whether the events are timestamped with the time-stamp counter.
+///
+/// When it is not available, the timer ticks are used instead.
+pub fn uses_tsc() -> bool {
+    *HAS_TSC.get_or_init(has_tsc)
+}
+
+/// Returns the current timestamp. See [`Event::timestamp`].
+fn timestamp() -> u64 {
+    if uses_tsc() {
+        unsafe { rdtsc() }
+    } else {
+        timer::now() as u64
+    }
+}
+
+/// Records an event. This function is used by the [`trace!`] macro.
+pub fn record(subsys: &'static str, args: Arguments) {
+    let timestamp = timestamp();
+
+    let mut message = ArrayVec::new();
+    // Errors only indicate that the message was truncated.
+    let _ = message.write_fmt(args);
+
+    let mut ring = EVENTS.lock();
+    let seq = ring.next;
+    ring.events[seq as usize % MAX_EVENTS] = Some(Event {
+        seq,
+        timestamp,
+        subsys,
+        message,
+    });
+    ring.next = seq.wrapping_add(1);
+}
+
+/// Returns the sequence numbers of the events that are still in the ring buffer.
+pub fn range() -> core::ops::Range<u32> {
+    let next = EVENTS.lock().next;
+    next.saturating_sub(MAX_EVENTS as u32)..next
+}
+
+/// Returns the event with the provided sequence number, if it is still in the ring buffer.
+pub fn event(seq: u32) -> Option<Event> {
+    EVENTS.lock().events[seq as usize % MAX_EVENTS]
+        .clone()
+        .filter(|e| e.seq == seq)
+}
+
+/// Forgets every event recorded so far.
+pub fn clear() {
+    let mut ring = EVENTS.lock();
+    ring.events.iter_mut().for_each(|e| *e = None);
+}
+
+/// Writes the events to `out`, one per line, as comma-separated values.
+///
+/// Each line contains the sequence number, the timestamp, the subsystem and the message of an
+/// event. This is the format used to export the trace through the serial port.
+pub fn export(out: &mut dyn Write) -> core::fmt::Result {
+    let unit = if uses_tsc() { "cycles" } else { "ticks" };
+    write!(out, "seq,{unit},subsys,message\r\n")?;
+
+    for event in range().filter_map(event) {
+        write!(
+            out,
+            "{},{},{},{}\r\n",
+            event.seq,
+            event.timestamp,
+            event.subsys,
+            event.message(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The `trace` command of the shell.
+pub static COMMAND: Command = Command {
+    name: b"trace",
+    summary: "print the recent events",
+    usage: "trace [dump|serial|clear]",
+    details: "`dump` prints the events, `serial` exports them to the serial port as\n\
+              comma-separated values and `clear` forgets them.",
+    handler: trace,
+};
+
+/// The `trace` command.
+fn trace(shell: &mut Shell, args: &[u8]) {
+    match args {
+        b"" | b"dump" => {
+            let mut term = TERMINAL.lock();
+            let unit = if uses_tsc() { "cycles" } else { "ticks" };
+            let _ = writeln!(term, "times are in {unit}, relative to the first event");
+
+            let mut table = Table::new(
+                &mut *term,
+                [
+                    Column::right("TIME", 12),
+                    Column::right("DELTA", 10),
+                    Column::left("SUBSYS", 8),
+                    Column::left("EVENT", 44),
+                ],
+            );
+            let _ = table.header();
+
+            let mut first = None;
+            let mut previous = None;
+            for event in range().filter_map(event) {
+                let first = *first.get_or_insert(event.timestamp);
+                let delta = event.timestamp - previous.unwrap_or(event.timestamp);
+                previous = Some(event.timestamp);
+
+                let _ = table.row([
+                    &(event.timestamp - first),
+                    &delta,
+                    &event.subsys,
+                    &event.message(),
+                ]);
+            }
+        }
+        b"serial" => {
+            if export(&mut serial::Serial).is_ok() {
+                printk!("the trace was exported to the serial port\n");
+            }
+        }
+        b"clear" => clear(),
+        _ => {
+            printk!("usage: {}\n", usage(b"trace"));
+            shell.fail();
+        }
+    }
+}
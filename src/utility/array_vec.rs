@@ -4,7 +4,7 @@ use core::ops::{Deref, DerefMut, RangeBounds};
 /// A fixed-capacity array-backed vector.
 pub struct ArrayVec<T, const N: usize> {
     data: [MaybeUninit<T>; N],
-    len: u8,
+    len: usize,
 }
 
 impl<T, const N: usize> Default for ArrayVec<T, N> {
@@ -15,8 +15,6 @@ impl<T, const N: usize> Default for ArrayVec<T, N> {
 }
 
 impl<T, const N: usize> ArrayVec<T, N> {
-    const _ENSURE_CAPACITY: () = assert!(N <= 255, "ArrayVec must have a non-zero capacity");
-
     /// Creates a new [`ArrayVec<T, N>`] instance.
     #[inline]
     pub const fn new() -> Self {
@@ -47,7 +45,7 @@ impl<T, const N: usize> ArrayVec<T, N> {
     /// Returns the current length of the vector.
     #[inline(always)]
     pub const fn len(&self) -> usize {
-        self.len as usize
+        self.len
     }
 
     /// Returns whether the vector is empty.
@@ -216,7 +214,7 @@ impl<T, const N: usize> ArrayVec<T, N> {
             self.len() - end,
         );
 
-        self.len -= count as u8;
+        self.len -= count;
     }
 
     /// Removes a range of values from the vector.
@@ -269,7 +267,7 @@ impl<T, const N: usize> ArrayVec<T, N> {
             self.data.as_mut_ptr().add(len).cast(),
             slice.len(),
         );
-        self.len += slice.len() as u8;
+        self.len += slice.len();
     }
 
     /// Extends the vector with elements from a slice.
@@ -309,6 +307,12 @@ impl<T, const N: usize> DerefMut for ArrayVec<T, N> {
     }
 }
 
+impl<T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
 impl<T, const N: usize> FromIterator<T> for ArrayVec<T, N> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
         let mut this = Self::new();
@@ -316,3 +320,24 @@ impl<T, const N: usize> FromIterator<T> for ArrayVec<T, N> {
         this
     }
 }
+
+impl<const N: usize> core::fmt::Write for ArrayVec<u8, N> {
+    /// Appends the string to the vector.
+    ///
+    /// If the string does not fit, as many characters as possible are appended and an error
+    /// is returned.
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            let mut buf = [0; 4];
+            let encoded = c.encode_utf8(&mut buf).as_bytes();
+
+            if self.len() + encoded.len() > self.capacity() {
+                return Err(core::fmt::Error);
+            }
+
+            self.extend_from_slice(encoded);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! Checksums used to validate data, such as on-disk structures and network packets.
+
+/// The reversed polynomial of the CRC32 used by Ethernet, zlib and FAT (IEEE 802.3).
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// The CRC32 of every possible byte, used to process one byte at a time.
+static CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes a CRC32 incrementally.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    /// Creates a new [`Crc32`] that has not processed any byte.
+    pub const fn new() -> Self {
+        Self(!0)
+    }
+
+    /// Feeds the provided bytes to the checksum.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = CRC32_TABLE[((self.0 ^ byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    /// Returns the checksum of the bytes processed so far.
+    #[inline]
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Computes the CRC32 of the provided bytes.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+/// Computes an Internet checksum incrementally, such as over a pseudo-header followed by the
+/// packet it describes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InternetChecksum(u32);
+
+impl InternetChecksum {
+    /// Creates a new [`InternetChecksum`] that has not processed any byte.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Feeds the provided bytes to the checksum.
+    ///
+    /// Only the last bytes fed may have an odd length, as they are summed as 16-bit words.
+    pub fn update(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(2);
+        for word in &mut words {
+            self.0 += u16::from_be_bytes([word[0], word[1]]) as u32;
+            // Fold the carry back into the lower 16 bits.
+            self.0 = (self.0 & 0xFFFF) + (self.0 >> 16);
+        }
+        if let &[last] = words.remainder() {
+            self.0 += (last as u32) << 8;
+            self.0 = (self.0 & 0xFFFF) + (self.0 >> 16);
+        }
+    }
+
+    /// Returns the checksum of the bytes processed so far.
+    #[inline]
+    pub fn finish(&self) -> u16 {
+        !(self.0 as u16)
+    }
+}
+
+/// Computes the Internet checksum of the provided bytes (RFC 1071), used by IPv4 and ICMP.
+///
+/// The bytes are summed as big-endian 16-bit words, a trailing byte being padded with zero.
+/// Computing the checksum of data that includes a valid checksum yields 0.
+pub fn internet_checksum(bytes: &[u8]) -> u16 {
+    let mut checksum = InternetChecksum::new();
+    checksum.update(bytes);
+    checksum.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn crc32_incremental() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), crc32(b"123456789"));
+    }
+
+    #[test]
+    fn internet_checksum_rfc1071_example() {
+        // The example of section 3 of RFC 1071, whose sum is 0xDDF2.
+        let data = [0x00, 0x01, 0xF2, 0x03, 0xF4, 0xF5, 0xF6, 0xF7];
+        assert_eq!(internet_checksum(&data), !0xDDF2);
+
+        let mut with_checksum = [0; 10];
+        with_checksum[..8].copy_from_slice(&data);
+        with_checksum[8..].copy_from_slice(&internet_checksum(&data).to_be_bytes());
+        assert_eq!(internet_checksum(&with_checksum), 0);
+    }
+
+    #[test]
+    fn internet_checksum_odd_length() {
+        assert_eq!(internet_checksum(&[0x12]), !0x1200);
+        let mut checksum = InternetChecksum::new();
+        checksum.update(&[0x00, 0x01, 0xF2, 0x03]);
+        checksum.update(&[0xF4, 0xF5, 0xF6, 0xF7]);
+        assert_eq!(checksum.finish(), !0xDDF2);
+    }
+}
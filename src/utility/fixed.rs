@@ -0,0 +1,241 @@
+use core::fmt::{Display, Formatter, Result};
+use core::ops::{Add, Div, Mul, Sub};
+
+/// An unsigned fixed-point number with 32 integer bits and 32 fractional bits.
+///
+/// This allows computing fractional values without relying on the FPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Fixed(u64);
+
+impl Fixed {
+    /// The number of fractional bits.
+    pub const FRAC_BITS: u32 = 32;
+
+    /// The value `0`.
+    pub const ZERO: Self = Self(0);
+
+    /// The value `1`.
+    pub const ONE: Self = Self(1 << Self::FRAC_BITS);
+
+    /// Creates a [`Fixed`] from its raw 32.32 representation.
+    #[inline]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw 32.32 representation of this number.
+    #[inline]
+    pub const fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    /// Creates a [`Fixed`] from an integer.
+    #[inline]
+    pub const fn from_int(value: u32) -> Self {
+        Self((value as u64) << Self::FRAC_BITS)
+    }
+
+    /// Computes `num / denom`, rounded to the nearest representable value.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `denom` is zero. The result is truncated to 64 bits if the
+    /// quotient does not fit in the integer part.
+    pub const fn from_ratio(num: u64, denom: u64) -> Self {
+        let num = (num as u128) << Self::FRAC_BITS;
+        let denom = denom as u128;
+        Self(((num + denom / 2) / denom) as u64)
+    }
+
+    /// Returns the integer part of this number.
+    #[inline]
+    pub const fn int(self) -> u32 {
+        (self.0 >> Self::FRAC_BITS) as u32
+    }
+
+    /// Returns the fractional part of this number.
+    #[inline]
+    pub const fn fract(self) -> Self {
+        Self(self.0 & 0xFFFF_FFFF)
+    }
+
+    /// Rounds this number to the nearest integer.
+    ///
+    /// Numbers that would round past [`u32::MAX`] saturate to it.
+    #[inline]
+    pub const fn round(self) -> u32 {
+        (self.0.saturating_add(1 << (Self::FRAC_BITS - 1)) >> Self::FRAC_BITS) as u32
+    }
+
+    /// Computes `self + rhs`, returning `None` if the result does not fit.
+    #[inline]
+    pub const fn checked_add(self, rhs: Self) -> Option<Self> {
+        match self.0.checked_add(rhs.0) {
+            Some(bits) => Some(Self(bits)),
+            None => None,
+        }
+    }
+
+    /// Computes `self - rhs`, returning `None` if the result would be negative.
+    #[inline]
+    pub const fn checked_sub(self, rhs: Self) -> Option<Self> {
+        match self.0.checked_sub(rhs.0) {
+            Some(bits) => Some(Self(bits)),
+            None => None,
+        }
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// This function panics if the result does not fit.
+    #[inline]
+    #[track_caller]
+    fn add(self, rhs: Self) -> Self {
+        self.checked_add(rhs)
+            .expect("fixed-point addition overflowed")
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// This function panics if the result would be negative, which a [`Fixed`] cannot represent.
+    #[inline]
+    #[track_caller]
+    fn sub(self, rhs: Self) -> Self {
+        self.checked_sub(rhs)
+            .expect("fixed-point subtraction underflowed")
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        Self(((self.0 as u128 * rhs.0 as u128) >> Self::FRAC_BITS) as u64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+
+    #[inline]
+    fn div(self, rhs: Self) -> Self {
+        Self((((self.0 as u128) << Self::FRAC_BITS) / rhs.0 as u128) as u64)
+    }
+}
+
+impl Display for Fixed {
+    /// Displays the number in decimal, rounded to the requested precision (`{:.2}`).
+    ///
+    /// Without an explicit precision, six decimals are displayed.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        // Beyond that many decimals, the scale would overflow a 64-bit integer.
+        let precision = f.precision().unwrap_or(6).min(19);
+        let scale = 10u64.pow(precision as u32);
+
+        let half = 1u128 << (Self::FRAC_BITS - 1);
+        let scaled = (self.0 as u128 * scale as u128 + half) >> Self::FRAC_BITS;
+        let int = scaled / scale as u128;
+        let frac = scaled % scale as u128;
+
+        if precision == 0 {
+            write!(f, "{int}")
+        } else {
+            write!(f, "{int}.{frac:0precision$}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The largest representable number, just below 2^32.
+    const MAX: Fixed = Fixed::from_bits(u64::MAX);
+
+    #[test]
+    fn from_ratio_rounds_to_nearest() {
+        assert_eq!(Fixed::from_ratio(1, 2), Fixed::from_bits(1 << 31));
+        assert_eq!(Fixed::from_ratio(6, 3), Fixed::from_int(2));
+        // 1/3 is 0x5555_5555.55.. and rounds down, 2/3 is 0xAAAA_AAAA.AA.. and rounds up.
+        assert_eq!(Fixed::from_ratio(1, 3), Fixed::from_bits(0x5555_5555));
+        assert_eq!(Fixed::from_ratio(2, 3), Fixed::from_bits(0xAAAA_AAAB));
+    }
+
+    #[test]
+    fn round() {
+        assert_eq!(Fixed::ZERO.round(), 0);
+        assert_eq!(Fixed::from_ratio(1, 2).round(), 1);
+        assert_eq!(Fixed::from_ratio(1, 2).int(), 0);
+        assert_eq!(Fixed::from_ratio(5, 2).round(), 3);
+        assert_eq!(Fixed::from_ratio(7, 3).round(), 2);
+        assert_eq!(Fixed::from_int(u32::MAX).round(), u32::MAX);
+        assert_eq!(MAX.round(), u32::MAX);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let half = Fixed::from_ratio(1, 2);
+        assert_eq!(half + half, Fixed::ONE);
+        assert_eq!(Fixed::ONE - half, half);
+        assert_eq!(half * Fixed::from_int(3), Fixed::from_ratio(3, 2));
+        assert_eq!(Fixed::ONE / Fixed::from_int(4), Fixed::from_ratio(1, 4));
+        assert_eq!(Fixed::from_ratio(7, 2).fract(), half);
+    }
+
+    #[test]
+    fn negative_results() {
+        assert_eq!(Fixed::ZERO.checked_sub(Fixed::ONE), None);
+        assert_eq!(Fixed::ONE.checked_sub(Fixed::ONE), Some(Fixed::ZERO));
+    }
+
+    #[test]
+    #[should_panic = "underflowed"]
+    fn sub_panics_when_negative() {
+        let _ = Fixed::from_ratio(1, 2) - Fixed::ONE;
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(MAX.checked_add(Fixed::from_bits(1)), None);
+        assert_eq!(Fixed::ZERO.checked_add(MAX), Some(MAX));
+        // The quotient is truncated to 64 bits.
+        assert_eq!(Fixed::from_ratio(1 << 32, 1), Fixed::ZERO);
+    }
+
+    #[test]
+    #[should_panic = "overflowed"]
+    fn add_panics_on_overflow() {
+        let _ = MAX + Fixed::from_bits(1);
+    }
+
+    #[test]
+    fn display_rounds_to_precision() {
+        let third = Fixed::from_ratio(1, 3);
+        assert_eq!(format!("{third}"), "0.333333");
+        assert_eq!(format!("{:.2}", Fixed::from_ratio(2, 3)), "0.67");
+        assert_eq!(format!("{:.2}", Fixed::from_ratio(1005, 1000)), "1.00");
+        assert_eq!(format!("{:.2}", Fixed::from_ratio(1995, 1000)), "2.00");
+        assert_eq!(format!("{:.0}", Fixed::from_ratio(1, 2)), "1");
+        assert_eq!(format!("{:.0}", Fixed::ZERO), "0");
+        assert_eq!(format!("{:.3}", Fixed::from_int(42)), "42.000");
+    }
+
+    #[test]
+    fn display_at_the_boundaries() {
+        assert_eq!(format!("{:.19}", Fixed::ONE), "1.0000000000000000000");
+        // Precision is capped at 19 decimals, so that the scale fits in 64 bits.
+        assert_eq!(format!("{:.30}", Fixed::ONE), "1.0000000000000000000");
+        assert_eq!(format!("{MAX:.2}"), "4294967296.00");
+        assert_eq!(format!("{MAX:.0}"), "4294967296");
+        assert_eq!(format!("{MAX:.19}"), "4294967295.9999999997671693563");
+    }
+}
@@ -1,37 +1,364 @@
-use core::fmt::{Display, Formatter, Result};
+use core::fmt::{Display, Formatter, Result, Write};
+
+use super::ArrayVec;
 
 /// Displays a size in a way that's readable.
+///
+/// By default, binary units are used (KiB, MiB, ...). The alternate flag (`{:#}`) selects SI
+/// units instead (KB, MB, ...).
 #[derive(Debug, Clone, Copy)]
 pub struct HumanBytes(pub u64);
 
+impl HumanBytes {
+    /// Parses a size, such as `4096`, `16MiB` or `2 GB`.
+    ///
+    /// Binary units (`KiB`, `MiB`, `GiB`, `TiB`) and their single-letter forms (`K`, `M`, `G`,
+    /// `T`) are multiples of 1024, while SI units (`KB`, `MB`, `GB`, `TB`) are multiples of
+    /// 1000. Units are case-insensitive.
+    ///
+    /// `None` is returned if the size is invalid or does not fit in 64 bits.
+    pub fn parse(s: &[u8]) -> Option<Self> {
+        let digits = s.iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+
+        let value = s[..digits].iter().try_fold(0u64, |acc, &c| {
+            acc.checked_mul(10)?.checked_add((c - b'0') as u64)
+        })?;
+
+        let mut unit = [0u8; 3];
+        let suffix = s[digits..].strip_prefix(b" ").unwrap_or(&s[digits..]);
+        if suffix.len() > unit.len() {
+            return None;
+        }
+        unit[..suffix.len()].copy_from_slice(suffix);
+        unit.make_ascii_uppercase();
+
+        let multiplier: u64 = match &unit[..suffix.len()] {
+            b"" | b"B" => 1,
+            b"K" | b"KIB" => 1 << 10,
+            b"M" | b"MIB" => 1 << 20,
+            b"G" | b"GIB" => 1 << 30,
+            b"T" | b"TIB" => 1 << 40,
+            b"KB" => 1_000,
+            b"MB" => 1_000_000,
+            b"GB" => 1_000_000_000,
+            b"TB" => 1_000_000_000_000,
+            _ => return None,
+        };
+
+        value.checked_mul(multiplier).map(Self)
+    }
+}
+
 impl Display for HumanBytes {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        fn write_with_point(x: u64, f: &mut Formatter, end: &str) -> Result {
-            let frac = ((x % 1024) * 100) / 1024;
-            let int = x / 1024;
-
-            if frac != 0 {
-                write!(f, "{int}.{frac} {end}")
-            } else {
-                write!(f, "{int} {end}")
+        let (base, units) = if f.alternate() {
+            (1000, ["B", "KB", "MB", "GB", "TB", "PB"])
+        } else {
+            (1024, ["B", "KiB", "MiB", "GiB", "TiB", "PiB"])
+        };
+
+        // Find the largest unit that keeps the integral part non-zero.
+        let mut unit = 0;
+        let mut scale = 1u64;
+        while unit + 1 < units.len() && self.0 / scale >= base {
+            scale *= base;
+            unit += 1;
+        }
+
+        let int = self.0 / scale;
+        let frac = ((self.0 % scale) * 100) / scale;
+        let name = units[unit];
+
+        if frac != 0 {
+            write!(f, "{int}.{frac:02} {name}")
+        } else {
+            write!(f, "{int} {name}")
+        }
+    }
+}
+
+/// Displays a slice of bytes as space-separated hexadecimal values.
+///
+/// For example, `[0xDE, 0xAD, 0xBE, 0xEF]` is displayed as `de ad be ef`.
+#[derive(Debug, Clone, Copy)]
+pub struct HexSlice<'a>(pub &'a [u8]);
+
+impl Display for HexSlice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_char(' ')?;
             }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays a slice of bytes in the classic hex-dump format.
+///
+/// Each line contains the address of its first byte, up to 16 bytes in hexadecimal, and the
+/// same bytes as ASCII characters:
+///
+/// ```text
+/// 0x00001000  48 65 6c 6c 6f 2c 20 57 6f 72 6c 64 21 00 00 00  |Hello, World!...|
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct HexDump<'a> {
+    /// The bytes to display.
+    pub data: &'a [u8],
+    /// The address of the first byte.
+    pub base: u32,
+}
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        const BYTES_PER_LINE: usize = 16;
+
+        for (i, line) in self.data.chunks(BYTES_PER_LINE).enumerate() {
+            let address = self.base.wrapping_add((i * BYTES_PER_LINE) as u32);
+            write!(f, "{}  {}", Address(address), HexSlice(line))?;
+
+            for _ in line.len()..BYTES_PER_LINE {
+                f.write_str("   ")?;
+            }
+
+            f.write_str("  |")?;
+            for &byte in line {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                f.write_char(c)?;
+            }
+            f.write_str("|\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays an address as a zero-padded hexadecimal value, such as `0x000b8000`.
+#[derive(Debug, Clone, Copy)]
+pub struct Address(pub u32);
+
+impl Display for Address {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
+
+/// The alignment of the cells of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// The content is aligned to the left of the column.
+    Left,
+    /// The content is aligned to the right of the column.
+    Right,
+}
+
+/// Describes a column of a [`Table`].
+#[derive(Debug, Clone, Copy)]
+pub struct Column {
+    /// The name of the column, displayed in the header.
+    pub name: &'static str,
+    /// The width of the column, in characters.
+    pub width: usize,
+    /// How the content of the column is aligned.
+    pub align: Align,
+}
+
+impl Column {
+    /// Creates a new column of the provided width whose content is aligned to the left.
+    pub const fn left(name: &'static str, width: usize) -> Self {
+        Self {
+            name,
+            width,
+            align: Align::Left,
+        }
+    }
+
+    /// Creates a new column of the provided width whose content is aligned to the right.
+    pub const fn right(name: &'static str, width: usize) -> Self {
+        Self {
+            name,
+            width,
+            align: Align::Right,
         }
+    }
+}
+
+/// The maximum width of a cell of a [`Table`].
+const MAX_CELL_WIDTH: usize = 64;
+
+/// Writes rows of values as aligned columns.
+///
+/// Cells that are wider than their column are truncated. Columns are separated by two spaces,
+/// and trailing spaces are never written.
+pub struct Table<'a, const N: usize> {
+    /// The output of the table.
+    out: &'a mut dyn Write,
+    /// The columns of the table.
+    columns: [Column; N],
+}
+
+impl<'a, const N: usize> Table<'a, N> {
+    /// Creates a new [`Table`] that writes to `out`.
+    pub fn new(out: &'a mut dyn Write, columns: [Column; N]) -> Self {
+        Self { out, columns }
+    }
 
-        let mut val = self.0;
+    /// Writes the names of the columns.
+    pub fn header(&mut self) -> Result {
+        let names = self.columns.map(|c| c.name);
+        let cells = core::array::from_fn(|i| &names[i] as &dyn Display);
+        self.row(cells)
+    }
+
+    /// Writes a row of values, one for each column.
+    pub fn row(&mut self, cells: [&dyn Display; N]) -> Result {
+        for (i, (column, cell)) in self.columns.iter().zip(cells).enumerate() {
+            let mut buf = ArrayVec::<u8, MAX_CELL_WIDTH>::new();
+            // Errors only indicate that the cell was truncated.
+            let _ = write!(buf, "{cell}");
+            let text = core::str::from_utf8(&buf).unwrap_or("?");
+            let text = text.get(..column.width).unwrap_or(text);
+            let padding = column.width.saturating_sub(text.chars().count());
+            let last = i + 1 == N;
+
+            if i != 0 {
+                self.out.write_str("  ")?;
+            }
 
-        if val < 1024 {
-            return write!(f, "{} B", val);
+            match column.align {
+                Align::Left if last => self.out.write_str(text)?,
+                Align::Left => {
+                    self.out.write_str(text)?;
+                    write_spaces(self.out, padding)?;
+                }
+                Align::Right => {
+                    write_spaces(self.out, padding)?;
+                    self.out.write_str(text)?;
+                }
+            }
         }
 
-        for ext in ["KiB", "MiB", "GiB", "TiB"] {
-            if val < 1024 * 1024 {
-                return write_with_point(val, f, ext);
+        self.out.write_char('\n')
+    }
+}
+
+/// Writes `n` spaces to `out`.
+fn write_spaces(out: &mut dyn Write, n: usize) -> Result {
+    (0..n).try_for_each(|_| out.write_char(' '))
+}
+
+/// Displays a slice of bytes using the base32 encoding of RFC 4648, without padding.
+///
+/// The alphabet only contains upper-case letters and digits that are hard to confuse with each
+/// other, which makes it suitable for text that has to be copied by hand.
+#[derive(Debug, Clone, Copy)]
+pub struct Base32<'a>(pub &'a [u8]);
+
+impl Display for Base32<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+        let mut buffer = 0u32;
+        let mut bits = 0;
+
+        for &byte in self.0 {
+            buffer = (buffer << 8) | byte as u32;
+            bits += 8;
+
+            while bits >= 5 {
+                bits -= 5;
+                f.write_char(ALPHABET[((buffer >> bits) & 0x1F) as usize] as char)?;
             }
+        }
 
-            val /= 1024;
+        if bits > 0 {
+            f.write_char(ALPHABET[((buffer << (5 - bits)) & 0x1F) as usize] as char)?;
         }
 
-        // Wtf this is so large??
-        write_with_point(val, f, "PiB")
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Option<u64> {
+        HumanBytes::parse(s.as_bytes()).map(|size| size.0)
+    }
+
+    #[test]
+    fn parse_plain_numbers() {
+        assert_eq!(parse("0"), Some(0));
+        assert_eq!(parse("4096"), Some(4096));
+        assert_eq!(parse("4096B"), Some(4096));
+        assert_eq!(parse("007"), Some(7));
+    }
+
+    #[test]
+    fn parse_binary_suffixes() {
+        assert_eq!(parse("1K"), Some(1 << 10));
+        assert_eq!(parse("1KiB"), Some(1 << 10));
+        assert_eq!(parse("16MiB"), Some(16 << 20));
+        assert_eq!(parse("2G"), Some(2 << 30));
+        assert_eq!(parse("3TiB"), Some(3 << 40));
+    }
+
+    #[test]
+    fn parse_si_suffixes() {
+        assert_eq!(parse("1KB"), Some(1_000));
+        assert_eq!(parse("16MB"), Some(16_000_000));
+        assert_eq!(parse("2 GB"), Some(2_000_000_000));
+        assert_eq!(parse("3TB"), Some(3_000_000_000_000));
+    }
+
+    #[test]
+    fn parse_ignores_suffix_case() {
+        assert_eq!(parse("1kib"), Some(1 << 10));
+        assert_eq!(parse("1mB"), Some(1_000_000));
+        assert_eq!(parse("1 g"), Some(1 << 30));
+    }
+
+    #[test]
+    fn parse_overflow() {
+        assert_eq!(parse("18446744073709551615"), Some(u64::MAX));
+        assert_eq!(parse("18446744073709551616"), None);
+        assert_eq!(parse("16777215T"), Some(16777215 << 40));
+        assert_eq!(parse("16777216T"), None);
+        assert_eq!(parse("18446745TB"), None);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse(" "), None);
+        assert_eq!(parse("K"), None);
+        assert_eq!(parse("-1"), None);
+        assert_eq!(parse(" 1"), None);
+        assert_eq!(parse("1.5G"), None);
+        assert_eq!(parse("1,5G"), None);
+        assert_eq!(parse("1  G"), None);
+        assert_eq!(parse("1X"), None);
+        assert_eq!(parse("1GiBs"), None);
+        assert_eq!(parse("1PiB"), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(format!("{}", HumanBytes(0)), "0 B");
+        assert_eq!(format!("{}", HumanBytes(1023)), "1023 B");
+        assert_eq!(format!("{}", HumanBytes(1536)), "1.50 KiB");
+        assert_eq!(format!("{:#}", HumanBytes(1500)), "1.50 KB");
+        assert_eq!(format!("{}", HumanBytes(16 << 20)), "16 MiB");
+        assert_eq!(format!("{}", HumanBytes(u64::MAX)), "16383.99 PiB");
     }
 }
@@ -1,5 +1,6 @@
 use core::alloc::Layout;
 use core::mem::MaybeUninit;
+use core::ops::Range;
 
 use crate::oom;
 use crate::state::OutOfMemory;
@@ -8,7 +9,9 @@ use crate::state::OutOfMemory;
 /// even enabled).
 ///
 /// It cannot deallocate anything, meaning that any memory it gives out is forever lost and
-/// cannot be reclaimed.
+/// cannot be reclaimed. The memory it did not give out is returned by [`finish`].
+///
+/// [`finish`]: InitAllocator::finish
 pub struct InitAllocator {
     /// The top of the stack that's used to allocate memory.
     ///
@@ -96,4 +99,12 @@ impl InitAllocator {
     pub fn base(&self) -> usize {
         self.base
     }
+
+    /// Stops using the allocator, and returns the memory that it did not give out.
+    ///
+    /// The memory that was allocated is not part of the returned range, and remains valid.
+    #[inline]
+    pub fn finish(self) -> Range<usize> {
+        self.base..self.top
+    }
 }
@@ -1,6 +1,7 @@
 //! Common CPU instructions.
 
 use core::arch::asm;
+use core::arch::x86::{__cpuid, has_cpuid};
 
 use bitflags::bitflags;
 
@@ -99,6 +100,79 @@ pub unsafe fn sidt() -> DescriptorTablePointer {
     idt
 }
 
+/// Returns whether the CPU supports the RDTSC instruction.
+pub fn has_tsc() -> bool {
+    has_cpuid() && unsafe { __cpuid(1).edx } & (1 << 4) != 0
+}
+
+/// Reads the time-stamp counter of the CPU.
+///
+/// # Safety
+///
+/// The CPU must support the RDTSC instruction.
+#[inline(always)]
+pub unsafe fn rdtsc() -> u64 {
+    let lo: u32;
+    let hi: u32;
+    asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+    (hi as u64) << 32 | lo as u64
+}
+
+/// Returns whether the CPU supports the RDRAND instruction.
+pub fn has_rdrand() -> bool {
+    has_cpuid() && unsafe { __cpuid(1).ecx } & (1 << 30) != 0
+}
+
+/// Reads a random number from the hardware generator of the CPU.
+///
+/// `None` is returned if the generator had no random number available.
+///
+/// # Safety
+///
+/// The CPU must support the RDRAND instruction.
+#[inline(always)]
+pub unsafe fn rdrand() -> Option<u32> {
+    let value: u32;
+    let ok: u8;
+    asm!(
+        "rdrand {value:e}",
+        "setc {ok}",
+        value = out(reg) value,
+        ok = out(reg_byte) ok,
+        options(nomem, nostack),
+    );
+    (ok != 0).then_some(value)
+}
+
+/// Reads the model-specific register `msr`.
+///
+/// # Safety
+///
+/// The register must exist on the current CPU, otherwise a general protection fault is raised.
+#[inline(always)]
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let lo: u32;
+    let hi: u32;
+    asm!("rdmsr", in("ecx") msr, out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+    (hi as u64) << 32 | lo as u64
+}
+
+/// Writes `value` to the model-specific register `msr`.
+///
+/// # Safety
+///
+/// The register must exist on the current CPU, and writing to it can compromise memory safety.
+#[inline(always)]
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags),
+    );
+}
+
 /// "Pauses" the CPU for a short period of time, saving power.
 ///
 /// This function should be called when a "spin loop" is being executed to avoid
@@ -148,3 +222,233 @@ impl EFlags {
         Self::from_bits_retain(flags)
     }
 }
+
+bitflags! {
+    /// The flags in the CR0 register.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Cr0: u32 {
+        const PROTECTED_MODE = 1 << 0;
+        const MONITOR_COPROCESSOR = 1 << 1;
+        const EMULATION = 1 << 2;
+        const TASK_SWITCHED = 1 << 3;
+        const EXTENSION_TYPE = 1 << 4;
+        const NUMERIC_ERROR = 1 << 5;
+        const WRITE_PROTECT = 1 << 16;
+        const ALIGNMENT_MASK = 1 << 18;
+        const NOT_WRITE_THROUGH = 1 << 29;
+        const CACHE_DISABLE = 1 << 30;
+        const PAGING = 1 << 31;
+    }
+}
+
+impl Cr0 {
+    /// Reads the current value of the CR0 register.
+    #[inline]
+    pub fn read() -> Self {
+        let value: u32;
+        unsafe {
+            asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+        Self::from_bits_retain(value)
+    }
+
+    /// Writes to the CR0 register.
+    ///
+    /// # Safety
+    ///
+    /// Changing the operating mode of the CPU can compromise memory safety.
+    #[inline]
+    pub unsafe fn write(self) {
+        asm!("mov cr0, {}", in(reg) self.bits(), options(nostack, preserves_flags));
+    }
+}
+
+bitflags! {
+    /// The flags in the CR4 register.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Cr4: u32 {
+        const VIRTUAL_8086_EXTENSIONS = 1 << 0;
+        const PROTECTED_VIRTUAL_INTERRUPTS = 1 << 1;
+        const TIMESTAMP_DISABLE = 1 << 2;
+        const DEBUGGING_EXTENSIONS = 1 << 3;
+        const PAGE_SIZE_EXTENSION = 1 << 4;
+        const PHYSICAL_ADDRESS_EXTENSION = 1 << 5;
+        const MACHINE_CHECK = 1 << 6;
+        const PAGE_GLOBAL = 1 << 7;
+        const PERFORMANCE_COUNTER = 1 << 8;
+        const OSFXSR = 1 << 9;
+        const OSXMMEXCPT = 1 << 10;
+        const USER_MODE_INSTRUCTION_PREVENTION = 1 << 11;
+        const VMX = 1 << 13;
+        const SMX = 1 << 14;
+        const FSGSBASE = 1 << 16;
+        const PCID = 1 << 17;
+        const OSXSAVE = 1 << 18;
+        const SMEP = 1 << 20;
+        const SMAP = 1 << 21;
+    }
+}
+
+impl Cr4 {
+    /// Reads the current value of the CR4 register.
+    #[inline]
+    pub fn read() -> Self {
+        let value: u32;
+        unsafe {
+            asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+        Self::from_bits_retain(value)
+    }
+
+    /// Writes to the CR4 register.
+    ///
+    /// # Safety
+    ///
+    /// Changing the operating mode of the CPU can compromise memory safety.
+    #[inline]
+    pub unsafe fn write(self) {
+        asm!("mov cr4, {}", in(reg) self.bits(), options(nostack, preserves_flags));
+    }
+}
+
+/// Reads the CR2 register, which holds the address that caused the last page fault.
+#[inline]
+pub fn read_cr2() -> u32 {
+    let value: u32;
+    unsafe {
+        asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Reads the CR3 register, which holds the physical address of the root page table.
+#[inline]
+pub fn read_cr3() -> u32 {
+    let value: u32;
+    unsafe {
+        asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Writes to the CR3 register, switching to another address space.
+///
+/// # Safety
+///
+/// `value` must reference a valid root page table that maps the running code.
+#[inline]
+pub unsafe fn write_cr3(value: u32) {
+    asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// A model-specific register whose availability can be checked with CPUID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msr {
+    /// The time-stamp counter.
+    Tsc,
+    /// The base address and the state of the local APIC.
+    ApicBase,
+    /// The code segment used by SYSENTER.
+    SysenterCs,
+    /// The stack pointer used by SYSENTER.
+    SysenterEsp,
+    /// The instruction pointer used by SYSENTER.
+    SysenterEip,
+    /// The page attribute table.
+    Pat,
+    /// The Extended Feature Enable Register.
+    Efer,
+}
+
+impl Msr {
+    /// All the registers.
+    pub const ALL: [Self; 7] = [
+        Self::Tsc,
+        Self::ApicBase,
+        Self::SysenterCs,
+        Self::SysenterEsp,
+        Self::SysenterEip,
+        Self::Pat,
+        Self::Efer,
+    ];
+
+    /// Returns the address of the register.
+    pub fn address(self) -> u32 {
+        match self {
+            Self::Tsc => 0x10,
+            Self::ApicBase => 0x1B,
+            Self::SysenterCs => 0x174,
+            Self::SysenterEsp => 0x175,
+            Self::SysenterEip => 0x176,
+            Self::Pat => 0x277,
+            Self::Efer => 0xC000_0080,
+        }
+    }
+
+    /// Returns the name of the register.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tsc => "IA32_TSC",
+            Self::ApicBase => "IA32_APIC_BASE",
+            Self::SysenterCs => "IA32_SYSENTER_CS",
+            Self::SysenterEsp => "IA32_SYSENTER_ESP",
+            Self::SysenterEip => "IA32_SYSENTER_EIP",
+            Self::Pat => "IA32_PAT",
+            Self::Efer => "IA32_EFER",
+        }
+    }
+
+    /// Returns whether the CPU reports that the register exists.
+    pub fn is_supported(self) -> bool {
+        if !has_cpuid() {
+            return false;
+        }
+
+        let edx = unsafe { __cpuid(1).edx };
+        if edx & (1 << 5) == 0 {
+            // RDMSR and WRMSR are not available at all.
+            return false;
+        }
+
+        let bit = match self {
+            Self::Tsc => 1 << 4,
+            Self::ApicBase => 1 << 9,
+            Self::SysenterCs | Self::SysenterEsp | Self::SysenterEip => 1 << 11,
+            Self::Pat => 1 << 16,
+            Self::Efer => {
+                // EFER exists when one of the features it controls (SYSCALL, NX, long mode) is
+                // available.
+                let ext = unsafe { __cpuid(0x8000_0000).eax };
+                return ext >= 0x8000_0001
+                    && unsafe { __cpuid(0x8000_0001).edx } & (1 << 11 | 1 << 20 | 1 << 29) != 0;
+            }
+        };
+
+        edx & bit != 0
+    }
+
+    /// Reads the register, if it exists.
+    pub fn read(self) -> Option<u64> {
+        if self.is_supported() {
+            Some(unsafe { rdmsr(self.address()) })
+        } else {
+            None
+        }
+    }
+
+    /// Writes to the register, if it exists.
+    ///
+    /// Returns whether the register was written.
+    ///
+    /// # Safety
+    ///
+    /// Writing to model-specific registers can compromise memory safety.
+    pub unsafe fn write(self, value: u64) -> bool {
+        if self.is_supported() {
+            wrmsr(self.address(), value);
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::ArrayVec;
+
+/// An error that might occur while inserting an entry in a [`KeyValueTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyValueError {
+    /// The key is longer than the table allows.
+    KeyTooLong,
+    /// The value is longer than the table allows.
+    ValueTooLong,
+    /// The table is full.
+    Full,
+}
+
+/// An entry of a [`KeyValueTable`].
+pub struct KeyValue<const K: usize, const V: usize> {
+    /// The key of the entry.
+    pub key: ArrayVec<u8, K>,
+    /// The value of the entry.
+    pub value: ArrayVec<u8, V>,
+}
+
+/// A bounded table that maps byte strings of up to `K` bytes to byte strings of up to `V`
+/// bytes, holding at most `N` entries.
+///
+/// Entries are kept in the order in which they were first inserted.
+pub struct KeyValueTable<const K: usize, const V: usize, const N: usize> {
+    entries: ArrayVec<KeyValue<K, V>, N>,
+}
+
+impl<const K: usize, const V: usize, const N: usize> Default for KeyValueTable<K, V, N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const K: usize, const V: usize, const N: usize> KeyValueTable<K, V, N> {
+    /// Creates a new, empty [`KeyValueTable`] instance.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            entries: ArrayVec::new(),
+        }
+    }
+
+    /// Returns the value associated with `key`, if any.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|entry| &*entry.key == key)
+            .map(|entry| &*entry.value)
+    }
+
+    /// Associates `value` with `key`, replacing the previous value of the same key.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), KeyValueError> {
+        if key.len() > K {
+            return Err(KeyValueError::KeyTooLong);
+        }
+        if value.len() > V {
+            return Err(KeyValueError::ValueTooLong);
+        }
+
+        let value = ArrayVec::from_slice_truncated(value);
+        if let Some(entry) = self.entries.iter_mut().find(|e| &*e.key == key) {
+            entry.value = value;
+            return Ok(());
+        }
+
+        self.entries
+            .try_push(KeyValue {
+                key: ArrayVec::from_slice_truncated(key),
+                value,
+            })
+            .map_err(|_| KeyValueError::Full)
+    }
+
+    /// Removes the entry of `key`.
+    ///
+    /// Returns whether there was one.
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        match self.entries.iter().position(|e| &*e.key == key) {
+            Some(index) => {
+                self.entries.remove_range(index..=index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns an iterator over the entries, in the order in which they were first inserted.
+    pub fn iter(&self) -> impl '_ + Iterator<Item = &KeyValue<K, V>> {
+        self.entries.iter()
+    }
+}
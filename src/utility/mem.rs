@@ -0,0 +1,229 @@
+//! The memory functions that the compiler calls, such as `memcpy` and `memset`.
+//!
+//! They replace the generic loops of `compiler_builtins` with the string instructions of the
+//! CPU: the bulk of the copy moves 32-bit words with `rep movsd` or `rep stosd`, once the
+//! destination is aligned. Whole pages are zeroed with the SSE registers when they are
+//! available (see [`zero_pages`]).
+//!
+//! The `esi` register cannot be used as an operand of inline assembly, so it is swapped in and
+//! out around the instructions that need it.
+
+use core::arch::asm;
+
+use crate::cpu::fpu;
+
+/// Copies `[src, src + len)` forward to `dst`, aligning the destination first.
+///
+/// # Safety
+///
+/// Both ranges must be valid. When they overlap, `dst` must come before `src`.
+#[inline(always)]
+unsafe fn copy_forward(dst: *mut u8, src: *const u8, len: usize) {
+    let head = dst.align_offset(4).min(len);
+    asm!(
+        "xchg esi, {src}",
+        "rep movsb",
+        "mov ecx, {rest}",
+        "shr ecx, 2",
+        "rep movsd",
+        "mov ecx, {rest}",
+        "and ecx, 3",
+        "rep movsb",
+        "mov esi, {src}",
+        src = inout(reg) src => _,
+        rest = in(reg) len - head,
+        inout("ecx") head => _,
+        inout("edi") dst => _,
+        options(nostack),
+    );
+}
+
+/// Copies `[src, src + len)` backward to `dst`, starting with its last byte.
+///
+/// # Safety
+///
+/// Both ranges must be valid, and `len` must not be zero.
+#[inline(always)]
+unsafe fn copy_backward(dst: *mut u8, src: *const u8, len: usize) {
+    asm!(
+        "std",
+        "xchg esi, {src}",
+        "rep movsb",
+        "sub esi, 3",
+        "sub edi, 3",
+        "mov ecx, {words}",
+        "rep movsd",
+        "mov esi, {src}",
+        "cld",
+        src = inout(reg) src.add(len - 1) => _,
+        words = in(reg) len / 4,
+        inout("ecx") len % 4 => _,
+        inout("edi") dst.add(len - 1) => _,
+        options(nostack),
+    );
+}
+
+/// Fills `[dst, dst + len)` with `byte`, aligning the destination first.
+///
+/// # Safety
+///
+/// The range must be valid.
+#[inline(always)]
+unsafe fn fill(dst: *mut u8, byte: u8, len: usize) {
+    let head = dst.align_offset(4).min(len);
+    asm!(
+        "rep stosb",
+        "mov ecx, {rest}",
+        "shr ecx, 2",
+        "rep stosd",
+        "mov ecx, {rest}",
+        "and ecx, 3",
+        "rep stosb",
+        rest = in(reg) len - head,
+        in("eax") byte as u32 * 0x0101_0101,
+        inout("ecx") head => _,
+        inout("edi") dst => _,
+        options(nostack),
+    );
+}
+
+/// Copies `len` bytes from `src` to `dst`.
+///
+/// # Safety
+///
+/// Both ranges must be valid, and must not overlap.
+#[no_mangle]
+pub unsafe extern "C" fn memcpy(dst: *mut u8, src: *const u8, len: usize) -> *mut u8 {
+    copy_forward(dst, src, len);
+    dst
+}
+
+/// Copies `len` bytes from `src` to `dst`, which may overlap.
+///
+/// # Safety
+///
+/// Both ranges must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn memmove(dst: *mut u8, src: *const u8, len: usize) -> *mut u8 {
+    if (dst as usize).wrapping_sub(src as usize) >= len {
+        // The destination starts before the source, or after its end.
+        copy_forward(dst, src, len);
+    } else if len != 0 {
+        copy_backward(dst, src, len);
+    }
+    dst
+}
+
+/// Fills `len` bytes at `dst` with the lowest byte of `byte`.
+///
+/// # Safety
+///
+/// The range must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn memset(dst: *mut u8, byte: i32, len: usize) -> *mut u8 {
+    fill(dst, byte as u8, len);
+    dst
+}
+
+/// Compares `len` bytes at `a` and `b`, and returns the difference of the first ones that
+/// differ.
+///
+/// # Safety
+///
+/// Both ranges must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn memcmp(a: *const u8, b: *const u8, len: usize) -> i32 {
+    for i in 0..len {
+        let (x, y) = (*a.add(i), *b.add(i));
+        if x != y {
+            return x as i32 - y as i32;
+        }
+    }
+    0
+}
+
+/// Returns whether `len` bytes at `a` and `b` differ, as a non-zero value.
+///
+/// # Safety
+///
+/// Both ranges must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn bcmp(a: *const u8, b: *const u8, len: usize) -> i32 {
+    memcmp(a, b, len)
+}
+
+/// Returns the length of the null-terminated string at `s`.
+///
+/// # Safety
+///
+/// The string must be valid, up to its null byte.
+#[no_mangle]
+pub unsafe extern "C" fn strlen(s: *const u8) -> usize {
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
+    }
+    len
+}
+
+/// Fills `buf` with `value`, two cells at a time.
+///
+/// This is meant for the cells of the VGA text buffer.
+pub fn fill_u16(buf: &mut [u16], value: u16) {
+    let (dst, len) = (buf.as_mut_ptr(), buf.len());
+    let head = (dst as usize % 4 != 0 && len != 0) as usize;
+    unsafe {
+        asm!(
+            "rep stosw",
+            "mov ecx, {rest}",
+            "shr ecx, 1",
+            "rep stosd",
+            "mov ecx, {rest}",
+            "and ecx, 1",
+            "rep stosw",
+            rest = in(reg) len - head,
+            in("eax") value as u32 * 0x0001_0001,
+            inout("ecx") head => _,
+            inout("edi") dst => _,
+            options(nostack),
+        );
+    }
+}
+
+/// Zeroes `len` bytes at `dst`, with the SSE registers if possible.
+///
+/// # Safety
+///
+/// The range must be valid, and aligned on 4 KiB. `len` must be a multiple of 4 KiB.
+pub unsafe fn zero_pages(dst: *mut u8, len: usize) {
+    debug_assert!(dst as usize % 4096 == 0 && len % 4096 == 0);
+    if fpu::with_sse(|| zero_sse(dst, len)).is_none() {
+        fill(dst, 0, len);
+    }
+}
+
+/// See [`zero_pages`].
+///
+/// The stores bypass the caches, as the pages are usually not read right away. The kernel is
+/// built without SSE, so the compiler never keeps values in the registers that this clobbers:
+/// they only hold the state of a task, which [`fpu::with_sse`] saved.
+unsafe fn zero_sse(dst: *mut u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    asm!(
+        "xorps xmm0, xmm0",
+        "2:",
+        "movntps [{dst}], xmm0",
+        "movntps [{dst} + 16], xmm0",
+        "movntps [{dst} + 32], xmm0",
+        "movntps [{dst} + 48], xmm0",
+        "add {dst}, 64",
+        "sub {len}, 64",
+        "jnz 2b",
+        "sfence",
+        dst = inout(reg) dst => _,
+        len = inout(reg) len => _,
+        options(nostack),
+    );
+}
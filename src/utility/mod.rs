@@ -1,17 +1,27 @@
 //! Provides useful functions and other constructs.
 
 mod array_vec;
+mod checksum;
 mod critical_section;
+mod fixed;
 mod format;
 mod init_allocator;
+mod key_value_table;
+mod mem;
 mod mutex;
 mod once_cell;
+mod ring_buffer;
 
 pub mod instr;
 
 pub use self::array_vec::*;
+pub use self::checksum::*;
 pub use self::critical_section::*;
+pub use self::fixed::*;
 pub use self::format::*;
 pub use self::init_allocator::*;
+pub use self::key_value_table::*;
+pub use self::mem::{fill_u16, zero_pages};
 pub use self::mutex::*;
 pub use self::once_cell::*;
+pub use self::ring_buffer::*;
@@ -0,0 +1,94 @@
+use core::mem::MaybeUninit;
+
+/// A fixed-capacity queue backed by an array.
+///
+/// Values are popped in the order in which they were pushed.
+pub struct RingBuffer<T, const N: usize> {
+    data: [MaybeUninit<T>; N],
+    /// The index of the oldest value.
+    head: usize,
+    /// The number of values in the queue.
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    /// Creates a new, empty [`RingBuffer<T, N>`] instance.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            data: MaybeUninit::uninit_array(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values in the queue.
+    #[inline(always)]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the queue is empty.
+    #[inline(always)]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns whether the queue is full.
+    #[inline(always)]
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Returns the capacity of the queue.
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Removes all the values from the queue.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Attempts to push a value at the back of the queue.
+    ///
+    /// # Errors
+    ///
+    /// If the queue is full, this function returns its input as an error.
+    #[inline]
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        let index = (self.head + self.len) % N;
+        unsafe { self.data.get_unchecked_mut(index).write(value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Pops the value at the front of the queue.
+    ///
+    /// If the queue is empty, this function returns `None`.
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let value = unsafe { self.data.get_unchecked(self.head).assume_init() };
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(value)
+    }
+}